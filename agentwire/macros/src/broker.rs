@@ -42,6 +42,7 @@ enum BrokerAttr {
     Plan(Path),
     Error(Path),
     PollExtra,
+    OnEvent,
 }
 
 impl Parse for BrokerAttr {
@@ -57,6 +58,7 @@ impl Parse for BrokerAttr {
                 Ok(Self::Error(input.parse()?))
             }
             "poll_extra" => Ok(Self::PollExtra),
+            "on_event" => Ok(Self::OnEvent),
             ident => panic!("Unknown #[broker] option: {ident}"),
         }
     }
@@ -87,6 +89,10 @@ pub fn proc_macro_derive(input: TokenStream) -> TokenStream {
         .iter()
         .find_map(|attr| if let BrokerAttr::Error(expr) = attr { Some(expr) } else { None })
         .expect("#[broker] attribute must set an `error`");
+    let on_event = broker_attrs.contains(&BrokerAttr::OnEvent);
+    let emit = |receiver: proc_macro2::TokenStream, event: proc_macro2::TokenStream| {
+        on_event.then(|| quote!(#receiver.on_event(::agentwire::BrokerEvent::#event);))
+    };
 
     let agent_fields = fields.iter().filter_map(|field| {
         field.attrs.iter().find(|attr| attr.path().is_ident("agent")).map(|attr| {
@@ -116,6 +122,8 @@ pub fn proc_macro_derive(input: TokenStream) -> TokenStream {
     let run_handlers = agent_fields.clone().map(|(field, _)| {
         let ident = field.ident.as_ref().unwrap();
         let handler = format_ident!("handle_{}", ident);
+        let on_break = emit(quote!(fut.broker), quote!(Break(::std::stringify!(#ident))));
+        let on_error = emit(quote!(fut.broker), quote!(Error(::std::stringify!(#ident))));
         quote! {
             if let Some(port) = fut.broker.#ident.enabled() {
                 loop {
@@ -123,12 +131,14 @@ pub fn proc_macro_derive(input: TokenStream) -> TokenStream {
                         ::std::task::Poll::Ready(Some(output)) if output.source_ts > fence => {
                             match fut.broker.#handler(fut.plan, output) {
                                 ::std::result::Result::Ok(::agentwire::BrokerFlow::Break) => {
+                                    #on_break
                                     return ::std::task::Poll::Ready(::std::result::Result::Ok(()));
                                 }
                                 ::std::result::Result::Ok(::agentwire::BrokerFlow::Continue) => {
                                     continue 'outer;
                                 }
                                 ::std::result::Result::Err(err) => {
+                                    #on_error
                                     return ::std::task::Poll::Ready(
                                         ::std::result::Result::Err(
                                             ::agentwire::BrokerError::Handler(
@@ -161,15 +171,21 @@ pub fn proc_macro_derive(input: TokenStream) -> TokenStream {
         }
     });
     let poll_extra = broker_attrs.contains(&BrokerAttr::PollExtra).then(|| {
+        let on_break = emit(quote!(fut.broker), quote!(Break("poll_extra")));
+        let on_error = emit(quote!(fut.broker), quote!(Error("poll_extra")));
         quote! {
             match fut.broker.poll_extra(fut.plan, cx, fence) {
                 ::std::result::Result::Ok(::std::option::Option::Some(poll)) => {
+                    if poll.is_ready() {
+                        #on_break
+                    }
                     break poll.map(Ok);
                 }
                 ::std::result::Result::Ok(::std::option::Option::None) => {
                     continue;
                 }
                 ::std::result::Result::Err(err) => {
+                    #on_error
                     return ::std::task::Poll::Ready(::std::result::Result::Err(
                         ::agentwire::BrokerError::PollExtra(err),
                     ));
@@ -228,6 +244,8 @@ pub fn proc_macro_derive(input: TokenStream) -> TokenStream {
         let try_enable = format_ident!("try_enable_{}", ident);
         let disable = format_ident!("disable_{}", ident);
         let init = format_ident!("init_{}", ident);
+        let on_enabled = emit(quote!(self), quote!(AgentEnabled(::std::stringify!(#ident))));
+        let on_disabled = emit(quote!(self), quote!(AgentDisabled(::std::stringify!(#ident))));
         let (init, init_async) = if attrs.contains(&AgentAttr::InitAsync) {
             let init = quote! {
                 match self.#init().await {
@@ -246,10 +264,13 @@ pub fn proc_macro_derive(input: TokenStream) -> TokenStream {
             (quote!(Default::default()), quote!())
         };
         let constructor = if attrs.contains(&AgentAttr::Process) {
-            let logger = if let Some(logger) = attrs
-                .iter()
-                .find_map(|attr| if let AgentAttr::Logger(expr) = attr { Some(expr) } else { None })
-            {
+            let logger = if let Some(logger) = attrs.iter().find_map(|attr| {
+                if let AgentAttr::Logger(expr) = attr {
+                    Some(expr)
+                } else {
+                    None
+                }
+            }) {
                 quote!(#logger)
             } else {
                 quote!(::agentwire::agent::process::default_logger)
@@ -280,10 +301,14 @@ pub fn proc_macro_derive(input: TokenStream) -> TokenStream {
                 match ::std::mem::replace(&mut self.#ident, ::agentwire::agent::Cell::Vacant) {
                     ::agentwire::agent::Cell::Vacant => {
                         self.#ident = ::agentwire::agent::Cell::Enabled(#constructor);
+                        #on_enabled
+                    }
+                    ::agentwire::agent::Cell::Enabled(agent) => {
+                        self.#ident = ::agentwire::agent::Cell::Enabled(agent);
                     }
-                    ::agentwire::agent::Cell::Enabled(agent)
-                    | ::agentwire::agent::Cell::Disabled(agent) => {
+                    ::agentwire::agent::Cell::Disabled(agent) => {
                         self.#ident = ::agentwire::agent::Cell::Enabled(agent);
+                        #on_enabled
                     }
                 }
                 ::std::result::Result::Ok(())
@@ -293,10 +318,13 @@ pub fn proc_macro_derive(input: TokenStream) -> TokenStream {
             pub fn #try_enable(&mut self) {
                 match ::std::mem::replace(&mut self.#ident, ::agentwire::agent::Cell::Vacant) {
                     ::agentwire::agent::Cell::Vacant => {}
-                    ::agentwire::agent::Cell::Enabled(agent)
-                    | ::agentwire::agent::Cell::Disabled(agent) => {
+                    ::agentwire::agent::Cell::Enabled(agent) => {
                         self.#ident = ::agentwire::agent::Cell::Enabled(agent);
                     }
+                    ::agentwire::agent::Cell::Disabled(agent) => {
+                        self.#ident = ::agentwire::agent::Cell::Enabled(agent);
+                        #on_enabled
+                    }
                 }
             }
 
@@ -304,8 +332,11 @@ pub fn proc_macro_derive(input: TokenStream) -> TokenStream {
             pub fn #disable(&mut self) {
                 match ::std::mem::replace(&mut self.#ident, ::agentwire::agent::Cell::Vacant) {
                     ::agentwire::agent::Cell::Vacant => {}
-                    ::agentwire::agent::Cell::Enabled(agent)
-                    | ::agentwire::agent::Cell::Disabled(agent) => {
+                    ::agentwire::agent::Cell::Enabled(agent) => {
+                        self.#ident = ::agentwire::agent::Cell::Disabled(agent);
+                        #on_disabled
+                    }
+                    ::agentwire::agent::Cell::Disabled(agent) => {
                         self.#ident = ::agentwire::agent::Cell::Disabled(agent);
                     }
                 }
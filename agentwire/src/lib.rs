@@ -194,7 +194,7 @@ pub use agentwire_macros::test;
 /// # Examples
 ///
 /// ```ignore
-/// use agentwire::{agent, Broker, BrokerFlow};
+/// use agentwire::{agent, Broker, BrokerEvent, BrokerFlow};
 /// use futures::future::BoxFuture;
 /// use std::task::{Context, Instant, Poll};
 /// use thiserror::Error;
@@ -220,6 +220,7 @@ pub use agentwire_macros::test;
 ///   plan = Plan, // Plan trait for the broker (required)
 ///   error = Error, // Error type used by the generated `run` method (required)
 ///   poll_extra, // Call `poll_extra` method in the generated `run` method (optional)
+///   on_event, // Call `on_event` method on the broker decisions (optional)
 /// )]
 /// pub struct MyBroker {
 ///     // Define the agents. Each agent should be annotated with the `agent`
@@ -276,6 +277,11 @@ pub use agentwire_macros::test;
 ///         Ok(Some(Poll::Pending))
 ///     }
 ///
+///     // Implement the `on_event` method if it's enabled.
+///     fn on_event(&mut self, event: BrokerEvent) {
+///         println!("{event:?}");
+///     }
+///
 ///     // Implement a custom logger for process-based agents.
 ///     async fn process_logger(
 ///         &self,
@@ -305,6 +311,19 @@ pub enum BrokerFlow {
     Break,
 }
 
+/// A broker decision reported to the `on_event` method.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BrokerEvent {
+    /// An agent has been enabled.
+    AgentEnabled(&'static str),
+    /// An agent has been disabled.
+    AgentDisabled(&'static str),
+    /// An agent handler or `poll_extra` method returned [`BrokerFlow::Break`].
+    Break(&'static str),
+    /// An agent handler or `poll_extra` method returned an error.
+    Error(&'static str),
+}
+
 /// The type of error that can occur in a broker.
 #[derive(Error, Debug)]
 pub enum BrokerError<T: Display> {
@@ -257,6 +257,8 @@ pub struct Output<T: Port> {
     pub value: T::Output,
     /// Source data timestamp.
     pub source_ts: Instant,
+    /// Timestamp of the message creation, right before it's queued.
+    pub enqueue_ts: Instant,
 }
 
 /// A handle for bi-directional communication for the outside of the computation
@@ -331,14 +333,14 @@ impl<T: Port> Input<T> {
 
     /// Creates a new output value with the source timestamp of the input.
     pub fn chain(&self, value: T::Output) -> Output<T> {
-        Output { value, source_ts: self.source_ts }
+        Output { value, source_ts: self.source_ts, enqueue_ts: Instant::now() }
     }
 
     /// Returns a closure, which creates a new output value with the source
     /// timestamp of the input.
     pub fn chain_fn(&self) -> impl Fn(T::Output) -> Output<T> {
         let source_ts = self.source_ts;
-        move |value| Output { value, source_ts }
+        move |value| Output { value, source_ts, enqueue_ts: Instant::now() }
     }
 }
 
@@ -348,34 +350,35 @@ where
 {
     /// Creates a new output value with the source timestamp of the input.
     pub fn chain(&self, value: T::Output) -> Output<T> {
-        Output { value, source_ts: self.source_ts }
+        Output { value, source_ts: self.source_ts, enqueue_ts: Instant::now() }
     }
 
     /// Returns a closure, which creates a new output value with the source
     /// timestamp of the input.
     pub fn chain_fn(&self) -> impl Fn(T::Output) -> Output<T> {
         let source_ts = self.source_ts;
-        move |value| Output { value, source_ts }
+        move |value| Output { value, source_ts, enqueue_ts: Instant::now() }
     }
 }
 
 impl<T: Port> Output<T> {
     /// Creates a new output value with the source timestamp of now.
     pub fn new(value: T::Output) -> Self {
-        Self { value, source_ts: Instant::now() }
+        let now = Instant::now();
+        Self { value, source_ts: now, enqueue_ts: now }
     }
 
     /// Creates a new output value with the source timestamp of the original
     /// output.
     pub fn derive<O: Port>(&self, value: O::Output) -> Output<O> {
-        Output { value, source_ts: self.source_ts }
+        Output { value, source_ts: self.source_ts, enqueue_ts: Instant::now() }
     }
 
     /// Returns a closure, which creates a new output value with the source
     /// timestamp of the original output.
     pub fn derive_fn<O: Port>(&self) -> impl Fn(O::Output) -> Output<O> {
         let source_ts = self.source_ts;
-        move |value| Output { value, source_ts }
+        move |value| Output { value, source_ts, enqueue_ts: Instant::now() }
     }
 
     /// Creates a new input value with the source timestamp of the output.
@@ -504,6 +507,7 @@ where
     input_count: usize,
     input_index: usize,
     output_ts: Instant,
+    output_enqueue_ts: Instant,
     output_tx: sem_t,
     output_rx: sem_t,
     _marker: PhantomData<T>,
@@ -721,6 +725,7 @@ where
             sem_wait(&mut (*self.shared_memory).output_tx).expect("semaphore failure");
             serialize_message((*self.shared_memory).output(), &mut self.scratch, &output.value);
             (*self.shared_memory).output_ts = output.source_ts;
+            (*self.shared_memory).output_enqueue_ts = output.enqueue_ts;
             sem_post(&mut (*self.shared_memory).output_rx).expect("semaphore failure");
         }
     }
@@ -812,16 +817,17 @@ where
                 sem_wait.await.unwrap();
                 break;
             }
-            let (value, source_ts) = unsafe {
+            let (value, source_ts, enqueue_ts) = unsafe {
                 let shared_memory = addr as *mut SharedMemory<T>;
                 let archived = deserialize_message::<T::Output>((*shared_memory).output());
                 // Reuse of `SharedDeserializeMap` doesn't work
                 let value = archived.deserialize(&mut SharedDeserializeMap::new()).unwrap();
                 let source_ts = (*shared_memory).output_ts;
+                let enqueue_ts = (*shared_memory).output_enqueue_ts;
                 sem_post(&mut (*shared_memory).output_tx).expect("semaphore failure");
-                (value, source_ts)
+                (value, source_ts, enqueue_ts)
             };
-            let mut send = tx.feed(Output { value, source_ts });
+            let mut send = tx.feed(Output { value, source_ts, enqueue_ts });
             match select(&mut stop_tx_rx, &mut send).await {
                 Either::Left((_, _)) | Either::Right((Err(_), _)) => break,
                 Either::Right((Ok(result), _)) => result,
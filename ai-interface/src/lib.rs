@@ -1,9 +1,10 @@
 //! Rust <-> Python interface for Worldcoin's AI models.
 
-use pyo3::{prelude::*, FromPyObject};
+use pyo3::{prelude::*, types::PyDict, FromPyObject};
 use rkyv::{Archive, Deserialize, Serialize};
 use schemars::JsonSchema;
 use serde::Serialize as SerdeSerialize;
+use std::sync::atomic::{AtomicU64, Ordering};
 use thiserror::Error;
 
 /// Python Agent Error.
@@ -48,3 +49,62 @@ pub struct InitAgent<'a> {
     /// An error, if any.
     pub error: Option<PyError>,
 }
+
+/// Cooperative cancellation token for long-running Python calls.
+///
+/// The token is passed to the models as the `cancellation_token` keyword
+/// argument. Models poll `cancellation_token.is_cancelled()` between the
+/// inference stages and return early once it becomes `True`.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct CancellationToken {
+    cancelled_at: &'static AtomicU64,
+    issued_at: u64,
+}
+
+impl CancellationToken {
+    /// Creates a new token for a call issued at `issued_at`. The token is
+    /// cancelled once `cancelled_at` reaches `issued_at`. Both values share the
+    /// same arbitrary monotonic time base.
+    #[must_use]
+    pub fn new(cancelled_at: &'static AtomicU64, issued_at: u64) -> Self {
+        Self { cancelled_at, issued_at }
+    }
+}
+
+#[pymethods]
+impl CancellationToken {
+    /// Returns `true` if the call has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled_at.load(Ordering::Acquire) >= self.issued_at
+    }
+}
+
+/// Returns `true` if the Python `method` accepts a keyword argument `name`.
+pub fn accepts_kwarg(py: Python, method: &PyAny, name: &str) -> PyResult<bool> {
+    let signature = py.import("inspect")?.getattr("signature")?.call1((method,))?;
+    signature.getattr("parameters")?.contains(name)
+}
+
+/// Keyword argument of the model initializers carrying the CUDA stream
+/// priority. Lower values mean higher priority, and out-of-range values are
+/// clamped by the CUDA runtime.
+pub const CUDA_STREAM_PRIORITY_KWARG: &str = "cuda_stream_priority";
+
+/// Returns the keyword arguments for the model initializer `init`.
+///
+/// The `cuda_stream_priority` is forwarded only if the installed model
+/// supports it.
+pub fn init_kwargs<'py>(
+    py: Python<'py>,
+    init: &PyAny,
+    cuda_stream_priority: Option<i32>,
+) -> PyResult<&'py PyDict> {
+    let kwargs = PyDict::new(py);
+    if let Some(cuda_stream_priority) = cuda_stream_priority {
+        if accepts_kwarg(py, init, CUDA_STREAM_PRIORITY_KWARG)? {
+            kwargs.set_item(CUDA_STREAM_PRIORITY_KWARG, cuda_stream_priority)?;
+        }
+    }
+    Ok(kwargs)
+}
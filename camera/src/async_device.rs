@@ -0,0 +1,68 @@
+use crate::{Buffer, Dequeued};
+use futures::{ready, Stream};
+use libc::c_int;
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::unix::AsyncFd;
+
+/// Asynchronous frame source integrated with the tokio reactor.
+///
+/// Unlike [`Waiter`](crate::Waiter), which blocks a dedicated thread in
+/// `select()`, this wrapper registers the device descriptor with
+/// [`AsyncFd`] and yields the dequeued buffers as a [`Stream`]. The buffers
+/// are to be re-enqueued with [`Buffer::enqueue`] after processing, the same
+/// as with the blocking interface.
+#[derive(Debug)]
+pub struct AsyncDevice<'a> {
+    fd: AsyncFd<c_int>,
+    buffer: &'a Buffer<'a>,
+}
+
+impl<'a> AsyncDevice<'a> {
+    /// Registers the device of the buffer set with the current tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// If called outside of a tokio runtime.
+    pub fn new(buffer: &'a Buffer<'a>) -> io::Result<Self> {
+        Ok(Self { fd: AsyncFd::new(buffer.device().fd)?, buffer })
+    }
+
+    /// Returns the underlying buffer set.
+    #[must_use]
+    pub fn buffer(&self) -> &'a Buffer<'a> {
+        self.buffer
+    }
+
+    /// Waits for the next filled buffer and dequeues it.
+    pub async fn dequeue(&self) -> io::Result<Dequeued> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            match self.buffer.dequeue()? {
+                Some(dequeued) => return Ok(dequeued),
+                None => guard.clear_ready(),
+            }
+        }
+    }
+}
+
+impl Stream for AsyncDevice<'_> {
+    type Item = io::Result<Dequeued>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let mut guard = match ready!(self.fd.poll_read_ready(cx)) {
+                Ok(guard) => guard,
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            };
+            match self.buffer.dequeue() {
+                Ok(Some(dequeued)) => return Poll::Ready(Some(Ok(dequeued))),
+                Ok(None) => guard.clear_ready(),
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+}
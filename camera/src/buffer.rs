@@ -1,10 +1,11 @@
 use crate::{ioctl, mmap, munmap, Device};
 use libc::{MAP_SHARED, PROT_READ, PROT_WRITE};
-use std::{io, mem, ptr, slice, time::Duration};
+use std::{cell::Cell, io, mem, ptr, slice, time::Duration};
 use v4l2_sys::{
-    v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE, v4l2_buffer, v4l2_memory_V4L2_MEMORY_MMAP,
-    v4l2_requestbuffers, V4L2_BUF_FLAG_QUEUED, VIDIOC_DQBUF, VIDIOC_QBUF, VIDIOC_QUERYBUF,
-    VIDIOC_REQBUFS,
+    v4l2_buffer, v4l2_memory_V4L2_MEMORY_MMAP, v4l2_plane, v4l2_requestbuffers,
+    V4L2_BUF_FLAG_QUEUED, V4L2_BUF_FLAG_TIMESTAMP_MASK, V4L2_BUF_FLAG_TIMESTAMP_MONOTONIC,
+    V4L2_BUF_FLAG_TSTAMP_SRC_MASK, V4L2_BUF_FLAG_TSTAMP_SRC_SOE, VIDEO_MAX_PLANES, VIDIOC_DQBUF,
+    VIDIOC_QBUF, VIDIOC_QUERYBUF, VIDIOC_REQBUFS,
 };
 
 /// Set of video4linux buffers.
@@ -12,7 +13,16 @@ use v4l2_sys::{
 pub struct Buffer<'a> {
     device: &'a Device,
     count: u32,
-    buffers: Vec<&'a mut [u8]>,
+    buffers: Vec<BufferPlanes<'a>>,
+    last_sequence: Cell<Option<u32>>,
+}
+
+/// Memory planes of a single video4linux buffer. Single-planar devices have
+/// one plane per buffer, multi-planar formats like NV12M have one per image
+/// component.
+#[derive(Debug)]
+pub struct BufferPlanes<'a> {
+    planes: Vec<&'a [u8]>,
 }
 
 /// Dequeued buffer description returned from [`Buffer::dequeue`].
@@ -20,10 +30,39 @@ pub struct Buffer<'a> {
 pub struct Dequeued {
     /// Index of the dequeued buffer.
     pub index: u32,
-    /// Time when the first data byte was captured.
+    /// Frame timestamp on the monotonic clock, see `timestamp_source`.
     pub timestamp: Duration,
+    /// Frame sequence number assigned by the driver.
+    pub sequence: u32,
+    /// Source of the frame timestamp.
+    pub timestamp_source: TimestampSource,
+    /// Number of frames dropped by the driver since the previously dequeued
+    /// frame, computed from the gaps in the sequence numbers.
+    pub dropped: u32,
+}
+
+/// Source of a [`Dequeued`] frame timestamp.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampSource {
+    /// The driver doesn't provide the timestamps, so it's taken at the dequeue
+    /// time.
+    #[default]
+    Dequeue,
+    /// The driver timestamp of the start of the exposure.
+    StartOfExposure,
+    /// The driver timestamp of the end of the frame.
+    EndOfFrame,
 }
 
+/// Returns the number of frames skipped between the `last` and the current
+/// sequence numbers. A non-increasing sequence means the stream was restarted.
+fn sequence_gap(last: Option<u32>, sequence: u32) -> u32 {
+    last.filter(|&last| sequence > last).map_or(0, |last| sequence - last - 1)
+}
+
+/// Plane descriptors referenced by a multi-planar [`v4l2_buffer`].
+type RawPlanes = [v4l2_plane; VIDEO_MAX_PLANES as usize];
+
 impl<'a> Buffer<'a> {
     /// Request specified number of buffers for the device.
     ///
@@ -36,39 +75,55 @@ impl<'a> Buffer<'a> {
         let mut req: v4l2_requestbuffers = unsafe { mem::zeroed() };
         req.memory = v4l2_memory_V4L2_MEMORY_MMAP;
         req.count = count;
-        req.type_ = v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        req.type_ = device.buf_type;
         unsafe { ioctl(device.fd, VIDIOC_REQBUFS, ptr::addr_of_mut!(req).cast())? };
         assert_eq!(req.count, count);
 
         for i in 0..req.count {
-            let mut buffer: v4l2_buffer = unsafe { mem::zeroed() };
-            buffer.memory = v4l2_memory_V4L2_MEMORY_MMAP;
+            let mut raw_planes: RawPlanes = unsafe { mem::zeroed() };
+            let mut buffer = raw_buffer(device, &mut raw_planes);
             buffer.index = i;
-            buffer.type_ = v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
             unsafe { ioctl(device.fd, VIDIOC_QUERYBUF, ptr::addr_of_mut!(buffer).cast())? };
 
-            let ptr = unsafe {
-                mmap(
-                    ptr::null_mut(),
-                    buffer.length as usize,
-                    PROT_READ | PROT_WRITE,
-                    MAP_SHARED,
-                    device.fd,
-                    buffer.m.offset.into(),
-                )?
+            // For multi-planar buffers the driver replaces `length` with the
+            // number of planes.
+            let layout = if device.is_multi_planar() {
+                raw_planes[..(buffer.length as usize).min(raw_planes.len())]
+                    .iter()
+                    .map(|plane| (plane.length, unsafe { plane.m.mem_offset }))
+                    .collect()
+            } else {
+                vec![(buffer.length, unsafe { buffer.m.offset })]
             };
-            let slice = unsafe { slice::from_raw_parts_mut(ptr.cast(), buffer.length as usize) };
-            buffers.push(slice);
+            let mut planes = BufferPlanes { planes: Vec::with_capacity(layout.len()) };
+            for (length, offset) in layout {
+                let ptr = unsafe {
+                    mmap(
+                        ptr::null_mut(),
+                        length as usize,
+                        PROT_READ | PROT_WRITE,
+                        MAP_SHARED,
+                        device.fd,
+                        offset.into(),
+                    )?
+                };
+                let slice = unsafe { slice::from_raw_parts(ptr.cast(), length as usize) };
+                planes.planes.push(slice);
+            }
+            buffers.push(planes);
         }
 
-        Ok(Self { device, count, buffers })
+        Ok(Self { device, count, buffers, last_sequence: Cell::new(None) })
+    }
+
+    pub(crate) fn device(&self) -> &'a Device {
+        self.device
     }
 
     /// Sends the buffer to the queue for filling with new frames.
     pub fn enqueue(&self, index: u32) -> io::Result<()> {
-        let mut buffer: v4l2_buffer = unsafe { mem::zeroed() };
-        buffer.memory = v4l2_memory_V4L2_MEMORY_MMAP;
-        buffer.type_ = v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        let mut raw_planes: RawPlanes = unsafe { mem::zeroed() };
+        let mut buffer = raw_buffer(self.device, &mut raw_planes);
         buffer.index = index;
         unsafe { ioctl(self.device.fd, VIDIOC_QBUF, ptr::addr_of_mut!(buffer).cast())? };
         Ok(())
@@ -78,30 +133,55 @@ impl<'a> Buffer<'a> {
     /// are no new frames. Otherwise returns `Some(index)` with the buffer
     /// index.
     pub fn dequeue(&self) -> io::Result<Option<Dequeued>> {
-        let mut buffer: v4l2_buffer = unsafe { mem::zeroed() };
-        buffer.memory = v4l2_memory_V4L2_MEMORY_MMAP;
-        buffer.type_ = v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        let mut raw_planes: RawPlanes = unsafe { mem::zeroed() };
+        let mut buffer = raw_buffer(self.device, &mut raw_planes);
         let ret = unsafe { ioctl(self.device.fd, VIDIOC_DQBUF, ptr::addr_of_mut!(buffer).cast())? };
         if ret.is_some() && buffer.flags & V4L2_BUF_FLAG_QUEUED == 0 {
-            // FIXME current vcmipi driver doesn't return timestamps
-            // let timestamp = {
-            //     #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-            //     Duration::new(
-            //         buffer.timestamp.tv_sec as u64,
-            //         buffer.timestamp.tv_usec as u32 * 1000,
-            //     )
-            // };
-            let timestamp = crate::now()?;
-            Ok(Some(Dequeued { index: buffer.index, timestamp }))
+            #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+            let driver_timestamp = Duration::new(
+                buffer.timestamp.tv_sec as u64,
+                buffer.timestamp.tv_usec as u32 * 1000,
+            );
+            let monotonic =
+                buffer.flags & V4L2_BUF_FLAG_TIMESTAMP_MASK == V4L2_BUF_FLAG_TIMESTAMP_MONOTONIC;
+            // The current vcmipi driver doesn't return timestamps.
+            let (timestamp, timestamp_source) = if monotonic && !driver_timestamp.is_zero() {
+                let source = if buffer.flags & V4L2_BUF_FLAG_TSTAMP_SRC_MASK
+                    == V4L2_BUF_FLAG_TSTAMP_SRC_SOE
+                {
+                    TimestampSource::StartOfExposure
+                } else {
+                    TimestampSource::EndOfFrame
+                };
+                (driver_timestamp, source)
+            } else {
+                (crate::now()?, TimestampSource::Dequeue)
+            };
+            let sequence = buffer.sequence;
+            let dropped = sequence_gap(self.last_sequence.replace(Some(sequence)), sequence);
+            Ok(Some(Dequeued {
+                index: buffer.index,
+                timestamp,
+                sequence,
+                timestamp_source,
+                dropped,
+            }))
         } else {
             Ok(None)
         }
     }
 
-    /// Returns the buffer data with the specified index.
+    /// Returns the data of the first plane of the buffer with the specified
+    /// index, which is the whole buffer for single-planar devices.
     #[must_use]
     pub fn get(&self, index: u32) -> &[u8] {
-        self.buffers[index as usize]
+        self.buffers[index as usize].planes[0]
+    }
+
+    /// Returns the planes of the buffer with the specified index.
+    #[must_use]
+    pub fn planes(&self, index: u32) -> &BufferPlanes<'a> {
+        &self.buffers[index as usize]
     }
 
     /// Returns the number of buffers in the buffer set.
@@ -111,14 +191,16 @@ impl<'a> Buffer<'a> {
     }
 
     fn free(&mut self) -> io::Result<()> {
-        while let Some(buffer) = self.buffers.pop() {
-            unsafe { munmap(buffer.as_mut_ptr().cast(), buffer.len())? };
+        while let Some(mut buffer) = self.buffers.pop() {
+            while let Some(plane) = buffer.planes.pop() {
+                unsafe { munmap(plane.as_ptr().cast_mut().cast(), plane.len())? };
+            }
         }
 
         let mut req: v4l2_requestbuffers = unsafe { mem::zeroed() };
         req.memory = v4l2_memory_V4L2_MEMORY_MMAP;
         req.count = 0;
-        req.type_ = v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        req.type_ = self.device.buf_type;
         unsafe { ioctl(self.device.fd, VIDIOC_REQBUFS, ptr::addr_of_mut!(req).cast())? };
 
         Ok(())
@@ -132,3 +214,55 @@ impl Drop for Buffer<'_> {
         }
     }
 }
+
+impl BufferPlanes<'_> {
+    /// Returns the data of the plane with the specified index.
+    #[must_use]
+    pub fn get(&self, plane: usize) -> Option<&[u8]> {
+        self.planes.get(plane).map(|plane| &**plane)
+    }
+
+    /// Returns an iterator over the plane data.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.planes.iter().map(|plane| &**plane)
+    }
+
+    /// Returns the number of planes.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.planes.len()
+    }
+
+    /// Returns `true` if the buffer has no planes.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.planes.is_empty()
+    }
+}
+
+/// Returns a buffer descriptor for the device capture interface. For
+/// multi-planar devices the descriptor points to `planes`, which must outlive
+/// its use.
+fn raw_buffer(device: &Device, planes: &mut RawPlanes) -> v4l2_buffer {
+    let mut buffer: v4l2_buffer = unsafe { mem::zeroed() };
+    buffer.memory = v4l2_memory_V4L2_MEMORY_MMAP;
+    buffer.type_ = device.buf_type;
+    if device.is_multi_planar() {
+        buffer.m.planes = planes.as_mut_ptr();
+        buffer.length = VIDEO_MAX_PLANES;
+    }
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_gap() {
+        assert_eq!(sequence_gap(None, 5), 0);
+        assert_eq!(sequence_gap(Some(4), 5), 0);
+        assert_eq!(sequence_gap(Some(4), 8), 3);
+        assert_eq!(sequence_gap(Some(8), 0), 0);
+    }
+}
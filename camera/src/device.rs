@@ -4,7 +4,7 @@ use crate::{
 };
 use libc::{c_int, c_uint, c_void, O_CLOEXEC, O_NONBLOCK, O_RDWR};
 use std::{
-    ffi::{CStr, CString},
+    ffi::{CStr, CString, OsStr},
     io, mem,
     os::unix::ffi::OsStrExt,
     path::Path,
@@ -12,21 +12,32 @@ use std::{
     task::Context,
 };
 use v4l2_sys::{
-    v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE, v4l2_ctrl_type,
-    v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64, v4l2_ext_control, v4l2_ext_controls, v4l2_format,
-    v4l2_pix_format, v4l2_queryctrl, V4L2_CTRL_FLAG_DISABLED, V4L2_CTRL_FLAG_NEXT_CTRL,
-    VIDIOC_G_EXT_CTRLS, VIDIOC_G_FMT, VIDIOC_QUERYCTRL, VIDIOC_STREAMOFF, VIDIOC_STREAMON,
-    VIDIOC_S_EXT_CTRLS, VIDIOC_S_FMT,
+    v4l2_buf_type, v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE,
+    v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE, v4l2_capability, v4l2_control,
+    v4l2_ctrl_type, v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64, v4l2_event_subscription,
+    v4l2_ext_control, v4l2_ext_controls, v4l2_format, v4l2_pix_format, v4l2_pix_format_mplane,
+    v4l2_queryctrl, V4L2_CAP_DEVICE_CAPS, V4L2_CAP_VIDEO_CAPTURE, V4L2_CAP_VIDEO_CAPTURE_MPLANE,
+    V4L2_CID_EXPOSURE_ABSOLUTE, V4L2_CID_FOCUS_ABSOLUTE, V4L2_CID_GAIN,
+    V4L2_CID_WHITE_BALANCE_TEMPERATURE, V4L2_CTRL_FLAG_DISABLED, V4L2_CTRL_FLAG_NEXT_CTRL,
+    V4L2_EVENT_ALL, V4L2_EVENT_CTRL, V4L2_EVENT_SOURCE_CHANGE, VIDIOC_G_CTRL, VIDIOC_G_EXT_CTRLS,
+    VIDIOC_G_FMT, VIDIOC_QUERYCAP, VIDIOC_QUERYCTRL, VIDIOC_STREAMOFF, VIDIOC_STREAMON,
+    VIDIOC_SUBSCRIBE_EVENT, VIDIOC_S_CTRL, VIDIOC_S_EXT_CTRLS, VIDIOC_S_FMT,
+    VIDIOC_UNSUBSCRIBE_EVENT,
 };
 
+/// Mask of the control class bits of a control ID.
+const CTRL_CLASS_MASK: u32 = 0x0fff_0000;
+
 /// IMX392 device interface.
 #[derive(Debug)]
 pub struct Device {
     pub(crate) fd: c_int,
+    pub(crate) buf_type: v4l2_buf_type,
+    path: CString,
 }
 
 /// Camera format returned by [`Device::format`] method.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Format {
     /// Image width in pixels.
     pub width: u32,
@@ -38,43 +49,161 @@ pub struct Format {
     pub bytes_per_line: c_uint,
     /// Size in bytes of the buffer to hold a complete image, set by the driver.
     pub size: c_uint,
+    /// Layout of each memory plane of the image. Single-planar formats have
+    /// one plane matching `bytes_per_line` and `size`.
+    pub planes: Vec<PlaneFormat>,
+}
+
+/// Layout of a single image memory plane within a [`Format`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlaneFormat {
+    /// Distance in bytes between the leftmost pixels in two adjacent lines of
+    /// the plane.
+    pub bytes_per_line: c_uint,
+    /// Size in bytes of the plane, set by the driver.
+    pub size: c_uint,
+}
+
+/// Typed camera control.
+///
+/// A control is resolved to the standard V4L2 control if the driver supports
+/// it, and otherwise to the vendor-specific control of the same meaning, found
+/// by [`name`](Self::name). The exposure is resolved the other way around,
+/// because the vendor-specific control is in µs. The values are converted to
+/// the typed control units.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Control {
+    /// Sensor exposure time in µs.
+    Exposure,
+    /// Sensor analog gain.
+    Gain,
+    /// White balance temperature.
+    WhiteBalance,
+    /// Absolute focus position.
+    Focus,
+}
+
+/// Value range of a camera control, as reported by the driver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ControlRange {
+    /// Minimum value.
+    pub min: i64,
+    /// Maximum value.
+    pub max: i64,
+    /// Distance between two valid values.
+    pub step: u64,
+    /// Default value.
+    pub default: i64,
+}
+
+impl Control {
+    /// Returns the standard V4L2 control ID.
+    #[must_use]
+    pub fn id(self) -> u32 {
+        match self {
+            Self::Exposure => V4L2_CID_EXPOSURE_ABSOLUTE,
+            Self::Gain => V4L2_CID_GAIN,
+            Self::WhiteBalance => V4L2_CID_WHITE_BALANCE_TEMPERATURE,
+            Self::Focus => V4L2_CID_FOCUS_ABSOLUTE,
+        }
+    }
+
+    /// Returns the name of the vendor-specific control, as exposed by the
+    /// proprietary NVidia camera drivers.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Exposure => "Exposure",
+            Self::Gain => "Gain",
+            Self::WhiteBalance => "White Balance",
+            Self::Focus => "Focus",
+        }
+    }
+
+    /// Returns the number of typed control units in a driver unit of the
+    /// resolved control `id`. The standard exposure control is in 100 µs
+    /// units.
+    fn unit(self, id: u32) -> i64 {
+        if self == Self::Exposure && id == V4L2_CID_EXPOSURE_ABSOLUTE { 100 } else { 1 }
+    }
 }
 
 impl Device {
     /// Opens the camera device.
+    ///
+    /// Devices exposing only the multi-planar capture interface
+    /// (`V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE`) are captured through it, others
+    /// through the single-planar one.
     pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
         let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
         let fd = unsafe { open(path.as_ptr(), O_RDWR | O_NONBLOCK | O_CLOEXEC)? };
-        Ok(Self { fd })
+        let mut device = Self { fd, buf_type: v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE, path };
+        device.buf_type = capture_buf_type(device.capabilities()?)?;
+        Ok(device)
+    }
+
+    /// Re-opens the device node at the original path.
+    ///
+    /// After a device reset the old descriptor is stale, and all further
+    /// operations on it fail with `ENODEV`. Use [`Watcher`](crate::Watcher) to
+    /// detect when the device node is re-attached, then call this method and
+    /// set up the format and buffers again. The format and controls are not
+    /// restored. On error the old descriptor is kept.
+    pub fn reopen(&mut self) -> io::Result<()> {
+        let fd = unsafe { open(self.path.as_ptr(), O_RDWR | O_NONBLOCK | O_CLOEXEC)? };
+        let old_fd = mem::replace(&mut self.fd, fd);
+        if let Err(err) = unsafe { close(old_fd) } {
+            log::warn!("Couldn't close stale video4linux device descriptor: {err}");
+        }
+        self.buf_type = capture_buf_type(self.capabilities()?)?;
+        Ok(())
+    }
+
+    /// Returns the path of the device node.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        Path::new(OsStr::from_bytes(self.path.as_bytes()))
+    }
+
+    /// Returns `true` if the device is captured through the multi-planar
+    /// interface.
+    #[must_use]
+    pub fn is_multi_planar(&self) -> bool {
+        self.buf_type == v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE
     }
 
     /// Reads the current device format.
     pub fn format(&self) -> io::Result<Format> {
         let mut v4l_fmt: v4l2_format = unsafe { mem::zeroed() };
-        v4l_fmt.type_ = v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        v4l_fmt.type_ = self.buf_type;
         unsafe { ioctl(self.fd, VIDIOC_G_FMT, ptr::addr_of_mut!(v4l_fmt).cast::<c_void>())? };
-        Ok(Format::from(unsafe { v4l_fmt.fmt.pix }))
+        Ok(self.read_raw_format(&v4l_fmt))
     }
 
-    /// Attempts to change the device format.
+    /// Attempts to change the device format. For multi-planar devices the
+    /// plane layout is chosen by the driver.
     pub fn set_format(&self, format: &Format) -> io::Result<Format> {
         let mut v4l_fmt: v4l2_format = unsafe { mem::zeroed() };
-        v4l_fmt.type_ = v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
-        unsafe { format.update_raw(&mut v4l_fmt.fmt.pix) };
+        v4l_fmt.type_ = self.buf_type;
+        if self.is_multi_planar() {
+            unsafe { format.update_raw_mplane(&mut v4l_fmt.fmt.pix_mp) };
+        } else {
+            unsafe { format.update_raw(&mut v4l_fmt.fmt.pix) };
+        }
         unsafe { ioctl(self.fd, VIDIOC_S_FMT, ptr::addr_of_mut!(v4l_fmt).cast::<c_void>())? };
-        Ok(Format::from(unsafe { v4l_fmt.fmt.pix }))
+        Ok(self.read_raw_format(&v4l_fmt))
     }
 
     /// Starts frames capturing.
     pub fn start(&self) -> io::Result<()> {
-        let mut type_ = v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        let mut type_ = self.buf_type;
         unsafe { ioctl(self.fd, VIDIOC_STREAMON, ptr::addr_of_mut!(type_).cast::<c_void>())? };
         Ok(())
     }
 
     /// Stops frames capturing.
     pub fn stop(&self) -> io::Result<()> {
-        let mut type_ = v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        let mut type_ = self.buf_type;
         unsafe { ioctl(self.fd, VIDIOC_STREAMOFF, ptr::addr_of_mut!(type_).cast::<c_void>())? };
         Ok(())
     }
@@ -111,12 +240,38 @@ impl Device {
         f: impl FnOnce(Waiter, &mut Context<'_>) -> R,
     ) -> io::Result<R> {
         let wake = Wake::new()?;
-        let waiter = Waiter::new(&wake, self);
+        let waiter = Waiter::new(&wake, self.fd);
         let waker = wake.into_waker();
         let mut cx = Context::from_waker(&waker);
         Ok(f(waiter, &mut cx))
     }
 
+    /// Subscribes to the source change events, which the driver emits when
+    /// the sensor is reconfigured mid-stream, e.g. changes its resolution. The
+    /// events are delivered through [`Waiter::next_event`].
+    pub fn subscribe_source_change(&self) -> io::Result<()> {
+        self.subscribe_event(V4L2_EVENT_SOURCE_CHANGE, 0)
+    }
+
+    /// Subscribes to the value and range change events of the control.
+    /// Returns the resolved control ID, which is reported in the events
+    /// delivered through [`Waiter::next_event`].
+    pub fn subscribe_control(&self, control: Control) -> io::Result<u32> {
+        let id = self.resolve_control(control)?.id;
+        self.subscribe_event(V4L2_EVENT_CTRL, id)?;
+        Ok(id)
+    }
+
+    /// Unsubscribes from all driver events.
+    pub fn unsubscribe_events(&self) -> io::Result<()> {
+        let mut sub: v4l2_event_subscription = unsafe { mem::zeroed() };
+        sub.type_ = V4L2_EVENT_ALL;
+        unsafe {
+            ioctl(self.fd, VIDIOC_UNSUBSCRIBE_EVENT, ptr::addr_of_mut!(sub).cast::<c_void>())?
+        };
+        Ok(())
+    }
+
     /// Sets camera control value by name.
     pub fn set_control(&self, name: &str, value: i64) -> io::Result<()> {
         let (id, type_) = self.find_control(name)?;
@@ -160,7 +315,105 @@ impl Device {
         }
     }
 
+    /// Sets a typed camera control value.
+    pub fn write_control(&self, control: Control, value: i64) -> io::Result<()> {
+        let queryctl = self.resolve_control(control)?;
+        let unit = control.unit(queryctl.id);
+        let value = (value + unit / 2) / unit;
+        if queryctl.type_ == v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64 {
+            let mut ext_ctls: v4l2_ext_controls = unsafe { mem::zeroed() };
+            let mut ext_ctl: v4l2_ext_control = unsafe { mem::zeroed() };
+            ext_ctl.id = queryctl.id;
+            ext_ctl.__bindgen_anon_1.value64 = value;
+            ext_ctls.__bindgen_anon_1.ctrl_class = queryctl.id & CTRL_CLASS_MASK;
+            ext_ctls.count = 1;
+            ext_ctls.controls = &mut ext_ctl;
+            unsafe {
+                ioctl(self.fd, VIDIOC_S_EXT_CTRLS, ptr::addr_of_mut!(ext_ctls).cast::<c_void>())?
+            };
+        } else {
+            let mut ctl: v4l2_control = unsafe { mem::zeroed() };
+            ctl.id = queryctl.id;
+            ctl.value = i32::try_from(value).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{control:?} value {value} is out of range"),
+                )
+            })?;
+            unsafe { ioctl(self.fd, VIDIOC_S_CTRL, ptr::addr_of_mut!(ctl).cast::<c_void>())? };
+        }
+        Ok(())
+    }
+
+    /// Gets a typed camera control value.
+    pub fn read_control(&self, control: Control) -> io::Result<i64> {
+        let queryctl = self.resolve_control(control)?;
+        if queryctl.type_ == v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64 {
+            let mut ext_ctls: v4l2_ext_controls = unsafe { mem::zeroed() };
+            let mut ext_ctl: v4l2_ext_control = unsafe { mem::zeroed() };
+            ext_ctl.id = queryctl.id;
+            ext_ctls.__bindgen_anon_1.ctrl_class = queryctl.id & CTRL_CLASS_MASK;
+            ext_ctls.count = 1;
+            ext_ctls.controls = &mut ext_ctl;
+            unsafe {
+                ioctl(self.fd, VIDIOC_G_EXT_CTRLS, ptr::addr_of_mut!(ext_ctls).cast::<c_void>())?
+            };
+            Ok(unsafe { ext_ctl.__bindgen_anon_1.value64 } * control.unit(queryctl.id))
+        } else {
+            let mut ctl: v4l2_control = unsafe { mem::zeroed() };
+            ctl.id = queryctl.id;
+            unsafe { ioctl(self.fd, VIDIOC_G_CTRL, ptr::addr_of_mut!(ctl).cast::<c_void>())? };
+            Ok(i64::from(ctl.value) * control.unit(queryctl.id))
+        }
+    }
+
+    /// Returns the value range of a typed camera control.
+    pub fn control_range(&self, control: Control) -> io::Result<ControlRange> {
+        let queryctl = self.resolve_control(control)?;
+        let unit = control.unit(queryctl.id);
+        Ok(ControlRange {
+            min: i64::from(queryctl.minimum) * unit,
+            max: i64::from(queryctl.maximum) * unit,
+            step: u64::try_from(queryctl.step).unwrap_or(1) * unit.unsigned_abs(),
+            default: i64::from(queryctl.default_value) * unit,
+        })
+    }
+
+    /// Returns the capabilities of the opened device node.
+    fn subscribe_event(&self, type_: u32, id: u32) -> io::Result<()> {
+        let mut sub: v4l2_event_subscription = unsafe { mem::zeroed() };
+        sub.type_ = type_;
+        sub.id = id;
+        unsafe { ioctl(self.fd, VIDIOC_SUBSCRIBE_EVENT, ptr::addr_of_mut!(sub).cast::<c_void>())? };
+        Ok(())
+    }
+
+    fn capabilities(&self) -> io::Result<u32> {
+        let mut cap: v4l2_capability = unsafe { mem::zeroed() };
+        unsafe { ioctl(self.fd, VIDIOC_QUERYCAP, ptr::addr_of_mut!(cap).cast::<c_void>())? };
+        Ok(if cap.capabilities & V4L2_CAP_DEVICE_CAPS == 0 {
+            cap.capabilities
+        } else {
+            cap.device_caps
+        })
+    }
+
+    fn read_raw_format(&self, v4l_fmt: &v4l2_format) -> Format {
+        if self.is_multi_planar() {
+            Format::from(unsafe { v4l_fmt.fmt.pix_mp })
+        } else {
+            Format::from(unsafe { v4l_fmt.fmt.pix })
+        }
+    }
+
     fn find_control(&self, name: &str) -> io::Result<(u32, v4l2_ctrl_type)> {
+        let Some(queryctl) = self.query_control_by_name(name)? else {
+            panic!("Camera control with name `{name}` not found");
+        };
+        Ok((queryctl.id, queryctl.type_))
+    }
+
+    fn query_control_by_name(&self, name: &str) -> io::Result<Option<v4l2_queryctrl>> {
         let c_name = CString::new(name).unwrap();
         let mut queryctl: v4l2_queryctrl = unsafe { mem::zeroed() };
         // Due to proprietary NVidia IDs we have to review the ID by its name.
@@ -171,17 +424,43 @@ impl Device {
             };
             if let Err(err) = &result {
                 if matches!(err.kind(), io::ErrorKind::InvalidInput) {
-                    panic!("Camera control with name `{name}` not found");
+                    return Ok(None);
                 }
             }
             result?;
             let end = queryctl.name.iter().position(|&x| x == 0).unwrap();
             let query_name = CStr::from_bytes_with_nul(&queryctl.name[..=end]).unwrap();
             if queryctl.flags & V4L2_CTRL_FLAG_DISABLED == 0 && query_name == c_name.as_c_str() {
-                break;
+                return Ok(Some(queryctl));
             }
         }
-        Ok((queryctl.id, queryctl.type_))
+    }
+
+    fn resolve_control(&self, control: Control) -> io::Result<v4l2_queryctrl> {
+        // Prefer the vendor-specific exposure control in µs over the standard
+        // one in 100 µs units.
+        if control == Control::Exposure {
+            if let Some(queryctl) = self.query_control_by_name(control.name())? {
+                return Ok(queryctl);
+            }
+        }
+        let mut queryctl: v4l2_queryctrl = unsafe { mem::zeroed() };
+        queryctl.id = control.id();
+        let result = unsafe {
+            ioctl(self.fd, VIDIOC_QUERYCTRL, ptr::addr_of_mut!(queryctl).cast::<c_void>())
+        };
+        match result {
+            Ok(_) if queryctl.flags & V4L2_CTRL_FLAG_DISABLED == 0 => return Ok(queryctl),
+            Ok(_) => {}
+            Err(err) if matches!(err.kind(), io::ErrorKind::InvalidInput) => {}
+            Err(err) => return Err(err),
+        }
+        self.query_control_by_name(control.name())?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("camera control {control:?} is not supported"),
+            )
+        })
     }
 }
 
@@ -203,6 +482,24 @@ impl From<v4l2_pix_format> for Format {
             pixel_format: fmt.pixelformat,
             bytes_per_line: fmt.bytesperline,
             size: fmt.sizeimage,
+            planes: vec![PlaneFormat { bytes_per_line: fmt.bytesperline, size: fmt.sizeimage }],
+        }
+    }
+}
+
+impl From<v4l2_pix_format_mplane> for Format {
+    fn from(fmt: v4l2_pix_format_mplane) -> Self {
+        let planes = fmt.plane_fmt[..usize::from(fmt.num_planes).min(fmt.plane_fmt.len())]
+            .iter()
+            .map(|plane| PlaneFormat { bytes_per_line: plane.bytesperline, size: plane.sizeimage })
+            .collect::<Vec<_>>();
+        Self {
+            width: fmt.width,
+            height: fmt.height,
+            pixel_format: fmt.pixelformat,
+            bytes_per_line: planes.first().map_or(0, |plane| plane.bytes_per_line),
+            size: planes.iter().map(|plane| plane.size).sum(),
+            planes,
         }
     }
 }
@@ -213,4 +510,59 @@ impl Format {
         fmt.height = self.height;
         fmt.pixelformat = self.pixel_format;
     }
+
+    fn update_raw_mplane(&self, fmt: &mut v4l2_pix_format_mplane) {
+        fmt.width = self.width;
+        fmt.height = self.height;
+        fmt.pixelformat = self.pixel_format;
+    }
+}
+
+/// Selects the capture buffer type from the device capabilities, preferring
+/// the single-planar interface.
+fn capture_buf_type(capabilities: u32) -> io::Result<v4l2_buf_type> {
+    if capabilities & V4L2_CAP_VIDEO_CAPTURE != 0 {
+        Ok(v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE)
+    } else if capabilities & V4L2_CAP_VIDEO_CAPTURE_MPLANE != 0 {
+        Ok(v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE)
+    } else {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "not a video capture device"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use v4l2_sys::V4L2_PIX_FMT_NV12M;
+
+    #[test]
+    fn test_capture_buf_type() {
+        assert_eq!(
+            capture_buf_type(V4L2_CAP_VIDEO_CAPTURE | V4L2_CAP_VIDEO_CAPTURE_MPLANE).unwrap(),
+            v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE
+        );
+        assert_eq!(
+            capture_buf_type(V4L2_CAP_VIDEO_CAPTURE_MPLANE).unwrap(),
+            v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE
+        );
+        assert!(capture_buf_type(0).is_err());
+    }
+
+    #[test]
+    fn test_mplane_format() {
+        let mut fmt: v4l2_pix_format_mplane = unsafe { mem::zeroed() };
+        fmt.width = 1920;
+        fmt.height = 1080;
+        fmt.pixelformat = V4L2_PIX_FMT_NV12M;
+        fmt.num_planes = 2;
+        fmt.plane_fmt[0].bytesperline = 1920;
+        fmt.plane_fmt[0].sizeimage = 1920 * 1080;
+        fmt.plane_fmt[1].bytesperline = 1920;
+        fmt.plane_fmt[1].sizeimage = 1920 * 540;
+        let format = Format::from(fmt);
+        assert_eq!(format.planes.len(), 2);
+        assert_eq!(format.planes[1], PlaneFormat { bytes_per_line: 1920, size: 1920 * 540 });
+        assert_eq!(format.bytes_per_line, 1920);
+        assert_eq!(format.size, 1920 * 1620);
+    }
 }
@@ -0,0 +1,95 @@
+use v4l2_sys::{
+    v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64, v4l2_event, V4L2_EVENT_CTRL, V4L2_EVENT_CTRL_CH_RANGE,
+    V4L2_EVENT_CTRL_CH_VALUE, V4L2_EVENT_EOS, V4L2_EVENT_SOURCE_CHANGE,
+    V4L2_EVENT_SRC_CH_RESOLUTION,
+};
+
+/// Driver event, dequeued with [`Waiter::next_event`](crate::Waiter::next_event)
+/// after subscribing with
+/// [`Device::subscribe_source_change`](crate::Device::subscribe_source_change)
+/// or [`Device::subscribe_control`](crate::Device::subscribe_control).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The source parameters have changed mid-stream. The buffers still
+    /// hold frames in the old format, so the format must be read and
+    /// negotiated again before decoding further frames.
+    SourceChange {
+        /// The resolution has changed.
+        resolution: bool,
+    },
+    /// A control has changed, possibly by the driver itself.
+    Control {
+        /// ID of the control, as returned by
+        /// [`Device::subscribe_control`](crate::Device::subscribe_control).
+        id: u32,
+        /// The new value of the control.
+        value: i64,
+        /// The value has changed.
+        value_changed: bool,
+        /// The value range has changed.
+        range_changed: bool,
+    },
+    /// The last frame of the stream was dequeued.
+    EndOfStream,
+    /// Event of another type.
+    Other(u32),
+}
+
+impl Event {
+    pub(crate) fn from_raw(event: &v4l2_event) -> Self {
+        match event.type_ {
+            V4L2_EVENT_SOURCE_CHANGE => {
+                let changes = unsafe { event.u.src_change.changes };
+                Self::SourceChange { resolution: changes & V4L2_EVENT_SRC_CH_RESOLUTION != 0 }
+            }
+            V4L2_EVENT_CTRL => {
+                let ctrl = unsafe { &event.u.ctrl };
+                let value = if ctrl.type_ == v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64 {
+                    unsafe { ctrl.__bindgen_anon_1.value64 }
+                } else {
+                    i64::from(unsafe { ctrl.__bindgen_anon_1.value })
+                };
+                Self::Control {
+                    id: event.id,
+                    value,
+                    value_changed: ctrl.changes & V4L2_EVENT_CTRL_CH_VALUE != 0,
+                    range_changed: ctrl.changes & V4L2_EVENT_CTRL_CH_RANGE != 0,
+                }
+            }
+            V4L2_EVENT_EOS => Self::EndOfStream,
+            type_ => Self::Other(type_),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+    use v4l2_sys::{v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER, V4L2_CID_GAIN};
+
+    #[test]
+    fn test_from_raw() {
+        let mut event: v4l2_event = unsafe { mem::zeroed() };
+        event.type_ = V4L2_EVENT_SOURCE_CHANGE;
+        event.u.src_change.changes = V4L2_EVENT_SRC_CH_RESOLUTION;
+        assert_eq!(Event::from_raw(&event), Event::SourceChange { resolution: true });
+
+        let mut event: v4l2_event = unsafe { mem::zeroed() };
+        event.type_ = V4L2_EVENT_CTRL;
+        event.id = V4L2_CID_GAIN;
+        event.u.ctrl.type_ = v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER;
+        event.u.ctrl.changes = V4L2_EVENT_CTRL_CH_VALUE;
+        event.u.ctrl.__bindgen_anon_1.value = -42;
+        assert_eq!(Event::from_raw(&event), Event::Control {
+            id: V4L2_CID_GAIN,
+            value: -42,
+            value_changed: true,
+            range_changed: false,
+        });
+
+        let mut event: v4l2_event = unsafe { mem::zeroed() };
+        event.type_ = 0x0800_0001;
+        assert_eq!(Event::from_raw(&event), Event::Other(0x0800_0001));
+    }
+}
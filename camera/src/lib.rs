@@ -4,14 +4,24 @@
 #![warn(clippy::pedantic)]
 #![allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
 
+mod async_device;
 mod buffer;
 mod device;
+mod event;
+mod negotiate;
 mod wait;
+mod watch;
 
 pub use self::{
-    buffer::{Buffer, Dequeued},
-    device::{Device, Format},
+    async_device::AsyncDevice,
+    buffer::{Buffer, BufferPlanes, Dequeued, TimestampSource},
+    device::{Control, ControlRange, Device, Format, PlaneFormat},
+    event::Event,
+    negotiate::{
+        closest_frame_rate, closest_size, fourcc, FormatDesc, FrameRate, FrameSize, Negotiated,
+    },
     wait::Waiter,
+    watch::{DeviceEvent, Node, Watcher},
 };
 
 use libc::{
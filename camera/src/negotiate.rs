@@ -0,0 +1,353 @@
+use crate::{ioctl, Device, Format};
+use libc::{c_ulong, c_void};
+use std::{ffi::CStr, io, mem, ptr};
+use v4l2_sys::{
+    v4l2_fmtdesc, v4l2_frmivalenum, v4l2_frmivaltypes_V4L2_FRMIVAL_TYPE_DISCRETE, v4l2_frmsizeenum,
+    v4l2_frmsizetypes_V4L2_FRMSIZE_TYPE_DISCRETE, v4l2_streamparm, V4L2_CAP_TIMEPERFRAME,
+    V4L2_FMT_FLAG_COMPRESSED, V4L2_FMT_FLAG_EMULATED, VIDIOC_ENUM_FMT, VIDIOC_ENUM_FRAMEINTERVALS,
+    VIDIOC_ENUM_FRAMESIZES, VIDIOC_G_PARM, VIDIOC_S_PARM,
+};
+
+/// Pixel format supported by the device, returned by
+/// [`Device::enumerate_formats`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormatDesc {
+    /// The pixel format four-character code.
+    pub pixel_format: u32,
+    /// Human-readable description from the driver.
+    pub description: String,
+    /// The format is compressed.
+    pub compressed: bool,
+    /// The format is converted in software rather than natively supported.
+    pub emulated: bool,
+}
+
+/// Frame size supported for a pixel format, returned by
+/// [`Device::enumerate_framesizes`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameSize {
+    /// A single supported size.
+    Discrete {
+        /// Width in pixels.
+        width: u32,
+        /// Height in pixels.
+        height: u32,
+    },
+    /// A range of supported sizes. A continuous range has the steps of 1.
+    Stepwise {
+        /// Minimum width in pixels.
+        min_width: u32,
+        /// Maximum width in pixels.
+        max_width: u32,
+        /// Width step in pixels.
+        step_width: u32,
+        /// Minimum height in pixels.
+        min_height: u32,
+        /// Maximum height in pixels.
+        max_height: u32,
+        /// Height step in pixels.
+        step_height: u32,
+    },
+}
+
+/// Frame rate supported for a frame size, returned by
+/// [`Device::enumerate_frame_rates`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameRate {
+    /// A single supported frame rate.
+    Discrete(f64),
+    /// A range of supported frame rates.
+    Range {
+        /// Minimum frame rate.
+        min: f64,
+        /// Maximum frame rate.
+        max: f64,
+    },
+}
+
+/// Format and frame rate picked by [`Device::negotiate`].
+#[derive(Clone, Debug)]
+pub struct Negotiated {
+    /// The format set by the driver.
+    pub format: Format,
+    /// The frame rate set by the driver, if the device supports setting it.
+    pub fps: Option<f64>,
+}
+
+impl Device {
+    /// Returns the pixel formats supported by the device.
+    pub fn enumerate_formats(&self) -> io::Result<Vec<FormatDesc>> {
+        let mut formats = Vec::new();
+        for index in 0.. {
+            let mut fmtdesc: v4l2_fmtdesc = unsafe { mem::zeroed() };
+            fmtdesc.index = index;
+            fmtdesc.type_ = self.buf_type;
+            if !enumerate(self, VIDIOC_ENUM_FMT, ptr::addr_of_mut!(fmtdesc).cast())? {
+                break;
+            }
+            let description = CStr::from_bytes_until_nul(&fmtdesc.description)
+                .map(|description| description.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            formats.push(FormatDesc {
+                pixel_format: fmtdesc.pixelformat,
+                description,
+                compressed: fmtdesc.flags & V4L2_FMT_FLAG_COMPRESSED != 0,
+                emulated: fmtdesc.flags & V4L2_FMT_FLAG_EMULATED != 0,
+            });
+        }
+        Ok(formats)
+    }
+
+    /// Returns the frame sizes supported for the pixel format. Returns an
+    /// empty list if the driver doesn't report them.
+    pub fn enumerate_framesizes(&self, pixel_format: u32) -> io::Result<Vec<FrameSize>> {
+        let mut sizes = Vec::new();
+        for index in 0.. {
+            let mut frmsize: v4l2_frmsizeenum = unsafe { mem::zeroed() };
+            frmsize.index = index;
+            frmsize.pixel_format = pixel_format;
+            if !enumerate(self, VIDIOC_ENUM_FRAMESIZES, ptr::addr_of_mut!(frmsize).cast())? {
+                break;
+            }
+            if frmsize.type_ == v4l2_frmsizetypes_V4L2_FRMSIZE_TYPE_DISCRETE {
+                let discrete = unsafe { frmsize.__bindgen_anon_1.discrete };
+                sizes.push(FrameSize::Discrete { width: discrete.width, height: discrete.height });
+            } else {
+                // Continuous and stepwise ranges are reported only at index 0.
+                let stepwise = unsafe { frmsize.__bindgen_anon_1.stepwise };
+                sizes.push(FrameSize::Stepwise {
+                    min_width: stepwise.min_width,
+                    max_width: stepwise.max_width,
+                    step_width: stepwise.step_width.max(1),
+                    min_height: stepwise.min_height,
+                    max_height: stepwise.max_height,
+                    step_height: stepwise.step_height.max(1),
+                });
+                break;
+            }
+        }
+        Ok(sizes)
+    }
+
+    /// Returns the frame rates supported for the pixel format and frame size.
+    /// Returns an empty list if the driver doesn't report them.
+    pub fn enumerate_frame_rates(
+        &self,
+        pixel_format: u32,
+        width: u32,
+        height: u32,
+    ) -> io::Result<Vec<FrameRate>> {
+        let mut rates = Vec::new();
+        for index in 0.. {
+            let mut frmival: v4l2_frmivalenum = unsafe { mem::zeroed() };
+            frmival.index = index;
+            frmival.pixel_format = pixel_format;
+            frmival.width = width;
+            frmival.height = height;
+            if !enumerate(self, VIDIOC_ENUM_FRAMEINTERVALS, ptr::addr_of_mut!(frmival).cast())? {
+                break;
+            }
+            if frmival.type_ == v4l2_frmivaltypes_V4L2_FRMIVAL_TYPE_DISCRETE {
+                let discrete = unsafe { frmival.__bindgen_anon_1.discrete };
+                rates
+                    .extend(fps(discrete.numerator, discrete.denominator).map(FrameRate::Discrete));
+            } else {
+                // The shortest interval gives the highest frame rate.
+                let stepwise = unsafe { frmival.__bindgen_anon_1.stepwise };
+                if let (Some(min), Some(max)) = (
+                    fps(stepwise.max.numerator, stepwise.max.denominator),
+                    fps(stepwise.min.numerator, stepwise.min.denominator),
+                ) {
+                    rates.push(FrameRate::Range { min, max });
+                }
+                break;
+            }
+        }
+        Ok(rates)
+    }
+
+    /// Sets the capture frame rate. Returns the frame rate set by the driver,
+    /// or `None` if the device doesn't support setting it.
+    pub fn set_frame_rate(&self, fps: f64) -> io::Result<Option<f64>> {
+        let mut parm: v4l2_streamparm = unsafe { mem::zeroed() };
+        parm.type_ = self.buf_type;
+        unsafe { ioctl(self.fd, VIDIOC_G_PARM, ptr::addr_of_mut!(parm).cast::<c_void>())? };
+        if unsafe { parm.parm.capture.capability } & V4L2_CAP_TIMEPERFRAME == 0 {
+            return Ok(None);
+        }
+        let (numerator, denominator) = fraction(fps);
+        unsafe {
+            parm.parm.capture.timeperframe.numerator = numerator;
+            parm.parm.capture.timeperframe.denominator = denominator;
+            ioctl(self.fd, VIDIOC_S_PARM, ptr::addr_of_mut!(parm).cast::<c_void>())?;
+        }
+        let timeperframe = unsafe { parm.parm.capture.timeperframe };
+        Ok(self::fps(timeperframe.numerator, timeperframe.denominator))
+    }
+
+    /// Sets the pixel format with the supported frame size closest to
+    /// `width`×`height`, and optionally the supported frame rate closest to
+    /// `fps`. Fails if the pixel format is not supported.
+    pub fn negotiate(
+        &self,
+        pixel_format: u32,
+        width: u32,
+        height: u32,
+        fps: Option<f64>,
+    ) -> io::Result<Negotiated> {
+        let formats = self.enumerate_formats()?;
+        if !formats.is_empty() && !formats.iter().any(|desc| desc.pixel_format == pixel_format) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("pixel format {} is not supported", fourcc(pixel_format)),
+            ));
+        }
+        let sizes = self.enumerate_framesizes(pixel_format)?;
+        let (width, height) = closest_size(&sizes, width, height).unwrap_or((width, height));
+        let mut format = self.format()?;
+        format.pixel_format = pixel_format;
+        format.width = width;
+        format.height = height;
+        let format = self.set_format(&format)?;
+        if format.pixel_format != pixel_format {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("couldn't set pixel format {}", fourcc(pixel_format)),
+            ));
+        }
+        let fps = match fps {
+            Some(fps) => {
+                let rates =
+                    self.enumerate_frame_rates(pixel_format, format.width, format.height)?;
+                self.set_frame_rate(closest_frame_rate(&rates, fps).unwrap_or(fps))?
+            }
+            None => None,
+        };
+        Ok(Negotiated { format, fps })
+    }
+}
+
+/// Returns the supported frame size closest to `width`×`height`.
+#[must_use]
+pub fn closest_size(sizes: &[FrameSize], width: u32, height: u32) -> Option<(u32, u32)> {
+    let distance = |(w, h): (u32, u32)| {
+        let (dw, dh) = (u64::from(w.abs_diff(width)), u64::from(h.abs_diff(height)));
+        dw * dw + dh * dh
+    };
+    sizes
+        .iter()
+        .map(|size| match *size {
+            FrameSize::Discrete { width, height } => (width, height),
+            FrameSize::Stepwise {
+                min_width,
+                max_width,
+                step_width,
+                min_height,
+                max_height,
+                step_height,
+            } => (
+                snap(width, min_width, max_width, step_width),
+                snap(height, min_height, max_height, step_height),
+            ),
+        })
+        .min_by_key(|&size| distance(size))
+}
+
+/// Returns the supported frame rate closest to `fps`.
+#[must_use]
+pub fn closest_frame_rate(rates: &[FrameRate], fps: f64) -> Option<f64> {
+    rates
+        .iter()
+        .map(|rate| match *rate {
+            FrameRate::Discrete(rate) => rate,
+            FrameRate::Range { min, max } => fps.clamp(min, max),
+        })
+        .min_by(|a, b| (a - fps).abs().total_cmp(&(b - fps).abs()))
+}
+
+/// Formats a four-character code.
+#[must_use]
+pub fn fourcc(code: u32) -> String {
+    code.to_le_bytes()
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '?' })
+        .collect()
+}
+
+/// Calls an enumeration ioctl. Returns `false` past the last entry.
+fn enumerate(device: &Device, request: c_ulong, argp: *mut c_void) -> io::Result<bool> {
+    match unsafe { ioctl(device.fd, request, argp) } {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::InvalidInput => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Clamps the value to the range and rounds it to the nearest step.
+fn snap(value: u32, min: u32, max: u32, step: u32) -> u32 {
+    let value = value.clamp(min, max);
+    let steps = (value - min + step / 2) / step;
+    (min + steps * step).min(max)
+}
+
+/// Converts a frame interval in seconds to a frame rate.
+fn fps(numerator: u32, denominator: u32) -> Option<f64> {
+    (numerator > 0).then(|| f64::from(denominator) / f64::from(numerator))
+}
+
+/// Converts a frame rate to a frame interval in seconds, with a millisecond
+/// precision for the fractional rates.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn fraction(fps: f64) -> (u32, u32) {
+    if fps.fract() == 0.0 {
+        (1, fps as u32)
+    } else {
+        (1000, (fps * 1000.0).round() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_size() {
+        let sizes = [
+            FrameSize::Discrete { width: 1920, height: 1200 },
+            FrameSize::Discrete { width: 1440, height: 1080 },
+            FrameSize::Discrete { width: 640, height: 480 },
+        ];
+        assert_eq!(closest_size(&sizes, 1440, 1080), Some((1440, 1080)));
+        assert_eq!(closest_size(&sizes, 1280, 960), Some((1440, 1080)));
+        assert_eq!(closest_size(&sizes, 4000, 3000), Some((1920, 1200)));
+        assert_eq!(closest_size(&[], 640, 480), None);
+        let sizes = [FrameSize::Stepwise {
+            min_width: 320,
+            max_width: 1920,
+            step_width: 16,
+            min_height: 240,
+            max_height: 1080,
+            step_height: 8,
+        }];
+        assert_eq!(closest_size(&sizes, 1000, 700), Some((1008, 704)));
+        assert_eq!(closest_size(&sizes, 100, 5000), Some((320, 1080)));
+    }
+
+    #[test]
+    fn test_closest_frame_rate() {
+        let rates = [FrameRate::Discrete(30.0), FrameRate::Discrete(60.0)];
+        assert_eq!(closest_frame_rate(&rates, 50.0), Some(60.0));
+        assert_eq!(closest_frame_rate(&rates, 15.0), Some(30.0));
+        let rates = [FrameRate::Range { min: 1.0, max: 120.0 }];
+        assert_eq!(closest_frame_rate(&rates, 90.0), Some(90.0));
+        assert_eq!(closest_frame_rate(&rates, 200.0), Some(120.0));
+        assert_eq!(closest_frame_rate(&[], 30.0), None);
+    }
+
+    #[test]
+    fn test_fraction() {
+        assert_eq!(fraction(30.0), (1, 30));
+        assert_eq!(fraction(29.97), (1000, 29970));
+        assert_eq!(fourcc(v4l2_sys::V4L2_PIX_FMT_Y10), "Y10 ");
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{close, eventfd, read, select, write, Device};
+use crate::{close, event::Event, eventfd, ioctl, read, select, write};
 use libc::{
     c_int, c_void, fd_set, suseconds_t, time_t, timeval, EFD_CLOEXEC, FD_ISSET, FD_SET, FD_ZERO,
 };
@@ -10,6 +10,7 @@ use std::{
     task::{RawWaker, RawWakerVTable, Waker},
     time::Duration,
 };
+use v4l2_sys::{v4l2_event, VIDIOC_DQEVENT};
 
 static VTABLE: RawWakerVTable = RawWakerVTable::new(wake_clone, wake_wake, wake_wake, wake_drop);
 
@@ -18,6 +19,7 @@ static VTABLE: RawWakerVTable = RawWakerVTable::new(wake_clone, wake_wake, wake_
 pub struct Waiter {
     waker: Wake,
     device: c_int,
+    events: bool,
 }
 
 #[repr(transparent)]
@@ -27,12 +29,21 @@ pub(crate) struct Wake {
 }
 
 impl Waiter {
-    pub(crate) fn new(waker: &Wake, device: &Device) -> Self {
-        Self { waker: waker.clone(), device: device.fd }
+    pub(crate) fn new(waker: &Wake, device: c_int) -> Self {
+        Self { waker: waker.clone(), device, events: true }
+    }
+
+    /// Creates a waiter for a descriptor which is not a video4linux device, so
+    /// it has no driver events.
+    pub(crate) fn without_events(waker: &Wake, fd: c_int) -> Self {
+        Self { waker: waker.clone(), device: fd, events: false }
     }
 
     /// Puts the current thread to sleep until either a new frame data becomes
-    /// ready or woken by an asynchronous event.
+    /// ready, a subscribed driver event is pending, or woken by an asynchronous
+    /// event. The pending driver events must be dequeued with
+    /// [`next_event`](Self::next_event), otherwise this method returns
+    /// immediately.
     pub fn wait(&self, timeout: Duration) -> io::Result<()> {
         let mut tv = timeval {
             tv_sec: timeout.as_secs() as time_t,
@@ -44,11 +55,17 @@ impl Waiter {
             FD_ZERO(&mut fd_set);
             FD_SET(self.device, &mut fd_set);
             FD_SET(*self.waker.eventfd, &mut fd_set);
+            #[allow(invalid_value, clippy::uninit_assumed_init)]
+            let mut except_fd_set: fd_set = MaybeUninit::uninit().assume_init();
+            FD_ZERO(&mut except_fd_set);
+            if self.events {
+                FD_SET(self.device, &mut except_fd_set);
+            }
             let n = select(
                 cmp::max(self.device, *self.waker.eventfd) + 1,
                 &mut fd_set,
                 ptr::null_mut(),
-                ptr::null_mut(),
+                &mut except_fd_set,
                 &mut tv,
             )?;
             if n > 0 && FD_ISSET(*self.waker.eventfd, &fd_set) {
@@ -92,6 +109,24 @@ impl Waiter {
         }
         Ok(())
     }
+
+    /// Dequeues the next pending driver event without blocking. Returns
+    /// `None` if no event is pending.
+    pub fn next_event(&self) -> io::Result<Option<Event>> {
+        if !self.events {
+            return Ok(None);
+        }
+        let mut event: v4l2_event = unsafe { mem::zeroed() };
+        let result = unsafe {
+            ioctl(self.device, VIDIOC_DQEVENT, ptr::addr_of_mut!(event).cast::<c_void>())
+        };
+        match result {
+            Ok(Some(_)) => Ok(Some(Event::from_raw(&event))),
+            Ok(None) => Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
 }
 
 impl Wake {
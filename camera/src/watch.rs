@@ -0,0 +1,247 @@
+use crate::close;
+use libc::{
+    c_int, c_void, pollfd, sa_family_t, sockaddr, sockaddr_nl, socklen_t, AF_NETLINK, ENOBUFS,
+    MSG_DONTWAIT, NETLINK_KOBJECT_UEVENT, POLLIN, SOCK_CLOEXEC, SOCK_DGRAM,
+};
+use std::{
+    fs, io, mem,
+    os::fd::{AsRawFd, RawFd},
+    path::{Path, PathBuf},
+    ptr,
+    time::{Duration, Instant},
+};
+
+/// Netlink multicast group of the kernel uevents.
+const KERNEL_UEVENT_GROUP: u32 = 1;
+
+/// Maximum size of a single uevent message.
+const UEVENT_BUFFER_SIZE: usize = 8192;
+
+/// Sysfs directory of the `video4linux` device nodes.
+const SYSFS_CLASS_DIR: &str = "/sys/class/video4linux";
+
+/// Video device hot-plug watcher.
+///
+/// Listens to the kernel uevents over a netlink socket and reports the
+/// `video4linux` device nodes being removed and re-attached, e.g. when a USB
+/// camera resets. Receiving the events doesn't block, so it can be polled
+/// from a capture loop. The events must be drained regularly, otherwise the
+/// socket receive buffer overflows and the events are lost.
+#[derive(Debug)]
+pub struct Watcher {
+    fd: c_int,
+}
+
+/// Video device hot-plug event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceEvent {
+    /// A device node was attached.
+    Added(Node),
+    /// A device node was removed.
+    Removed(Node),
+    /// The socket receive buffer overflowed and some events were lost.
+    Overflow,
+}
+
+/// Video device node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    /// Path of the device node, e.g. `/dev/video1`.
+    pub path: PathBuf,
+    /// Kernel device path, the udev `DEVPATH` property, e.g.
+    /// `/devices/platform/usb/video4linux/video1`.
+    pub devpath: PathBuf,
+}
+
+impl Node {
+    /// Resolves the kernel device path of the device node at `path`.
+    pub fn resolve(path: &Path) -> io::Result<Self> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a device node path"))?;
+        let sysfs = Path::new(SYSFS_CLASS_DIR).join(name).canonicalize()?;
+        let devpath = sysfs.strip_prefix("/sys").map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "device path outside of sysfs")
+        })?;
+        Ok(Self { path: path.to_owned(), devpath: Path::new("/").join(devpath) })
+    }
+
+    /// Finds the device node whose name reported by the driver contains
+    /// `name`. Unlike the node path, the name doesn't depend on the probe
+    /// order of the devices.
+    pub fn find(name: &str) -> io::Result<Self> {
+        let mut entries = fs::read_dir(SYSFS_CLASS_DIR)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(fs::DirEntry::file_name);
+        for entry in entries {
+            let Ok(device_name) = fs::read_to_string(entry.path().join("name")) else { continue };
+            if device_name.trim_end().contains(name) {
+                return Self::resolve(&Path::new("/dev").join(entry.file_name()));
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("no video device named {name:?}")))
+    }
+
+    /// Returns the kernel device path of the hardware the node belongs to.
+    /// Unlike the node name, it stays the same when the hardware is
+    /// re-attached under another node name.
+    #[must_use]
+    pub fn parent(&self) -> Option<&Path> {
+        // Strips the `video4linux/videoN` suffix.
+        self.devpath.parent()?.parent()
+    }
+}
+
+impl Watcher {
+    /// Subscribes to the kernel uevents.
+    pub fn new() -> io::Result<Self> {
+        let fd =
+            unsafe { libc::socket(AF_NETLINK, SOCK_DGRAM | SOCK_CLOEXEC, NETLINK_KOBJECT_UEVENT) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let watcher = Self { fd };
+        let mut addr: sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = AF_NETLINK as sa_family_t;
+        addr.nl_groups = KERNEL_UEVENT_GROUP;
+        let result = unsafe {
+            libc::bind(
+                watcher.fd,
+                ptr::addr_of!(addr).cast::<sockaddr>(),
+                mem::size_of_val(&addr) as socklen_t,
+            )
+        };
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(watcher)
+    }
+
+    /// Returns the next pending video device event without blocking.
+    /// [`DeviceEvent::Overflow`] is returned if some events were lost.
+    pub fn try_recv(&mut self) -> io::Result<Option<DeviceEvent>> {
+        let mut buf = [0; UEVENT_BUFFER_SIZE];
+        loop {
+            let len = unsafe {
+                libc::recv(self.fd, buf.as_mut_ptr().cast::<c_void>(), buf.len(), MSG_DONTWAIT)
+            };
+            if len == -1 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(ENOBUFS) {
+                    return Ok(Some(DeviceEvent::Overflow));
+                }
+                return match err.kind() {
+                    io::ErrorKind::WouldBlock => Ok(None),
+                    io::ErrorKind::Interrupted => continue,
+                    _ => Err(err),
+                };
+            }
+            #[allow(clippy::cast_sign_loss)]
+            if let Some(event) = parse_uevent(&buf[..len as usize]) {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for the next video device event.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> io::Result<Option<DeviceEvent>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.try_recv()? {
+                return Ok(Some(event));
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            let mut fds = pollfd { fd: self.fd, events: POLLIN, revents: 0 };
+            let timeout = c_int::try_from(remaining.as_millis()).unwrap_or(c_int::MAX).max(1);
+            if unsafe { libc::poll(&mut fds, 1, timeout) } == -1 {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+impl AsRawFd for Watcher {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(err) = close(self.fd) {
+                log::error!("Couldn't close uevent socket descriptor: {err}");
+            }
+        }
+    }
+}
+
+/// Parses a kernel uevent message of the form
+/// `action@devpath\0KEY=VALUE\0...`, keeping only the `video4linux` events.
+fn parse_uevent(msg: &[u8]) -> Option<DeviceEvent> {
+    let mut fields = msg.split(|&b| b == 0).filter(|field| !field.is_empty());
+    let header = fields.next()?;
+    if !header.contains(&b'@') {
+        // Not a kernel message, e.g. re-broadcasted by udev.
+        return None;
+    }
+    let (mut action, mut subsystem, mut devname, mut devpath) = (None, None, None, None);
+    for field in fields {
+        let Some(eq) = field.iter().position(|&b| b == b'=') else { continue };
+        let (key, value) = (&field[..eq], &field[eq + 1..]);
+        match key {
+            b"ACTION" => action = Some(value),
+            b"SUBSYSTEM" => subsystem = Some(value),
+            b"DEVNAME" => devname = Some(value),
+            b"DEVPATH" => devpath = Some(value),
+            _ => {}
+        }
+    }
+    if subsystem? != b"video4linux" {
+        return None;
+    }
+    let devname = std::str::from_utf8(devname?).ok()?;
+    let devpath = std::str::from_utf8(devpath?).ok()?;
+    let node = Node { path: Path::new("/dev").join(devname), devpath: devpath.into() };
+    match action? {
+        b"add" => Some(DeviceEvent::Added(node)),
+        b"remove" => Some(DeviceEvent::Removed(node)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uevent() {
+        let node = |name: &str| Node {
+            path: Path::new("/dev").join(name),
+            devpath: Path::new("/devices/platform/usb/video4linux").join(name),
+        };
+        let msg = b"remove@/devices/platform/usb/video4linux/video2\0ACTION=remove\0\
+            DEVPATH=/devices/platform/usb/video4linux/video2\0SUBSYSTEM=video4linux\0\
+            MAJOR=81\0MINOR=2\0DEVNAME=video2\0SEQNUM=4242\0";
+        assert_eq!(parse_uevent(msg), Some(DeviceEvent::Removed(node("video2"))));
+        let msg = b"add@/devices/platform/usb/video4linux/video3\0ACTION=add\0\
+            DEVPATH=/devices/platform/usb/video4linux/video3\0SUBSYSTEM=video4linux\0\
+            DEVNAME=video3\0";
+        let Some(DeviceEvent::Added(added)) = parse_uevent(msg) else { panic!() };
+        assert_eq!(added, node("video3"));
+        assert_eq!(added.parent(), node("video2").parent());
+        assert_eq!(added.parent(), Some(Path::new("/devices/platform/usb")));
+        let msg =
+            b"add@/devices/platform/usb/1-1\0ACTION=add\0SUBSYSTEM=usb\0DEVNAME=bus/usb/001/002\0";
+        assert_eq!(parse_uevent(msg), None);
+        let msg = b"change@/devices/platform/usb/video4linux/video2\0ACTION=change\0\
+            SUBSYSTEM=video4linux\0DEVNAME=video2\0";
+        assert_eq!(parse_uevent(msg), None);
+        assert_eq!(parse_uevent(b"libudev\0ACTION=add\0SUBSYSTEM=video4linux\0"), None);
+    }
+}
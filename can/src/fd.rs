@@ -1,13 +1,52 @@
 //! CAN FD interface.
+//!
+//! The socket also receives the error frames generated by the CAN controller,
+//! which are used to track the [`BusState`]. A controller in the bus-off state
+//! stops transmitting until the interface is restarted with [`restart`].
+//!
+//! With the `tokio` feature, [`open_async`] creates a non-blocking socket
+//! driven by the tokio reactor, which doesn't need dedicated threads.
 
-use super::{bind, close, recvmsg, sendmsg, setsockopt, socket};
+use super::{bind, close, ip, recvmsg, sendmsg, setsockopt, socket};
 use libc::{
-    c_int, canfd_frame, canid_t, iovec, msghdr, sockaddr_can, AF_CAN, CANFD_MTU, CAN_RAW,
-    CAN_RAW_FD_FRAMES, PF_CAN, SOCK_CLOEXEC, SOCK_RAW, SOL_CAN_RAW,
+    c_int, can_err_mask_t, canfd_frame, canid_t, iovec, msghdr, sockaddr_can, AF_CAN, CANFD_MTU,
+    CAN_ERR_FLAG, CAN_ERR_MASK, CAN_MTU, CAN_RAW, CAN_RAW_ERR_FILTER, CAN_RAW_FD_FRAMES, PF_CAN,
+    SOCK_CLOEXEC, SOCK_NONBLOCK, SOCK_RAW, SOL_CAN_RAW,
 };
 use nix::{net::if_::if_nametoindex, NixPath};
-use std::{convert::TryInto, io, mem, ptr, sync::Arc};
+use std::{
+    convert::TryInto,
+    io, mem,
+    os::fd::{AsRawFd, RawFd},
+    ptr,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
 use thiserror::Error;
+#[cfg(feature = "tokio")]
+use tokio::io::unix::AsyncFd;
+
+// Error classes from `linux/can/error.h`.
+const CAN_ERR_CRTL: canid_t = 0x0000_0004;
+const CAN_ERR_BUSOFF: canid_t = 0x0000_0040;
+const CAN_ERR_BUSERROR: canid_t = 0x0000_0080;
+const CAN_ERR_RESTARTED: canid_t = 0x0000_0100;
+
+// Controller status bits in `data[1]` of a `CAN_ERR_CRTL` error frame.
+const CAN_ERR_CRTL_RX_WARNING: u8 = 0x04;
+const CAN_ERR_CRTL_TX_WARNING: u8 = 0x08;
+const CAN_ERR_CRTL_RX_PASSIVE: u8 = 0x10;
+const CAN_ERR_CRTL_TX_PASSIVE: u8 = 0x20;
+const CAN_ERR_CRTL_ACTIVE: u8 = 0x40;
+
+/// CAN FD flags of the sent frames.
+pub(crate) const TX_FLAGS: u8 = 0x0F;
+
+/// Error classes delivered to the socket.
+const ERR_FILTER: can_err_mask_t =
+    CAN_ERR_CRTL | CAN_ERR_BUSOFF | CAN_ERR_BUSERROR | CAN_ERR_RESTARTED;
 
 /// Error returned by [`Tx::send`].
 #[derive(Error, Debug)]
@@ -32,6 +71,41 @@ pub enum RecvError {
     /// Incomplete read.
     #[error("Incomplete read: {}/{} bytes read", .0, .1)]
     Incomplete(isize, usize),
+    /// Error frame generated by the CAN controller.
+    #[error("Bus error: {:?}", .0)]
+    Bus(ErrorFrame),
+}
+
+/// State of the CAN controller on the bus.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum BusState {
+    /// Normal operation.
+    #[default]
+    ErrorActive,
+    /// An error counter has exceeded the warning level of 96.
+    ErrorWarning,
+    /// An error counter has exceeded 127, the controller no longer sends
+    /// active error flags.
+    ErrorPassive,
+    /// The transmit error counter has exceeded 255, the controller is
+    /// disconnected from the bus until restarted.
+    BusOff,
+}
+
+/// Error frame generated by the CAN controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErrorFrame {
+    /// Error class mask.
+    pub class: canid_t,
+    /// Controller status bits.
+    pub controller: u8,
+    /// Transmit error counter.
+    pub tx_errors: u8,
+    /// Receive error counter.
+    pub rx_errors: u8,
+    /// Raw error frame payload.
+    pub data: [u8; 8],
 }
 
 /// CAN FD socket transmitter.
@@ -46,14 +120,29 @@ pub struct Rx {
     inner: Arc<Socket>,
 }
 
+/// Non-blocking CAN FD socket transmitter.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct AsyncTx {
+    inner: Arc<AsyncFd<Socket>>,
+}
+
+/// Non-blocking CAN FD socket receiver.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct AsyncRx {
+    inner: Arc<AsyncFd<Socket>>,
+}
+
 #[derive(Debug)]
 struct Socket {
     socket: c_int,
+    bus_state: AtomicU8,
 }
 
 /// Creates a new CAN FD socket, returning its tx/rx pair.
 pub fn open<T: ?Sized + NixPath>(name: &T) -> io::Result<(Tx, Rx)> {
-    let mut socket = Socket::new()?;
+    let mut socket = Socket::new(false)?;
     socket.bind(name)?;
     let socket = Arc::new(socket);
     let tx = Tx::from(Arc::clone(&socket));
@@ -61,6 +150,22 @@ pub fn open<T: ?Sized + NixPath>(name: &T) -> io::Result<(Tx, Rx)> {
     Ok((tx, rx))
 }
 
+/// Creates a new non-blocking CAN FD socket registered with the current tokio
+/// runtime, returning its tx/rx pair.
+///
+/// # Panics
+///
+/// If called outside of a tokio runtime.
+#[cfg(feature = "tokio")]
+pub fn open_async<T: ?Sized + NixPath>(name: &T) -> io::Result<(AsyncTx, AsyncRx)> {
+    let mut socket = Socket::new(true)?;
+    socket.bind(name)?;
+    let socket = Arc::new(AsyncFd::new(socket)?);
+    let tx = AsyncTx { inner: Arc::clone(&socket) };
+    let rx = AsyncRx { inner: socket };
+    Ok((tx, rx))
+}
+
 impl From<Arc<Socket>> for Tx {
     fn from(inner: Arc<Socket>) -> Self {
         Self { inner }
@@ -74,18 +179,127 @@ impl From<Arc<Socket>> for Rx {
 }
 
 impl Tx {
+    /// Returns the last bus state observed by the socket.
+    #[must_use]
+    pub fn bus_state(&self) -> BusState {
+        self.inner.bus_state()
+    }
+
     /// Sends `data` with specific `can_id`.
     pub fn send(&self, can_id: canid_t, data: &[u8]) -> Result<(), SendError> {
+        let mut frame = tx_frame(can_id, data)?;
+        let written = self.inner.write_frame(&mut frame)?;
+        check_written(written)
+    }
+}
+
+impl Rx {
+    /// Returns the last bus state observed by the socket.
+    #[must_use]
+    pub fn bus_state(&self) -> BusState {
+        self.inner.bus_state()
+    }
+
+    /// Receives a frame. Error frames are returned as [`RecvError::Bus`] and
+    /// update the [`bus_state`](Self::bus_state).
+    pub fn recv(&self) -> Result<canfd_frame, RecvError> {
+        let mut frame: canfd_frame = unsafe { mem::zeroed() };
+        let read = self.inner.read_frame(&mut frame)?;
+        self.inner.check_read(frame, read)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncTx {
+    /// Returns the last bus state observed by the socket.
+    #[must_use]
+    pub fn bus_state(&self) -> BusState {
+        self.inner.get_ref().bus_state()
+    }
+
+    /// Sends `data` with specific `can_id`, waiting for the socket to become
+    /// writable.
+    pub async fn send(&self, can_id: canid_t, data: &[u8]) -> Result<(), SendError> {
+        let mut frame = tx_frame(can_id, data)?;
+        let written = loop {
+            let mut guard = self.inner.writable().await?;
+            if let Ok(result) = guard.try_io(|socket| socket.get_ref().write_frame(&mut frame)) {
+                break result?;
+            }
+        };
+        check_written(written)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRx {
+    /// Returns the last bus state observed by the socket.
+    #[must_use]
+    pub fn bus_state(&self) -> BusState {
+        self.inner.get_ref().bus_state()
+    }
+
+    /// Resets the bus state to [`BusState::ErrorActive`] after a
+    /// [`restart`] of the interface, which doesn't always deliver a
+    /// `CAN_ERR_RESTARTED` error frame.
+    pub fn reset_bus_state(&self) {
+        self.inner.get_ref().bus_state.store(BusState::ErrorActive as u8, Ordering::Relaxed);
+    }
+
+    /// Receives a frame, waiting for the socket to become readable. Error
+    /// frames are returned as [`RecvError::Bus`] and update the
+    /// [`bus_state`](Self::bus_state).
+    pub async fn recv(&self) -> Result<canfd_frame, RecvError> {
         let mut frame: canfd_frame = unsafe { mem::zeroed() };
-        frame.can_id = can_id;
-        frame.flags = 0x0F;
-        if data.len() > frame.data.len() {
-            return Err(SendError::SizeTooLarge(data.len(), frame.data.len()));
+        let read = loop {
+            let mut guard = self.inner.readable().await?;
+            if let Ok(result) = guard.try_io(|socket| socket.get_ref().read_frame(&mut frame)) {
+                break result?;
+            }
+        };
+        self.inner.get_ref().check_read(frame, read)
+    }
+}
+
+impl Socket {
+    fn new(nonblocking: bool) -> io::Result<Self> {
+        // open socket
+        let mut ty = SOCK_RAW | SOCK_CLOEXEC;
+        if nonblocking {
+            ty |= SOCK_NONBLOCK;
         }
-        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), frame.data.as_mut_ptr(), data.len()) };
-        // ensure discrete CAN FD length values 0..8, 12, 16, 20, 24, 32, 48, 64
-        frame.len = can_fd_dlc_to_len(can_fd_len_to_dlc(data.len()));
-        let mut iov = iovec { iov_base: ptr::addr_of_mut!(frame).cast(), iov_len: CANFD_MTU };
+        let socket = unsafe { socket(PF_CAN, ty, CAN_RAW)? };
+        // try to switch the socket into CAN FD mode
+        let enable_canfd: c_int = 1;
+        unsafe {
+            setsockopt(
+                socket,
+                SOL_CAN_RAW,
+                CAN_RAW_FD_FRAMES,
+                ptr::addr_of!(enable_canfd).cast(),
+                mem::size_of_val(&enable_canfd).try_into().unwrap(),
+            )?;
+        }
+        // receive the error frames needed to track the bus state
+        let err_mask: can_err_mask_t = ERR_FILTER;
+        unsafe {
+            setsockopt(
+                socket,
+                SOL_CAN_RAW,
+                CAN_RAW_ERR_FILTER,
+                ptr::addr_of!(err_mask).cast(),
+                mem::size_of_val(&err_mask).try_into().unwrap(),
+            )?;
+        }
+        Ok(Self { socket, bus_state: AtomicU8::new(BusState::ErrorActive as u8) })
+    }
+
+    fn bus_state(&self) -> BusState {
+        BusState::from_u8(self.bus_state.load(Ordering::Relaxed))
+    }
+
+    fn write_frame(&self, frame: &mut canfd_frame) -> io::Result<isize> {
+        let mut iov = iovec { iov_base: ptr::addr_of_mut!(*frame).cast(), iov_len: CANFD_MTU };
         let msg = msghdr {
             msg_name: ptr::null_mut(),
             msg_namelen: 0,
@@ -95,19 +309,11 @@ impl Tx {
             msg_controllen: 0,
             msg_flags: 0,
         };
-        let written = unsafe { sendmsg(self.inner.socket, ptr::addr_of!(msg).cast(), 0)? };
-        if written != CANFD_MTU.try_into().unwrap() {
-            return Err(SendError::Incomplete(written, CANFD_MTU));
-        }
-        Ok(())
+        unsafe { sendmsg(self.socket, ptr::addr_of!(msg).cast(), 0) }
     }
-}
 
-impl Rx {
-    /// Receives a frame.
-    pub fn recv(&self) -> Result<canfd_frame, RecvError> {
-        let mut frame: canfd_frame = unsafe { mem::zeroed() };
-        let mut iov = iovec { iov_base: ptr::addr_of_mut!(frame).cast(), iov_len: CANFD_MTU };
+    fn read_frame(&self, frame: &mut canfd_frame) -> io::Result<isize> {
+        let mut iov = iovec { iov_base: ptr::addr_of_mut!(*frame).cast(), iov_len: CANFD_MTU };
         let mut msg = msghdr {
             msg_name: ptr::null_mut(),
             msg_namelen: 0,
@@ -117,31 +323,22 @@ impl Rx {
             msg_controllen: 0,
             msg_flags: 0,
         };
-        let read = unsafe { recvmsg(self.inner.socket, ptr::addr_of_mut!(msg).cast(), 0)? };
+        unsafe { recvmsg(self.socket, ptr::addr_of_mut!(msg).cast(), 0) }
+    }
+
+    fn check_read(&self, frame: canfd_frame, read: isize) -> Result<canfd_frame, RecvError> {
+        if read == CAN_MTU.try_into().unwrap() && frame.can_id & CAN_ERR_FLAG != 0 {
+            let error_frame = ErrorFrame::from(&frame);
+            if let Some(bus_state) = error_frame.bus_state() {
+                self.bus_state.store(bus_state as u8, Ordering::Relaxed);
+            }
+            return Err(RecvError::Bus(error_frame));
+        }
         if read != CANFD_MTU.try_into().unwrap() {
             return Err(RecvError::Incomplete(read, CANFD_MTU));
         }
         Ok(frame)
     }
-}
-
-impl Socket {
-    fn new() -> io::Result<Self> {
-        // open socket
-        let socket = unsafe { socket(PF_CAN, SOCK_RAW | SOCK_CLOEXEC, CAN_RAW)? };
-        // try to switch the socket into CAN FD mode
-        let enable_canfd: c_int = 1;
-        unsafe {
-            setsockopt(
-                socket,
-                SOL_CAN_RAW,
-                CAN_RAW_FD_FRAMES,
-                ptr::addr_of!(enable_canfd).cast(),
-                mem::size_of_val(&enable_canfd).try_into().unwrap(),
-            )?;
-        }
-        Ok(Self { socket })
-    }
 
     fn bind<T: ?Sized + NixPath>(&mut self, name: &T) -> io::Result<()> {
         let mut addr: sockaddr_can = unsafe { mem::zeroed() };
@@ -158,6 +355,12 @@ impl Socket {
     }
 }
 
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket
+    }
+}
+
 impl Drop for Socket {
     fn drop(&mut self) {
         unsafe {
@@ -168,6 +371,54 @@ impl Drop for Socket {
     }
 }
 
+impl BusState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::ErrorActive,
+            1 => Self::ErrorWarning,
+            2 => Self::ErrorPassive,
+            _ => Self::BusOff,
+        }
+    }
+}
+
+impl ErrorFrame {
+    /// Returns the bus state reported by the error frame, if any.
+    #[must_use]
+    pub fn bus_state(&self) -> Option<BusState> {
+        if self.class & CAN_ERR_BUSOFF != 0 {
+            return Some(BusState::BusOff);
+        }
+        if self.class & CAN_ERR_RESTARTED != 0 {
+            return Some(BusState::ErrorActive);
+        }
+        if self.class & CAN_ERR_CRTL == 0 {
+            return None;
+        }
+        if self.controller & (CAN_ERR_CRTL_RX_PASSIVE | CAN_ERR_CRTL_TX_PASSIVE) != 0 {
+            Some(BusState::ErrorPassive)
+        } else if self.controller & (CAN_ERR_CRTL_RX_WARNING | CAN_ERR_CRTL_TX_WARNING) != 0 {
+            Some(BusState::ErrorWarning)
+        } else if self.controller & CAN_ERR_CRTL_ACTIVE != 0 {
+            Some(BusState::ErrorActive)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<&canfd_frame> for ErrorFrame {
+    fn from(frame: &canfd_frame) -> Self {
+        Self {
+            class: frame.can_id & CAN_ERR_MASK,
+            controller: frame.data[1],
+            tx_errors: frame.data[6],
+            rx_errors: frame.data[7],
+            data: frame.data[..8].try_into().unwrap(),
+        }
+    }
+}
+
 impl From<io::Error> for SendError {
     fn from(err: io::Error) -> Self {
         Self::Io(err)
@@ -180,6 +431,37 @@ impl From<io::Error> for RecvError {
     }
 }
 
+/// Restarts the CAN interface `name` by bringing it down and up again, which
+/// recovers the controller from the bus-off state. Requires `CAP_NET_ADMIN`.
+///
+/// Sockets bound to the interface stay valid, but may receive an `ENETDOWN`
+/// error in the meantime.
+pub fn restart(name: &str) -> io::Result<()> {
+    ip(&["link", "set", "dev", name, "down"])?;
+    ip(&["link", "set", "dev", name, "up"])
+}
+
+/// Builds a CAN FD frame to send `data` with specific `can_id`.
+fn tx_frame(can_id: canid_t, data: &[u8]) -> Result<canfd_frame, SendError> {
+    let mut frame: canfd_frame = unsafe { mem::zeroed() };
+    frame.can_id = can_id;
+    frame.flags = TX_FLAGS;
+    if data.len() > frame.data.len() {
+        return Err(SendError::SizeTooLarge(data.len(), frame.data.len()));
+    }
+    unsafe { ptr::copy_nonoverlapping(data.as_ptr(), frame.data.as_mut_ptr(), data.len()) };
+    // ensure discrete CAN FD length values 0..8, 12, 16, 20, 24, 32, 48, 64
+    frame.len = can_fd_dlc_to_len(can_fd_len_to_dlc(data.len()));
+    Ok(frame)
+}
+
+fn check_written(written: isize) -> Result<(), SendError> {
+    if written != CANFD_MTU.try_into().unwrap() {
+        return Err(SendError::Incomplete(written, CANFD_MTU));
+    }
+    Ok(())
+}
+
 /// Maps the sanitized data length to an appropriate data length code.
 fn can_fd_len_to_dlc(len: usize) -> u8 {
     const LEN_TO_DLC: &[u8] = &[
@@ -202,3 +484,35 @@ fn can_fd_dlc_to_len(dlc: u8) -> u8 {
     const DLC_TO_LEN: &[u8] = &[0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
     DLC_TO_LEN[usize::from(dlc & 0x0F)]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_frame(class: canid_t, controller: u8) -> ErrorFrame {
+        let mut frame: canfd_frame = unsafe { mem::zeroed() };
+        frame.can_id = CAN_ERR_FLAG | class;
+        frame.data[1] = controller;
+        frame.data[6] = 130;
+        ErrorFrame::from(&frame)
+    }
+
+    #[test]
+    fn test_error_frame_bus_state() {
+        let frame = error_frame(CAN_ERR_CRTL, CAN_ERR_CRTL_TX_PASSIVE);
+        assert_eq!(frame.tx_errors, 130);
+        assert_eq!(frame.bus_state(), Some(BusState::ErrorPassive));
+        let frame = error_frame(CAN_ERR_CRTL, CAN_ERR_CRTL_RX_WARNING);
+        assert_eq!(frame.bus_state(), Some(BusState::ErrorWarning));
+        let frame = error_frame(CAN_ERR_CRTL, CAN_ERR_CRTL_ACTIVE);
+        assert_eq!(frame.bus_state(), Some(BusState::ErrorActive));
+        let frame = error_frame(CAN_ERR_BUSOFF | CAN_ERR_CRTL, CAN_ERR_CRTL_TX_PASSIVE);
+        assert_eq!(frame.bus_state(), Some(BusState::BusOff));
+        let frame = error_frame(CAN_ERR_RESTARTED, 0);
+        assert_eq!(frame.bus_state(), Some(BusState::ErrorActive));
+        assert_eq!(error_frame(CAN_ERR_BUSERROR, 0).bus_state(), None);
+        for state in [BusState::ErrorWarning, BusState::ErrorPassive, BusState::BusOff] {
+            assert_eq!(BusState::from_u8(state as u8), state);
+        }
+    }
+}
@@ -2,10 +2,11 @@
 
 use super::{bind, close, recv, send, setsockopt, socket};
 use libc::{
-    c_int, canid_t, sockaddr_can, AF_CAN, CAN_ISOTP, CAN_MAX_DLEN, CAN_MTU, PF_CAN, SOCK_DGRAM,
+    c_int, canid_t, sockaddr_can, time_t, timeval, AF_CAN, CAN_ISOTP, CAN_MAX_DLEN, CAN_MTU,
+    PF_CAN, SOCK_DGRAM, SOL_SOCKET, SO_RCVTIMEO,
 };
 use nix::{net::if_::if_nametoindex, NixPath};
-use std::{convert::TryInto, io, mem, ptr};
+use std::{convert::TryInto, io, mem, ptr, time::Duration};
 
 /// Undocumented can.h constant.
 pub const SOL_CAN_BASE: c_int = 100;
@@ -138,6 +139,24 @@ impl Socket {
         }
         Ok(())
     }
+
+    /// Sets the timeout of the blocking reads, after which they fail with
+    /// [`io::ErrorKind::WouldBlock`]. A zero timeout blocks indefinitely.
+    pub fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        let timeval = timeval {
+            tv_sec: timeout.as_secs().try_into().unwrap_or(time_t::MAX),
+            tv_usec: timeout.subsec_micros().try_into().unwrap(),
+        };
+        unsafe {
+            setsockopt(
+                self.socket,
+                SOL_SOCKET,
+                SO_RCVTIMEO,
+                ptr::addr_of!(timeval).cast(),
+                mem::size_of::<timeval>().try_into().unwrap(),
+            )
+        }
+    }
 }
 
 impl io::Read for Socket {
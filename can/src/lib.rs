@@ -6,9 +6,11 @@
 
 pub mod fd;
 pub mod isotp;
+pub mod trace;
+pub mod vcan;
 
 use libc::{c_int, c_void, msghdr, size_t, sockaddr, socklen_t, ssize_t};
-use std::io;
+use std::{io, process::Command};
 
 unsafe fn socket(domain: c_int, ty: c_int, protocol: c_int) -> io::Result<c_int> {
     let fd = unsafe { libc::socket(domain, ty, protocol) };
@@ -60,3 +62,13 @@ unsafe fn sendmsg(fd: c_int, msg: *const msghdr, flags: c_int) -> io::Result<ssi
     let result = unsafe { libc::sendmsg(fd, msg, flags) };
     if result == -1 { Err(io::Error::last_os_error()) } else { Ok(result) }
 }
+
+/// Runs the `ip` utility with `args`.
+fn ip(args: &[&str]) -> io::Result<()> {
+    let status = Command::new("ip").args(args).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("`ip {}` failed with {status}", args.join(" "))))
+    }
+}
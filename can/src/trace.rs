@@ -0,0 +1,403 @@
+//! CAN traffic capture and replay.
+//!
+//! Frames are recorded in the `candump -L` log format, so the logs can be
+//! inspected and replayed with the standard `can-utils` as well:
+//!
+//! ```text
+//! (1436509052.249713) can0 123#DEADBEEF
+//! (1436509052.250012) can0 00000080##1DEADBEEF
+//! ```
+//!
+//! Each line holds the timestamp in seconds since the Unix epoch, the
+//! interface name, and the frame. The identifier has 3 hex digits for standard
+//! frames and 8 hex digits for extended and error frames. CAN FD frames use
+//! `##` followed by the flags nibble.
+//!
+//! [`Recorder`] writes the log from a background thread, so recording never
+//! blocks the traffic, and rotates the log once it reaches its size limit.
+
+use crate::fd;
+use libc::{canfd_frame, canid_t, CAN_EFF_FLAG, CAN_EFF_MASK, CAN_ERR_FLAG, CAN_SFF_MASK};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, prelude::*, LineWriter},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+
+/// Capacity of the queue of the entries to write. Entries are dropped while
+/// the queue is full.
+const RECORD_QUEUE_CAPACITY: usize = 4096;
+
+/// Error returned by [`Entry::from_str`].
+#[derive(Error, Debug)]
+pub enum ParseError {
+    /// IO error.
+    #[error("IO error: {}", .0)]
+    Io(#[from] io::Error),
+    /// Malformed log line.
+    #[error("Malformed candump log line: {}", .0)]
+    Malformed(String),
+}
+
+/// Error returned by [`replay`].
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    /// Log parsing error.
+    #[error("Parse error: {}", .0)]
+    Parse(#[from] ParseError),
+    /// Send error.
+    #[error("Send error: {}", .0)]
+    Send(#[from] fd::SendError),
+}
+
+/// Single frame of a CAN log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// Time of the frame since the Unix epoch.
+    pub timestamp: Duration,
+    /// Network interface name.
+    pub interface: String,
+    /// CAN identifier with the EFF/ERR flags.
+    pub can_id: canid_t,
+    /// CAN FD flags, `None` for classic CAN frames.
+    pub fd_flags: Option<u8>,
+    /// Frame payload.
+    pub data: Vec<u8>,
+}
+
+/// Records CAN frames to a log file. Cloned recorders share the same file.
+///
+/// The entries are written by a background thread and are dropped if it falls
+/// behind. Once the log exceeds its size limit, it's renamed with a `.1`
+/// suffix, replacing the previous rotated log, and a new log is started.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    tx: SyncSender<Entry>,
+    dropped: Arc<AtomicUsize>,
+}
+
+/// Size-limited log file written by the [`Recorder`] thread.
+#[derive(Debug)]
+struct LogWriter {
+    path: PathBuf,
+    max_size: u64,
+    size: u64,
+    writer: LineWriter<File>,
+}
+
+impl Entry {
+    /// Creates a new entry from a CAN FD frame received at the current time.
+    #[must_use]
+    pub fn from_frame(interface: &str, frame: &canfd_frame) -> Self {
+        Self {
+            timestamp: SystemTime::UNIX_EPOCH.elapsed().unwrap_or_default(),
+            interface: interface.to_owned(),
+            can_id: frame.can_id,
+            fd_flags: Some(frame.flags),
+            data: frame.data[..usize::from(frame.len).min(frame.data.len())].to_vec(),
+        }
+    }
+
+    /// Creates a new entry from an error frame received at the current time.
+    #[must_use]
+    pub fn from_error_frame(interface: &str, error_frame: &fd::ErrorFrame) -> Self {
+        Self {
+            timestamp: SystemTime::UNIX_EPOCH.elapsed().unwrap_or_default(),
+            interface: interface.to_owned(),
+            can_id: CAN_ERR_FLAG | error_frame.class,
+            fd_flags: None,
+            data: error_frame.data.to_vec(),
+        }
+    }
+
+    /// Returns `true` if the frame is an error frame.
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        self.can_id & CAN_ERR_FLAG != 0
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({}.{:06}) {} ",
+            self.timestamp.as_secs(),
+            self.timestamp.subsec_micros(),
+            self.interface
+        )?;
+        if self.can_id & CAN_ERR_FLAG != 0 {
+            write!(f, "{:08X}", self.can_id & (CAN_ERR_FLAG | libc::CAN_ERR_MASK))?;
+        } else if self.can_id & CAN_EFF_FLAG != 0 {
+            write!(f, "{:08X}", self.can_id & CAN_EFF_MASK)?;
+        } else {
+            write!(f, "{:03X}", self.can_id & CAN_SFF_MASK)?;
+        }
+        match self.fd_flags {
+            Some(flags) => write!(f, "##{:X}", flags & 0x0F)?,
+            None => write!(f, "#")?,
+        }
+        for byte in &self.data {
+            write!(f, "{byte:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Entry {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let malformed = || ParseError::Malformed(line.to_owned());
+        let mut fields = line.split_whitespace();
+        let timestamp = fields
+            .next()
+            .and_then(|field| field.strip_prefix('(')?.strip_suffix(')'))
+            .and_then(|field| field.split_once('.'))
+            .and_then(|(secs, fraction)| {
+                let secs = secs.parse().ok()?;
+                let micros = format!("{fraction:0<6}").get(..6)?.parse().ok()?;
+                Some(Duration::from_secs(secs) + Duration::from_micros(micros))
+            })
+            .ok_or_else(malformed)?;
+        let interface = fields.next().ok_or_else(malformed)?.to_owned();
+        let frame = fields.next().ok_or_else(malformed)?;
+        let (id, rest) = frame.split_once('#').ok_or_else(malformed)?;
+        let can_id = canid_t::from_str_radix(id, 16).map_err(|_| malformed())?;
+        let can_id = match id.len() {
+            3 => can_id,
+            8 if can_id & CAN_ERR_FLAG != 0 => can_id,
+            8 => can_id | CAN_EFF_FLAG,
+            _ => return Err(malformed()),
+        };
+        let (fd_flags, data) = match rest.strip_prefix('#') {
+            Some(rest) => {
+                let flags = rest.get(..1).ok_or_else(malformed)?;
+                (Some(u8::from_str_radix(flags, 16).map_err(|_| malformed())?), &rest[1..])
+            }
+            None => (None, rest),
+        };
+        let data = data.replace('.', "");
+        if data.len() % 2 != 0 {
+            return Err(malformed());
+        }
+        let data = (0..data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&data[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .map_err(|_| malformed())?;
+        Ok(Self { timestamp, interface, can_id, fd_flags, data })
+    }
+}
+
+impl Recorder {
+    /// Creates a new log file at `path`, truncating an existing one, and
+    /// spawns the writer thread. The log is rotated once it exceeds
+    /// `max_size` bytes.
+    pub fn create<P: AsRef<Path>>(path: P, max_size: u64) -> io::Result<Self> {
+        let writer = LogWriter::create(path.as_ref().to_owned(), max_size)?;
+        let (tx, rx) = mpsc::sync_channel(RECORD_QUEUE_CAPACITY);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let writer_dropped = Arc::clone(&dropped);
+        thread::Builder::new()
+            .name("can-trace".to_owned())
+            .spawn(move || writer.run(&rx, &writer_dropped))?;
+        Ok(Self { tx, dropped })
+    }
+
+    /// Queues an entry to append to the log. The entry is dropped if the
+    /// writer thread is behind.
+    pub fn record(&self, entry: Entry) {
+        if self.tx.try_send(entry).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Appends a CAN FD frame received on `interface` at the current time.
+    pub fn record_received(&self, interface: &str, frame: &canfd_frame) {
+        self.record(Entry::from_frame(interface, frame));
+    }
+
+    /// Appends an error frame received on `interface` at the current time.
+    pub fn record_error(&self, interface: &str, error_frame: &fd::ErrorFrame) {
+        self.record(Entry::from_error_frame(interface, error_frame));
+    }
+
+    /// Appends `data` sent with [`fd::Tx::send`] on `interface` at the current
+    /// time.
+    pub fn record_sent(&self, interface: &str, can_id: canid_t, data: &[u8]) {
+        self.record(Entry {
+            timestamp: SystemTime::UNIX_EPOCH.elapsed().unwrap_or_default(),
+            interface: interface.to_owned(),
+            can_id,
+            fd_flags: Some(fd::TX_FLAGS),
+            data: data.to_vec(),
+        });
+    }
+}
+
+impl LogWriter {
+    fn create(path: PathBuf, max_size: u64) -> io::Result<Self> {
+        let writer = LineWriter::new(File::create(&path)?);
+        Ok(Self { path, max_size, size: 0, writer })
+    }
+
+    fn run(mut self, rx: &Receiver<Entry>, dropped: &AtomicUsize) {
+        for entry in rx {
+            if let Err(err) = self.write(&entry) {
+                log::error!("Couldn't record CAN frame: {}", err);
+            }
+            let dropped = dropped.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                log::warn!("{} CAN frames dropped from the trace", dropped);
+            }
+        }
+    }
+
+    fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        if self.size >= self.max_size {
+            self.rotate()?;
+        }
+        let line = format!("{entry}\n");
+        self.writer.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(&self.path, rotated)?;
+        self.writer = LineWriter::new(File::create(&self.path)?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Reads the entries of a candump log. Empty lines are skipped.
+pub fn read<R: BufRead>(reader: R) -> impl Iterator<Item = Result<Entry, ParseError>> {
+    reader.lines().filter_map(|line| match line {
+        Ok(line) if line.trim().is_empty() => None,
+        Ok(line) => Some(line.parse()),
+        Err(err) => Some(Err(err.into())),
+    })
+}
+
+/// Opens and reads the entries of a candump log file.
+pub fn open<P: AsRef<Path>>(
+    path: P,
+) -> io::Result<impl Iterator<Item = Result<Entry, ParseError>>> {
+    Ok(read(io::BufReader::new(File::open(path)?)))
+}
+
+/// Sends the CAN FD frames of `entries` accepted by `filter` to `tx`,
+/// preserving the original inter-frame timing. Classic CAN and error frames
+/// are skipped.
+pub fn replay(
+    entries: impl IntoIterator<Item = Result<Entry, ParseError>>,
+    tx: &fd::Tx,
+    mut filter: impl FnMut(&Entry) -> bool,
+) -> Result<(), ReplayError> {
+    let mut prev_timestamp = None;
+    for entry in entries {
+        let entry = entry?;
+        if entry.fd_flags.is_none() || entry.is_error() || !filter(&entry) {
+            continue;
+        }
+        if let Some(prev_timestamp) = prev_timestamp {
+            thread::sleep(entry.timestamp.saturating_sub(prev_timestamp));
+        }
+        prev_timestamp = Some(entry.timestamp);
+        tx.send(entry.can_id, &entry.data)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn test_entry_format() {
+        let entry = Entry {
+            timestamp: Duration::from_micros(1_436_509_052_249_713),
+            interface: "can0".to_owned(),
+            can_id: 0x80 | CAN_EFF_FLAG,
+            fd_flags: Some(0x0F),
+            data: vec![0xDE, 0xAD, 0xBE, 0xEF],
+        };
+        let line = entry.to_string();
+        assert_eq!(line, "(1436509052.249713) can0 00000080##FDEADBEEF");
+        assert_eq!(line.parse::<Entry>().unwrap(), entry);
+
+        let entry = "(1436509052.5) vcan0 123#01.02".parse::<Entry>().unwrap();
+        assert_eq!(entry.timestamp, Duration::from_millis(1_436_509_052_500));
+        assert_eq!(entry.can_id, 0x123);
+        assert_eq!(entry.fd_flags, None);
+        assert_eq!(entry.data, [1, 2]);
+        assert!(!entry.is_error());
+
+        let entry = "(1.000000) can0 20000040#0000000000000000".parse::<Entry>().unwrap();
+        assert!(entry.is_error());
+        assert_eq!(entry.to_string(), "(1.000000) can0 20000040#0000000000000000");
+
+        let error_frame = fd::ErrorFrame {
+            class: 0x04,
+            controller: 0x20,
+            tx_errors: 130,
+            rx_errors: 0,
+            data: [0, 0x20, 0, 0, 0, 0, 130, 0],
+        };
+        let entry = Entry::from_error_frame("can0", &error_frame);
+        assert!(entry.is_error());
+        assert!(entry.to_string().ends_with(" can0 20000004#0020000000008200"));
+
+        assert!("(1.0) can0 1234#00".parse::<Entry>().is_err());
+        assert!("(1.0) can0 123#0".parse::<Entry>().is_err());
+        assert!("can0 123#00".parse::<Entry>().is_err());
+    }
+
+    #[test]
+    fn test_read() {
+        let mut frame: canfd_frame = unsafe { mem::zeroed() };
+        frame.can_id = 0x80 | CAN_EFF_FLAG;
+        frame.len = 3;
+        frame.data[..3].copy_from_slice(&[1, 2, 3]);
+        let entry = Entry::from_frame("can0", &frame);
+        let log = format!("{entry}\n\n{entry}\n");
+        let entries = read(log.as_bytes()).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].data, [1, 2, 3]);
+        assert_eq!(entries[1].timestamp.as_micros(), entry.timestamp.as_micros());
+    }
+
+    #[test]
+    fn test_log_rotation() {
+        let dir = std::env::temp_dir().join(format!("orb-can-trace-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("can.log");
+        let entry = "(1.000000) can0 00000080##F01020304".parse::<Entry>().unwrap();
+        let line_len = entry.to_string().len() as u64 + 1;
+        let mut writer = LogWriter::create(path.clone(), line_len * 2).unwrap();
+        for _ in 0..3 {
+            writer.write(&entry).unwrap();
+        }
+        writer.writer.flush().unwrap();
+        let count = |path: &Path| open(path).unwrap().count();
+        assert_eq!(count(&dir.join("can.log.1")), 2);
+        assert_eq!(count(&path), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+//! Virtual CAN interfaces for integration tests.
+//!
+//! Requires the `vcan` kernel module and `CAP_NET_ADMIN`. Each [`Vcan`] is a
+//! separate interface with a unique name, so tests using it can run in
+//! parallel. The interface is removed on drop.
+
+use crate::ip;
+use std::{
+    io,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// CAN FD MTU, which enables CAN FD frames on the interface.
+const CANFD_MTU: &str = "72";
+
+static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Virtual CAN FD interface.
+#[derive(Debug)]
+pub struct Vcan {
+    name: String,
+}
+
+impl Vcan {
+    /// Creates a new virtual CAN FD interface with a unique name and brings it
+    /// up.
+    pub fn new() -> io::Result<Self> {
+        // Interface names are limited to 15 characters.
+        let name = format!(
+            "vcan{}x{}",
+            std::process::id() % 100_000,
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        Self::create(name)
+    }
+
+    /// Creates a new virtual CAN FD interface named `name` and brings it up.
+    pub fn create(name: String) -> io::Result<Self> {
+        ip(&["link", "add", "dev", &name, "type", "vcan"])?;
+        let vcan = Self { name };
+        ip(&["link", "set", "dev", &vcan.name, "mtu", CANFD_MTU])?;
+        ip(&["link", "set", "dev", &vcan.name, "up"])?;
+        Ok(vcan)
+    }
+
+    /// Returns the interface name to pass to [`fd::open`](crate::fd::open).
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for Vcan {
+    fn drop(&mut self) {
+        if let Err(err) = ip(&["link", "delete", "dev", &self.name]) {
+            log::error!("Couldn't delete {}: {}", self.name, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fd;
+
+    #[test]
+    #[ignore = "requires the vcan kernel module and CAP_NET_ADMIN"]
+    fn test_vcan_loopback() {
+        let vcan = Vcan::new().unwrap();
+        let (tx, _rx) = fd::open(vcan.name()).unwrap();
+        let (_tx, rx) = fd::open(vcan.name()).unwrap();
+        tx.send(0x42 | libc::CAN_EFF_FLAG, &[1, 2, 3]).unwrap();
+        let frame = rx.recv().unwrap();
+        assert_eq!(frame.can_id, 0x42 | libc::CAN_EFF_FLAG);
+        assert_eq!(&frame.data[..3], &[1, 2, 3]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    #[ignore = "requires the vcan kernel module and CAP_NET_ADMIN"]
+    async fn test_vcan_async_loopback() {
+        let vcan = Vcan::new().unwrap();
+        let (tx, _rx) = fd::open_async(vcan.name()).unwrap();
+        let (_tx, rx) = fd::open_async(vcan.name()).unwrap();
+        tx.send(0x42 | libc::CAN_EFF_FLAG, &[1, 2, 3]).await.unwrap();
+        let frame = rx.recv().await.unwrap();
+        assert_eq!(frame.can_id, 0x42 | libc::CAN_EFF_FLAG);
+        assert_eq!(&frame.data[..3], &[1, 2, 3]);
+    }
+}
@@ -4,7 +4,7 @@
 #![warn(clippy::pedantic)]
 #![allow(clippy::doc_markdown, clippy::missing_errors_doc)]
 
-use ai_interface::InitAgent;
+use ai_interface::{accepts_kwarg, init_kwargs, CancellationToken, InitAgent};
 use eyre::{Result, WrapErr};
 use numpy::PyArray2;
 use pyo3::{prelude::*, types::PyDict};
@@ -13,19 +13,30 @@ use pyo3::{prelude::*, types::PyDict};
 pub struct IrNet<'p> {
     py: Python<'p>,
     agent: &'p PyAny,
+    cancellable: bool,
 }
 
 impl<'p> IrNet<'p> {
     /// Initializes a new [`IrNet`].
+    ///
+    /// The model runs its inference on a CUDA stream with
+    /// `cuda_stream_priority` if provided and supported by the installed
+    /// model.
     #[allow(clippy::missing_panics_doc)]
-    pub fn init(py: Python<'p>, config: &String) -> Result<Self> {
+    pub fn init(
+        py: Python<'p>,
+        config: &String,
+        cuda_stream_priority: Option<i32>,
+    ) -> Result<Self> {
         let ir_net = py.import("ir_net")?;
-        let init: InitAgent =
-            ir_net.getattr("IRNet")?.getattr("init_from_config")?.call1((config,))?.extract()?;
+        let init = ir_net.getattr("IRNet")?.getattr("init_from_config")?;
+        let kwargs = init_kwargs(py, init, cuda_stream_priority)?;
+        let init: InitAgent = init.call((config,), Some(kwargs))?.extract()?;
         let agent =
             init.agent.ok_or_else(|| init.error.expect("error should exist if agent is None"))?;
+        let cancellable = accepts_kwarg(py, agent.getattr("estimate")?, "cancellation_token")?;
 
-        Ok(Self { py, agent })
+        Ok(Self { py, agent, cancellable })
     }
 
     /// Returns the Python module.
@@ -33,18 +44,31 @@ impl<'p> IrNet<'p> {
         py.import("ir_net")
     }
 
+    /// Returns `true` if the model accepts a cancellation token.
+    #[must_use]
+    pub fn is_cancellable(&self) -> bool {
+        self.cancellable
+    }
+
     /// Estimates landmarks, pupil-to-iris ratio, occlusion values and
     /// fractional LaPlace sharpness of a single image of the human eye.
+    ///
+    /// The `cancellation_token` is forwarded only if the installed model
+    /// supports it.
     pub fn estimate(
         &self,
         image: &PyArray2<u8>,
         target_left_eye: bool,
         focus_matrix_code: bool,
+        cancellation_token: Option<CancellationToken>,
     ) -> Result<&PyAny> {
         let kwargs = PyDict::new(self.py);
         if focus_matrix_code {
             kwargs.set_item("focus_matrix_code", true)?;
         }
+        if let Some(cancellation_token) = cancellation_token.filter(|_| self.cancellable) {
+            kwargs.set_item("cancellation_token", Py::new(self.py, cancellation_token)?)?;
+        }
         kwargs.set_item("target_side", i32::from(!target_left_eye))?;
         self.agent
             .call_method("estimate", (image,), Some(kwargs))
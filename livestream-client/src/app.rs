@@ -11,7 +11,7 @@ use eframe::{
     CreationContext, Frame,
 };
 use egui::{load::SizedTexture, CentralPanel, Context, Image, RawInput, Rect, TextureId};
-use livestream_event::{Event, Pos2};
+use livestream_event::{Command, Event, Pos2};
 use std::{
     mem::take,
     sync::Arc,
@@ -42,15 +42,22 @@ pub struct App {
     texture_id: TextureId,
     texture_rect: Rect,
     upstream: Upstream,
+    control: bool,
     events_buffer: Vec<Event>,
     events_last_sent: Instant,
     join_handle: Option<JoinHandle<()>>,
 }
 
 impl App {
-    /// Creates a new [`App`].
+    /// Creates a new [`App`]. If `control` is `true`, the remote control
+    /// shortcuts are sent as commands instead of key events.
     #[must_use]
-    pub fn new(cc: &CreationContext<'_>, downstream: Downstream, upstream: Upstream) -> Self {
+    pub fn new(
+        cc: &CreationContext<'_>,
+        downstream: Downstream,
+        upstream: Upstream,
+        control: bool,
+    ) -> Self {
         let wgpu = cc.wgpu_render_state.as_ref().expect("renderer is not wgpu");
         let queue = Arc::clone(&wgpu.queue);
         let texture = wgpu.device.create_texture(&TextureDescriptor {
@@ -74,6 +81,7 @@ impl App {
             texture_id,
             texture_rect: Rect::from_min_max(egui::Pos2::ZERO, TEXTURE_SIZE.to_pos2()),
             upstream,
+            control,
             events_buffer: Vec::new(),
             events_last_sent: Instant::now(),
             join_handle: Some(join_handle),
@@ -114,6 +122,16 @@ impl eframe::App for App {
     fn raw_input_hook(&mut self, _ctx: &Context, raw_input: &mut RawInput) {
         for event in &raw_input.events {
             let Ok(mut event) = event.try_into() else { continue };
+            if let Event::Key { key, pressed, modifiers, .. } = &event {
+                if self.control {
+                    if let Some(command) = Command::from_shortcut(key, modifiers) {
+                        if *pressed {
+                            self.events_buffer.push(Event::Control(command));
+                        }
+                        continue;
+                    }
+                }
+            }
             if let Event::PointerMoved(pos) | Event::PointerButton { pos, .. } = &mut event {
                 *pos = Pos2 {
                     x: ((pos.x - self.texture_rect.min.x) / self.texture_rect.width())
@@ -1,6 +1,6 @@
 //! Command Line Interface.
 
-use std::net::IpAddr;
+use std::{net::IpAddr, path::PathBuf};
 
 use clap::StructOpt;
 
@@ -10,4 +10,9 @@ use clap::StructOpt;
 pub struct Cli {
     /// IP-address of the Orb
     pub ip: IpAddr,
+    /// Path to the orb control token file. Enables the remote control
+    /// shortcuts: F5 runs the health check, F2 toggles the overlays, and
+    /// Ctrl+arrows move the mirror while the orb is idle.
+    #[clap(long)]
+    pub control_token: Option<PathBuf>,
 }
@@ -14,7 +14,8 @@ use clap::Parser as _;
 use cli::Cli;
 use downstream::Downstream;
 use egui::ViewportBuilder;
-use eyre::{eyre, Result};
+use eyre::{eyre, Result, WrapErr as _};
+use std::fs;
 use upstream::Upstream;
 
 /// Livestream frame width.
@@ -26,9 +27,15 @@ pub const LIVESTREAM_FRAME_HEIGHT: u32 = 1080;
 fn main() -> Result<()> {
     color_eyre::install()?;
     gstreamer::init()?;
-    let Cli { ip } = Cli::parse();
-
-    let upstream = Upstream::new(ip)?;
+    let Cli { ip, control_token } = Cli::parse();
+
+    let mut upstream = Upstream::new(ip)?;
+    let control = control_token.is_some();
+    if let Some(path) = control_token {
+        let token = fs::read_to_string(&path)
+            .wrap_err_with(|| format!("reading control token from {}", path.display()))?;
+        upstream.authenticate(token.trim())?;
+    }
     let downstream = Downstream::new()?;
 
     #[allow(clippy::cast_precision_loss)]
@@ -43,7 +50,7 @@ fn main() -> Result<()> {
     eframe::run_native(
         "Orb Livestream Client",
         options,
-        Box::new(|cc| Box::new(App::new(cc, downstream, upstream))),
+        Box::new(|cc| Box::new(App::new(cc, downstream, upstream, control))),
     )
     .map_err(|err| eyre!("failed to run eframe app: {err}"))?;
 
@@ -1,6 +1,7 @@
 //! Upstream for the events stream.
 
 use eyre::Result;
+use livestream_event::Event;
 use std::{
     io::prelude::*,
     net::{IpAddr, TcpStream},
@@ -20,6 +21,12 @@ impl Upstream {
         Ok(Self { stream })
     }
 
+    /// Authenticates the connection for the remote control commands.
+    pub fn authenticate(&mut self, token: &str) -> Result<()> {
+        let bytes = rkyv::to_bytes::<_, 256>(&vec![Event::Authenticate(token.to_owned())])?;
+        self.send(&bytes)
+    }
+
     /// Sends the given input to the upstream.
     pub fn send(&mut self, bytes: &[u8]) -> Result<()> {
         let len: u32 = bytes.len().try_into().unwrap();
@@ -33,6 +33,34 @@ pub enum Event {
     CompositionUpdate(String),
     CompositionEnd(String),
     MouseWheel { unit: MouseWheelUnit, delta: Vec2, modifiers: Modifiers },
+    Authenticate(String),
+    Control(Command),
+}
+
+/// Remote hardware control command.
+///
+/// Sent as [`Event::Control`], and ignored by the orb unless the connection
+/// has been authenticated with [`Event::Authenticate`] carrying the orb control
+/// token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive(check_bytes)]
+pub enum Command {
+    /// Run the health check.
+    TriggerHealthCheck,
+    /// Show or hide the estimation overlays on the livestream.
+    ToggleOverlay,
+    /// Move the mirror one step in the given direction.
+    MoveMirror(Direction),
+}
+
+/// Mirror movement direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+#[archive(check_bytes)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
 }
 
 #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
@@ -216,9 +244,30 @@ impl TryFrom<&egui::Event> for Event {
     }
 }
 
-impl Into<egui::Event> for Event {
-    fn into(self) -> egui::Event {
-        match self {
+impl Command {
+    /// Maps a key press to a remote control command.
+    ///
+    /// F5 triggers the health check, F2 toggles the overlays, and the arrow
+    /// keys with the Ctrl modifier move the mirror.
+    #[must_use]
+    pub fn from_shortcut(key: &Key, modifiers: &Modifiers) -> Option<Self> {
+        match key {
+            Key::F5 => Some(Self::TriggerHealthCheck),
+            Key::F2 => Some(Self::ToggleOverlay),
+            Key::ArrowUp if modifiers.ctrl => Some(Self::MoveMirror(Direction::Up)),
+            Key::ArrowDown if modifiers.ctrl => Some(Self::MoveMirror(Direction::Down)),
+            Key::ArrowLeft if modifiers.ctrl => Some(Self::MoveMirror(Direction::Left)),
+            Key::ArrowRight if modifiers.ctrl => Some(Self::MoveMirror(Direction::Right)),
+            _ => None,
+        }
+    }
+}
+
+impl TryInto<egui::Event> for Event {
+    type Error = ();
+
+    fn try_into(self) -> Result<egui::Event, Self::Error> {
+        Ok(match self {
             Event::Copy => egui::Event::Copy,
             Event::Cut => egui::Event::Cut,
             Event::Paste(string) => egui::Event::Paste(string),
@@ -250,7 +299,8 @@ impl Into<egui::Event> for Event {
                 delta: delta.into(),
                 modifiers: modifiers.into(),
             },
-        }
+            Event::Authenticate(_) | Event::Control(_) => return Err(()),
+        })
     }
 }
 
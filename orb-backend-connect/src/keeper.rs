@@ -0,0 +1,176 @@
+//! Connectivity keeper daemon mode.
+//!
+//! Keeps checking the backend connectivity after the initial connection. On
+//! loss, the WiFi plan is re-run after an exponentially growing delay, which is
+//! reset once the connection stays up for [`STABLE_PERIOD`]. The orb hardware
+//! is acquired only for the WiFi plan and released right after it, so orb-core
+//! can use it in the meantime. The operator LEDs follow the network monitor
+//! reports, and the state is exposed over DBus.
+
+use super::Shared;
+use eyre::Result;
+use futures::prelude::*;
+use orb::{
+    consts::{DBUS_BACKEND_CONNECT_BUS_NAME, DBUS_CONNECTIVITY_OBJECT_PATH},
+    dbus::{Connectivity, ConnectivityState},
+    dd_incr,
+    monitor::{
+        self,
+        net::{Diagnosis, Monitor as _},
+    },
+    network,
+    plans::wifi,
+    ui::{self, Engine as _},
+};
+use std::time::{Duration, Instant};
+use tokio::{task, time::sleep};
+
+/// Interval between the connectivity checks.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Initial delay before re-running the WiFi plan.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Maximum delay before re-running the WiFi plan.
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Uptime after which the connection is considered stable again.
+const STABLE_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+/// Exponential backoff between the reconnection attempts.
+#[derive(Debug, Default)]
+struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    /// Returns the delay before the next attempt.
+    fn next_delay(&mut self) -> Duration {
+        let delay = INITIAL_BACKOFF.saturating_mul(2_u32.saturating_pow(self.attempt));
+        self.attempt = self.attempt.saturating_add(1);
+        delay.min(MAX_BACKOFF)
+    }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Runs the connectivity keeper forever.
+pub async fn run(shared: &Shared) -> Result<()> {
+    task::spawn(update_leds(shared.ui.clone(), shared.net_monitor.clone()));
+    let dbus = init_dbus()
+        .await
+        .map_err(|err| {
+            tracing::error!("failed to initialize dbus connection, leaving disabled; error: {err}");
+        })
+        .ok();
+    let mut backoff = Backoff::default();
+    let mut recovered_at = Some(Instant::now());
+    loop {
+        match network::status().await {
+            Ok(network::Status::Connected { has_internet: true }) => {
+                if recovered_at.is_some_and(|recovered_at| recovered_at.elapsed() >= STABLE_PERIOD)
+                {
+                    backoff.reset();
+                    recovered_at = None;
+                }
+                set_state(dbus.as_ref(), ConnectivityState::Connected, None).await;
+                sleep(CHECK_INTERVAL).await;
+                continue;
+            }
+            Ok(status) => tracing::warn!("Network connection lost: {status:?}"),
+            Err(err) => tracing::error!("Checking network status failed: {err:?}"),
+        }
+        dd_incr!("main.count.global.network_lost");
+        let delay = backoff.next_delay();
+        tracing::info!("Re-running WiFi plan in {delay:?}");
+        set_state(dbus.as_ref(), ConnectivityState::Lost, Some(delay)).await;
+        sleep(delay).await;
+        set_state(dbus.as_ref(), ConnectivityState::Reconnecting, None).await;
+        let result = match shared.build_orb().await {
+            Ok(mut orb) => wifi::Plan.ensure_network_connection(&mut orb).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => {
+                dd_incr!("main.count.global.network_connected");
+                recovered_at = Some(Instant::now());
+            }
+            Err(err) => tracing::error!("WiFi plan failed: {err:?}"),
+        }
+    }
+}
+
+/// Shows the network quality on the operator LEDs.
+async fn update_leds(ui: Box<dyn ui::Engine>, mut net_monitor: Box<dyn monitor::net::Monitor>) {
+    while let Some(report) = net_monitor.next().await {
+        match report.reachability.map_or(Diagnosis::Ok, |reachability| reachability.diagnosis()) {
+            Diagnosis::NoInternet => ui.no_internet(),
+            Diagnosis::DnsFailure => ui.dns_failure(),
+            Diagnosis::BackendUnreachable => ui.backend_unreachable(),
+            Diagnosis::RelayUnreachable => ui.relay_unreachable(),
+            Diagnosis::Ok if report.is_no_internet() => ui.no_internet(),
+            Diagnosis::Ok if report.is_slow_internet() => ui.slow_internet(),
+            Diagnosis::Ok => ui.good_internet(),
+        }
+    }
+}
+
+async fn init_dbus() -> zbus::Result<zbus::Connection> {
+    zbus::ConnectionBuilder::session()?
+        .name(DBUS_BACKEND_CONNECT_BUS_NAME)?
+        .serve_at(DBUS_CONNECTIVITY_OBJECT_PATH, Connectivity::default())?
+        .build()
+        .await
+}
+
+/// Updates the DBus interface, emitting the property change signals.
+async fn set_state(
+    dbus: Option<&zbus::Connection>,
+    state: ConnectivityState,
+    backoff: Option<Duration>,
+) {
+    let Some(dbus) = dbus else { return };
+    let result = async {
+        let iface = dbus
+            .object_server()
+            .interface::<_, Connectivity>(DBUS_CONNECTIVITY_OBJECT_PATH)
+            .await?;
+        let mut connectivity = iface.get_mut().await;
+        let backoff = backoff.map_or(0, |backoff| backoff.as_secs());
+        if connectivity.state == state && connectivity.backoff == backoff {
+            return Ok(());
+        }
+        if state == ConnectivityState::Reconnecting {
+            connectivity.reconnects += 1;
+            connectivity.reconnects_changed(iface.signal_context()).await?;
+        }
+        connectivity.state = state;
+        connectivity.backoff = backoff;
+        connectivity.state_changed(iface.signal_context()).await?;
+        connectivity.backoff_changed(iface.signal_context()).await
+    };
+    if let Err(err) = result.await {
+        tracing::error!("Failed to update the connectivity DBus interface: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::default();
+        assert_eq!(backoff.next_delay(), INITIAL_BACKOFF);
+        assert_eq!(backoff.next_delay(), INITIAL_BACKOFF * 2);
+        assert_eq!(backoff.next_delay(), INITIAL_BACKOFF * 4);
+        for _ in 0..40 {
+            backoff.next_delay();
+        }
+        assert_eq!(backoff.next_delay(), MAX_BACKOFF);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), INITIAL_BACKOFF);
+    }
+}
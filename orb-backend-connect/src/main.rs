@@ -1,42 +1,77 @@
+mod keeper;
+
+use clap::Parser;
 use eyre::Result;
 use orb::{
     async_main,
     brokers::Orb,
     config::Config,
-    dd_incr, logger,
-    mcu::{self, Mcu},
-    monitor,
+    dd_incr, logger, mcu,
+    monitor::{self, cpu::Monitor as _, net::Monitor as _},
     plans::wifi,
     ui::{self, Engine},
 };
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+/// Establishes the backend connection.
+#[derive(Parser, Debug)]
+#[clap(about)]
+struct Cli {
+    /// Keep monitoring the connectivity and reconnect on loss instead of
+    /// exiting after the connection is established.
+    #[clap(long)]
+    daemon: bool,
+}
+
 fn main() -> Result<()> {
-    async_main(run())
+    let cli = Cli::parse();
+    async_main(run(cli))
 }
 
-async fn run() -> Result<()> {
+async fn run(cli: Cli) -> Result<()> {
     logger::init::<false>();
     let ui = ui::Jetson::spawn();
     let config = Arc::new(Mutex::new(Config::load_or_default().await));
     config.lock().await.propagate_to_ui(&ui);
-    let main_mcu = Box::new(mcu::main::Jetson::spawn()?);
     let net_monitor = monitor::net::Jetson::spawn(Arc::clone(&config))
         .expect("did you forget 'setcap cap_net_raw+ep'?");
-    let cpu_monitor = Box::new(monitor::cpu::Jetson::spawn());
+    let cpu_monitor = monitor::cpu::Jetson::spawn();
     ui.bootup();
 
-    let mut orb = Orb::builder()
-        .config(Arc::clone(&config))
-        .ui(ui.clone())
-        .main_mcu(main_mcu.clone())
-        .net_monitor(Box::new(net_monitor))
-        .cpu_monitor(cpu_monitor)
-        .build()
-        .await?;
-
+    let shared = Shared { config, ui, net_monitor, cpu_monitor, disable_dbus: cli.daemon };
+    let mut orb = shared.build_orb().await?;
     wifi::Plan.ensure_network_connection(&mut orb).await?;
     dd_incr!("main.count.global.network_connected");
+    if cli.daemon {
+        // Release the hardware for orb-core while the connection is up.
+        drop(orb);
+        keeper::run(&shared).await?;
+    }
     Ok(())
 }
+
+/// Resources kept for the whole run, unlike the orb hardware.
+struct Shared {
+    config: Arc<Mutex<Config>>,
+    ui: ui::Jetson,
+    net_monitor: monitor::net::Jetson,
+    cpu_monitor: monitor::cpu::Jetson,
+    disable_dbus: bool,
+}
+
+impl Shared {
+    /// Builds an orb with its own main MCU interface, which is released when
+    /// the orb is dropped.
+    async fn build_orb(&self) -> Result<Orb> {
+        Orb::builder()
+            .config(Arc::clone(&self.config))
+            .ui(self.ui.clone())
+            .main_mcu(Box::new(mcu::main::Jetson::spawn()?))
+            .net_monitor(self.net_monitor.clone())
+            .cpu_monitor(self.cpu_monitor.clone())
+            .disable_dbus(self.disable_dbus)
+            .build()
+            .await
+    }
+}
@@ -3,28 +3,70 @@
 //! This needs to be a seperate and minimal binary,
 //! since it is going to have the SETUID/SETGID bit set
 //!
+//! With `--json`, the id is printed together with its provenance: whether it
+//! was read from the fuses or from `orb-id-legacy`, the `odm_lock` state, and
+//! a Secure Element signature over [`signed_message`]. Provisioning tooling
+//! verifies the signature with the orb public key instead of trusting the raw
+//! output. The Secure Element key is shared with the iris code signatures, so
+//! the message starts with [`SIGNATURE_DOMAIN`] and can't be mistaken for one.
+//! The JSON is written by hand to keep the binary free of extra dependencies,
+//! all of its values are validated beforehand.
+//!
 //! <https://www.notion.so/worldcoin/Fuse-Burning-eb91c53aa4a84db481f93a64290c46f5#b96ab8dc3a85421a91343f4c9b4c64b9>
 
 #![warn(clippy::pedantic)]
 #![allow(clippy::doc_markdown, clippy::missing_errors_doc)]
 
-use eyre::{eyre, Result, WrapErr as _};
-use std::{fs, process::Command, str};
+use data_encoding::BASE64;
+use eyre::{bail, eyre, Result, WrapErr as _};
+use std::{
+    env, fs,
+    io::Write as _,
+    process::{Command, Stdio},
+    str,
+};
 
 const ODM_LOCK_FUSE_PATH: &str = "/sys/devices/platform/tegra-fuse/odm_lock";
 const RESERVED_ODM0_FUSE_PATH: &str = "/sys/devices/platform/tegra-fuse/reserved_odm0";
+const SIGN_PATH: &str = "/usr/bin/orb-sign-iris-code";
+
+/// Domain-separation tag of the `--json` signature.
+const SIGNATURE_DOMAIN: &str = "orb-id-provenance-v1";
 
 const ODM_LOCK_MASK: i32 = 0x0000_0001;
 
+/// Where the orb-id was read from.
+#[derive(Clone, Copy)]
+enum Source {
+    /// The `reserved_odm0` fuse, locked by `odm_lock`.
+    Fused,
+    /// The `orb-id-legacy` tool, for orbs without burned fuses.
+    Legacy,
+}
+
+impl Source {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Fused => "fused",
+            Self::Legacy => "legacy",
+        }
+    }
+}
+
 fn main() -> Result<()> {
+    let json = match env::args().nth(1).as_deref() {
+        None => false,
+        Some("--json") => true,
+        Some(arg) => bail!("unexpected argument `{arg}`, usage: orb-id [--json]"),
+    };
     let odm_lock_set = check_odm_lock_set().wrap_err("failed to `check the odm_lock` fuse")?;
 
-    let orb_id = if odm_lock_set {
+    let (orb_id, source) = if odm_lock_set {
         let reserved_odm0_string = fs::read_to_string(RESERVED_ODM0_FUSE_PATH)
             .wrap_err_with(|| format!("unable to read `{RESERVED_ODM0_FUSE_PATH}`"))?;
 
         // The string is in format of "0x00000000", returning 8 chars without the prefix.
-        reserved_odm0_string[2..10].to_string()
+        (reserved_odm0_string[2..10].to_string(), Source::Fused)
     } else {
         let output = Command::new("/usr/local/bin/orb-id-legacy")
             .output()
@@ -36,11 +78,26 @@ fn main() -> Result<()> {
             .ok_or_else(|| eyre!("`/usr/local/bin/orb-id-legacy` terminated unsuccessfully"))?;
 
         // TODO(andronat): We should remove legacy support.
-        str::from_utf8(&output.stdout)
+        let orb_id = str::from_utf8(&output.stdout)
             .wrap_err("failed parsing `orb-id-legacy` output as utf8")?
-            .to_string()
+            .to_string();
+        (orb_id, Source::Legacy)
     };
-    print!("{orb_id}");
+    if json {
+        let orb_id = orb_id.trim();
+        if !orb_id.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            bail!("orb-id `{orb_id}` is not a hex string");
+        }
+        let signature = sign(&signed_message(orb_id, source, odm_lock_set))
+            .wrap_err("failed signing the orb-id with the Secure Element")?;
+        let source = source.as_str();
+        println!(
+            "{{\"orb_id\":\"{orb_id}\",\"source\":\"{source}\",\"odm_lock\":{odm_lock_set},\"\
+             signature\":\"{signature}\"}}"
+        );
+    } else {
+        print!("{orb_id}");
+    }
     Ok(())
 }
 
@@ -53,3 +110,38 @@ fn check_odm_lock_set() -> Result<bool> {
         .wrap_err_with(|| format!("failed parsing `{subslice}` as hex string"))?;
     Ok((ODM_LOCK_MASK & odm_lock_value) == 1)
 }
+
+/// Returns the message covered by the `--json` signature: the
+/// [`SIGNATURE_DOMAIN`] tag, the orb-id, the source, and the `odm_lock` state,
+/// separated by newlines, e.g. `"orb-id-provenance-v1\n0a1b2c3d\nfused\ntrue"`.
+fn signed_message(orb_id: &str, source: Source, odm_lock_set: bool) -> String {
+    format!("{SIGNATURE_DOMAIN}\n{orb_id}\n{}\n{odm_lock_set}", source.as_str())
+}
+
+/// Signs the message with the Secure Element and returns the base64-encoded
+/// signature.
+fn sign(message: &str) -> Result<String> {
+    let mut child = Command::new(SIGN_PATH)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .wrap_err_with(|| format!("failed running `{SIGN_PATH}`"))?;
+    let mut stdin = child.stdin.take().ok_or_else(|| eyre!("`{SIGN_PATH}` stdin is closed"))?;
+    stdin.write_all(BASE64.encode(message.as_bytes()).as_bytes())?;
+    drop(stdin);
+    let output =
+        child.wait_with_output().wrap_err_with(|| format!("failed waiting `{SIGN_PATH}`"))?;
+    output
+        .status
+        .success()
+        .then_some(())
+        .ok_or_else(|| eyre!("`{SIGN_PATH}` terminated unsuccessfully"))?;
+    let signature = str::from_utf8(&output.stdout)
+        .wrap_err_with(|| format!("failed parsing `{SIGN_PATH}` output"))?
+        .trim();
+    if BASE64.decode(signature.as_bytes()).is_err() {
+        bail!("`{SIGN_PATH}` output is not base64");
+    }
+    Ok(signature.to_string())
+}
@@ -4,6 +4,7 @@
 #![warn(clippy::pedantic)]
 #![allow(clippy::doc_markdown, clippy::missing_errors_doc)]
 
+use ai_interface::{accepts_kwarg, CancellationToken};
 use numpy::PyArray3;
 use pyo3::{prelude::*, types::PyDict};
 
@@ -11,6 +12,7 @@ use pyo3::{prelude::*, types::PyDict};
 pub struct RgbNet<'p> {
     rgb_net: &'p PyModule,
     agent: &'p PyAny,
+    cancellable: bool,
 }
 
 impl<'p> RgbNet<'p> {
@@ -19,7 +21,8 @@ impl<'p> RgbNet<'p> {
         let rgb_net = py.import("rgb_net")?;
         let kwargs = PyDict::new(py);
         let agent = rgb_net.getattr("RGBNet")?.call((), Some(kwargs))?;
-        Ok(Self { rgb_net, agent })
+        let cancellable = accepts_kwarg(py, agent.getattr("estimate")?, "cancellation_token")?;
+        Ok(Self { rgb_net, agent, cancellable })
     }
 
     /// Returns the Python module.
@@ -33,8 +36,28 @@ impl<'p> RgbNet<'p> {
         self.rgb_net.getattr("__version__")?.extract()
     }
 
+    /// Returns `true` if the model accepts a cancellation token.
+    #[must_use]
+    pub fn is_cancellable(&self) -> bool {
+        self.cancellable
+    }
+
     /// Estimate the position of the eyes.
-    pub fn estimate(&self, image: &PyArray3<u8>) -> PyResult<&PyAny> {
-        self.agent.call_method1("estimate", (image,))
+    ///
+    /// The `cancellation_token` is forwarded only if the installed model
+    /// supports it.
+    pub fn estimate(
+        &self,
+        image: &PyArray3<u8>,
+        cancellation_token: Option<CancellationToken>,
+    ) -> PyResult<&PyAny> {
+        match cancellation_token.filter(|_| self.cancellable) {
+            Some(cancellation_token) => {
+                let kwargs = PyDict::new(image.py());
+                kwargs.set_item("cancellation_token", Py::new(image.py(), cancellation_token)?)?;
+                self.agent.call_method("estimate", (image,), Some(kwargs))
+            }
+            None => self.agent.call_method1("estimate", (image,)),
+        }
     }
 }
@@ -16,6 +16,15 @@ pub struct Camera {
     frame_rx: mpsc::Receiver<Frame>,
 }
 
+/// Mode of operation for acquisition of the exposure time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExposureMode {
+    /// The camera adjusts the exposure time on its own.
+    Automatic,
+    /// The exposure time is set with [`Camera::set_exposure_time`].
+    Manual,
+}
+
 /// Error returned from `Camera::attach`.
 #[derive(Debug, thiserror::Error)]
 pub enum AttachError {
@@ -149,20 +158,33 @@ impl Camera {
         unsafe { result_from(royale_sys::camera_set_frame_rate(self.camera_ptr, frame_rate)) }
     }
 
+    /// Sets the frame rate to the closest value supported by the current use
+    /// case, i.e. limited by [`Camera::get_max_frame_rate`]. Returns the
+    /// applied frame rate.
+    ///
+    /// # Errors
+    ///
+    /// This method can result in a generic [`Error`].
+    #[cfg(all(target_arch = "aarch64", target_os = "linux"))]
+    pub fn select_frame_rate(&self, frame_rate: u16) -> Result<u16, Error> {
+        let frame_rate = frame_rate.clamp(1, self.get_max_frame_rate()?.max(1));
+        self.set_frame_rate(frame_rate)?;
+        Ok(frame_rate)
+    }
+
     /// Retrieves the current mode of operation for acquisition of the exposure
-    /// time. Returns true if the camera is in manual exposure mode, otherwise
-    /// false.
+    /// time.
     ///
     /// # Errors
     ///
     /// This method can result in a generic [`Error`].
     #[cfg(all(target_arch = "aarch64", target_os = "linux"))]
-    pub fn is_exposure_mode_manual(&self) -> Result<bool, Error> {
+    pub fn get_exposure_mode(&self) -> Result<ExposureMode, Error> {
         let mut is_manual = false;
         unsafe {
             result_from(royale_sys::camera_get_exposure_mode(self.camera_ptr, &mut is_manual))?;
         }
-        Ok(is_manual)
+        Ok(if is_manual { ExposureMode::Manual } else { ExposureMode::Automatic })
     }
 
     /// Changes the exposure mode for the supported operated operation modes.
@@ -171,7 +193,8 @@ impl Camera {
     ///
     /// This method can result in a generic [`Error`].
     #[cfg(all(target_arch = "aarch64", target_os = "linux"))]
-    pub fn set_exposure_mode(&self, is_manual: bool) -> Result<(), Error> {
+    pub fn set_exposure_mode(&self, mode: ExposureMode) -> Result<(), Error> {
+        let is_manual = matches!(mode, ExposureMode::Manual);
         unsafe { result_from(royale_sys::camera_set_exposure_mode(self.camera_ptr, is_manual)) }
     }
 
@@ -194,7 +217,8 @@ impl Camera {
         Ok(limits)
     }
 
-    /// Changes the exposure time for the supported operated operation modes.
+    /// Changes the exposure time in microseconds for the supported operated
+    /// operation modes. The camera must be in the [`ExposureMode::Manual`].
     ///
     /// # Errors
     ///
@@ -6,7 +6,9 @@
 mod camera;
 mod error;
 mod frame;
+mod point_cloud;
 
-pub use camera::{AttachError, Camera};
+pub use camera::{AttachError, Camera, ExposureMode};
 pub use error::Error;
 pub use frame::{DepthPoint, Frame};
+pub use point_cloud::PointCloud;
@@ -0,0 +1,189 @@
+use crate::Frame;
+use std::io::{self, prelude::*};
+
+/// Organized point cloud.
+///
+/// The points are stored in separate arrays in row-major order, preserving the
+/// sensor layout. Coordinates are in meters.
+#[derive(Clone, Debug, Default)]
+pub struct PointCloud {
+    width: u16,
+    height: u16,
+    x: Vec<f32>,
+    y: Vec<f32>,
+    z: Vec<f32>,
+    confidence: Vec<u8>,
+}
+
+impl PointCloud {
+    /// Creates an organized point cloud from a depth frame.
+    #[must_use]
+    pub fn from_frame(frame: &Frame) -> Self {
+        let len = frame.len();
+        let mut point_cloud = Self {
+            width: frame.width(),
+            height: frame.height(),
+            x: Vec::with_capacity(len),
+            y: Vec::with_capacity(len),
+            z: Vec::with_capacity(len),
+            confidence: Vec::with_capacity(len),
+        };
+        for point in frame.iter() {
+            point_cloud.x.push(point.x);
+            point_cloud.y.push(point.y);
+            point_cloud.z.push(point.z);
+            point_cloud.confidence.push(point.depth_confidence);
+        }
+        point_cloud
+    }
+
+    /// Returns the point cloud width.
+    #[must_use]
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Returns the point cloud height.
+    #[must_use]
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Returns the number of points.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.confidence.len()
+    }
+
+    /// Returns `true` if the point cloud has no points.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.confidence.is_empty()
+    }
+
+    /// Returns the X coordinates in meters.
+    #[must_use]
+    pub fn x(&self) -> &[f32] {
+        &self.x
+    }
+
+    /// Returns the Y coordinates in meters.
+    #[must_use]
+    pub fn y(&self) -> &[f32] {
+        &self.y
+    }
+
+    /// Returns the Z coordinates in meters.
+    #[must_use]
+    pub fn z(&self) -> &[f32] {
+        &self.z
+    }
+
+    /// Returns the confidence values from 0 (invalid) to 255 (full
+    /// confidence).
+    #[must_use]
+    pub fn confidence(&self) -> &[u8] {
+        &self.confidence
+    }
+
+    /// Writes the point cloud in the binary PCD format. The layout is kept
+    /// organized, invalid points have NaN coordinates.
+    ///
+    /// # Errors
+    ///
+    /// If writing to `writer` fails.
+    pub fn write_pcd<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(
+            writer,
+            "# .PCD v0.7 - Point Cloud Data file format\nVERSION 0.7\nFIELDS x y z \
+             confidence\nSIZE 4 4 4 1\nTYPE F F F U\nCOUNT 1 1 1 1\nWIDTH {}\nHEIGHT \
+             {}\nVIEWPOINT 0 0 0 1 0 0 0\nPOINTS {}\nDATA binary\n",
+            self.width,
+            self.height,
+            self.len()
+        )?;
+        for i in 0..self.len() {
+            let [x, y, z] = if self.confidence[i] == 0 {
+                [f32::NAN; 3]
+            } else {
+                [self.x[i], self.y[i], self.z[i]]
+            };
+            writer.write_all(&x.to_le_bytes())?;
+            writer.write_all(&y.to_le_bytes())?;
+            writer.write_all(&z.to_le_bytes())?;
+            writer.write_all(&[self.confidence[i]])?;
+        }
+        Ok(())
+    }
+
+    /// Writes the point cloud in the binary PLY format. Invalid points are
+    /// omitted, the original layout is recorded in a header comment.
+    ///
+    /// # Errors
+    ///
+    /// If writing to `writer` fails.
+    pub fn write_ply<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let valid = self.confidence.iter().filter(|&&confidence| confidence > 0).count();
+        write!(
+            writer,
+            "ply\nformat binary_little_endian 1.0\ncomment organized {} {}\nelement vertex \
+             {valid}\nproperty float x\nproperty float y\nproperty float z\nproperty uchar \
+             confidence\nend_header\n",
+            self.width, self.height
+        )?;
+        for i in (0..self.len()).filter(|&i| self.confidence[i] > 0) {
+            writer.write_all(&self.x[i].to_le_bytes())?;
+            writer.write_all(&self.y[i].to_le_bytes())?;
+            writer.write_all(&self.z[i].to_le_bytes())?;
+            writer.write_all(&[self.confidence[i]])?;
+        }
+        Ok(())
+    }
+}
+
+impl Frame {
+    /// Returns the frame as an organized point cloud.
+    #[must_use]
+    pub fn point_cloud(&self) -> PointCloud {
+        PointCloud::from_frame(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DepthPoint;
+    use std::time::Duration;
+
+    #[test]
+    fn test_point_cloud() {
+        let point =
+            |z, depth_confidence| DepthPoint { x: 0.1, y: 0.2, z, noise: 0.0, depth_confidence };
+        let frame = Frame::new(
+            vec![point(0.3, 255), point(0.0, 0), point(0.4, 128)],
+            vec![0; 3],
+            Duration::ZERO,
+            3,
+            1,
+        );
+        let point_cloud = frame.point_cloud();
+        assert_eq!(point_cloud.len(), 3);
+        assert!((point_cloud.z()[2] - 0.4).abs() < f32::EPSILON);
+        assert_eq!(point_cloud.confidence(), &[255, 0, 128]);
+
+        let mut pcd = Vec::new();
+        point_cloud.write_pcd(&mut pcd).unwrap();
+        let header_len = pcd.windows(12).position(|w| w == b"DATA binary\n").unwrap() + 12;
+        assert!(pcd.starts_with(b"# .PCD v0.7"));
+        assert_eq!(pcd.len(), header_len + 3 * 13);
+        let invalid_x =
+            f32::from_le_bytes(pcd[header_len + 13..header_len + 17].try_into().unwrap());
+        assert!(invalid_x.is_nan());
+
+        let mut ply = Vec::new();
+        point_cloud.write_ply(&mut ply).unwrap();
+        let header_len = ply.windows(11).position(|w| w == b"end_header\n").unwrap() + 11;
+        assert!(ply.windows(16).any(|w| w == b"element vertex 2"));
+        assert_eq!(ply.len(), header_len + 2 * 13);
+    }
+}
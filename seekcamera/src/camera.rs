@@ -1,17 +1,28 @@
 use crate::{
     error::{result_from, Error},
     frame::Rotation,
-    register_event_callback, register_frame_callback, Frame,
+    register_event_callback, register_frame_callback,
+    settings::{AgcMode, ColorPalette, Filter, PipelineMode},
+    Frame,
 };
+use rkyv::{Archive, Deserialize, Serialize};
 use seekcamera_sys::{
     seekcamera_capture_session_start, seekcamera_capture_session_stop,
+    seekcamera_filter_state_t_SEEKCAMERA_FILTER_STATE_DISABLED,
+    seekcamera_filter_state_t_SEEKCAMERA_FILTER_STATE_ENABLED,
     seekcamera_flat_scene_correction_id_t_SEEKCAMERA_FLAT_SCENE_CORRECTION_ID_0,
     seekcamera_frame_format_t_SEEKCAMERA_FRAME_FORMAT_GRAYSCALE,
-    seekcamera_io_type_t_SEEKCAMERA_IO_TYPE_USB, seekcamera_manager_create,
-    seekcamera_manager_destroy, seekcamera_manager_t, seekcamera_store_calibration_data,
-    seekcamera_store_flat_scene_correction, seekcamera_t,
+    seekcamera_frame_format_t_SEEKCAMERA_FRAME_FORMAT_THERMOGRAPHY_FLOAT,
+    seekcamera_get_shutter_mode, seekcamera_io_type_t_SEEKCAMERA_IO_TYPE_USB,
+    seekcamera_manager_create, seekcamera_manager_destroy, seekcamera_manager_t,
+    seekcamera_set_agc_mode, seekcamera_set_color_palette, seekcamera_set_filter_state,
+    seekcamera_set_pipeline_mode, seekcamera_set_shutter_mode, seekcamera_shutter_mode_t,
+    seekcamera_shutter_mode_t_SEEKCAMERA_SHUTTER_MODE_AUTO,
+    seekcamera_shutter_mode_t_SEEKCAMERA_SHUTTER_MODE_MANUAL, seekcamera_shutter_trigger,
+    seekcamera_store_calibration_data, seekcamera_store_flat_scene_correction, seekcamera_t,
 };
 use std::{
+    cell::Cell,
     ptr,
     sync::mpsc,
     time::{Duration, Instant},
@@ -26,6 +37,7 @@ pub struct Camera {
     camera: *mut seekcamera_t,
     event_rx: EventRx,
     frame_rx: FrameRx,
+    sensor_temperature: Cell<Option<f32>>,
 }
 
 /// Error returned from [`Camera::attach`].
@@ -65,6 +77,17 @@ pub enum RecvError {
     Frame(Error),
 }
 
+/// Shutter mode of the camera.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Archive, Serialize, Deserialize)]
+pub enum ShutterMode {
+    /// The camera closes the shutter and runs a flat field correction (FFC)
+    /// on its own whenever the sensor temperature drifts. Each FFC freezes the
+    /// output for a few frames.
+    Auto,
+    /// The shutter closes only on [`Camera::trigger_ffc`].
+    Manual,
+}
+
 #[derive(Debug)]
 enum Event {
     Connect,
@@ -90,7 +113,7 @@ impl Camera {
         let frame_rx = unsafe {
             make_frame_channel(camera, rotation).map_err(AttachError::RegisterFrameCallback)?
         };
-        Ok(Self { camera_manager, camera, event_rx, frame_rx })
+        Ok(Self { camera_manager, camera, event_rx, frame_rx, sensor_temperature: Cell::new(None) })
     }
 
     /// Attempts to wait for a frame from this camera.
@@ -110,10 +133,22 @@ impl Camera {
             Err(mpsc::TryRecvError::Disconnected) => unreachable!(),
         }
         let (_camera, frame) = self.frame_rx.recv().unwrap();
-        frame.map_err(RecvError::Frame)
+        let frame = frame.map_err(RecvError::Frame)?;
+        if let Some(sensor_temperature) = frame.sensor_temperature() {
+            self.sensor_temperature.set(Some(sensor_temperature));
+        }
+        Ok(frame)
     }
 
-    /// Begins streaming frames of the grayscale output format from the camera.
+    /// Returns the temperature of the camera sensor itself in Kelvin, as
+    /// reported with the latest received frame.
+    #[must_use]
+    pub fn sensor_temperature(&self) -> Option<f32> {
+        self.sensor_temperature.get()
+    }
+
+    /// Begins streaming frames of the grayscale and the thermography output
+    /// formats from the camera.
     ///
     /// # Errors
     ///
@@ -122,7 +157,8 @@ impl Camera {
         unsafe {
             result_from(seekcamera_capture_session_start(
                 self.camera,
-                seekcamera_frame_format_t_SEEKCAMERA_FRAME_FORMAT_GRAYSCALE,
+                seekcamera_frame_format_t_SEEKCAMERA_FRAME_FORMAT_GRAYSCALE
+                    | seekcamera_frame_format_t_SEEKCAMERA_FRAME_FORMAT_THERMOGRAPHY_FLOAT,
             ))
         }
     }
@@ -136,6 +172,77 @@ impl Camera {
         unsafe { result_from(seekcamera_capture_session_stop(self.camera)) }
     }
 
+    /// Closes the shutter and runs a flat field correction (FFC). The camera
+    /// must be capturing.
+    ///
+    /// # Errors
+    ///
+    /// This method can result in a generic [`Error`].
+    pub fn trigger_ffc(&self) -> Result<(), Error> {
+        unsafe { result_from(seekcamera_shutter_trigger(self.camera)) }
+    }
+
+    /// Sets the shutter mode.
+    ///
+    /// # Errors
+    ///
+    /// This method can result in a generic [`Error`].
+    pub fn set_shutter_mode(&self, mode: ShutterMode) -> Result<(), Error> {
+        unsafe { result_from(seekcamera_set_shutter_mode(self.camera, mode.into())) }
+    }
+
+    /// Returns the current shutter mode.
+    ///
+    /// # Errors
+    ///
+    /// This method can result in a generic [`Error`].
+    pub fn shutter_mode(&self) -> Result<ShutterMode, Error> {
+        let mut mode = seekcamera_shutter_mode_t_SEEKCAMERA_SHUTTER_MODE_AUTO;
+        unsafe { result_from(seekcamera_get_shutter_mode(self.camera, &mut mode))? };
+        Ok(ShutterMode::from(mode))
+    }
+
+    /// Sets the color palette of the color output formats.
+    ///
+    /// # Errors
+    ///
+    /// This method can result in a generic [`Error`].
+    pub fn set_color_palette(&self, palette: ColorPalette) -> Result<(), Error> {
+        unsafe { result_from(seekcamera_set_color_palette(self.camera, palette.into())) }
+    }
+
+    /// Sets the automated gain control mode.
+    ///
+    /// # Errors
+    ///
+    /// This method can result in a generic [`Error`].
+    pub fn set_agc_mode(&self, mode: AgcMode) -> Result<(), Error> {
+        unsafe { result_from(seekcamera_set_agc_mode(self.camera, mode.into())) }
+    }
+
+    /// Sets the image processing pipeline mode.
+    ///
+    /// # Errors
+    ///
+    /// This method can result in a generic [`Error`].
+    pub fn set_pipeline_mode(&self, mode: PipelineMode) -> Result<(), Error> {
+        unsafe { result_from(seekcamera_set_pipeline_mode(self.camera, mode.into())) }
+    }
+
+    /// Enables or disables an image processing filter.
+    ///
+    /// # Errors
+    ///
+    /// This method can result in a generic [`Error`].
+    pub fn set_filter_enabled(&self, filter: Filter, enabled: bool) -> Result<(), Error> {
+        let state = if enabled {
+            seekcamera_filter_state_t_SEEKCAMERA_FILTER_STATE_ENABLED
+        } else {
+            seekcamera_filter_state_t_SEEKCAMERA_FILTER_STATE_DISABLED
+        };
+        unsafe { result_from(seekcamera_set_filter_state(self.camera, filter.into(), state)) }
+    }
+
     /// Stores a flat scene correction.
     ///
     /// # Errors
@@ -162,6 +269,25 @@ impl Drop for Camera {
     }
 }
 
+impl From<ShutterMode> for seekcamera_shutter_mode_t {
+    fn from(mode: ShutterMode) -> Self {
+        match mode {
+            ShutterMode::Auto => seekcamera_shutter_mode_t_SEEKCAMERA_SHUTTER_MODE_AUTO,
+            ShutterMode::Manual => seekcamera_shutter_mode_t_SEEKCAMERA_SHUTTER_MODE_MANUAL,
+        }
+    }
+}
+
+impl From<seekcamera_shutter_mode_t> for ShutterMode {
+    fn from(mode: seekcamera_shutter_mode_t) -> Self {
+        if mode == seekcamera_shutter_mode_t_SEEKCAMERA_SHUTTER_MODE_MANUAL {
+            Self::Manual
+        } else {
+            Self::Auto
+        }
+    }
+}
+
 fn manager_create() -> Result<*mut seekcamera_manager_t, Error> {
     let mut camera_manager = ptr::null_mut();
     unsafe {
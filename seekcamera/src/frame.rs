@@ -2,8 +2,9 @@ use crate::error::{result_from, Error};
 use rkyv::{Archive, Deserialize, Serialize};
 use seekcamera_sys::{
     seekcamera_frame_format_t_SEEKCAMERA_FRAME_FORMAT_GRAYSCALE,
-    seekcamera_frame_get_frame_by_format, seekcamera_frame_t, seekframe_get_data,
-    seekframe_get_height, seekframe_get_width,
+    seekcamera_frame_format_t_SEEKCAMERA_FRAME_FORMAT_THERMOGRAPHY_FLOAT,
+    seekcamera_frame_get_frame_by_format, seekcamera_frame_header_t, seekcamera_frame_t,
+    seekframe_get_data, seekframe_get_header, seekframe_get_height, seekframe_get_width,
 };
 use std::{
     fmt,
@@ -12,10 +13,15 @@ use std::{
     time::{Duration, SystemTime},
 };
 
+/// Offset between the Celsius and Kelvin scales.
+const CELSIUS_TO_KELVIN: f32 = 273.15;
+
 /// Seek thermal camera frame.
 #[derive(Clone, Archive, Serialize, Deserialize)]
 pub struct Frame {
     data: Vec<u8>,
+    temperatures: Vec<f32>,
+    sensor_temperature: Option<f32>,
     timestamp: Duration,
     width: usize,
     height: usize,
@@ -44,12 +50,14 @@ impl fmt::Debug for Frame {
             .field("timestamp", &self.timestamp)
             .field("width", &self.width)
             .field("height", &self.height)
+            .field("sensor_temperature", &self.sensor_temperature)
             .finish_non_exhaustive()
     }
 }
 
 impl Frame {
-    /// Copies a frame from the frame storage. Performs rotation clockwise.
+    /// Copies a frame from the frame storage. Performs the rotation of both
+    /// the grayscale and the thermography data.
     ///
     /// # Errors
     ///
@@ -69,20 +77,34 @@ impl Frame {
                 seekcamera_frame_format_t_SEEKCAMERA_FRAME_FORMAT_GRAYSCALE,
                 &mut frame_ptr,
             ))?;
-            let data = seekframe_get_data(frame_ptr);
             let width = seekframe_get_width(frame_ptr);
             let height = seekframe_get_height(frame_ptr);
-            let mut rotated = vec![0; width * height];
-            match rotation {
-                Rotation::Clockwise => {
-                    copy_rotated_cw(data.cast(), rotated.as_mut_ptr(), width, height);
-                }
-                Rotation::CounterClockwise => {
-                    copy_rotated_ccw(data.cast(), rotated.as_mut_ptr(), width, height);
-                }
+            let header = seekframe_get_header(frame_ptr).cast::<seekcamera_frame_header_t>();
+            let sensor_temperature = (!header.is_null()).then(|| {
+                ptr::addr_of!((*header).environment_temperature).read_unaligned()
+                    + CELSIUS_TO_KELVIN
+            });
+            let data = rotate(seekframe_get_data(frame_ptr).cast(), width, height, rotation);
+
+            let mut thermography_ptr = ptr::null_mut();
+            result_from(seekcamera_frame_get_frame_by_format(
+                frame,
+                seekcamera_frame_format_t_SEEKCAMERA_FRAME_FORMAT_THERMOGRAPHY_FLOAT,
+                &mut thermography_ptr,
+            ))?;
+            let mut temperatures = rotate::<f32>(
+                seekframe_get_data(thermography_ptr).cast(),
+                seekframe_get_width(thermography_ptr),
+                seekframe_get_height(thermography_ptr),
+                rotation,
+            );
+            for temperature in &mut temperatures {
+                *temperature += CELSIUS_TO_KELVIN;
             }
             Ok(Self {
-                data: rotated,
+                data,
+                temperatures,
+                sensor_temperature,
                 timestamp: SystemTime::UNIX_EPOCH.elapsed().unwrap_or(Duration::MAX),
                 width: height,
                 height: width,
@@ -90,10 +112,23 @@ impl Frame {
         }
     }
 
-    /// Creates a new frame from raw data.
+    /// Creates a new frame from raw data without thermography.
     #[must_use]
     pub fn new(data: Vec<u8>, timestamp: Duration, width: usize, height: usize) -> Self {
-        Self { data, timestamp, width, height }
+        Self { data, temperatures: Vec::new(), sensor_temperature: None, timestamp, width, height }
+    }
+
+    /// Sets the per-pixel temperatures in Kelvin and the sensor temperature in
+    /// Kelvin.
+    #[must_use]
+    pub fn with_thermography(
+        mut self,
+        temperatures: Vec<f32>,
+        sensor_temperature: Option<f32>,
+    ) -> Self {
+        self.temperatures = temperatures;
+        self.sensor_temperature = sensor_temperature;
+        self
     }
 
     /// Returns the frame data.
@@ -102,6 +137,28 @@ impl Frame {
         &self.data
     }
 
+    /// Returns the per-pixel temperatures in Kelvin in the row-major order.
+    /// Returns an empty slice if the frame has no thermography.
+    #[must_use]
+    pub fn temperatures(&self) -> &[f32] {
+        &self.temperatures
+    }
+
+    /// Returns the temperature of the pixel in Kelvin.
+    #[must_use]
+    pub fn temperature(&self, x: usize, y: usize) -> Option<f32> {
+        if x >= self.width {
+            return None;
+        }
+        self.temperatures.get(y * self.width + x).copied()
+    }
+
+    /// Returns the temperature of the camera sensor itself in Kelvin.
+    #[must_use]
+    pub fn sensor_temperature(&self) -> Option<f32> {
+        self.sensor_temperature
+    }
+
     /// Returns the frame timestamp.
     #[must_use]
     pub fn timestamp(&self) -> Duration {
@@ -121,7 +178,21 @@ impl Frame {
     }
 }
 
-fn copy_rotated_cw(mut src: *const u8, dst: *mut u8, width: usize, height: usize) {
+fn rotate<T: Copy + Default>(
+    src: *const T,
+    width: usize,
+    height: usize,
+    rotation: &Rotation,
+) -> Vec<T> {
+    let mut rotated = vec![T::default(); width * height];
+    match rotation {
+        Rotation::Clockwise => copy_rotated_cw(src, rotated.as_mut_ptr(), width, height),
+        Rotation::CounterClockwise => copy_rotated_ccw(src, rotated.as_mut_ptr(), width, height),
+    }
+    rotated
+}
+
+fn copy_rotated_cw<T: Copy>(mut src: *const T, dst: *mut T, width: usize, height: usize) {
     unsafe {
         for x in 0..height {
             let mut dst_row = dst.add(height - 1 - x);
@@ -134,7 +205,7 @@ fn copy_rotated_cw(mut src: *const u8, dst: *mut u8, width: usize, height: usize
     }
 }
 
-fn copy_rotated_ccw(mut src: *const u8, dst: *mut u8, width: usize, height: usize) {
+fn copy_rotated_ccw<T: Copy>(mut src: *const T, dst: *mut T, width: usize, height: usize) {
     unsafe {
         let dst_end = dst.add(width * height);
         for x in 0..height {
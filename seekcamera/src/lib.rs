@@ -6,10 +6,12 @@
 mod camera;
 mod error;
 mod frame;
+mod settings;
 
-pub use camera::{AttachError, Camera, RecvError};
+pub use camera::{AttachError, Camera, RecvError, ShutterMode};
 pub use error::Error;
 pub use frame::{Frame, Rotation};
+pub use settings::{AgcMode, ColorPalette, Filter, PipelineMode};
 
 use error::result_from;
 use seekcamera_sys::{
@@ -0,0 +1,117 @@
+use seekcamera_sys::{
+    seekcamera_agc_mode_t, seekcamera_agc_mode_t_SEEKCAMERA_AGC_MODE_HISTEQ,
+    seekcamera_agc_mode_t_SEEKCAMERA_AGC_MODE_LINEAR, seekcamera_color_palette_t,
+    seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_AMBER,
+    seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_BLACK_HOT,
+    seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_GREEN,
+    seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_HI,
+    seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_IRON,
+    seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_PRISM,
+    seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_SPECTRA,
+    seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_TYRIAN,
+    seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_WHITE_HOT, seekcamera_filter_t,
+    seekcamera_filter_t_SEEKCAMERA_FILTER_FLAT_SCENE_CORRECTION,
+    seekcamera_filter_t_SEEKCAMERA_FILTER_GRADIENT_CORRECTION, seekcamera_pipeline_mode_t,
+    seekcamera_pipeline_mode_t_SEEKCAMERA_IMAGE_LEGACY,
+    seekcamera_pipeline_mode_t_SEEKCAMERA_IMAGE_LITE,
+    seekcamera_pipeline_mode_t_SEEKCAMERA_IMAGE_SEEKVISION,
+};
+
+/// Color palette applied to the color output formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorPalette {
+    /// Hot is white, cold is black.
+    WhiteHot,
+    /// Hot is black, cold is white.
+    BlackHot,
+    /// Spectra palette.
+    Spectra,
+    /// Prism palette.
+    Prism,
+    /// Tyrian palette.
+    Tyrian,
+    /// Iron palette.
+    Iron,
+    /// Amber palette.
+    Amber,
+    /// Hi palette.
+    Hi,
+    /// Green palette.
+    Green,
+}
+
+/// Automated gain control (AGC) mode, which maps the thermal signal to the
+/// display range of the grayscale and color output formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AgcMode {
+    /// Linear mapping of the scene range. Preserves the relative intensities.
+    Linear,
+    /// Histogram equalization. Maximizes the contrast.
+    HistogramEqualization,
+}
+
+/// Image processing pipeline mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PipelineMode {
+    /// Minimal processing.
+    Lite,
+    /// Processing of the older SDK versions.
+    Legacy,
+    /// Full processing with the SeekVision enhancements.
+    SeekVision,
+}
+
+/// Optional image processing filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Gradient correction.
+    GradientCorrection,
+    /// Flat scene correction.
+    FlatSceneCorrection,
+}
+
+impl From<ColorPalette> for seekcamera_color_palette_t {
+    fn from(palette: ColorPalette) -> Self {
+        match palette {
+            ColorPalette::WhiteHot => seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_WHITE_HOT,
+            ColorPalette::BlackHot => seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_BLACK_HOT,
+            ColorPalette::Spectra => seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_SPECTRA,
+            ColorPalette::Prism => seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_PRISM,
+            ColorPalette::Tyrian => seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_TYRIAN,
+            ColorPalette::Iron => seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_IRON,
+            ColorPalette::Amber => seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_AMBER,
+            ColorPalette::Hi => seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_HI,
+            ColorPalette::Green => seekcamera_color_palette_t_SEEKCAMERA_COLOR_PALETTE_GREEN,
+        }
+    }
+}
+
+impl From<AgcMode> for seekcamera_agc_mode_t {
+    fn from(mode: AgcMode) -> Self {
+        match mode {
+            AgcMode::Linear => seekcamera_agc_mode_t_SEEKCAMERA_AGC_MODE_LINEAR,
+            AgcMode::HistogramEqualization => seekcamera_agc_mode_t_SEEKCAMERA_AGC_MODE_HISTEQ,
+        }
+    }
+}
+
+impl From<PipelineMode> for seekcamera_pipeline_mode_t {
+    fn from(mode: PipelineMode) -> Self {
+        match mode {
+            PipelineMode::Lite => seekcamera_pipeline_mode_t_SEEKCAMERA_IMAGE_LITE,
+            PipelineMode::Legacy => seekcamera_pipeline_mode_t_SEEKCAMERA_IMAGE_LEGACY,
+            PipelineMode::SeekVision => seekcamera_pipeline_mode_t_SEEKCAMERA_IMAGE_SEEKVISION,
+        }
+    }
+}
+
+impl From<Filter> for seekcamera_filter_t {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::GradientCorrection => seekcamera_filter_t_SEEKCAMERA_FILTER_GRADIENT_CORRECTION,
+            Filter::FlatSceneCorrection => {
+                seekcamera_filter_t_SEEKCAMERA_FILTER_FLAT_SCENE_CORRECTION
+            }
+        }
+    }
+}
@@ -5,7 +5,10 @@
 use super::FrameResolution;
 use crate::{
     consts::{
-        DEPTH_EXPOSURE_MANUAL, DEPTH_EXPOSURE_TIME, DEPTH_HEIGHT, DEPTH_USE_CASE, DEPTH_WIDTH,
+        DEPTH_CLOSE_RANGE_ENTER_DISTANCE, DEPTH_CLOSE_RANGE_EXPOSURE_TIME,
+        DEPTH_CLOSE_RANGE_FRAME_RATE, DEPTH_CLOSE_RANGE_LEAVE_DISTANCE, DEPTH_CLOSE_RANGE_USE_CASE,
+        DEPTH_HEIGHT, DEPTH_LONG_RANGE_EXPOSURE_TIME, DEPTH_LONG_RANGE_FRAME_RATE,
+        DEPTH_LONG_RANGE_USE_CASE, DEPTH_WIDTH,
     },
     ext::mpsc::{ReceiverExt as _, SenderExt as _},
 };
@@ -25,12 +28,24 @@ pub struct Sensor;
 /// Sensor commands.
 #[derive(Debug)]
 pub enum Command {
-    /// Start frame capturing.
-    Start,
+    /// Start frame capturing in the given range mode.
+    Start(Range),
+    /// Switch the range mode. Applied immediately if capturing.
+    SetRange(Range),
     /// Stop frame capturing.
     Stop,
 }
 
+/// Depth camera range mode.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum Range {
+    /// Long-range mode, while the user is approaching.
+    #[default]
+    Long,
+    /// Close-range mode, while the user is in the capture position.
+    Close,
+}
+
 /// 2D ToF camera frame.
 ///
 /// This structure wraps the frame data into [`Arc`] inside, so the cloning is
@@ -67,30 +82,22 @@ impl agentwire::agent::Thread for Sensor {
                     return Ok(());
                 }
             };
+            let use_cases = camera.get_use_cases()?;
+            tracing::debug!("Depth camera supported use cases: {use_cases:?}");
+            let mut range = Range::default();
             loop {
                 loop {
                     let Some(input) = rt.block_on(port.next()) else { return Ok(()) };
                     match input.value {
-                        Command::Start => break,
+                        Command::Start(start_range) => {
+                            range = start_range;
+                            break;
+                        }
+                        Command::SetRange(new_range) => range = new_range,
                         Command::Stop => {}
                     }
                 }
-                let use_cases = camera.get_use_cases()?;
-                tracing::debug!("Depth camera supported use cases: {use_cases:?}");
-                camera.set_use_case(DEPTH_USE_CASE)?;
-                camera.set_exposure_mode(DEPTH_EXPOSURE_MANUAL)?;
-                camera.set_exposure_time(DEPTH_EXPOSURE_TIME)?;
-                let frame_rate = camera.get_frame_rate()?;
-                let max_frame_rate = camera.get_max_frame_rate()?;
-                let exposure_limits = camera.get_exposure_limits()?;
-                let is_manual = camera.is_exposure_mode_manual()?;
-                tracing::info!(
-                    "Depth camera frame rate: {frame_rate} FPS (max: {max_frame_rate} FPS), \
-                     exposure limits: {}..{}, exposure mode is {}",
-                    exposure_limits[0],
-                    exposure_limits[1],
-                    if is_manual { "manual" } else { "automatic" }
-                );
+                range.apply(&camera, &use_cases)?;
                 camera.capture_start()?;
                 loop {
                     let frame = camera.recv();
@@ -98,7 +105,12 @@ impl agentwire::agent::Thread for Sensor {
                     let Ok(command) = port.rx.try_recv() else { return Ok(()) };
                     if let Some(command) = command {
                         match command.value {
-                            Command::Start => {}
+                            Command::Start(new_range) | Command::SetRange(new_range) => {
+                                if new_range != range {
+                                    range = new_range;
+                                    range.apply(&camera, &use_cases)?;
+                                }
+                            }
                             Command::Stop => break,
                         }
                     }
@@ -114,6 +126,69 @@ impl agentwire::agent::Thread for Sensor {
     }
 }
 
+impl Range {
+    /// Returns the range mode for the user `distance` in millimeters, with a
+    /// hysteresis around the switching distance.
+    #[must_use]
+    pub fn for_distance(self, distance: f64) -> Self {
+        match self {
+            Self::Long if distance < DEPTH_CLOSE_RANGE_ENTER_DISTANCE => Self::Close,
+            Self::Close if distance > DEPTH_CLOSE_RANGE_LEAVE_DISTANCE => Self::Long,
+            range => range,
+        }
+    }
+
+    /// Returns the royale use case name for the range mode.
+    #[must_use]
+    pub fn use_case(self) -> &'static str {
+        match self {
+            Self::Long => DEPTH_LONG_RANGE_USE_CASE,
+            Self::Close => DEPTH_CLOSE_RANGE_USE_CASE,
+        }
+    }
+
+    /// Returns the manual exposure time in microseconds for the range mode.
+    #[must_use]
+    pub fn exposure_time(self) -> u32 {
+        match self {
+            Self::Long => DEPTH_LONG_RANGE_EXPOSURE_TIME,
+            Self::Close => DEPTH_CLOSE_RANGE_EXPOSURE_TIME,
+        }
+    }
+
+    /// Returns the frame rate for the range mode.
+    #[must_use]
+    pub fn frame_rate(self) -> u16 {
+        match self {
+            Self::Long => DEPTH_LONG_RANGE_FRAME_RATE,
+            Self::Close => DEPTH_CLOSE_RANGE_FRAME_RATE,
+        }
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_os = "linux"))]
+    fn apply(self, camera: &orb_royale::Camera, use_cases: &[String]) -> Result<()> {
+        let use_case = self.use_case();
+        if use_cases.iter().any(|supported| supported == use_case) {
+            camera.set_use_case(use_case)?;
+        } else {
+            tracing::warn!("Depth camera doesn't support use case {use_case}, keeping the current");
+        }
+        camera.set_exposure_mode(orb_royale::ExposureMode::Manual)?;
+        camera.set_exposure_time(self.exposure_time())?;
+        let frame_rate = camera.select_frame_rate(self.frame_rate())?;
+        let max_frame_rate = camera.get_max_frame_rate()?;
+        let exposure_limits = camera.get_exposure_limits()?;
+        let exposure_mode = camera.get_exposure_mode()?;
+        tracing::info!(
+            "Depth camera {self:?} range mode: frame rate: {frame_rate} FPS (max: \
+             {max_frame_rate} FPS), exposure limits: {}..{}, exposure mode is {exposure_mode:?}",
+            exposure_limits[0],
+            exposure_limits[1],
+        );
+        Ok(())
+    }
+}
+
 impl Deref for Frame {
     type Target = orb_royale::Frame;
 
@@ -177,3 +252,20 @@ impl super::Frame for Frame {
         u32::from(self.0.height())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_for_distance() {
+        let range = Range::default().for_distance(DEPTH_CLOSE_RANGE_LEAVE_DISTANCE);
+        assert_eq!(range, Range::Long);
+        let range = range.for_distance(DEPTH_CLOSE_RANGE_ENTER_DISTANCE - 1.0);
+        assert_eq!(range, Range::Close);
+        let range = range.for_distance(DEPTH_CLOSE_RANGE_LEAVE_DISTANCE);
+        assert_eq!(range, Range::Close);
+        let range = range.for_distance(DEPTH_CLOSE_RANGE_LEAVE_DISTANCE + 1.0);
+        assert_eq!(range, Range::Long);
+    }
+}
@@ -0,0 +1,91 @@
+//! Frame drop statistics.
+//!
+//! The cameras report the number of frames skipped before each delivered frame
+//! in its [`FrameMeta`]. The [`FrameDrops`] aggregates them per camera over a
+//! signup, so the debug report can tell whether a failed capture was starved
+//! of frames.
+
+use super::{smudge::Camera, FrameMeta, TimestampSource};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Frame drop statistics of a single camera.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct DropStats {
+    /// Number of delivered frames.
+    pub frames: u64,
+    /// Total number of dropped frames.
+    pub dropped: u64,
+    /// Longest run of consecutively dropped frames.
+    pub max_gap: u32,
+    /// Source of the frame timestamps, if any frame was delivered.
+    pub timestamp_source: Option<TimestampSource>,
+}
+
+/// Frame drop statistics of all cameras.
+#[derive(Clone, Debug, Default, Serialize, JsonSchema)]
+pub struct FrameDrops {
+    /// RGB camera.
+    pub rgb: DropStats,
+    /// IR eye camera.
+    pub ir_eye: DropStats,
+    /// IR face camera.
+    pub ir_face: DropStats,
+}
+
+impl DropStats {
+    /// Accounts a delivered frame.
+    pub fn push(&mut self, meta: &FrameMeta) {
+        self.frames = self.frames.saturating_add(1);
+        self.dropped = self.dropped.saturating_add(meta.dropped.into());
+        self.max_gap = self.max_gap.max(meta.dropped);
+        self.timestamp_source = Some(meta.timestamp_source);
+    }
+
+    /// Returns the share of the dropped frames among all frames.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn drop_ratio(&self) -> f64 {
+        let total = self.frames.saturating_add(self.dropped);
+        if total == 0 {
+            0.0
+        } else {
+            self.dropped as f64 / total as f64
+        }
+    }
+}
+
+impl FrameDrops {
+    /// Accounts a delivered frame of the camera.
+    pub fn push(&mut self, camera: Camera, meta: &FrameMeta) {
+        match camera {
+            Camera::Rgb => self.rgb.push(meta),
+            Camera::IrEye => self.ir_eye.push(meta),
+            Camera::IrFace => self.ir_face.push(meta),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push() {
+        let mut drops = FrameDrops::default();
+        for dropped in [0, 2, 0, 1] {
+            let meta =
+                FrameMeta { sequence: 0, timestamp_source: TimestampSource::EndOfFrame, dropped };
+            drops.push(Camera::IrEye, &meta);
+        }
+        assert_eq!(drops.ir_eye, DropStats {
+            frames: 4,
+            dropped: 3,
+            max_gap: 2,
+            timestamp_source: Some(TimestampSource::EndOfFrame),
+        });
+        assert!((drops.ir_eye.drop_ratio() - 3.0 / 7.0).abs() < f64::EPSILON);
+        assert_eq!(drops.rgb, DropStats::default());
+        assert!(drops.rgb.drop_ratio().abs() < f64::EPSILON);
+    }
+}
@@ -26,7 +26,7 @@
 // - V4L2_PIX_FMT_SGRBG12
 // - V4L2_PIX_FMT_SRGGB12
 
-use super::{frame_flip, frame_rotate_cw, frame_rotate_cw_flip, FrameResolution};
+use super::{frame_flip, frame_rotate_cw, frame_rotate_cw_flip, FrameMeta, FrameResolution};
 use crate::{
     consts::{
         IR_CAMERA_DEFAULT_BLACK_LEVEL, IR_CAMERA_DEFAULT_EXPOSURE, IR_CAMERA_DEFAULT_GAIN,
@@ -38,13 +38,13 @@ use crate::{
     time_series::TimeSeries,
 };
 use agentwire::port::{self, Port};
-use eyre::{ensure, Error, Result, WrapErr};
+use eyre::{Error, Result, WrapErr};
 use futures::{
     channel::{mpsc, oneshot},
     prelude::*,
 };
 use ndarray::prelude::*;
-use orb_camera::{Buffer, Device, Format};
+use orb_camera::{Buffer, Control, Device, Event, Format};
 use png::EncodingError;
 use rkyv::{Archive, Deserialize, Infallible, Serialize};
 use std::{
@@ -54,6 +54,7 @@ use std::{
     pin::Pin,
     sync::Arc,
     task::Poll,
+    thread,
     time::{Duration, Instant, SystemTime},
 };
 
@@ -61,6 +62,8 @@ const EYE_DEVICE_PATH: &str = "/dev/video0";
 const FACE_DEVICE_PATH: &str = "/dev/video2";
 const BUF_COUNT: u32 = 4;
 const SLEEP_TIMEOUT: Duration = Duration::from_millis(100);
+/// Delay before reopening the sensor after a failure.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
 const PIX_FMT: u32 = v4l2_sys::V4L2_PIX_FMT_Y10;
 const TRIGGER_MODE: i64 = 1; // mode 0: free run | mode 1: external trigger
 
@@ -141,6 +144,7 @@ pub struct Frame {
     width: u32,
     height: u32,
     mean: u8,
+    meta: FrameMeta,
 }
 
 #[derive(Debug)]
@@ -148,6 +152,7 @@ struct PendingFrame<'a> {
     buf: &'a Buffer<'a>,
     buf_idx: u32,
     timestamp: Duration,
+    meta: FrameMeta,
     format: &'a Format,
 }
 
@@ -174,8 +179,9 @@ impl agentwire::agent::Thread for Sensor {
                 Err(err) => {
                     if let Some(state_tx) = &mut self.state_tx {
                         state_tx.send_now(super::State::Error)?;
-                        tracing::warn!("IR camera: {err}");
                     }
+                    tracing::warn!("IR camera {}: {err}", self.device_path);
+                    thread::sleep(RESTART_DELAY);
                 }
             }
         }
@@ -187,24 +193,39 @@ impl Sensor {
     #[allow(clippy::too_many_lines)]
     fn main_loop(&mut self, port: &mut port::Inner<Self>) -> Result<bool> {
         let mut exit = false;
-        let mut scratch_buffer = Vec::<u16>::with_capacity((IR_WIDTH * IR_HEIGHT) as usize);
         let sensor = Device::open(self.device_path)?;
-        let mut format = sensor.format()?;
-        format.pixel_format = PIX_FMT;
-        format = sensor.set_format(&format)?;
-        ensure!(format.pixel_format == PIX_FMT, "couldn't set pixel format");
+        let format = sensor.negotiate(PIX_FMT, IR_WIDTH, IR_HEIGHT, None)?.format;
+        if (format.width, format.height) != (IR_WIDTH, IR_HEIGHT) {
+            tracing::warn!(
+                "IR camera {} resolution negotiated to {}x{}",
+                self.device_path,
+                format.width,
+                format.height
+            );
+        }
+        let mut scratch_buffer =
+            Vec::<u16>::with_capacity(format.width as usize * format.height as usize);
         sensor.set_control("Trigger Mode", TRIGGER_MODE)?;
-        sensor.set_control("Gain", IR_CAMERA_DEFAULT_GAIN)?;
-        sensor.set_control("Exposure", IR_CAMERA_DEFAULT_EXPOSURE)?;
+        sensor.write_control(Control::Gain, IR_CAMERA_DEFAULT_GAIN)?;
+        sensor.write_control(Control::Exposure, IR_CAMERA_DEFAULT_EXPOSURE)?;
         sensor.set_control("Black Level", IR_CAMERA_DEFAULT_BLACK_LEVEL)?;
+        if let Err(err) = sensor.subscribe_source_change() {
+            tracing::debug!("IR camera source change events are not supported: {err}");
+        }
+        let gain_id = sensor.subscribe_control(Control::Gain).ok();
+        let exposure_id = sensor.subscribe_control(Control::Exposure).ok();
         let buf = Buffer::new(&sensor, BUF_COUNT)?;
         sensor.with_waiter_context(|waiter, cx| {
             'enable: loop {
                 if !self.capturing {
                     'start: loop {
                         poll_commands! { |port, cx|
-                            Some(Command::SetGain(gain)) => sensor.set_control("Gain", gain)?,
-                            Some(Command::SetExposure(exposure)) => sensor.set_control("Exposure", exposure)?,
+                            Some(Command::SetGain(gain)) => {
+                                sensor.write_control(Control::Gain, gain)?;
+                            }
+                            Some(Command::SetExposure(exposure)) => {
+                                sensor.write_control(Control::Exposure, exposure)?;
+                            }
                             Some(Command::SetFlip(new_flip)) => self.flip = new_flip,
                             Some(Command::SetBlackLevel(black_level)) => {
                                 sensor.set_control("Black Level", black_level)?;
@@ -234,11 +255,11 @@ impl Sensor {
                 let log_tx = 'capture: loop {
                     poll_commands! { |port, cx|
                         Some(Command::SetGain(gain)) => {
-                            sensor.set_control("Gain", gain)?;
+                            sensor.write_control(Control::Gain, gain)?;
                             log.gain.push(gain);
                         }
                         Some(Command::SetExposure(exposure)) => {
-                            sensor.set_control("Exposure", exposure)?;
+                            sensor.write_control(Control::Exposure, exposure)?;
                             log.exposure.push(exposure);
                         }
                         Some(Command::SetFlip(new_flip)) => {
@@ -256,13 +277,60 @@ impl Sensor {
                         },
                     }
                     waiter.wait(SLEEP_TIMEOUT)?;
+                    let mut source_changed = false;
+                    while let Some(event) = waiter.next_event()? {
+                        match event {
+                            Event::SourceChange { resolution } => {
+                                tracing::warn!(
+                                    "IR camera {} source changed (resolution: {resolution})",
+                                    self.device_path
+                                );
+                                source_changed = true;
+                            }
+                            Event::Control { id, value, value_changed: true, .. } => {
+                                // Our own changes aren't reported back, so it's the driver.
+                                if Some(id) == gain_id {
+                                    log.gain.push(value);
+                                } else if Some(id) == exposure_id {
+                                    log.exposure.push(value);
+                                }
+                                tracing::debug!("IR camera control {id:#x} changed to {value}");
+                            }
+                            _ => {}
+                        }
+                    }
+                    if source_changed {
+                        // The queued buffers hold frames in the old format, restart with the
+                        // format negotiated again.
+                        dd_incr!("main.count.hardware.camera.issue.ir_camera.source_change");
+                        sensor.stop()?;
+                        break 'enable Ok(());
+                    }
                     match buf.dequeue() {
                         Ok(Some(dequeued)) => {
                             let timestamp = dequeued.timestamp;
+                            let mut meta = FrameMeta {
+                                sequence: dequeued.sequence,
+                                timestamp_source: dequeued.timestamp_source.into(),
+                                dropped: dequeued.dropped,
+                            };
+                            if dequeued.dropped > 0 {
+                                dd_incr!("main.count.camera.ir_camera.dropped_frames");
+                            }
+                            // A frame the consumer didn't take is dropped too, along with the
+                            // driver drops before it.
+                            if let Some(skipped) = &latest_frame {
+                                dd_incr!("main.count.camera.ir_camera.skipped_frames");
+                                meta.dropped = meta
+                                    .dropped
+                                    .saturating_add(skipped.meta.dropped)
+                                    .saturating_add(1);
+                            }
                             latest_frame = Some(PendingFrame::new(
                                 &buf,
                                 dequeued.index,
                                 timestamp,
+                                meta,
                                 &format,
                             ));
                             dd_timing!("main.time.camera.ir_frame", latest_timestamp);
@@ -367,13 +435,24 @@ impl super::Frame for Frame {
     fn height(&self) -> u32 {
         self.height
     }
+
+    fn meta(&self) -> FrameMeta {
+        self.meta
+    }
 }
 
 impl Frame {
     /// Creates a new frame.
     #[must_use]
     pub fn new(data: Vec<u8>, timestamp: Duration, width: u32, height: u32, mean: u8) -> Self {
-        Self { data: Arc::new(data), timestamp, width, height, mean }
+        Self { data: Arc::new(data), timestamp, width, height, mean, meta: FrameMeta::default() }
+    }
+
+    /// Sets the frame capture metadata.
+    #[must_use]
+    pub fn with_meta(mut self, meta: FrameMeta) -> Self {
+        self.meta = meta;
+        self
     }
 
     /// Decodes a PNG image into a frame.
@@ -417,7 +496,7 @@ impl ArchivedFrame {
     /// Converts this frame into an owned 2-dimensional array.
     pub fn into_ndarray(&self) -> Array2<u8> {
         let vec = (*self.data).deserialize(&mut Infallible).unwrap();
-        Array::from_shape_vec((IR_HEIGHT as usize, IR_WIDTH as usize), vec).unwrap()
+        Array::from_shape_vec((self.height as usize, self.width as usize), vec).unwrap()
     }
 }
 
@@ -437,6 +516,7 @@ impl Default for Frame {
             width: IR_WIDTH,
             height: IR_HEIGHT,
             mean: 0,
+            meta: FrameMeta::default(),
         }
     }
 }
@@ -444,6 +524,8 @@ impl Default for Frame {
 impl PendingFrame<'_> {
     fn convert(self, flip: bool, rotation: bool, buf: &mut Vec<u16>) -> Frame {
         let src = self.buf.get(self.buf_idx);
+        buf.clear();
+        buf.reserve(src.len() / 2);
         unsafe {
             buf.set_len(src.len() / 2);
             // memcpy-ing the source into an intermediate buffer significantly improves performance.
@@ -481,7 +563,7 @@ impl PendingFrame<'_> {
             (sum / dst.len() as u64) as u8
         };
         dd_gauge!("main.gauge.camera.ir_camera.mean", mean.to_string());
-        Frame::new(dst, self.timestamp, width, height, mean)
+        Frame::new(dst, self.timestamp, width, height, mean).with_meta(self.meta)
     }
 }
 
@@ -502,13 +584,20 @@ impl fmt::Debug for Frame {
             .field("width", &self.width)
             .field("height", &self.height)
             .field("mean", &self.mean)
+            .field("meta", &self.meta)
             .finish_non_exhaustive()
     }
 }
 
 impl<'a> PendingFrame<'a> {
-    fn new(buf: &'a Buffer<'a>, buf_idx: u32, timestamp: Duration, format: &'a Format) -> Self {
-        Self { buf, buf_idx, timestamp, format }
+    fn new(
+        buf: &'a Buffer<'a>,
+        buf_idx: u32,
+        timestamp: Duration,
+        meta: FrameMeta,
+        format: &'a Format,
+    ) -> Self {
+        Self { buf, buf_idx, timestamp, meta, format }
     }
 }
 
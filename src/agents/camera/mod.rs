@@ -1,12 +1,15 @@
 //! A common frame trait.
 
 pub mod depth;
+pub mod drops;
 pub mod ir;
 pub mod rgb;
+pub mod smudge;
 pub mod thermal;
 
 use orb_wld_data_id::{ImageId, SignupId};
 use png::EncodingError;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{io::Write, time::Duration};
 
@@ -44,6 +47,11 @@ pub trait Frame: Clone {
     /// Returns the sensor frame height.
     fn height(&self) -> u32;
 
+    /// Returns the frame capture metadata.
+    fn meta(&self) -> FrameMeta {
+        FrameMeta::default()
+    }
+
     /// Returns a image id for the frame.
     fn image_id(&self, signup_id: &SignupId) -> ImageId {
         let mut hasher = crc32fast::Hasher::new();
@@ -55,6 +63,57 @@ pub trait Frame: Clone {
     }
 }
 
+/// Frame capture metadata.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub struct FrameMeta {
+    /// Frame sequence number.
+    pub sequence: u32,
+    /// Source of the frame timestamp.
+    pub timestamp_source: TimestampSource,
+    /// Number of frames dropped right before this frame.
+    pub dropped: u32,
+}
+
+/// Source of a frame timestamp.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampSource {
+    /// Taken by the orb-core when the frame was dequeued.
+    #[default]
+    Dequeue,
+    /// V4L2 driver timestamp of the start of the exposure.
+    StartOfExposure,
+    /// V4L2 driver timestamp of the end of the frame.
+    EndOfFrame,
+    /// GStreamer pipeline presentation timestamp.
+    Pipeline,
+}
+
+impl From<orb_camera::TimestampSource> for TimestampSource {
+    fn from(source: orb_camera::TimestampSource) -> Self {
+        match source {
+            orb_camera::TimestampSource::Dequeue => Self::Dequeue,
+            orb_camera::TimestampSource::StartOfExposure => Self::StartOfExposure,
+            orb_camera::TimestampSource::EndOfFrame => Self::EndOfFrame,
+        }
+    }
+}
+
 /// Camera State.
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
 pub enum State {
@@ -4,12 +4,13 @@
 
 use crate::{
     agents::{
-        camera::{self, Frame as _, FrameResolution},
+        camera::{self, Frame as _, FrameMeta, FrameResolution, TimestampSource},
         ProcessInitializer,
     },
     consts::{
         RGB_DEFAULT_HEIGHT, RGB_DEFAULT_WIDTH, RGB_EXPOSURE_RANGE, RGB_FPS, RGB_NATIVE_HEIGHT,
-        RGB_NATIVE_WIDTH, RGB_REDUCED_HEIGHT, RGB_REDUCED_WIDTH,
+        RGB_NATIVE_WIDTH, RGB_REATTACH_BACKOFF, RGB_REATTACH_TIMEOUT, RGB_REDUCED_HEIGHT,
+        RGB_REDUCED_WIDTH,
     },
     image::fisheye::{self, Fisheye},
 };
@@ -30,6 +31,7 @@ use opencv::{
     imgproc::{resize, INTER_LINEAR},
     prelude::*,
 };
+use orb_camera::{DeviceEvent, Node, Watcher};
 use png::EncodingError;
 use rkyv::{
     ser::Serializer,
@@ -40,12 +42,19 @@ use std::{
     fmt,
     io::prelude::*,
     mem::{size_of, take},
+    path::PathBuf,
     ptr::copy_nonoverlapping,
     slice,
     sync::Arc,
+    thread,
     time::{Duration, Instant, SystemTime},
 };
 
+/// Driver name of the RGB camera sensor device node. The node path depends on
+/// the probe order, and after a reset the camera can be re-attached under
+/// another node name.
+const DEVICE_NAME: &str = "imx477";
+
 /// RGB camera worker process.
 #[derive(Default, Clone, Debug, Archive, Serialize, Deserialize)]
 pub struct Worker;
@@ -60,6 +69,7 @@ pub struct Frame {
     timestamp: Duration,
     width: u32,
     height: u32,
+    meta: FrameMeta,
 }
 
 enum FrameData {
@@ -87,6 +97,20 @@ struct Stream {
     appsink: AppSink,
 }
 
+/// RGB camera device node tracker.
+struct Hotplug {
+    watcher: Watcher,
+    /// Current device node of the camera.
+    path: PathBuf,
+    /// Kernel device path of the camera hardware, which identifies the camera
+    /// across the node renames.
+    parent: PathBuf,
+    /// The node was removed since the pipeline was built.
+    removed: bool,
+    /// The node is currently attached.
+    attached: bool,
+}
+
 impl Port for Worker {
     type Input = Command;
     type Output = Frame;
@@ -115,6 +139,9 @@ impl agentwire::agent::Process for Worker {
         let mut fisheye = apply_fisheye_config(fisheye::Config::default())?;
         let mut prev_fps = RGB_FPS;
         let mut stream = Stream::new(prev_fps)?;
+        let mut hotplug = Hotplug::new()
+            .map_err(|err| tracing::warn!("Couldn't watch RGB camera hot-plug events: {err}"))
+            .ok();
         'outer: loop {
             let fps = loop {
                 match port.recv().value.deserialize(&mut Infallible).unwrap() {
@@ -143,6 +170,8 @@ impl agentwire::agent::Process for Worker {
             }
             stream.pipeline.set_state(gstreamer::State::Playing)?;
             let mut errors_count = 0;
+            let mut sequence = 0_u32;
+            let mut prev_timestamp = None;
             loop {
                 match stream.appsink.pull_sample() {
                     Ok(sample) => {
@@ -158,12 +187,28 @@ impl agentwire::agent::Process for Worker {
                         let data = buffer
                             .into_mapped_buffer_readable()
                             .map_err(|_| eyre!("unable to obtain readable mapped buffer"))?;
+                        let meta = FrameMeta {
+                            sequence,
+                            timestamp_source: TimestampSource::Pipeline,
+                            dropped: prev_timestamp
+                                .map_or(0, |prev| dropped_frames(prev, timestamp, fps)),
+                        };
+                        sequence = sequence.wrapping_add(1);
+                        prev_timestamp = Some(timestamp);
                         let mut frame =
-                            Frame::new(data, timestamp, RGB_NATIVE_WIDTH, RGB_NATIVE_HEIGHT);
+                            Frame::new(data, timestamp, RGB_NATIVE_WIDTH, RGB_NATIVE_HEIGHT)
+                                .with_meta(meta);
                         if undistortion_enabled {
                             frame.undistort(&fisheye)?;
                         }
-                        port.try_send(&port::Output { value: frame, source_ts });
+                        port.try_send(&port::Output {
+                            value: frame,
+                            source_ts,
+                            enqueue_ts: Instant::now(),
+                        });
+                        if let Some(hotplug) = &mut hotplug {
+                            hotplug.drain();
+                        }
                         if let Some(command) = port.try_recv() {
                             match command.value.deserialize(&mut Infallible).unwrap() {
                                 Command::Reset | Command::Play(_) => {
@@ -184,6 +229,13 @@ impl agentwire::agent::Process for Worker {
                     }
                     Err(err) => {
                         tracing::error!("Failed to pull sample from GStreamer: {err:?}");
+                        if let Some(hotplug) = &mut hotplug {
+                            if reattach(hotplug, &mut stream, fps) {
+                                errors_count = 0;
+                                prev_timestamp = None;
+                                continue;
+                            }
+                        }
                         errors_count += 1;
                         if errors_count > 50 {
                             break 'outer;
@@ -207,6 +259,125 @@ impl agentwire::agent::Process for Worker {
     }
 }
 
+/// Checks whether the camera device node was removed, e.g. by a USB reset,
+/// and if so, waits for it to be re-attached and rebuilds the pipeline,
+/// retrying with a backoff. Returns `true` if the pipeline was rebuilt.
+fn reattach(hotplug: &mut Hotplug, stream: &mut Stream, fps: u32) -> bool {
+    hotplug.drain();
+    if !hotplug.removed {
+        return false;
+    }
+    tracing::warn!("RGB camera device {} removed", hotplug.path.display());
+    if let Err(err) = stream.pipeline.set_state(gstreamer::State::Null) {
+        tracing::warn!("Couldn't stop the RGB camera pipeline: {err}");
+    }
+    let deadline = Instant::now() + RGB_REATTACH_TIMEOUT;
+    let mut backoff = RGB_REATTACH_BACKOFF;
+    loop {
+        if hotplug.attached {
+            match Stream::new(fps).and_then(|new| {
+                new.pipeline.set_state(gstreamer::State::Playing)?;
+                Ok(new)
+            }) {
+                Ok(new) => {
+                    tracing::info!(
+                        "RGB camera device {} re-attached, pipeline rebuilt",
+                        hotplug.path.display()
+                    );
+                    *stream = new;
+                    hotplug.removed = false;
+                    return true;
+                }
+                Err(err) => tracing::warn!("Couldn't rebuild the RGB camera pipeline: {err:?}"),
+            }
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            tracing::error!("RGB camera device not re-attached");
+            return false;
+        }
+        hotplug.wait(backoff.min(remaining));
+        backoff *= 2;
+    }
+}
+
+impl Hotplug {
+    fn new() -> Result<Self> {
+        let node = Node::find(DEVICE_NAME)?;
+        let parent = node
+            .parent()
+            .ok_or_else(|| eyre!("unexpected device path {}", node.devpath.display()))?
+            .to_owned();
+        Ok(Self {
+            watcher: Watcher::new()?,
+            path: node.path,
+            parent,
+            removed: false,
+            attached: true,
+        })
+    }
+
+    /// Handles the pending hot-plug events. Called on every frame, so the
+    /// events don't overflow the socket.
+    fn drain(&mut self) {
+        loop {
+            match self.watcher.try_recv() {
+                Ok(Some(event)) => self.handle(event),
+                Ok(None) => break,
+                Err(err) => {
+                    tracing::warn!("Couldn't receive RGB camera hot-plug events: {err}");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for a hot-plug event, and handles the pending
+    /// events.
+    fn wait(&mut self, timeout: Duration) {
+        match self.watcher.recv_timeout(timeout) {
+            Ok(Some(event)) => self.handle(event),
+            Ok(None) => return,
+            Err(err) => {
+                tracing::warn!("Couldn't receive RGB camera hot-plug events: {err}");
+                thread::sleep(timeout);
+                return;
+            }
+        }
+        self.drain();
+    }
+
+    fn handle(&mut self, event: DeviceEvent) {
+        match event {
+            DeviceEvent::Removed(node) if node.parent() == Some(&self.parent) => {
+                self.removed = true;
+                self.attached = false;
+            }
+            DeviceEvent::Added(node) if node.parent() == Some(&self.parent) => {
+                self.path = node.path;
+                self.attached = true;
+            }
+            DeviceEvent::Overflow => {
+                tracing::warn!("RGB camera hot-plug events lost");
+                if !self.path.exists() {
+                    self.removed = true;
+                    self.attached = false;
+                }
+            }
+            DeviceEvent::Removed(_) | DeviceEvent::Added(_) => {}
+        }
+    }
+}
+
+/// Returns the number of frames missing between two presentation timestamps
+/// at the nominal framerate.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn dropped_frames(prev: Duration, timestamp: Duration, fps: u32) -> u32 {
+    let Some(gap) = timestamp.checked_sub(prev) else { return 0 };
+    let frames = (gap.as_secs_f64() * f64::from(fps)).round();
+    (frames as u32).saturating_sub(1)
+}
+
 fn apply_fisheye_config(fisheye_config: fisheye::Config) -> Result<Fisheye> {
     Fisheye::try_from(fisheye_config).wrap_err("failed constructing fisheye from fisheye config")
 }
@@ -305,19 +476,42 @@ impl camera::Frame for Frame {
     fn height(&self) -> u32 {
         self.height
     }
+
+    fn meta(&self) -> FrameMeta {
+        self.meta
+    }
 }
 
 impl Frame {
     /// Creates a new frame.
     #[must_use]
     pub fn new(data: MappedBuffer<Readable>, timestamp: Duration, width: u32, height: u32) -> Self {
-        Self { data: Arc::new(FrameData::Mapped(data)), timestamp, width, height }
+        Self {
+            data: Arc::new(FrameData::Mapped(data)),
+            timestamp,
+            width,
+            height,
+            meta: FrameMeta::default(),
+        }
     }
 
     /// Creates a new frame from a vector.
     #[must_use]
     pub fn from_vec(data: Vec<u8>, timestamp: Duration, width: u32, height: u32) -> Self {
-        Self { data: Arc::new(FrameData::Owned(data)), timestamp, width, height }
+        Self {
+            data: Arc::new(FrameData::Owned(data)),
+            timestamp,
+            width,
+            height,
+            meta: FrameMeta::default(),
+        }
+    }
+
+    /// Sets the frame capture metadata.
+    #[must_use]
+    pub fn with_meta(mut self, meta: FrameMeta) -> Self {
+        self.meta = meta;
+        self
     }
 
     /// Decodes a PNG image into a frame.
@@ -331,6 +525,7 @@ impl Frame {
             timestamp: SystemTime::UNIX_EPOCH.elapsed().unwrap_or(Duration::MAX),
             width: info.width,
             height: info.height,
+            meta: FrameMeta::default(),
         })
     }
 
@@ -428,6 +623,7 @@ impl Default for Frame {
             timestamp: Duration::default(),
             width: RGB_DEFAULT_WIDTH,
             height: RGB_DEFAULT_HEIGHT,
+            meta: FrameMeta::default(),
         }
     }
 }
@@ -438,6 +634,7 @@ impl fmt::Debug for Frame {
             .field("timestamp", &self.timestamp)
             .field("width", &self.width)
             .field("height", &self.height)
+            .field("meta", &self.meta)
             .finish_non_exhaustive()
     }
 }
@@ -0,0 +1,288 @@
+//! Lens dirt detection.
+//!
+//! A smudge or dust on the camera optics blurs the image and scatters the
+//! light, which lowers the contrast of the fine details and flattens the
+//! natural vignetting of the lens. The [`Monitor`] samples the frames of the
+//! RGB and IR cameras, computes these spatial statistics, and compares their
+//! short-term average against a long-term baseline. A sustained drop of the
+//! sharpness or change of the vignetting flags the camera lens as likely
+//! dirty.
+//!
+//! The baseline is learned from scratch on every start and is frozen while the
+//! lens is flagged, so it doesn't adapt to the dirt.
+
+use super::{ir, rgb, Frame as _};
+use crate::consts::{
+    LENS_DIRT_BASELINE_SAMPLES, LENS_DIRT_MIN_BRIGHTNESS, LENS_DIRT_SAMPLE_INTERVAL,
+    LENS_DIRT_SHARPNESS_RATIO, LENS_DIRT_VIGNETTING_DELTA,
+};
+use std::time::Duration;
+
+/// Distance between the sampled pixels.
+const PIXEL_STEP: usize = 4;
+
+/// Smoothing factor of the short-term average.
+const SHORT_TERM_ALPHA: f64 = 0.05;
+
+/// Smoothing factor of the long-term baseline.
+const BASELINE_ALPHA: f64 = 0.002;
+
+/// Monitored camera.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Camera {
+    /// RGB camera.
+    Rgb,
+    /// IR eye camera.
+    IrEye,
+    /// IR face camera.
+    IrFace,
+}
+
+/// Spatial statistics of a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// Mean absolute luma gradient relative to the mean luma.
+    pub sharpness: f64,
+    /// Relative brightness fall-off from the center to the corners.
+    pub vignetting: f64,
+}
+
+/// Lens dirt detector of a single camera.
+#[derive(Debug, Default)]
+pub struct Detector {
+    last_sample: Option<Duration>,
+    samples: u32,
+    short_term: Stats,
+    baseline: Stats,
+    dirty: bool,
+}
+
+/// Lens dirt detectors of all monitored cameras.
+#[derive(Debug, Default)]
+pub struct Monitor {
+    rgb: Detector,
+    ir_eye: Detector,
+    ir_face: Detector,
+}
+
+impl Camera {
+    /// Returns the metrics tag value of the camera.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rgb => "rgb",
+            Self::IrEye => "ir_eye",
+            Self::IrFace => "ir_face",
+        }
+    }
+}
+
+impl Stats {
+    /// Computes the statistics of a `width`×`height` image, reading the luma
+    /// of a pixel with `luma(x, y)`. Returns `None` if the image is too small
+    /// or too dark to be assessed.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn compute(width: usize, height: usize, luma: impl Fn(usize, usize) -> u8) -> Option<Self> {
+        if width < PIXEL_STEP * 8 || height < PIXEL_STEP * 8 {
+            return None;
+        }
+        let (mut sum, mut gradient, mut count) = (0.0, 0.0, 0.0);
+        for y in (0..height - PIXEL_STEP).step_by(PIXEL_STEP) {
+            for x in (0..width - PIXEL_STEP).step_by(PIXEL_STEP) {
+                let px = f64::from(luma(x, y));
+                sum += px;
+                gradient += (f64::from(luma(x + PIXEL_STEP, y)) - px).abs()
+                    + (f64::from(luma(x, y + PIXEL_STEP)) - px).abs();
+                count += 1.0;
+            }
+        }
+        let mean = sum / count;
+        if mean < LENS_DIRT_MIN_BRIGHTNESS {
+            return None;
+        }
+        let region_mean = |x0: usize, y0: usize, w: usize, h: usize| {
+            let (mut sum, mut count) = (0.0, 0.0);
+            for y in (y0..y0 + h).step_by(PIXEL_STEP) {
+                for x in (x0..x0 + w).step_by(PIXEL_STEP) {
+                    sum += f64::from(luma(x, y));
+                    count += 1.0;
+                }
+            }
+            sum / count
+        };
+        let (cw, ch) = (width / 8, height / 8);
+        let center = region_mean(width / 2 - cw, height / 2 - ch, cw * 2, ch * 2);
+        let corners = (region_mean(0, 0, cw, ch)
+            + region_mean(width - cw, 0, cw, ch)
+            + region_mean(0, height - ch, cw, ch)
+            + region_mean(width - cw, height - ch, cw, ch))
+            / 4.0;
+        let vignetting = if center > 0.0 { 1.0 - corners / center } else { 0.0 };
+        Some(Self { sharpness: gradient / count / mean, vignetting })
+    }
+
+    /// Computes the statistics of a grayscale IR frame.
+    #[must_use]
+    pub fn ir(frame: &ir::Frame) -> Option<Self> {
+        let (width, height) = (frame.width() as usize, frame.height() as usize);
+        let data = frame.as_bytes();
+        if data.len() < width * height {
+            return None;
+        }
+        Self::compute(width, height, |x, y| data[y * width + x])
+    }
+
+    /// Computes the statistics of an RGB frame from its green channel.
+    #[must_use]
+    pub fn rgb(frame: &rgb::Frame) -> Option<Self> {
+        let (width, height) = (frame.width() as usize, frame.height() as usize);
+        let data = frame.as_bytes();
+        if data.len() < width * height * 3 {
+            return None;
+        }
+        Self::compute(width, height, |x, y| data[(y * width + x) * 3 + 1])
+    }
+
+    fn blend(&mut self, other: Self, alpha: f64) {
+        self.sharpness += (other.sharpness - self.sharpness) * alpha;
+        self.vignetting += (other.vignetting - self.vignetting) * alpha;
+    }
+}
+
+impl Detector {
+    /// Returns `true` if a frame with the timestamp should be sampled.
+    #[must_use]
+    pub fn is_due(&self, timestamp: Duration) -> bool {
+        self.last_sample
+            .map_or(true, |last| timestamp < last || timestamp - last >= LENS_DIRT_SAMPLE_INTERVAL)
+    }
+
+    /// Feeds the statistics of a sampled frame. Returns the new lens state if
+    /// it has changed.
+    pub fn push(&mut self, timestamp: Duration, stats: Option<Stats>) -> Option<bool> {
+        self.last_sample = Some(timestamp);
+        let stats = stats?;
+        if self.samples == 0 {
+            self.short_term = stats;
+            self.baseline = stats;
+        }
+        self.samples = self.samples.saturating_add(1);
+        self.short_term.blend(stats, SHORT_TERM_ALPHA);
+        if self.samples < LENS_DIRT_BASELINE_SAMPLES {
+            self.baseline.blend(stats, 1.0 / f64::from(self.samples));
+            return None;
+        }
+        let ratio = self.sharpness_ratio();
+        let delta = self.short_term.vignetting - self.baseline.vignetting;
+        let dirty = if self.dirty {
+            // Hysteresis, so the prompt doesn't flicker around the thresholds.
+            ratio < (1.0 + LENS_DIRT_SHARPNESS_RATIO) / 2.0
+                || delta.abs() > LENS_DIRT_VIGNETTING_DELTA / 2.0
+        } else {
+            ratio < LENS_DIRT_SHARPNESS_RATIO || delta.abs() > LENS_DIRT_VIGNETTING_DELTA
+        };
+        if !dirty {
+            self.baseline.blend(stats, BASELINE_ALPHA);
+        }
+        (dirty != self.dirty).then(|| {
+            self.dirty = dirty;
+            dirty
+        })
+    }
+
+    /// Returns `true` if the lens is flagged as dirty.
+    #[must_use]
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns the short-term sharpness relative to the baseline.
+    #[must_use]
+    pub fn sharpness_ratio(&self) -> f64 {
+        if self.baseline.sharpness > 0.0 {
+            self.short_term.sharpness / self.baseline.sharpness
+        } else {
+            1.0
+        }
+    }
+}
+
+impl Monitor {
+    /// Feeds an RGB frame. Returns the new lens state if it has changed.
+    pub fn push_rgb(&mut self, frame: &rgb::Frame) -> Option<bool> {
+        let timestamp = frame.timestamp();
+        self.rgb.is_due(timestamp).then(|| self.rgb.push(timestamp, Stats::rgb(frame)))?
+    }
+
+    /// Feeds an IR frame. Returns the new lens state if it has changed.
+    pub fn push_ir(&mut self, camera: Camera, frame: &ir::Frame) -> Option<bool> {
+        let detector = self.detector_mut(camera);
+        let timestamp = frame.timestamp();
+        detector.is_due(timestamp).then(|| detector.push(timestamp, Stats::ir(frame)))?
+    }
+
+    /// Returns the detector of the camera.
+    #[must_use]
+    pub fn detector(&self, camera: Camera) -> &Detector {
+        match camera {
+            Camera::Rgb => &self.rgb,
+            Camera::IrEye => &self.ir_eye,
+            Camera::IrFace => &self.ir_face,
+        }
+    }
+
+    fn detector_mut(&mut self, camera: Camera) -> &mut Detector {
+        match camera {
+            Camera::Rgb => &mut self.rgb,
+            Camera::IrEye => &mut self.ir_eye,
+            Camera::IrFace => &mut self.ir_face,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard(blur: bool) -> impl Fn(usize, usize) -> u8 {
+        move |x, y| {
+            let on = (x / PIXEL_STEP + y / PIXEL_STEP) % 2 == 0;
+            match (on, blur) {
+                (true, false) => 200,
+                (false, false) => 50,
+                (true, true) => 140,
+                (false, true) => 110,
+            }
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let sharp = Stats::compute(128, 96, checkerboard(false)).unwrap();
+        let blurred = Stats::compute(128, 96, checkerboard(true)).unwrap();
+        assert!(blurred.sharpness < sharp.sharpness / 2.0);
+        assert!(sharp.vignetting.abs() < 0.1);
+        let vignetted =
+            Stats::compute(128, 96, |x, y| if (32..96).contains(&x) { 200 } else { 100 }).unwrap();
+        assert!(vignetted.vignetting > 0.4);
+        assert!(Stats::compute(128, 96, |_, _| 0).is_none());
+        assert!(Stats::compute(16, 16, |_, _| 100).is_none());
+    }
+
+    #[test]
+    fn test_detector() {
+        let sharp = Stats::compute(128, 96, checkerboard(false));
+        let blurred = Stats::compute(128, 96, checkerboard(true));
+        let mut detector = Detector::default();
+        let mut t = Duration::ZERO;
+        let mut changes = Vec::new();
+        for stats in [sharp; 2000].into_iter().chain([blurred; 200]).chain([sharp; 200]) {
+            t += LENS_DIRT_SAMPLE_INTERVAL;
+            assert!(detector.is_due(t));
+            changes.extend(detector.push(t, stats));
+        }
+        assert_eq!(changes, [true, false]);
+        assert!(!detector.is_due(t));
+    }
+}
@@ -2,6 +2,22 @@
 //!
 //! The Seek Thermal thermographic camera is connected via USB and provides
 //! grayscale images of infrared radiation.
+//!
+//! The camera periodically closes its shutter to run a flat field correction
+//! (FFC), which freezes the output for a few frames. To keep the shutter
+//! events out of a capture, the broker triggers an FFC right after starting
+//! the camera and switches the shutter to the manual mode until the camera is
+//! stopped.
+//!
+//! Besides the grayscale image, each frame carries the per-pixel temperatures
+//! in Kelvin and the temperature of the sensor itself, which the broker
+//! forwards to the [thermal monitor](crate::monitor::thermal::CameraSensor).
+//!
+//! The image processing of the camera is configured with [`Settings`]. The
+//! lite pipeline with the linear AGC and the filters disabled keeps the
+//! frames close to the raw thermography data for the fraud models.
+
+pub mod alignment;
 
 use super::FrameResolution;
 use crate::{
@@ -15,9 +31,11 @@ use agentwire::{
     port::{self, Port, SharedPort},
 };
 use eyre::{Error, Result, WrapErr};
+pub use orb_seekcamera::ShutterMode;
 use orb_seekcamera::{Camera, Rotation};
 use png::EncodingError;
 use rkyv::{Archive, Deserialize, Infallible, Serialize};
+use schemars::JsonSchema;
 use std::{
     env,
     io::prelude::*,
@@ -33,6 +51,111 @@ use std::{
 #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
 pub struct Sensor {
     pairing_status_timeout: Duration,
+    settings: Settings,
+}
+
+/// Image processing settings of the thermal camera.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    JsonSchema,
+    Archive,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "PascalCase")]
+pub struct Settings {
+    /// Image processing pipeline mode.
+    pub pipeline_mode: PipelineMode,
+    /// Automated gain control mode.
+    pub agc_mode: AgcMode,
+    /// Color palette of the color output formats.
+    pub color_palette: ColorPalette,
+    /// Whether the gradient correction filter is enabled.
+    pub gradient_correction: bool,
+    /// Whether the flat scene correction filter is enabled.
+    pub flat_scene_correction: bool,
+}
+
+/// Image processing pipeline mode.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    JsonSchema,
+    Archive,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineMode {
+    /// Minimal processing.
+    Lite,
+    /// Processing of the older SDK versions.
+    Legacy,
+    /// Full processing with the SeekVision enhancements.
+    #[default]
+    SeekVision,
+}
+
+/// Automated gain control mode.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    JsonSchema,
+    Archive,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AgcMode {
+    /// Linear mapping of the scene range.
+    Linear,
+    /// Histogram equalization.
+    #[default]
+    HistogramEqualization,
+}
+
+/// Color palette of the color output formats.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    JsonSchema,
+    Archive,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorPalette {
+    /// Hot is white, cold is black.
+    #[default]
+    WhiteHot,
+    /// Hot is black, cold is white.
+    BlackHot,
+    /// Iron palette.
+    Iron,
 }
 
 /// Thermal camera frame.
@@ -51,6 +174,11 @@ pub enum Command {
     Stop,
     /// Creates a new Flat Scene Correction (FSC).
     FscCalibrate,
+    /// Closes the shutter and runs a Flat Field Correction (FFC). Ignored
+    /// while not capturing.
+    TriggerFfc,
+    /// Sets the shutter mode.
+    SetShutterMode(ShutterMode),
 }
 
 impl Port for Sensor {
@@ -65,7 +193,8 @@ impl SharedPort for Sensor {
     const SERIALIZED_INIT_SIZE: usize =
         size_of::<usize>() + size_of::<<Sensor as Archive>::Archived>();
     const SERIALIZED_INPUT_SIZE: usize = 128;
-    const SERIALIZED_OUTPUT_SIZE: usize = 128 + THERMAL_HEIGHT as usize * THERMAL_WIDTH as usize;
+    const SERIALIZED_OUTPUT_SIZE: usize =
+        128 + THERMAL_HEIGHT as usize * THERMAL_WIDTH as usize * (1 + size_of::<f32>());
 }
 
 impl agentwire::Agent for Sensor {
@@ -86,7 +215,10 @@ impl agentwire::agent::Process for Sensor {
             Rotation::Clockwise
         };
         let camera = match Camera::attach(self.pairing_status_timeout, rotation) {
-            Ok(camera) => camera,
+            Ok(camera) => {
+                self.settings.apply(&camera)?;
+                camera
+            }
             Err(err) => {
                 tracing::error!("Error connecting to the thermal camera: {err}");
                 loop {
@@ -99,7 +231,8 @@ impl agentwire::agent::Process for Sensor {
             loop {
                 match port.recv().value.deserialize(&mut Infallible).unwrap() {
                     Command::Start => break,
-                    Command::Stop | Command::FscCalibrate => {}
+                    Command::SetShutterMode(mode) => set_shutter_mode(&camera, mode)?,
+                    Command::Stop | Command::FscCalibrate | Command::TriggerFfc => {}
                 }
             }
             camera.capture_start()?;
@@ -111,6 +244,11 @@ impl agentwire::agent::Process for Sensor {
                         Command::Start => {}
                         Command::Stop => break,
                         Command::FscCalibrate => camera.store_flat_scene_correction()?,
+                        Command::TriggerFfc => {
+                            tracing::info!("Triggering thermal camera FFC");
+                            camera.trigger_ffc()?;
+                        }
+                        Command::SetShutterMode(mode) => set_shutter_mode(&camera, mode)?,
                     }
                 }
             }
@@ -123,9 +261,79 @@ impl agentwire::agent::Process for Sensor {
     }
 }
 
+fn set_shutter_mode(camera: &Camera, mode: ShutterMode) -> Result<()> {
+    if camera.shutter_mode()? != mode {
+        tracing::info!("Setting thermal camera shutter mode to {mode:?}");
+        camera.set_shutter_mode(mode)?;
+    }
+    Ok(())
+}
+
 impl From<&Config> for Sensor {
     fn from(config: &Config) -> Self {
-        Self { pairing_status_timeout: config.thermal_camera_pairing_status_timeout }
+        Self {
+            pairing_status_timeout: config.thermal_camera_pairing_status_timeout,
+            settings: config.thermal_camera_settings,
+        }
+    }
+}
+
+impl Settings {
+    fn apply(&self, camera: &Camera) -> Result<()> {
+        tracing::info!("Applying thermal camera settings: {self:?}");
+        camera.set_pipeline_mode(self.pipeline_mode.into())?;
+        camera.set_agc_mode(self.agc_mode.into())?;
+        camera.set_color_palette(self.color_palette.into())?;
+        camera.set_filter_enabled(
+            orb_seekcamera::Filter::GradientCorrection,
+            self.gradient_correction,
+        )?;
+        camera.set_filter_enabled(
+            orb_seekcamera::Filter::FlatSceneCorrection,
+            self.flat_scene_correction,
+        )?;
+        Ok(())
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            pipeline_mode: PipelineMode::default(),
+            agc_mode: AgcMode::default(),
+            color_palette: ColorPalette::default(),
+            gradient_correction: true,
+            flat_scene_correction: true,
+        }
+    }
+}
+
+impl From<PipelineMode> for orb_seekcamera::PipelineMode {
+    fn from(mode: PipelineMode) -> Self {
+        match mode {
+            PipelineMode::Lite => Self::Lite,
+            PipelineMode::Legacy => Self::Legacy,
+            PipelineMode::SeekVision => Self::SeekVision,
+        }
+    }
+}
+
+impl From<AgcMode> for orb_seekcamera::AgcMode {
+    fn from(mode: AgcMode) -> Self {
+        match mode {
+            AgcMode::Linear => Self::Linear,
+            AgcMode::HistogramEqualization => Self::HistogramEqualization,
+        }
+    }
+}
+
+impl From<ColorPalette> for orb_seekcamera::ColorPalette {
+    fn from(palette: ColorPalette) -> Self {
+        match palette {
+            ColorPalette::WhiteHot => Self::WhiteHot,
+            ColorPalette::BlackHot => Self::BlackHot,
+            ColorPalette::Iron => Self::Iron,
+        }
     }
 }
 
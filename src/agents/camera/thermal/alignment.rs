@@ -0,0 +1,215 @@
+//! Temporal alignment of thermal frames with IR captures.
+//!
+//! Seek frames arrive at a lower and jittery rate compared to the IR captures.
+//! [`Aligner`] keeps a short history of thermal frames on the common timebase
+//! of the port source timestamps, and provides the nearest or a linearly
+//! interpolated thermal frame for the timestamp of an IR frame.
+
+use super::Frame;
+use crate::consts::{THERMAL_ALIGNMENT_BUFFER_SIZE, THERMAL_ALIGNMENT_MAX_GAP};
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// History of thermal frames for temporal alignment.
+#[derive(Debug)]
+pub struct Aligner {
+    frames: VecDeque<(Instant, Frame)>,
+    capacity: usize,
+    max_gap: Duration,
+}
+
+/// Thermal frame aligned to a target timestamp.
+#[derive(Clone, Debug)]
+pub struct Aligned {
+    /// Thermal frame.
+    pub frame: Frame,
+    /// Source timestamp of the frame on the common timebase.
+    pub source_ts: Instant,
+    /// Absolute distance between the frame and the target timestamp. Zero for
+    /// interpolated frames.
+    pub offset: Duration,
+    /// Whether the frame is interpolated from two neighbouring frames.
+    pub interpolated: bool,
+}
+
+impl Default for Aligner {
+    fn default() -> Self {
+        Self::new(THERMAL_ALIGNMENT_BUFFER_SIZE, THERMAL_ALIGNMENT_MAX_GAP)
+    }
+}
+
+impl Aligner {
+    /// Creates a new aligner keeping up to `capacity` frames. Frames further
+    /// than `max_gap` from the target timestamp are never used.
+    #[must_use]
+    pub fn new(capacity: usize, max_gap: Duration) -> Self {
+        Self { frames: VecDeque::with_capacity(capacity), capacity, max_gap }
+    }
+
+    /// Adds a new thermal frame with its source timestamp.
+    pub fn push(&mut self, source_ts: Instant, frame: Frame) {
+        // Frames normally arrive in order, but keep the history sorted anyway.
+        let index = self.frames.partition_point(|(ts, _)| *ts <= source_ts);
+        self.frames.insert(index, (source_ts, frame));
+        while self.frames.len() > self.capacity {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Removes all frames from the history.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Returns the number of frames in the history.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns `true` if the history is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Returns the thermal frame closest to `target_ts`.
+    #[must_use]
+    pub fn nearest(&self, target_ts: Instant) -> Option<Aligned> {
+        let (before, after) = self.neighbours(target_ts);
+        [before, after]
+            .into_iter()
+            .flatten()
+            .map(|(source_ts, frame)| Aligned {
+                frame: frame.clone(),
+                source_ts: *source_ts,
+                offset: distance(*source_ts, target_ts),
+                interpolated: false,
+            })
+            .min_by_key(|aligned| aligned.offset)
+    }
+
+    /// Returns a thermal frame linearly interpolated between the two frames
+    /// around `target_ts`. Falls back to [`Self::nearest`] if only one
+    /// neighbouring frame is available.
+    #[must_use]
+    pub fn interpolate(&self, target_ts: Instant) -> Option<Aligned> {
+        let (Some((before_ts, before)), Some((after_ts, after))) = self.neighbours(target_ts)
+        else {
+            return self.nearest(target_ts);
+        };
+        let span = after_ts.saturating_duration_since(*before_ts);
+        if span.is_zero() || before.data().len() != after.data().len() {
+            return self.nearest(target_ts);
+        }
+        let weight =
+            target_ts.saturating_duration_since(*before_ts).as_secs_f64() / span.as_secs_f64();
+        Some(Aligned {
+            frame: blend(before, after, weight),
+            source_ts: target_ts,
+            offset: Duration::ZERO,
+            interpolated: true,
+        })
+    }
+
+    /// Returns the last frame at or before `target_ts` and the first frame
+    /// after it, skipping frames further than the maximum gap.
+    fn neighbours(
+        &self,
+        target_ts: Instant,
+    ) -> (Option<&(Instant, Frame)>, Option<&(Instant, Frame)>) {
+        let index = self.frames.partition_point(|(ts, _)| *ts <= target_ts);
+        let within_gap = |entry: &&(Instant, Frame)| distance(entry.0, target_ts) <= self.max_gap;
+        let before = index.checked_sub(1).and_then(|i| self.frames.get(i)).filter(within_gap);
+        let after = self.frames.get(index).filter(within_gap);
+        (before, after)
+    }
+}
+
+fn distance(a: Instant, b: Instant) -> Duration {
+    a.saturating_duration_since(b).max(b.saturating_duration_since(a))
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn blend(before: &Frame, after: &Frame, weight: f64) -> Frame {
+    let data = before
+        .data()
+        .iter()
+        .zip(after.data())
+        .map(|(&a, &b)| (f64::from(a) * (1.0 - weight) + f64::from(b) * weight).round() as u8)
+        .collect();
+    let temperatures = before
+        .temperatures()
+        .iter()
+        .zip(after.temperatures())
+        .map(|(&a, &b)| (f64::from(a) * (1.0 - weight) + f64::from(b) * weight) as f32)
+        .collect();
+    let sensor_temperature = match (before.sensor_temperature(), after.sensor_temperature()) {
+        (Some(a), Some(b)) => Some((f64::from(a) * (1.0 - weight) + f64::from(b) * weight) as f32),
+        (a, b) => a.or(b),
+    };
+    let timestamp = before.timestamp().mul_f64(1.0 - weight) + after.timestamp().mul_f64(weight);
+    Frame(Arc::new(
+        orb_seekcamera::Frame::new(data, timestamp, before.width(), before.height())
+            .with_thermography(temperatures, sensor_temperature),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(value: u8) -> Frame {
+        Frame(Arc::new(orb_seekcamera::Frame::new(vec![value; 4], Duration::ZERO, 2, 2)))
+    }
+
+    fn aligner(start: Instant) -> Aligner {
+        let mut aligner = Aligner::new(3, Duration::from_millis(200));
+        aligner.push(start, frame(0));
+        aligner.push(start + Duration::from_millis(100), frame(100));
+        aligner
+    }
+
+    #[test]
+    fn test_nearest() {
+        let start = Instant::now();
+        let aligner = aligner(start);
+        let aligned = aligner.nearest(start + Duration::from_millis(70)).unwrap();
+        assert_eq!(aligned.frame.data(), [100; 4]);
+        assert_eq!(aligned.offset, Duration::from_millis(30));
+        assert!(!aligned.interpolated);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let start = Instant::now();
+        let aligner = aligner(start);
+        let aligned = aligner.interpolate(start + Duration::from_millis(25)).unwrap();
+        assert_eq!(aligned.frame.data(), [25; 4]);
+        assert!(aligned.interpolated);
+    }
+
+    #[test]
+    fn test_interpolate_falls_back_to_nearest() {
+        let start = Instant::now();
+        let aligner = aligner(start);
+        let aligned = aligner.interpolate(start + Duration::from_millis(150)).unwrap();
+        assert_eq!(aligned.frame.data(), [100; 4]);
+        assert!(!aligned.interpolated);
+        assert!(aligner.interpolate(start + Duration::from_millis(400)).is_none());
+    }
+
+    #[test]
+    fn test_out_of_order_and_capacity() {
+        let start = Instant::now();
+        let mut aligner = aligner(start);
+        aligner.push(start + Duration::from_millis(300), frame(30));
+        aligner.push(start + Duration::from_millis(200), frame(20));
+        assert_eq!(aligner.len(), 3);
+        let aligned = aligner.nearest(start).unwrap();
+        assert_eq!(aligned.frame.data(), [100; 4]);
+    }
+}
@@ -1,23 +1,48 @@
 //! Data uploader agent.
 //!
 //! The agent uploads data asynchronously in the background to the backend.
+//! The upload queues are persisted on the SSD, and can be inspected and
+//! modified at runtime through the [`Control`] requests, which are exposed
+//! over DBus and by the `orb-core uploads` subcommand.
+//!
+//! The items persisted by a previous run are re-validated before their upload
+//! is resumed, and the ones that don't pass are dropped.
 
-use crate::{backend, config::Config, dd_incr, dd_timing, ssd};
+use crate::{
+    backend,
+    config::Config,
+    consts::DATA_UPLOADER_BASE_DIR,
+    dd_incr, dd_timing,
+    monitor::traffic::{self, Class},
+    ssd,
+};
 use agentwire::port::{self, Port};
-use eyre::{Error, Result};
-use futures::{channel::oneshot, prelude::*, stream::FuturesUnordered};
+use eyre::{bail, Error, Result};
+use futures::{
+    channel::oneshot,
+    future::{AbortHandle, AbortRegistration, Abortable},
+    prelude::*,
+    stream::FuturesUnordered,
+};
 use orb_wld_data_id::SignupId;
+use ring::digest::{digest, SHA256};
 use serde::{Deserialize, Serialize};
 use std::{
     array,
-    collections::{BTreeSet, VecDeque},
+    collections::{BTreeSet, HashMap, VecDeque},
     convert::Infallible,
     mem::take,
-    path::PathBuf,
-    sync::Arc,
-    time::Instant,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    fs, select,
+    sync::{mpsc, Mutex},
 };
-use tokio::{fs, select, sync::Mutex};
 
 const PARALLEL_UPLOAD_STREAMS: usize = 4;
 const TIERS_COUNT: u8 = 2;
@@ -27,6 +52,8 @@ const TIERS_COUNT: u8 = 2;
 pub struct Agent {
     /// Shared Orb configuration.
     pub config: Arc<Mutex<Config>>,
+    /// Queue control requests receiver.
+    pub control: Arc<Mutex<mpsc::Receiver<Control>>>,
 }
 
 /// Data uploader agent input.
@@ -36,6 +63,53 @@ pub enum Input {
     Pcp(Pcp),
     /// Wait for all queues to be not full.
     WaitQueues(oneshot::Sender<()>),
+    /// Wait for all queues to be empty and the uploads to complete.
+    Drain(oneshot::Sender<()>),
+}
+
+/// Upload queue control request.
+#[derive(Debug)]
+pub enum Control {
+    /// List all queued items.
+    List(oneshot::Sender<Vec<QueueItem>>),
+    /// Move an item to the front of its queue. An item being uploaded is
+    /// interrupted and uploaded again from scratch.
+    Requeue {
+        /// Package tier.
+        tier: u8,
+        /// Item ID.
+        id: u64,
+        /// Result sender.
+        tx: oneshot::Sender<Result<()>>,
+    },
+    /// Remove an item from its queue without uploading it.
+    Drop {
+        /// Package tier.
+        tier: u8,
+        /// Item ID.
+        id: u64,
+        /// Result sender.
+        tx: oneshot::Sender<Result<()>>,
+    },
+}
+
+/// Upload queue item description.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueueItem {
+    /// Package tier.
+    pub tier: u8,
+    /// Item ID. `None` for the in-memory fallback queue.
+    pub id: Option<u64>,
+    /// Signup ID.
+    pub signup_id: SignupId,
+    /// Whether the item is being uploaded.
+    pub in_progress: bool,
+    /// Time since the item was queued in seconds, if known.
+    pub age: Option<u64>,
+    /// Package size in bytes.
+    pub size: u64,
+    /// Number of upload attempts.
+    pub attempts: u32,
 }
 
 /// Personal-custody package to upload.
@@ -54,19 +128,45 @@ pub struct Pcp {
     pub tier: u8,
 }
 
+impl Pcp {
+    /// Checks that the package persisted by a previous run is intact.
+    fn validate(&self) -> Result<()> {
+        if !(1..=TIERS_COUNT).contains(&self.tier) {
+            bail!("invalid tier: {}", self.tier);
+        }
+        if self.user_id.is_empty() {
+            bail!("empty user ID");
+        }
+        if digest(&SHA256, &self.data).as_ref() != self.checksum.as_slice() {
+            bail!("checksum mismatch");
+        }
+        Ok(())
+    }
+}
+
 enum Queue {
     Memory {
         queue: VecDeque<Pcp>,
     },
-    #[allow(dead_code)]
     Persistent {
         path: PathBuf,
         queue: VecDeque<u64>,
         next_id: u64,
         in_progress: u64,
+        /// Items with lower IDs were persisted by a previous run.
+        restored: u64,
     },
 }
 
+struct Upload {
+    tier: u8,
+    id: u64,
+    signup_id: SignupId,
+    size: u64,
+    attempts: Arc<AtomicU32>,
+    abort: AbortHandle,
+}
+
 impl Port for Agent {
     type Input = Input;
     type Output = Infallible;
@@ -92,6 +192,7 @@ macro_rules! log_queues {
 impl agentwire::agent::Task for Agent {
     type Error = Error;
 
+    #[allow(clippy::too_many_lines)]
     async fn run(self, mut port: port::Inner<Self>) -> Result<(), Self::Error> {
         let Config {
             pcp_tier1_blocking_threshold,
@@ -103,8 +204,19 @@ impl agentwire::agent::Task for Agent {
         let blocking_thresholds = [pcp_tier1_blocking_threshold, pcp_tier2_blocking_threshold];
         let dropping_thresholds = [pcp_tier1_dropping_threshold, pcp_tier2_dropping_threshold];
         let mut queues: [_; TIERS_COUNT as usize] = array::from_fn(|_| Queue::new_memory());
+        for (i, queue) in queues.iter_mut().enumerate() {
+            *queue =
+                Queue::new(Path::new(DATA_UPLOADER_BASE_DIR).join(format!("tier{}", i + 1))).await;
+        }
+        let mut control = self.control.lock().await;
         let mut uploaders = FuturesUnordered::new();
+        let mut uploads = Vec::<Upload>::new();
+        let mut retries = HashMap::<(u8, u64), u32>::new();
         let mut waiters = Vec::<oneshot::Sender<()>>::new();
+        let mut drainers = Vec::<oneshot::Sender<()>>::new();
+        let is_drained = |queues: &[Queue], uploading: bool| -> bool {
+            !uploading && queues.iter().all(|queue| queue.len() == 0)
+        };
         let check_blocking = |queues: &[Queue]| -> bool {
             for (i, queue) in queues.iter().enumerate() {
                 if queue.len() >= blocking_thresholds[i] as usize {
@@ -113,11 +225,23 @@ impl agentwire::agent::Task for Agent {
             }
             false
         };
+        // Resume the uploads persisted before the restart.
+        for queue in &mut queues {
+            while uploaders.len() < PARALLEL_UPLOAD_STREAMS {
+                let Some((pcp, id)) = queue.pop().await else { break };
+                uploaders.push(self.start_upload(&mut uploads, &mut retries, pcp, id));
+            }
+        }
         loop {
             select! {
                 biased;
-                Some((tier, id)) = uploaders.next() => {
-                    queues[usize::from(tier - 1)].commit(id).await;
+                Some((tier, id, completed)) = uploaders.next() => {
+                    if completed {
+                        if let Some(i) = uploads.iter().position(|u| u.tier == tier && u.id == id) {
+                            uploads.swap_remove(i);
+                        }
+                        queues[usize::from(tier - 1)].commit(id).await;
+                    }
                     if !check_blocking(&queues) {
                         for tx in take(&mut waiters) {
                             tx.send(()).unwrap();
@@ -126,10 +250,15 @@ impl agentwire::agent::Task for Agent {
                     for queue in &mut queues {
                         if let Some((pcp, id)) = queue.pop().await {
                             log_queues!(queues);
-                            uploaders.push(self.upload_pcp(pcp, id));
+                            uploaders.push(self.start_upload(&mut uploads, &mut retries, pcp, id));
                             break;
                         }
                     }
+                    if is_drained(&queues, !uploaders.is_empty()) {
+                        for tx in take(&mut drainers) {
+                            let _ = tx.send(());
+                        }
+                    }
                 },
                 input = port.next() => match input {
                     None => break,
@@ -147,7 +276,9 @@ impl agentwire::agent::Task for Agent {
                             if uploaders.len() < PARALLEL_UPLOAD_STREAMS {
                                 if let Some((pcp, id)) = queues[i].pop().await {
                                     log_queues!(queues);
-                                    uploaders.push(self.upload_pcp(pcp, id));
+                                    let upload =
+                                        self.start_upload(&mut uploads, &mut retries, pcp, id);
+                                    uploaders.push(upload);
                                 }
                             }
                         },
@@ -158,6 +289,38 @@ impl agentwire::agent::Task for Agent {
                                 tx.send(()).unwrap();
                             }
                         },
+                        Input::Drain(tx) => {
+                            if is_drained(&queues, !uploaders.is_empty()) {
+                                let _ = tx.send(());
+                            } else {
+                                drainers.push(tx);
+                            }
+                        },
+                    }
+                },
+                Some(request) = control.recv() => match request {
+                    Control::List(tx) => {
+                        let _ = tx.send(list(&queues, &uploads, &retries).await);
+                    }
+                    Control::Requeue { tier, id, tx } => {
+                        let result =
+                            modify(&mut queues, &mut uploads, &mut retries, tier, id, false).await;
+                        let _ = tx.send(result);
+                    }
+                    Control::Drop { tier, id, tx } => {
+                        let result =
+                            modify(&mut queues, &mut uploads, &mut retries, tier, id, true).await;
+                        if result.is_ok() && !check_blocking(&queues) {
+                            for tx in take(&mut waiters) {
+                                tx.send(()).unwrap();
+                            }
+                        }
+                        if result.is_ok() && is_drained(&queues, !uploaders.is_empty()) {
+                            for tx in take(&mut drainers) {
+                                let _ = tx.send(());
+                            }
+                        }
+                        let _ = tx.send(result);
                     }
                 },
             }
@@ -167,13 +330,50 @@ impl agentwire::agent::Task for Agent {
 }
 
 impl Agent {
-    async fn upload_pcp(&self, pcp: Pcp, id: u64) -> (u8, u64) {
+    fn start_upload<'a>(
+        &'a self,
+        uploads: &mut Vec<Upload>,
+        retries: &mut HashMap<(u8, u64), u32>,
+        pcp: Pcp,
+        id: u64,
+    ) -> impl Future<Output = (u8, u64, bool)> + 'a {
+        let (abort, registration) = AbortHandle::new_pair();
+        let attempts = Arc::new(AtomicU32::new(retries.remove(&(pcp.tier, id)).unwrap_or(0)));
+        uploads.push(Upload {
+            tier: pcp.tier,
+            id,
+            signup_id: pcp.signup_id.clone(),
+            size: pcp.data.len() as u64,
+            attempts: Arc::clone(&attempts),
+            abort,
+        });
+        self.upload(pcp, id, attempts, registration)
+    }
+
+    async fn upload(
+        &self,
+        pcp: Pcp,
+        id: u64,
+        attempts: Arc<AtomicU32>,
+        registration: AbortRegistration,
+    ) -> (u8, u64, bool) {
+        let tier = pcp.tier;
+        let result = Abortable::new(self.upload_pcp(pcp, &attempts), registration).await;
+        if result.is_err() {
+            tracing::info!("Personal custody package tier {tier} uploading interrupted");
+        }
+        (tier, id, result.is_ok())
+    }
+
+    async fn upload_pcp(&self, pcp: Pcp, attempts: &AtomicU32) {
         let Pcp { signup_id, user_id, data, checksum, tier } = pcp;
         tracing::info!(
             "Start uploading a personal custody package tier {tier} for signup_id={signup_id}"
         );
         let t = Instant::now();
         loop {
+            traffic::wait_budget(Class::Uploader).await;
+            attempts.fetch_add(1, Ordering::Relaxed);
             let response = backend::upload_personal_custody_package::request(
                 &signup_id,
                 &user_id,
@@ -213,16 +413,96 @@ impl Agent {
                 }
             }
         }
-        (tier, id)
     }
 }
 
+async fn list(
+    queues: &[Queue],
+    uploads: &[Upload],
+    retries: &HashMap<(u8, u64), u32>,
+) -> Vec<QueueItem> {
+    let mut items = Vec::new();
+    for upload in uploads {
+        let queue = &queues[usize::from(upload.tier - 1)];
+        items.push(QueueItem {
+            tier: upload.tier,
+            id: queue.is_persistent().then_some(upload.id),
+            signup_id: upload.signup_id.clone(),
+            in_progress: true,
+            age: queue.age(upload.id).await.map(|age| age.as_secs()),
+            size: upload.size,
+            attempts: upload.attempts.load(Ordering::Relaxed),
+        });
+    }
+    for (i, queue) in queues.iter().enumerate() {
+        #[allow(clippy::cast_possible_truncation)]
+        let tier = i as u8 + 1;
+        for (id, signup_id, size) in queue.pending().await {
+            items.push(QueueItem {
+                tier,
+                id,
+                signup_id,
+                in_progress: false,
+                age: match id {
+                    Some(id) => queue.age(id).await.map(|age| age.as_secs()),
+                    None => None,
+                },
+                size,
+                attempts: id.and_then(|id| retries.get(&(tier, id))).copied().unwrap_or(0),
+            });
+        }
+    }
+    items
+}
+
+/// Requeues or drops the item `id` of the `tier` queue.
+async fn modify(
+    queues: &mut [Queue],
+    uploads: &mut Vec<Upload>,
+    retries: &mut HashMap<(u8, u64), u32>,
+    tier: u8,
+    id: u64,
+    drop: bool,
+) -> Result<()> {
+    if !(1..=TIERS_COUNT).contains(&tier) {
+        bail!("invalid tier: {tier}");
+    }
+    let queue = &mut queues[usize::from(tier - 1)];
+    if !queue.is_persistent() {
+        bail!("tier {tier} queue is not persistent");
+    }
+    if let Some(i) = uploads.iter().position(|u| u.tier == tier && u.id == id) {
+        let upload = uploads.swap_remove(i);
+        upload.abort.abort();
+        if drop {
+            queue.commit(id).await;
+        } else {
+            retries.insert((tier, id), upload.attempts.load(Ordering::Relaxed));
+            queue.restore(id);
+        }
+    } else if drop {
+        if !queue.remove(id).await {
+            bail!("no item {id} in tier {tier} queue");
+        }
+        retries.remove(&(tier, id));
+    } else if !queue.requeue(id) {
+        bail!("no item {id} in tier {tier} queue");
+    }
+    if drop {
+        tracing::warn!("Data uploader item {id} of tier {tier} dropped");
+        dd_incr!("main.count.data_uploader.control", "action:drop");
+    } else {
+        tracing::info!("Data uploader item {id} of tier {tier} requeued");
+        dd_incr!("main.count.data_uploader.control", "action:requeue");
+    }
+    Ok(())
+}
+
 impl Queue {
     fn new_memory() -> Self {
         Self::Memory { queue: VecDeque::new() }
     }
 
-    #[allow(dead_code)]
     async fn new(path: PathBuf) -> Self {
         let ssd_perform = ssd::perform_async(async {
             fs::create_dir_all(&path).await?;
@@ -247,7 +527,7 @@ impl Queue {
             Some(Some(queue)) => {
                 let next_id =
                     queue.back().map_or(0, |id| id.checked_add(1).expect("shouldn't grow so fast"));
-                Self::Persistent { path, queue, next_id, in_progress: 0 }
+                Self::Persistent { path, queue, next_id, in_progress: 0, restored: next_id }
             }
         }
     }
@@ -290,38 +570,46 @@ impl Queue {
     }
 
     async fn pop(&mut self) -> Option<(Pcp, u64)> {
-        match self {
-            Self::Memory { queue } => queue.pop_front().map(|pcp| (pcp, 0)),
-            Self::Persistent { path, queue, in_progress, .. } => {
-                let id = queue.pop_front()?;
-                let ssd_perform = ssd::perform_async(async {
-                    let path = path.join(id.to_string());
-                    let meta = fs::read_to_string(path.join("meta.json")).await?;
-                    let mut pcp = serde_json::from_str::<Pcp>(&meta)?;
-                    pcp.data = fs::read(path.join("data.bin")).await?;
-                    Ok(pcp)
-                });
-                match ssd_perform.await {
-                    None => {
-                        tracing::error!(
-                            "Persistent queue is failed during pop, switching to memory"
-                        );
-                        *self = Self::new_memory();
-                        None
-                    }
-                    Some(pcp) => {
-                        *in_progress += 1;
-                        Some((pcp, id))
-                    }
+        loop {
+            let (path, queue, in_progress, restored) = match self {
+                Self::Memory { queue } => return queue.pop_front().map(|pcp| (pcp, 0)),
+                Self::Persistent { path, queue, in_progress, restored, .. } => {
+                    (path, queue, in_progress, restored)
+                }
+            };
+            let id = queue.pop_front()?;
+            let ssd_perform = ssd::perform_async(async {
+                let path = path.join(id.to_string());
+                let meta = fs::read_to_string(path.join("meta.json")).await?;
+                let mut pcp = serde_json::from_str::<Pcp>(&meta)?;
+                pcp.data = fs::read(path.join("data.bin")).await?;
+                Ok(pcp)
+            });
+            let Some(pcp) = ssd_perform.await else {
+                tracing::error!("Persistent queue is failed during pop, switching to memory");
+                *self = Self::new_memory();
+                return None;
+            };
+            *in_progress += 1;
+            if id < *restored {
+                if let Err(err) = pcp.validate() {
+                    tracing::error!(
+                        "Dropping persisted item {id} for signup_id={}: {err}",
+                        pcp.signup_id
+                    );
+                    dd_incr!("main.count.data_uploader.control", "action:invalid");
+                    self.commit(id).await;
+                    continue;
                 }
             }
+            return Some((pcp, id));
         }
     }
 
     async fn commit(&mut self, id: u64) {
         match self {
             Self::Memory { .. } => {}
-            Self::Persistent { path, next_id, in_progress, .. } => {
+            Self::Persistent { path, queue, next_id, in_progress, .. } => {
                 *in_progress = in_progress.checked_sub(1).expect("shouldn't go negative");
                 match ssd::perform_async(fs::remove_dir_all(path.join(id.to_string()))).await {
                     None => {
@@ -331,7 +619,7 @@ impl Queue {
                         *self = Self::new_memory();
                     }
                     Some(()) => {
-                        if *in_progress == 0 {
+                        if *in_progress == 0 && queue.is_empty() {
                             *next_id = 0;
                         }
                     }
@@ -359,6 +647,72 @@ impl Queue {
             }
         }
     }
+
+    fn is_persistent(&self) -> bool {
+        matches!(self, Self::Persistent { .. })
+    }
+
+    /// Returns the ID, signup ID, and size of every pending item.
+    async fn pending(&self) -> Vec<(Option<u64>, SignupId, u64)> {
+        match self {
+            Self::Memory { queue } => queue
+                .iter()
+                .map(|pcp| (None, pcp.signup_id.clone(), pcp.data.len() as u64))
+                .collect(),
+            Self::Persistent { path, queue, .. } => {
+                let mut items = Vec::with_capacity(queue.len());
+                for &id in queue {
+                    let ssd_perform = ssd::perform_async(async {
+                        let path = path.join(id.to_string());
+                        let meta = fs::read_to_string(path.join("meta.json")).await?;
+                        let pcp = serde_json::from_str::<Pcp>(&meta)?;
+                        let size = fs::metadata(path.join("data.bin")).await?.len();
+                        Ok((Some(id), pcp.signup_id, size))
+                    });
+                    items.extend(ssd_perform.await);
+                }
+                items
+            }
+        }
+    }
+
+    /// Returns the time since the item `id` was persisted.
+    async fn age(&self, id: u64) -> Option<Duration> {
+        let Self::Persistent { path, .. } = self else { return None };
+        let modified =
+            ssd::perform_async(async { fs::metadata(path.join(id.to_string())).await?.modified() })
+                .await?;
+        SystemTime::now().duration_since(modified).ok()
+    }
+
+    /// Moves the pending item `id` to the front of the queue.
+    fn requeue(&mut self, id: u64) -> bool {
+        let Self::Persistent { queue, .. } = self else { return false };
+        let Some(i) = queue.iter().position(|&queued| queued == id) else { return false };
+        queue.remove(i);
+        queue.push_front(id);
+        true
+    }
+
+    /// Returns the interrupted item `id` to the front of the queue.
+    fn restore(&mut self, id: u64) {
+        if let Self::Persistent { queue, in_progress, .. } = self {
+            *in_progress = in_progress.checked_sub(1).expect("shouldn't go negative");
+            queue.push_front(id);
+        }
+    }
+
+    /// Removes the pending item `id` from the queue.
+    async fn remove(&mut self, id: u64) -> bool {
+        let Self::Persistent { path, queue, .. } = self else { return false };
+        let Some(i) = queue.iter().position(|&queued| queued == id) else { return false };
+        queue.remove(i);
+        if ssd::perform_async(fs::remove_dir_all(path.join(id.to_string()))).await.is_none() {
+            tracing::error!("Persistent queue is failed during remove, switching to memory");
+            *self = Self::new_memory();
+        }
+        true
+    }
 }
 
 /// Waits for all queues to be not full.
@@ -368,6 +722,23 @@ pub async fn wait_queues(port: &mut port::Outer<Agent>) -> Result<()> {
     Ok(rx.await?)
 }
 
+/// Waits for all queues to be empty and the uploads to complete.
+pub async fn drain(port: &mut port::Outer<Agent>) -> Result<()> {
+    let (tx, rx) = oneshot::channel();
+    port.send(port::Input::new(Input::Drain(tx))).await?;
+    Ok(rx.await?)
+}
+
+/// Sends a control request to the data uploader agent.
+pub async fn control<T>(
+    tx: &mpsc::Sender<Control>,
+    request: impl FnOnce(oneshot::Sender<T>) -> Control,
+) -> Result<T> {
+    let (response_tx, response_rx) = oneshot::channel();
+    tx.send(request(response_tx)).await.map_err(|_| eyre::eyre!("data uploader is not running"))?;
+    Ok(response_rx.await?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -534,4 +905,65 @@ mod tests {
         let mut queue = Queue::new(tempdir.path().to_path_buf()).await;
         assert_eq!(queue.pop().await, None);
     }
+
+    #[tokio::test]
+    async fn test_persistent_queue_control() {
+        let tempdir = tempdir().unwrap();
+        let mut queue = Queue::new(tempdir.path().to_path_buf()).await;
+        for data in [vec![1], vec![2, 3], vec![4, 5, 6]] {
+            queue
+                .push(Pcp {
+                    signup_id: SignupId::default(),
+                    user_id: "test".to_string(),
+                    data,
+                    checksum: vec![],
+                    tier: 1,
+                })
+                .await;
+        }
+        let pending = queue.pending().await;
+        let sizes = pending.iter().map(|(id, _, size)| (id.unwrap(), *size)).collect::<Vec<_>>();
+        assert_eq!(sizes, [(0, 1), (1, 2), (2, 3)]);
+        assert!(queue.age(0).await.is_some());
+
+        assert!(queue.requeue(2));
+        assert!(!queue.requeue(3));
+        assert!(queue.remove(0).await);
+        assert!(!queue.remove(0).await);
+        assert_eq!(queue.pop().await.unwrap().1, 2);
+        queue.restore(2);
+        assert_eq!(queue.pop().await.unwrap().1, 2);
+        queue.commit(2).await;
+        assert_eq!(queue.pop().await.unwrap().1, 1);
+        queue.commit(1).await;
+        assert_eq!(queue.pop().await, None);
+
+        // The directory should be empty now.
+        let mut queue = Queue::new(tempdir.path().to_path_buf()).await;
+        assert_eq!(queue.pop().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_queue_revalidation() {
+        let tempdir = tempdir().unwrap();
+        let mut queue = Queue::new(tempdir.path().to_path_buf()).await;
+        let pcp = |data: Vec<u8>, checksum: Vec<u8>| Pcp {
+            signup_id: SignupId::default(),
+            user_id: "test".to_string(),
+            checksum,
+            data,
+            tier: 1,
+        };
+        queue.push(pcp(vec![1, 2, 3], vec![4, 5, 6])).await;
+        queue.push(pcp(vec![7, 8, 9], digest(&SHA256, &[7, 8, 9]).as_ref().to_vec())).await;
+
+        // Simulate a restart.
+        let mut queue = Queue::new(tempdir.path().to_path_buf()).await;
+        let (restored, id) = queue.pop().await.unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(restored.data, [7, 8, 9]);
+        assert_eq!(queue.pop().await, None);
+        queue.commit(1).await;
+        assert!(!tempdir.path().join("0").exists());
+    }
 }
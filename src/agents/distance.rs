@@ -1,5 +1,7 @@
 //! Distance measurement agent.
 
+pub mod occupancy;
+
 use crate::{
     agents::python,
     consts::{IRIS_SHARPNESS_MIN, IR_FOCUS_DISTANCE, IR_FOCUS_RANGE, IR_FOCUS_RANGE_SMALL},
@@ -0,0 +1,207 @@
+//! Anonymous capture zone occupancy counting.
+//!
+//! Counts people entering and leaving the capture zone in front of the orb and
+//! their dwell times, using only the transitions of the 1D ToF distance
+//! reported by the main MCU. No image data is involved. The counts are
+//! aggregated over [`OCCUPANCY_REPORT_INTERVAL`] windows and published as
+//! Datadog metrics for the venue throughput analytics.
+
+use crate::{
+    consts::{
+        OCCUPANCY_DEBOUNCE, OCCUPANCY_ENTER_DISTANCE_MM, OCCUPANCY_LEAVE_DISTANCE_MM,
+        OCCUPANCY_REPORT_INTERVAL,
+    },
+    dd_count, dd_gauge,
+};
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the dwell time histogram buckets. The last bucket is
+/// unbounded.
+const DWELL_BUCKETS: [(Duration, &str); 5] = [
+    (Duration::from_secs(10), "lt_10s"),
+    (Duration::from_secs(30), "lt_30s"),
+    (Duration::from_secs(60), "lt_1m"),
+    (Duration::from_secs(2 * 60), "lt_2m"),
+    (Duration::from_secs(5 * 60), "lt_5m"),
+];
+
+/// Name of the unbounded dwell time bucket.
+const DWELL_BUCKET_LONG: &str = "ge_5m";
+
+/// Occupancy counter fed with the ToF distance readings.
+#[derive(Debug)]
+pub struct Counter {
+    occupied_since: Option<Instant>,
+    pending: Option<Instant>,
+    last_sample: Option<Instant>,
+    window: Report,
+}
+
+/// Occupancy aggregated over a report window.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Window duration.
+    pub duration: Duration,
+    /// Number of people entered the capture zone.
+    pub entries: u32,
+    /// Number of people left the capture zone.
+    pub exits: u32,
+    /// Number of visits per dwell time bucket, see [`DWELL_BUCKETS`].
+    pub dwell_histogram: [u32; DWELL_BUCKETS.len() + 1],
+    /// Total time the capture zone was occupied.
+    pub occupied: Duration,
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Counter {
+    /// Creates a new counter with a vacant capture zone.
+    #[must_use]
+    pub fn new() -> Self {
+        Self { occupied_since: None, pending: None, last_sample: None, window: Report::default() }
+    }
+
+    /// Feeds a ToF distance reading. Returns the aggregated report when the
+    /// report window has elapsed.
+    pub fn push(&mut self, distance_mm: u32, now: Instant) -> Option<Report> {
+        if let Some(last_sample) = self.last_sample.replace(now) {
+            let elapsed = now.saturating_duration_since(last_sample);
+            self.window.duration += elapsed;
+            if self.occupied_since.is_some() {
+                self.window.occupied += elapsed;
+            }
+        }
+        // Hysteresis between the enter and leave distances, so a person
+        // standing at the zone border doesn't produce spurious transitions.
+        let transition = match self.occupied_since {
+            None => distance_mm <= OCCUPANCY_ENTER_DISTANCE_MM,
+            Some(_) => distance_mm > OCCUPANCY_LEAVE_DISTANCE_MM,
+        };
+        if !transition {
+            self.pending = None;
+        } else if let Some(pending) = self.pending {
+            if now.saturating_duration_since(pending) >= OCCUPANCY_DEBOUNCE {
+                self.pending = None;
+                match self.occupied_since.take() {
+                    None => {
+                        self.occupied_since = Some(pending);
+                        self.window.entries += 1;
+                    }
+                    Some(since) => {
+                        self.window.exits += 1;
+                        self.window.record_dwell(pending.saturating_duration_since(since));
+                    }
+                }
+            }
+        } else {
+            self.pending = Some(now);
+        }
+        (self.window.duration >= OCCUPANCY_REPORT_INTERVAL)
+            .then(|| std::mem::take(&mut self.window))
+    }
+
+    /// Returns `true` if somebody is in the capture zone.
+    #[must_use]
+    pub fn is_occupied(&self) -> bool {
+        self.occupied_since.is_some()
+    }
+}
+
+impl Report {
+    fn record_dwell(&mut self, dwell: Duration) {
+        let bucket = DWELL_BUCKETS
+            .iter()
+            .position(|&(bound, _)| dwell < bound)
+            .unwrap_or(DWELL_BUCKETS.len());
+        self.dwell_histogram[bucket] += 1;
+    }
+
+    /// Returns the fraction of the window the capture zone was occupied.
+    #[must_use]
+    pub fn occupied_ratio(&self) -> f64 {
+        if self.duration.is_zero() {
+            0.0
+        } else {
+            self.occupied.as_secs_f64() / self.duration.as_secs_f64()
+        }
+    }
+
+    /// Publishes the report as Datadog metrics.
+    pub fn publish(&self) {
+        tracing::info!(
+            "Occupancy: {} entries, {} exits, {:.0}% occupied, dwell histogram {:?}",
+            self.entries,
+            self.exits,
+            self.occupied_ratio() * 100.0,
+            self.dwell_histogram
+        );
+        dd_count!("main.count.venue.occupancy.entries", i64::from(self.entries));
+        dd_count!("main.count.venue.occupancy.exits", i64::from(self.exits));
+        dd_gauge!("main.gauge.venue.occupancy.occupied_ratio", self.occupied_ratio().to_string());
+        let names = DWELL_BUCKETS.iter().map(|&(_, name)| name).chain([DWELL_BUCKET_LONG]);
+        for (name, &count) in names.zip(&self.dwell_histogram) {
+            dd_count!(
+                "main.count.venue.occupancy.dwell",
+                i64::from(count),
+                &format!("dwell:{name}")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAR: u32 = OCCUPANCY_LEAVE_DISTANCE_MM + 500;
+    const NEAR: u32 = OCCUPANCY_ENTER_DISTANCE_MM - 500;
+
+    fn feed(counter: &mut Counter, t: &mut Instant, distance_mm: u32, duration: Duration) {
+        let end = *t + duration;
+        while *t < end {
+            assert!(counter.push(distance_mm, *t).is_none());
+            *t += Duration::from_millis(100);
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        let mut counter = Counter::new();
+        let mut t = Instant::now();
+        feed(&mut counter, &mut t, FAR, Duration::from_secs(1));
+        // A glitch shorter than the debounce period is ignored.
+        feed(&mut counter, &mut t, NEAR, OCCUPANCY_DEBOUNCE / 2);
+        feed(&mut counter, &mut t, FAR, Duration::from_secs(1));
+        assert!(!counter.is_occupied());
+
+        feed(&mut counter, &mut t, NEAR, Duration::from_secs(20));
+        assert!(counter.is_occupied());
+        // Standing between the enter and leave distances keeps the zone occupied.
+        let border = (OCCUPANCY_ENTER_DISTANCE_MM + OCCUPANCY_LEAVE_DISTANCE_MM) / 2;
+        feed(&mut counter, &mut t, border, Duration::from_secs(5));
+        feed(&mut counter, &mut t, FAR, Duration::from_secs(1));
+        assert!(!counter.is_occupied());
+
+        let report = counter.window.clone();
+        assert_eq!((report.entries, report.exits), (1, 1));
+        assert_eq!(report.dwell_histogram, [0, 1, 0, 0, 0, 0]);
+        assert!((report.occupied.as_secs_f64() - 25.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_report_window() {
+        let mut counter = Counter::new();
+        let start = Instant::now();
+        assert!(counter.push(NEAR, start).is_none());
+        assert!(counter.push(NEAR, start + OCCUPANCY_DEBOUNCE).is_none());
+        let report = counter.push(FAR, start + OCCUPANCY_REPORT_INTERVAL).unwrap();
+        assert_eq!(report.entries, 1);
+        assert_eq!(report.duration, OCCUPANCY_REPORT_INTERVAL);
+        assert!(counter.is_occupied());
+        assert_eq!(counter.window, Report::default());
+    }
+}
@@ -1,16 +1,27 @@
 //! Eye PID controller agent.
+//!
+//! A single PID gain set overshoots at close range and is sluggish at far
+//! range, so the gains are scheduled on the user distance. The
+//! [`GainSchedule`] holds gain sets for several distances, and the active
+//! gains are linearly interpolated between them. The default schedule holds
+//! only the gains tuned for the best focus distance, so the distance-dependent
+//! gains come from the backend config.
 
 use crate::{
     agents::{mirror, python},
+    consts::IR_FOCUS_DISTANCE,
     dd_gauge,
     pid::{InstantTimer, Pid, Timer},
+    time_series::TimeSeries,
     utils::RkyvNdarray,
 };
 use agentwire::port::{self, Port};
 use eyre::{Error, Result};
-use futures::prelude::*;
+use futures::{channel::oneshot, prelude::*};
 use ndarray::prelude::*;
-use std::time::Duration;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{mem::take, time::Duration};
 
 const IRIS_DIAMETER_MM: f64 = 12.0;
 
@@ -41,13 +52,62 @@ pub struct Agent;
 #[derive(Debug)]
 pub enum Input {
     /// IR Net estimation.
-    IrNetEstimate(python::ir_net::EstimateOutput),
+    IrNetEstimate {
+        /// IR Net estimation.
+        estimate: python::ir_net::EstimateOutput,
+        /// Latest user distance estimate in millimeters, if any.
+        user_distance: Option<f64>,
+    },
     /// Notifies that the mirror is going to switch the taget eye.
     SwitchEye,
+    /// Replaces the gain schedule. Survives [`Input::Reset`].
+    SetGainSchedule(GainSchedule),
+    /// Takes the gain schedule log from the agent.
+    TakeLog(oneshot::Sender<Log>),
     /// Resets the internal state of the agent.
     Reset,
 }
 
+/// PID gains for a user distance.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "PascalCase")]
+pub struct Gains {
+    /// User distance in millimeters.
+    pub distance: f64,
+    /// Proportional gain.
+    pub proportional: f64,
+    /// Integral gain.
+    pub integral: f64,
+    /// Derivative gain.
+    pub derivative: f64,
+    /// Time constant of the derivative low-pass filter in seconds.
+    pub filter: f64,
+}
+
+/// PID gain sets keyed on the user distance, sorted by the distance.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct GainSchedule(Vec<Gains>);
+
+/// Gain schedule history.
+#[derive(Debug)]
+pub struct Log {
+    /// Active gains, recorded on every change.
+    pub gains: TimeSeries<Gains>,
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self { gains: TimeSeries::builder().limit(1_000_000).build() }
+    }
+}
+
+/// Takes the gain schedule history.
+pub async fn take_log(port: &mut port::Outer<Agent>) -> Result<Log> {
+    let (tx, rx) = oneshot::channel();
+    port.send_unjam(port::Input::new(Input::TakeLog(tx))).await?;
+    Ok(rx.await?)
+}
+
 impl Port for Agent {
     type Input = Input;
     type Output = mirror::Point;
@@ -64,21 +124,30 @@ impl agentwire::agent::Task for Agent {
     type Error = Error;
 
     async fn run(self, mut port: port::Inner<Self>) -> Result<(), Self::Error> {
+        let mut schedule = GainSchedule::default();
+        let mut log = Log::default();
         'reset: loop {
             let mut timer = InstantTimer::default();
             let mut controller = EyeOffsetController::new(RESET_DELAY.as_secs_f64());
+            let mut active_gains = None;
             let mut suppress_time = 0.0;
             while let Some(input) = port.next().await {
-                match &input.value {
-                    Input::IrNetEstimate(ir_net_estimate) => {
-                        let python::ir_net::EstimateOutput { landmarks, sharpness, .. } =
-                            ir_net_estimate;
+                let chain = input.chain_fn();
+                match input.value {
+                    Input::IrNetEstimate { estimate, user_distance } => {
+                        let python::ir_net::EstimateOutput { landmarks, sharpness, .. } = estimate;
                         let dt = timer.get_dt().unwrap_or(0.0);
                         suppress_time -= dt;
                         if suppress_time > 0.0 {
                             continue;
                         }
-                        let iris_center = (*sharpness > 1.1)
+                        let gains = schedule.gains(user_distance);
+                        if active_gains != Some(gains) {
+                            controller.set_gains(&gains);
+                            log.gains.push(gains);
+                            active_gains = Some(gains);
+                        }
+                        let iris_center = (sharpness > 1.1)
                             .then_some(landmarks.as_ref())
                             .flatten()
                             .map(RkyvNdarray::<_, Ix2>::as_ndarray)
@@ -98,12 +167,20 @@ impl agentwire::agent::Task for Agent {
                             y.to_string(),
                             "type:theta_degrees"
                         );
-                        port.send(input.chain(mirror::Point { phi_degrees: x, theta_degrees: y }))
+                        port.send(chain(mirror::Point { phi_degrees: x, theta_degrees: y }))
                             .await?;
                     }
                     Input::SwitchEye => {
                         suppress_time = SWITCH_EYE_INTERVAL.as_secs_f64();
                     }
+                    Input::SetGainSchedule(new_schedule) => {
+                        schedule = new_schedule;
+                        active_gains = None;
+                    }
+                    Input::TakeLog(log_tx) => {
+                        #[allow(let_underscore_drop)]
+                        let _ = log_tx.send(take(&mut log));
+                    }
                     Input::Reset => continue 'reset,
                 }
             }
@@ -123,7 +200,8 @@ pub struct EyeOffsetController {
 }
 
 impl EyeOffsetController {
-    /// Creates a new [`EyeOffsetController`].
+    /// Creates a new [`EyeOffsetController`] with the gains for the best
+    /// focus distance.
     #[must_use]
     pub fn new(reset_delay: f64) -> Self {
         let default_pid = Pid::default()
@@ -140,6 +218,16 @@ impl EyeOffsetController {
         }
     }
 
+    /// Replaces the PID gains, keeping the accumulated state.
+    pub fn set_gains(&mut self, gains: &Gains) {
+        for pid in [&mut self.horizontal, &mut self.vertical] {
+            pid.set_proportional(gains.proportional)
+                .set_integral(gains.integral)
+                .set_derivative(gains.derivative)
+                .set_filter(gains.filter.max(0.0));
+        }
+    }
+
     /// Updates the controller with predicted iris offset. Returns the mirror
     /// offset.
     pub fn update(&mut self, x: f64, y: f64, dt: f64) -> (f64, f64) {
@@ -164,7 +252,67 @@ impl EyeOffsetController {
     }
 }
 
-fn iris_center_from_landmarks(landmarks: ArrayView2<f32>) -> Option<(f64, f64)> {
+impl Gains {
+    fn lerp(&self, other: &Self, distance: f64) -> Self {
+        let t = (distance - self.distance) / (other.distance - self.distance);
+        let mix = |a: f64, b: f64| a + (b - a) * t;
+        Self {
+            distance,
+            proportional: mix(self.proportional, other.proportional),
+            integral: mix(self.integral, other.integral),
+            derivative: mix(self.derivative, other.derivative),
+            filter: mix(self.filter, other.filter),
+        }
+    }
+}
+
+impl GainSchedule {
+    /// Creates a new gain schedule. Returns `None` if the schedule is empty,
+    /// not sorted by strictly increasing distance, or has negative gains.
+    #[must_use]
+    pub fn new(gains: Vec<Gains>) -> Option<Self> {
+        let valid = !gains.is_empty()
+            && gains.windows(2).all(|pair| pair[0].distance < pair[1].distance)
+            && gains.iter().all(|gains| {
+                [gains.proportional, gains.integral, gains.derivative, gains.filter]
+                    .iter()
+                    .all(|gain| gain.is_finite() && *gain >= 0.0)
+            });
+        valid.then_some(Self(gains))
+    }
+
+    /// Returns the gains for the user distance in millimeters, linearly
+    /// interpolated between the neighbouring entries and clamped to the
+    /// outermost ones. Uses the best focus distance if the user distance is
+    /// unknown.
+    #[must_use]
+    pub fn gains(&self, user_distance: Option<f64>) -> Gains {
+        let distance = user_distance.filter(|distance| distance.is_finite());
+        let distance = distance.unwrap_or(IR_FOCUS_DISTANCE);
+        let upper = self.0.partition_point(|gains| gains.distance <= distance);
+        match (upper.checked_sub(1).and_then(|i| self.0.get(i)), self.0.get(upper)) {
+            (Some(lower), Some(upper)) => lower.lerp(upper, distance),
+            (Some(gains), None) | (None, Some(gains)) => *gains,
+            (None, None) => unreachable!("gain schedule is never empty"),
+        }
+    }
+}
+
+impl Default for GainSchedule {
+    fn default() -> Self {
+        Self(vec![Gains {
+            distance: IR_FOCUS_DISTANCE,
+            proportional: PID_PROPORTIONAL,
+            integral: PID_INTEGRAL,
+            derivative: PID_DERIVATIVE,
+            filter: PID_FILTER,
+        }])
+    }
+}
+
+/// Returns the iris center offset from the image center in millimeters, or
+/// `None` if the landmarks are incomplete or untrusted.
+pub fn iris_center_from_landmarks(landmarks: ArrayView2<f32>) -> Option<(f64, f64)> {
     let iris_width =
         IRIS_DIAMETER_MM / f64::from((landmarks.get((4, 0))? - landmarks.get((6, 0))?).abs());
     let center = landmarks.slice(s![4..8, ..]).mean_axis(Axis(0))?;
@@ -203,6 +351,29 @@ mod tests {
         assert_eq!(iris_center_from_landmarks(data.view()), expect);
     }
 
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_gain_schedule() {
+        let schedule = GainSchedule::default();
+        assert_eq!(schedule.gains(None).proportional, PID_PROPORTIONAL);
+        assert_eq!(schedule.gains(Some(100.0)).proportional, PID_PROPORTIONAL);
+        assert_eq!(schedule.gains(Some(1000.0)).integral, PID_INTEGRAL);
+
+        let near = Gains { distance: 200.0, proportional: 0.008, ..schedule.gains(None) };
+        let far = Gains { distance: 400.0, proportional: 0.016, ..schedule.gains(None) };
+        let schedule = GainSchedule::new(vec![near, far]).unwrap();
+        assert_eq!(schedule.gains(Some(100.0)).proportional, 0.008);
+        assert_eq!(schedule.gains(Some(1000.0)).proportional, 0.016);
+        let gains = schedule.gains(Some(300.0));
+        assert!((gains.proportional - 0.012).abs() < 1e-12);
+        assert_eq!(gains.derivative, PID_DERIVATIVE);
+
+        let gains = schedule.0.clone();
+        assert!(GainSchedule::new(gains.clone()).is_some());
+        assert!(GainSchedule::new(Vec::new()).is_none());
+        assert!(GainSchedule::new(gains.into_iter().rev().collect()).is_none());
+    }
+
     #[test]
     #[allow(clippy::float_cmp)]
     fn test_eye_offset_controller() {
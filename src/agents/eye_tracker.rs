@@ -357,12 +357,13 @@ impl Input {
         rgb_net_estimate: &python::rgb_net::EstimateOutput,
     ) -> Option<Self> {
         let prediction = rgb_net_estimate.primary()?;
+        let landmarks = prediction.smoothed_landmarks();
         Some(Self::Track {
             target_left_eye,
-            distorted_left_x: prediction.landmarks.left_eye.x,
-            distorted_left_y: prediction.landmarks.left_eye.y,
-            distorted_right_x: prediction.landmarks.right_eye.x,
-            distorted_right_y: prediction.landmarks.right_eye.y,
+            distorted_left_x: landmarks.left_eye.x,
+            distorted_left_y: landmarks.left_eye.y,
+            distorted_right_x: landmarks.right_eye.x,
+            distorted_right_y: landmarks.right_eye.y,
             user_distance: prediction.user_distance(),
         })
     }
@@ -7,6 +7,10 @@
     doc = "which will eventually be uploaded by [`crate::agents::image_uploader`]."
 )]
 
+pub mod compaction;
+pub mod metadata;
+pub mod retention;
+
 #[cfg(all(
     not(feature = "no-image-encryption"),
     any(feature = "internal-data-acquisition", test)
@@ -30,6 +34,7 @@ use crate::{
 use agentwire::port::{self, Port};
 use eyre::{bail, Error, Result, WrapErr};
 use futures::{channel::oneshot, prelude::*};
+use metadata::{CaptureContext, ImageMetadata};
 use orb_wld_data_id::{ImageId, SignupId};
 use ordered_float::OrderedFloat;
 use png::EncodingError;
@@ -50,7 +55,10 @@ use walkdir::WalkDir;
 
 type SharpnessHeaps = HashMap<
     (IrLed, bool),
-    BTreeMap<OrderedFloat<f64>, (Option<python::ir_net::EstimateOutput>, camera::ir::Frame)>,
+    BTreeMap<
+        OrderedFloat<f64>,
+        (Option<python::ir_net::EstimateOutput>, camera::ir::Frame, CaptureContext),
+    >,
 >;
 
 #[cfg(not(feature = "internal-data-acquisition"))]
@@ -59,8 +67,8 @@ use std::fs::remove_dir_all;
 #[cfg(feature = "internal-data-acquisition")]
 use crate::{
     consts::{
-        IRIS_SCORE_MIN, IR_EYE_SAVE_FPS, IR_FACE_SAVE_FPS, NUM_SHARP_IR_FRAMES, RGB_SAVE_FPS,
-        THERMAL_SAVE_FPS,
+        DEPTH_SAVE_FPS, IRIS_SCORE_MIN, IR_EYE_SAVE_FPS, IR_FACE_SAVE_FPS, NUM_SHARP_IR_FRAMES,
+        RGB_SAVE_FPS, THERMAL_SAVE_FPS,
     },
     utils::sample_at_fps,
 };
@@ -74,6 +82,7 @@ pub struct Agent {
     last_ir_face_save_time: Duration,
     last_rgb_save_time: Duration,
     last_thermal_save_time: Duration,
+    last_depth_save_time: Duration,
     sharpest_frames: SharpnessHeaps,
     log: Log,
 }
@@ -92,6 +101,8 @@ pub enum Input {
     SaveRgbNetEstimate(SaveRgbNetEstimateInput),
     SaveFusionRnFi(SaveFusionRnFiInput),
     SaveThermalData(SaveThermalDataInput),
+    /// Persist the depth frame as a point cloud.
+    SaveDepthData(SaveDepthDataInput),
     /// Get the sharpest frame since the initialization
     GetSharpestFrame(GetSharpestFrameInput),
     /// Write the sharpest frames seen since initialization to disk
@@ -116,6 +127,8 @@ pub struct SaveIrNetEstimateInput {
     pub frame: camera::ir::Frame,
     pub wavelength: IrLed,
     pub target_left_eye: bool,
+    /// Capture conditions embedded in the saved image.
+    pub capture: CaptureContext,
     /// If not `None`, overrides the target FPS for saving.
     pub fps_override: Option<f32>,
     pub log_metadata_always: bool,
@@ -126,6 +139,8 @@ pub struct SaveIrNetEstimateInput {
 pub struct SaveIrFaceDataInput {
     pub frame: camera::ir::Frame,
     pub wavelength: IrLed,
+    /// Capture conditions embedded in the saved image.
+    pub capture: CaptureContext,
     /// If not `None`, overrides the target FPS for saving.
     pub fps_override: Option<f32>,
     pub log_metadata_always: bool,
@@ -159,6 +174,12 @@ pub struct SaveThermalDataInput {
     pub log_metadata_always: bool,
 }
 
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct SaveDepthDataInput {
+    pub frame: camera::depth::Frame,
+}
+
 #[allow(missing_docs)]
 #[derive(Debug)]
 pub struct GetSharpestFrameInput {
@@ -317,6 +338,9 @@ impl agentwire::agent::Thread for Agent {
                     Input::SaveThermalData(input) => {
                         self.handle_save_thermal_data(input, &mut port)?;
                     }
+                    Input::SaveDepthData(input) => {
+                        self.handle_save_depth_data(input)?;
+                    }
                     Input::GetSharpestFrame(input) => {
                         self.handle_get_sharpest_frame(input);
                     }
@@ -347,6 +371,7 @@ impl Agent {
         self.last_ir_face_save_time = Duration::ZERO;
         self.last_rgb_save_time = Duration::ZERO;
         self.last_thermal_save_time = Duration::ZERO;
+        self.last_depth_save_time = Duration::ZERO;
         self.log = Log::default();
         self.sharpest_frames = SharpnessHeaps::default();
     }
@@ -412,6 +437,7 @@ impl Agent {
             frame,
             wavelength,
             target_left_eye,
+            capture,
             fps_override,
             log_metadata_always,
         } = input;
@@ -451,9 +477,17 @@ impl Agent {
                 }
                 return Ok(());
             }
+            let metadata = ImageMetadata::new(&self.signup_id, &image_id, frame.timestamp())
+                .wavelength(Some(wavelength))
+                .capture(capture);
             ssd_save_png(|| {
-                let frame_path =
-                    save_frame_with_id(&image_id, &frame, &self.save_dir.join("ir_camera"), None)?;
+                let frame_path = save_frame_with_id(
+                    &image_id,
+                    &frame,
+                    &self.save_dir.join("ir_camera"),
+                    None,
+                    &metadata,
+                )?;
                 self.last_ir_save_time = frame.timestamp();
                 log_metadata(image_id, true);
                 #[cfg(not(test))]
@@ -476,7 +510,7 @@ impl Agent {
                 map.pop_first();
             }
             let score = estimate.as_ref().map_or(0.0, |e| e.score);
-            map.insert(OrderedFloat(score), (estimate, frame.clone()));
+            map.insert(OrderedFloat(score), (estimate, frame.clone(), capture));
             Ok(())
         }
     }
@@ -487,7 +521,8 @@ impl Agent {
         input: SaveIrFaceDataInput,
         port: &mut port::Inner<Self>,
     ) -> Result<()> {
-        let SaveIrFaceDataInput { frame, wavelength, fps_override, log_metadata_always } = input;
+        let SaveIrFaceDataInput { frame, wavelength, capture, fps_override, log_metadata_always } =
+            input;
         let image_id = frame.image_id(&self.signup_id);
         // helper closure
         let mut log_metadata = |image_id, saved| {
@@ -516,9 +551,17 @@ impl Agent {
                 }
                 return Ok(());
             }
+            let metadata = ImageMetadata::new(&self.signup_id, &image_id, frame.timestamp())
+                .wavelength(Some(wavelength))
+                .capture(capture);
             ssd_save_png(|| {
-                let frame_path =
-                    save_frame_with_id(&image_id, &frame, &self.save_dir.join("ir_face"), None)?;
+                let frame_path = save_frame_with_id(
+                    &image_id,
+                    &frame,
+                    &self.save_dir.join("ir_face"),
+                    None,
+                    &metadata,
+                )?;
                 self.last_ir_face_save_time = frame.timestamp();
                 log_metadata(image_id, true);
                 #[cfg(not(test))]
@@ -566,6 +609,7 @@ impl Agent {
                 }
                 return Ok(());
             }
+            let metadata = ImageMetadata::new(&self.signup_id, &image_id, frame.timestamp());
             ssd_save_png(|| {
                 self.last_rgb_save_time = frame.timestamp();
                 save_frame_with_id(
@@ -573,6 +617,7 @@ impl Agent {
                     &frame,
                     &self.save_dir.join("rgb_camera"),
                     resolution_override.or(Some(FrameResolution::LOW)),
+                    &metadata,
                 )?;
                 log_metadata(image_id, true);
                 Ok(())
@@ -621,6 +666,7 @@ impl Agent {
                 }
                 return Ok(());
             }
+            let metadata = ImageMetadata::new(&self.signup_id, &image_id, frame.timestamp());
             ssd_save_png(|| {
                 self.last_rgb_save_time = frame.timestamp();
                 save_frame_with_id(
@@ -628,6 +674,7 @@ impl Agent {
                     &frame,
                     &self.save_dir.join("rgb_camera"),
                     Some(FrameResolution::LOW),
+                    &metadata,
                 )?;
                 log_metadata(image_id, true);
                 Ok(())
@@ -671,9 +718,16 @@ impl Agent {
                 }
                 return Ok(());
             }
+            let metadata = ImageMetadata::new(&self.signup_id, &image_id, frame.timestamp())
+                .wavelength(Some(wavelength));
             ssd_save_png(|| {
-                let frame_path =
-                    save_frame_with_id(&image_id, &frame, &self.save_dir.join("thermal"), None)?;
+                let frame_path = save_frame_with_id(
+                    &image_id,
+                    &frame,
+                    &self.save_dir.join("thermal"),
+                    None,
+                    &metadata,
+                )?;
                 self.last_thermal_save_time = frame.timestamp();
                 log_metadata(image_id, true);
                 #[cfg(not(test))]
@@ -692,12 +746,35 @@ impl Agent {
         }
     }
 
+    #[allow(unused_variables, clippy::unnecessary_wraps)]
+    fn handle_save_depth_data(&mut self, input: SaveDepthDataInput) -> Result<()> {
+        #[cfg(feature = "internal-data-acquisition")]
+        {
+            let SaveDepthDataInput { frame } = input;
+            if frame.is_empty()
+                || !sample_at_fps(DEPTH_SAVE_FPS, frame.timestamp(), self.last_depth_save_time)
+            {
+                return Ok(());
+            }
+            let image_id = frame.image_id(&self.signup_id);
+            ssd_save_png(|| {
+                let file_path =
+                    self.save_dir.join("depth").join(image_id.to_string()).with_extension("pcd");
+                tracing::trace!("Writing point cloud to {file_path:?}");
+                save_point_cloud(&frame, &file_path)?;
+                self.last_depth_save_time = frame.timestamp();
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
     fn handle_get_sharpest_frame(&mut self, input: GetSharpestFrameInput) {
         let GetSharpestFrameInput { wavelength, side, tx } = input;
         let _ = tx.send(self.sharpest_frames.get_mut(&(wavelength, side)).and_then(|heap| {
             heap.keys()
                 .next_back()
-                .and_then(|last| heap.get(last).and_then(|(estimate, _)| estimate.clone()))
+                .and_then(|last| heap.get(last).and_then(|(estimate, _, _)| estimate.clone()))
         }));
     }
 }
@@ -710,7 +787,7 @@ fn save_sharpest_frames(
     log: &mut Log,
 ) -> Result<(), EncodingError> {
     for ((wavelength, side), sharpness_heap) in sharpest_frames {
-        for (score, (ir_net_estimate, frame)) in sharpness_heap {
+        for (score, (ir_net_estimate, frame, capture)) in sharpness_heap {
             tracing::debug!(
                 "Saving {} sharpest frames for wavelength {:?}, side {:?}, with score {:?}",
                 sharpness_heap.len(),
@@ -719,7 +796,10 @@ fn save_sharpest_frames(
                 score
             );
             let image_id = frame.image_id(signup_id);
-            save_frame_with_id(&image_id, frame, &save_dir.join("ir_camera"), None)?;
+            let metadata = ImageMetadata::new(signup_id, &image_id, frame.timestamp())
+                .wavelength(Some(*wavelength))
+                .capture(*capture);
+            save_frame_with_id(&image_id, frame, &save_dir.join("ir_camera"), None, &metadata)?;
             log.ir_net_metadata.push((
                 image_id,
                 ir_net_estimate.clone().map(Into::into),
@@ -741,12 +821,16 @@ fn save_identification_images_impl(
     right: &EyeCapture,
     self_custody_candidate: &SelfCustodyCandidate,
 ) -> Result<IdentificationImages, EncodingError> {
+    let metadata = |image_id: &ImageId, timestamp: Duration, wavelength: Option<IrLed>| {
+        ImageMetadata::new(signup_id, image_id, timestamp).wavelength(wavelength)
+    };
     let left_ir = left.ir_frame.image_id(signup_id);
     save_frame_with_id(
         &left_ir,
         &left.ir_frame,
         &save_dir.join("identification").join("ir").join("left"),
         None,
+        &metadata(&left_ir, left.ir_frame.timestamp(), None),
     )?;
     let left_ir_940nm = left
         .ir_frame_940nm
@@ -758,6 +842,7 @@ fn save_identification_images_impl(
                 frame,
                 &save_dir.join("identification").join("ir").join("left_940nm"),
                 None,
+                &metadata(&image_id, frame.timestamp(), Some(IrLed::L940)),
             )
             .map(|_| image_id)
         })
@@ -772,6 +857,7 @@ fn save_identification_images_impl(
                 frame,
                 &save_dir.join("identification").join("ir").join("left_740nm"),
                 None,
+                &metadata(&image_id, frame.timestamp(), Some(IrLed::L740)),
             )
             .map(|_| image_id)
         })
@@ -782,6 +868,7 @@ fn save_identification_images_impl(
         &right.ir_frame,
         &save_dir.join("identification").join("ir").join("right"),
         None,
+        &metadata(&right_ir, right.ir_frame.timestamp(), None),
     )?;
     let right_ir_940nm = right
         .ir_frame_940nm
@@ -793,6 +880,7 @@ fn save_identification_images_impl(
                 frame,
                 &save_dir.join("identification").join("ir").join("right_940nm"),
                 None,
+                &metadata(&image_id, frame.timestamp(), Some(IrLed::L940)),
             )
             .map(|_| image_id)
         })
@@ -807,6 +895,7 @@ fn save_identification_images_impl(
                 frame,
                 &save_dir.join("identification").join("ir").join("right_740nm"),
                 None,
+                &metadata(&image_id, frame.timestamp(), Some(IrLed::L740)),
             )
             .map(|_| image_id)
         })
@@ -817,6 +906,7 @@ fn save_identification_images_impl(
         &left.rgb_frame,
         &save_dir.join("identification").join("rgb").join("left"),
         Some(FrameResolution::MEDIUM),
+        &metadata(&left_rgb, left.rgb_frame.timestamp(), None),
     )?;
     let left_rgb_fullres = left.rgb_frame.image_id(signup_id);
     save_frame_with_id(
@@ -824,6 +914,7 @@ fn save_identification_images_impl(
         &left.rgb_frame,
         &save_dir.join("rgb_camera"),
         Some(FrameResolution::MAX),
+        &metadata(&left_rgb_fullres, left.rgb_frame.timestamp(), None),
     )?;
     let right_rgb = right.rgb_frame.image_id(signup_id);
     save_frame_with_id(
@@ -831,6 +922,7 @@ fn save_identification_images_impl(
         &right.rgb_frame,
         &save_dir.join("identification").join("rgb").join("right"),
         Some(FrameResolution::LOW),
+        &metadata(&right_rgb, right.rgb_frame.timestamp(), None),
     )?;
     let right_rgb_fullres = right.rgb_frame.image_id(signup_id);
     save_frame_with_id(
@@ -838,6 +930,7 @@ fn save_identification_images_impl(
         &right.rgb_frame,
         &save_dir.join("rgb_camera"),
         Some(FrameResolution::MAX),
+        &metadata(&right_rgb_fullres, right.rgb_frame.timestamp(), None),
     )?;
     let self_custody_candidate_id = self_custody_candidate.rgb_frame.image_id(signup_id);
     save_frame_with_id(
@@ -845,6 +938,7 @@ fn save_identification_images_impl(
         &self_custody_candidate.rgb_frame,
         &save_dir.join("identification").join("rgb").join("self_custody_candidate"),
         Some(FrameResolution::MAX),
+        &metadata(&self_custody_candidate_id, self_custody_candidate.rgb_frame.timestamp(), None),
     )?;
     Ok(IdentificationImages {
         left_ir,
@@ -867,12 +961,13 @@ fn save_frame_with_id(
     #[allow(unused_variables)] frame: &impl Frame,
     save_dir: &Path,
     #[allow(unused_variables)] resolution: Option<FrameResolution>,
+    #[allow(unused_variables)] metadata: &ImageMetadata,
 ) -> Result<PathBuf, EncodingError> {
     let frame_path = save_dir.join(image_id.to_string()).with_extension("png");
     #[cfg(any(feature = "internal-data-acquisition", test))]
     {
         tracing::trace!("Writing frame to {frame_path:?}");
-        save_frame(frame, &frame_path, resolution)?;
+        save_frame(frame, &frame_path, resolution, metadata)?;
     }
     #[cfg(not(any(feature = "internal-data-acquisition", test)))]
     {
@@ -886,26 +981,50 @@ fn save_frame(
     frame: &impl Frame,
     file_path: &Path,
     resolution: Option<FrameResolution>,
+    metadata: &ImageMetadata,
 ) -> Result<(), EncodingError> {
+    use crate::image::phash::PerceptualHash;
+    use std::io::Write;
     if let Some(parent) = file_path.parent() {
         std::fs::create_dir_all(parent)?;
     }
     let mut file = std::fs::File::create(file_path)?;
+    let mut png_buf = Vec::new();
+    frame.write_png(&mut png_buf, resolution.unwrap_or_default())?;
+    let metadata = metadata.clone().perceptual_hash(PerceptualHash::from_frame(frame));
+    metadata::embed(&mut png_buf, &metadata)?;
     #[cfg(feature = "no-image-encryption")]
     {
-        frame.write_png(&mut file, resolution.unwrap_or_default())?;
+        file.write_all(&png_buf)?;
     }
     #[cfg(not(feature = "no-image-encryption"))]
     {
-        use std::io::Write;
-        let mut png_buf = std::io::Cursor::new(Vec::new());
-        frame.write_png(&mut png_buf, resolution.unwrap_or_default())?;
-        let encrypted_frame = encrypt_and_seal(&png_buf.into_inner());
+        let encrypted_frame = encrypt_and_seal(&png_buf);
         file.write_all(&encrypted_frame)?;
     }
     Ok(())
 }
 
+#[cfg(feature = "internal-data-acquisition")]
+fn save_point_cloud(frame: &camera::depth::Frame, file_path: &Path) -> Result<(), EncodingError> {
+    use std::io::Write;
+    if let Some(parent) = file_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::File::create(file_path)?;
+    let mut pcd_buf = Vec::new();
+    frame.point_cloud().write_pcd(&mut pcd_buf)?;
+    #[cfg(feature = "no-image-encryption")]
+    {
+        file.write_all(&pcd_buf)?;
+    }
+    #[cfg(not(feature = "no-image-encryption"))]
+    {
+        file.write_all(&encrypt_and_seal(&pcd_buf))?;
+    }
+    Ok(())
+}
+
 fn ssd_save_png<R, F: FnOnce() -> Result<R, EncodingError>>(
     f: F,
 ) -> Result<Option<R>, EncodingError> {
@@ -1043,6 +1162,7 @@ mod tests {
                 frame: expected_frame.clone(),
                 wavelength: expected_wavelength,
                 target_left_eye: false,
+                capture: CaptureContext::default(),
                 fps_override: Some(f32::INFINITY),
                 log_metadata_always: true,
             })))
@@ -1059,7 +1179,18 @@ mod tests {
                 .wrap_err("Failed to read file the image notary should have produced")?;
             #[cfg(not(feature = "no-image-encryption"))]
             let saved_bytes = decrypt_and_unseal(&saved_bytes);
-            ensure!(expected_bytes == saved_bytes, "saved bytes didn't match");
+            let (png_head, png_end) = expected_bytes.split_at(expected_bytes.len() - 12);
+            ensure!(
+                saved_bytes.starts_with(png_head) && saved_bytes.ends_with(png_end),
+                "saved bytes didn't match"
+            );
+            let Some(saved_metadata) = metadata::read(&saved_bytes)? else {
+                bail!("metadata was not embedded");
+            };
+            ensure!(
+                saved_metadata.wavelength == Some(expected_wavelength),
+                "wavelength was not embedded correctly"
+            );
 
             // Check that saved frame properties make sense.
             let saved_frame = camera::ir::Frame::read_png(saved_bytes.as_slice())
@@ -1111,6 +1242,7 @@ mod tests {
             .send(port::Input::new(Input::SaveIrFaceData(SaveIrFaceDataInput {
                 frame: expected_frame.clone(),
                 wavelength: expected_wavelength,
+                capture: CaptureContext::default(),
                 fps_override: Some(f32::INFINITY),
                 log_metadata_always: true,
             })))
@@ -1128,7 +1260,18 @@ mod tests {
                 .wrap_err("Failed to read file the image notary should have produced")?;
             #[cfg(not(feature = "no-image-encryption"))]
             let saved_bytes = decrypt_and_unseal(&saved_bytes);
-            ensure!(expected_bytes == saved_bytes, "saved bytes didn't match");
+            let (png_head, png_end) = expected_bytes.split_at(expected_bytes.len() - 12);
+            ensure!(
+                saved_bytes.starts_with(png_head) && saved_bytes.ends_with(png_end),
+                "saved bytes didn't match"
+            );
+            let Some(saved_metadata) = metadata::read(&saved_bytes)? else {
+                bail!("metadata was not embedded");
+            };
+            ensure!(
+                saved_metadata.wavelength == Some(expected_wavelength),
+                "wavelength was not embedded correctly"
+            );
 
             // Check that saved frame properties make sense.
             let saved_frame = camera::ir::Frame::read_png(saved_bytes.as_slice())
@@ -1193,7 +1336,18 @@ mod tests {
                 .wrap_err("Failed to read file the image notary should have produced")?;
             #[cfg(not(feature = "no-image-encryption"))]
             let saved_bytes = decrypt_and_unseal(&saved_bytes);
-            ensure!(expected_bytes == saved_bytes, "saved bytes didn't match");
+            let (png_head, png_end) = expected_bytes.split_at(expected_bytes.len() - 12);
+            ensure!(
+                saved_bytes.starts_with(png_head) && saved_bytes.ends_with(png_end),
+                "saved bytes didn't match"
+            );
+            let Some(saved_metadata) = metadata::read(&saved_bytes)? else {
+                bail!("metadata was not embedded");
+            };
+            ensure!(
+                saved_metadata.wavelength == Some(expected_wavelength),
+                "wavelength was not embedded correctly"
+            );
 
             // Check that saved frame properties make sense.
             let saved_frame = camera::thermal::Frame::read_png(saved_bytes.as_slice())
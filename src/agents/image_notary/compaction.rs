@@ -0,0 +1,421 @@
+//! Background compaction of cold data-acquisition images.
+//!
+//! The cameras save data-acquisition images with the fastest PNG compression
+//! to keep up with the frame rate. Once an image is older than
+//! `data_acquisition_compaction_age`, the compactor re-encodes it with the best
+//! zlib compression and the Paeth filter, which typically reclaims around 30%
+//! of the SSD space. The SHA-256 checksum of the decoded pixels is verified
+//! before the original file is replaced, so the compaction is lossless.
+//! The ancillary chunks, like the [metadata](super::metadata) text chunks, are
+//! copied over. Compacted images carry a private `orCp` chunk and are not
+//! processed again.
+//! Encrypted images can't be recompressed and are skipped.
+//!
+//! The compactor runs from the [image uploader](crate::agents::image_uploader),
+//! so it is paused during signups. It also waits while the CPU load is above
+//! `data_acquisition_compaction_max_cpu_load`, and while the orb is
+//! [pre-cooling](crate::monitor::thermal).
+
+use super::{
+    metadata,
+    retention::{self, ImageClass},
+};
+use crate::{
+    config::Config, consts::IMAGE_COMPACTION_THROTTLE_DELAY, dd_gauge, dd_incr, monitor, ssd,
+};
+use eyre::{bail, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Private ancillary chunk which marks a compacted image.
+const COMPACTED_CHUNK: [u8; 4] = *b"orCp";
+
+/// Number of bytes read from the start of a file to check whether it is
+/// already compacted.
+const HEADER_PEEK_SIZE: usize = 256;
+
+/// Compaction limits.
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    /// Only images older than this are compacted.
+    pub min_age: Duration,
+    /// The compactor waits while the CPU load is above this fraction.
+    pub max_cpu_load: f64,
+}
+
+/// Background compactor of the data-acquisition images.
+pub struct Compactor {
+    policy: Policy,
+    cpu_monitor: Box<dyn monitor::cpu::Monitor>,
+    canceled: Arc<AtomicBool>,
+}
+
+/// Results of a compaction run.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Stats {
+    /// Number of compacted images.
+    pub compacted: u64,
+    /// Total size of the compacted images before the compaction in bytes.
+    pub original_size: u64,
+    /// Total size of the compacted images after the compaction in bytes.
+    pub compacted_size: u64,
+    /// Number of images which failed the compaction.
+    pub failed: u64,
+}
+
+impl Policy {
+    /// Creates the compaction policy from the orb configuration.
+    #[must_use]
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            min_age: config.data_acquisition_compaction_age,
+            max_cpu_load: config.data_acquisition_compaction_max_cpu_load,
+        }
+    }
+}
+
+impl Compactor {
+    /// Creates a new compactor.
+    #[must_use]
+    pub fn new(policy: Policy, cpu_monitor: Box<dyn monitor::cpu::Monitor>) -> Self {
+        Self { policy, cpu_monitor, canceled: Arc::new(AtomicBool::new(false)) }
+    }
+
+    /// Returns a flag which stops the compaction after the current image once
+    /// set.
+    #[must_use]
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.canceled)
+    }
+
+    /// Compacts the cold data-acquisition images under `base_dir`.
+    pub fn run(&mut self, base_dir: &Path) -> Result<Stats> {
+        let mut stats = Stats::default();
+        let Some(class_dirs) = ssd::perform(|| collect_class_dirs(base_dir)) else {
+            return Ok(stats);
+        };
+        let now = SystemTime::now();
+        for class_dir in class_dirs {
+            let Some(images) =
+                ssd::perform(|| collect_images(&class_dir, now, self.policy.min_age))
+            else {
+                continue;
+            };
+            if images.is_empty() {
+                continue;
+            }
+            // Renaming the compacted images touches the directory, which would
+            // reset its age for the retention policies.
+            let Some(dir_modified) = ssd::perform(|| fs::metadata(&class_dir)?.modified()) else {
+                continue;
+            };
+            for image in images {
+                if !self.throttle()? {
+                    restore_modified(&class_dir, dir_modified);
+                    return Ok(finish(stats));
+                }
+                // An upload could have started since the images were collected.
+                if retention::is_upload_in_progress(&image) {
+                    break;
+                }
+                match compact_file(&image) {
+                    Ok(Some((original_size, compacted_size))) => {
+                        stats.compacted += 1;
+                        stats.original_size += original_size;
+                        stats.compacted_size += compacted_size;
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        tracing::warn!("Image compaction of {} failed: {err:?}", image.display());
+                        dd_incr!("main.count.data_acquisition.compaction.failed");
+                        stats.failed += 1;
+                    }
+                }
+            }
+            restore_modified(&class_dir, dir_modified);
+        }
+        Ok(finish(stats))
+    }
+
+    /// Waits while the CPU load is too high. Returns `false` if the compaction
+    /// was canceled.
+    fn throttle(&mut self) -> Result<bool> {
+        loop {
+            if self.canceled.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
+            if monitor::thermal::is_pre_cooling() {
+                thread::sleep(IMAGE_COMPACTION_THROTTLE_DELAY);
+                continue;
+            }
+            match self.cpu_monitor.last_report()? {
+                Some(report) if report.cpu_load > self.policy.max_cpu_load => {
+                    thread::sleep(IMAGE_COMPACTION_THROTTLE_DELAY);
+                }
+                _ => return Ok(true),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Compactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Compactor").field("policy", &self.policy).finish_non_exhaustive()
+    }
+}
+
+/// Re-encodes a PNG image with the best compression and verifies that the
+/// pixels are unchanged. The ancillary chunks keep their position relative to
+/// the image data. Returns `None` if `data` is not a PNG image or is already
+/// compacted.
+pub fn recompress(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    if !data.starts_with(&PNG_SIGNATURE) || is_compacted(data) {
+        return Ok(None);
+    }
+    let (info, pixels) = decode(data)?;
+    if info.color_type == png::ColorType::Indexed {
+        return Ok(None);
+    }
+    let checksum = Sha256::digest(&pixels);
+    let mut before_data = Vec::new();
+    let mut after_data = Vec::new();
+    for (kind, chunk) in metadata::chunks(data) {
+        if kind == *b"IDAT" {
+            before_data.append(&mut after_data);
+        } else if is_ancillary(kind) {
+            after_data.push((kind, chunk));
+        }
+    }
+    let mut output = Vec::with_capacity(data.len());
+    let mut encoder = png::Encoder::new(&mut output, info.width, info.height);
+    encoder.set_color(info.color_type);
+    encoder.set_depth(info.bit_depth);
+    encoder.set_compression(png::Compression::Best);
+    encoder.set_filter(png::FilterType::Paeth);
+    let mut writer = encoder.write_header()?;
+    writer.write_chunk(COMPACTED_CHUNK, &[])?;
+    for &(kind, chunk) in &before_data {
+        writer.write_chunk(kind, chunk)?;
+    }
+    writer.write_image_data(&pixels)?;
+    for &(kind, chunk) in &after_data {
+        writer.write_chunk(kind, chunk)?;
+    }
+    drop(writer);
+    let (_, recompressed_pixels) = decode(&output)?;
+    if Sha256::digest(&recompressed_pixels) != checksum {
+        bail!("checksum mismatch after recompression");
+    }
+    Ok(Some(output))
+}
+
+/// Returns `true` if the chunk is ancillary, i.e. its name starts with a
+/// lowercase letter.
+fn is_ancillary(kind: [u8; 4]) -> bool {
+    kind[0].is_ascii_lowercase()
+}
+
+/// Returns `true` if the PNG image has the compaction marker chunk before the
+/// image data.
+fn is_compacted(data: &[u8]) -> bool {
+    let mut chunks = &data[PNG_SIGNATURE.len().min(data.len())..];
+    while chunks.len() >= 8 {
+        let length = u32::from_be_bytes([chunks[0], chunks[1], chunks[2], chunks[3]]) as usize;
+        let name = &chunks[4..8];
+        if name == COMPACTED_CHUNK {
+            return true;
+        }
+        if name == b"IDAT" {
+            return false;
+        }
+        // Chunk length, name, data, and CRC.
+        let Some(rest) = chunks.get(length.saturating_add(12)..) else {
+            return false;
+        };
+        chunks = rest;
+    }
+    false
+}
+
+fn decode(data: &[u8]) -> Result<(png::OutputInfo, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels)?;
+    Ok((info, pixels))
+}
+
+/// Compacts a single image file. Returns the sizes before and after the
+/// compaction, or `None` if the image was skipped.
+fn compact_file(path: &Path) -> Result<Option<(u64, u64)>> {
+    let Some(header) = ssd::perform(|| read_header(path)) else {
+        return Ok(None);
+    };
+    if !header.starts_with(&PNG_SIGNATURE) || is_compacted(&header) {
+        return Ok(None);
+    }
+    let Some(data) = ssd::perform(|| fs::read(path)) else {
+        return Ok(None);
+    };
+    let Some(compacted) = recompress(&data)? else {
+        return Ok(None);
+    };
+    if compacted.len() >= data.len() {
+        return Ok(None);
+    }
+    let tmp_path = path.with_extension("compacting");
+    let written = ssd::perform(|| {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&compacted)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    });
+    if written.is_none() {
+        bail!("failed to replace the image");
+    }
+    Ok(Some((data.len() as u64, compacted.len() as u64)))
+}
+
+fn read_header(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(HEADER_PEEK_SIZE);
+    File::open(path)?.take(HEADER_PEEK_SIZE as u64).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+fn restore_modified(dir: &Path, modified: SystemTime) {
+    ssd::perform(|| File::open(dir)?.set_modified(modified));
+}
+
+fn collect_class_dirs(base_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut class_dirs = Vec::new();
+    if !base_dir.exists() {
+        return Ok(class_dirs);
+    }
+    for signup_dir in fs::read_dir(base_dir)? {
+        let signup_dir = signup_dir?;
+        if !signup_dir.file_type()?.is_dir() || retention::is_upload_in_progress(&signup_dir.path())
+        {
+            continue;
+        }
+        for class_dir in fs::read_dir(signup_dir.path())? {
+            let class_dir = class_dir?;
+            if class_dir.file_type()?.is_dir()
+                && ImageClass::from_dir_name(&class_dir.file_name().to_string_lossy())
+                    == ImageClass::DataAcquisition
+            {
+                class_dirs.push(class_dir.path());
+            }
+        }
+    }
+    Ok(class_dirs)
+}
+
+fn collect_images(
+    class_dir: &Path,
+    now: SystemTime,
+    min_age: Duration,
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    for entry in fs::read_dir(class_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().map_or(true, |extension| extension != "png") {
+            continue;
+        }
+        let age = now.duration_since(entry.metadata()?.modified()?).unwrap_or_default();
+        if age > min_age {
+            images.push(path);
+        }
+    }
+    Ok(images)
+}
+
+fn finish(stats: Stats) -> Stats {
+    if stats.compacted > 0 {
+        tracing::info!(
+            "Image compaction: compacted {} images from {} to {} bytes",
+            stats.compacted,
+            stats.original_size,
+            stats.compacted_size
+        );
+        dd_gauge!(
+            "main.gauge.data_acquisition.compaction.reclaimed_bytes",
+            stats.original_size.saturating_sub(stats.compacted_size).to_string()
+        );
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn fast_png(width: u32, height: u32) -> Vec<u8> {
+        let pixels = (0..width * height).map(|i| (i % 251 / 4) as u8).collect::<Vec<_>>();
+        let mut output = Vec::new();
+        let mut encoder = png::Encoder::new(&mut output, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(png::Compression::Fast);
+        encoder.write_header().unwrap().write_image_data(&pixels).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_recompress() {
+        let original = fast_png(128, 64);
+        let compacted = recompress(&original).unwrap().unwrap();
+        assert!(is_compacted(&compacted));
+        assert!(!is_compacted(&original));
+        assert_eq!(decode(&original).unwrap().1, decode(&compacted).unwrap().1);
+        assert!(recompress(&compacted).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_recompress_keeps_ancillary_chunks() {
+        let pixels = vec![7; 32 * 16];
+        let mut original = Vec::new();
+        let mut encoder = png::Encoder::new(&mut original, 32, 16);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_chunk(*b"pHYs", &[0, 0, 0, 1, 0, 0, 0, 1, 0]).unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        writer.write_chunk(*b"iTXt", b"OrbMetadata\0\0\0\0\0{}").unwrap();
+        writer.write_chunk(*b"tEXt", b"Creation Time\0now").unwrap();
+        drop(writer);
+        let compacted = recompress(&original).unwrap().unwrap();
+        let kinds = metadata::chunks(&compacted).map(|(kind, _)| kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [
+            *b"IHDR",
+            COMPACTED_CHUNK,
+            *b"pHYs",
+            *b"IDAT",
+            *b"iTXt",
+            *b"tEXt",
+            *b"IEND"
+        ]);
+        let text = metadata::chunks(&compacted).find(|(kind, _)| kind == b"tEXt").unwrap().1;
+        assert_eq!(text, b"Creation Time\0now");
+    }
+
+    #[test]
+    fn test_skip_non_png() {
+        assert!(recompress(b"encrypted image data").unwrap().is_none());
+    }
+}
@@ -0,0 +1,236 @@
+//! Structured metadata embedded in the saved images.
+//!
+//! Every image written by the notary carries its capture context, so a single
+//! file can be interpreted without the signup JSON. The metadata is stored in
+//! standard PNG text chunks inserted right before `IEND`:
+//!
+//! - an `iTXt` chunk with the [`METADATA_KEYWORD`] keyword holding the
+//!   [`ImageMetadata`] as JSON;
+//! - a `tEXt` chunk with the registered `Creation Time` keyword.
+//!
+//! The metadata includes the [perceptual hash](crate::image::phash) of the
+//! frame, which lets the backend deduplicate images across captures and detect
+//! images substituted after the capture.
+//!
+//! Decoders which are unaware of the chunks skip them, so the pixel data is
+//! unaffected. The [`read`] function is used by the replay harness to restore
+//! the context of the loaded images.
+
+use crate::{agents::mirror, image::phash::PerceptualHash, mcu::main::IrLed};
+use eyre::{bail, Result};
+use orb_wld_data_id::{ImageId, SignupId};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    time::{Duration, SystemTime},
+};
+use time::{format_description::well_known::Rfc2822, OffsetDateTime};
+
+/// Keyword of the `iTXt` chunk with the JSON metadata.
+pub const METADATA_KEYWORD: &str = "OrbMetadata";
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+const CREATION_TIME_KEYWORD: &str = "Creation Time";
+
+/// Capture conditions at the moment the frame was taken.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+pub struct CaptureContext {
+    /// IR LED on-duration in microseconds, which bounds the exposure.
+    pub exposure: Option<u16>,
+    /// Mirror angles, including the eye PID controller offset.
+    pub mirror: Option<mirror::Point>,
+}
+
+/// Metadata of a single saved image.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ImageMetadata {
+    /// Signup the image belongs to.
+    pub signup_id: String,
+    /// Image ID assigned by the notary.
+    pub image_id: String,
+    /// Active IR LED wavelength, if applicable.
+    pub wavelength: Option<IrLed>,
+    /// Capture conditions.
+    #[serde(flatten)]
+    pub capture: CaptureContext,
+    /// Frame timestamp from the camera.
+    pub frame_timestamp: Duration,
+    /// Wall-clock time of saving, in milliseconds since the Unix epoch.
+    pub saved_at: u64,
+    /// Perceptual hash of the frame.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub perceptual_hash: Option<PerceptualHash>,
+}
+
+impl ImageMetadata {
+    /// Creates a new metadata with the current time of saving.
+    #[must_use]
+    pub fn new(signup_id: &SignupId, image_id: &ImageId, frame_timestamp: Duration) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        let saved_at = SystemTime::UNIX_EPOCH.elapsed().unwrap_or_default().as_millis() as u64;
+        Self {
+            signup_id: signup_id.to_string(),
+            image_id: image_id.to_string(),
+            wavelength: None,
+            capture: CaptureContext::default(),
+            frame_timestamp,
+            saved_at,
+            perceptual_hash: None,
+        }
+    }
+
+    /// Sets the IR LED wavelength.
+    #[must_use]
+    pub fn wavelength(mut self, wavelength: Option<IrLed>) -> Self {
+        self.wavelength = wavelength;
+        self
+    }
+
+    /// Sets the capture conditions.
+    #[must_use]
+    pub fn capture(mut self, capture: CaptureContext) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Sets the perceptual hash of the frame.
+    #[must_use]
+    pub fn perceptual_hash(mut self, perceptual_hash: PerceptualHash) -> Self {
+        self.perceptual_hash = Some(perceptual_hash);
+        self
+    }
+}
+
+/// Embeds the metadata into an encoded PNG image.
+pub fn embed(png: &mut Vec<u8>, metadata: &ImageMetadata) -> io::Result<()> {
+    let Some(iend) = find_chunk(png, *b"IEND") else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "PNG image without IEND chunk"));
+    };
+    let mut chunks = Vec::new();
+    let mut itxt = Vec::new();
+    // Keyword, compression flag and method, empty language tag and translated
+    // keyword, and the UTF-8 text.
+    itxt.extend_from_slice(METADATA_KEYWORD.as_bytes());
+    itxt.extend_from_slice(&[0, 0, 0, 0, 0]);
+    itxt.extend_from_slice(&serde_json::to_vec(metadata)?);
+    write_chunk(&mut chunks, *b"iTXt", &itxt);
+    let creation_time =
+        OffsetDateTime::from_unix_timestamp_nanos(i128::from(metadata.saved_at) * 1_000_000)
+            .ok()
+            .and_then(|time| time.format(&Rfc2822).ok());
+    if let Some(creation_time) = creation_time {
+        let mut text = Vec::new();
+        text.extend_from_slice(CREATION_TIME_KEYWORD.as_bytes());
+        text.push(0);
+        text.extend_from_slice(creation_time.as_bytes());
+        write_chunk(&mut chunks, *b"tEXt", &text);
+    }
+    png.splice(iend..iend, chunks);
+    Ok(())
+}
+
+/// Reads the metadata from an encoded PNG image. Returns `None` if the image
+/// has no metadata, e.g. it was saved before the metadata was introduced.
+pub fn read(png: &[u8]) -> Result<Option<ImageMetadata>> {
+    if !png.starts_with(&PNG_SIGNATURE) {
+        bail!("not a PNG image, maybe encrypted?");
+    }
+    for (kind, data) in chunks(png) {
+        if kind != *b"iTXt" {
+            continue;
+        }
+        let Some(text) = data.strip_prefix(METADATA_KEYWORD.as_bytes()) else {
+            continue;
+        };
+        let Some(header) = text.get(..5) else {
+            bail!("truncated {METADATA_KEYWORD} chunk");
+        };
+        if header != [0, 0, 0, 0, 0] {
+            bail!("unsupported {METADATA_KEYWORD} chunk header");
+        }
+        return Ok(Some(serde_json::from_slice(&text[5..])?));
+    }
+    Ok(None)
+}
+
+/// Iterates over the well-formed chunks of a PNG image.
+pub(super) fn chunks(png: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut offset = PNG_SIGNATURE.len();
+    std::iter::from_fn(move || {
+        let len = u32::from_be_bytes(png.get(offset..offset + 4)?.try_into().ok()?);
+        let len = usize::try_from(len).ok()?;
+        let kind: [u8; 4] = png.get(offset + 4..offset + 8)?.try_into().ok()?;
+        let data = png.get(offset + 8..offset + 8 + len)?;
+        offset += 12 + len;
+        Some((kind, data))
+    })
+}
+
+/// Returns the byte offset of the first chunk of `kind`.
+fn find_chunk(png: &[u8], kind: [u8; 4]) -> Option<usize> {
+    if !png.starts_with(&PNG_SIGNATURE) {
+        return None;
+    }
+    let mut offset = PNG_SIGNATURE.len();
+    for (chunk_kind, data) in chunks(png) {
+        if chunk_kind == kind {
+            return Some(offset);
+        }
+        offset += 12 + data.len();
+    }
+    None
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: [u8; 4], data: &[u8]) {
+    let len = u32::try_from(data.len()).expect("PNG chunk to fit in 4 GiB");
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(&kind);
+    out.extend_from_slice(data);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&kind);
+    hasher.update(data);
+    out.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::camera::{self, Frame, FrameResolution};
+    use orb_wld_data_id::S3Region;
+
+    #[test]
+    fn test_embed_and_read() {
+        let frame = camera::ir::Frame::new(vec![42; 16], Duration::from_millis(1500), 4, 4, 42);
+        let mut png = Vec::new();
+        frame.write_png(&mut png, FrameResolution::MAX).unwrap();
+        assert!(read(&png).unwrap().is_none());
+
+        let signup_id = SignupId::new(S3Region::Unknown);
+        let image_id = frame.image_id(&signup_id);
+        let metadata = ImageMetadata::new(&signup_id, &image_id, frame.timestamp())
+            .wavelength(Some(IrLed::L940))
+            .capture(CaptureContext {
+                exposure: Some(2500),
+                mirror: Some(mirror::Point { phi_degrees: 45.0, theta_degrees: 90.5 }),
+            })
+            .perceptual_hash(PerceptualHash::from_frame(&frame));
+        let original = png.clone();
+        embed(&mut png, &metadata).unwrap();
+        assert!(png.starts_with(&original[..original.len() - 12]));
+        assert!(png.ends_with(&original[original.len() - 12..]));
+
+        let restored = read(&png).unwrap().unwrap();
+        assert_eq!(restored.signup_id, signup_id.to_string());
+        assert_eq!(restored.image_id, image_id.to_string());
+        assert_eq!(restored.wavelength, Some(IrLed::L940));
+        assert_eq!(restored.capture.exposure, Some(2500));
+        assert!((restored.capture.mirror.unwrap().theta_degrees - 90.5).abs() < f64::EPSILON);
+        assert_eq!(restored.frame_timestamp, Duration::from_millis(1500));
+        assert_eq!(restored.saved_at, metadata.saved_at);
+        assert_eq!(restored.perceptual_hash, metadata.perceptual_hash);
+
+        let decoded = camera::ir::Frame::read_png(png.as_slice()).unwrap();
+        assert_eq!(*decoded, *frame);
+        assert!(read(&png[1..]).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+//! Retention policies for the images saved by the image notary.
+//!
+//! Every signup directory under [`DATA_ACQUISITION_BASE_DIR`] is split into
+//! image classes by its sub-directories. Each class has its own maximum age
+//! and maximum total size. The reaper deletes the class directories which
+//! exceed their policy, skipping signups which are currently being uploaded or
+//! queued for the upload, and records a signed deletion receipt in telemetry.
+//!
+//! [`DATA_ACQUISITION_BASE_DIR`]: crate::consts::DATA_ACQUISITION_BASE_DIR
+
+use crate::{config::Config, dd_gauge, dd_incr, secure_element, ssd};
+use data_encoding::BASE64;
+use eyre::Result;
+use fs_extra::dir;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+/// Number of live upload guards per signup directory.
+static UPLOADS_IN_PROGRESS: Lazy<Mutex<HashMap<PathBuf, usize>>> = Lazy::new(Mutex::default);
+
+/// Class of the saved images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageClass {
+    /// Images used for the identification of the user.
+    Identification,
+    /// Data-acquisition bursts from the cameras.
+    DataAcquisition,
+    /// Debug crops and everything else.
+    Debug,
+}
+
+/// Retention limits for a single image class.
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    /// Images older than this are deleted.
+    pub max_age: Duration,
+    /// Total size of the class in bytes, above which the oldest images are
+    /// deleted.
+    pub max_size: u64,
+}
+
+/// Retention policies for all image classes.
+#[derive(Clone, Copy, Debug)]
+pub struct Policies {
+    /// Policy for [`ImageClass::Identification`].
+    pub identification: Policy,
+    /// Policy for [`ImageClass::DataAcquisition`].
+    pub data_acquisition: Policy,
+    /// Policy for [`ImageClass::Debug`].
+    pub debug: Policy,
+}
+
+/// A directory of images of a single class inside a signup directory.
+#[derive(Clone, Debug, Serialize)]
+pub struct Entry {
+    /// Path to the directory.
+    pub path: PathBuf,
+    /// Class of the images.
+    pub class: ImageClass,
+    /// Total size in bytes.
+    pub size: u64,
+    /// Last modification time.
+    #[serde(skip)]
+    pub modified: SystemTime,
+}
+
+/// Signed record of the deleted images.
+#[derive(Debug, Serialize)]
+pub struct Receipt {
+    /// Deleted entries.
+    pub entries: Vec<Entry>,
+    /// Time of the deletion in seconds since the UNIX epoch.
+    pub timestamp: u64,
+}
+
+/// Marks a signup directory as being uploaded or queued for the upload. The
+/// reaper doesn't touch the directory until all its guards are dropped.
+#[derive(Debug)]
+pub struct UploadGuard {
+    signup_dir: PathBuf,
+}
+
+impl ImageClass {
+    /// Returns the image class for a sub-directory name of a signup directory.
+    #[must_use]
+    pub fn from_dir_name(name: &str) -> Self {
+        match name {
+            "identification" => Self::Identification,
+            "ir_camera" | "rgb_camera" | "ir_face" | "thermal" => Self::DataAcquisition,
+            _ => Self::Debug,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Identification => "identification",
+            Self::DataAcquisition => "data_acquisition",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+impl Policies {
+    /// Creates retention policies from the orb configuration.
+    #[must_use]
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            identification: Policy {
+                max_age: config.identification_images_max_age,
+                max_size: config.identification_images_max_size,
+            },
+            data_acquisition: Policy {
+                max_age: config.data_acquisition_images_max_age,
+                max_size: config.data_acquisition_images_max_size,
+            },
+            debug: Policy {
+                max_age: config.debug_images_max_age,
+                max_size: config.debug_images_max_size,
+            },
+        }
+    }
+
+    /// Returns the policy for the image `class`.
+    #[must_use]
+    pub fn get(&self, class: ImageClass) -> &Policy {
+        match class {
+            ImageClass::Identification => &self.identification,
+            ImageClass::DataAcquisition => &self.data_acquisition,
+            ImageClass::Debug => &self.debug,
+        }
+    }
+}
+
+impl Default for Policies {
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+impl UploadGuard {
+    /// Marks `signup_dir` as being uploaded.
+    #[must_use]
+    pub fn new(signup_dir: &Path) -> Self {
+        *UPLOADS_IN_PROGRESS.lock().unwrap().entry(signup_dir.to_owned()).or_default() += 1;
+        Self { signup_dir: signup_dir.to_owned() }
+    }
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        let mut uploads = UPLOADS_IN_PROGRESS.lock().unwrap();
+        if let Some(count) = uploads.get_mut(&self.signup_dir) {
+            *count -= 1;
+            if *count == 0 {
+                uploads.remove(&self.signup_dir);
+            }
+        }
+    }
+}
+
+/// Selects the entries which exceed their retention policy.
+///
+/// Entries older than the maximum age of their class are selected first. Then
+/// the oldest remaining entries of each class are selected until the total
+/// size of the class fits into its maximum size.
+#[must_use]
+pub fn select_expired(mut entries: Vec<Entry>, policies: &Policies, now: SystemTime) -> Vec<Entry> {
+    entries.sort_by_key(|entry| entry.modified);
+    let mut expired = Vec::new();
+    let mut retained = Vec::new();
+    for entry in entries {
+        let age = now.duration_since(entry.modified).unwrap_or_default();
+        if age > policies.get(entry.class).max_age {
+            expired.push(entry);
+        } else {
+            retained.push(entry);
+        }
+    }
+    for class in [ImageClass::Identification, ImageClass::DataAcquisition, ImageClass::Debug] {
+        let max_size = policies.get(class).max_size;
+        let mut total_size: u64 =
+            retained.iter().filter(|entry| entry.class == class).map(|entry| entry.size).sum();
+        retained.retain(|entry| {
+            if entry.class != class || total_size <= max_size {
+                return true;
+            }
+            total_size -= entry.size;
+            expired.push(entry.clone());
+            false
+        });
+    }
+    expired
+}
+
+/// Deletes the images under `base_dir` which exceed their retention policy.
+/// Returns the signed deletion receipt if anything was deleted.
+pub fn reap(base_dir: &Path, policies: &Policies) -> Result<Option<Receipt>> {
+    let Some(entries) = ssd::perform(|| collect_entries(base_dir)) else {
+        return Ok(None);
+    };
+    let expired = select_expired(entries, policies, SystemTime::now());
+    let mut deleted = Vec::new();
+    for entry in expired {
+        // An upload could have started since the entries were collected.
+        if is_upload_in_progress(&entry.path) {
+            continue;
+        }
+        if ssd::perform(|| fs::remove_dir_all(&entry.path)).is_some() {
+            tracing::info!(
+                "Image retention: removed {} ({} bytes of {} images)",
+                entry.path.display(),
+                entry.size,
+                entry.class.as_str()
+            );
+            dd_incr!(
+                "main.count.data_acquisition.retention.deleted",
+                &format!("type:{}", entry.class.as_str())
+            );
+            deleted.push(entry);
+        }
+    }
+    ssd::perform(|| remove_empty_signup_dirs(base_dir));
+    if deleted.is_empty() {
+        return Ok(None);
+    }
+    let receipt = Receipt {
+        entries: deleted,
+        timestamp: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs(),
+    };
+    receipt.record()?;
+    Ok(Some(receipt))
+}
+
+impl Receipt {
+    /// Signs the receipt with the secure element and records it in telemetry.
+    fn record(&self) -> Result<()> {
+        let payload = serde_json::to_vec(self)?;
+        let signature = BASE64.encode(&secure_element::sign(&payload)?);
+        let size: u64 = self.entries.iter().map(|entry| entry.size).sum();
+        tracing::info!(
+            "Image retention deletion receipt: {}, signature: {signature}",
+            String::from_utf8_lossy(&payload)
+        );
+        dd_gauge!("main.gauge.data_acquisition.retention.deleted_bytes", size.to_string());
+        Ok(())
+    }
+}
+
+pub(super) fn is_upload_in_progress(path: &Path) -> bool {
+    UPLOADS_IN_PROGRESS.lock().unwrap().keys().any(|signup_dir| path.starts_with(signup_dir))
+}
+
+fn collect_entries(base_dir: &Path) -> std::io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    if !base_dir.exists() {
+        return Ok(entries);
+    }
+    for signup_dir in fs::read_dir(base_dir)? {
+        let signup_dir = signup_dir?;
+        if !signup_dir.file_type()?.is_dir() || is_upload_in_progress(&signup_dir.path()) {
+            continue;
+        }
+        for class_dir in fs::read_dir(signup_dir.path())? {
+            let class_dir = class_dir?;
+            if !class_dir.file_type()?.is_dir() {
+                continue;
+            }
+            let path = class_dir.path();
+            entries.push(Entry {
+                class: ImageClass::from_dir_name(&class_dir.file_name().to_string_lossy()),
+                size: dir::get_size(&path).unwrap_or(0),
+                modified: class_dir.metadata()?.modified()?,
+                path,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn remove_empty_signup_dirs(base_dir: &Path) -> std::io::Result<()> {
+    if !base_dir.exists() {
+        return Ok(());
+    }
+    for signup_dir in fs::read_dir(base_dir)? {
+        let path = signup_dir?.path();
+        if path.is_dir() && !is_upload_in_progress(&path) && fs::read_dir(&path)?.next().is_none() {
+            fs::remove_dir(&path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(60 * 60 * 24);
+
+    fn policies() -> Policies {
+        Policies {
+            identification: Policy { max_age: DAY * 7, max_size: 1000 },
+            data_acquisition: Policy { max_age: DAY, max_size: 1000 },
+            debug: Policy { max_age: DAY, max_size: 100 },
+        }
+    }
+
+    fn entry(name: &str, class: ImageClass, size: u64, modified: SystemTime) -> Entry {
+        Entry { path: PathBuf::from(name), class, size, modified }
+    }
+
+    fn names(entries: &[Entry]) -> Vec<String> {
+        let mut names: Vec<_> =
+            entries.iter().map(|entry| entry.path.display().to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_class_from_dir_name() {
+        assert_eq!(ImageClass::from_dir_name("identification"), ImageClass::Identification);
+        assert_eq!(ImageClass::from_dir_name("ir_camera"), ImageClass::DataAcquisition);
+        assert_eq!(ImageClass::from_dir_name("thermal"), ImageClass::DataAcquisition);
+        assert_eq!(ImageClass::from_dir_name("crops"), ImageClass::Debug);
+    }
+
+    #[test]
+    fn test_select_by_age() {
+        let now = SystemTime::now();
+        let entries = vec![
+            entry("a", ImageClass::Identification, 10, now - DAY * 2),
+            entry("b", ImageClass::DataAcquisition, 10, now - DAY * 2),
+            entry("c", ImageClass::DataAcquisition, 10, now),
+        ];
+        assert_eq!(names(&select_expired(entries, &policies(), now)), ["b"]);
+    }
+
+    #[test]
+    fn test_select_by_size() {
+        let now = SystemTime::now();
+        let entries = vec![
+            entry("new", ImageClass::DataAcquisition, 600, now),
+            entry("old", ImageClass::DataAcquisition, 600, now - Duration::from_secs(60)),
+            entry("debug", ImageClass::Debug, 50, now - Duration::from_secs(60)),
+        ];
+        assert_eq!(names(&select_expired(entries, &policies(), now)), ["old"]);
+    }
+
+    #[test]
+    fn test_upload_guard_protects_signup() {
+        let signup_dir = Path::new("./tmp/test/retention/signup");
+        {
+            let _guard = UploadGuard::new(signup_dir);
+            assert!(is_upload_in_progress(&signup_dir.join("ir_camera")));
+        }
+        assert!(!is_upload_in_progress(&signup_dir.join("ir_camera")));
+    }
+
+    #[test]
+    fn test_nested_upload_guards() {
+        let signup_dir = Path::new("./tmp/test/retention/nested");
+        let queued = UploadGuard::new(signup_dir);
+        drop(UploadGuard::new(signup_dir));
+        assert!(is_upload_in_progress(signup_dir));
+        drop(queued);
+        assert!(!is_upload_in_progress(signup_dir));
+    }
+}
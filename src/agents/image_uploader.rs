@@ -1,13 +1,23 @@
 //! Image upload agent
 //!
 //! This agent will use the files saved to disk by [`crate::agents::image_notary`].
+//! While uploading, it also enforces the image
+//! [retention policies](crate::agents::image_notary::retention) and
+//! [compacts](crate::agents::image_notary::compaction) the cold images. The
+//! uploads are deferred while the orb is [pre-cooling](crate::monitor::thermal).
+//! Large images are uploaded [directly to S3](crate::backend::s3) in parallel
+//! parts, and small images are [bundled](bundle) into archives to save the
+//! per-request overhead.
 //!
 //! It is only enabled with the `internal-data-acquisition` feature.
 
+pub mod bundle;
+
 use crate::{
-    backend::{presigned_url::UrlType, upload_image},
-    consts::DATA_ACQUISITION_BASE_DIR,
-    dd_gauge, dd_incr, dd_timing, ssd,
+    agents::image_notary::{compaction, retention},
+    backend::{presigned_url::UrlType, s3},
+    consts::{DATA_ACQUISITION_BASE_DIR, IMAGE_COMPACTION_INTERVAL, IMAGE_RETENTION_REAP_INTERVAL},
+    dd_gauge, dd_incr, dd_timing, monitor, ssd,
 };
 use agentwire::port::{self, Port};
 use bytesize::ByteSize;
@@ -19,22 +29,41 @@ use std::{
     convert::Infallible,
     path::{Path, PathBuf},
     pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::{Duration, Instant, SystemTime},
 };
-use tokio::{fs, task::spawn_blocking, time::sleep};
+use tokio::{
+    fs,
+    task::spawn_blocking,
+    time::{sleep, Sleep},
+};
 
 type UploadImages = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
 
+type CompactImages = Pin<Box<dyn Future<Output = Result<compaction::Compactor>> + Send>>;
+
 /// Image upload agent
 #[derive(Default, Debug)]
-pub struct Agent;
+pub struct Agent {
+    retention: Option<retention::Policies>,
+    compactor: Option<compaction::Compactor>,
+    compaction_cancel: Option<Arc<AtomicBool>>,
+}
 
 /// Image upload agent inputs
 #[allow(missing_docs)]
 #[derive(Debug)]
 pub enum Input {
-    /// Start uploading all currently available images.
-    StartUpload { image_upload_delay: Duration },
+    /// Start uploading all currently available images, enforcing the
+    /// retention policies, and compacting the cold images.
+    StartUpload {
+        image_upload_delay: Duration,
+        retention: retention::Policies,
+        compactor: compaction::Compactor,
+    },
     /// Stop upload - killing any pending requests.
     PauseUpload,
 }
@@ -58,11 +87,28 @@ impl agentwire::agent::Task for Agent {
     async fn run(mut self, mut port: port::Inner<Self>) -> Result<(), Self::Error> {
         let network_request = Fuse::terminated();
         pin_mut!(network_request);
+        let reap_timer = Fuse::<Pin<Box<Sleep>>>::terminated();
+        pin_mut!(reap_timer);
+        let compaction = Fuse::<CompactImages>::terminated();
+        pin_mut!(compaction);
+        let compaction_timer = Fuse::<Pin<Box<Sleep>>>::terminated();
+        pin_mut!(compaction_timer);
         loop {
             select! {
                 input = port.next() => {
                     if let Some(input) = input {
                         self.handle_input(input.value, &mut network_request).await?;
+                        if self.retention.is_some() {
+                            reap_timer.set(Box::pin(sleep(Duration::ZERO)).fuse());
+                        } else {
+                            reap_timer.set(Fuse::terminated());
+                        }
+                        if self.compactor.is_some() {
+                            compaction_timer.set(Box::pin(sleep(IMAGE_COMPACTION_INTERVAL)).fuse());
+                        } else {
+                            compaction_timer.set(Fuse::terminated());
+                            compaction.set(Fuse::terminated());
+                        }
                     } else {
                         break;
                     }
@@ -70,6 +116,38 @@ impl agentwire::agent::Task for Agent {
                 _ = network_request => {
                     tracing::info!("Data upload complete.");
                 }
+                () = reap_timer => {
+                    if let Some(policies) = self.retention {
+                        let reap = spawn_blocking(move || {
+                            retention::reap(Path::new(DATA_ACQUISITION_BASE_DIR), &policies)
+                        });
+                        if let Err(err) = reap.await? {
+                            tracing::error!("Image retention enforcement failed: {err:?}");
+                        }
+                    }
+                    reap_timer.set(Box::pin(sleep(IMAGE_RETENTION_REAP_INTERVAL)).fuse());
+                }
+                () = compaction_timer => {
+                    if let Some(mut compactor) = self.compactor.take() {
+                        let compact: CompactImages = Box::pin(async move {
+                            spawn_blocking(move || {
+                                compactor.run(Path::new(DATA_ACQUISITION_BASE_DIR))?;
+                                Ok(compactor)
+                            })
+                            .await?
+                        });
+                        compaction.set(compact.fuse());
+                    }
+                }
+                result = compaction => {
+                    match result {
+                        Ok(compactor) => self.compactor = Some(compactor),
+                        Err(err) => tracing::error!("Image compaction failed: {err:?}"),
+                    }
+                    if self.compactor.is_some() {
+                        compaction_timer.set(Box::pin(sleep(IMAGE_COMPACTION_INTERVAL)).fuse());
+                    }
+                }
             }
         }
         Ok(())
@@ -88,13 +166,22 @@ impl Agent {
         network_request: &mut Pin<&mut Fuse<UploadImages>>,
     ) -> Result<()> {
         match input {
-            Input::StartUpload { image_upload_delay } => {
+            Input::StartUpload { image_upload_delay, retention, compactor } => {
                 let box_var: UploadImages = Box::pin(upload_all_signup_images(image_upload_delay));
                 network_request.set(box_var.fuse());
+                self.retention = Some(retention);
+                self.compaction_cancel = Some(compactor.cancel_flag());
+                self.compactor = Some(compactor);
             }
             Input::PauseUpload => {
                 //Immediately drop any pending request.
                 network_request.set(Fuse::terminated());
+                self.retention = None;
+                // The compaction thread stops after the current image.
+                if let Some(cancel) = self.compaction_cancel.take() {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+                self.compactor = None;
             }
         }
         Ok(())
@@ -112,18 +199,22 @@ async fn upload_saved_images(
         tracing::warn!("The directory {:?} does not exist, skipping image upload", image_dir);
         return Ok(());
     }
-    let mut paths = Vec::new();
+    let mut files = Vec::new();
     ssd::perform_async(async {
         let mut dir_reader = fs::read_dir(&image_dir).await?;
         while let Some(entry) = dir_reader.next_entry().await? {
             let path = entry.path();
-            if path.extension().map_or(false, |path| path == "png") {
-                paths.push(path);
+            if path.extension().map_or(false, |path| path == "png" || path == "pcd") {
+                files.push((path, entry.metadata().await?.len()));
             }
         }
         Ok(())
     })
     .await;
+    let (groups, paths) = bundle::plan(files);
+    for group in groups {
+        upload_bundle(signup_id, presigned_url_type, &group, image_dir_name).await?;
+    }
     for path in paths {
         let image_id = ImageId::from_image_path(&path)?;
         let img_data = ssd::perform_async(async { fs::read(&path).await }).await;
@@ -152,11 +243,11 @@ async fn upload_image(
     log_image_path: &str,
     dd_image_tag: &str,
 ) -> Result<()> {
+    monitor::thermal::defer_while_pre_cooling().await;
     tracing::info!("Uploading image: {log_image_path}");
     let t = Instant::now();
     let response =
-        upload_image::request(signup_id, image_id, presigned_url_type, img_data, dd_image_tag)
-            .await;
+        s3::upload(signup_id, image_id, presigned_url_type, img_data, dd_image_tag).await;
     dd_timing!("main.time.data_acquisition.upload" + format!("{}.full", dd_image_tag), t);
     match response {
         Ok(()) => {
@@ -170,9 +261,51 @@ async fn upload_image(
     Ok(())
 }
 
+async fn upload_bundle(
+    signup_id: &SignupId,
+    presigned_url_type: UrlType,
+    paths: &[PathBuf],
+    dd_image_tag: &str,
+) -> Result<()> {
+    let Some(first) = paths.first() else {
+        return Ok(());
+    };
+    // The bundle object is keyed by its first image, which belongs to no other
+    // bundle.
+    let image_id = ImageId::from_image_path(first)?;
+    let mut files = Vec::with_capacity(paths.len());
+    for path in paths {
+        let Some(contents) = ssd::perform_async(async { fs::read(path).await }).await else {
+            continue;
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        files.push((name, contents));
+    }
+    if files.is_empty() {
+        return Ok(());
+    }
+    let bundle = spawn_blocking(move || bundle::Bundle::pack(&files, presigned_url_type)).await??;
+    tracing::info!(
+        "Uploading a bundle of {} images ({}) from {dd_image_tag}",
+        bundle.manifest.files.len(),
+        ByteSize::b(bundle.data.len() as u64)
+    );
+    dd_incr!("main.count.data_acquisition.upload.bundle" + format!("{}", dd_image_tag));
+    upload_image(
+        signup_id,
+        &image_id,
+        UrlType::Bundle,
+        bundle.data,
+        &format!("bundle of {}", first.display()),
+        &format!("{dd_image_tag}.bundle"),
+    )
+    .await
+}
+
 async fn upload_signup_images(signup_dir: &Path) -> Result<()> {
     // extract last element of signup directory path as String
     let signup_id = SignupId::from_signup_dir(signup_dir)?;
+    let _upload_guard = retention::UploadGuard::new(signup_dir);
     let t0 = Instant::now();
     upload_saved_images(signup_dir, "ir_camera", &signup_id, UrlType::Ir).await?;
     dd_timing!("main.time.data_acquisition.upload.batch.ir_camera", t0);
@@ -185,6 +318,9 @@ async fn upload_signup_images(signup_dir: &Path) -> Result<()> {
     let t3 = Instant::now();
     upload_saved_images(signup_dir, "thermal", &signup_id, UrlType::Thermal).await?;
     dd_timing!("main.time.data_acquisition.upload.batch.thermal", t3);
+    let t4 = Instant::now();
+    upload_saved_images(signup_dir, "depth", &signup_id, UrlType::Tof2dDepth).await?;
+    dd_timing!("main.time.data_acquisition.upload.batch.depth", t4);
     upload_identification_images_impl(signup_id).await?;
     dd_timing!("main.time.data_acquisition.upload.batch.full_signup", t0);
     ssd::perform_async(async { fs::remove_dir_all(signup_dir).await }).await;
@@ -246,7 +382,12 @@ async fn upload_all_signup_images(image_upload_delay: Duration) -> Result<()> {
     // Wifi to upload overnight
     spawn_blocking(log_data_to_upload_left).await??;
     sleep(image_upload_delay).await;
-    for path in get_signup_paths().await {
+    // The queued signups are kept from the retention reaper until their turn.
+    let queue = get_signup_paths()
+        .await
+        .map(|path| (retention::UploadGuard::new(&path), path))
+        .collect::<Vec<_>>();
+    for (_queued, path) in queue {
         spawn_blocking(log_data_to_upload_left).await??;
         tracing::info!("Starting to upload images from {}", path.display());
         if let Err(err) = upload_signup_images(&path).await {
@@ -258,6 +399,7 @@ async fn upload_all_signup_images(image_upload_delay: Duration) -> Result<()> {
 
 async fn upload_identification_images_impl(signup_id: SignupId) -> Result<()> {
     let signup_dir = Path::new(DATA_ACQUISITION_BASE_DIR).join(signup_id.to_string());
+    let _upload_guard = retention::UploadGuard::new(&signup_dir);
     spawn_blocking(log_data_to_upload_left).await??;
     tracing::info!("Starting to upload identification images from {}", signup_dir.display());
     let identification_dir = signup_dir.join("identification");
@@ -0,0 +1,175 @@
+//! Bundling of small files into archives.
+//!
+//! Uploading thousands of small data-acquisition images one request each is
+//! dominated by the per-request overhead. The small files are grouped into
+//! `tar.zst` bundles bounded by [`IMAGE_UPLOADER_BUNDLE_MAX_SIZE`] and
+//! [`IMAGE_UPLOADER_BUNDLE_MAX_FILES`]. The first archive entry of a bundle is
+//! the [`Manifest`] at [`MANIFEST_NAME`], which the backend uses to unpack the
+//! bundle into the individual objects. Files of at least
+//! [`IMAGE_UPLOADER_BUNDLE_FILE_THRESHOLD`] are left to the regular upload.
+
+use crate::{
+    backend::presigned_url::UrlType,
+    consts::{
+        IMAGE_UPLOADER_BUNDLE_FILE_THRESHOLD, IMAGE_UPLOADER_BUNDLE_MAX_FILES,
+        IMAGE_UPLOADER_BUNDLE_MAX_SIZE,
+    },
+};
+use eyre::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{mem::take, path::PathBuf};
+
+/// Name of the manifest entry.
+pub const MANIFEST_NAME: &str = "index.json";
+
+/// Manifest format version.
+pub const MANIFEST_VERSION: u32 = 1;
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Bundle index.
+#[derive(Clone, Debug, Serialize)]
+pub struct Manifest {
+    /// Manifest format version.
+    pub version: u32,
+    /// Presigned URL type of the bundled files.
+    #[serde(rename = "type")]
+    pub url_type: UrlType,
+    /// Bundled files in the archive order.
+    pub files: Vec<Entry>,
+}
+
+/// Bundled file description.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    /// File name, which is also the archive entry name.
+    pub name: String,
+    /// File size in bytes.
+    pub size: u64,
+    /// Hex-encoded SHA-256 of the file contents.
+    pub sha256: String,
+}
+
+/// Compressed bundle ready for the upload.
+#[derive(Debug)]
+pub struct Bundle {
+    /// Bundle index.
+    pub manifest: Manifest,
+    /// `tar.zst` archive contents.
+    pub data: Vec<u8>,
+}
+
+/// Splits the `files` with their sizes into the groups of small files to be
+/// bundled, and the large files to be uploaded on their own. The groups
+/// preserve the input order.
+#[must_use]
+pub fn plan(files: Vec<(PathBuf, u64)>) -> (Vec<Vec<PathBuf>>, Vec<PathBuf>) {
+    let mut groups = Vec::new();
+    let mut large = Vec::new();
+    let mut group = Vec::new();
+    let mut group_size = 0;
+    for (path, size) in files {
+        if size >= IMAGE_UPLOADER_BUNDLE_FILE_THRESHOLD {
+            large.push(path);
+            continue;
+        }
+        if !group.is_empty()
+            && (group.len() == IMAGE_UPLOADER_BUNDLE_MAX_FILES
+                || group_size + size > IMAGE_UPLOADER_BUNDLE_MAX_SIZE)
+        {
+            groups.push(take(&mut group));
+            group_size = 0;
+        }
+        group.push(path);
+        group_size += size;
+    }
+    if !group.is_empty() {
+        groups.push(group);
+    }
+    (groups, large)
+}
+
+impl Bundle {
+    /// Packs the named file contents into a bundle. This is a CPU-heavy
+    /// operation.
+    pub fn pack(files: &[(String, Vec<u8>)], url_type: UrlType) -> Result<Self> {
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            url_type,
+            files: files
+                .iter()
+                .map(|(name, contents)| Entry {
+                    name: name.clone(),
+                    size: contents.len() as u64,
+                    sha256: hex::encode(Sha256::digest(contents)),
+                })
+                .collect(),
+        };
+        let mut archive = tar::Builder::new(Vec::new());
+        append(&mut archive, MANIFEST_NAME, &serde_json::to_vec(&manifest)?)?;
+        for (name, contents) in files {
+            append(&mut archive, name, contents)?;
+        }
+        let data = zstd::encode_all(archive.into_inner()?.as_slice(), ZSTD_LEVEL)?;
+        Ok(Self { manifest, data })
+    }
+}
+
+fn append(archive: &mut tar::Builder<Vec<u8>>, name: &str, contents: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, name, contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read as _;
+
+    #[test]
+    fn test_plan() {
+        let small = IMAGE_UPLOADER_BUNDLE_FILE_THRESHOLD - 1;
+        let mut files = (0..IMAGE_UPLOADER_BUNDLE_MAX_FILES + 1)
+            .map(|i| (PathBuf::from(format!("{i}.png")), 1))
+            .collect::<Vec<_>>();
+        files.insert(1, (PathBuf::from("large.png"), IMAGE_UPLOADER_BUNDLE_FILE_THRESHOLD));
+        let (groups, large) = plan(files);
+        assert_eq!(large, [PathBuf::from("large.png")]);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].len(), IMAGE_UPLOADER_BUNDLE_MAX_FILES);
+        assert_eq!(groups[1], [PathBuf::from(format!("{IMAGE_UPLOADER_BUNDLE_MAX_FILES}.png"))]);
+
+        let count = IMAGE_UPLOADER_BUNDLE_MAX_SIZE / small + 1;
+        let files = (0..count).map(|i| (PathBuf::from(format!("{i}.png")), small)).collect();
+        let (groups, _) = plan(files);
+        assert_eq!(groups.len(), 2);
+        assert!(groups[0].len() as u64 * small <= IMAGE_UPLOADER_BUNDLE_MAX_SIZE);
+    }
+
+    #[test]
+    fn test_pack() {
+        let files = vec![("a.png".to_owned(), b"aaa".to_vec()), ("b.png".to_owned(), Vec::new())];
+        let bundle = Bundle::pack(&files, UrlType::Ir).unwrap();
+        assert_eq!(bundle.manifest.files.len(), 2);
+        assert_eq!(bundle.manifest.files[0].size, 3);
+        let tar = zstd::decode_all(bundle.data.as_slice()).unwrap();
+        let mut archive = tar::Archive::new(tar.as_slice());
+        let mut entries = archive.entries().unwrap().map(Result::unwrap);
+        let mut index = entries.next().unwrap();
+        assert_eq!(index.path().unwrap().to_str(), Some(MANIFEST_NAME));
+        let mut json = String::new();
+        index.read_to_string(&mut json).unwrap();
+        let manifest = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(manifest["type"], "ir");
+        assert_eq!(manifest["files"][1]["name"], "b.png");
+        drop(index);
+        let names = entries
+            .map(|entry| entry.path().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a.png", "b.png"]);
+    }
+}
@@ -0,0 +1,208 @@
+//! IMU agent.
+//!
+//! Records the IMU samples streamed by the main MCU and estimates the orb
+//! tilt from the gravity vector, which is the accelerometer signal low-pass
+//! filtered with [`GRAVITY_FILTER_RC`]. The tilt is the angle between the
+//! gravity vector and the vertical axis of the orb, and is reported every
+//! [`TILT_REPORT_INTERVAL`]. Orbs mounted at a bad angle exceed
+//! [`IMU_MAX_TILT_DEGREES`] for the whole signup. The raw samples are recorded
+//! into the [`Log`] downsampled to one per [`LOG_INTERVAL`].
+
+use crate::{
+    consts::IMU_MAX_TILT_DEGREES,
+    ext::broadcast::ReceiverExt as _,
+    mcu::{
+        self,
+        main::{ImuSample, Output as McuOutput},
+        Mcu,
+    },
+    pid::derivative::LowPassFilter,
+    time_series::TimeSeries,
+};
+use agentwire::port::{self, Port};
+use eyre::{Error, Result};
+use futures::{
+    channel::oneshot,
+    future::{self, Either},
+    prelude::*,
+};
+use std::time::{Duration, Instant};
+
+/// Time constant of the gravity low-pass filter in seconds.
+pub const GRAVITY_FILTER_RC: f64 = 0.5;
+
+/// Interval between the tilt reports.
+pub const TILT_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval between the raw samples recorded into the [`Log`].
+pub const LOG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Maximum number of entries in each [`Log`] time-series, which is 10 minutes
+/// at [`LOG_INTERVAL`].
+pub const LOG_LIMIT: usize = 6_000;
+
+/// IMU agent.
+///
+/// See [the module-level documentation](self) for details.
+pub struct Agent {
+    /// Main MCU handle, used to receive the IMU samples.
+    pub main_mcu: Box<dyn Mcu<mcu::Main>>,
+}
+
+/// Agent input.
+#[derive(Debug)]
+pub enum Input {
+    /// Resets the internal state of the agent.
+    Reset(oneshot::Sender<Log>),
+}
+
+/// Agent output.
+#[derive(Clone, Copy, Debug)]
+pub struct Output {
+    /// Angle between the gravity vector and the vertical axis of the orb in
+    /// degrees.
+    pub tilt_degrees: f64,
+}
+
+impl Output {
+    /// Returns `true` if the orb is tilted more than
+    /// [`IMU_MAX_TILT_DEGREES`].
+    #[must_use]
+    pub fn is_bad_mounting(&self) -> bool {
+        self.tilt_degrees > IMU_MAX_TILT_DEGREES
+    }
+}
+
+/// IMU history.
+#[derive(Debug)]
+pub struct Log {
+    /// Raw accelerometer time-series, downsampled to [`LOG_INTERVAL`].
+    pub accel: TimeSeries<[i32; 3]>,
+    /// Raw gyroscope time-series, downsampled to [`LOG_INTERVAL`].
+    pub gyro: TimeSeries<[i32; 3]>,
+    /// Tilt time-series in degrees.
+    pub tilt_degrees: TimeSeries<f64>,
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self {
+            accel: TimeSeries::builder().limit(LOG_LIMIT).build(),
+            gyro: TimeSeries::builder().limit(LOG_LIMIT).build(),
+            tilt_degrees: TimeSeries::builder().limit(LOG_LIMIT).build(),
+        }
+    }
+}
+
+/// Resets the recording and returns the IMU history log.
+pub async fn reset(port: &mut port::Outer<Agent>) -> Result<Log> {
+    let (tx, rx) = oneshot::channel();
+    port.send_unjam(port::Input::new(Input::Reset(tx))).await?;
+    Ok(rx.await?)
+}
+
+/// Gravity vector estimator.
+#[derive(Default)]
+pub struct Gravity {
+    filters: [LowPassFilter; 3],
+    last: Option<Instant>,
+}
+
+impl Gravity {
+    /// Adds an accelerometer sample and returns the current tilt in degrees.
+    pub fn add(&mut self, accel: [i32; 3], now: Instant) -> f64 {
+        let dt = self.last.map_or(0.0, |last| now.duration_since(last).as_secs_f64());
+        self.last = Some(now);
+        let [x, y, z] = [0, 1, 2]
+            .map(|axis| self.filters[axis].add(f64::from(accel[axis]), dt, GRAVITY_FILTER_RC));
+        tilt_degrees(x, y, z)
+    }
+}
+
+/// Returns the angle between the gravity vector and the vertical axis in
+/// degrees.
+#[must_use]
+pub fn tilt_degrees(x: f64, y: f64, z: f64) -> f64 {
+    x.hypot(y).atan2(z.abs()).to_degrees()
+}
+
+impl Port for Agent {
+    type Input = Input;
+    type Output = Output;
+
+    const INPUT_CAPACITY: usize = 0;
+    const OUTPUT_CAPACITY: usize = 0;
+}
+
+impl agentwire::Agent for Agent {
+    const NAME: &'static str = "imu";
+}
+
+impl agentwire::agent::Task for Agent {
+    type Error = Error;
+
+    async fn run(mut self, mut port: port::Inner<Self>) -> Result<(), Self::Error> {
+        'reset: loop {
+            let mut log = Log::default();
+            let mut gravity = Gravity::default();
+            let mut last_report: Option<Instant> = None;
+            let mut last_log: Option<Instant> = None;
+            self.main_mcu.rx_mut().clear()?;
+            loop {
+                match future::select(port.next(), self.main_mcu.rx_mut().next_broadcast()).await {
+                    Either::Left((Some(input), _)) => match input.value {
+                        Input::Reset(log_tx) => {
+                            #[allow(let_underscore_drop)]
+                            let _ = log_tx.send(log);
+                            continue 'reset;
+                        }
+                    },
+                    Either::Left((None, _)) => return Ok(()),
+                    Either::Right((output, _)) => {
+                        let McuOutput::Imu(ImuSample { accel, gyro, .. }) = output? else {
+                            continue;
+                        };
+                        let now = Instant::now();
+                        let tilt_degrees = gravity.add(accel, now);
+                        if !last_log.is_some_and(|last| now - last < LOG_INTERVAL) {
+                            last_log = Some(now);
+                            log.accel.push(accel);
+                            log.gyro.push(gyro);
+                        }
+                        if !last_report.is_some_and(|last| now - last < TILT_REPORT_INTERVAL) {
+                            last_report = Some(now);
+                            log.tilt_degrees.push(tilt_degrees);
+                            port.send(port::Output::new(Output { tilt_degrees })).await?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tilt_degrees() {
+        assert!(tilt_degrees(0.0, 0.0, 1000.0).abs() < 1e-9);
+        assert!(tilt_degrees(0.0, 0.0, -1000.0).abs() < 1e-9);
+        assert!((tilt_degrees(1000.0, 0.0, 1000.0) - 45.0).abs() < 1e-9);
+        assert!((tilt_degrees(0.0, 1000.0, 0.0) - 90.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_gravity_ignores_vibration() {
+        let mut gravity = Gravity::default();
+        let start = Instant::now();
+        let mut tilt = 0.0;
+        for i in 0..400 {
+            let vibration = if i % 2 == 0 { 300 } else { -300 };
+            tilt = gravity.add([174 + vibration, 0, 985], start + Duration::from_millis(i * 5));
+        }
+        assert!((tilt - 10.0).abs() < 1.0);
+        assert!(!Output { tilt_degrees: tilt }.is_bad_mounting());
+    }
+}
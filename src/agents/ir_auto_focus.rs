@@ -1,8 +1,15 @@
 //! Auto-focus for IR camera.
+//!
+//! The liquid lens is normally driven by a sharpness servo. When the servo
+//! oscillates, e.g. because of dirty optics or unusual eyelid geometry, the
+//! agent falls back to focus bracketing: it steps through a short bracket
+//! around the current setpoint, holds the sharpest point, and re-engages the
+//! servo after a successful capture.
 
 use crate::{
     agents::{camera, camera::Frame, python},
     consts::{AUTOFOCUS_MAX, AUTOFOCUS_MIN, IR_FOCUS_RANGE},
+    dd_incr,
     dsp::Lagging,
     pid::{derivative::LowPassFilter, InstantTimer, Pid, Timer},
 };
@@ -10,7 +17,7 @@ use agentwire::port::{self, Port};
 use eyre::{Error, Result};
 use futures::prelude::*;
 use ndarray::prelude::*;
-use std::{ops::RangeInclusive, time::Instant};
+use std::{collections::VecDeque, ops::RangeInclusive, time::Instant};
 
 const FOCUS_RANGE: RangeInclusive<i16> = AUTOFOCUS_MIN..=AUTOFOCUS_MAX;
 
@@ -46,6 +53,27 @@ const DISTANCE_SEARCH_RANGE: f64 = 130.0;
 // TODO: Replace with actual values
 const DISTANCE_TO_FOCUS_PARAMS: (f64, f64) = (-1.603_612_87, 462.536_655);
 
+// Number of focus direction flips within `OSCILLATION_WINDOW` after which the
+// servo is considered oscillating.
+const OSCILLATION_FLIPS: usize = 6;
+
+// Oscillation detection window in seconds.
+const OSCILLATION_WINDOW: f64 = 2.0;
+
+// Number of focus points in a bracket.
+const BRACKET_POINTS: i16 = 5;
+
+// Focus setting distance between the bracket points.
+const BRACKET_STEP: i16 = 20;
+
+// Number of sharpness samples at each bracket point. The first sample is
+// dropped to let the liquid lens settle.
+const BRACKET_SAMPLES_PER_POINT: u32 = 3;
+
+// Number of sharpness samples to hold the sharpest point before bracketing
+// again around it.
+const BRACKET_HOLD_SAMPLES: u32 = 30;
+
 /// Auto-focus for IR camera.
 ///
 /// See [the module-level documentation](self) for details.
@@ -63,6 +91,9 @@ pub enum Input {
     UserDistance(f64),
     /// Set minimal viable sharpness.
     SetMinSharpness(f64),
+    /// A sharp iris has been captured. Re-engages the servo if the agent is
+    /// in the bracketing mode.
+    Captured,
     /// Resets the internal state of the agent.
     Reset,
 }
@@ -88,6 +119,7 @@ impl agentwire::agent::Task for Agent {
             let mut update_counter: u64 = 0;
             let mut timer = InstantTimer::default();
             let mut controller = LiquidLensController::new(DEFAULT_MIN_SHARPNESS);
+            let mut bracket: Option<Bracket> = None;
             let mut range = FOCUS_RANGE;
             let mut sharpness;
             while let Some(input) = port.next().await {
@@ -126,6 +158,14 @@ impl agentwire::agent::Task for Agent {
                         controller.set_min_sharpness(min_sharpness);
                         continue;
                     }
+                    Input::Captured => {
+                        if let Some(bracket) = bracket.take() {
+                            let focus = bracket.focus();
+                            tracing::info!("IR auto-focus: re-engaging the servo at {focus}");
+                            controller.reengage(focus);
+                        }
+                        continue;
+                    }
                     Input::Reset => {
                         let capture_time = start_timestamp.elapsed().as_secs();
                         let fps = if capture_time > 0 { update_counter / capture_time } else { 0 };
@@ -134,7 +174,24 @@ impl agentwire::agent::Task for Agent {
                     }
                 }
                 let dt = timer.get_dt().unwrap_or(0.0);
-                let focus = controller.update(sharpness, range.clone(), dt);
+                let focus = if let Some(bracket) = &mut bracket {
+                    let focus = bracket.update(sharpness);
+                    if bracket.is_expired() {
+                        *bracket = Bracket::new(focus, &range);
+                    }
+                    bracket.focus()
+                } else {
+                    let focus = controller.update(sharpness, range.clone(), dt);
+                    if controller.is_oscillating() {
+                        tracing::info!(
+                            "IR auto-focus: servo oscillates, bracketing around {focus}"
+                        );
+                        dd_incr!("main.count.signup.during.biometric_capture.focus_bracketing");
+                        bracket.insert(Bracket::new(focus, &range)).focus()
+                    } else {
+                        focus
+                    }
+                };
                 port.send(port::Output::new(focus)).await?;
                 update_counter += 1;
             }
@@ -172,6 +229,19 @@ pub struct LiquidLensController {
     sharpness_last: f64,
     sharpness_peak: f64,
     sharpness_peak_searching: bool,
+    elapsed: f64,
+    flips: VecDeque<f64>,
+}
+
+/// Focus bracket around a setpoint, used when [`LiquidLensController`]
+/// oscillates.
+#[derive(Debug)]
+pub struct Bracket {
+    points: Vec<i16>,
+    index: usize,
+    samples: u32,
+    best: Option<(i16, f64)>,
+    hold: u32,
 }
 
 /// Generates the derived signal for [`LiquidLensController`].
@@ -196,9 +266,28 @@ impl LiquidLensController {
             sharpness_last: 0.0,
             sharpness_peak: 0.0,
             sharpness_peak_searching: true,
+            elapsed: 0.0,
+            flips: VecDeque::new(),
         }
     }
 
+    /// Restarts the servo from the `focus` setting.
+    pub fn reengage(&mut self, focus: i16) {
+        self.pid.reset();
+        self.derived.reset();
+        self.focus_curr = focus;
+        self.sharpness_forward = true;
+        self.sharpness_peak = 0.0;
+        self.sharpness_peak_searching = true;
+        self.flips.clear();
+    }
+
+    /// Returns `true` if the focus direction flips too often to converge.
+    #[must_use]
+    pub fn is_oscillating(&self) -> bool {
+        self.flips.len() >= OSCILLATION_FLIPS
+    }
+
     /// Sets the minimal viable sharpness.
     pub fn set_min_sharpness(&mut self, min_sharpness: f64) {
         self.min_sharpness = min_sharpness;
@@ -211,6 +300,10 @@ impl LiquidLensController {
         if sharpness.is_finite() {
             self.sharpness_last = sharpness;
         }
+        self.elapsed += dt;
+        while self.flips.front().is_some_and(|&flip| self.elapsed - flip > OSCILLATION_WINDOW) {
+            self.flips.pop_front();
+        }
         self.sharpness_peak = self.sharpness_peak.max(self.sharpness_last);
         if let Some(derived) = self.derived.add(self.sharpness_last, dt) {
             if derived.abs() >= FLIP_THRESHOLD && self.sharpness_forward != (derived > 0.0) {
@@ -221,6 +314,7 @@ impl LiquidLensController {
                         self.focus_forward = !self.focus_forward;
                         self.sharpness_peak_searching = false;
                         self.pid.reset();
+                        self.flips.push_back(self.elapsed);
                     }
                 }
             }
@@ -256,6 +350,56 @@ impl LiquidLensController {
     }
 }
 
+impl Bracket {
+    /// Creates a new bracket centered on the `center` focus setting.
+    #[must_use]
+    pub fn new(center: i16, range: &RangeInclusive<i16>) -> Self {
+        let half = BRACKET_POINTS / 2;
+        let mut points = (-half..=half)
+            .map(|i| center.saturating_add(i * BRACKET_STEP).clamp(*range.start(), *range.end()))
+            .collect::<Vec<_>>();
+        points.dedup();
+        Self { points, index: 0, samples: 0, best: None, hold: 0 }
+    }
+
+    /// Updates the bracket with the `sharpness` of a frame taken at the last
+    /// focus setting. Returns the next focus setting.
+    pub fn update(&mut self, sharpness: f64) -> i16 {
+        if let Some(&point) = self.points.get(self.index) {
+            if self.samples > 0
+                && sharpness.is_finite()
+                && self.best.map_or(true, |(_, best)| sharpness > best)
+            {
+                self.best = Some((point, sharpness));
+            }
+            self.samples += 1;
+            if self.samples == BRACKET_SAMPLES_PER_POINT {
+                self.index += 1;
+                self.samples = 0;
+            }
+        } else {
+            self.hold += 1;
+        }
+        self.focus()
+    }
+
+    /// Returns the current focus setting: the current bracket point, or the
+    /// sharpest point once the bracket is complete.
+    #[must_use]
+    pub fn focus(&self) -> i16 {
+        self.points.get(self.index).copied().unwrap_or_else(|| {
+            self.best.map_or_else(|| self.points[self.points.len() / 2], |(point, _)| point)
+        })
+    }
+
+    /// Returns `true` if the sharpest point has been held for too long
+    /// without a capture.
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.hold >= BRACKET_HOLD_SAMPLES
+    }
+}
+
 impl DerivedSignal {
     /// Adds a new partition of the target function. Returns the derived value.
     pub fn add(&mut self, sharpness: f64, dt: f64) -> Option<f64> {
@@ -289,3 +433,44 @@ fn compute_theoretical_focus_setting(mut user_distance: f64) -> i16 {
     let focus_setting = (m * user_distance + t) as i16;
     focus_setting.clamp(*FOCUS_RANGE.start(), *FOCUS_RANGE.end())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bracket_picks_sharpest_point() {
+        let sharpness = |focus: i16| 10.0 - f64::from((focus - 20).abs()) / 10.0;
+        let mut bracket = Bracket::new(0, &FOCUS_RANGE);
+        assert_eq!(bracket.points, [-40, -20, 0, 20, 40]);
+        let mut focus = bracket.focus();
+        for _ in 0..BRACKET_POINTS {
+            for _ in 0..BRACKET_SAMPLES_PER_POINT {
+                focus = bracket.update(sharpness(focus));
+            }
+        }
+        assert_eq!(focus, 20);
+        assert!(!bracket.is_expired());
+    }
+
+    #[test]
+    fn test_bracket_clamped_to_range() {
+        let bracket = Bracket::new(*FOCUS_RANGE.end() - 10, &FOCUS_RANGE);
+        assert_eq!(bracket.points, [350, 370, 390, 400]);
+    }
+
+    #[test]
+    fn test_oscillation() {
+        let mut controller = LiquidLensController::new(0.0);
+        assert!(!controller.is_oscillating());
+        for _ in 0..OSCILLATION_FLIPS {
+            controller.flips.push_back(0.0);
+        }
+        assert!(controller.is_oscillating());
+        controller.update(1.0, FOCUS_RANGE, OSCILLATION_WINDOW * 2.0);
+        assert!(!controller.is_oscillating());
+        controller.flips.extend([controller.elapsed; OSCILLATION_FLIPS]);
+        controller.reengage(0);
+        assert!(!controller.is_oscillating());
+    }
+}
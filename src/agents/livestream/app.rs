@@ -1,6 +1,7 @@
 use crate::{
     agents::{mirror, python, qr_code},
     consts::{AUTOFOCUS_MAX, AUTOFOCUS_MIN, IRIS_SCORE_MIN, IR_LED_MAX_DURATION},
+    identification,
     utils::RkyvNdarray,
 };
 use egui::{
@@ -18,6 +19,7 @@ pub struct App {
     phase: Option<&'static str>,
     expanded: Expanded,
     show_mirror_window: bool,
+    hide_overlay: bool,
     ir_eye_state: bool,
     ir_face_state: bool,
     rgb_state: bool,
@@ -58,6 +60,10 @@ impl App {
         self.target_left_eye = false;
     }
 
+    pub fn toggle_overlay(&mut self) {
+        self.hide_overlay = !self.hide_overlay;
+    }
+
     pub fn set_phase(&mut self, name: &'static str) {
         self.phase = Some(name);
         self.qr_code_points = Vec::new();
@@ -115,15 +121,19 @@ impl App {
     }
 
     pub fn update_rgb_viewport(&mut self, ui: &mut Ui, rect: Rect, response: &Response) {
-        self.update_rgb_net_estimate(ui, rect);
-        self.update_qr_code(ui, rect);
+        if !self.hide_overlay {
+            self.update_rgb_net_estimate(ui, rect);
+            self.update_qr_code(ui, rect);
+        }
         self.put_expanded_button(ui, rect, response, Expanded::Rgb);
         self.put_capturing_state(ui, rect, self.rgb_state);
     }
 
     pub fn update_ir_eye_viewport(&mut self, ui: &mut Ui, rect: Rect, response: &Response) {
-        self.update_ir_net_estimate(ui, rect);
-        self.update_ir_params(ui, rect);
+        if !self.hide_overlay {
+            self.update_ir_net_estimate(ui, rect);
+            self.update_ir_params(ui, rect);
+        }
         self.put_expanded_button(ui, rect, response, Expanded::IrEye);
         self.put_capturing_state(ui, rect, self.ir_eye_state);
     }
@@ -144,7 +154,12 @@ impl App {
     }
 
     pub fn update_dashboard(&mut self, ui: &mut Ui, rect: Rect) {
+        let alias = identification::alias::get();
         ui.allocate_ui_at_rect(rect, |ui| {
+            match &alias.venue_tag {
+                Some(venue_tag) => ui.label(format!("ORB: {} @ {venue_tag}", alias.display_name())),
+                None => ui.label(format!("ORB: {}", alias.display_name())),
+            };
             ui.label(format!("PHASE: {}", self.phase.unwrap_or("Initializing")));
             if ui
                 .button(egui::RichText::new(egui_phosphor::regular::ARROWS_OUT_CARDINAL).size(24.0))
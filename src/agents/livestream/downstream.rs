@@ -1,55 +1,147 @@
-use crate::consts::{LIVESTREAM_FRAME_HEIGHT, LIVESTREAM_FRAME_WIDTH};
+use crate::{
+    consts::{LIVESTREAM_FRAME_HEIGHT, LIVESTREAM_FRAME_WIDTH},
+    monitor::traffic::{self, Class},
+};
 use eyre::Result;
-use gstreamer::{prelude::*, ElementFactory, Pipeline};
+use gstreamer::{
+    prelude::*, Caps, ClockTime, Element, ElementFactory, MessageType, PadProbeReturn,
+    PadProbeType, Pipeline,
+};
 use gstreamer_app::AppSrc;
 use gstreamer_video::{VideoFormat, VideoFrameRef, VideoInfo};
-use std::{net::IpAddr, ptr};
+use std::{
+    net::IpAddr,
+    path::Path,
+    ptr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 const PORT: u16 = 9200;
 
+/// RTP payload type of the H.264 stream offered to WebRTC peers.
+const WEBRTC_PAYLOAD_TYPE: i32 = 96;
+
+/// Maximum time to wait for the muxer to finalize a recording.
+const EOS_TIMEOUT: ClockTime = ClockTime::from_seconds(2);
+
 pub struct Downstream {
     pipeline: Pipeline,
     appsrc: AppSrc,
     video_info: VideoInfo,
+    /// UDP sink of a network stream, for the traffic accounting.
+    udpsink: Option<Element>,
+    /// RTP bytes sent to a WebRTC peer, for the traffic accounting.
+    rtp_bytes: Option<Arc<AtomicU64>>,
+    bytes_accounted: AtomicU64,
 }
 
 impl Downstream {
-    #[allow(clippy::cast_possible_truncation)]
+    /// Streams H.264 over RTP to `addr`.
     pub fn new(addr: IpAddr) -> Result<Self> {
+        let rtph264pay = ElementFactory::make("rtph264pay").build()?;
+        let udpsink = ElementFactory::make("udpsink").build()?;
+        udpsink.set_property_from_str("host", &addr.to_string());
+        udpsink.set_property_from_str("port", &PORT.to_string());
+        let mut this = Self::with_sink("livestream", &[rtph264pay, udpsink.clone()])?;
+        this.udpsink = Some(udpsink);
+        Ok(this)
+    }
+
+    /// Streams H.264 over RTP to a WebRTC peer through `webrtcbin`. The
+    /// signaling and the ICE configuration of `webrtcbin` are up to the caller.
+    ///
+    /// The pipeline is left in the ready state, so the data channels can be
+    /// created before the negotiation. Call [`Downstream::play`] afterwards.
+    pub fn webrtc(webrtcbin: &Element) -> Result<Self> {
+        let rtph264pay = ElementFactory::make("rtph264pay")
+            .property_from_str("config-interval", "-1")
+            .build()?;
+        let caps = Caps::builder("application/x-rtp")
+            .field("media", "video")
+            .field("encoding-name", "H264")
+            .field("payload", WEBRTC_PAYLOAD_TYPE)
+            .build();
+        let capsfilter = ElementFactory::make("capsfilter").property("caps", caps).build()?;
+        let rtp_bytes = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&rtp_bytes);
+        rtph264pay.static_pad("src").expect("rtph264pay has a src pad").add_probe(
+            PadProbeType::BUFFER,
+            move |_, info| {
+                if let Some(buffer) = info.buffer() {
+                    counter.fetch_add(buffer.size() as u64, Ordering::Relaxed);
+                }
+                PadProbeReturn::Ok
+            },
+        );
+        let mut this =
+            Self::build("livestream-webrtc", &[rtph264pay, capsfilter, webrtcbin.clone()])?;
+        this.pipeline.set_state(gstreamer::State::Ready)?;
+        this.rtp_bytes = Some(rtp_bytes);
+        Ok(this)
+    }
+
+    /// Records H.264 into a Matroska file at `path`. Frames must be pushed
+    /// with timestamps.
+    pub fn record(path: &Path) -> Result<Self> {
+        let h264parse = ElementFactory::make("h264parse").build()?;
+        let matroskamux = ElementFactory::make("matroskamux").build()?;
+        let filesink = ElementFactory::make("filesink").build()?;
+        filesink.set_property_from_str("location", &path.to_string_lossy());
+        Self::with_sink("livestream-record", &[h264parse, matroskamux, filesink])
+    }
+
+    fn with_sink(name: &str, sink: &[Element]) -> Result<Self> {
+        let this = Self::build(name, sink)?;
+        this.play()?;
+        Ok(this)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn build(name: &str, sink: &[Element]) -> Result<Self> {
         let video_info =
             VideoInfo::builder(VideoFormat::Bgrx, LIVESTREAM_FRAME_WIDTH, LIVESTREAM_FRAME_HEIGHT)
                 .build()?;
-        let pipeline = Pipeline::with_name("livestream");
-        let appsrc = AppSrc::builder().caps(&video_info.to_caps()?).build();
+        let pipeline = Pipeline::with_name(name);
+        let appsrc =
+            AppSrc::builder().caps(&video_info.to_caps()?).format(gstreamer::Format::Time).build();
         let nvvidconv = ElementFactory::make("nvvidconv").build()?;
         let nvv4l2h264enc = ElementFactory::make("nvv4l2h264enc").build()?;
-        let rtph264pay = ElementFactory::make("rtph264pay").build()?;
-        let udpsink = ElementFactory::make("udpsink").build()?;
-        pipeline.add_many([
-            appsrc.upcast_ref(),
-            &nvvidconv,
-            &nvv4l2h264enc,
-            &rtph264pay,
-            &udpsink,
-        ])?;
+        pipeline.add_many([appsrc.upcast_ref(), &nvvidconv, &nvv4l2h264enc])?;
+        pipeline.add_many(sink)?;
         nvv4l2h264enc.set_property_from_str("insert-sps-pps", "1");
         nvv4l2h264enc.set_property_from_str("insert-vui", "1");
-        udpsink.set_property_from_str("host", &addr.to_string());
-        udpsink.set_property_from_str("port", &PORT.to_string());
         appsrc.link(&nvvidconv)?;
         nvvidconv.link(&nvv4l2h264enc)?;
-        nvv4l2h264enc.link(&rtph264pay)?;
-        rtph264pay.link(&udpsink)?;
-        pipeline.set_state(gstreamer::State::Playing)?;
+        Element::link_many([&nvv4l2h264enc].into_iter().chain(sink))?;
         appsrc.set_block(true);
-        Ok(Self { pipeline, appsrc, video_info })
+        Ok(Self {
+            pipeline,
+            appsrc,
+            video_info,
+            udpsink: None,
+            rtp_bytes: None,
+            bytes_accounted: AtomicU64::new(0),
+        })
     }
 
-    pub fn push(&self, frame: &[u8]) -> Result<()> {
+    /// Starts the pipeline.
+    pub fn play(&self) -> Result<()> {
+        self.pipeline.set_state(gstreamer::State::Playing)?;
+        Ok(())
+    }
+
+    /// Pushes a frame. `pts` is the presentation timestamp relative to the
+    /// stream start.
+    pub fn push(&self, frame: &[u8], pts: Option<Duration>) -> Result<()> {
         let mut buffer = gstreamer::Buffer::with_size(self.video_info.size())
             .expect("failed to create a new gstreamer buffer");
         {
             let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(pts.and_then(|pts| ClockTime::try_from(pts).ok()));
             let mut video_frame =
                 VideoFrameRef::from_buffer_ref_writable(buffer, &self.video_info).unwrap();
             let plane_data = video_frame.plane_data_mut(0).unwrap();
@@ -58,12 +150,34 @@ impl Downstream {
             }
         }
         self.appsrc.push_buffer(buffer)?;
+        self.account_traffic();
         Ok(())
     }
+
+    /// Accounts the bytes sent by the UDP sink or to the WebRTC peer since the
+    /// last call.
+    fn account_traffic(&self) {
+        let served = if let Some(udpsink) = &self.udpsink {
+            udpsink.property::<u64>("bytes-served")
+        } else if let Some(rtp_bytes) = &self.rtp_bytes {
+            rtp_bytes.load(Ordering::Relaxed)
+        } else {
+            return;
+        };
+        let accounted = self.bytes_accounted.swap(served, Ordering::Relaxed);
+        traffic::record(Class::Livestream, served.saturating_sub(accounted), 0);
+    }
 }
 
 impl Drop for Downstream {
     fn drop(&mut self) {
+        // Let the muxer write its trailer before tearing the pipeline down.
+        if self.appsrc.end_of_stream().is_ok() {
+            if let Some(bus) = self.pipeline.bus() {
+                let _ =
+                    bus.timed_pop_filtered(EOS_TIMEOUT, &[MessageType::Eos, MessageType::Error]);
+            }
+        }
         let _ = self.pipeline.set_state(gstreamer::State::Null);
     }
 }
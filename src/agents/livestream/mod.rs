@@ -1,25 +1,45 @@
 //! Live-streaming over the network.
 //!
-//! Used for live-streaming data from camera sensors.
+//! Used for live-streaming data from camera sensors. The rendered stream can
+//! also be recorded on the orb together with a [sidecar] of timestamped
+//! sensor data.
+//!
+//! A client authenticated with the orb control token can also send a
+//! constrained set of remote control [commands](Command). Overlay toggling is
+//! handled by the agent itself, the rest is passed to the orb broker.
+//!
+//! Besides the direct connection, a remote client can connect through the
+//! [WebRTC](webrtc) transport when the orb is behind a NAT.
 
 mod app;
 mod downstream;
 mod gpu;
+mod record;
+pub mod sidecar;
 mod upstream;
+pub mod webrtc;
 
 use self::{
     downstream::Downstream,
     gpu::Gpu,
+    record::Recording,
+    sidecar::Record,
     upstream::{Event, Upstream},
+    webrtc::WebRtc,
+};
+use crate::{
+    agents::{camera, camera::Frame, mirror, python, qr_code},
+    mcu,
+    monitor::traffic::{self, Class},
 };
-use crate::agents::{camera, camera::Frame, mirror, python, qr_code};
 use agentwire::port::{self, Port};
 use eyre::{Error, Result};
 use futures::{
     future::{self, Either},
     prelude::*,
 };
-use std::{convert::Infallible, sync::Arc, task::Poll};
+pub use livestream_event::{Command, Direction};
+use std::{path::PathBuf, sync::Arc, task::Poll};
 use tokio::runtime;
 
 /// Live-streaming agent.
@@ -69,11 +89,15 @@ pub enum Input {
     Exposure(u16),
     /// Target the left eye if `true` or the right eye if `false`.
     TargetLeftEye(bool),
+    /// Command sent to the main MCU. Only written to the recording sidecar.
+    McuCommand(mcu::main::Input),
+    /// Start recording into the directory, or stop recording if `None`.
+    Record(Option<PathBuf>),
 }
 
 impl Port for Agent {
     type Input = Input;
-    type Output = Infallible;
+    type Output = Command;
 
     const INPUT_CAPACITY: usize = 0;
     const OUTPUT_CAPACITY: usize = 0;
@@ -90,7 +114,9 @@ impl agentwire::agent::Thread for Agent {
     fn run(self, mut port: port::Inner<Self>) -> Result<(), Self::Error> {
         let rt = runtime::Builder::new_current_thread().enable_all().build()?;
         let mut upstream = rt.block_on(Upstream::new())?;
+        let mut webrtc = webrtc::Config::load().map(WebRtc::new);
         let mut downstream = None;
+        let mut recording: Option<Recording> = None;
         let mut gpu = rt.block_on(Gpu::new())?;
         gpu.clear_textures();
         loop {
@@ -101,6 +127,11 @@ impl agentwire::agent::Thread for Agent {
                 if let Poll::Ready(event) = upstream.poll_next_unpin(cx) {
                     return Poll::Ready(Either::Right(event));
                 }
+                if let Some(webrtc) = &mut webrtc {
+                    if let Poll::Ready(event) = webrtc.poll_next_unpin(cx) {
+                        return Poll::Ready(Either::Right(event));
+                    }
+                }
                 Poll::Pending
             }));
             let mut events = Vec::new();
@@ -131,6 +162,9 @@ impl agentwire::agent::Thread for Agent {
                         gpu.update_camera_depth(&frame);
                     }
                     Input::Phase(name) => {
+                        if let Some(recording) = &mut recording {
+                            recording.record(Record::Phase { name });
+                        }
                         gpu.app.set_phase(name);
                     }
                     Input::IrEyeState(ir_eye_state) => {
@@ -154,10 +188,19 @@ impl agentwire::agent::Thread for Agent {
                         continue;
                     }
                     Input::IrNetEstimate(ir_net_estimate) => {
+                        if let Some(recording) = &mut recording {
+                            recording.record(Record::from(&ir_net_estimate));
+                        }
                         gpu.app.set_ir_net_estimate(ir_net_estimate);
                         continue;
                     }
                     Input::RgbNetEstimate(rgb_net_estimate) => {
+                        if let Some(recording) = &mut recording {
+                            if let Some(prediction) = rgb_net_estimate.primary() {
+                                let value = prediction.user_distance();
+                                recording.record(Record::Distance { value });
+                            }
+                        }
                         gpu.app.set_rgb_net_estimate(rgb_net_estimate);
                         continue;
                     }
@@ -181,22 +224,73 @@ impl agentwire::agent::Thread for Agent {
                         gpu.app.set_target_left_eye(target_left_eye);
                         continue;
                     }
+                    Input::McuCommand(command) => {
+                        if let Some(recording) = &mut recording {
+                            recording.record(Record::Mcu { command: &command });
+                        }
+                        continue;
+                    }
+                    Input::Record(dir) => {
+                        recording = None;
+                        if let Some(dir) = dir {
+                            match Recording::start(&dir) {
+                                Ok(new_recording) => recording = Some(new_recording),
+                                Err(err) => {
+                                    tracing::error!("Livestream recording error: {err:?}");
+                                }
+                            }
+                        }
+                        continue;
+                    }
                 },
                 Either::Right(Some(Ok(Event::Connected(addr)))) => {
                     tracing::info!("Accepted a new Livestream connection from {}", addr.ip());
                     downstream = Some(Arc::new(Downstream::new(addr.ip())?));
                 }
+                Either::Right(Some(Ok(Event::PeerConnected(peer)))) => {
+                    tracing::info!("Accepted a new Livestream WebRTC peer");
+                    downstream = Some(peer);
+                }
                 Either::Right(Some(Ok(Event::Closed))) => {
                     tracing::info!("Livestream connection closed by client");
                     downstream = None;
                 }
                 Either::Right(Some(Ok(Event::UiEvents(ui_events)))) => {
-                    events = ui_events.into_iter().map(Into::into).collect();
+                    events =
+                        ui_events.into_iter().filter_map(|event| event.try_into().ok()).collect();
+                }
+                Either::Right(Some(Ok(Event::Control(Command::ToggleOverlay)))) => {
+                    gpu.app.toggle_overlay();
+                }
+                Either::Right(Some(Ok(Event::Control(command)))) => {
+                    tracing::info!("Livestream remote control command: {command:?}");
+                    // Don't stall the stream until the broker takes the
+                    // command, a client can repeat it.
+                    match port.tx.try_send(port::Output::new(command)) {
+                        Ok(()) => {}
+                        Err(err) if err.is_full() => {
+                            tracing::warn!("Livestream remote control command dropped: {err}");
+                        }
+                        Err(err) => return Err(err.into_send_error().into()),
+                    }
+                    continue;
                 }
             };
-            if let Some(downstream) = &downstream {
-                let downstream = Arc::clone(downstream);
-                gpu.render(events, move |buffer| downstream.push(&buffer));
+            // Over the daily budget, keep the connection but stop streaming.
+            let streaming =
+                downstream.as_ref().filter(|_| !traffic::is_throttled(Class::Livestream));
+            if streaming.is_some() || recording.is_some() {
+                let downstream = streaming.cloned();
+                let recording = recording.as_ref().map(Recording::pusher);
+                gpu.render(events, move |buffer| {
+                    if let Some(downstream) = downstream {
+                        downstream.push(&buffer, None)?;
+                    }
+                    if let Some(push) = recording {
+                        push(&buffer)?;
+                    }
+                    Ok(())
+                });
             }
         }
         Ok(())
@@ -0,0 +1,58 @@
+use super::{
+    downstream::Downstream,
+    sidecar::{self, Record},
+};
+use eyre::Result;
+use std::{
+    fs,
+    path::Path,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Livestream recording with its [sidecar](sidecar).
+pub struct Recording {
+    video: Arc<Downstream>,
+    sidecar: sidecar::Writer,
+    start: Instant,
+}
+
+impl Recording {
+    /// Starts a new recording in `dir`. The files are named after the UNIX
+    /// time of the recording start.
+    pub fn start(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let started_at = SystemTime::now();
+        let name = started_at.duration_since(UNIX_EPOCH)?.as_millis().to_string();
+        let video_name = format!("{name}.mkv");
+        let video = Arc::new(Downstream::record(&dir.join(&video_name))?);
+        let sidecar =
+            sidecar::Writer::create(&dir.join(format!("{name}.jsonl")), &video_name, started_at)?;
+        tracing::info!("Livestream recording started: {}", dir.join(&video_name).display());
+        Ok(Self { video, sidecar, start: Instant::now() })
+    }
+
+    /// Writes a sidecar record at the current time.
+    pub fn record(&mut self, record: Record<'_>) {
+        if let Err(err) = self.sidecar.write(self.start.elapsed(), record) {
+            tracing::error!("Livestream sidecar write error: {err:?}");
+        }
+    }
+
+    /// Returns a closure pushing a rendered frame into the recording at the
+    /// current time.
+    pub fn pusher(&self) -> impl FnOnce(&[u8]) -> Result<()> + Send + 'static {
+        let video = Arc::clone(&self.video);
+        let pts = self.start.elapsed();
+        move |frame| video.push(frame, Some(pts))
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        if let Err(err) = self.sidecar.flush() {
+            tracing::error!("Livestream sidecar flush error: {err:?}");
+        }
+        tracing::info!("Livestream recording stopped");
+    }
+}
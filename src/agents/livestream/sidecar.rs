@@ -0,0 +1,190 @@
+//! Sensor sidecar of a livestream recording.
+//!
+//! Every recording `<name>.mkv` is accompanied by `<name>.jsonl`, a [JSON
+//! Lines] file with the sensor data which was rendered on the livestream at
+//! the time. The first line is a [`Header`]:
+//!
+//! ```json
+//! {"format":"orb-livestream-sidecar","version":1,"video":"<name>.mkv","startedAt":1700000000000}
+//! ```
+//!
+//! `startedAt` is the UNIX time of the recording start in milliseconds. Every
+//! following line is a [`Record`], tagged by `kind`. The `t` field of a record
+//! is the number of milliseconds since the recording start, and matches the
+//! presentation timestamp of the video frame rendered right after the record:
+//!
+//! ```json
+//! {"t":1520,"kind":"phase","name":"Biometric capture"}
+//! {"t":1533,"kind":"distance","value":0.31}
+//! {"t":1534,"kind":"irNet","sharpness":0.8,"occlusion30":0.1,...}
+//! {"t":1540,"kind":"mcu","command":{"mcu_input":"Mirror","value":[45000,90000]}}
+//! ```
+//!
+//! Consumers must ignore unknown record kinds and fields. Incompatible
+//! changes bump [`VERSION`].
+//!
+//! [JSON Lines]: https://jsonlines.org
+
+use crate::{agents::python, mcu};
+use eyre::Result;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Sidecar format identifier.
+pub const FORMAT: &str = "orb-livestream-sidecar";
+
+/// Sidecar format version.
+pub const VERSION: u32 = 1;
+
+/// First line of a sidecar file.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Header<'a> {
+    /// Always [`FORMAT`].
+    pub format: &'static str,
+    /// Always [`VERSION`].
+    pub version: u32,
+    /// File name of the video this sidecar belongs to.
+    pub video: &'a str,
+    /// UNIX time of the recording start in milliseconds.
+    pub started_at: u128,
+}
+
+/// Timestamped sidecar record.
+#[derive(Serialize, Debug)]
+pub struct Entry<'a> {
+    /// Milliseconds since the recording start.
+    pub t: u128,
+    /// The record.
+    #[serde(flatten)]
+    pub record: Record<'a>,
+}
+
+/// Sidecar record.
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Record<'a> {
+    /// Plan phase change.
+    Phase {
+        /// Phase name.
+        name: &'a str,
+    },
+    /// User distance estimated from the primary RGB-Net prediction.
+    Distance {
+        /// Estimated distance.
+        value: f64,
+    },
+    /// IR-Net scores.
+    #[serde(rename_all = "camelCase")]
+    IrNet {
+        /// Fractional sharpness score.
+        sharpness: f64,
+        /// Occlusion 30% score.
+        occlusion_30: f64,
+        /// Occlusion 90% score.
+        occlusion_90: f64,
+        /// Offgaze score.
+        gaze: f64,
+        /// Eye-classification score.
+        eye_detected: f64,
+        /// QR-code classification score.
+        qr_code_detected: f64,
+        /// Selection score.
+        score: f64,
+        /// Whether the estimation is valid for identification.
+        valid_for_identification: bool,
+    },
+    /// Command sent to the main MCU.
+    Mcu {
+        /// The command.
+        command: &'a mcu::main::Input,
+    },
+}
+
+impl<'a> From<&'a python::ir_net::EstimateOutput> for Record<'a> {
+    fn from(estimate: &'a python::ir_net::EstimateOutput) -> Self {
+        Self::IrNet {
+            sharpness: estimate.sharpness,
+            occlusion_30: estimate.occlusion_30,
+            occlusion_90: estimate.occlusion_90,
+            gaze: estimate.gaze,
+            eye_detected: estimate.eye_detected,
+            qr_code_detected: estimate.qr_code_detected,
+            score: estimate.score,
+            valid_for_identification: estimate.valid_for_identification,
+        }
+    }
+}
+
+/// Sidecar file writer.
+pub struct Writer<W: Write = BufWriter<File>> {
+    inner: W,
+}
+
+impl Writer {
+    /// Creates a sidecar file at `path` for the `video` file.
+    pub fn create(path: &Path, video: &str, started_at: SystemTime) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), video, started_at)
+    }
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a new writer and writes the header.
+    pub fn new(inner: W, video: &str, started_at: SystemTime) -> Result<Self> {
+        let mut writer = Self { inner };
+        let started_at = started_at.duration_since(UNIX_EPOCH)?.as_millis();
+        writer.write_line(&Header { format: FORMAT, version: VERSION, video, started_at })?;
+        Ok(writer)
+    }
+
+    /// Writes `record` at time `t` since the recording start.
+    pub fn write(&mut self, t: Duration, record: Record<'_>) -> Result<()> {
+        self.write_line(&Entry { t: t.as_millis(), record })
+    }
+
+    /// Flushes buffered records.
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.inner.flush()?)
+    }
+
+    fn write_line(&mut self, value: &impl Serialize) -> Result<()> {
+        serde_json::to_writer(&mut self.inner, value)?;
+        self.inner.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let mut writer =
+            Writer::new(Vec::new(), "1.mkv", UNIX_EPOCH + Duration::from_millis(1234)).unwrap();
+        writer.write(Duration::from_millis(10), Record::Phase { name: "Idle" }).unwrap();
+        writer.write(Duration::from_millis(20), Record::Distance { value: 0.5 }).unwrap();
+        let estimate = python::ir_net::EstimateOutput { sharpness: 0.25, ..Default::default() };
+        writer.write(Duration::from_millis(30), Record::from(&estimate)).unwrap();
+        let command = mcu::main::Input::Mirror(1, 2);
+        writer.write(Duration::from_millis(40), Record::Mcu { command: &command }).unwrap();
+        let output = String::from_utf8(writer.inner).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            r#"{"format":"orb-livestream-sidecar","version":1,"video":"1.mkv","startedAt":1234}"#
+        );
+        assert_eq!(lines[1], r#"{"t":10,"kind":"phase","name":"Idle"}"#);
+        assert_eq!(lines[2], r#"{"t":20,"kind":"distance","value":0.5}"#);
+        assert!(lines[3].starts_with(r#"{"t":30,"kind":"irNet","sharpness":0.25,"occlusion30":"#));
+        assert_eq!(
+            lines[4],
+            r#"{"t":40,"kind":"mcu","command":{"mcu_input":"Mirror","value":[1,2]}}"#
+        );
+    }
+}
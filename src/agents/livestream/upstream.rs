@@ -1,9 +1,15 @@
+use super::downstream::Downstream;
+use crate::consts::LIVESTREAM_CONTROL_TOKEN_PATH;
 use eyre::{eyre, Result};
 use futures::prelude::*;
+use livestream_event::Command;
 use std::{
+    collections::VecDeque,
+    fs,
     mem::take,
     net::SocketAddr,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 use tokio::{
@@ -16,12 +22,18 @@ const PORT: u16 = 9201;
 pub struct Upstream {
     listener: TcpListener,
     stream: Option<(TcpStream, EventReader)>,
+    authenticated: bool,
+    pending: VecDeque<Event>,
 }
 
 pub enum Event {
     Connected(SocketAddr),
+    /// A WebRTC peer connected, streaming to the downstream.
+    PeerConnected(Arc<Downstream>),
     Closed,
     UiEvents(Vec<livestream_event::Event>),
+    /// Remote control command from an authenticated connection.
+    Control(Command),
 }
 
 #[derive(Default)]
@@ -35,7 +47,57 @@ struct EventReader {
 impl Upstream {
     pub async fn new() -> Result<Self> {
         let listener = TcpListener::bind(format!("0.0.0.0:{PORT}")).await?;
-        Ok(Self { listener, stream: None })
+        Ok(Self { listener, stream: None, authenticated: false, pending: VecDeque::new() })
+    }
+
+    fn dispatch(&mut self, events: Vec<livestream_event::Event>) {
+        dispatch(events, &mut self.authenticated, &mut self.pending);
+    }
+}
+
+/// Splits the authentication and the remote control commands from the UI
+/// events.
+pub fn dispatch(
+    events: Vec<livestream_event::Event>,
+    authenticated: &mut bool,
+    pending: &mut VecDeque<Event>,
+) {
+    let mut ui_events = Vec::with_capacity(events.len());
+    for event in events {
+        match event {
+            livestream_event::Event::Authenticate(token) => {
+                *authenticated = check_control_token(&token);
+                if *authenticated {
+                    tracing::info!("Livestream remote control authenticated");
+                } else {
+                    tracing::warn!("Livestream remote control authentication failed");
+                }
+            }
+            livestream_event::Event::Control(command) if *authenticated => {
+                pending.push_back(Event::Control(command));
+            }
+            livestream_event::Event::Control(command) => {
+                tracing::warn!("Ignoring unauthenticated livestream command: {command:?}");
+            }
+            event => ui_events.push(event),
+        }
+    }
+    pending.push_front(Event::UiEvents(ui_events));
+}
+
+/// Checks the token against the operator remote control token. Returns
+/// `false` if the remote control is disabled.
+pub fn check_control_token(token: &str) -> bool {
+    match fs::read_to_string(LIVESTREAM_CONTROL_TOKEN_PATH) {
+        Ok(expected) => {
+            let expected = expected.trim();
+            !expected.is_empty()
+                && sodiumoxide::utils::memcmp(expected.as_bytes(), token.as_bytes())
+        }
+        Err(err) => {
+            tracing::warn!("Livestream remote control is disabled: {err}");
+            false
+        }
     }
 }
 
@@ -43,27 +105,31 @@ impl Stream for Upstream {
     type Item = Result<Event>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        if let Some((stream, event_reader)) = &mut self.stream {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            let Some((stream, event_reader)) = &mut self.stream else { break };
             match event_reader.poll_stream(cx, Pin::new(stream)) {
                 Poll::Ready(events) => {
                     *event_reader = EventReader::default();
                     match events {
-                        Ok(Some(events)) => {
-                            return Poll::Ready(Some(Ok(Event::UiEvents(events))));
-                        }
+                        Ok(Some(events)) => self.dispatch(events),
                         Ok(None) => {
                             self.stream = None;
+                            self.authenticated = false;
                             return Poll::Ready(Some(Ok(Event::Closed)));
                         }
                         Err(err) => return Poll::Ready(Some(Err(err))),
                     }
                 }
-                Poll::Pending => {}
+                Poll::Pending => break,
             }
         }
         match self.listener.poll_accept(cx) {
             Poll::Ready(Ok((stream, addr))) => {
                 self.stream = Some((stream, EventReader::default()));
+                self.authenticated = false;
                 Poll::Ready(Some(Ok(Event::Connected(addr))))
             }
             Poll::Ready(Err(err)) => {
@@ -1,5 +1,7 @@
 //! Movable mirrors agent.
 
+pub mod step_loss;
+
 use crate::{
     calibration, calibration::Calibration, ext::mpsc::SenderExt as _, time_series::TimeSeries,
 };
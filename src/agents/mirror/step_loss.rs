@@ -0,0 +1,180 @@
+//! Mirror step-loss detection.
+//!
+//! The mirror motors are open-loop steppers, so the main MCU doesn't report
+//! the achieved angles. Steps are lost when the motors are commanded to move
+//! faster than they can follow. [`Tracker`] follows the commanded trajectory
+//! and accumulates the angle which exceeded [`MIRROR_MAX_SLEW_DEGREES_PER_SEC`].
+//! Once the accumulated excess reaches [`MIRROR_STEP_LOSS_THRESHOLD_DEGREES`],
+//! the step loss is suspected, and the biometric capture rehomes the mirror
+//! before the next objective.
+//!
+//! The motor ranges reported by the MCU after a homing are compared against
+//! the first ones seen since boot. The homing between the objectives is the
+//! only time the MCU reports the mirror position, so a suspected step loss is
+//! recorded only if the range reported while the mirror is idle confirms it.
+//! Fast but normal moves are dropped this way. Otherwise a deviating range is
+//! reported as a mechanical drift.
+
+use crate::{
+    calibration::drift,
+    consts::{
+        MIRROR_MAX_SLEW_DEGREES_PER_SEC, MIRROR_RANGE_TOLERANCE, MIRROR_STEP_LOSS_THRESHOLD_DEGREES,
+    },
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Tracker shared between the observer and the orb brokers.
+pub type Shared = Arc<Mutex<Tracker>>;
+
+/// Commanded-trajectory tracker.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    last_command: Option<(Instant, u32, u32)>,
+    excess_phi_degrees: f64,
+    excess_theta_degrees: f64,
+    reference_range: [Option<u32>; 2],
+    latest_range: [Option<u32>; 2],
+    suspected_degrees: [Option<f64>; 2],
+}
+
+/// Mirror motor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Motor {
+    /// Horizontal phi motor.
+    Phi,
+    /// Vertical theta motor.
+    Theta,
+}
+
+impl Tracker {
+    /// Records a mirror command sent to the MCU in millidegrees.
+    pub fn command(&mut self, phi_millidegrees: u32, theta_millidegrees: u32) {
+        self.command_at(Instant::now(), phi_millidegrees, theta_millidegrees);
+    }
+
+    /// Returns `true` if the step loss is suspected, and resets the tracking.
+    /// Should be called when the mirror gets rehomed. The suspicion is kept
+    /// until the motor ranges reported after the homing confirm or clear it.
+    pub fn take_suspected(&mut self) -> bool {
+        let mut suspected = false;
+        for (motor, excess) in
+            [(Motor::Phi, self.excess_phi_degrees), (Motor::Theta, self.excess_theta_degrees)]
+        {
+            if excess >= MIRROR_STEP_LOSS_THRESHOLD_DEGREES {
+                self.suspected_degrees[motor as usize] = Some(excess);
+                suspected = true;
+            }
+        }
+        self.last_command = None;
+        self.excess_phi_degrees = 0.0;
+        self.excess_theta_degrees = 0.0;
+        suspected
+    }
+
+    /// Records a motor range reported by the MCU after a homing. Returns a
+    /// step-loss event if the range deviates from the first reported one
+    /// while a step loss of the motor is suspected, or a drift event if it
+    /// deviates otherwise.
+    pub fn motor_range(&mut self, motor: Motor, range_microsteps: u32) -> Option<drift::Event> {
+        let reference = *self.reference_range[motor as usize].get_or_insert(range_microsteps);
+        self.latest_range[motor as usize] = Some(range_microsteps);
+        let suspected = self.suspected_degrees[motor as usize].take();
+        let deviation = f64::from(range_microsteps.abs_diff(reference));
+        if deviation <= f64::from(reference) * MIRROR_RANGE_TOLERANCE {
+            if let Some(excess) = suspected {
+                tracing::info!("Mirror {motor:?} step loss of {excess:.1}° not confirmed");
+            }
+            return None;
+        }
+        Some(match (suspected, motor) {
+            (Some(excess), Motor::Phi) => drift::Event::step_loss(excess, 0.0),
+            (Some(excess), Motor::Theta) => drift::Event::step_loss(0.0, excess),
+            (None, _) => drift::Event::range_change(motor, reference, range_microsteps),
+        })
+    }
+
+    /// Returns the latest reported motor range relative to the first one
+    /// reported since boot.
+    #[must_use]
+    pub fn range_ratio(&self, motor: Motor) -> Option<f64> {
+        let reference = self.reference_range[motor as usize]?;
+        let latest = self.latest_range[motor as usize]?;
+        (reference > 0).then(|| f64::from(latest) / f64::from(reference))
+    }
+
+    fn command_at(&mut self, now: Instant, phi_millidegrees: u32, theta_millidegrees: u32) {
+        if let Some((last, last_phi, last_theta)) = self.last_command {
+            let max_degrees =
+                now.duration_since(last).as_secs_f64() * MIRROR_MAX_SLEW_DEGREES_PER_SEC;
+            let excess =
+                |from: u32, to: u32| (f64::from(from.abs_diff(to)) / 1000.0 - max_degrees).max(0.0);
+            self.excess_phi_degrees += excess(last_phi, phi_millidegrees);
+            self.excess_theta_degrees += excess(last_theta, theta_millidegrees);
+        }
+        self.last_command = Some((now, phi_millidegrees, theta_millidegrees));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_slow_trajectory() {
+        let start = Instant::now();
+        let mut tracker = Tracker::default();
+        for i in 0..100 {
+            let t = start + Duration::from_millis(20 * i);
+            tracker.command_at(t, 45_000 + 100 * u32::try_from(i).unwrap(), 90_000);
+        }
+        assert!(!tracker.take_suspected());
+    }
+
+    #[test]
+    fn test_jumps() {
+        let start = Instant::now();
+        let mut tracker = Tracker::default();
+        for i in 0..10 {
+            let t = start + Duration::from_millis(10 * i);
+            let phi = if i % 2 == 0 { 40_000 } else { 50_000 };
+            tracker.command_at(t, phi, 90_000);
+        }
+        assert!(tracker.take_suspected());
+        assert!(!tracker.take_suspected());
+    }
+
+    #[test]
+    fn test_confirmation() {
+        let start = Instant::now();
+        let mut tracker = Tracker::default();
+        assert!(tracker.motor_range(Motor::Phi, 10_000).is_none());
+        tracker.command_at(start, 40_000, 90_000);
+        tracker.command_at(start + Duration::from_millis(10), 50_000, 90_000);
+        assert!(tracker.take_suspected());
+        // Not confirmed by the homing.
+        assert!(tracker.motor_range(Motor::Phi, 10_000).is_none());
+        tracker.command_at(start, 40_000, 90_000);
+        tracker.command_at(start + Duration::from_millis(10), 50_000, 90_000);
+        assert!(tracker.take_suspected());
+        let event = tracker.motor_range(Motor::Phi, 9_000);
+        assert!(matches!(event, Some(drift::Event::StepLoss { .. })));
+        // A drift without a suspected step loss.
+        let event = tracker.motor_range(Motor::Phi, 9_000);
+        assert!(matches!(event, Some(drift::Event::RangeChange { .. })));
+    }
+
+    #[test]
+    fn test_motor_range() {
+        let mut tracker = Tracker::default();
+        assert!(tracker.motor_range(Motor::Phi, 10_000).is_none());
+        assert!(tracker.motor_range(Motor::Phi, 10_100).is_none());
+        assert!(tracker.motor_range(Motor::Theta, 20_000).is_none());
+        assert!(tracker.motor_range(Motor::Phi, 9_000).is_some());
+        assert!((tracker.range_ratio(Motor::Phi).unwrap() - 0.9).abs() < f64::EPSILON);
+        assert!((tracker.range_ratio(Motor::Theta).unwrap() - 1.0).abs() < f64::EPSILON);
+    }
+}
@@ -10,6 +10,7 @@ pub mod eye_tracker;
 pub mod image_notary;
 #[cfg(feature = "internal-data-acquisition")]
 pub mod image_uploader;
+pub mod imu;
 pub mod internal_temperature;
 pub mod ir_auto_exposure;
 pub mod ir_auto_focus;
@@ -55,6 +56,7 @@ pub fn call_process_agent(name: &str, fd: OwnedFd) -> Result<(), Box<dyn Error>>
 
 struct ProcessInitializer {
     dogstatsd_fd: OwnedFd,
+    cancellation_fd: RawFd,
 }
 
 impl Default for ProcessInitializer {
@@ -62,17 +64,23 @@ impl Default for ProcessInitializer {
         let dogstatsd_socket: UdpSocket = create_default_datadog_client().try_into().unwrap();
         let dogstatsd_fd = OwnedFd::from(dogstatsd_socket);
         clear_descriptor_cloexec(&dogstatsd_fd).unwrap();
-        Self { dogstatsd_fd }
+        Self { dogstatsd_fd, cancellation_fd: python::cancellation::shared_memory_fd() }
     }
 }
 
 impl agentwire::agent::process::Initializer for ProcessInitializer {
     fn keep_file_descriptors(&self) -> Vec<RawFd> {
-        vec![self.dogstatsd_fd.as_raw_fd()]
+        vec![self.dogstatsd_fd.as_raw_fd(), self.cancellation_fd]
     }
 
     fn envs(&self) -> Vec<(String, String)> {
-        vec![(PROCESS_DOGSTATSD_ENV.to_string(), self.dogstatsd_fd.as_raw_fd().to_string())]
+        vec![
+            (PROCESS_DOGSTATSD_ENV.to_string(), self.dogstatsd_fd.as_raw_fd().to_string()),
+            (
+                python::cancellation::PROCESS_CANCELLATION_ENV.to_string(),
+                self.cancellation_fd.to_string(),
+            ),
+        ]
     }
 }
 
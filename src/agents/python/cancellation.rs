@@ -0,0 +1,129 @@
+//! Cancellation of in-flight Python calls.
+//!
+//! The main process and the Python agent processes share a tiny memory region,
+//! which holds the monotonic time of the latest cancellation. Every input whose
+//! source timestamp precedes the cancellation is stale. The agents skip stale
+//! inputs without calling into Python, and the models which accept a
+//! `cancellation_token` keyword argument poll it to abort an in-flight
+//! inference.
+
+use ai_interface::CancellationToken;
+use once_cell::sync::Lazy;
+use std::{
+    env, io,
+    mem::size_of,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    ptr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+#[doc(hidden)]
+pub const PROCESS_CANCELLATION_ENV: &str = "ORB_CORE_PROCESS_CANCELLATION";
+
+static SHARED: Lazy<Shared> =
+    Lazy::new(|| Shared::new().expect("couldn't initialize python cancellation memory"));
+
+struct Shared {
+    fd: OwnedFd,
+    cancelled_at: &'static AtomicU64,
+}
+
+impl Shared {
+    /// Maps the memory region inherited from the parent process, or creates a
+    /// new one if this is the main process.
+    fn new() -> io::Result<Self> {
+        let fd = if let Ok(fd) = env::var(PROCESS_CANCELLATION_ENV) {
+            let fd =
+                fd.parse::<RawFd>().map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            unsafe { OwnedFd::from_raw_fd(fd) }
+        } else {
+            let fd = unsafe { libc::memfd_create(b"python-cancellation\0".as_ptr().cast(), 0) };
+            if fd == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            #[allow(clippy::cast_possible_wrap)]
+            let len = size_of::<AtomicU64>() as libc::off_t;
+            if unsafe { libc::ftruncate(fd.as_raw_fd(), len) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            fd
+        };
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size_of::<AtomicU64>(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // The mapping lives until the end of the process.
+        let cancelled_at = unsafe { &*addr.cast::<AtomicU64>() };
+        Ok(Self { fd, cancelled_at })
+    }
+}
+
+/// Cancels all Python calls for the inputs produced until now.
+pub fn cancel() {
+    let now = monotonic_now();
+    SHARED.cancelled_at.fetch_max(now, Ordering::AcqRel);
+    tracing::info!("Python calls cancelled");
+}
+
+/// Returns a cancellation token for an input with the source timestamp
+/// `source_ts`.
+#[must_use]
+pub fn token(source_ts: Instant) -> CancellationToken {
+    let issued_at = monotonic_now().saturating_sub(duration_nanos(source_ts.elapsed())).max(1);
+    CancellationToken::new(SHARED.cancelled_at, issued_at)
+}
+
+/// Returns the time elapsed since the latest cancellation.
+#[must_use]
+pub fn latency() -> Duration {
+    Duration::from_nanos(
+        monotonic_now().saturating_sub(SHARED.cancelled_at.load(Ordering::Acquire)),
+    )
+}
+
+/// Returns the file descriptor of the shared memory region, which must be
+/// inherited by the agent processes.
+#[must_use]
+pub fn shared_memory_fd() -> RawFd {
+    SHARED.fd.as_raw_fd()
+}
+
+/// Returns `CLOCK_MONOTONIC` in nanoseconds, the same clock as [`Instant`]
+/// uses on Linux, so the value is consistent across processes.
+#[allow(clippy::cast_sign_loss)]
+fn monotonic_now() -> u64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+fn duration_nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_stale_inputs() {
+        let stale = token(Instant::now());
+        assert!(!stale.is_cancelled());
+        cancel();
+        assert!(stale.is_cancelled());
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(!token(Instant::now()).is_cancelled());
+        assert!(latency() >= Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,95 @@
+//! Determinism mode for the Python agents.
+//!
+//! The biometric pipeline outputs vary between runs because of random seeds
+//! and nondeterministic GPU kernels selected by cuDNN and cuBLAS. When the
+//! `pipeline_deterministic_seed` config field is set, the mega agents seed
+//! every random generator they know of and ask the deep learning frameworks
+//! for deterministic algorithms before loading the models. The settings are
+//! part of the mega agent configs, so they end up in the debug report.
+
+#![allow(clippy::used_underscore_binding)] // triggered by rkyv
+
+use crate::config::Config;
+use eyre::Result;
+use pyo3::{types::IntoPyDict, Python};
+use rkyv::{Archive, Deserialize, Serialize};
+use schemars::JsonSchema;
+use serde::Serialize as SerdeSerialize;
+
+/// Python snippet applying the determinism settings. The optional frameworks
+/// are skipped if they are not installed.
+const APPLY_SCRIPT: &str = r#"
+import os
+import random
+
+os.environ["PYTHONHASHSEED"] = str(seed)
+os.environ["CUBLAS_WORKSPACE_CONFIG"] = ":4096:8"
+os.environ["TF_DETERMINISTIC_OPS"] = "1"
+os.environ["TF_CUDNN_DETERMINISTIC"] = "1"
+random.seed(seed)
+try:
+    import numpy
+    numpy.random.seed(seed % 2**32)
+except ImportError:
+    pass
+try:
+    import torch
+    torch.manual_seed(seed)
+    torch.cuda.manual_seed_all(seed)
+    torch.backends.cudnn.deterministic = True
+    torch.backends.cudnn.benchmark = False
+    torch.use_deterministic_algorithms(True, warn_only=True)
+except ImportError:
+    pass
+"#;
+
+/// Determinism settings of a Python agent.
+#[derive(
+    Clone, Copy, Debug, Default, Archive, Serialize, Deserialize, SerdeSerialize, JsonSchema,
+)]
+pub struct Determinism {
+    /// Fixed seed for the random generators. `None` disables the determinism
+    /// mode.
+    pub seed: Option<u64>,
+}
+
+impl Determinism {
+    /// Applies the settings to the Python interpreter. Must be called before
+    /// the models are loaded.
+    pub fn apply(&self, py: Python) -> Result<()> {
+        let Some(seed) = self.seed else {
+            return Ok(());
+        };
+        tracing::info!("Python determinism mode enabled with seed: {seed}");
+        py.run(APPLY_SCRIPT, None, Some([("seed", seed)].into_py_dict(py)))?;
+        Ok(())
+    }
+}
+
+impl From<&Config> for Determinism {
+    fn from(config: &Config) -> Self {
+        Self { seed: config.pipeline_deterministic_seed }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random(py: Python) -> f64 {
+        py.import("random").unwrap().call_method0("random").unwrap().extract().unwrap()
+    }
+
+    #[allow(clippy::float_cmp)]
+    #[test]
+    fn test_apply() {
+        Python::with_gil(|py| {
+            Determinism { seed: Some(42) }.apply(py).unwrap();
+            let first = random(py);
+            Determinism { seed: Some(42) }.apply(py).unwrap();
+            assert_eq!(random(py), first);
+            Determinism::default().apply(py).unwrap();
+            assert_ne!(random(py), first);
+        });
+    }
+}
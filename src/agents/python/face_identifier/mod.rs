@@ -24,7 +24,7 @@ use agentwire::{
     agent::{self, Agent as _},
     port::{self, Port, SharedPort},
 };
-use ai_interface::PyError;
+use ai_interface::{CancellationToken, PyError};
 use eyre::{Error, Result};
 use ndarray::{Array, Array3};
 use pyo3::{prelude::*, types::PyDict};
@@ -39,6 +39,7 @@ use std::{collections::HashMap, time::Instant};
 #[derive(Clone, Debug, Archive, Serialize, Deserialize, SerdeSerialize, JsonSchema)]
 pub struct Model {
     configs: Option<HashMap<String, String>>,
+    gpu_stream_priority: Option<i32>,
 }
 
 /// Agent input.
@@ -130,7 +131,12 @@ impl agentwire::Agent for Model {
 }
 
 impl super::Environment<Model> for Environment<'_> {
-    fn iterate(&mut self, py: Python, input: &ArchivedInput) -> Result<Output> {
+    fn iterate(
+        &mut self,
+        py: Python,
+        input: &ArchivedInput,
+        _token: &CancellationToken,
+    ) -> Result<Output> {
         let t = Instant::now();
 
         let (op, res) = match input {
@@ -201,7 +207,12 @@ impl super::Environment<Model> for Environment<'_> {
 impl Environment<'_> {
     /// Create a new python agent environment.
     pub fn new<'a>(py: Python<'a>, configs: &'_ Model) -> Result<Environment<'a>> {
-        tracing::info!("{} agent: loading model with config: {:?}", Model::NAME, configs.configs);
+        tracing::info!(
+            "{} agent: loading model with config: {:?}, GPU stream priority: {:?}",
+            Model::NAME,
+            configs.configs,
+            configs.gpu_stream_priority
+        );
         #[cfg(feature = "integration_testing")]
         if cfg!(feature = "integration_testing") {
             return Ok(Environment { agent: PyDict::new(py) });
@@ -309,6 +320,9 @@ impl agentwire::agent::Process for Model {
 
 impl From<&Config> for Model {
     fn from(config: &Config) -> Self {
-        Self { configs: config.face_identifier_model_configs.face_identifier_model_configs.clone() }
+        Self {
+            configs: config.face_identifier_model_configs.face_identifier_model_configs.clone(),
+            gpu_stream_priority: config.gpu_stream_priority(Self::NAME),
+        }
     }
 }
@@ -1,4 +1,10 @@
 //! IR-Net python agent.
+//!
+//! IR-Net score scales shift between model versions. The sharpness and
+//! selection scores are mapped to a stable calibrated scale with the
+//! [`ScoreCalibration`] curve configured for the loaded model version, so the
+//! score thresholds like [`IRIS_SCORE_MIN`](crate::consts::IRIS_SCORE_MIN)
+//! stay valid. The raw scores are kept along with the calibrated ones.
 
 #![allow(clippy::used_underscore_binding)] // triggered by rkyv
 
@@ -17,6 +23,7 @@ use agentwire::{
     agent::{self, Agent as _},
     port::{self, Port, SharedPort},
 };
+use ai_interface::CancellationToken;
 use eyre::{Error, Result};
 use ndarray::prelude::*;
 use numpy::PyArray2;
@@ -24,7 +31,7 @@ use orb_ir_net::IrNet;
 use pyo3::prelude::*;
 use rkyv::{Archive, Deserialize, Serialize};
 use schemars::JsonSchema;
-use serde::Serialize as SerdeSerialize;
+use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
 use std::{collections::HashMap, str, time::Instant};
 
 /// IR-Net python agent.
@@ -33,6 +40,31 @@ use std::{collections::HashMap, str, time::Instant};
 #[derive(Default, Clone, Debug, Archive, Serialize, Deserialize, SerdeSerialize, JsonSchema)]
 pub struct Model {
     configs: Option<HashMap<String, String>>,
+    score_calibrations: Option<HashMap<String, ScoreCalibration>>,
+    gpu_stream_priority: Option<i32>,
+}
+
+/// Piecewise-linear calibration curve mapping raw IR-Net scores to the
+/// calibrated scale.
+///
+/// The curve is a list of `[raw, calibrated]` points sorted by the raw score.
+/// Scores outside of the curve range are extrapolated from the nearest
+/// segment. An empty curve is the identity.
+#[derive(
+    Default,
+    Clone,
+    Debug,
+    PartialEq,
+    Archive,
+    Serialize,
+    Deserialize,
+    SerdeSerialize,
+    SerdeDeserialize,
+    JsonSchema,
+)]
+#[serde(transparent)]
+pub struct ScoreCalibration {
+    points: Vec<[f64; 2]>,
 }
 
 /// Agent input.
@@ -64,6 +96,8 @@ pub enum Output {
     Warmup,
     /// IR-Net returned with a Python exception error.
     Error,
+    /// IR-Net call was cancelled.
+    Cancelled,
 }
 
 /// IR-Net estimate output.
@@ -72,8 +106,10 @@ pub enum Output {
 pub struct EstimateOutput {
     /// Iris landmarks.
     pub landmarks: Option<RkyvNdarray<f32, Ix2>>,
-    /// Fractional sharpness score.
+    /// Calibrated fractional sharpness score.
     pub sharpness: f64,
+    /// Raw fractional sharpness score, as returned by the model.
+    pub sharpness_raw: f64,
     /// Occlusion 30% score.
     pub occlusion_30: f64,
     /// Occlusion 90% score.
@@ -106,8 +142,10 @@ pub struct EstimateOutput {
     pub status: i64,
     /// IR-Net status message.
     pub message: String,
-    /// Selection score.
+    /// Calibrated selection score.
     pub score: f64,
+    /// Raw selection score.
+    pub score_raw: f64,
     /// Mean brightness of input IR image.
     pub mean_brightness_raw: f64,
     /// Targeted eye.
@@ -119,6 +157,7 @@ pub struct EstimateOutput {
 struct Environment<'py> {
     ir_net: IrNet<'py>,
     version: String,
+    score_calibration: Option<ScoreCalibration>,
 }
 
 impl Port for Model {
@@ -140,14 +179,28 @@ impl agentwire::Agent for Model {
 }
 
 impl super::Environment<Model> for Environment<'_> {
-    fn iterate(&mut self, py: Python, input: &ArchivedInput) -> Result<Output> {
+    fn iterate(
+        &mut self,
+        py: Python,
+        input: &ArchivedInput,
+        token: &CancellationToken,
+    ) -> Result<Output> {
         let t = Instant::now();
 
         let (op, res) = match input {
+            ArchivedInput::Estimate { .. } if token.is_cancelled() => {
+                return Ok(Output::Cancelled);
+            }
             ArchivedInput::Estimate { frame, target_left_eye, focus_matrix_code } => (
                 "estimate",
-                self.estimate(py, frame.into_ndarray(), *target_left_eye, *focus_matrix_code)
-                    .map(Output::Estimate),
+                self.estimate(
+                    py,
+                    frame.into_ndarray(),
+                    *target_left_eye,
+                    *focus_matrix_code,
+                    token,
+                )
+                .map(Output::Estimate),
             ),
             ArchivedInput::Version => ("version", Ok(Output::Version(self.version()))),
             ArchivedInput::Warmup => ("warmup", self.warmup(py).map(|()| Output::Warmup)),
@@ -161,6 +214,10 @@ impl super::Environment<Model> for Environment<'_> {
             t.elapsed().as_millis()
         );
 
+        if op == "estimate" && token.is_cancelled() {
+            // The model could have aborted the call with an exception.
+            return Ok(Output::Cancelled);
+        }
         res.or_else(|e| {
             if let Some(pe) = e.downcast_ref::<PyErr>() {
                 <Model as super::AgentPython>::report_python_exception(py, &e, pe);
@@ -175,7 +232,7 @@ impl super::Environment<Model> for Environment<'_> {
 impl Environment<'_> {
     fn warmup(&self, py: Python) -> Result<()> {
         let image = PyArray2::from_owned_array(py, camera::ir::Frame::default().into_ndarray());
-        self.ir_net.estimate(image, false, false)?;
+        self.ir_net.estimate(image, false, false, None)?;
         Ok(())
     }
 
@@ -189,10 +246,16 @@ impl Environment<'_> {
         frame: Array2<u8>,
         target_left_eye: bool,
         focus_matrix_code: bool,
+        token: &CancellationToken,
     ) -> Result<EstimateOutput> {
         let image = PyArray2::from_owned_array(py, frame);
-        let estimate = self.ir_net.estimate(image, target_left_eye, focus_matrix_code)?;
-        extract(estimate)
+        let estimate =
+            self.ir_net.estimate(image, target_left_eye, focus_matrix_code, Some(token.clone()))?;
+        let mut estimate = extract(estimate)?;
+        if let Some(score_calibration) = &self.score_calibration {
+            estimate.calibrate(score_calibration);
+        }
+        Ok(estimate)
     }
 }
 
@@ -206,7 +269,16 @@ impl super::AgentPython for Model {
 
         let version = check_model_version(IrNet::module(py)?, Model::MINIMUM_MODEL_VERSION)?;
         let config = choose_config(self.configs.as_ref(), &version)?;
-        let ir_net = IrNet::init(py, &config)?;
+        let ir_net = IrNet::init(py, &config, self.gpu_stream_priority)?;
+        let score_calibration =
+            self.score_calibrations.as_ref().and_then(|calibrations| calibrations.get(&version));
+        if score_calibration.is_none() {
+            tracing::warn!(
+                "{} agent: no score calibration found for version {version}, using raw scores",
+                Model::NAME
+            );
+        }
+        let score_calibration = score_calibration.cloned();
 
         tracing::info!(
             "Python agent {} <benchmark>: initialization done in {} ms",
@@ -214,7 +286,7 @@ impl super::AgentPython for Model {
             t.elapsed().as_millis()
         );
         dd_timing!("main.time.neural_network.init" + format!("{}", Model::DD_NS), t);
-        Ok(Box::new(Environment { ir_net, version }))
+        Ok(Box::new(Environment { ir_net, version, score_calibration }))
     }
 }
 
@@ -232,7 +304,11 @@ impl agentwire::agent::Process for Model {
 
 impl From<&Config> for Model {
     fn from(config: &Config) -> Self {
-        Self { configs: config.ir_net_model_configs.clone() }
+        Self {
+            configs: config.ir_net_model_configs.clone(),
+            score_calibrations: config.ir_net_score_calibrations.clone(),
+            gpu_stream_priority: config.gpu_stream_priority(Self::NAME),
+        }
     }
 }
 
@@ -269,6 +345,7 @@ pub fn extract(estimation: &PyAny) -> Result<EstimateOutput> {
     let estimate = EstimateOutput {
         landmarks,
         sharpness,
+        sharpness_raw: sharpness,
         occlusion_30,
         occlusion_90,
         pupil_to_iris_ratio,
@@ -286,6 +363,7 @@ pub fn extract(estimation: &PyAny) -> Result<EstimateOutput> {
         status,
         message,
         score,
+        score_raw: score,
         mean_brightness_raw,
         target_side,
         perceived_side,
@@ -307,7 +385,50 @@ fn calculate_selection_score(sharpness: f64, valid_for_identification: bool, sta
     if status != 0 || !valid_for_identification || sharpness.is_nan() { -1.0 } else { sharpness }
 }
 
+impl ScoreCalibration {
+    /// Creates a new calibration curve from `[raw, calibrated]` points sorted
+    /// by the raw score.
+    #[must_use]
+    pub fn new(points: Vec<[f64; 2]>) -> Self {
+        Self { points }
+    }
+
+    /// Maps a raw score to the calibrated scale.
+    #[must_use]
+    pub fn apply(&self, raw: f64) -> f64 {
+        match self.points.as_slice() {
+            [] => raw,
+            [[x, y]] => raw - x + y,
+            points => {
+                let i = points
+                    .windows(2)
+                    .position(|segment| raw < segment[1][0])
+                    .unwrap_or(points.len() - 2);
+                let [x0, y0] = points[i];
+                let [x1, y1] = points[i + 1];
+                if (x1 - x0).abs() < f64::EPSILON {
+                    return y1;
+                }
+                y0 + (raw - x0) * (y1 - y0) / (x1 - x0)
+            }
+        }
+    }
+}
+
 impl EstimateOutput {
+    /// Maps the raw sharpness and selection scores to the calibrated scale.
+    /// Invalid selection scores are kept as is.
+    pub fn calibrate(&mut self, calibration: &ScoreCalibration) {
+        self.sharpness = calibration.apply(self.sharpness_raw);
+        if self.score_raw >= 0.0 {
+            self.score = calibration.apply(self.score_raw);
+        }
+        dd_gauge!(
+            "main.gauge.neural_network.ir_net.sharpness_calibrated",
+            self.sharpness.to_string()
+        );
+    }
+
     fn log(&self) {
         tracing::trace!(
             "Ir net result: sharpness {:?}, occlusion_30 {:?}, occlusion_90 {:?}, \
@@ -358,6 +479,33 @@ pub fn estimate_once(py: Python, frame: &camera::ir::Frame) -> Result<EstimateOu
     let shape = (frame.height() as usize, frame.width() as usize);
     let image = Array::from_shape_vec(shape, frame.to_vec())?;
     let image = PyArray2::from_owned_array(py, image);
-    let estimate = ir_net.estimate(image, false, false)?;
+    let estimate = ir_net.estimate(image, false, false, None)?;
     extract(estimate)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_calibration() {
+        let calibration = ScoreCalibration::new(vec![[0.0, 0.0], [1.0, 0.5], [3.0, 2.5]]);
+        assert!((calibration.apply(0.5) - 0.25).abs() < f64::EPSILON);
+        assert!((calibration.apply(2.0) - 1.5).abs() < f64::EPSILON);
+        assert!((calibration.apply(4.0) - 3.5).abs() < f64::EPSILON);
+        assert!((calibration.apply(-1.0) + 0.5).abs() < f64::EPSILON);
+        assert!((ScoreCalibration::default().apply(1.7) - 1.7).abs() < f64::EPSILON);
+
+        let mut estimate = EstimateOutput {
+            sharpness: 2.0,
+            sharpness_raw: 2.0,
+            score: -1.0,
+            score_raw: -1.0,
+            ..Default::default()
+        };
+        estimate.calibrate(&calibration);
+        assert!((estimate.sharpness - 1.5).abs() < f64::EPSILON);
+        assert!((estimate.sharpness_raw - 2.0).abs() < f64::EPSILON);
+        assert!((estimate.score + 1.0).abs() < f64::EPSILON);
+    }
+}
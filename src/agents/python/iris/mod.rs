@@ -23,7 +23,7 @@ use agentwire::{
     agent::{self, Agent as _},
     port::{self, Port, SharedPort},
 };
-use ai_interface::{InitAgent, PyError};
+use ai_interface::{init_kwargs, CancellationToken, InitAgent, PyError};
 use eyre::{Error, Result};
 use iris_mpc::{galois_engine::degree4::GaloisRingIrisCodeShare, iris_db::iris::IrisCodeArray};
 use numpy::PyArray2;
@@ -40,6 +40,7 @@ use std::{collections::HashMap, str, time::Instant};
 #[cfg_attr(feature = "stage", derive(Debug))]
 pub struct Model {
     configs: Option<HashMap<String, String>>,
+    gpu_stream_priority: Option<i32>,
 }
 
 #[cfg(not(feature = "stage"))]
@@ -154,7 +155,12 @@ struct Environment<'py> {
 }
 
 impl super::Environment<Model> for Environment<'_> {
-    fn iterate(&mut self, py: Python, input: &ArchivedInput) -> Result<Output> {
+    fn iterate(
+        &mut self,
+        py: Python,
+        input: &ArchivedInput,
+        _token: &CancellationToken,
+    ) -> Result<Output> {
         let t = Instant::now();
 
         let (op, res) = match input {
@@ -234,11 +240,9 @@ impl super::AgentPython for Model {
         let config = choose_config(self.configs.as_ref(), &version)?;
 
         let module = py.import("iris.pipelines.iris_pipeline")?;
-        let init: InitAgent = module
-            .getattr("IRISPipeline")?
-            .getattr("load_from_config")?
-            .call1((config,))?
-            .extract()?;
+        let init = module.getattr("IRISPipeline")?.getattr("load_from_config")?;
+        let kwargs = init_kwargs(py, init, self.gpu_stream_priority)?;
+        let init: InitAgent = init.call((config,), Some(kwargs))?.extract()?;
         let agent =
             init.agent.ok_or_else(|| init.error.expect("error should exist if agent is None"))?;
 
@@ -266,7 +270,10 @@ impl agentwire::agent::Process for Model {
 
 impl From<&Config> for Model {
     fn from(config: &Config) -> Self {
-        Self { configs: config.iris_model_configs.clone() }
+        Self {
+            configs: config.iris_model_configs.clone(),
+            gpu_stream_priority: config.gpu_stream_priority(Self::NAME),
+        }
     }
 }
 
@@ -13,7 +13,7 @@
 
 use crate::{
     agents::{
-        python::{ir_net, iris, occlusion},
+        python::{determinism::Determinism, ir_net, iris, occlusion},
         ProcessInitializer,
     },
     config::Config,
@@ -23,6 +23,7 @@ use agentwire::{
     agent::{self, Agent as _},
     port::{self, Port, SharedPort},
 };
+use ai_interface::CancellationToken;
 use eyre::{Error, Result};
 use pyo3::Python;
 use rkyv::{Archive, Deserialize, Serialize};
@@ -43,6 +44,8 @@ pub struct MegaAgentOne {
     pub iris: iris::Model,
     /// Initial state for the Occlusion model.
     pub occlusion: occlusion::Model,
+    /// Determinism settings.
+    pub determinism: Determinism,
 }
 
 impl agentwire::Agent for MegaAgentOne {
@@ -105,20 +108,25 @@ struct Environment<'py> {
 }
 
 impl super::Environment<MegaAgentOne> for Environment<'_> {
-    fn iterate(&mut self, py: Python, input: &ArchivedInput) -> Result<Output> {
+    fn iterate(
+        &mut self,
+        py: Python,
+        input: &ArchivedInput,
+        token: &CancellationToken,
+    ) -> Result<Output> {
         match input {
             ArchivedInput::Occlusion(input) => {
                 tracing::debug!("{}: Received input for Occlusion model", MegaAgentOne::NAME);
-                Ok(Output::Occlusion(self.occlusion_env.iterate(py, input)?))
+                Ok(Output::Occlusion(self.occlusion_env.iterate(py, input, token)?))
             }
             ArchivedInput::Iris(input) => {
                 tracing::debug!("{}: Received input for Iris", MegaAgentOne::NAME);
-                Ok(Output::Iris(self.iris_env.iterate(py, input)?))
+                Ok(Output::Iris(self.iris_env.iterate(py, input, token)?))
             }
             ArchivedInput::IRNet(input) => {
                 // This is at Trace level as we expect lots of inputs.
                 tracing::trace!("{}: Received input for IR-Net", MegaAgentOne::NAME);
-                Ok(Output::IRNet(self.ir_net_env.iterate(py, input)?))
+                Ok(Output::IRNet(self.ir_net_env.iterate(py, input, token)?))
             }
             ArchivedInput::Config => {
                 tracing::debug!("{}: Received input for Config", MegaAgentOne::NAME);
@@ -137,6 +145,7 @@ impl super::AgentPython for MegaAgentOne {
         let config_clone = self.clone();
         let t = Instant::now();
 
+        self.determinism.apply(py)?;
         let occlusion_env = occlusion::Model::init(self.occlusion, py)?;
         let iris_env = iris::Model::init(self.iris, py)?;
         let ir_net_env = ir_net::Model::init(self.ir_net, py)?;
@@ -165,6 +174,11 @@ impl agentwire::agent::Process for MegaAgentOne {
 
 impl From<&Config> for MegaAgentOne {
     fn from(config: &Config) -> Self {
-        Self { occlusion: occlusion::Model::default(), iris: config.into(), ir_net: config.into() }
+        Self {
+            occlusion: occlusion::Model::default(),
+            iris: config.into(),
+            ir_net: config.into(),
+            determinism: config.into(),
+        }
     }
 }
@@ -14,7 +14,7 @@
 use crate::{
     agents::{
         camera,
-        python::{face_identifier, iris, rgb_net, AgentPython},
+        python::{determinism::Determinism, face_identifier, iris, rgb_net, AgentPython},
         ProcessInitializer,
     },
     config::Config,
@@ -25,7 +25,7 @@ use agentwire::{
     agent::{self, Agent as _},
     port::{self, Port, SharedPort},
 };
-use ai_interface::PyError;
+use ai_interface::{CancellationToken, PyError};
 use eyre::{Error, Result};
 use pyo3::{PyErr, Python};
 use rkyv::{Archive, Deserialize, Serialize};
@@ -44,6 +44,8 @@ pub struct MegaAgentTwo {
     pub rgb_net: rgb_net::Model,
     /// Initial state for the Iris model.
     pub iris: iris::Model,
+    /// Determinism settings.
+    pub determinism: Determinism,
 }
 
 impl agentwire::Agent for MegaAgentTwo {
@@ -127,15 +129,20 @@ struct Environment<'py> {
 }
 
 impl super::Environment<MegaAgentTwo> for Environment<'_> {
-    fn iterate(&mut self, py: Python, input: &ArchivedInput) -> Result<Output> {
+    fn iterate(
+        &mut self,
+        py: Python,
+        input: &ArchivedInput,
+        token: &CancellationToken,
+    ) -> Result<Output> {
         match input {
             ArchivedInput::FaceIdentifier(input) => {
                 tracing::debug!("{}: Received input for FaceIdentifier", MegaAgentTwo::NAME);
-                self.face_identifier_env.iterate(py, input).map(Output::FaceIdentifier)
+                self.face_identifier_env.iterate(py, input, token).map(Output::FaceIdentifier)
             }
             ArchivedInput::RgbNet(input) => {
                 tracing::trace!("{}: Received input for RGB-Net", MegaAgentTwo::NAME);
-                self.rgb_net_env.iterate(py, input).map(Output::RgbNet)
+                self.rgb_net_env.iterate(py, input, token).map(Output::RgbNet)
             }
             ArchivedInput::FusionRgbNetFaceIdentifier { frame } => {
                 tracing::info!(
@@ -144,7 +151,7 @@ impl super::Environment<MegaAgentTwo> for Environment<'_> {
                 );
                 let t = Instant::now();
 
-                let res = self.fusion_rgb_net_face_identifier(py, frame).or_else(|e| {
+                let res = self.fusion_rgb_net_face_identifier(py, frame, token).or_else(|e| {
                     if let Some(pe) = e.downcast_ref::<PyErr>() {
                         <MegaAgentTwo as super::AgentPython>::report_python_exception(py, &e, pe);
                         Ok(Output::FusionError(FusionErrors::RgbNetFaceIdentifier(
@@ -169,7 +176,7 @@ impl super::Environment<MegaAgentTwo> for Environment<'_> {
             }
             ArchivedInput::Iris(input) => {
                 tracing::debug!("{}: Received input for Iris", MegaAgentTwo::NAME);
-                Ok(Output::Iris(Box::new(self.iris_env.iterate(py, input)?)))
+                Ok(Output::Iris(Box::new(self.iris_env.iterate(py, input, token)?)))
             }
             ArchivedInput::Config => {
                 tracing::debug!("{}: Received input for Config", MegaAgentTwo::NAME);
@@ -184,10 +191,23 @@ impl Environment<'_> {
         &mut self,
         py: Python,
         frame: &camera::rgb::ArchivedFrame,
+        token: &CancellationToken,
     ) -> Result<Output> {
+        let cancelled = || {
+            Ok(Output::FusionError(FusionErrors::RgbNetFaceIdentifier(
+                Some(rgb_net::Output::Cancelled),
+                None,
+            )))
+        };
+        if token.is_cancelled() {
+            return cancelled();
+        }
         let ndframe = frame.into_ndarray();
 
-        let rgb_net_res = self.rgb_net_env.rgb_net_estimate(py, ndframe.clone())?;
+        let rgb_net_res = self.rgb_net_env.rgb_net_estimate(py, ndframe.clone(), Some(token))?;
+        if token.is_cancelled() {
+            return cancelled();
+        }
         // If RGB-Net fails to estimate the eyes coordinates and bbox, avoid running the slow Face Identifier.
         let Some(prediction) = rgb_net_res.primary() else {
             return Ok(Output::FusionError(FusionErrors::RgbNetFaceIdentifier(
@@ -218,6 +238,7 @@ impl super::AgentPython for MegaAgentTwo {
         let config_clone = self.clone();
         let t = Instant::now();
 
+        self.determinism.apply(py)?;
         // The ROC's face model has to initialize first or else everything else fails.
         let face_identifier_env = face_identifier::Environment::new(py, &self.face_identifier)?;
         let rgb_net_env = rgb_net::Environment::new(py)?;
@@ -256,6 +277,7 @@ impl From<&Config> for MegaAgentTwo {
             rgb_net: rgb_net::Model::default(),
             iris: config.into(),
             face_identifier: config.into(),
+            determinism: config.into(),
         }
     }
 }
@@ -1,10 +1,11 @@
 //! Python-based agents.
 
-use crate::{dd_incr, utils::RkyvNdarray};
+use crate::{dd_incr, dd_timing, utils::RkyvNdarray};
 use agentwire::{
     agent,
     port::{self, SharedPort, SharedSerializer},
 };
+use ai_interface::CancellationToken;
 use eyre::{Report, Result, WrapErr};
 use ndarray::{Ix1, Ix2};
 use numpy::{PyArray1, PyArray2};
@@ -13,6 +14,8 @@ use regex::Regex;
 use rkyv::{de::deserializers::SharedDeserializeMap, Archive, Deserialize, Infallible, Serialize};
 use std::{collections::HashMap, ffi::CString};
 
+pub mod cancellation;
+pub mod determinism;
 pub mod face_identifier;
 pub mod ir_net;
 pub mod iris;
@@ -153,9 +156,29 @@ where
 
                 let input = port.recv();
                 let chain = input.chain_fn();
+                let token = cancellation::token(input.source_ts);
+                let cancelled_before = token.is_cancelled();
 
-                match environment.iterate(py, input.value) {
+                match environment.iterate(py, input.value, &token) {
                     Ok(output) => {
+                        if cancelled_before {
+                            dd_incr!(
+                                "main.count.neural_network"
+                                    + format!("{}.cancellation.stale_input", Self::DD_NS)
+                            );
+                        } else if token.is_cancelled() {
+                            let latency = cancellation::latency();
+                            tracing::info!(
+                                "Python agent '{}': in-flight call cancelled, latency: {} ms",
+                                Self::NAME,
+                                latency.as_millis()
+                            );
+                            dd_timing!(
+                                "main.time.neural_network"
+                                    + format!("{}.cancellation_latency", Self::DD_NS),
+                                latency
+                            );
+                        }
                         port.send(&chain(output));
                     }
                     Err(err) => {
@@ -179,8 +202,15 @@ where
     <T::Output as Archive>::Archived: Deserialize<T::Output, SharedDeserializeMap>,
 {
     /// This method is called continuously by the main loop.
-    fn iterate(&mut self, py: Python, input: &<T::Input as Archive>::Archived)
-    -> Result<T::Output>;
+    ///
+    /// Long-running calls should check the cancellation `token` and return
+    /// early once it's cancelled.
+    fn iterate(
+        &mut self,
+        py: Python,
+        input: &<T::Input as Archive>::Archived,
+        token: &CancellationToken,
+    ) -> Result<T::Output>;
 }
 
 /// hack to fix module 'sys' has no attribute 'argv' error
@@ -19,7 +19,7 @@ use agentwire::{
     agent::{self, Agent as _},
     port::{self, Port, SharedPort},
 };
-use ai_interface::PyError;
+use ai_interface::{CancellationToken, PyError};
 use eyre::{Error, Result};
 use ndarray::prelude::*;
 use numpy::{PyArray1, PyArray3};
@@ -98,7 +98,12 @@ impl agentwire::Agent for Model {
 }
 
 impl super::Environment<Model> for Environment<'_> {
-    fn iterate(&mut self, py: Python, input: &ArchivedInput) -> Result<Output> {
+    fn iterate(
+        &mut self,
+        py: Python,
+        input: &ArchivedInput,
+        _token: &CancellationToken,
+    ) -> Result<Output> {
         let t = Instant::now();
 
         let (op, res) = match input {
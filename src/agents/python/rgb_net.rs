@@ -2,6 +2,8 @@
 
 #![allow(clippy::used_underscore_binding)] // triggered by rkyv
 
+pub mod tracker;
+
 use crate::{
     agents::{
         camera::{self, Frame},
@@ -15,6 +17,7 @@ use agentwire::{
     agent::{self, Agent as _},
     port::{self, Port, SharedPort},
 };
+use ai_interface::CancellationToken;
 use eyre::{Error, Result, WrapErr};
 use ndarray::prelude::*;
 use numpy::PyArray3;
@@ -54,6 +57,8 @@ pub enum Output {
     Warmup,
     /// RGB-Net returned with a Python exception error.
     Error,
+    /// RGB-Net call was cancelled.
+    Cancelled,
 }
 
 /// RGB-Net estimate output.
@@ -72,6 +77,22 @@ pub struct EstimatePredictionOutput {
     pub bbox: EstimatePredictionBboxOutput,
     /// Landmarks prediction.
     pub landmarks: EstimatePredictionLandmarksOutput,
+    /// Id of the person track across frames, assigned by the
+    /// [`tracker`](tracker::Tracker).
+    pub track_id: Option<u64>,
+    /// Bounding box and landmarks smoothed over the track, assigned by the
+    /// [`tracker`](tracker::Tracker). The `bbox` and `landmarks` fields keep
+    /// the raw prediction.
+    pub smoothed: Option<EstimatePredictionSmoothedOutput>,
+}
+
+/// RGB-Net prediction smoothed over the person track.
+#[derive(Clone, Debug, Archive, Serialize, Deserialize, SerdeSerialize, JsonSchema)]
+pub struct EstimatePredictionSmoothedOutput {
+    /// Smoothed bounding box coordinates.
+    pub bbox: Rectangle,
+    /// Smoothed landmarks.
+    pub landmarks: EstimatePredictionLandmarksOutput,
 }
 
 /// RGB-Net bounding box prediction for a person.
@@ -170,13 +191,22 @@ impl agentwire::Agent for Model {
 }
 
 impl super::Environment<Model> for Environment<'_> {
-    fn iterate(&mut self, py: Python, input: &ArchivedInput) -> Result<Output> {
+    fn iterate(
+        &mut self,
+        py: Python,
+        input: &ArchivedInput,
+        token: &CancellationToken,
+    ) -> Result<Output> {
         let t = Instant::now();
 
         let (op, res) = match input {
-            ArchivedInput::Estimate { frame } => {
-                ("estimate", self.rgb_net_estimate(py, frame.into_ndarray()).map(Output::Estimate))
+            ArchivedInput::Estimate { .. } if token.is_cancelled() => {
+                return Ok(Output::Cancelled);
             }
+            ArchivedInput::Estimate { frame } => (
+                "estimate",
+                self.rgb_net_estimate(py, frame.into_ndarray(), Some(token)).map(Output::Estimate),
+            ),
             ArchivedInput::Warmup => ("warmup", self.warmup(py).map(|()| Output::Warmup)),
         };
 
@@ -188,6 +218,10 @@ impl super::Environment<Model> for Environment<'_> {
             t.elapsed().as_millis()
         );
 
+        if op == "estimate" && token.is_cancelled() {
+            // The model could have aborted the call with an exception.
+            return Ok(Output::Cancelled);
+        }
         res.or_else(|e| {
             if let Some(pe) = e.downcast_ref::<PyErr>() {
                 <Model as super::AgentPython>::report_python_exception(py, &e, pe);
@@ -218,17 +252,23 @@ impl Environment<'_> {
     }
 
     /// Run the estimate function.
-    pub fn rgb_net_estimate(&self, py: Python, frame: Array3<u8>) -> Result<EstimateOutput> {
-        let estimation = self.rgb_net.estimate(PyArray3::from_owned_array(py, frame))?;
+    pub fn rgb_net_estimate(
+        &self,
+        py: Python,
+        frame: Array3<u8>,
+        token: Option<&CancellationToken>,
+    ) -> Result<EstimateOutput> {
+        let estimation =
+            self.rgb_net.estimate(PyArray3::from_owned_array(py, frame), token.cloned())?;
         extract(estimation).wrap_err("RGB-Net rgb_net_estimate.extract failed")
     }
 
     /// Run the estimate function.
     pub fn warmup(&self, py: Python) -> Result<()> {
-        self.rgb_net.estimate(PyArray3::from_owned_array(
-            py,
-            camera::rgb::Frame::default().into_ndarray(),
-        ))?;
+        self.rgb_net.estimate(
+            PyArray3::from_owned_array(py, camera::rgb::Frame::default().into_ndarray()),
+            None,
+        )?;
         Ok(())
     }
 }
@@ -264,13 +304,28 @@ impl EstimateOutput {
 }
 
 impl EstimatePredictionOutput {
-    /// Estimates user distance.
+    /// Returns the smoothed bounding box, or the raw one if the prediction
+    /// wasn't tracked.
+    #[must_use]
+    pub fn smoothed_bbox(&self) -> &Rectangle {
+        self.smoothed.as_ref().map_or(&self.bbox.coordinates, |smoothed| &smoothed.bbox)
+    }
+
+    /// Returns the smoothed landmarks, or the raw ones if the prediction wasn't
+    /// tracked.
+    #[must_use]
+    pub fn smoothed_landmarks(&self) -> &EstimatePredictionLandmarksOutput {
+        self.smoothed.as_ref().map_or(&self.landmarks, |smoothed| &smoothed.landmarks)
+    }
+
+    /// Estimates user distance from the smoothed landmarks.
     #[must_use]
     pub fn user_distance(&self) -> f64 {
         // TODO(valff) this value got old and is no longer precise, should be re-measured
         const ALPHA_RGB_CAMERA: f64 = 40.0;
-        let delta_x = (self.landmarks.left_eye.x - self.landmarks.right_eye.x).abs();
-        let delta_y = (self.landmarks.left_eye.y - self.landmarks.right_eye.y).abs();
+        let landmarks = self.smoothed_landmarks();
+        let delta_x = (landmarks.left_eye.x - landmarks.right_eye.x).abs();
+        let delta_y = (landmarks.left_eye.y - landmarks.right_eye.y).abs();
         let iris_distance_in_percent = (delta_x.powi(2) + delta_y.powi(2)).sqrt();
         ALPHA_RGB_CAMERA / iris_distance_in_percent
     }
@@ -317,6 +372,8 @@ pub fn extract(estimation: &PyAny) -> Result<EstimateOutput> {
                 right_eye: extract_point(get_item!(rgbnet_landmarks, "right_eye")?)?,
                 right_mouth: extract_point(get_item!(rgbnet_landmarks, "right_mouth")?)?,
             },
+            track_id: None,
+            smoothed: None,
         });
     }
     Ok(EstimateOutput { rgbnet_version, predictions })
@@ -342,6 +399,6 @@ pub fn estimate_once(py: Python, frame: &camera::rgb::Frame) -> Result<EstimateO
     let shape = (frame.height() as usize, frame.width() as usize, 3);
     let image = Array::from_shape_vec(shape, frame.as_bytes().to_vec())?;
     let image = PyArray3::from_owned_array(py, image);
-    let estimate = rgb_net.estimate(image)?;
+    let estimate = rgb_net.estimate(image, None)?;
     extract(estimate)
 }
@@ -0,0 +1,297 @@
+//! Temporal tracking of the RGB-Net predictions.
+//!
+//! RGB-Net processes each frame independently, so its predictions jitter from
+//! frame to frame, and the primary prediction can jump between people.
+//! [`Tracker`] associates the predictions of consecutive frames into tracks by
+//! the bounding box IoU and the distance between the landmark layouts, which
+//! serve as a cheap identity embedding. Each prediction gets the stable id of
+//! its track, and the exponential moving averages of its bounding box and
+//! landmarks over the track. The raw prediction is kept unchanged for the
+//! saved estimates and the debug report. A track is dropped after
+//! [`MAX_MISSED_FRAMES`] consecutive frames without a prediction.
+
+use super::{
+    EstimateOutput, EstimatePredictionLandmarksOutput, EstimatePredictionOutput,
+    EstimatePredictionSmoothedOutput, Point, Rectangle,
+};
+
+/// Minimal bounding box IoU to associate a prediction with a track.
+pub const MIN_IOU: f64 = 0.3;
+
+/// Maximal RMS distance between the landmark layouts, in bounding box units,
+/// to associate a prediction with a track.
+pub const MAX_LAYOUT_DISTANCE: f64 = 0.15;
+
+/// Weight of the new prediction in the moving average.
+pub const SMOOTHING: f64 = 0.5;
+
+/// Number of consecutive frames without a prediction before a track is
+/// dropped.
+pub const MAX_MISSED_FRAMES: u32 = 5;
+
+type Landmarks = [Point; 5];
+
+/// RGB-Net prediction tracker.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    tracks: Vec<Track>,
+    next_id: u64,
+}
+
+#[derive(Debug)]
+struct Track {
+    id: u64,
+    bbox: Rectangle,
+    landmarks: Landmarks,
+    missed: u32,
+}
+
+impl Tracker {
+    /// Assigns the track ids and the smoothed values to the predictions of the
+    /// next frame.
+    pub fn update(&mut self, estimate: &mut EstimateOutput) {
+        let mut pairs = Vec::new();
+        for (i, prediction) in estimate.predictions.iter().enumerate() {
+            for (j, track) in self.tracks.iter().enumerate() {
+                if let Some(cost) = track.cost(prediction) {
+                    pairs.push((cost, i, j));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut assigned = vec![None; estimate.predictions.len()];
+        let mut matched = vec![false; self.tracks.len()];
+        for (_, i, j) in pairs {
+            if assigned[i].is_none() && !matched[j] {
+                assigned[i] = Some(j);
+                matched[j] = true;
+            }
+        }
+        for (track, &matched) in self.tracks.iter_mut().zip(&matched) {
+            track.missed = if matched { 0 } else { track.missed + 1 };
+        }
+        for (prediction, track) in estimate.predictions.iter_mut().zip(assigned) {
+            if let Some(j) = track {
+                self.tracks[j].smooth(prediction);
+            } else {
+                let track = Track::new(self.next_id, prediction);
+                track.assign(prediction);
+                self.tracks.push(track);
+                self.next_id += 1;
+            }
+        }
+        self.tracks.retain(|track| track.missed <= MAX_MISSED_FRAMES);
+    }
+
+    /// Drops all tracks.
+    pub fn reset(&mut self) {
+        self.tracks.clear();
+    }
+}
+
+impl Track {
+    fn new(id: u64, prediction: &EstimatePredictionOutput) -> Self {
+        Self {
+            id,
+            bbox: prediction.bbox.coordinates,
+            landmarks: landmarks(&prediction.landmarks),
+            missed: 0,
+        }
+    }
+
+    /// Returns the association cost with `prediction`, or `None` if it can't
+    /// belong to the track.
+    fn cost(&self, prediction: &EstimatePredictionOutput) -> Option<f64> {
+        let iou = iou(&self.bbox, &prediction.bbox.coordinates);
+        if iou < MIN_IOU {
+            return None;
+        }
+        let distance = layout_distance(
+            &self.bbox,
+            &self.landmarks,
+            &prediction.bbox.coordinates,
+            &landmarks(&prediction.landmarks),
+        );
+        if distance.is_some_and(|distance| distance > MAX_LAYOUT_DISTANCE) {
+            return None;
+        }
+        Some(1.0 - iou + distance.unwrap_or(0.0))
+    }
+
+    fn smooth(&mut self, prediction: &mut EstimatePredictionOutput) {
+        let bbox = &prediction.bbox.coordinates;
+        self.bbox = Rectangle {
+            start_x: ema(self.bbox.start_x, bbox.start_x),
+            start_y: ema(self.bbox.start_y, bbox.start_y),
+            end_x: ema(self.bbox.end_x, bbox.end_x),
+            end_y: ema(self.bbox.end_y, bbox.end_y),
+        };
+        for (smoothed, point) in self.landmarks.iter_mut().zip(landmarks(&prediction.landmarks)) {
+            // A missing landmark resets its average.
+            *smoothed = if is_nan(smoothed) || is_nan(&point) {
+                point
+            } else {
+                Point { x: ema(smoothed.x, point.x), y: ema(smoothed.y, point.y) }
+            };
+        }
+        self.assign(prediction);
+    }
+
+    fn assign(&self, prediction: &mut EstimatePredictionOutput) {
+        let [left_eye, left_mouth, nose, right_eye, right_mouth] = self.landmarks;
+        prediction.smoothed = Some(EstimatePredictionSmoothedOutput {
+            bbox: self.bbox,
+            landmarks: EstimatePredictionLandmarksOutput {
+                left_eye,
+                left_mouth,
+                nose,
+                right_eye,
+                right_mouth,
+            },
+        });
+        prediction.track_id = Some(self.id);
+    }
+}
+
+fn landmarks(landmarks: &EstimatePredictionLandmarksOutput) -> Landmarks {
+    let EstimatePredictionLandmarksOutput { left_eye, left_mouth, nose, right_eye, right_mouth } =
+        *landmarks;
+    [left_eye, left_mouth, nose, right_eye, right_mouth]
+}
+
+fn ema(average: f64, value: f64) -> f64 {
+    average + SMOOTHING * (value - average)
+}
+
+fn is_nan(point: &Point) -> bool {
+    point.x.is_nan() || point.y.is_nan()
+}
+
+fn area(rect: &Rectangle) -> f64 {
+    (rect.end_x - rect.start_x).max(0.0) * (rect.end_y - rect.start_y).max(0.0)
+}
+
+fn iou(a: &Rectangle, b: &Rectangle) -> f64 {
+    let width = (a.end_x.min(b.end_x) - a.start_x.max(b.start_x)).max(0.0);
+    let height = (a.end_y.min(b.end_y) - a.start_y.max(b.start_y)).max(0.0);
+    let intersection = width * height;
+    let union = area(a) + area(b) - intersection;
+    if union > 0.0 {
+        intersection / union
+    } else {
+        0.0
+    }
+}
+
+/// Returns the RMS distance between the landmarks relative to their bounding
+/// boxes, or `None` if no landmark is present in both.
+fn layout_distance(
+    a_bbox: &Rectangle,
+    a: &Landmarks,
+    b_bbox: &Rectangle,
+    b: &Landmarks,
+) -> Option<f64> {
+    let relative = |bbox: &Rectangle, point: &Point| {
+        let width = bbox.end_x - bbox.start_x;
+        let height = bbox.end_y - bbox.start_y;
+        ((point.x - bbox.start_x) / width, (point.y - bbox.start_y) / height)
+    };
+    let mut sum = 0.0;
+    let mut count = 0;
+    for (a, b) in a.iter().zip(b) {
+        let (ax, ay) = relative(a_bbox, a);
+        let (bx, by) = relative(b_bbox, b);
+        let squared = (ax - bx).powi(2) + (ay - by).powi(2);
+        if squared.is_finite() {
+            sum += squared;
+            count += 1;
+        }
+    }
+    (count > 0).then(|| (sum / f64::from(count)).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::python::rgb_net::EstimatePredictionBboxOutput;
+
+    fn prediction(x: f64, y: f64, is_primary: bool) -> EstimatePredictionOutput {
+        let point = |dx: f64, dy: f64| Point { x: x + dx, y: y + dy };
+        EstimatePredictionOutput {
+            bbox: EstimatePredictionBboxOutput {
+                coordinates: Rectangle { start_x: x, start_y: y, end_x: x + 0.2, end_y: y + 0.3 },
+                is_primary,
+                score: 0.9,
+            },
+            landmarks: EstimatePredictionLandmarksOutput {
+                left_eye: point(0.06, 0.1),
+                left_mouth: point(0.07, 0.22),
+                nose: point(0.1, 0.16),
+                right_eye: point(0.14, 0.1),
+                right_mouth: point(0.13, 0.22),
+            },
+            track_id: None,
+            smoothed: None,
+        }
+    }
+
+    fn estimate(predictions: Vec<EstimatePredictionOutput>) -> EstimateOutput {
+        EstimateOutput { rgbnet_version: String::new(), predictions }
+    }
+
+    fn track_ids(estimate: &EstimateOutput) -> Vec<Option<u64>> {
+        estimate.predictions.iter().map(|prediction| prediction.track_id).collect()
+    }
+
+    #[test]
+    fn test_jitter() {
+        let mut tracker = Tracker::default();
+        let mut first = estimate(vec![prediction(0.4, 0.3, true)]);
+        tracker.update(&mut first);
+        let mut second = estimate(vec![prediction(0.42, 0.3, true)]);
+        tracker.update(&mut second);
+        assert_eq!(track_ids(&second), track_ids(&first));
+        let prediction = &second.predictions[0];
+        assert!((prediction.smoothed_bbox().start_x - 0.41).abs() < 1e-9);
+        assert!((prediction.smoothed_landmarks().nose.x - 0.51).abs() < 1e-9);
+        assert!((prediction.bbox.coordinates.start_x - 0.42).abs() < 1e-9);
+        assert!((prediction.landmarks.nose.x - 0.52).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_identity_continuity() {
+        let mut tracker = Tracker::default();
+        let mut first = estimate(vec![prediction(0.1, 0.3, true), prediction(0.6, 0.3, false)]);
+        tracker.update(&mut first);
+        assert_eq!(track_ids(&first), [Some(0), Some(1)]);
+
+        let mut swapped = estimate(vec![prediction(0.61, 0.3, true), prediction(0.1, 0.31, false)]);
+        tracker.update(&mut swapped);
+        assert_eq!(track_ids(&swapped), [Some(1), Some(0)]);
+
+        let mut distorted = prediction(0.1, 0.3, true);
+        distorted.landmarks.nose = Point { x: 0.3, y: 0.55 };
+        let mut distorted = estimate(vec![distorted]);
+        tracker.update(&mut distorted);
+        assert_eq!(track_ids(&distorted), [Some(2)]);
+    }
+
+    #[test]
+    fn test_missed_frames() {
+        let mut tracker = Tracker::default();
+        tracker.update(&mut estimate(vec![prediction(0.4, 0.3, true)]));
+        for _ in 0..MAX_MISSED_FRAMES {
+            tracker.update(&mut estimate(Vec::new()));
+        }
+        let mut back = estimate(vec![prediction(0.4, 0.3, true)]);
+        tracker.update(&mut back);
+        assert_eq!(track_ids(&back), [Some(0)]);
+
+        for _ in 0..=MAX_MISSED_FRAMES {
+            tracker.update(&mut estimate(Vec::new()));
+        }
+        let mut lost = estimate(vec![prediction(0.4, 0.3, true)]);
+        tracker.update(&mut lost);
+        assert_eq!(track_ids(&lost), [Some(1)]);
+    }
+}
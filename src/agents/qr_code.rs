@@ -1,7 +1,12 @@
 //! QR-code reader from the RGB camera.
+//!
+//! Frames which fail the single-frame decoding are accumulated for the
+//! [burst decoding](burst).
 
 #![allow(clippy::used_underscore_binding)] // triggered by rkyv
 
+pub mod burst;
+
 use crate::{
     agents::{camera::rgb::Frame, ProcessInitializer},
     consts::{RGB_NATIVE_HEIGHT, RGB_NATIVE_WIDTH},
@@ -10,8 +15,9 @@ use agentwire::{
     agent,
     port::{Port, RemoteInner, SharedPort},
 };
+use burst::{Burst, Luma};
 use eyre::{Error, Result};
-use image::{DynamicImage, RgbImage};
+use image::{DynamicImage, GrayImage, RgbImage};
 use rkyv::{Archive, Deserialize, Serialize};
 use rxing::{
     common::HybridBinarizer, qrcode::cpp_port::QrReader, BinaryBitmap,
@@ -23,7 +29,11 @@ use std::mem::size_of;
 ///
 /// See [the module-level documentation](self) for details.
 #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
-pub struct Agent {}
+pub struct Agent {
+    /// Number of frames merged by the burst decoding. Values below 2 disable
+    /// the burst decoding.
+    pub burst_frames: u8,
+}
 
 /// Qr-code reader output.
 #[derive(Debug, Archive, Serialize, Deserialize)]
@@ -71,16 +81,27 @@ impl agentwire::agent::Process for Agent {
 
     fn run(self, mut port: RemoteInner<Self>) -> Result<(), Self::Error> {
         let mut qr_scanner = QrReader;
+        let mut burst = Burst::new(self.burst_frames.into());
         loop {
             let input = port.recv();
             match input.value {
                 ArchivedInput::Frame(frame) => {
-                    match decode_rxing(
-                        &mut qr_scanner,
-                        frame.data().to_vec(),
-                        frame.width(),
-                        frame.height(),
-                    ) {
+                    let image = DynamicImage::ImageRgb8(
+                        RgbImage::from_vec(frame.width(), frame.height(), frame.data().to_vec())
+                            .expect("image size to be at least 3*width*height"),
+                    );
+                    let mut result =
+                        decode_rxing(&mut qr_scanner, image, frame.width(), frame.height());
+                    if result.is_ok() {
+                        burst.clear();
+                    } else if let Some(merged) = burst.push(Luma::from_rgb(
+                        frame.data(),
+                        frame.width() as usize,
+                        frame.height() as usize,
+                    )) {
+                        result = decode_burst(&mut qr_scanner, merged);
+                    }
+                    match result {
                         Ok(output) => {
                             tracing::debug!("Decoded QR-code with rxing: {:?}", output.payload);
                             let chain = input.chain_fn();
@@ -109,19 +130,26 @@ impl agentwire::agent::Process for Agent {
     }
 }
 
+#[allow(clippy::cast_possible_truncation)]
+fn decode_burst(qr_scanner: &mut QrReader, merged: Luma) -> Result<Output, rxing::Exceptions> {
+    let Luma { data, width, height } = merged;
+    let image = GrayImage::from_vec(width as u32, height as u32, data)
+        .expect("image size to be at least width*height");
+    let output =
+        decode_rxing(qr_scanner, DynamicImage::ImageLuma8(image), width as u32, height as u32)?;
+    tracing::info!("Decoded QR-code from a burst of frames");
+    Ok(output)
+}
+
 #[allow(clippy::cast_precision_loss)]
 fn decode_rxing(
     qr_scanner: &mut QrReader,
-    image: Vec<u8>,
+    image: DynamicImage,
     width: u32,
     height: u32,
 ) -> Result<Output, rxing::Exceptions> {
-    let mut binarized_image = BinaryBitmap::new(HybridBinarizer::new(
-        BufferedImageLuminanceSource::new(DynamicImage::ImageRgb8(
-            RgbImage::from_vec(width, height, image)
-                .expect("image size to be at least 3*width*height"),
-        )),
-    ));
+    let mut binarized_image =
+        BinaryBitmap::new(HybridBinarizer::new(BufferedImageLuminanceSource::new(image)));
     let rxing_result = qr_scanner.decode(&mut binarized_image)?;
     Ok(Output {
         payload: rxing_result.getText().to_owned(),
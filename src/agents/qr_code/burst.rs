@@ -0,0 +1,238 @@
+//! Burst multi-frame QR-code decoding.
+//!
+//! Phone screens in low light or with scratches are often too noisy to decode
+//! from a single frame. When the single-frame decoding fails, the frames are
+//! accumulated into a burst. Once the burst is full, the frames are aligned to
+//! the latest one with a sub-pixel translation estimate, and merged by
+//! shift-and-add onto a grid of twice the resolution. The merged image has a
+//! lower noise level and recovers some of the detail lost to the sensor
+//! sampling, without raising the exposure.
+
+use crate::consts::{QR_BURST_MAX_SHIFT, RGB_REDUCED_HEIGHT, RGB_REDUCED_WIDTH};
+use std::collections::VecDeque;
+
+/// Distance in pixels between the samples used for the alignment.
+const ALIGNMENT_SAMPLE_STEP: usize = 4;
+
+/// Super-resolution factor of the merged image.
+const UPSCALE: usize = 2;
+
+/// Grayscale image.
+#[derive(Clone, Debug)]
+pub struct Luma {
+    /// Pixel values, row by row.
+    pub data: Vec<u8>,
+    /// Width in pixels.
+    pub width: usize,
+    /// Height in pixels.
+    pub height: usize,
+}
+
+/// Burst frames accumulator.
+#[derive(Debug)]
+pub struct Burst {
+    len: usize,
+    frames: VecDeque<Luma>,
+}
+
+impl Luma {
+    /// Converts an RGB frame to grayscale, downscaling it with a box filter to
+    /// fit into the reduced RGB resolution.
+    #[must_use]
+    pub fn from_rgb(rgb: &[u8], width: usize, height: usize) -> Self {
+        let factor = width
+            .div_ceil(RGB_REDUCED_WIDTH as usize)
+            .max(height.div_ceil(RGB_REDUCED_HEIGHT as usize))
+            .max(1);
+        let (out_width, out_height) = (width / factor, height / factor);
+        let mut data = Vec::with_capacity(out_width * out_height);
+        for y in 0..out_height {
+            for x in 0..out_width {
+                let mut sum = 0;
+                for sy in y * factor..(y + 1) * factor {
+                    for sx in x * factor..(x + 1) * factor {
+                        let i = (sy * width + sx) * 3;
+                        sum += 77 * u32::from(rgb[i])
+                            + 150 * u32::from(rgb[i + 1])
+                            + 29 * u32::from(rgb[i + 2]);
+                    }
+                }
+                #[allow(clippy::cast_possible_truncation)]
+                data.push((sum / (256 * (factor * factor) as u32)) as u8);
+            }
+        }
+        Self { data, width: out_width, height: out_height }
+    }
+
+    fn get(&self, x: isize, y: isize) -> Option<u8> {
+        let x = usize::try_from(x).ok().filter(|&x| x < self.width)?;
+        let y = usize::try_from(y).ok().filter(|&y| y < self.height)?;
+        Some(self.data[y * self.width + x])
+    }
+}
+
+impl Burst {
+    /// Creates a new accumulator merging `len` frames.
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        Self { len, frames: VecDeque::with_capacity(len) }
+    }
+
+    /// Adds a frame which failed the single-frame decoding. Returns the merged
+    /// image once the burst is full, and starts a new burst.
+    pub fn push(&mut self, frame: Luma) -> Option<Luma> {
+        if self.len < 2 {
+            return None;
+        }
+        if self
+            .frames
+            .back()
+            .is_some_and(|last| (last.width, last.height) != (frame.width, frame.height))
+        {
+            self.frames.clear();
+        }
+        self.frames.push_back(frame);
+        if self.frames.len() < self.len {
+            return None;
+        }
+        let merged = merge(self.frames.make_contiguous());
+        self.frames.clear();
+        Some(merged)
+    }
+
+    /// Drops the accumulated frames.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+/// Aligns the frames to the last one and merges them onto an upscaled grid.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss, clippy::cast_sign_loss)]
+fn merge(frames: &[Luma]) -> Luma {
+    let reference = frames.last().expect("burst to be non-empty");
+    let (width, height) = (reference.width * UPSCALE, reference.height * UPSCALE);
+    let mut sum = vec![0.0_f32; width * height];
+    let mut weight = vec![0_u16; width * height];
+    for frame in frames {
+        let (dx, dy) = estimate_shift(reference, frame);
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                // Position of the frame pixel in the reference frame.
+                let rx = ((x as f64 - dx) * UPSCALE as f64).round();
+                let ry = ((y as f64 - dy) * UPSCALE as f64).round();
+                if rx < 0.0 || ry < 0.0 || rx >= width as f64 || ry >= height as f64 {
+                    continue;
+                }
+                let i = ry as usize * width + rx as usize;
+                sum[i] += f32::from(frame.data[y * frame.width + x]);
+                weight[i] += 1;
+            }
+        }
+    }
+    let data = (0..width * height)
+        .map(|i| {
+            if weight[i] > 0 {
+                (sum[i] / f32::from(weight[i])).round() as u8
+            } else {
+                // Holes are filled from the nearest reference pixel.
+                let (x, y) = (i % width / UPSCALE, i / width / UPSCALE);
+                reference.data[y * reference.width + x]
+            }
+        })
+        .collect();
+    Luma { data, width, height }
+}
+
+/// Estimates the sub-pixel translation `(dx, dy)` such that `frame` at
+/// `(x + dx, y + dy)` matches `reference` at `(x, y)`.
+#[allow(clippy::cast_possible_wrap, clippy::cast_precision_loss, clippy::cast_sign_loss)]
+fn estimate_shift(reference: &Luma, frame: &Luma) -> (f64, f64) {
+    let max_shift = QR_BURST_MAX_SHIFT as isize;
+    let size = (2 * max_shift + 1) as usize;
+    let mut costs = vec![f64::INFINITY; size * size];
+    for dy in -max_shift..=max_shift {
+        for dx in -max_shift..=max_shift {
+            let (mut total, mut count) = (0_u64, 0_u64);
+            for y in (0..reference.height).step_by(ALIGNMENT_SAMPLE_STEP) {
+                for x in (0..reference.width).step_by(ALIGNMENT_SAMPLE_STEP) {
+                    let Some(value) = frame.get(x as isize + dx, y as isize + dy) else {
+                        continue;
+                    };
+                    let expected = reference.data[y * reference.width + x];
+                    total += u64::from(value.abs_diff(expected));
+                    count += 1;
+                }
+            }
+            if count > 0 {
+                let i = (dy + max_shift) as usize * size + (dx + max_shift) as usize;
+                costs[i] = total as f64 / count as f64;
+            }
+        }
+    }
+    let best =
+        costs.iter().enumerate().min_by(|(_, a), (_, b)| a.total_cmp(b)).map_or(0, |(i, _)| i);
+    let (bx, by) = (best % size, best / size);
+    let cost = |x: usize, y: usize| costs[y * size + x];
+    let refine = |before: Option<f64>, center: f64, after: Option<f64>| {
+        let (Some(before), Some(after)) = (before, after) else {
+            return 0.0;
+        };
+        let curvature = before - 2.0 * center + after;
+        if curvature.is_finite() && curvature > 0.0 {
+            ((before - after) / (2.0 * curvature)).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        }
+    };
+    let center = cost(bx, by);
+    let sub_x = refine(
+        bx.checked_sub(1).map(|x| cost(x, by)),
+        center,
+        (bx + 1 < size).then(|| cost(bx + 1, by)),
+    );
+    let sub_y = refine(
+        by.checked_sub(1).map(|y| cost(bx, y)),
+        center,
+        (by + 1 < size).then(|| cost(bx, by + 1)),
+    );
+    (bx as f64 - max_shift as f64 + sub_x, by as f64 - max_shift as f64 + sub_y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(width: usize, height: usize, dx: usize, dy: usize) -> Luma {
+        let data = (0..height)
+            .flat_map(|y| {
+                (0..width).map(move |x| {
+                    let (x, y) = (x + dx, y + dy);
+                    if (x / 7 + y / 5) % 2 == 0 && (x * y) % 11 != 0 { 220 } else { 30 }
+                })
+            })
+            .collect();
+        Luma { data, width, height }
+    }
+
+    #[test]
+    fn test_estimate_shift() {
+        let reference = pattern(120, 90, 0, 0);
+        let (dx, dy) = estimate_shift(&reference, &pattern(120, 90, 0, 0));
+        assert!(dx.abs() < 0.5 && dy.abs() < 0.5, "{dx} {dy}");
+        // The shifted frame at (x - 3, y - 2) matches the reference at (x, y).
+        let (dx, dy) = estimate_shift(&reference, &pattern(120, 90, 3, 2));
+        assert!((dx + 3.0).abs() < 0.5 && (dy + 2.0).abs() < 0.5, "{dx} {dy}");
+    }
+
+    #[test]
+    fn test_burst() {
+        let mut burst = Burst::new(3);
+        assert!(burst.push(pattern(120, 90, 0, 0)).is_none());
+        assert!(burst.push(pattern(120, 90, 1, 0)).is_none());
+        let merged = burst.push(pattern(120, 90, 0, 1)).unwrap();
+        assert_eq!((merged.width, merged.height), (240, 180));
+        assert_eq!(merged.data.len(), 240 * 180);
+        assert!(burst.push(pattern(120, 90, 0, 0)).is_none());
+        assert!(Burst::new(1).push(pattern(120, 90, 0, 0)).is_none());
+    }
+}
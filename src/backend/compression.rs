@@ -0,0 +1,123 @@
+//! Request body compression.
+//!
+//! Presigned object storage uploads are compressed with gzip, because the
+//! storage keeps the body as is along with its `Content-Encoding`, and the
+//! consumers decode it on download. Object storage doesn't advertise request
+//! encodings, so there is no negotiation. If the storage rejects the encoding
+//! with `415 Unsupported Media Type`, the request is retried once without
+//! compression.
+
+use crate::{
+    dd_count, dd_gauge, dd_timing,
+    monitor::traffic::{self, Class},
+};
+use eyre::Result;
+use flate2::{write::GzEncoder, Compression};
+use reqwest::{header::CONTENT_ENCODING, RequestBuilder, Response, StatusCode};
+use std::{io::Write, time::SystemTime};
+
+/// Request body content encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// No compression.
+    Identity,
+    /// Gzip compression.
+    Gzip,
+}
+
+impl Encoding {
+    /// Returns the `Content-Encoding` header value, or `None` for
+    /// [`Encoding::Identity`].
+    #[must_use]
+    pub fn content_encoding(self) -> Option<&'static str> {
+        match self {
+            Self::Identity => None,
+            Self::Gzip => Some("gzip"),
+        }
+    }
+
+    /// Compresses `data`.
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Identity => Ok(data.to_vec()),
+            Self::Gzip => {
+                let mut compressed = Vec::new();
+                let mut encoder = GzEncoder::new(&mut compressed, Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()?;
+                Ok(compressed)
+            }
+        }
+    }
+}
+
+/// Sends `body` compressed with `encoding` in a request built by `request`.
+/// The `endpoint` name tags the compression metrics.
+pub async fn send(
+    class: Class,
+    endpoint: &str,
+    body: &[u8],
+    mut encoding: Encoding,
+    request: impl Fn() -> Result<RequestBuilder>,
+) -> Result<Response> {
+    loop {
+        let mut request = request()?;
+        if let Some(content_encoding) = encoding.content_encoding() {
+            request = request.header(CONTENT_ENCODING, content_encoding);
+        }
+        let request = request.body(compress(endpoint, encoding, body)?);
+        let response = traffic::send(class, request).await?;
+        if response.status() == StatusCode::UNSUPPORTED_MEDIA_TYPE && encoding != Encoding::Identity
+        {
+            tracing::warn!("{endpoint} rejected {encoding:?} request body, retrying");
+            encoding = Encoding::Identity;
+            continue;
+        }
+        return Ok(response);
+    }
+}
+
+fn compress(endpoint: &str, encoding: Encoding, body: &[u8]) -> Result<Vec<u8>> {
+    let t = SystemTime::now();
+    let compressed = encoding.compress(body)?;
+    let endpoint = format!("endpoint:{endpoint}");
+    let encoding = format!("encoding:{}", encoding.content_encoding().unwrap_or("identity"));
+    dd_timing!("main.time.http.request_compression", t, &endpoint, &encoding);
+    let saved = body.len().saturating_sub(compressed.len());
+    dd_count!(
+        "main.count.http.request_compression_saved_bytes",
+        i64::try_from(saved).unwrap_or(i64::MAX),
+        &endpoint,
+        &encoding
+    );
+    if !body.is_empty() {
+        #[allow(clippy::cast_precision_loss)]
+        let ratio = compressed.len() as f64 / body.len() as f64;
+        dd_gauge!(
+            "main.gauge.http.request_compression_ratio",
+            ratio.to_string(),
+            &endpoint,
+            &encoding
+        );
+    }
+    Ok(compressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_compress() {
+        let data = br#"{"signup_id":"0123456789abcdef"}"#.repeat(100);
+        assert_eq!(Encoding::Identity.compress(&data).unwrap(), data);
+
+        let gzip = Encoding::Gzip.compress(&data).unwrap();
+        assert!(gzip.len() < data.len());
+        let mut decoded = Vec::new();
+        GzDecoder::new(gzip.as_slice()).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+}
@@ -1,14 +1,24 @@
 //! Orb config endpoint.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
 use crate::{
-    agents::python::face_identifier,
-    backend::endpoints::MANAGEMENT_BACKEND_URL,
-    identification::{get_orb_token, ORB_ID},
+    agents::{
+        camera::thermal,
+        eye_pid_controller,
+        python::{face_identifier, ir_net},
+    },
+    config::impact,
+    debug_report,
+    identification::ORB_ID,
+    mcu,
     plans::fraud_check,
+    ui,
 };
-use eyre::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use eyre::{eyre, Result};
+use reqwest::Method;
+use ring::signature::{UnparsedPublicKey, ED25519};
 use serde::Deserialize;
 
 /// Response of the orb config endpoint.
@@ -39,6 +49,7 @@ pub struct Config {
     #[serde(flatten)]
     pub fraud_check_engine_config: fraud_check::BackendConfig,
     pub ir_net_model_configs: Option<HashMap<String, String>>,
+    pub ir_net_score_calibrations: Option<HashMap<String, ir_net::ScoreCalibration>>,
     pub iris_model_configs: Option<HashMap<String, String>>,
     pub child_threshold: Option<f32>,
     #[serde(flatten)]
@@ -46,6 +57,11 @@ pub struct Config {
     /// In milliseconds
     pub thermal_camera_pairing_status_timeout: Option<u64>,
     pub thermal_camera: Option<bool>,
+    pub thermal_camera_pipeline_mode: Option<thermal::PipelineMode>,
+    pub thermal_camera_agc_mode: Option<thermal::AgcMode>,
+    pub thermal_camera_color_palette: Option<thermal::ColorPalette>,
+    pub thermal_camera_gradient_correction: Option<bool>,
+    pub thermal_camera_flat_scene_correction: Option<bool>,
     pub depth_camera: Option<bool>,
     pub self_serve: Option<bool>,
     pub self_serve_button: Option<bool>,
@@ -54,9 +70,21 @@ pub struct Config {
     pub self_serve_app_skip_capture_trigger: Option<bool>,
     pub self_serve_app_capture_trigger_timeout: Option<u64>,
     pub self_serve_biometric_capture_timeout: Option<u64>,
+    pub self_serve_user_queue_size: Option<u32>,
+    /// In milliseconds
+    pub self_serve_user_queue_expiration: Option<u64>,
+    /// In milliseconds
+    pub self_serve_user_queue_scan_window: Option<u64>,
+    pub self_serve_queue_indicator: Option<bool>,
+    pub signup_rate_limit_max_failures: Option<u32>,
+    /// In milliseconds
+    pub signup_rate_limit_window: Option<u64>,
+    /// In milliseconds
+    pub signup_rate_limit_cooldown: Option<u64>,
     pub mirror_default_phi_offset_degrees: Option<f64>,
     pub mirror_default_theta_offset_degrees: Option<f64>,
     pub process_agent_logger_pruning: Option<bool>,
+    pub broker_message_sample_rate: Option<f64>,
     pub backend_http_request_timeout: Option<u64>,
     pub backend_http_connect_timeout: Option<u64>,
     pub pcp_v3: Option<bool>,
@@ -72,19 +100,159 @@ pub struct Config {
     pub orb_relay_announce_orb_id_retries: Option<u32>,
     pub orb_relay_announce_orb_id_timeout: Option<u64>,
     pub operator_qr_expiration_time: Option<u64>,
+    /// In milliseconds
+    pub identification_images_max_age: Option<u64>,
+    /// In bytes
+    pub identification_images_max_size: Option<u64>,
+    /// In milliseconds
+    pub data_acquisition_images_max_age: Option<u64>,
+    /// In bytes
+    pub data_acquisition_images_max_size: Option<u64>,
+    /// In milliseconds
+    pub debug_images_max_age: Option<u64>,
+    /// In bytes
+    pub debug_images_max_size: Option<u64>,
+    /// In milliseconds
+    pub data_acquisition_compaction_age: Option<u64>,
+    pub data_acquisition_compaction_max_cpu_load: Option<f64>,
+    /// Names of the disabled subsystems
+    pub kill_switches: Option<Vec<String>>,
+    pub biometric_capture_early_exit: Option<bool>,
+    pub biometric_capture_early_exit_score: Option<f64>,
+    pub biometric_capture_early_exit_sharpness: Option<f64>,
+    /// In milliseconds
+    pub biometric_capture_early_exit_min_dwell: Option<u64>,
+    pub biometric_capture_bilateral: Option<bool>,
+    /// Python agent name to CUDA stream priority
+    pub gpu_stream_priorities: Option<HashMap<String, i32>>,
+    pub orb_display_name: Option<String>,
+    pub venue_tag: Option<String>,
+    pub venue_cache_url: Option<String>,
+    /// In minutes after the venue local midnight
+    pub night_mode_start: Option<u16>,
+    /// In minutes after the venue local midnight
+    pub night_mode_end: Option<u16>,
+    /// In minutes
+    pub night_mode_utc_offset: Option<i16>,
+    pub night_mode_brightness_cap: Option<f64>,
+    pub night_mode_flash_intensity: Option<f64>,
+    pub night_mode_animation_speed: Option<f64>,
+    pub ui_themes: Option<Vec<ui::theme::Rule>>,
+    /// Enables the deterministic biometric pipeline with this seed
+    pub pipeline_deterministic_seed: Option<u64>,
+    /// In minutes after the venue local midnight
+    pub reboot_window_start: Option<u16>,
+    /// In minutes after the venue local midnight
+    pub reboot_window_end: Option<u16>,
+    /// In minutes
+    pub reboot_window_utc_offset: Option<i16>,
+    /// In milliseconds
+    pub reboot_min_uptime: Option<u64>,
+    /// In milliseconds
+    pub thermal_precool_lead_time: Option<u64>,
+    /// Number of frames merged by the burst QR code decoding
+    pub qr_burst_frames: Option<u8>,
+    pub mcu_led_transport: Option<mcu::Transport>,
+    pub mcu_control_transport: Option<mcu::Transport>,
+    pub mcu_led_ack_policy: Option<McuAckPolicy>,
+    pub mcu_query_ack_policy: Option<McuAckPolicy>,
+    pub mcu_motion_ack_policy: Option<McuAckPolicy>,
+    pub mcu_control_ack_policy: Option<McuAckPolicy>,
+    pub mcu_uart_device: Option<String>,
+    pub silent_confirmation: Option<ui::haptics::Mode>,
+    pub deep_debug_sample_rate: Option<f64>,
+    pub deep_debug_artifacts: Option<Vec<debug_report::artifacts::Kind>>,
+    /// In bytes per day
+    pub traffic_budget_uploader: Option<u64>,
+    /// In bytes per day
+    pub traffic_budget_livestream: Option<u64>,
+    /// In bytes per day
+    pub traffic_budget_uploader_wifi: Option<u64>,
+    /// In bytes per day
+    pub traffic_budget_livestream_wifi: Option<u64>,
+    /// Sorted by the distance in millimeters
+    pub eye_pid_gain_schedule: Option<Vec<eye_pid_controller::Gains>>,
+    pub config_rollout: Option<impact::Rollout>,
     pub last_updated: u64,
+    /// Ed25519 signature of the orb ID and the last update time, base64
+    /// encoded
+    pub last_updated_signature: Option<String>,
+}
+
+impl Config {
+    /// Verifies the backend signature of the last update time, which serves as
+    /// the anti-rollback config epoch.
+    pub fn verify_last_updated(&self, public_key: &[u8], orb_id: &str) -> Result<()> {
+        let signature = STANDARD.decode(
+            self.last_updated_signature.as_deref().ok_or_else(|| eyre!("missing signature"))?,
+        )?;
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(format!("{orb_id}\n{}", self.last_updated).as_bytes(), &signature)
+            .map_err(|_| eyre!("config epoch signature verification failed"))
+    }
 }
 
-/// Makes an orb config request.
-pub async fn request() -> Result<Response> {
-    let request = super::client()?
-        .get(format!("{}/api/v1/orbs/{}", *MANAGEMENT_BACKEND_URL, *ORB_ID))
-        .basic_auth(&*ORB_ID, Some(get_orb_token()?));
-    match request.send().await?.error_for_status() {
-        Ok(response) => Ok(response.json().await?),
-        Err(err) => {
-            tracing::error!("Received error response {:?}", err);
-            Err(err.into())
+/// Override of an MCU message class acknowledge policy. See
+/// [`AckPolicy`](mcu::AckPolicy) for individual field docs.
+#[allow(missing_docs)]
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "PascalCase")]
+pub struct McuAckPolicy {
+    /// In milliseconds
+    pub timeout: Option<u64>,
+    pub retries: Option<u8>,
+    /// In milliseconds
+    pub backoff: Option<u64>,
+}
+
+impl McuAckPolicy {
+    /// Overrides the `policy` fields set by the backend.
+    #[must_use]
+    pub fn apply(self, policy: mcu::AckPolicy) -> mcu::AckPolicy {
+        mcu::AckPolicy {
+            timeout: self.timeout.map_or(policy.timeout, Duration::from_millis),
+            retries: self.retries.unwrap_or(policy.retries),
+            backoff: self.backoff.map_or(policy.backoff, Duration::from_millis),
         }
     }
 }
+
+/// Makes an orb config request. The current anti-rollback config `epoch`, if
+/// known, is reported to the backend, so that a reverted config can be
+/// re-published with a newer signed epoch instead of being rejected.
+pub async fn request(epoch: Option<u64>) -> Result<Response> {
+    let mut path = format!("/api/v1/orbs/{}", *ORB_ID);
+    if let Some(epoch) = epoch {
+        path.push_str(&format!("?configEpoch={epoch}"));
+    }
+    super::venue_cache::request(Method::GET, &path, None).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+
+    #[test]
+    fn test_verify_last_updated() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let public_key = key_pair.public_key().as_ref();
+        let signature = STANDARD.encode(key_pair.sign(b"abc\n1700000000"));
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "SoundVolume": 10,
+            "LastUpdated": 1_700_000_000,
+            "LastUpdatedSignature": signature,
+        }))
+        .unwrap();
+        assert!(config.verify_last_updated(public_key, "abc").is_ok());
+        assert!(config.verify_last_updated(public_key, "def").is_err());
+        let replayed = Config { last_updated: 1_800_000_000, ..config };
+        assert!(replayed.verify_last_updated(public_key, "abc").is_err());
+        let unsigned = Config { last_updated_signature: None, ..replayed };
+        assert!(unsigned.verify_last_updated(public_key, "abc").is_err());
+    }
+}
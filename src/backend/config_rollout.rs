@@ -0,0 +1,34 @@
+//! Config rollout acknowledgement endpoint.
+
+use crate::{
+    backend::endpoints::MANAGEMENT_BACKEND_URL,
+    identification::{get_orb_token, ORB_ID},
+    monitor::traffic::{self, Class},
+};
+use eyre::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Acknowledgement of the configuration diff of a staged rollout.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    /// Rollout identifier.
+    pub rollout_id: String,
+    /// Serialized names of the changed parameters by domain name.
+    pub changes: BTreeMap<&'static str, Vec<String>>,
+}
+
+/// Acknowledges the dry run of a config rollout.
+pub async fn request(request: &Request) -> Result<()> {
+    let request = super::client()?
+        .post(format!(
+            "{}/api/v1/orbs/{}/config/rollouts/{}/ack",
+            *MANAGEMENT_BACKEND_URL, *ORB_ID, request.rollout_id
+        ))
+        .basic_auth(&*ORB_ID, Some(get_orb_token()?))
+        .json(request);
+    let response = traffic::send(Class::Telemetry, request).await?;
+    response.error_for_status_ref()?;
+    Ok(())
+}
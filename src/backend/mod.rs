@@ -1,25 +1,46 @@
 //! Communication with the Orb backend.
 
+pub mod compression;
 pub mod config;
+pub mod config_rollout;
 pub mod endpoints;
 pub mod operator_status;
 pub mod orb_os_status;
 pub mod presigned_url;
+pub mod s3;
 pub mod s3_region;
 pub mod signup_poll;
 pub mod signup_post;
+pub mod signup_receipt;
 pub mod status;
+pub mod upload_crash_report;
 pub mod upload_debug_report;
 pub mod upload_image;
 pub mod upload_personal_custody_package;
 pub mod user_status;
+pub mod venue_cache;
 
-use eyre::Error;
-use std::time::Duration;
+use crate::consts::BACKEND_RESPONSE_SIGNING_PUBKEY_PATH;
+use eyre::{eyre, Error, Result};
+use once_cell::sync::Lazy;
+use std::{fs, time::Duration};
 const APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
 
+static RESPONSE_SIGNING_PUBKEY: Lazy<Option<[u8; 32]>> = Lazy::new(load_response_signing_pubkey);
+
+/// Returns the Ed25519 public key the backend signs the responses with.
+///
+/// # Errors
+///
+/// If the key is missing or invalid.
+pub fn response_signing_pubkey() -> Result<&'static [u8; 32]> {
+    RESPONSE_SIGNING_PUBKEY
+        .as_ref()
+        .ok_or_else(|| eyre!("backend response signing key is not provisioned"))
+}
+
 /// Creates a new HTTPS client.
 pub fn client() -> reqwest::Result<reqwest::Client> {
     client_with_timeouts(REQUEST_TIMEOUT, CONNECT_TIMEOUT)
@@ -46,3 +67,17 @@ pub fn log_decoding_error(err: &Error) {
     };
     tracing::error!("{log_msg}: {err:?}", log_msg = log_msg, err = err);
 }
+
+fn load_response_signing_pubkey() -> Option<[u8; 32]> {
+    match fs::read(BACKEND_RESPONSE_SIGNING_PUBKEY_PATH).map(<[u8; 32]>::try_from) {
+        Ok(Ok(key)) => Some(key),
+        Ok(Err(key)) => {
+            tracing::error!("Backend response signing key has invalid length: {}", key.len());
+            None
+        }
+        Err(err) => {
+            tracing::error!("Couldn't read the backend response signing key: {err}");
+            None
+        }
+    }
+}
@@ -3,13 +3,14 @@
 use crate::{
     backend::endpoints::SIGNUP_BACKEND_URL,
     identification::{get_orb_token, ORB_ID},
+    monitor::traffic::{self, Class},
     plans::qr_scan,
 };
 use eyre::Result;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Coordinates.
-#[derive(Deserialize, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Coordinates {
     /// Latitude.
@@ -18,8 +19,21 @@ pub struct Coordinates {
     pub longitude: f64,
 }
 
+impl Coordinates {
+    /// Returns the great-circle distance to `other` in meters.
+    #[must_use]
+    pub fn distance(&self, other: &Self) -> f64 {
+        const EARTH_RADIUS: f64 = 6_371_000.0;
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.longitude - self.longitude).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+}
+
 /// Location data of the operator.
-#[derive(Deserialize, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct LocationData {
     /// The operator's team country.
@@ -42,6 +56,71 @@ pub struct Status {
     pub reason: Option<String>,
 }
 
+/// Source of the coordinates of a location session.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LocationSource {
+    /// The operator's expected stationary location.
+    Stationary,
+    /// The orb's GPS fix.
+    Gps,
+}
+
+/// Location session registered in the backend. Signup requests refer to the
+/// session instead of carrying the coordinates.
+#[derive(Debug, Clone)]
+pub struct LocationSession {
+    /// Session handle returned by the backend.
+    pub id: String,
+    /// Registered coordinates.
+    pub coordinates: Coordinates,
+    /// Source of the registered coordinates.
+    pub source: LocationSource,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LocationSessionRequest<'a> {
+    coordinates: &'a Coordinates,
+    source: LocationSource,
+    team_operating_country: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LocationSessionResponse {
+    session_id: String,
+}
+
+/// Registers the location of a stationary orb for the operator.
+pub async fn register_location_session(
+    qr_code: &qr_scan::user::Data,
+    location_data: &LocationData,
+    coordinates: Coordinates,
+    source: LocationSource,
+) -> Result<LocationSession> {
+    let request = super::client()?
+        .post(format!(
+            "{}/api/v1/distributor/{}/orb/{}/location-session",
+            *SIGNUP_BACKEND_URL, qr_code.user_id, *ORB_ID
+        ))
+        .basic_auth(&*ORB_ID, Some(get_orb_token()?))
+        .json(&LocationSessionRequest {
+            coordinates: &coordinates,
+            source,
+            team_operating_country: &location_data.team_operating_country,
+        });
+    let LocationSessionResponse { session_id } =
+        match traffic::send(Class::Control, request).await?.error_for_status() {
+            Ok(response) => response.json().await?,
+            Err(err) => {
+                tracing::error!("Received error response {err:?}");
+                return Err(err.into());
+            }
+        };
+    Ok(LocationSession { id: session_id, coordinates, source })
+}
+
 /// Makes a validation request.
 pub async fn request(qr_code: &qr_scan::user::Data) -> Result<Status> {
     let request = super::client()?
@@ -50,7 +129,7 @@ pub async fn request(qr_code: &qr_scan::user::Data) -> Result<Status> {
             *SIGNUP_BACKEND_URL, qr_code.user_id, *ORB_ID
         ))
         .basic_auth(&*ORB_ID, Some(get_orb_token()?));
-    let status: Status = match request.send().await?.error_for_status() {
+    let status: Status = match traffic::send(Class::Control, request).await?.error_for_status() {
         Ok(response) => response.json().await?,
         Err(err) => {
             tracing::error!("Received error response {err:?}");
@@ -74,3 +153,16 @@ pub async fn request(qr_code: &qr_scan::user::Data) -> Result<Status> {
     }
     Ok(status)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coordinates_distance() {
+        let berlin = Coordinates { latitude: 52.5200, longitude: 13.4050 };
+        let munich = Coordinates { latitude: 48.1351, longitude: 11.5820 };
+        assert!((berlin.distance(&munich) - 504_000.0).abs() < 1_000.0);
+        assert!(berlin.distance(&berlin).abs() < f64::EPSILON);
+    }
+}
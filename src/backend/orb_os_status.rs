@@ -1,8 +1,8 @@
 //! Orb OS version validation endpoint.
 
-use super::endpoints::MANAGEMENT_BACKEND_URL;
-use crate::identification::{get_orb_token, ORB_ID, ORB_OS_VERSION};
+use crate::identification::{ORB_ID, ORB_OS_VERSION};
 use eyre::Result;
+use reqwest::Method;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize)]
@@ -45,13 +45,6 @@ pub async fn request() -> Result<OrbOsVersionCheckResponse> {
         orb_os_version: ORB_OS_VERSION.to_string(),
     };
 
-    let response = super::client()?
-        .post(format!("{}/api/v1/orbs/check/orbos/version", *MANAGEMENT_BACKEND_URL))
-        .basic_auth(&*ORB_ID, Some(get_orb_token()?))
-        .json(&request)
-        .send()
-        .await?
-        .error_for_status()?;
-
-    response.json::<OrbOsVersionCheckResponse>().await.map_err(Into::into)
+    let body = serde_json::to_vec(&request)?;
+    super::venue_cache::request(Method::POST, "/api/v1/orbs/check/orbos/version", Some(body)).await
 }
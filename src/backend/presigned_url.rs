@@ -1,8 +1,12 @@
 //! Signup endpoint.
 
-use crate::identification::{get_orb_token, ORB_ID};
+use crate::{
+    identification::{get_orb_token, ORB_ID},
+    monitor::traffic::{self, Class},
+};
 use eyre::Result;
 use orb_wld_data_id::{ImageId, SignupId};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -39,6 +43,14 @@ pub struct TieredPackageRequest<'a> {
     pub tier: u8,
 }
 
+/// The JSON structure of the config snapshot presigned URL request.
+#[allow(missing_docs)]
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigSnapshotRequest<'a> {
+    pub orb_id: &'a str,
+}
+
 /// The presinged URL request response
 #[allow(missing_docs)]
 #[derive(Deserialize, Debug)]
@@ -85,6 +97,13 @@ pub enum UrlType {
     /// Normalized Iris mask.
     #[serde(rename = "normalizediris_mask")]
     NormalizedIrisMask,
+    /// Pipeline intermediate artifact for the model debugging.
+    #[serde(rename = "debug_artifact")]
+    DebugArtifact,
+    /// `tar.zst` bundle of small files, unpacked by the backend according to
+    /// its manifest.
+    #[serde(rename = "bundle")]
+    Bundle,
 }
 
 /// Request a presigned url
@@ -103,7 +122,9 @@ pub async fn request(
         | UrlType::Tof2dIr
         | UrlType::Tof2dDepth
         | UrlType::NormalizedIrisImage
-        | UrlType::NormalizedIrisMask => {
+        | UrlType::NormalizedIrisMask
+        | UrlType::DebugArtifact
+        | UrlType::Bundle => {
             format!("{backend_url}/api/v2/signups/{signup_id}/upload")
         }
         UrlType::Metadata | UrlType::Tof2dConfidence | UrlType::Tof2dNoise => {
@@ -113,7 +134,7 @@ pub async fn request(
     let request = super::client()?.post(endpoint).basic_auth(&*ORB_ID, Some(get_orb_token()?));
     let request = request.json(&Request { url_type, orb_id: ORB_ID.as_str(), image_id: &image_id });
     tracing::debug!("Sending request {request:#?}");
-    let response = request.send().await?;
+    let response = traffic::send(Class::Control, request).await?;
     match response.error_for_status_ref() {
         Ok(_) => {
             let response = response.json::<Response>().await?;
@@ -139,7 +160,7 @@ pub async fn request_package(
     let request = super::client()?.post(endpoint).basic_auth(&*ORB_ID, Some(get_orb_token()?));
     let request = request.json(&PackageRequest { orb_id: ORB_ID.as_str(), session_id, checksum });
     tracing::debug!("Sending request {request:#?}");
-    let response = request.send().await?;
+    let response = traffic::send(Class::Control, request).await?;
     match response.error_for_status_ref() {
         Ok(_) => {
             let response = response.json::<Response>().await?;
@@ -167,7 +188,7 @@ pub async fn request_tiered_package(
     let request =
         request.json(&TieredPackageRequest { orb_id: ORB_ID.as_str(), session_id, checksum, tier });
     tracing::debug!("Sending request {request:#?}");
-    let response = request.send().await?;
+    let response = traffic::send(Class::Control, request).await?;
     match response.error_for_status_ref() {
         Ok(_) => {
             let response = response.json::<Response>().await?;
@@ -181,3 +202,29 @@ pub async fn request_tiered_package(
         }
     }
 }
+
+/// Request a presigned url for a content-addressed config snapshot. Returns
+/// `None` if the backend already has the snapshot.
+pub async fn request_config_snapshot(backend_url: &str, sha256: &str) -> Result<Option<Response>> {
+    let endpoint = format!("{backend_url}/api/v1/config-snapshots/{sha256}/upload");
+    let request = super::client()?.post(endpoint).basic_auth(&*ORB_ID, Some(get_orb_token()?));
+    let request = request.json(&ConfigSnapshotRequest { orb_id: ORB_ID.as_str() });
+    tracing::debug!("Sending request {request:#?}");
+    let response = traffic::send(Class::Control, request).await?;
+    if response.status() == StatusCode::CONFLICT {
+        tracing::debug!("Config snapshot {sha256} is already known");
+        return Ok(None);
+    }
+    match response.error_for_status_ref() {
+        Ok(_) => {
+            let response = response.json::<Response>().await?;
+            tracing::debug!("Received response {response:#?}");
+            Ok(Some(response))
+        }
+        Err(err) => {
+            let response = response.text().await?;
+            tracing::error!("Received error response {err:#?} with body: {response}");
+            Err(err.into())
+        }
+    }
+}
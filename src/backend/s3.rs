@@ -0,0 +1,230 @@
+//! Direct S3 upload of bulk research data via presigned URLs.
+//!
+//! Large objects are uploaded with the S3 multipart protocol. The backend only
+//! presigns the part URLs for the bucket in the signup region, and the data
+//! goes straight to S3 in parallel parts. Objects below the multipart threshold
+//! are uploaded with a single presigned request by [`upload_image`].
+
+use crate::{
+    backend::{endpoints::DATA_BACKEND_URL, presigned_url::UrlType, upload_image},
+    dd_incr, dd_timing,
+    identification::{get_orb_token, ORB_ID},
+    monitor::traffic::{self, Class},
+};
+use eyre::{ensure, eyre, Result};
+use futures::prelude::*;
+use orb_wld_data_id::{ImageId, SignupId};
+use reqwest::header::{CONTENT_LENGTH, ETAG};
+use serde::{Deserialize, Serialize};
+use std::{ops::Range, time::Instant};
+
+/// Size of a multipart upload part. S3 requires at least 5 MiB for all parts
+/// except the last one.
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Objects of at least this size are uploaded with the multipart protocol.
+const MULTIPART_THRESHOLD: usize = PART_SIZE;
+
+/// Number of parts uploaded concurrently.
+const PARALLEL_PARTS: usize = 4;
+
+/// The JSON structure of the multipart upload creation request.
+#[allow(missing_docs)]
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateRequest<'a> {
+    #[serde(rename = "type")]
+    pub url_type: UrlType,
+    pub orb_id: &'a str,
+    pub image_id: &'a str,
+    /// AWS region of the bucket, or `None` for the backend default.
+    pub region: Option<&'static str>,
+    pub parts: usize,
+}
+
+/// The multipart upload creation response.
+#[allow(missing_docs)]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateResponse {
+    pub upload_id: String,
+    pub key: String,
+    /// Presigned URLs of the parts, in the part number order.
+    pub part_urls: Vec<String>,
+}
+
+/// The JSON structure of the multipart upload completion request.
+#[allow(missing_docs)]
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CompleteRequest<'a> {
+    pub upload_id: &'a str,
+    pub key: &'a str,
+    pub parts: &'a [CompletedPart],
+}
+
+/// The JSON structure of the multipart upload abort request.
+#[allow(missing_docs)]
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AbortRequest<'a> {
+    pub upload_id: &'a str,
+    pub key: &'a str,
+}
+
+/// An uploaded part.
+#[allow(missing_docs)]
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletedPart {
+    pub part_number: usize,
+    pub etag: String,
+}
+
+/// Uploads an image directly to the S3 bucket in the signup region.
+pub async fn upload(
+    signup_id: &SignupId,
+    image_id: &ImageId,
+    url_type: UrlType,
+    data: Vec<u8>,
+    dd_image_type: &str,
+) -> Result<()> {
+    if data.len() < MULTIPART_THRESHOLD {
+        return upload_image::request(signup_id, image_id, url_type, data, dd_image_type).await;
+    }
+    let t = Instant::now();
+    let ranges = split_parts(data.len(), PART_SIZE);
+    let upload = create(signup_id, image_id, url_type, ranges.len()).await?;
+    dd_timing!(
+        "main.time.data_acquisition.upload" + format!("{}.multipart_create", dd_image_type),
+        t
+    );
+    let t = Instant::now();
+    let result = async {
+        let parts = upload_parts(&upload, &data, ranges).await?;
+        complete(signup_id, &upload, &parts).await
+    }
+    .await;
+    match result {
+        Ok(()) => {
+            dd_timing!(
+                "main.time.data_acquisition.upload" + format!("{}.multipart", dd_image_type),
+                t
+            );
+            Ok(())
+        }
+        Err(err) => {
+            dd_incr!("main.count.data_acquisition.upload.multipart_abort");
+            if let Err(abort_err) = abort(signup_id, &upload).await {
+                tracing::error!("Aborting multipart upload {} failed: {abort_err}", upload.key);
+            }
+            Err(err)
+        }
+    }
+}
+
+async fn create(
+    signup_id: &SignupId,
+    image_id: &ImageId,
+    url_type: UrlType,
+    parts: usize,
+) -> Result<CreateResponse> {
+    let endpoint = format!("{}/api/v2/signups/{signup_id}/multipart", *DATA_BACKEND_URL);
+    let request = super::client()?.post(endpoint).basic_auth(&*ORB_ID, Some(get_orb_token()?));
+    let request = request.json(&CreateRequest {
+        url_type,
+        orb_id: ORB_ID.as_str(),
+        image_id: &image_id.to_string(),
+        region: signup_id.s3_region().as_str(),
+        parts,
+    });
+    tracing::debug!("Sending request {request:#?}");
+    let response = traffic::send(Class::Uploader, request).await?;
+    match response.error_for_status_ref() {
+        Ok(_) => {
+            let response = response.json::<CreateResponse>().await?;
+            tracing::debug!("Received response {response:#?}");
+            ensure!(
+                response.part_urls.len() == parts,
+                "expected {parts} presigned part URLs, got {}",
+                response.part_urls.len()
+            );
+            Ok(response)
+        }
+        Err(err) => {
+            let response = response.text().await?;
+            tracing::error!("Received error response {err:#?} with body: {response}");
+            Err(err.into())
+        }
+    }
+}
+
+async fn upload_parts(
+    upload: &CreateResponse,
+    data: &[u8],
+    ranges: Vec<Range<usize>>,
+) -> Result<Vec<CompletedPart>> {
+    let client = super::client()?;
+    let mut parts = stream::iter(upload.part_urls.iter().zip(ranges).enumerate())
+        .map(|(i, (url, range))| {
+            let request =
+                client.put(url).header(CONTENT_LENGTH, range.len()).body(data[range].to_vec());
+            async move {
+                let response = traffic::send(Class::Uploader, request).await?.error_for_status()?;
+                let etag = response
+                    .headers()
+                    .get(ETAG)
+                    .ok_or_else(|| eyre!("no ETag in the response for part {}", i + 1))?
+                    .to_str()?
+                    .to_owned();
+                Ok::<_, eyre::Error>(CompletedPart { part_number: i + 1, etag })
+            }
+        })
+        .buffer_unordered(PARALLEL_PARTS)
+        .try_collect::<Vec<_>>()
+        .await?;
+    parts.sort_by_key(|part| part.part_number);
+    Ok(parts)
+}
+
+async fn complete(
+    signup_id: &SignupId,
+    upload: &CreateResponse,
+    parts: &[CompletedPart],
+) -> Result<()> {
+    let endpoint = format!("{}/api/v2/signups/{signup_id}/multipart/complete", *DATA_BACKEND_URL);
+    let request = super::client()?.post(endpoint).basic_auth(&*ORB_ID, Some(get_orb_token()?));
+    let request =
+        request.json(&CompleteRequest { upload_id: &upload.upload_id, key: &upload.key, parts });
+    tracing::debug!("Sending request {request:#?}");
+    traffic::send(Class::Uploader, request).await?.error_for_status()?;
+    Ok(())
+}
+
+async fn abort(signup_id: &SignupId, upload: &CreateResponse) -> Result<()> {
+    let endpoint = format!("{}/api/v2/signups/{signup_id}/multipart/abort", *DATA_BACKEND_URL);
+    let request = super::client()?.post(endpoint).basic_auth(&*ORB_ID, Some(get_orb_token()?));
+    let request = request.json(&AbortRequest { upload_id: &upload.upload_id, key: &upload.key });
+    tracing::debug!("Sending request {request:#?}");
+    traffic::send(Class::Uploader, request).await?.error_for_status()?;
+    Ok(())
+}
+
+/// Splits `len` bytes into consecutive parts of `part_size` bytes. The last
+/// part may be shorter.
+fn split_parts(len: usize, part_size: usize) -> Vec<Range<usize>> {
+    (0..len).step_by(part_size).map(|start| start..(start + part_size).min(len)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_parts() {
+        assert_eq!(split_parts(0, 4), Vec::<Range<usize>>::new());
+        assert_eq!(split_parts(8, 4), [0..4, 4..8]);
+        assert_eq!(split_parts(9, 4), [0..4, 4..8, 8..9]);
+        assert_eq!(split_parts(PART_SIZE * 3 - 1, PART_SIZE).len(), 3);
+    }
+}
@@ -3,6 +3,7 @@
 use crate::{
     backend::endpoints::MANAGEMENT_BACKEND_URL,
     identification::{get_orb_token, ORB_ID},
+    monitor::traffic::{self, Class},
 };
 use eyre::Result;
 use orb_wld_data_id::S3Region;
@@ -20,7 +21,7 @@ pub async fn request() -> Result<Response> {
         .get(format!("{}/api/v1/region", *MANAGEMENT_BACKEND_URL))
         .basic_auth(&*ORB_ID, Some(get_orb_token()?));
     tracing::debug!("Sending request {:#?}", request);
-    let response = request.send().await?;
+    let response = traffic::send(Class::Control, request).await?;
     tracing::debug!("Received response {:#?}", response);
     response.error_for_status_ref()?;
     let response = response.json::<Response>().await?;
@@ -3,6 +3,7 @@
 use crate::{
     backend::endpoints::SIGNUP_BACKEND_URL,
     identification::{get_orb_token, ORB_ID},
+    monitor::traffic::{self, Class},
 };
 use eyre::{Context, Result};
 use serde::{Deserialize, Deserializer};
@@ -42,7 +43,7 @@ pub async fn request(signup_id: &str) -> Result<Response> {
         .get(format!("{}/api/v1/signups/{signup_id}", *SIGNUP_BACKEND_URL))
         .basic_auth(&*ORB_ID, Some(get_orb_token()?));
     tracing::debug!("Sending request {:#?}", request);
-    let response = request.send().await?;
+    let response = traffic::send(Class::Control, request).await?;
     tracing::debug!("Received response {:#?}", response);
     response.error_for_status_ref()?;
 
@@ -4,6 +4,7 @@ use crate::{
     backend::endpoints::SIGNUP_BACKEND_URL,
     dd_gauge, dd_timing,
     identification::{get_orb_token, ORB_ID, ORB_OS_VERSION},
+    monitor::traffic::{self, Class},
     plans::{
         biometric_capture::Capture,
         biometric_pipeline::{EyePipeline, Pipeline},
@@ -131,11 +132,10 @@ pub async fn request(
         .text("signature", signature.map_or(String::default(), Clone::clone))
         .text("codes", codes)
         .text("reason", signup_reason.to_screaming_snake_case().to_string());
-    if let Some(latitude) = capture.latitude {
-        form = form.text("latitude", latitude.to_string());
-    }
-    if let Some(longitude) = capture.longitude {
-        form = form.text("longitude", longitude.to_string());
+    if let Some(location) = capture.location.as_ref().filter(|location| location.is_reliable()) {
+        form = form
+            .text("latitude", location.latitude.to_string())
+            .text("longitude", location.longitude.to_string());
     }
     let request = super::client()?
         .post(format!("{}/api/v2/signups/{signup_id}", *SIGNUP_BACKEND_URL))
@@ -150,6 +150,11 @@ pub async fn request(
     let t = SystemTime::now();
     let response = super::client()?.execute(request).await?;
     tracing::debug!("Received response {:#?}", response);
+    traffic::record(
+        Class::Control,
+        request_size.and_then(|size| size.to_str().ok()?.parse().ok()).unwrap_or(0),
+        response.content_length().unwrap_or(0),
+    );
     response.error_for_status_ref()?;
     let response = response.json::<Response>().await?;
     dd_timing!("main.time.http.signup_request", t);
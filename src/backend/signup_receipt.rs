@@ -0,0 +1,257 @@
+//! Signup receipts.
+//!
+//! After a successful enrollment the orb fetches a receipt for the signup,
+//! signed by the backend with Ed25519 over the signing timestamp, the signup
+//! ID, and the signup status. Verified receipts are stored together with the
+//! enrolled signups in an append-only JSON Lines ledger on the SSD. The signups
+//! lacking a receipt can be listed with `orb-core receipts missing` for a later
+//! dispute resolution.
+
+use crate::{
+    backend::endpoints::SIGNUP_BACKEND_URL,
+    consts::SIGNUP_RECEIPTS_LEDGER_PATH,
+    dd_incr,
+    identification::{get_orb_token, ORB_ID},
+    monitor::traffic::{self, Class},
+    ssd,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use eyre::{ensure, eyre, Result};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+use tokio::{fs, io::AsyncWriteExt, time::sleep};
+
+const FETCH_RETRIES_COUNT: usize = 5;
+const FETCH_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Server-signed signup acknowledgment.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    /// Signup ID.
+    pub signup_id: String,
+    /// Signup status as reported by the backend.
+    pub status: String,
+    /// UNIX time in seconds when the receipt was signed.
+    pub signed_at: u64,
+    /// Base64-encoded Ed25519 signature.
+    pub signature: String,
+}
+
+/// Signup receipts ledger entry.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Entry {
+    /// Successfully enrolled signup.
+    Enrolled {
+        /// Signup ID.
+        signup_id: String,
+        /// UNIX time in seconds of the enrollment.
+        timestamp: u64,
+    },
+    /// Verified receipt.
+    Receipt(Receipt),
+}
+
+/// Append-only signup receipts ledger.
+#[derive(Debug)]
+pub struct Ledger {
+    path: PathBuf,
+}
+
+impl Receipt {
+    /// Verifies the receipt signature, and that the receipt belongs to the
+    /// signup.
+    pub fn verify(&self, public_key: &[u8], signup_id: &str) -> Result<()> {
+        ensure!(
+            self.signup_id == signup_id,
+            "receipt is for a different signup: {}",
+            self.signup_id
+        );
+        let signature = STANDARD.decode(&self.signature)?;
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(&self.signed_message(), &signature)
+            .map_err(|_| eyre!("receipt signature verification failed"))
+    }
+
+    fn signed_message(&self) -> Vec<u8> {
+        format!("{}\n{}\n{}", self.signed_at, self.signup_id, self.status).into_bytes()
+    }
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Self::new(SIGNUP_RECEIPTS_LEDGER_PATH)
+    }
+}
+
+impl Ledger {
+    /// Creates a new ledger handle for the file at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Appends an entry to the ledger.
+    pub async fn append(&self, entry: &Entry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        file.write_all(&line).await?;
+        file.sync_data().await
+    }
+
+    /// Reads all entries of the ledger. Malformed lines, e.g. a line torn by a
+    /// power loss, are skipped.
+    pub async fn entries(&self) -> io::Result<Vec<Entry>> {
+        if !fs::try_exists(&self.path).await? {
+            return Ok(Vec::new());
+        }
+        let contents = fs::read_to_string(&self.path).await?;
+        Ok(contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                serde_json::from_str(line)
+                    .inspect_err(|err| tracing::warn!("Skipping malformed ledger line: {err}"))
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// Returns the enrolled signups lacking a receipt, the oldest first.
+    pub async fn missing_receipts(&self) -> io::Result<Vec<(String, u64)>> {
+        let entries = self.entries().await?;
+        let receipts = entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Receipt(receipt) => Some(receipt.signup_id.as_str()),
+                Entry::Enrolled { .. } => None,
+            })
+            .collect::<HashSet<_>>();
+        Ok(entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Enrolled { signup_id, timestamp }
+                    if !receipts.contains(signup_id.as_str()) =>
+                {
+                    Some((signup_id.clone(), *timestamp))
+                }
+                Entry::Enrolled { .. } | Entry::Receipt(_) => None,
+            })
+            .collect())
+    }
+}
+
+/// Makes a signup receipt request.
+pub async fn request(signup_id: &str) -> Result<Receipt> {
+    let request = super::client()?
+        .get(format!("{}/api/v1/signups/{signup_id}/receipt", *SIGNUP_BACKEND_URL))
+        .basic_auth(&*ORB_ID, Some(get_orb_token()?));
+    let response = traffic::send(Class::Control, request).await?.error_for_status()?;
+    Ok(response.json().await?)
+}
+
+/// Records a successfully enrolled signup in the ledger, then fetches,
+/// verifies, and stores its receipt. Gives up after a few attempts, leaving
+/// the signup without a receipt in the ledger.
+pub async fn fetch_and_store(signup_id: String) {
+    let ledger = Ledger::default();
+    let enrolled = Entry::Enrolled { signup_id: signup_id.clone(), timestamp: unix_now() };
+    if ssd::perform_async(ledger.append(&enrolled)).await.is_none() {
+        tracing::error!("Failed to record the enrolled signup {signup_id} in the receipts ledger");
+        return;
+    }
+    let public_key = match super::response_signing_pubkey() {
+        Ok(public_key) => public_key,
+        Err(err) => {
+            tracing::error!("Can't verify the signup receipt: {err:?}");
+            dd_incr!("main.count.http.signup_receipt.error", "type:no_key");
+            return;
+        }
+    };
+    for i in 0..FETCH_RETRIES_COUNT {
+        if i > 0 {
+            sleep(FETCH_RETRY_INTERVAL).await;
+        }
+        let receipt = match request(&signup_id).await {
+            Ok(receipt) => receipt,
+            Err(err) => {
+                tracing::warn!("Signup receipt request failed: {err:?}");
+                continue;
+            }
+        };
+        if let Err(err) = receipt.verify(public_key, &signup_id) {
+            tracing::error!("Signup receipt verification failed: {err:?}");
+            dd_incr!("main.count.http.signup_receipt.error", "type:verification");
+            return;
+        }
+        if ssd::perform_async(ledger.append(&Entry::Receipt(receipt))).await.is_some() {
+            tracing::info!("Signup receipt stored for {signup_id}");
+            dd_incr!("main.count.http.signup_receipt.success");
+        }
+        return;
+    }
+    dd_incr!("main.count.http.signup_receipt.error", "type:request");
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |now| now.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+
+    fn signed_receipt(key_pair: &Ed25519KeyPair, signup_id: &str) -> Receipt {
+        let mut receipt = Receipt {
+            signup_id: signup_id.to_owned(),
+            status: "Completed".to_owned(),
+            signed_at: 1_700_000_000,
+            signature: String::new(),
+        };
+        receipt.signature = STANDARD.encode(key_pair.sign(&receipt.signed_message()));
+        receipt
+    }
+
+    #[test]
+    fn test_verify() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let public_key = key_pair.public_key().as_ref();
+        let receipt = signed_receipt(&key_pair, "abc");
+        assert!(receipt.verify(public_key, "abc").is_ok());
+        assert!(receipt.verify(public_key, "def").is_err());
+        let tampered = Receipt { status: "Failed".to_owned(), ..receipt };
+        assert!(tampered.verify(public_key, "abc").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_missing_receipts() {
+        let temp_dir = tempfile::tempdir().expect("to create temp dir");
+        let ledger = Ledger::new(temp_dir.path().join("ledger/signup_receipts.jsonl"));
+        assert!(ledger.missing_receipts().await.unwrap().is_empty());
+
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        for signup_id in ["a", "b", "c"] {
+            let entry = Entry::Enrolled { signup_id: signup_id.to_owned(), timestamp: 1 };
+            ledger.append(&entry).await.unwrap();
+        }
+        ledger.append(&Entry::Receipt(signed_receipt(&key_pair, "b"))).await.unwrap();
+        let missing = ledger.missing_receipts().await.unwrap();
+        assert_eq!(missing, vec![("a".to_owned(), 1), ("c".to_owned(), 1)]);
+    }
+}
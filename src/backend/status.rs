@@ -3,6 +3,7 @@
 use crate::{
     backend::endpoints::MANAGEMENT_BACKEND_URL,
     identification::{get_orb_token, ORB_ID},
+    monitor::traffic::{self, Class},
 };
 use eyre::Result;
 use serde::Serialize;
@@ -128,12 +129,11 @@ pub struct OrbVersion {
 
 /// Makes an orb status request.
 pub async fn request(request: &Request) -> Result<()> {
-    let response = super::client()?
+    let request = super::client()?
         .post(format!("{}/api/v1/orbs/{}/status", *MANAGEMENT_BACKEND_URL, *ORB_ID))
         .basic_auth(&*ORB_ID, Some(get_orb_token()?))
-        .json(request)
-        .send()
-        .await?;
+        .json(request);
+    let response = traffic::send(Class::Telemetry, request).await?;
     response.error_for_status_ref()?;
     Ok(())
 }
@@ -0,0 +1,22 @@
+//! Crash report telemetry endpoint.
+
+use super::endpoints::MANAGEMENT_BACKEND_URL;
+use crate::{
+    crash::Report,
+    identification::{get_orb_token, ORB_ID},
+    monitor::traffic::{self, Class},
+};
+use eyre::Result;
+
+/// Uploads a crash report.
+pub async fn request(report: &Report) -> Result<()> {
+    let request = super::client()?
+        .post(format!(
+            "{}/api/v1/orbs/{}/telemetry/crash-reports",
+            *MANAGEMENT_BACKEND_URL, *ORB_ID
+        ))
+        .basic_auth(&*ORB_ID, Some(get_orb_token()?))
+        .json(report);
+    traffic::send(Class::Uploader, request).await?.error_for_status()?;
+    Ok(())
+}
@@ -2,42 +2,87 @@
 
 use crate::{
     backend::{
+        compression::{self, Encoding},
         endpoints::DATA_BACKEND_URL,
         presigned_url::{self, UrlType},
     },
-    dd_timing,
-    debug_report::DebugReport,
+    dd_incr, dd_timing,
+    debug_report::{ConfigSnapshot, DebugReport},
+    monitor::traffic::Class,
 };
 use eyre::Result;
-use flate2::{write::GzEncoder, Compression};
+use once_cell::sync::Lazy;
 use orb_wld_data_id::SignupId;
-use std::time::SystemTime;
+use reqwest::Url;
+use std::{collections::HashSet, sync::Mutex, time::SystemTime};
+
+/// Hashes of the config snapshots known to the backend.
+static KNOWN_CONFIG_SNAPSHOTS: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
 
 /// Compresses and uploads the signup JSON.
-pub async fn request(signup_id: &SignupId, debug_report: &DebugReport) -> Result<()> {
+///
+/// The backend config is uploaded separately as a content-addressed snapshot
+/// and referenced by its hash. If the snapshot upload fails, the config is
+/// inlined into the signup JSON instead.
+pub async fn request(signup_id: &SignupId, debug_report: &mut DebugReport) -> Result<()> {
+    if let Some(snapshot) = debug_report.backend_config_snapshot() {
+        if ensure_config_snapshot(&snapshot).await {
+            debug_report.reference_backend_config();
+            dd_incr!("main.count.data_acquisition.upload.config_snapshot", "type:referenced");
+        } else {
+            dd_incr!("main.count.data_acquisition.upload.config_snapshot", "type:inlined");
+        }
+    }
     let t0 = SystemTime::now();
     let presigned_url::Response { url: presigned_url, .. } =
         presigned_url::request(&DATA_BACKEND_URL, signup_id, None, UrlType::Metadata).await?;
     dd_timing!("main.time.data_acquisition.upload.signup_json.presigned", t0);
     tracing::debug!("Metadata presigned_url: {:?}", presigned_url);
-    let request = super::client()?
-        .put(presigned_url)
-        .header("content-type", "application/json")
-        .header("content-encoding", "gzip")
-        .body(compressed_signup_json(debug_report)?);
-    tracing::debug!("Sending request {:#?}", request);
+    let url = Url::parse(&presigned_url)?;
+    let json = serde_json::to_vec(&debug_report)?;
     let t1 = SystemTime::now();
-    let response = request.send().await?;
+    let response = compression::send(Class::Uploader, "signup_json", &json, Encoding::Gzip, || {
+        Ok(super::client()?.put(url.clone()).header("content-type", "application/json"))
+    })
+    .await?;
     dd_timing!("main.time.data_acquisition.upload.signup_json.upload", t1);
     tracing::debug!("Received response {:#?}", response);
     response.error_for_status()?;
     Ok(())
 }
 
-fn compressed_signup_json(debug_report: &DebugReport) -> Result<Vec<u8>> {
-    let mut compressed_debug_report = Vec::new();
-    let mut encoder = GzEncoder::new(&mut compressed_debug_report, Compression::default());
-    serde_json::to_writer(&mut encoder, &debug_report)?;
-    encoder.finish()?;
-    Ok(compressed_debug_report)
+/// Makes sure the backend has the config snapshot. Returns `false` if it
+/// couldn't be confirmed.
+async fn ensure_config_snapshot(snapshot: &ConfigSnapshot) -> bool {
+    if KNOWN_CONFIG_SNAPSHOTS.lock().unwrap().contains(&snapshot.sha256) {
+        return true;
+    }
+    match upload_config_snapshot(snapshot).await {
+        Ok(()) => {
+            KNOWN_CONFIG_SNAPSHOTS.lock().unwrap().insert(snapshot.sha256.clone());
+            true
+        }
+        Err(err) => {
+            tracing::warn!("Config snapshot upload failed, inlining the config: {err:?}");
+            false
+        }
+    }
+}
+
+async fn upload_config_snapshot(snapshot: &ConfigSnapshot) -> Result<()> {
+    let Some(presigned_url::Response { url: presigned_url, .. }) =
+        presigned_url::request_config_snapshot(&DATA_BACKEND_URL, &snapshot.sha256).await?
+    else {
+        return Ok(());
+    };
+    let t0 = SystemTime::now();
+    let url = Url::parse(&presigned_url)?;
+    compression::send(Class::Uploader, "config_snapshot", &snapshot.json, Encoding::Gzip, || {
+        Ok(super::client()?.put(url.clone()).header("content-type", "application/json"))
+    })
+    .await?
+    .error_for_status()?;
+    dd_timing!("main.time.data_acquisition.upload.config_snapshot.upload", t0);
+    tracing::info!("Config snapshot {} uploaded", snapshot.sha256);
+    Ok(())
 }
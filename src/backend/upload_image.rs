@@ -6,6 +6,7 @@ use crate::{
         presigned_url::{self, UrlType},
     },
     dd_timing,
+    monitor::traffic::{self, Class},
 };
 use eyre::Result;
 use orb_wld_data_id::{ImageId, SignupId};
@@ -29,7 +30,7 @@ pub async fn request(
     let request =
         super::client()?.put(presigned_url).header(CONTENT_LENGTH, img_data.len()).body(img_data);
     let t = Instant::now();
-    let response = request.send().await?;
+    let response = traffic::send(Class::Uploader, request).await?;
     dd_timing!("main.time.data_acquisition.upload" + format!("{}.upload", dd_image_type), t);
     response.error_for_status()?;
     Ok(())
@@ -4,6 +4,7 @@ use crate::{
     backend::{endpoints::DATA_BACKEND_URL, presigned_url},
     config::Config,
     dd_timing,
+    monitor::traffic::{self, Class},
 };
 use data_encoding::BASE64;
 use eyre::Result;
@@ -60,7 +61,7 @@ pub async fn request(
             .multipart(form);
     tracing::debug!("Sending request {request:#?}");
     let t1 = Instant::now();
-    let response = request.send().await?;
+    let response = traffic::send(Class::Uploader, request).await?;
     dd_timing!("main.time.signup.upload_custody_images.upload", t1);
     tracing::debug!("Received response {response:#?}");
     response.error_for_status()?;
@@ -1,8 +1,9 @@
 //! User ID validation endpoint.
 
 use crate::{
-    backend::endpoints::SIGNUP_BACKEND_URL,
+    backend::{endpoints::SIGNUP_BACKEND_URL, operator_status::LocationSession},
     identification::{get_orb_token, ORB_ID},
+    monitor::traffic::{self, Class},
     plans::{qr_scan, OperatorData},
 };
 use data_encoding::BASE64;
@@ -80,6 +81,7 @@ async fn do_request(
     _operator_data: &OperatorData,
     _use_full_operator_qr: bool,
     _use_only_operator_location: bool,
+    _location_session: Option<&LocationSession>,
 ) -> Result<Response> {
     let (public_key, _) = sodiumoxide::crypto::box_::gen_keypair();
     let backend_keys = Some(BackendKeys {
@@ -115,8 +117,14 @@ async fn do_request(
     operator_data: &OperatorData,
     use_full_operator_qr: bool,
     use_only_operator_location: bool,
+    location_session: Option<&LocationSession>,
 ) -> Result<Response> {
-    let request = if use_only_operator_location {
+    let location_session = location_session.filter(|_| use_only_operator_location);
+    let request = if let Some(location_session) = location_session {
+        super::client()?
+            .get(format!("{}/api/v2/session/{}/status", *SIGNUP_BACKEND_URL, qr_code.user_id))
+            .query(&[("location_session", &location_session.id)])
+    } else if use_only_operator_location {
         super::client()?
             .get(format!("{}/api/v2/session/{}/status", *SIGNUP_BACKEND_URL, qr_code.user_id,))
             .query(&[
@@ -133,7 +141,7 @@ async fn do_request(
     }
     .basic_auth(&*ORB_ID, Some(get_orb_token()?));
 
-    Ok(match request.send().await?.error_for_status() {
+    Ok(match traffic::send(Class::Control, request).await?.error_for_status() {
         Ok(response) => response.json().await?,
         Err(err) => {
             tracing::error!("Received error response {err:?}");
@@ -142,17 +150,25 @@ async fn do_request(
     })
 }
 
-/// Makes a validation request.
+/// Makes a validation request. With `use_only_operator_location`, the
+/// request refers to `location_session` if provided instead of carrying the
+/// operator coordinates.
 #[allow(clippy::too_many_lines)]
 pub async fn request(
     qr_code: &qr_scan::user::Data,
     operator_data: &OperatorData,
     use_full_operator_qr: bool,
     use_only_operator_location: bool,
+    location_session: Option<&LocationSession>,
 ) -> Result<Option<UserData>> {
-    let Response { valid, reason, backend_keys, authenticated_app_data } =
-        do_request(qr_code, operator_data, use_full_operator_qr, use_only_operator_location)
-            .await?;
+    let Response { valid, reason, backend_keys, authenticated_app_data } = do_request(
+        qr_code,
+        operator_data,
+        use_full_operator_qr,
+        use_only_operator_location,
+        location_session,
+    )
+    .await?;
     if !valid {
         tracing::info!(
             "User QR-code invalid: {qr_code:?}, reason: {:?}",
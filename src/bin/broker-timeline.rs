@@ -0,0 +1,45 @@
+//! Prints the orb broker decision timeline recorded in a debug report.
+
+#![warn(clippy::pedantic)]
+
+use clap::{ArgEnum, Parser};
+use eyre::{Result, WrapErr};
+use orb::brokers::{event_log, message_trace};
+use std::{fs, path::PathBuf};
+
+/// Reconstructs the orb broker decision timeline from a debug report.
+#[derive(Parser, Debug)]
+#[clap(about)]
+struct Cli {
+    /// Path to the debug report JSON.
+    path: PathBuf,
+    /// Print the sampled broker messages as folded stacks for flamegraph
+    /// tools instead, weighted by the given value.
+    #[clap(long, arg_enum)]
+    folded: Option<Weight>,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum Weight {
+    Count,
+    Bytes,
+    QueueTime,
+}
+
+fn main() -> Result<()> {
+    let Cli { path, folded } = Cli::parse();
+    let json = fs::read(&path).wrap_err_with(|| format!("reading {}", path.display()))?;
+    if let Some(weight) = folded {
+        let weight = match weight {
+            Weight::Count => message_trace::Weight::Count,
+            Weight::Bytes => message_trace::Weight::Bytes,
+            Weight::QueueTime => message_trace::Weight::QueueTime,
+        };
+        print!("{}", message_trace::from_debug_report(&json)?.folded(weight));
+        return Ok(());
+    }
+    for step in event_log::timeline(&event_log::from_debug_report(&json)?) {
+        println!("{step}");
+    }
+    Ok(())
+}
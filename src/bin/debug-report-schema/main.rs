@@ -1,7 +1,13 @@
 use clap::{Parser, Subcommand};
 use csv::WriterBuilder;
 use eyre::{Context, Result};
-use orb::debug_report::{DebugReport, DEBUG_REPORT_VERSION};
+use orb::{
+    debug_report::{DebugReport, DEBUG_REPORT_VERSION},
+    plans::{
+        health_check,
+        personal_custody_package::manifest::{self, Manifest},
+    },
+};
 use schema_traversal::ControlSchemaMetadata;
 use schemars::{gen::SchemaSettings, schema::RootSchema, JsonSchema};
 use serde::Serialize;
@@ -21,7 +27,9 @@ struct Cli {
 enum CliCommand {
     /// Check if the DEBUG_REPORT_VERSION is correct
     CheckVersion,
-    /// Export the DebugReport Schema in JSON and CSV formats
+    /// Export the DebugReport, the health check Report and the personal
+    /// custody package Manifest Schemas in JSON and CSV formats, along with
+    /// the package field registry
     Export,
 }
 
@@ -45,6 +53,9 @@ fn main() -> Result<ExitCode> {
         }
         CliCommand::Export => {
             write_schema_to_disk::<DebugReport>("debug_report_schema")?;
+            write_schema_to_disk::<health_check::Report>("health_check_report_schema")?;
+            write_schema_to_disk::<Manifest>("pcp_manifest_schema")?;
+            write_pcp_registry_to_disk("pcp_manifest_registry")?;
             Ok(ExitCode::SUCCESS)
         }
     }
@@ -104,6 +115,15 @@ fn write_schema_to_disk<T: JsonSchema>(file_basename: &str) -> Result<()> {
     Ok(())
 }
 
+fn write_pcp_registry_to_disk(file_basename: &str) -> Result<()> {
+    std::fs::write(
+        file_basename.to_owned() + ".json",
+        serde_json::to_string(manifest::REGISTRY).wrap_err("Failed to serialize registry.")?,
+    )
+    .wrap_err("Failed to write into JSON file.")?;
+    Ok(())
+}
+
 #[must_use]
 fn version_hash_schema_settings() -> SchemaSettings {
     SchemaSettings::default()
@@ -17,19 +17,73 @@ use orb::{
     config::Config,
     consts::IR_CAMERA_FRAME_RATE,
     logger, mcu, monitor,
-    plans::{health_check, MasterPlan},
+    plans::{
+        health_check::{self, FailureClass, Report},
+        MasterPlan,
+    },
     ui::{self, Engine},
 };
 
 use std::{sync::Arc, time::Duration};
 use tokio::{signal::ctrl_c, sync::Mutex};
 
+/// Runs the hardware health checks.
+#[derive(Parser, Debug)]
+#[clap(about, version = env!("GIT_VERSION"))]
+struct HealthCheckCli {
+    #[clap(flatten)]
+    cli: Cli,
+    /// Print the results as JSON to stdout and exit with a code specific to
+    /// the failure class.
+    #[clap(long)]
+    json: bool,
+    /// Run the guided optics check with the slanted-edge test target.
+    #[clap(long)]
+    optics: bool,
+    /// Run the guided 1D ToF accuracy check with the reference target.
+    #[clap(long)]
+    tof: bool,
+    /// Run the fan and mirror vibration check.
+    #[clap(long)]
+    vibration: bool,
+    /// Run the security MCU firmware check.
+    #[clap(long)]
+    security_mcu: bool,
+}
+
 fn main() -> Result<()> {
-    async_main(run(Cli::parse()))
+    async_main(run(HealthCheckCli::parse()))
 }
 
-async fn run(cli: Cli) -> Result<()> {
+async fn run(
+    HealthCheckCli { cli, json, optics, tof, vibration, security_mcu }: HealthCheckCli,
+) -> Result<()> {
     logger::init::<false>();
+    if json {
+        let report =
+            run_checks(cli, optics, tof, vibration, security_mcu).await.unwrap_or_else(|err| {
+                tracing::error!("Health check failed to run: {err:?}");
+                Report::failure("setup", FailureClass::Setup, format!("{err:#}"))
+            });
+        println!("{}", serde_json::to_string(&report)?);
+        std::process::exit(report.exit_code);
+    }
+    if run_checks(cli, optics, tof, vibration, security_mcu).await?.success {
+        tracing::info!("All checks passed!");
+    } else {
+        bail!("Health check failure!");
+    }
+    Ok(())
+}
+
+#[allow(clippy::fn_params_excessive_bools)]
+async fn run_checks(
+    cli: Cli,
+    optics: bool,
+    tof: bool,
+    vibration: bool,
+    security_mcu: bool,
+) -> Result<Report> {
     ensure!(sodiumoxide::init().is_ok(), "sodiumoxide initialization failure");
 
     let ui = ui::Jetson::spawn();
@@ -56,10 +110,22 @@ async fn run(cli: Cli) -> Result<()> {
     #[cfg(feature = "livestream")]
     if cli.livestream {
         orb.start_rgb_camera(RGB_FPS).await?;
-        orb.enable_livestream()?;
+        orb.start_livestream(None).await?;
     }
 
     let mut health_check = health_check::Plan::default();
+    if optics {
+        health_check = health_check.with_optics_mtf();
+    }
+    if tof {
+        health_check = health_check.with_tof_accuracy();
+    }
+    if vibration {
+        health_check = health_check.with_vibration();
+    }
+    if security_mcu {
+        health_check = health_check.with_security_mcu();
+    }
     let result = {
         let health_check = health_check.run(&mut orb);
         let ctrl_c = ctrl_c();
@@ -71,7 +137,15 @@ async fn run(cli: Cli) -> Result<()> {
                 Err(err)
             }
             Either::Left((result, _)) => result,
-            Either::Right((result, _)) => result.map(|()| false).map_err(Into::into),
+            Either::Right((result, _)) => result
+                .map(|()| {
+                    Report::failure(
+                        "interrupted",
+                        FailureClass::Interrupted,
+                        "Interrupted by the user".to_owned(),
+                    )
+                })
+                .map_err(Into::into),
         }
     };
     MasterPlan::builder()
@@ -79,10 +153,5 @@ async fn run(cli: Cli) -> Result<()> {
         .await?
         .reset_hardware(&mut orb, Duration::from_millis(100))
         .await?;
-    if result? {
-        tracing::info!("All checks passed!");
-    } else {
-        bail!("Health check failure!");
-    }
-    Ok(())
+    result
 }
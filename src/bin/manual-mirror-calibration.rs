@@ -71,7 +71,7 @@ impl Plan {
         orb.disable_ir_led().await?;
         orb.main_mcu.send(mcu::main::Input::LiquidLens(None)).await?;
         #[cfg(feature = "livestream")]
-        orb.enable_livestream()?;
+        orb.start_livestream(None).await?;
         orb.enable_ir_net().await?;
         orb.enable_rgb_net(true).await?;
         orb.start_ir_eye_camera().await?;
@@ -102,7 +102,7 @@ impl Plan {
                 }
                 Command::EyePidControllerToggle(enable_eye_pid_controller) => {
                     if enable_eye_pid_controller {
-                        orb.enable_eye_pid_controller()?;
+                        orb.start_eye_pid_controller().await?;
                     } else if orb.eye_pid_controller.is_enabled() {
                         orb.stop_eye_pid_controller().await?;
                     }
@@ -8,14 +8,20 @@ use futures::{pin_mut, prelude::*, select_biased};
 #[cfg(feature = "internal-data-acquisition")]
 use orb::logger::DATADOG_SUPPRESS;
 use orb::{
+    agents::{data_uploader::QueueItem, mirror},
     async_main,
+    backend::signup_receipt,
     brokers::{DefaultObserverPlan, Observer, Orb},
-    cli::Cli,
+    cli::{Cli, Command, ReceiptsCommand, UploadsCommand},
     config::Config,
+    crash,
+    dbus::UploadQueuesProxy,
     dd_incr, dd_timing, logger,
     mcu::{self, Mcu},
     monitor,
-    plans::{warmup, MasterPlan},
+    plans::{detect_face::presence::TofDistance, mcu_update, warmup, MasterPlan},
+    process::watchdog::Watchdog,
+    sound,
     ui::{self, Engine},
 };
 #[cfg(feature = "internal-data-acquisition")]
@@ -27,13 +33,33 @@ use std::{
 };
 use tokio::{fs, signal::ctrl_c, sync::Mutex, task};
 
+/// The rust main program running on the orb and responsible for signup and
+/// other main behaviors of the orb
+#[derive(Parser, Debug)]
+#[clap(about, version = env!("GIT_VERSION"))]
+struct OrbCoreCli {
+    #[clap(flatten)]
+    cli: Cli,
+    /// Run a subcommand instead of the main program.
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
 fn main() -> Result<()> {
-    async_main(run(Cli::parse()))
+    async_main(async {
+        let OrbCoreCli { cli, command } = OrbCoreCli::parse();
+        match command {
+            Some(Command::Uploads(command)) => uploads(command).await,
+            Some(Command::Receipts(command)) => receipts(command).await,
+            None => run(cli).await,
+        }
+    })
 }
 
 #[allow(let_underscore_drop, clippy::too_many_lines)]
 async fn run(cli: Cli) -> Result<()> {
     logger::init::<false>();
+    crash::init();
     ensure!(sodiumoxide::init().is_ok(), "sodiumoxide initialization failure");
     #[cfg(feature = "internal-data-acquisition")]
     if cli.data_acquisition {
@@ -43,23 +69,41 @@ async fn run(cli: Cli) -> Result<()> {
     dd_incr!("main.count.global.starting_main_program");
     let t = SystemTime::now();
 
-    let ui = ui::Jetson::spawn();
+    let (haptics_tx, haptics_rx) = ui::haptics::channel();
+    let ui = ui::Jetson::spawn_with_cues(
+        Some(sound::Jetson::spawn(cli.ignore_missing_sounds)),
+        haptics_tx,
+    );
 
     // When the orb boots up for the first time, there is no internet
     // connection, so we must rely solely on the local configuration. In any
     // case, this configuration setup is only used for playing basic startup
-    // sounds.
+    // sounds and for the traffic budgets.
     let config = if let Some(path) = &cli.config {
         serde_json::from_str(&fs::read_to_string(path).await?)?
     } else {
         Config::load_or_default().await
     };
+    monitor::traffic::set_budgets(config.basic_config.traffic_budgets);
     let config = Arc::new(Mutex::new(config));
     config.lock().await.propagate_to_ui(&ui);
+    task::spawn(ui::animation::watch(ui.clone()));
 
     let cpu_monitor = Box::new(monitor::cpu::Jetson::spawn());
 
-    let main_mcu = Box::new(mcu::main::Jetson::spawn()?);
+    if let Some(path) = &cli.can_trace {
+        mcu::can::trace_to(path)?;
+    }
+    let main_mcu: Box<dyn Mcu<mcu::Main>> = if let Some(path) = &cli.can_replay {
+        Box::new(mcu::main::Fake::replay(path)?)
+    } else {
+        let config = config.lock().await;
+        Box::new(mcu::main::Jetson::spawn_with_routing(
+            config.mcu_routing,
+            config.mcu_ack_policies,
+            &config.mcu_uart_device,
+        )?)
+    };
     let (net_monitor, net_monitor_trigger): (Box<dyn monitor::net::Monitor>, _) = 'net_monitor: {
         #[cfg(feature = "internal-data-acquisition")]
         if cli.data_acquisition {
@@ -72,12 +116,20 @@ async fn run(cli: Cli) -> Result<()> {
     };
 
     let signup_flag = Arc::new(AtomicBool::new(false));
+    let tof_distance = Arc::new(TofDistance::default());
+    let mirror_step_loss = mirror::step_loss::Shared::default();
+    let watchdog = Watchdog::open(cli.hardware_watchdog);
+    let heartbeat = watchdog.heartbeat();
     let observer = Observer::builder()
         .config(Arc::clone(&config))
         .ui(ui.clone())
         .main_mcu(main_mcu.clone())
         .net_monitor(net_monitor.clone())
         .signup_flag(Arc::clone(&signup_flag))
+        .tof_distance(Arc::clone(&tof_distance))
+        .mirror_step_loss(Arc::clone(&mirror_step_loss))
+        .haptics(haptics_rx)
+        .watchdog(watchdog)
         .build();
     let mut observer_task = task::spawn(DefaultObserverPlan::default().run(observer));
 
@@ -87,15 +139,19 @@ async fn run(cli: Cli) -> Result<()> {
         .main_mcu(main_mcu)
         .net_monitor(net_monitor)
         .cpu_monitor(cpu_monitor)
+        .tof_distance(tof_distance)
+        .mirror_step_loss(mirror_step_loss)
+        .heartbeat(heartbeat)
         .build()
         .await?;
     #[cfg(feature = "livestream")]
-    if cli.livestream {
-        orb.enable_livestream()?;
+    if cli.livestream || cli.livestream_record.is_some() {
+        orb.start_livestream(cli.livestream_record.clone()).await?;
     }
 
     setup_orb_token().await?;
     dd_incr!("main.count.global.token_acquired");
+    task::spawn(crash::run());
 
     // Now that we connected to the WiFi, we can monitor our connection.
     net_monitor_trigger.fire();
@@ -116,6 +172,9 @@ async fn run(cli: Cli) -> Result<()> {
         }
         break 'config_download Config::download_and_store(Arc::clone(&config)).await?;
     }
+    if let Err(err) = mcu_update::heal(&mut orb).await {
+        tracing::error!("MCU firmware update failed: {err:?}");
+    }
     warmup::Plan::default().run(&mut orb).await?;
 
     ui.boot_complete(false);
@@ -170,6 +229,57 @@ async fn run(cli: Cli) -> Result<()> {
     result
 }
 
+async fn uploads(command: UploadsCommand) -> Result<()> {
+    let connection = zbus::Connection::session().await?;
+    let proxy = UploadQueuesProxy::new(&connection).await?;
+    match command {
+        UploadsCommand::List { json } => {
+            let items = proxy.list().await?;
+            if json {
+                println!("{items}");
+                return Ok(());
+            }
+            let items: Vec<QueueItem> = serde_json::from_str(&items)?;
+            println!(
+                "{:<4} {:>6} {:<11} {:>8} {:>12} {:>8}  SIGNUP",
+                "TIER", "ID", "STATE", "AGE", "SIZE", "ATTEMPTS"
+            );
+            for item in items {
+                println!(
+                    "{:<4} {:>6} {:<11} {:>8} {:>12} {:>8}  {}",
+                    item.tier,
+                    item.id.map_or_else(|| "-".to_owned(), |id| id.to_string()),
+                    if item.in_progress { "in-progress" } else { "pending" },
+                    item.age.map_or_else(|| "-".to_owned(), |age| format!("{age}s")),
+                    item.size,
+                    item.attempts,
+                    item.signup_id,
+                );
+            }
+        }
+        UploadsCommand::Requeue { tier, id } => proxy.requeue(tier, id).await?,
+        UploadsCommand::Drop { tier, id } => proxy.drop_item(tier, id).await?,
+    }
+    Ok(())
+}
+
+async fn receipts(command: ReceiptsCommand) -> Result<()> {
+    match command {
+        ReceiptsCommand::Missing { json } => {
+            let missing = signup_receipt::Ledger::default().missing_receipts().await?;
+            if json {
+                println!("{}", serde_json::to_string(&missing)?);
+                return Ok(());
+            }
+            println!("{:<20}  SIGNUP", "ENROLLED AT");
+            for (signup_id, timestamp) in missing {
+                println!("{timestamp:<20}  {signup_id}");
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn setup_orb_token() -> Result<()> {
     let token_timing = SystemTime::now();
     orb::short_lived_token::wait_for_token().await;
@@ -0,0 +1,112 @@
+//! Secure Element monotonic counters.
+//!
+//! Each counter slot is stored as an 8-byte big-endian binary object in the
+//! Secure Element and accessed with the NXP `ssscli` tool. The objects are
+//! provisioned at manufacturing. The tool never lowers a counter, so a counter
+//! can only be advanced. The resulting value is printed to stdout.
+
+#![warn(clippy::pedantic)]
+
+use clap::{Parser, Subcommand};
+use eyre::{bail, ensure, eyre, Result, WrapErr};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+const SSSCLI: &str = "/usr/bin/ssscli";
+
+/// Object ID of the counter slot 0.
+const OBJECT_ID_BASE: u32 = 0x7DA0_0000;
+
+/// Reads and advances the Secure Element monotonic counters.
+#[derive(Parser, Debug)]
+#[clap(about, version = env!("GIT_VERSION"))]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the counter value.
+    Read {
+        /// Counter slot.
+        slot: u8,
+    },
+    /// Advance the counter to the value, unless it's already higher.
+    Advance {
+        /// Counter slot.
+        slot: u8,
+        /// New counter value.
+        value: u64,
+    },
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    ssscli(&["connect", "se05x", "t1oi2c", "none"])?;
+    let result = match cli.command {
+        Command::Read { slot } => read(slot),
+        Command::Advance { slot, value } => advance(slot, value),
+    };
+    if let Err(err) = ssscli(&["disconnect"]) {
+        eprintln!("Couldn't disconnect from the Secure Element: {err:?}");
+    }
+    println!("{}", result?);
+    Ok(())
+}
+
+fn read(slot: u8) -> Result<u64> {
+    let path = scratch_path(slot);
+    let result = ssscli(&["get", "bin", &object_id(slot), path_str(&path)?])
+        .and_then(|()| fs::read(&path).wrap_err("reading the counter object"));
+    let _ = fs::remove_file(&path);
+    let bytes = result?;
+    let bytes: [u8; 8] = bytes
+        .as_slice()
+        .try_into()
+        .wrap_err_with(|| format!("invalid counter object size: {}", bytes.len()))?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn advance(slot: u8, value: u64) -> Result<u64> {
+    let current = read(slot)?;
+    if value <= current {
+        return Ok(current);
+    }
+    let path = scratch_path(slot);
+    fs::write(&path, value.to_be_bytes()).wrap_err("writing the counter object")?;
+    let result = ssscli(&["set", "bin", &object_id(slot), path_str(&path)?]);
+    let _ = fs::remove_file(&path);
+    result?;
+    let advanced = read(slot)?;
+    ensure!(advanced == value, "counter {slot} reads {advanced} after advancing to {value}");
+    Ok(advanced)
+}
+
+fn ssscli(args: &[&str]) -> Result<()> {
+    let output = process::Command::new(SSSCLI)
+        .args(args)
+        .stdin(process::Stdio::null())
+        .output()
+        .wrap_err("running ssscli")?;
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        bail!("ssscli {} failed: {}", args.join(" "), output.status);
+    }
+    Ok(())
+}
+
+fn object_id(slot: u8) -> String {
+    format!("0x{:08X}", OBJECT_ID_BASE + u32::from(slot))
+}
+
+fn scratch_path(slot: u8) -> PathBuf {
+    env::temp_dir().join(format!("orb-se-counter-{}-{slot}.bin", process::id()))
+}
+
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str().ok_or_else(|| eyre!("non-UTF-8 path: {}", path.display()))
+}
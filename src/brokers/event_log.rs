@@ -0,0 +1,197 @@
+//! Append-only log of the orb broker decisions.
+//!
+//! During a signup the orb broker records every agent enabling and disabling,
+//! phase transition, and `BrokerFlow::Break` or error returned from a handler.
+//! The log is attached to the debug report, and the [`timeline`] function
+//! reconstructs the broker state after each decision for postmortems.
+
+use crate::consts::BROKER_EVENT_LOG_CAPACITY;
+use agentwire::BrokerEvent;
+use eyre::{eyre, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fmt, time::Instant};
+
+/// Orb broker event log.
+#[derive(Default, Debug)]
+pub struct EventLog {
+    start: Option<Instant>,
+    events: Vec<Event>,
+    dropped: usize,
+}
+
+/// A recorded broker decision.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Event {
+    /// Milliseconds since the start of the log.
+    pub offset_ms: u64,
+    /// The decision.
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Kind of a broker decision.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "event", content = "value", rename_all = "snake_case")]
+pub enum EventKind {
+    /// The log has been started with the given agents enabled.
+    Start(Vec<String>),
+    /// An agent has been enabled.
+    AgentEnabled(String),
+    /// An agent has been disabled.
+    AgentDisabled(String),
+    /// The broker has entered a new phase.
+    Phase(String),
+    /// A handler broke the broker loop.
+    Break(String),
+    /// A handler returned an error.
+    Error(String),
+}
+
+/// Broker state after a decision.
+#[derive(Clone, Debug)]
+pub struct Step {
+    /// The decision.
+    pub event: Event,
+    /// Current phase.
+    pub phase: Option<String>,
+    /// Enabled agents.
+    pub enabled_agents: BTreeSet<String>,
+}
+
+impl EventLog {
+    /// Clears the log and starts recording with the given agents enabled.
+    pub fn start(&mut self, enabled_agents: Vec<String>) {
+        self.start = Some(Instant::now());
+        self.events.clear();
+        self.dropped = 0;
+        self.record(EventKind::Start(enabled_agents));
+    }
+
+    /// Appends a decision to the log. Does nothing if the log is not started.
+    pub fn record(&mut self, kind: EventKind) {
+        let Some(start) = self.start else {
+            return;
+        };
+        if self.events.len() >= BROKER_EVENT_LOG_CAPACITY {
+            self.dropped += 1;
+            return;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        let offset_ms = start.elapsed().as_millis() as u64;
+        self.events.push(Event { offset_ms, kind });
+    }
+
+    /// Returns the recorded decisions and stops recording.
+    pub fn take(&mut self) -> Vec<Event> {
+        if self.dropped > 0 {
+            tracing::warn!("Broker event log overflow: {} events dropped", self.dropped);
+        }
+        self.start = None;
+        self.dropped = 0;
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl From<BrokerEvent> for EventKind {
+    fn from(event: BrokerEvent) -> Self {
+        match event {
+            BrokerEvent::AgentEnabled(name) => Self::AgentEnabled(name.to_owned()),
+            BrokerEvent::AgentDisabled(name) => Self::AgentDisabled(name.to_owned()),
+            BrokerEvent::Break(name) => Self::Break(name.to_owned()),
+            BrokerEvent::Error(name) => Self::Error(name.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { event: Event { offset_ms, kind }, phase, enabled_agents } = self;
+        write!(f, "{:>4}.{:03}s ", offset_ms / 1000, offset_ms % 1000)?;
+        match kind {
+            EventKind::Start(_) => write!(f, "start")?,
+            EventKind::AgentEnabled(name) => write!(f, "+{name}")?,
+            EventKind::AgentDisabled(name) => write!(f, "-{name}")?,
+            EventKind::Phase(name) => write!(f, "phase {name:?}")?,
+            EventKind::Break(name) => write!(f, "break by {name}")?,
+            EventKind::Error(name) => write!(f, "error in {name}")?,
+        }
+        write!(f, " [{}]", phase.as_deref().unwrap_or("-"))?;
+        let agents = enabled_agents.iter().map(String::as_str).collect::<Vec<_>>();
+        write!(f, " {{{}}}", agents.join(", "))
+    }
+}
+
+/// Reconstructs the broker state after each decision.
+#[must_use]
+pub fn timeline(events: &[Event]) -> Vec<Step> {
+    let mut phase = None;
+    let mut enabled_agents = BTreeSet::new();
+    events
+        .iter()
+        .map(|event| {
+            match &event.kind {
+                EventKind::Start(agents) => {
+                    phase = None;
+                    enabled_agents = agents.iter().cloned().collect();
+                }
+                EventKind::AgentEnabled(name) => {
+                    enabled_agents.insert(name.clone());
+                }
+                EventKind::AgentDisabled(name) => {
+                    enabled_agents.remove(name);
+                }
+                EventKind::Phase(name) => phase = Some(name.clone()),
+                EventKind::Break(_) | EventKind::Error(_) => {}
+            }
+            Step {
+                event: event.clone(),
+                phase: phase.clone(),
+                enabled_agents: enabled_agents.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Extracts the broker event log from a debug report JSON.
+pub fn from_debug_report(json: &[u8]) -> Result<Vec<Event>> {
+    let mut report: serde_json::Value = serde_json::from_slice(json)?;
+    let events = report
+        .get_mut("broker_events")
+        .map(serde_json::Value::take)
+        .ok_or_else(|| eyre!("debug report has no broker events"))?;
+    Ok(serde_json::from_value(events)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline() {
+        let mut log = EventLog::default();
+        log.record(EventKind::Phase("Idle".into()));
+        log.start(vec!["mirror".into()]);
+        log.record(EventKind::Phase("Biometric capture".into()));
+        log.record(BrokerEvent::AgentEnabled("ir_eye_camera").into());
+        log.record(BrokerEvent::AgentDisabled("mirror").into());
+        log.record(BrokerEvent::Break("poll_extra").into());
+        let events = log.take();
+        assert_eq!(events.len(), 5);
+        log.record(EventKind::Phase("Idle".into()));
+        assert!(log.take().is_empty());
+
+        let json = serde_json::json!({ "signup_id": "x", "broker_events": events });
+        let restored = from_debug_report(json.to_string().as_bytes()).unwrap();
+        assert_eq!(restored, events);
+        assert!(from_debug_report(b"{}").is_err());
+
+        let steps = timeline(&restored);
+        let last = steps.last().unwrap();
+        assert_eq!(last.phase.as_deref(), Some("Biometric capture"));
+        assert_eq!(last.enabled_agents.iter().collect::<Vec<_>>(), ["ir_eye_camera"]);
+        assert!(last
+            .to_string()
+            .ends_with("break by poll_extra [Biometric capture] {ir_eye_camera}"));
+    }
+}
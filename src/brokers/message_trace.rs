@@ -0,0 +1,262 @@
+//! Sampled tracing of the orb broker messages.
+//!
+//! When enabled with a non-zero sample rate, the orb broker records a random
+//! sample of the agent outputs it dispatches: the source agent, the message
+//! type and variant, its size, and the queue time since the message was
+//! enqueued by the agent. The samples are aggregated per agent and message
+//! kind into a [`Summary`], which
+//! is attached to the debug report of each signup. [`Summary::folded`] exports
+//! it in the folded stack format understood by the flamegraph tools, so the
+//! fan-out hot spots can be found without attaching a profiler.
+
+use agentwire::port::{self, Port};
+use eyre::{eyre, Result};
+use rand::random;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    any::type_name,
+    collections::BTreeMap,
+    fmt::{self, Debug, Write as _},
+    mem::size_of,
+    time::Duration,
+};
+
+/// Orb broker message trace.
+#[derive(Default, Debug)]
+pub struct MessageTrace {
+    sample_rate: f64,
+    stats: BTreeMap<(&'static str, String), Accumulator>,
+}
+
+#[derive(Default, Debug)]
+struct Accumulator {
+    sampled: u64,
+    total_size: u64,
+    total_queue_time_us: u64,
+    max_queue_time_us: u64,
+}
+
+/// Aggregated message statistics of a signup.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Summary {
+    /// Fraction of the messages that were sampled.
+    pub sample_rate: f64,
+    /// Statistics per agent and message type.
+    pub messages: Vec<MessageStats>,
+}
+
+/// Statistics of one message type of an agent.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct MessageStats {
+    /// Source agent name.
+    pub agent: String,
+    /// Message type name, followed by the variant name for enums.
+    pub kind: String,
+    /// Number of sampled messages.
+    pub sampled: u64,
+    /// Estimated total number of messages.
+    pub estimated_count: u64,
+    /// Mean message size in bytes.
+    pub mean_size: u64,
+    /// Mean queue time in microseconds.
+    pub mean_queue_time_us: u64,
+    /// Maximum queue time in microseconds.
+    pub max_queue_time_us: u64,
+}
+
+/// Weight of the folded stacks.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Weight {
+    /// Estimated number of messages.
+    Count,
+    /// Estimated number of bytes.
+    Bytes,
+    /// Estimated total queue time in microseconds.
+    QueueTime,
+}
+
+impl MessageTrace {
+    /// Clears the trace and starts sampling the messages with the given rate.
+    /// A zero rate disables the tracing.
+    pub fn start(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate.clamp(0.0, 1.0);
+        self.stats.clear();
+    }
+
+    /// Samples an agent output. `payload_size` returns the size of the data
+    /// owned by the message outside of its shallow size, like the pixels of a
+    /// camera frame. It is called only for the sampled messages.
+    pub fn sample<A: Port>(
+        &mut self,
+        agent: &'static str,
+        output: &port::Output<A>,
+        payload_size: impl FnOnce(&A::Output) -> usize,
+    ) {
+        if self.sample_rate <= 0.0 || random::<f64>() >= self.sample_rate {
+            return;
+        }
+        let size = size_of::<A::Output>() + payload_size(&output.value);
+        let kind = kind(short_type_name(type_name::<A::Output>()), &output.value);
+        self.record(agent, kind, size, output.enqueue_ts.elapsed());
+    }
+
+    /// Returns the aggregated statistics and stops sampling. Returns `None` if
+    /// the tracing was disabled.
+    pub fn take(&mut self) -> Option<Summary> {
+        let sample_rate = std::mem::take(&mut self.sample_rate);
+        if sample_rate <= 0.0 {
+            return None;
+        }
+        let messages = std::mem::take(&mut self.stats)
+            .into_iter()
+            .map(|((agent, kind), accumulator)| accumulator.stats(agent, kind, sample_rate))
+            .collect();
+        Some(Summary { sample_rate, messages })
+    }
+
+    fn record(&mut self, agent: &'static str, kind: String, size: usize, queue: Duration) {
+        let queue_time_us = u64::try_from(queue.as_micros()).unwrap_or(u64::MAX);
+        let accumulator = self.stats.entry((agent, kind)).or_default();
+        accumulator.sampled += 1;
+        accumulator.total_size += size as u64;
+        accumulator.total_queue_time_us += queue_time_us;
+        accumulator.max_queue_time_us = accumulator.max_queue_time_us.max(queue_time_us);
+    }
+}
+
+impl Accumulator {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss, clippy::cast_sign_loss)]
+    fn stats(&self, agent: &str, kind: &str, sample_rate: f64) -> MessageStats {
+        MessageStats {
+            agent: agent.to_owned(),
+            kind: kind.to_owned(),
+            sampled: self.sampled,
+            estimated_count: (self.sampled as f64 / sample_rate).round() as u64,
+            mean_size: self.total_size / self.sampled,
+            mean_queue_time_us: self.total_queue_time_us / self.sampled,
+            max_queue_time_us: self.max_queue_time_us,
+        }
+    }
+}
+
+impl Summary {
+    /// Exports the statistics as folded stacks, one `orb;<agent>;<kind>
+    /// <weight>` line per message type.
+    #[must_use]
+    pub fn folded(&self, weight: Weight) -> String {
+        let mut folded = String::new();
+        for stats in &self.messages {
+            let value = match weight {
+                Weight::Count => stats.estimated_count,
+                Weight::Bytes => stats.estimated_count * stats.mean_size,
+                Weight::QueueTime => stats.estimated_count * stats.mean_queue_time_us,
+            };
+            writeln!(folded, "orb;{};{} {value}", stats.agent, stats.kind)
+                .expect("writing to a string can't fail");
+        }
+        folded
+    }
+}
+
+/// Extracts the broker message trace from a debug report JSON.
+pub fn from_debug_report(json: &[u8]) -> Result<Summary> {
+    let mut report: serde_json::Value = serde_json::from_slice(json)?;
+    let summary = report
+        .get_mut("broker_message_trace")
+        .map(serde_json::Value::take)
+        .filter(|summary| !summary.is_null())
+        .ok_or_else(|| eyre!("debug report has no broker message trace"))?;
+    Ok(serde_json::from_value(summary)?)
+}
+
+/// Returns the message kind: the type name, followed by the variant name if
+/// the value is an enum, e.g. `ir_net::Output::Estimate`.
+fn kind<T: Debug>(type_name: &str, value: &T) -> String {
+    let mut variant = VariantName(String::new());
+    // The formatting is aborted right after the variant name.
+    let _ = write!(variant, "{value:?}");
+    let VariantName(variant) = variant;
+    if variant.is_empty() || type_name.rsplit("::").next() == Some(variant.as_str()) {
+        type_name.to_owned()
+    } else {
+        format!("{type_name}::{variant}")
+    }
+}
+
+/// Collects the leading identifier of a [`Debug`] output.
+struct VariantName(String);
+
+impl fmt::Write for VariantName {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if !c.is_alphanumeric() && c != '_' {
+                return Err(fmt::Error);
+            }
+            self.0.push(c);
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the last two path segments of a type name, e.g. `ir_net::Output`.
+fn short_type_name(name: &'static str) -> &'static str {
+    let path = name.split('<').next().unwrap_or(name);
+    path.rmatch_indices("::").nth(1).map_or(name, |(i, _)| &name[i + 2..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_type_name() {
+        assert_eq!(short_type_name("orb::agents::python::ir_net::Output"), "ir_net::Output");
+        assert_eq!(short_type_name("ir_net::Output"), "ir_net::Output");
+        assert_eq!(short_type_name("u32"), "u32");
+    }
+
+    #[test]
+    fn test_kind() {
+        #[derive(Debug)]
+        enum Output {
+            Estimate { _distance: f64 },
+            Warmup,
+        }
+        #[derive(Debug)]
+        struct Frame(#[allow(dead_code)] Vec<u8>);
+        let estimate = Output::Estimate { _distance: 1.0 };
+        assert_eq!(kind("rgb_net::Output", &estimate), "rgb_net::Output::Estimate");
+        assert_eq!(kind("rgb_net::Output", &Output::Warmup), "rgb_net::Output::Warmup");
+        assert_eq!(kind("ir::Frame", &Frame(vec![0; 16])), "ir::Frame");
+        assert_eq!(kind("(i32, i32)", &(1, 2)), "(i32, i32)");
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut trace = MessageTrace::default();
+        trace.record("ir_eye_camera", "ir::Frame".to_owned(), 100, Duration::from_micros(10));
+        assert!(trace.take().is_none());
+
+        trace.start(0.5);
+        trace.record("ir_eye_camera", "ir::Frame".to_owned(), 100, Duration::from_micros(10));
+        trace.record("ir_eye_camera", "ir::Frame".to_owned(), 300, Duration::from_micros(30));
+        trace.record("mirror", "mirror::Point".to_owned(), 16, Duration::from_micros(1));
+        let summary = trace.take().unwrap();
+        assert!(trace.take().is_none());
+        assert_eq!(summary.messages.len(), 2);
+        let frames = &summary.messages[0];
+        assert_eq!(frames.estimated_count, 4);
+        assert_eq!(frames.mean_size, 200);
+        assert_eq!(frames.mean_queue_time_us, 20);
+        assert_eq!(frames.max_queue_time_us, 30);
+        assert_eq!(
+            summary.folded(Weight::Bytes),
+            "orb;ir_eye_camera;ir::Frame 800\norb;mirror;mirror::Point 32\n"
+        );
+
+        let json = serde_json::json!({ "signup_id": "x", "broker_message_trace": summary });
+        assert_eq!(from_debug_report(json.to_string().as_bytes()).unwrap(), summary);
+        assert!(from_debug_report(br#"{"broker_message_trace":null}"#).is_err());
+    }
+}
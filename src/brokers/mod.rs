@@ -1,7 +1,10 @@
 //! Collection of brokers.
 
+pub mod event_log;
+pub mod message_trace;
 mod observer;
 mod orb;
+pub mod snapshot;
 
 pub use self::{
     observer::{
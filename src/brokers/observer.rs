@@ -1,21 +1,28 @@
 #[cfg(feature = "stage")]
 use crate::process::Command;
 use crate::{
-    agents::{internal_temperature, thermal},
+    agents::{distance, internal_temperature, mirror, thermal},
     backend::status,
-    config::Config,
+    calibration,
+    config::{audit, Config},
     consts::{
         BATTERY_VOLTAGE_SHUTDOWN_IDLE_THRESHOLD_MV, BATTERY_VOLTAGE_SHUTDOWN_SIGNUP_THRESHOLD_MV,
-        BUTTON_DOUBLE_PRESS_DEAD_TIME, BUTTON_DOUBLE_PRESS_DURATION, BUTTON_LONG_PRESS_DURATION,
-        BUTTON_TRIPLE_PRESS_DURATION, CONFIG_UPDATE_INTERVAL, DEFAULT_MAX_FAN_SPEED,
-        GRACEFUL_SHUTDOWN_MAX_DELAY_SECONDS, SHUTDOWN_SOUND_DURATION, STATUS_UPDATE_INTERVAL,
+        BROWNOUT_SHUTDOWN_DELAY_SECONDS, BUTTON_DOUBLE_PRESS_DEAD_TIME,
+        BUTTON_DOUBLE_PRESS_DURATION, BUTTON_LONG_PRESS_DURATION, BUTTON_TRIPLE_PRESS_DURATION,
+        CONFIG_UPDATE_INTERVAL, DEFAULT_MAX_FAN_SPEED, GRACEFUL_SHUTDOWN_MAX_DELAY_SECONDS,
+        HAPTIC_FAN_PULSE_SPEED, MAXIMUM_FAN_SPEED, NIGHT_MODE_UPDATE_INTERVAL,
+        PRESENCE_MAX_DISTANCE_MM, SHUTDOWN_SOUND_DURATION, STATUS_UPDATE_INTERVAL,
     },
     dbus::SupervisorProxy,
-    dd_gauge, dd_incr,
+    dd_event, dd_gauge, dd_incr,
     ext::{broadcast::ReceiverExt as _, mpsc::SenderExt as _},
     identification::{GIT_VERSION, ORB_OS_VERSION},
     mcu::{self, main::Version, Mcu},
-    monitor, ssd, ui,
+    monitor::{self, net::Diagnosis},
+    plans::detect_face,
+    process::watchdog::Watchdog,
+    secure_element::{self, Counter},
+    ssd, ui,
 };
 use agentwire::{agent, port, Broker, BrokerFlow};
 use eyre::{bail, eyre, Error, Result, WrapErr};
@@ -195,8 +202,15 @@ impl Plan for DefaultPlan {
             let ui = observer.ui.clone();
             observer.config_update = Some(tokio::spawn(async move {
                 if let Ok(new_config) = Config::download().await {
-                    *config.lock().await = new_config;
-                    config.lock().await.propagate_to_ui(ui.as_ref());
+                    let mut config = config.lock().await;
+                    if !Config::stage(&config, &new_config) {
+                        return Ok(());
+                    }
+                    Config::replace(&mut config, new_config);
+                    config.propagate_to_ui(ui.as_ref());
+                    if let Err(err) = audit::record(audit::Source::Backend, &config).await {
+                        tracing::error!("Failed to record config changes: {err:?}");
+                    }
                 }
                 Ok(())
             }));
@@ -237,16 +251,30 @@ pub struct Observer {
     button_press_sequence: VecDeque<Instant>,
     config_update: Option<JoinHandle<Result<()>>>,
     config_update_interval: IntervalStream,
+    night_mode_interval: IntervalStream,
     status_update: Fuse<StatusUpdate>,
     status_update_interval: IntervalStream,
     status_request: status::Request,
     ssd_rx: mpsc::UnboundedReceiver<ssd::Stats>,
     log_line: String,
     last_fan_max_speed: f32,
+    last_fan_speed: Option<f32>,
+    fan_pre_cooling: bool,
+    thermal_predictor: monitor::thermal::Predictor,
     battery_is_not_charging_counter: u32,
     network_unblocked: bool,
     battery_tags: Vec<String>,
     signup_flag: Arc<AtomicBool>,
+    tof_distance: Arc<detect_face::presence::TofDistance>,
+    mirror_step_loss: mirror::step_loss::Shared,
+    brownout: mcu::main::brownout::Predictor,
+    occupancy: distance::occupancy::Counter,
+    haptics_rx: mpsc::UnboundedReceiver<ui::haptics::Cue>,
+    haptics: ui::haptics::Player,
+    haptics_timer: Fuse<Pin<Box<time::Sleep>>>,
+    gps: monitor::gps::Tracker,
+    watchdog: Watchdog,
+    watchdog_interval: Option<IntervalStream>,
 }
 
 /// [`Observer`] builder.
@@ -257,6 +285,10 @@ pub struct Builder {
     main_mcu: Option<Box<dyn Mcu<mcu::Main>>>,
     net_monitor: Option<Box<dyn monitor::net::Monitor>>,
     signup_flag: Option<Arc<AtomicBool>>,
+    tof_distance: Option<Arc<detect_face::presence::TofDistance>>,
+    mirror_step_loss: Option<mirror::step_loss::Shared>,
+    haptics_rx: Option<mpsc::UnboundedReceiver<ui::haptics::Cue>>,
+    watchdog: Option<Watchdog>,
 }
 
 type StatusUpdate = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
@@ -265,13 +297,26 @@ impl Builder {
     /// Builds a new [`Observer`].
     #[must_use]
     pub fn build(self) -> Observer {
-        let Self { config, ui: led, main_mcu, net_monitor, signup_flag } = self;
+        let Self {
+            config,
+            ui: led,
+            main_mcu,
+            net_monitor,
+            signup_flag,
+            tof_distance,
+            mirror_step_loss,
+            haptics_rx,
+            watchdog,
+        } = self;
         let (ssd_tx, ssd_rx) = mpsc::unbounded_channel();
         task::spawn(ssd_health_check(ssd_tx));
         let mut status_update_interval = time::interval(STATUS_UPDATE_INTERVAL);
         status_update_interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
         let mut status_request = status::Request::default();
         status_request.version.current_release.clone_from(&ORB_OS_VERSION);
+        let watchdog = watchdog.unwrap_or_default();
+        let watchdog_interval =
+            watchdog.interval().map(|interval| IntervalStream::new(time::interval(interval)));
         new_observer!(
             config: config.unwrap_or_default(),
             ui: led.unwrap_or_else(|| Box::new(ui::Fake)),
@@ -282,16 +327,30 @@ impl Builder {
             button_press_sequence: VecDeque::new(),
             config_update: None,
             config_update_interval: IntervalStream::new(time::interval(CONFIG_UPDATE_INTERVAL)),
+            night_mode_interval: IntervalStream::new(time::interval(NIGHT_MODE_UPDATE_INTERVAL)),
             status_update: Fuse::terminated(),
             status_update_interval: IntervalStream::new(status_update_interval),
             status_request,
             ssd_rx,
             log_line: String::new(),
             last_fan_max_speed: DEFAULT_MAX_FAN_SPEED,
+            last_fan_speed: None,
+            fan_pre_cooling: false,
+            thermal_predictor: monitor::thermal::Predictor::default(),
             battery_is_not_charging_counter: 0,
             network_unblocked: false,
             battery_tags: Vec::new(),
             signup_flag: signup_flag.unwrap_or_default(),
+            tof_distance: tof_distance.unwrap_or_default(),
+            mirror_step_loss: mirror_step_loss.unwrap_or_default(),
+            brownout: mcu::main::brownout::Predictor::default(),
+            occupancy: distance::occupancy::Counter::default(),
+            haptics_rx: haptics_rx.unwrap_or_else(|| ui::haptics::channel().1),
+            haptics: ui::haptics::Player::default(),
+            haptics_timer: Fuse::terminated(),
+            gps: monitor::gps::Tracker::default(),
+            watchdog,
+            watchdog_interval,
         )
     }
 
@@ -329,6 +388,34 @@ impl Builder {
         self.signup_flag = Some(signup_flag);
         self
     }
+
+    /// Sets the shared ToF distance.
+    #[must_use]
+    pub fn tof_distance(mut self, tof_distance: Arc<detect_face::presence::TofDistance>) -> Self {
+        self.tof_distance = Some(tof_distance);
+        self
+    }
+
+    /// Sets the shared mirror step-loss tracker.
+    #[must_use]
+    pub fn mirror_step_loss(mut self, mirror_step_loss: mirror::step_loss::Shared) -> Self {
+        self.mirror_step_loss = Some(mirror_step_loss);
+        self
+    }
+
+    /// Sets the silent-mode confirmation cue receiver.
+    #[must_use]
+    pub fn haptics(mut self, haptics_rx: mpsc::UnboundedReceiver<ui::haptics::Cue>) -> Self {
+        self.haptics_rx = Some(haptics_rx);
+        self
+    }
+
+    /// Sets the watchdog fed from the event loop.
+    #[must_use]
+    pub fn watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
 }
 
 impl Observer {
@@ -338,6 +425,53 @@ impl Observer {
         Builder::default()
     }
 
+    /// Compares the mirror motor range reported after a homing with the
+    /// reference one, and records a confirmed step loss or a range change in
+    /// the calibration drift history.
+    fn track_mirror_range(&self, output: &orb_messages::mcu_main::MotorRange) {
+        let motor = if output.which_motor
+            == orb_messages::mcu_main::motor_range::Motor::HorizontalPhi as i32
+        {
+            mirror::step_loss::Motor::Phi
+        } else {
+            mirror::step_loss::Motor::Theta
+        };
+        let event =
+            self.mirror_step_loss.lock().unwrap().motor_range(motor, output.range_microsteps);
+        if let Some(event) = event {
+            task::spawn(async move {
+                if let Err(err) = calibration::drift::record(event).await {
+                    tracing::error!("Failed to record mirror drift: {err:?}");
+                }
+            });
+        }
+    }
+
+    /// Flushes the state to disk when a brownout is predicted. Besides the
+    /// config, this syncs the file system, so the persisted upload queues and
+    /// the written debug reports survive a battery pull. The main MCU is asked
+    /// for a delayed shutdown, so it doesn't cut the power before the flush is
+    /// done.
+    fn flush_before_brownout(&mut self, anomaly: mcu::main::brownout::Anomaly) {
+        tracing::warn!("Brownout predicted, flushing state to disk: {anomaly:?}");
+        dd_incr!("main.count.system.brownout_predicted", &format!("type:{}", anomaly.rail));
+        if let Err(err) =
+            self.main_mcu.send_now(mcu::main::Input::Shutdown(BROWNOUT_SHUTDOWN_DELAY_SECONDS))
+        {
+            tracing::error!("Failed to ask the MCU for a delayed shutdown: {err:?}");
+        }
+        let config = Arc::clone(&self.config);
+        task::spawn(async move {
+            if let Err(err) = config.lock().await.store().await {
+                tracing::error!("Failed to store the config before brownout: {err:?}");
+            }
+            if let Err(err) = task::spawn_blocking(sync).await {
+                tracing::error!("Failed to sync the file system before brownout: {err:?}");
+            }
+            tracing::info!("State flushed before brownout");
+        });
+    }
+
     /// Shuts down the orb.
     pub async fn shutdown(&mut self) -> Result<Infallible> {
         dd_incr!("main.count.global.shutting_down");
@@ -399,6 +533,10 @@ impl Observer {
         let thermal_agent = self.thermal.enabled().expect("thermal agent is not enabled");
         thermal_agent.tx.send_now(port::Input::new(thermal::Input::JetsonCpu(cpu)))?;
         thermal_agent.tx.send_now(port::Input::new(thermal::Input::JetsonGpu(gpu)))?;
+        self.thermal_predictor.push(cpu, gpu);
+        if self.fan_pre_cooling && !monitor::thermal::is_pre_cooling() {
+            self.update_fan_speed(plan)?;
+        }
         self.status_request.temperature.cpu = f64::from(cpu);
         self.status_request.temperature.gpu = f64::from(gpu);
         self.status_request.temperature.ssd = f64::from(ssd);
@@ -416,21 +554,8 @@ impl Observer {
     ) -> Result<BrokerFlow> {
         match output.value {
             thermal::Output::FanSpeed(fan_speed) => {
-                if plan.is_fan_control_active() {
-                    let fan_max_speed = if let Some(config) = &self.config.lock().now_or_never() {
-                        config.fan_max_speed.unwrap_or(self.last_fan_max_speed)
-                    } else {
-                        self.last_fan_max_speed
-                    };
-                    self.last_fan_max_speed = fan_max_speed;
-
-                    let adjusted_fan_speed =
-                        (fan_speed * fan_max_speed / 100.0).clamp(1.0, DEFAULT_MAX_FAN_SPEED);
-                    tracing::trace!(
-                        "Setting FAN speed to {adjusted_fan_speed} (config max {fan_max_speed})"
-                    );
-                    self.main_mcu.send_now(mcu::main::Input::FanSpeed(adjusted_fan_speed))?;
-                }
+                self.last_fan_speed = Some(fan_speed);
+                self.update_fan_speed(plan)?;
             }
             thermal::Output::TemperatureLevel(temperature_level) => {
                 plan.handle_temperature_level(temperature_level)?;
@@ -439,12 +564,101 @@ impl Observer {
         Ok(BrokerFlow::Continue)
     }
 
+    /// Sends the latest thermal agent fan speed, or the maximum one while
+    /// pre-cooling.
+    fn update_fan_speed(&mut self, plan: &mut dyn Plan) -> Result<()> {
+        self.fan_pre_cooling = monitor::thermal::is_pre_cooling();
+        let fan_speed =
+            if self.fan_pre_cooling { Some(MAXIMUM_FAN_SPEED) } else { self.last_fan_speed };
+        let Some(fan_speed) = fan_speed else {
+            return Ok(());
+        };
+        // The speed is restored when the pulse ends.
+        if self.haptics.is_fan_pulse_active() {
+            return Ok(());
+        }
+        if plan.is_fan_control_active() {
+            let fan_max_speed = if let Some(config) = &self.config.lock().now_or_never() {
+                config.fan_max_speed.unwrap_or(self.last_fan_max_speed)
+            } else {
+                self.last_fan_max_speed
+            };
+            self.last_fan_max_speed = fan_max_speed;
+
+            let adjusted_fan_speed =
+                (fan_speed * fan_max_speed / 100.0).clamp(1.0, DEFAULT_MAX_FAN_SPEED);
+            tracing::trace!(
+                "Setting FAN speed to {adjusted_fan_speed} (config max {fan_max_speed})"
+            );
+            self.main_mcu.send_now(mcu::main::Input::FanSpeed(adjusted_fan_speed))?;
+        }
+        Ok(())
+    }
+
+    /// Pre-cools the orb if a user approaches and the Jetson is predicted to
+    /// throttle during the signup.
+    fn handle_user_approach(&mut self, plan: &mut dyn Plan, distance: u32) -> Result<()> {
+        if distance > PRESENCE_MAX_DISTANCE_MM || self.signup_flag.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let Some(lead_time) =
+            self.config.lock().now_or_never().map(|config| config.thermal_precool_lead_time)
+        else {
+            return Ok(());
+        };
+        if self.thermal_predictor.signup_expected(lead_time) {
+            self.update_fan_speed(plan)?;
+        }
+        Ok(())
+    }
+
+    /// Starts or stops the silent-mode confirmation cue output.
+    fn play_haptics(&mut self, plan: &mut dyn Plan) -> Result<()> {
+        let Some(action) = self.haptics.poll(Instant::now()) else {
+            return Ok(());
+        };
+        tracing::debug!("Silent-mode confirmation cue: {action:?}");
+        let cone_pattern =
+            |pattern| mcu::main::Input::ConeLedPattern(mcu::main::ConeLedControl { pattern });
+        match action {
+            ui::haptics::Action::Start(ui::haptics::Mode::FanPulse) => {
+                if plan.is_fan_control_active() {
+                    self.main_mcu.send_now(mcu::main::Input::FanSpeed(HAPTIC_FAN_PULSE_SPEED))?;
+                }
+            }
+            ui::haptics::Action::Stop(ui::haptics::Mode::FanPulse) => {
+                self.update_fan_speed(plan)?;
+            }
+            ui::haptics::Action::Start(ui::haptics::Mode::ConeFlash) => {
+                self.main_mcu.send_now(cone_pattern(mcu::main::ConeLedPattern::CustomRgb(
+                    mcu::main::Rgb(255, 255, 255, None),
+                )))?;
+            }
+            ui::haptics::Action::Stop(ui::haptics::Mode::ConeFlash) => {
+                self.main_mcu.send_now(cone_pattern(mcu::main::ConeLedPattern::Off))?;
+            }
+            ui::haptics::Action::Start(ui::haptics::Mode::Off)
+            | ui::haptics::Action::Stop(ui::haptics::Mode::Off) => {}
+        }
+        self.haptics_timer = self.haptics.deadline().map_or_else(Fuse::terminated, |deadline| {
+            Box::pin(time::sleep_until(deadline.into())).fuse()
+        });
+        Ok(())
+    }
+
     fn poll_extra(
         &mut self,
         plan: &mut dyn Plan,
         cx: &mut Context<'_>,
         _fence: Instant,
     ) -> Result<Option<Poll<()>>> {
+        if let Some(watchdog_interval) = &mut self.watchdog_interval {
+            while watchdog_interval.next().poll_unpin(cx).is_ready() {
+                if let Err(err) = self.watchdog.feed() {
+                    tracing::error!("Failed to feed the watchdog: {err:?}");
+                }
+            }
+        }
         while let Poll::Ready(report) = self.net_monitor.poll_next_unpin(cx) {
             self.network_unblocked = true;
             self.handle_net_monitor(report.ok_or_else(|| eyre!("network monitor exited"))?);
@@ -472,6 +686,26 @@ impl Observer {
                 bail!("SSD health check failed");
             }
         }
+        while let Poll::Ready(Some(cue)) = self.haptics_rx.poll_recv(cx) {
+            // The cues replace the audio cues only while the sound is off.
+            let mode = self.config.try_lock().map_or(ui::haptics::Mode::Off, |config| {
+                if config.sound_volume() == 0 {
+                    config.silent_confirmation
+                } else {
+                    ui::haptics::Mode::Off
+                }
+            });
+            self.haptics.queue(mode, cue, Instant::now());
+            self.play_haptics(plan)?;
+        }
+        if let Poll::Ready(()) = self.haptics_timer.poll_unpin(cx) {
+            self.play_haptics(plan)?;
+        }
+        while self.night_mode_interval.next().poll_unpin(cx).is_ready() {
+            if let Ok(config) = self.config.try_lock() {
+                ui::night_mode::update(&config, self.ui.as_ref());
+            }
+        }
         if self.network_unblocked {
             while self.config_update_interval.next().poll_unpin(cx).is_ready() {
                 plan.config_update(self)?;
@@ -490,6 +724,19 @@ impl Observer {
         match output {
             mcu::main::Output::SuccessAck(input) => {
                 log_mcu_success_ack(&input);
+                // Defer the confirmation cues, and cut the active one short,
+                // while the IR frames are being exposed.
+                match input {
+                    mcu::main::Input::TriggeringIrEyeCamera(triggering) => {
+                        self.haptics.set_ir_eye_triggering(triggering);
+                        self.play_haptics(plan)?;
+                    }
+                    mcu::main::Input::TriggeringIrFaceCamera(triggering) => {
+                        self.haptics.set_ir_face_triggering(triggering);
+                        self.play_haptics(plan)?;
+                    }
+                    _ => {}
+                }
                 plan.handle_mcu_success_ack(input)?;
             }
             mcu::main::Output::Button(pressed) => {
@@ -531,6 +778,9 @@ impl Observer {
             }
             mcu::main::Output::Voltage(voltage) => {
                 log_mcu_voltage(&voltage);
+                if let Some(anomaly) = self.brownout.update(&voltage) {
+                    self.flush_before_brownout(anomaly);
+                }
                 plan.handle_mcu_voltage(voltage)?;
             }
             mcu::main::Output::BatteryCapacity(capacity) => {
@@ -603,6 +853,7 @@ impl Observer {
             }
             mcu::main::Output::MotorRange(motor_range) => {
                 log_mcu_motor_range(&motor_range);
+                self.track_mirror_range(&motor_range);
                 plan.handle_mcu_motor_range(motor_range)?;
             }
             mcu::main::Output::FanStatus(status) => {
@@ -672,11 +923,19 @@ impl Observer {
                     &format!("main_mcu_secondary:{}", versions.secondary),
                     &format!("orb_core:{}", *GIT_VERSION)
                 );
+                check_mcu_firmware_rollback(versions.primary);
                 plan.handle_mcu_versions(versions)?;
             }
             mcu::main::Output::Gps(message) => {
                 self.handle_gps(plan, message)?;
             }
+            mcu::main::Output::Imu(_) => {}
+            mcu::main::Output::ConePresent(present) => {
+                tracing::info!("Cone {}", if present { "attached" } else { "detached" });
+            }
+            mcu::main::Output::Shutdown(shutdown) => {
+                tracing::warn!("MCU announced an imminent shutdown: {shutdown:?}");
+            }
             mcu::main::Output::Logs(logs) => {
                 self.log_line.push_str(&logs);
                 if self.log_line.contains('\n') {
@@ -685,6 +944,11 @@ impl Observer {
                 }
             }
             mcu::main::Output::TofDistance(distance) => {
+                self.tof_distance.record(distance);
+                if let Some(report) = self.occupancy.push(distance, Instant::now()) {
+                    report.publish();
+                }
+                self.handle_user_approach(plan, distance)?;
                 plan.handle_mcu_tof_distance(distance)?;
             }
             mcu::main::Output::HardwareDiag(diag) => {
@@ -749,22 +1013,17 @@ impl Observer {
         plan: &mut dyn Plan,
         message: nmea_parser::ParsedMessage,
     ) -> Result<()> {
-        let (latitude, longitude, satellite_count) = match message {
-            nmea_parser::ParsedMessage::Gga(message) => {
-                (message.latitude, message.longitude, message.satellite_count)
-            }
-            nmea_parser::ParsedMessage::Gll(message) => (message.latitude, message.longitude, None),
-            nmea_parser::ParsedMessage::Gns(message) => {
-                (message.latitude, message.longitude, message.satellite_count)
-            }
-            nmea_parser::ParsedMessage::Rmc(message) => (message.latitude, message.longitude, None),
-            _ => (None, None, None),
-        };
-        if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
-            self.status_request.location.latitude = latitude;
-            self.status_request.location.longitude = longitude;
-        }
-        plan.handle_gps(latitude, longitude, satellite_count)?;
+        self.gps.push(&message, Instant::now());
+        let location = self.gps.location(Instant::now());
+        if let Some(location) = &location {
+            self.status_request.location.latitude = location.latitude;
+            self.status_request.location.longitude = location.longitude;
+        }
+        plan.handle_gps(
+            location.as_ref().map(|location| location.latitude),
+            location.as_ref().map(|location| location.longitude),
+            location.as_ref().and_then(|location| location.satellite_count),
+        )?;
         Ok(())
     }
 
@@ -775,12 +1034,15 @@ impl Observer {
             dd_gauge!("main.gauge.system.connectivity.ping_time", report.lag.to_string());
             dd_gauge!("main.gauge.system.connectivity.rssi", report.rssi.to_string());
         }
-        if report.is_no_internet() {
-            self.ui.no_internet();
-        } else if report.is_slow_internet() {
-            self.ui.slow_internet();
-        } else {
-            self.ui.good_internet();
+        // The reachability matrix tells the operator which endpoint fails.
+        match report.reachability.map_or(Diagnosis::Ok, |reachability| reachability.diagnosis()) {
+            Diagnosis::NoInternet => self.ui.no_internet(),
+            Diagnosis::DnsFailure => self.ui.dns_failure(),
+            Diagnosis::BackendUnreachable => self.ui.backend_unreachable(),
+            Diagnosis::RelayUnreachable => self.ui.relay_unreachable(),
+            Diagnosis::Ok if report.is_no_internet() => self.ui.no_internet(),
+            Diagnosis::Ok if report.is_slow_internet() => self.ui.slow_internet(),
+            Diagnosis::Ok => self.ui.good_internet(),
         }
         if report.is_no_wlan() {
             self.ui.no_wlan();
@@ -840,6 +1102,10 @@ fn log_mcu_success_ack(input: &mcu::main::Input) {
         | mcu::main::Input::MirrorRelative(_, _)
         | mcu::main::Input::PerformMirrorHoming(..)
         | mcu::main::Input::Shutdown(_)
+        | mcu::main::Input::Reboot(_)
+        | mcu::main::Input::FirmwareBlock { .. }
+        | mcu::main::Input::FirmwareImageCheck(_)
+        | mcu::main::Input::FirmwareActivateSecondary
         | mcu::main::Input::Temperature(..)
         | mcu::main::Input::TofTiming(_)
         | mcu::main::Input::TriggeringIrEyeCamera(_)
@@ -859,7 +1125,8 @@ fn log_mcu_success_ack(input: &mcu::main::Input) {
         | mcu::main::Input::IrEyeCameraFocusSweepValuesPolynomial(_)
         | mcu::main::Input::PerformIrEyeCameraFocusSweep
         | mcu::main::Input::IrEyeCameraMirrorSweepValuesPolynomial(_)
-        | mcu::main::Input::PerformIrEyeCameraMirrorSweep => {}
+        | mcu::main::Input::PerformIrEyeCameraMirrorSweep
+        | mcu::main::Input::Heartbeat(_) => {}
     }
 }
 
@@ -1698,3 +1965,32 @@ fn log_battery_state_of_health(
     );
     tags.pop();
 }
+
+/// Checks the running main MCU firmware against the Secure Element
+/// anti-rollback counter, reporting a downgrade to an older firmware as a
+/// Datadog event.
+fn check_mcu_firmware_rollback(version: Version) {
+    let value = version.counter_value();
+    if value == 0 {
+        return;
+    }
+    task::spawn_blocking(move || {
+        match secure_element::check_rollback(Counter::McuFirmware, value, true) {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::error!(
+                    "Main MCU firmware {version} is older than the previously seen firmware"
+                );
+                dd_event!(
+                    "MCU firmware rollback",
+                    format!(
+                        "Main MCU firmware {version} is older than the firmware recorded by the \
+                         Secure Element anti-rollback counter"
+                    ),
+                    &format!("main_mcu:{version}")
+                );
+            }
+            Err(err) => tracing::error!("MCU firmware anti-rollback check failed: {err:?}"),
+        }
+    });
+}
@@ -1,41 +1,59 @@
-use super::process_logger;
+use super::{
+    event_log::{self, EventLog},
+    message_trace::{self, MessageTrace},
+    process_logger,
+    snapshot::{OperatorSession, Snapshot},
+};
 #[cfg(feature = "livestream")]
 use crate::agents::livestream;
 use crate::{
     agents::{
-        camera, data_uploader, distance, eye_pid_controller, eye_tracker, image_notary,
+        camera::{self, Frame as _},
+        data_uploader, distance, eye_pid_controller, eye_tracker, image_notary, imu,
         ir_auto_exposure, ir_auto_focus, mirror,
         python::{
-            face_identifier, ir_net, mega_agent_one,
+            cancellation, face_identifier, ir_net, mega_agent_one,
             mega_agent_two::{self, FusionErrors},
             rgb_net,
         },
         qr_code,
     },
     calibration::Calibration,
-    config::Config,
+    config::{Config, Subsystem},
     consts::{
-        CALIBRATION_FILE_PATH, DBUS_SIGNUP_OBJECT_PATH, DBUS_WELL_KNOWN_BUS_NAME,
-        DEFAULT_IR_LED_DURATION, DEFAULT_IR_LED_WAVELENGTH, IR_CAMERA_FRAME_RATE,
-        IR_LED_MAX_DURATION, IR_LED_MAX_DURATION_740NM, IR_LED_MIN_DURATION,
-        MIRROR_PHI_MAX_DIAMOND, MIRROR_PHI_MAX_PEARL, MIRROR_PHI_MIN_DIAMOND, MIRROR_PHI_MIN_PEARL,
-        MIRROR_THETA_MAX_DIAMOND, MIRROR_THETA_MAX_PEARL, MIRROR_THETA_MIN_DIAMOND,
-        MIRROR_THETA_MIN_PEARL,
+        BROKER_SNAPSHOT_INTERVAL, CALIBRATION_FILE_PATH, DBUS_IDENTITY_OBJECT_PATH,
+        DBUS_MAINTENANCE_OBJECT_PATH, DBUS_NETWORK_OBJECT_PATH, DBUS_SIGNUP_OBJECT_PATH,
+        DBUS_UPLOADS_OBJECT_PATH, DBUS_WELL_KNOWN_BUS_NAME, DEFAULT_IR_LED_DURATION,
+        DEFAULT_IR_LED_WAVELENGTH, IR_CAMERA_FRAME_RATE, IR_LED_MAX_DURATION,
+        IR_LED_MAX_DURATION_740NM, IR_LED_MIN_DURATION, MIRROR_PHI_MAX_DIAMOND,
+        MIRROR_PHI_MAX_PEARL, MIRROR_PHI_MIN_DIAMOND, MIRROR_PHI_MIN_PEARL,
+        MIRROR_QUICK_HOMING_DURATION, MIRROR_THETA_MAX_DIAMOND, MIRROR_THETA_MAX_PEARL,
+        MIRROR_THETA_MIN_DIAMOND, MIRROR_THETA_MIN_PEARL, USER_DISTANCE_MAX_AGE,
     },
-    ext::mpsc::SenderExt as _,
+    dd_gauge, dd_incr,
+    ext::{broadcast::ReceiverExt as _, mpsc::SenderExt as _},
     identification,
     image::fisheye,
-    mcu::{self, main::IrLed, Mcu},
+    mcu::{
+        self,
+        main::{IrLed, MirrorHomingAngle, MirrorHomingMode},
+        Mcu,
+    },
     monitor,
-    plans::biometric_capture::{EyeCapture, SelfCustodyCandidate},
+    plans::{
+        biometric_capture::{mirror_sweep::fit::Limits, EyeCapture, SelfCustodyCandidate},
+        detect_face, OperatorData,
+    },
+    process::watchdog::Heartbeat,
     ui,
 };
-use agentwire::{agent, port, Broker, BrokerFlow};
+use agentwire::{agent, port, Broker, BrokerEvent, BrokerFlow};
 use eyre::{bail, Error, Result};
 use futures::{channel::mpsc, future::BoxFuture, prelude::*};
 use orb_wld_data_id::SignupId;
 use std::{
     collections::VecDeque,
+    mem,
     ops::RangeInclusive,
     sync::Arc,
     task::{Context, Poll},
@@ -43,12 +61,13 @@ use std::{
 };
 use tokio::{
     process::{ChildStderr, ChildStdout},
-    sync::Mutex,
+    sync::{watch, Mutex},
     time::sleep,
 };
+use tokio_stream::wrappers::WatchStream;
 
 #[cfg(feature = "internal-data-acquisition")]
-use crate::agents::image_uploader;
+use crate::{agents::image_uploader, consts::DEPTH_SAVE_FPS, utils::sample_at_fps};
 
 // Give the IR camera enough time to fetch the last frame before external_trigger stops.
 // Give it time to take 1-2 frames.
@@ -173,6 +192,42 @@ pub trait Plan {
         Ok(BrokerFlow::Continue)
     }
 
+    fn handle_imu(
+        &mut self,
+        _orb: &mut Orb,
+        _output: port::Output<imu::Agent>,
+    ) -> Result<BrokerFlow> {
+        Ok(BrokerFlow::Continue)
+    }
+
+    /// Handles the Diamond cone being attached or detached.
+    fn handle_cone_present(&mut self, _orb: &mut Orb, _present: bool) -> Result<BrokerFlow> {
+        Ok(BrokerFlow::Continue)
+    }
+
+    /// Handles an imminent shutdown announced by the main MCU. Plans can
+    /// checkpoint their state here before the power is cut.
+    fn handle_mcu_shutdown(
+        &mut self,
+        _orb: &mut Orb,
+        _shutdown: orb_messages::mcu_main::ShutdownScheduled,
+    ) -> Result<BrokerFlow> {
+        Ok(BrokerFlow::Continue)
+    }
+
+    /// Handles a remote control command from the livestream. The commands are
+    /// ignored by default, and enabled only by the plans running outside of
+    /// signups.
+    #[cfg(feature = "livestream")]
+    fn handle_livestream(
+        &mut self,
+        _orb: &mut Orb,
+        output: port::Output<livestream::Agent>,
+    ) -> Result<BrokerFlow> {
+        tracing::warn!("Ignoring livestream command in the current plan: {:?}", output.value);
+        Ok(BrokerFlow::Continue)
+    }
+
     fn poll_extra(&mut self, _orb: &mut Orb, _cx: &mut Context<'_>) -> Result<BrokerFlow> {
         Ok(BrokerFlow::Continue)
     }
@@ -181,7 +236,7 @@ pub trait Plan {
 /// The main Orb broker.
 #[allow(missing_docs, clippy::struct_excessive_bools)]
 #[derive(Broker)]
-#[broker(plan = Plan, error = Error, poll_extra)]
+#[broker(plan = Plan, error = Error, poll_extra, on_event)]
 pub struct Orb {
     #[agent(thread, init)]
     pub ir_eye_camera: agent::Cell<camera::ir::Sensor>,
@@ -209,6 +264,8 @@ pub struct Orb {
     pub mirror: agent::Cell<mirror::Actuator>,
     #[agent(task, init)]
     pub distance: agent::Cell<distance::Agent>,
+    #[agent(task, init)]
+    pub imu: agent::Cell<imu::Agent>,
     #[agent(process, init_async, logger = self.process_logger().await)]
     pub qr_code: agent::Cell<qr_code::Agent>,
     #[cfg(feature = "internal-data-acquisition")]
@@ -227,6 +284,9 @@ pub struct Orb {
     pub main_mcu: Box<dyn Mcu<mcu::Main>>,
     pub net_monitor: Box<dyn monitor::net::Monitor>,
     pub cpu_monitor: Box<dyn monitor::cpu::Monitor>,
+    pub tof_distance: Arc<detect_face::presence::TofDistance>,
+    pub mirror_step_loss: mirror::step_loss::Shared,
+    pub heartbeat: Heartbeat,
     pub orb_relay: Option<orb_relay_client::client::Client>,
     pub dbus_conn: Option<zbus::Connection>,
     pub state_rx: Option<StateRx>,
@@ -243,6 +303,28 @@ pub struct Orb {
     ir_net_frames: VecDeque<(camera::ir::Frame, Instant)>,
     rgb_net_enabled: bool,
     rgb_net_frames: VecDeque<(camera::rgb::Frame, Instant)>,
+    rgb_net_tracker: rgb_net::tracker::Tracker,
+    imu_bad_mounting_reported: bool,
+    /// Separate main MCU handle, so the broker receives the MCU events
+    /// independently of the plans consuming [`Orb::main_mcu`].
+    main_mcu_events: Box<dyn Mcu<mcu::Main>>,
+    /// Fence of the current [`Orb::run`], to drop the MCU events buffered
+    /// since the previous plan.
+    main_mcu_events_fence: Option<Instant>,
+    cone_present: Option<bool>,
+    thermal_aligner: camera::thermal::alignment::Aligner,
+    thermal_camera_sensor: monitor::thermal::CameraSensor,
+    depth_camera_range: camera::depth::Range,
+    /// Timestamp of the last depth frame sent to the image notary.
+    #[cfg_attr(not(feature = "internal-data-acquisition"), allow(dead_code))]
+    depth_save_time: Duration,
+    /// Whether the livestream is being recorded.
+    #[cfg_attr(not(feature = "livestream"), allow(dead_code))]
+    livestream_recording: bool,
+    lens_dirt: camera::smudge::Monitor,
+    frame_drops: camera::drops::FrameDrops,
+    data_uploader_control: Arc<Mutex<tokio::sync::mpsc::Receiver<data_uploader::Control>>>,
+    maintenance_mode: watch::Receiver<bool>,
 
     state_tx: StateTx,
     calibration: Calibration,
@@ -250,7 +332,14 @@ pub struct Orb {
     ir_led_wavelength: IrLed,
     ir_led_duration: u16,
     ir_auto_focus_use_rgb_net_estimate: bool,
+    rgb_net_user_distance: Option<(f64, Instant)>,
     rgb_camera_fake_port: Option<port::Outer<camera::rgb::Sensor>>,
+    phase: Option<&'static str>,
+    operator_session: Option<OperatorSession>,
+    last_snapshot: Option<Instant>,
+    event_log: EventLog,
+    message_trace: MessageTrace,
+    signup_queue: ui::queue::Indicator,
 }
 
 /// [`Orb`] builder.
@@ -261,7 +350,11 @@ pub struct Builder {
     main_mcu: Option<Box<dyn Mcu<mcu::Main>>>,
     net_monitor: Option<Box<dyn monitor::net::Monitor>>,
     cpu_monitor: Option<Box<dyn monitor::cpu::Monitor>>,
+    tof_distance: Option<Arc<detect_face::presence::TofDistance>>,
+    mirror_step_loss: Option<mirror::step_loss::Shared>,
+    heartbeat: Option<Heartbeat>,
     enable_state_rx: bool,
+    disable_dbus: bool,
     rgb_camera_fake_port: Option<port::Outer<camera::rgb::Sensor>>,
 }
 
@@ -283,11 +376,22 @@ struct StateTx {
     rgb_camera_state: Option<mpsc::Sender<camera::State>>,
 }
 
-async fn init_dbus() -> zbus::Result<zbus::Connection> {
+async fn init_dbus(
+    data_uploader_control: tokio::sync::mpsc::Sender<data_uploader::Control>,
+    net_monitor: Box<dyn monitor::net::Monitor>,
+    maintenance_mode: watch::Sender<bool>,
+) -> zbus::Result<zbus::Connection> {
     Box::pin(
         zbus::ConnectionBuilder::session()?
             .name(DBUS_WELL_KNOWN_BUS_NAME)?
             .serve_at(DBUS_SIGNUP_OBJECT_PATH, crate::dbus::Signup)?
+            .serve_at(DBUS_UPLOADS_OBJECT_PATH, crate::dbus::Uploads::new(data_uploader_control))?
+            .serve_at(DBUS_NETWORK_OBJECT_PATH, crate::dbus::Network::new(net_monitor))?
+            .serve_at(DBUS_IDENTITY_OBJECT_PATH, crate::dbus::Identity)?
+            .serve_at(
+                DBUS_MAINTENANCE_OBJECT_PATH,
+                crate::dbus::Maintenance::new(maintenance_mode),
+            )?
             .build(),
     )
     .await
@@ -302,7 +406,11 @@ impl Builder {
             main_mcu,
             net_monitor,
             cpu_monitor,
+            tof_distance,
+            mirror_step_loss,
+            heartbeat,
             enable_state_rx,
+            disable_dbus,
             rgb_camera_fake_port,
         } = self;
         let config = config.unwrap_or_default();
@@ -330,23 +438,38 @@ impl Builder {
         } else {
             (StateTx::default(), None)
         };
-        let dbus_conn = init_dbus()
-            .await
-            .map_err(|err| {
-                tracing::error!(
-                    "failed to initialize dbus connection, leaving disabled; error: {err}"
-                );
-            })
-            .ok();
+        let (data_uploader_control_tx, data_uploader_control_rx) = tokio::sync::mpsc::channel(4);
+        let (maintenance_mode_tx, maintenance_mode_rx) = watch::channel(false);
+        let dbus_net_monitor = net_monitor.as_ref().map_or_else(
+            || Box::new(monitor::net::Fake) as Box<dyn monitor::net::Monitor>,
+            |net_monitor| net_monitor.clone(),
+        );
+        let dbus_conn = if disable_dbus {
+            None
+        } else {
+            init_dbus(data_uploader_control_tx, dbus_net_monitor, maintenance_mode_tx)
+                .await
+                .map_err(|err| {
+                    tracing::error!(
+                        "failed to initialize dbus connection, leaving disabled; error: {err}"
+                    );
+                })
+                .ok()
+        };
         let ir_eye_save_fps_override = config.lock().await.ir_eye_save_fps_override;
         let ir_face_save_fps_override = config.lock().await.ir_face_save_fps_override;
         let thermal_save_fps_override = config.lock().await.thermal_save_fps_override;
+        let main_mcu = main_mcu.unwrap_or_else(|| Box::<mcu::main::Fake>::default());
+        let main_mcu_events = main_mcu.clone();
         Ok(new_orb!(
             config,
             ui: ui.unwrap_or_else(|| Box::new(ui::Fake)),
-            main_mcu: main_mcu.unwrap_or_else(|| Box::<mcu::main::Fake>::default()),
+            main_mcu,
             net_monitor: net_monitor.unwrap_or_else(|| Box::new(monitor::net::Fake)),
             cpu_monitor: cpu_monitor.unwrap_or_else(|| Box::new(monitor::cpu::Fake)),
+            tof_distance: tof_distance.unwrap_or_default(),
+            mirror_step_loss: mirror_step_loss.unwrap_or_default(),
+            heartbeat: heartbeat.unwrap_or_default(),
             orb_relay: None,
             dbus_conn,
             calibration,
@@ -362,12 +485,33 @@ impl Builder {
             ir_net_frames: VecDeque::new(),
             rgb_net_enabled: false,
             rgb_net_frames: VecDeque::new(),
+            rgb_net_tracker: rgb_net::tracker::Tracker::default(),
+            imu_bad_mounting_reported: false,
+            main_mcu_events,
+            main_mcu_events_fence: None,
+            cone_present: None,
+            thermal_aligner: camera::thermal::alignment::Aligner::default(),
+            thermal_camera_sensor: monitor::thermal::CameraSensor::default(),
+            depth_camera_range: camera::depth::Range::default(),
+            depth_save_time: Duration::ZERO,
+            livestream_recording: false,
+            lens_dirt: camera::smudge::Monitor::default(),
+            frame_drops: camera::drops::FrameDrops::default(),
+            data_uploader_control: Arc::new(Mutex::new(data_uploader_control_rx)),
+            maintenance_mode: maintenance_mode_rx,
             ir_led_wavelength: DEFAULT_IR_LED_WAVELENGTH,
             ir_led_duration: DEFAULT_IR_LED_DURATION,
             ir_auto_focus_use_rgb_net_estimate: true,
+            rgb_net_user_distance: None,
             state_tx,
             state_rx,
             rgb_camera_fake_port,
+            phase: None,
+            operator_session: None,
+            last_snapshot: None,
+            event_log: EventLog::default(),
+            message_trace: MessageTrace::default(),
+            signup_queue: ui::queue::Indicator::default(),
         ))
     }
 
@@ -406,6 +550,27 @@ impl Builder {
         self
     }
 
+    /// Sets the shared ToF distance.
+    #[must_use]
+    pub fn tof_distance(mut self, tof_distance: Arc<detect_face::presence::TofDistance>) -> Self {
+        self.tof_distance = Some(tof_distance);
+        self
+    }
+
+    /// Sets the shared mirror step-loss tracker.
+    #[must_use]
+    pub fn mirror_step_loss(mut self, mirror_step_loss: mirror::step_loss::Shared) -> Self {
+        self.mirror_step_loss = Some(mirror_step_loss);
+        self
+    }
+
+    /// Sets the watchdog heartbeat beaten from the event loop.
+    #[must_use]
+    pub fn heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
+
     /// Sets `enable_state_rx`.
     #[must_use]
     pub fn enable_state_rx(mut self, enable_state_rx: bool) -> Self {
@@ -413,6 +578,14 @@ impl Builder {
         self
     }
 
+    /// Sets `disable_dbus`. When set, the orb-core DBus interfaces are not
+    /// served, leaving the bus name to the orb-core process.
+    #[must_use]
+    pub fn disable_dbus(mut self, disable_dbus: bool) -> Self {
+        self.disable_dbus = disable_dbus;
+        self
+    }
+
     /// Sets `rgb_camera_fake_port`.
     #[must_use]
     pub fn rgb_camera_fake_port(
@@ -458,7 +631,10 @@ impl Orb {
 
     /// Sets active IR LED wavelength.
     pub async fn set_ir_wavelength(&mut self, ir_led_wavelength: IrLed) -> Result<()> {
-        self.main_mcu.send(mcu::main::Input::IrLed(ir_led_wavelength)).await?;
+        let command = mcu::main::Input::IrLed(ir_led_wavelength);
+        #[cfg(feature = "livestream")]
+        self.record_mcu_command(&command)?;
+        self.main_mcu.send(command).await?;
         self.ir_led_wavelength = ir_led_wavelength;
         let exposure_range = self.exposure_range();
         if let Some(ir_auto_exposure) = self.ir_auto_exposure.enabled() {
@@ -471,16 +647,38 @@ impl Orb {
         Ok(())
     }
 
+    /// Performs a quick mirror rehoming if a step loss is suspected since the
+    /// previous homing. The motor ranges reported after the homing confirm
+    /// the step loss, see [`mirror::step_loss`].
+    ///
+    /// # Panics
+    ///
+    /// If the step-loss tracker mutex is poisoned
+    pub async fn rehome_mirror_on_step_loss(&mut self) -> Result<()> {
+        if !self.mirror_step_loss.lock().unwrap().take_suspected() {
+            return Ok(());
+        }
+        tracing::warn!("Mirror step loss suspected, rehoming");
+        let command = mcu::main::Input::PerformMirrorHoming(
+            MirrorHomingMode::OneBlockingEnd,
+            MirrorHomingAngle::Both,
+        );
+        #[cfg(feature = "livestream")]
+        self.record_mcu_command(&command)?;
+        self.main_mcu.send(command).await?;
+        sleep(MIRROR_QUICK_HOMING_DURATION).await;
+        Ok(())
+    }
+
     /// Sets active IR LED PWM duration.
     pub fn set_ir_duration(&mut self, ir_led_duration: u16) -> Result<()> {
-        match self.ir_led_wavelength {
-            IrLed::L740 => {
-                self.main_mcu.send_now(mcu::main::Input::IrLedDuration740nm(ir_led_duration))?;
-            }
-            _ => {
-                self.main_mcu.send_now(mcu::main::Input::IrLedDuration(ir_led_duration))?;
-            }
-        }
+        let command = match self.ir_led_wavelength {
+            IrLed::L740 => mcu::main::Input::IrLedDuration740nm(ir_led_duration),
+            _ => mcu::main::Input::IrLedDuration(ir_led_duration),
+        };
+        #[cfg(feature = "livestream")]
+        self.record_mcu_command(&command)?;
+        self.main_mcu.send_now(command)?;
         self.ir_led_duration = ir_led_duration;
         Ok(())
     }
@@ -509,6 +707,45 @@ impl Orb {
         Ok(())
     }
 
+    /// Reports a change of a camera lens dirt state to the operator and the
+    /// telemetry.
+    fn report_lens_dirt(&mut self, camera: camera::smudge::Camera, dirty: Option<bool>) {
+        let Some(dirty) = dirty else {
+            return;
+        };
+        let ratio = self.lens_dirt.detector(camera).sharpness_ratio();
+        if dirty {
+            tracing::warn!("{camera:?} camera lens is likely dirty, sharpness ratio: {ratio:.2}");
+        } else {
+            tracing::info!("{camera:?} camera lens is clean, sharpness ratio: {ratio:.2}");
+        }
+        dd_incr!(
+            "main.count.camera.lens_dirt",
+            &format!("camera:{}", camera.name()),
+            &format!("dirty:{dirty}")
+        );
+        dd_gauge!(
+            "main.gauge.camera.lens_sharpness_ratio",
+            ratio.to_string(),
+            &format!("camera:{}", camera.name())
+        );
+        let ui_camera = match camera {
+            camera::smudge::Camera::Rgb => ui::LensCamera::Rgb,
+            camera::smudge::Camera::IrEye => ui::LensCamera::IrEye,
+            camera::smudge::Camera::IrFace => ui::LensCamera::IrFace,
+        };
+        self.ui.lens_dirty(ui_camera, dirty);
+    }
+
+    /// Returns the current capture conditions for the saved images.
+    #[must_use]
+    pub fn capture_context(&self) -> image_notary::metadata::CaptureContext {
+        image_notary::metadata::CaptureContext {
+            exposure: Some(self.ir_led_duration),
+            mirror: self.mirror_point.map(|point| point + self.mirror_offset.unwrap_or_default()),
+        }
+    }
+
     /// Returns a reference to the mirror calibration.
     #[must_use]
     pub fn calibration(&self) -> &Calibration {
@@ -656,82 +893,231 @@ impl Orb {
         Ok(())
     }
 
-    /// Starts the thermal camera
-    ///
-    /// # Panics
-    ///
-    /// If the camera agent is not enabled
+    /// Starts the thermal camera unless its kill switch is engaged. Runs a
+    /// fresh flat field correction and holds the shutter until the camera is
+    /// stopped, so the capture isn't interrupted by shutter events.
     pub async fn start_thermal_camera(&mut self) -> Result<()> {
+        if self.is_killed(Subsystem::ThermalCamera).await {
+            return Ok(());
+        }
         #[cfg(feature = "livestream")]
         if let Some(livestream) = self.livestream.enabled() {
             livestream.send(port::Input::new(livestream::Input::ThermalState(true))).await?;
         }
         self.enable_thermal_camera().await?;
-        self.thermal_camera
-            .enabled()
-            .unwrap()
-            .send(port::Input::new(camera::thermal::Command::Start))
+        let thermal_camera = self.thermal_camera.enabled().unwrap();
+        thermal_camera.send(port::Input::new(camera::thermal::Command::Start)).await?;
+        thermal_camera.send(port::Input::new(camera::thermal::Command::TriggerFfc)).await?;
+        thermal_camera
+            .send(port::Input::new(camera::thermal::Command::SetShutterMode(
+                camera::thermal::ShutterMode::Manual,
+            )))
             .await?;
         Ok(())
     }
 
-    /// Stops the thermal camera
-    ///
-    /// # Panics
-    ///
-    /// If the camera agent is not enabled
+    /// Stops the thermal camera. Does nothing if the camera wasn't started.
     pub async fn stop_thermal_camera(&mut self) -> Result<()> {
+        if !self.thermal_camera.is_enabled() {
+            return Ok(());
+        }
         #[cfg(feature = "livestream")]
         if let Some(livestream) = self.livestream.enabled() {
             livestream.send(port::Input::new(livestream::Input::ThermalState(false))).await?;
         }
-        self.thermal_camera
-            .enabled()
-            .expect("thermal camera is not enabled")
-            .send(port::Input::new(camera::thermal::Command::Stop))
+        let thermal_camera = self.thermal_camera.enabled().unwrap();
+        thermal_camera
+            .send(port::Input::new(camera::thermal::Command::SetShutterMode(
+                camera::thermal::ShutterMode::Auto,
+            )))
             .await?;
+        thermal_camera.send(port::Input::new(camera::thermal::Command::Stop)).await?;
         self.disable_thermal_camera();
+        self.thermal_aligner.clear();
         Ok(())
     }
 
-    /// Starts the depth camera
-    ///
-    /// # Panics
-    ///
-    /// If the camera agent is not enabled
+    /// Stops the cameras, disables the IR LEDs, and parks the mirror in the
+    /// center of its range after homing, so the orb can be safely serviced.
+    pub async fn park_hardware(&mut self) -> Result<()> {
+        if self.ir_eye_camera.is_enabled() {
+            self.stop_ir_eye_camera().await?;
+        }
+        if self.ir_face_camera.is_enabled() {
+            self.stop_ir_face_camera().await?;
+        }
+        if self.rgb_camera.is_enabled() {
+            self.stop_rgb_camera().await?;
+        }
+        self.stop_thermal_camera().await?;
+        self.stop_depth_camera().await?;
+        self.disable_ir_led().await?;
+        self.main_mcu.send(mcu::main::Input::LiquidLens(None)).await?;
+        let command = mcu::main::Input::PerformMirrorHoming(
+            MirrorHomingMode::OneBlockingEnd,
+            MirrorHomingAngle::Both,
+        );
+        #[cfg(feature = "livestream")]
+        self.record_mcu_command(&command)?;
+        self.main_mcu.send(command).await?;
+        sleep(MIRROR_QUICK_HOMING_DURATION).await;
+        let (phi, theta) = Limits::for_hardware().calibrated_center(&self.calibration);
+        let command = mcu::main::Input::Mirror(phi, theta);
+        #[cfg(feature = "livestream")]
+        self.record_mcu_command(&command)?;
+        self.main_mcu.send(command).await?;
+        self.mirror_step_loss.lock().unwrap().command(phi, theta);
+        self.mirror_point = Some(mirror::Point::neutral());
+        self.mirror_offset = None;
+        Ok(())
+    }
+
+    /// Returns `true` if the maintenance mode is requested through DBus.
+    #[must_use]
+    pub fn maintenance_mode(&self) -> bool {
+        *self.maintenance_mode.borrow()
+    }
+
+    /// Returns a stream of the maintenance mode requests, starting with the
+    /// current state.
+    #[must_use]
+    pub fn maintenance_mode_stream(&self) -> WatchStream<bool> {
+        WatchStream::new(self.maintenance_mode.clone())
+    }
+
+    /// Returns the thermal frame aligned to the source timestamp of an IR
+    /// frame. The frame is linearly interpolated if `interpolate` is `true`,
+    /// otherwise the nearest thermal frame is returned.
+    #[must_use]
+    pub fn aligned_thermal_frame(
+        &self,
+        ir_source_ts: Instant,
+        interpolate: bool,
+    ) -> Option<camera::thermal::alignment::Aligned> {
+        if interpolate {
+            self.thermal_aligner.interpolate(ir_source_ts)
+        } else {
+            self.thermal_aligner.nearest(ir_source_ts)
+        }
+    }
+
+    /// Starts the depth camera unless its kill switch is engaged.
     pub async fn start_depth_camera(&mut self) -> Result<()> {
+        if self.is_killed(Subsystem::DepthCamera).await {
+            return Ok(());
+        }
         #[cfg(feature = "livestream")]
         if let Some(livestream) = self.livestream.enabled() {
             livestream.send(port::Input::new(livestream::Input::DepthState(true))).await?;
         }
         self.enable_depth_camera()?;
+        self.depth_camera_range = camera::depth::Range::default();
         self.depth_camera
             .enabled()
             .unwrap()
-            .send(port::Input::new(camera::depth::Command::Start))
+            .send(port::Input::new(camera::depth::Command::Start(self.depth_camera_range)))
             .await?;
         Ok(())
     }
 
-    /// Stops the depth camera
-    ///
-    /// # Panics
-    ///
-    /// If the camera agent is not enabled
+    /// Switches the depth camera between the long-range and close-range modes.
+    /// Does nothing if the camera wasn't started.
+    pub fn set_depth_camera_range(&mut self, range: camera::depth::Range) -> Result<()> {
+        let Some(depth_camera) = self.depth_camera.enabled() else {
+            return Ok(());
+        };
+        if range != self.depth_camera_range {
+            tracing::debug!("Switching the depth camera to the {range:?} range mode");
+            depth_camera.tx.send_now(port::Input::new(camera::depth::Command::SetRange(range)))?;
+            self.depth_camera_range = range;
+        }
+        Ok(())
+    }
+
+    /// Stops the depth camera. Does nothing if the camera wasn't started.
     pub async fn stop_depth_camera(&mut self) -> Result<()> {
+        if !self.depth_camera.is_enabled() {
+            return Ok(());
+        }
         #[cfg(feature = "livestream")]
         if let Some(livestream) = self.livestream.enabled() {
             livestream.send(port::Input::new(livestream::Input::DepthState(false))).await?;
         }
         self.depth_camera
             .enabled()
-            .expect("depth camera is not enabled")
+            .unwrap()
             .send(port::Input::new(camera::depth::Command::Stop))
             .await?;
         self.disable_depth_camera();
         Ok(())
     }
 
+    /// Returns `true` if the kill switch for `subsystem` is engaged in the
+    /// current configuration.
+    pub async fn is_killed(&self, subsystem: Subsystem) -> bool {
+        let killed = self.config.lock().await.kill_switches.is_engaged(subsystem);
+        if killed {
+            tracing::info!("{} is disabled by the kill switch", subsystem.name());
+            dd_incr!(
+                "main.count.global.kill_switch.blocked",
+                &format!("subsystem:{}", subsystem.name())
+            );
+        }
+        killed
+    }
+
+    /// Stops the subsystems whose kill switches were engaged since they have
+    /// been started.
+    pub async fn apply_kill_switches(&mut self) -> Result<()> {
+        let kill_switches = self.config.lock().await.kill_switches;
+        if kill_switches.is_engaged(Subsystem::ThermalCamera) {
+            self.stop_thermal_camera().await?;
+        }
+        if kill_switches.is_engaged(Subsystem::DepthCamera) {
+            self.stop_depth_camera().await?;
+        }
+        #[cfg(feature = "livestream")]
+        if kill_switches.is_engaged(Subsystem::Livestream) && self.livestream.is_enabled() {
+            tracing::info!("Livestream is disabled by the kill switch");
+            self.disable_livestream();
+        }
+        Ok(())
+    }
+
+    /// Enables the livestream agent unless its kill switch is engaged. If
+    /// `record_dir` is provided, the livestream is also recorded into it.
+    #[cfg(feature = "livestream")]
+    pub async fn start_livestream(&mut self, record_dir: Option<std::path::PathBuf>) -> Result<()> {
+        if self.is_killed(Subsystem::Livestream).await {
+            return Ok(());
+        }
+        self.enable_livestream()?;
+        self.livestream_recording = record_dir.is_some();
+        if let Some(record_dir) = record_dir {
+            self.livestream
+                .enabled()
+                .unwrap()
+                .send(port::Input::new(livestream::Input::Record(Some(record_dir))))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Forwards a main MCU command to the livestream recording sidecar, if
+    /// the livestream is being recorded.
+    #[cfg(feature = "livestream")]
+    fn record_mcu_command(&mut self, command: &mcu::main::Input) -> Result<()> {
+        if !self.livestream_recording {
+            return Ok(());
+        }
+        if let Some(livestream) = self.livestream.enabled() {
+            livestream
+                .tx
+                .send_now(port::Input::new(livestream::Input::McuCommand(command.clone())))?;
+        }
+        Ok(())
+    }
+
     /// Starts IR auto-exposure agent.
     pub async fn start_ir_auto_exposure(&mut self, target_mean: f64) -> Result<()> {
         self.enable_ir_auto_exposure()?;
@@ -758,6 +1144,18 @@ impl Orb {
         Ok(log)
     }
 
+    /// Stops the IMU agent.
+    ///
+    /// # Panics
+    ///
+    /// If the agent is not enabled.
+    pub async fn stop_imu(&mut self) -> Result<imu::Log> {
+        let log = imu::reset(self.imu.enabled().expect("imu is not enabled")).await?;
+        self.disable_imu();
+        self.imu_bad_mounting_reported = false;
+        Ok(log)
+    }
+
     /// Starts IR auto-focus agent.
     pub async fn start_ir_auto_focus(
         &mut self,
@@ -816,6 +1214,40 @@ impl Orb {
         Ok(self.mirror_point.take())
     }
 
+    /// Starts the eye PID-controller agent with the configured gain schedule.
+    pub async fn start_eye_pid_controller(&mut self) -> Result<()> {
+        let schedule = self.config.lock().await.eye_pid_gain_schedule.clone();
+        self.enable_eye_pid_controller()?;
+        if let Some(eye_pid_controller) = self.eye_pid_controller.enabled() {
+            eye_pid_controller
+                .send_unjam(port::Input::new(eye_pid_controller::Input::SetGainSchedule(schedule)))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Takes the gain schedule history from the eye PID-controller agent.
+    ///
+    /// # Panics
+    ///
+    /// If the agent is not enabled.
+    pub async fn take_eye_pid_controller_log(&mut self) -> Result<eye_pid_controller::Log> {
+        eye_pid_controller::take_log(
+            self.eye_pid_controller.enabled().expect("eye_pid_controller is not enabled"),
+        )
+        .await
+    }
+
+    /// Returns the latest user distance in millimeters. Prefers the RGB-Net
+    /// estimate and falls back to the ToF sensor if the estimate is stale.
+    #[must_use]
+    pub fn user_distance(&self) -> Option<f64> {
+        self.rgb_net_user_distance
+            .filter(|(_, instant)| instant.elapsed() < USER_DISTANCE_MAX_AGE)
+            .map(|(distance, _)| distance)
+            .or_else(|| self.tof_distance.latest().map(f64::from))
+    }
+
     /// Stops the eye PID-controller agent.
     ///
     /// # Panics
@@ -843,6 +1275,40 @@ impl Orb {
         Ok(mirror_log)
     }
 
+    /// Moves the mirror one livestream remote control step in the given
+    /// direction from the current point, enabling the mirror agent if needed.
+    /// The mirror is kept within the calibrated range. Allowed only in the
+    /// maintenance mode.
+    #[cfg(feature = "livestream")]
+    pub fn move_mirror(&mut self, direction: livestream::Direction) -> Result<()> {
+        use crate::consts::LIVESTREAM_MIRROR_STEP_DEGREES as STEP;
+        if !self.maintenance_mode() {
+            tracing::warn!("Livestream mirror control is allowed only in the maintenance mode");
+            return Ok(());
+        }
+        let step = match direction {
+            livestream::Direction::Up => (0.0, -STEP),
+            livestream::Direction::Down => (0.0, STEP),
+            livestream::Direction::Left => (-STEP, 0.0),
+            livestream::Direction::Right => (STEP, 0.0),
+        };
+        let offset = self.mirror_offset.unwrap_or_default();
+        let point = Limits::for_hardware().clamp_point(
+            self.mirror_point.unwrap_or_else(mirror::Point::neutral)
+                + offset
+                + mirror::Point { phi_degrees: step.0, theta_degrees: step.1 },
+            &self.calibration,
+        );
+        self.mirror_point = Some(point - offset);
+        self.enable_mirror()?;
+        let mirror = self.mirror.enabled().expect("mirror is not enabled");
+        mirror.tx.send_now(port::Input::new(mirror::Command::SetPoint(point)))?;
+        if let Some(livestream) = self.livestream.enabled() {
+            livestream.tx.send_now(port::Input::new(livestream::Input::SetMirrorPoint(point)))?;
+        }
+        Ok(())
+    }
+
     /// Saves identification images.
     ///
     /// # Panics
@@ -890,6 +1356,7 @@ impl Orb {
         self.enable_mega_agent_two().await?;
         self.rgb_net_enabled = true;
         self.only_rgb_net_frames = only_rgb_net_frames;
+        self.rgb_net_tracker.reset();
         Ok(())
     }
 
@@ -902,6 +1369,13 @@ impl Orb {
     pub fn disable_rgb_net(&mut self) {
         self.only_rgb_net_frames = true;
         self.rgb_net_enabled = false;
+        self.rgb_net_tracker.reset();
+    }
+
+    /// Cancels the in-flight and queued Python calls for the frames captured
+    /// so far. Used when the plan which requested them is abandoned.
+    pub fn cancel_python_calls(&self) {
+        cancellation::cancel();
     }
 
     /// Returns `true` if IR-Net model is enabled.
@@ -952,6 +1426,107 @@ impl Orb {
                 .await
                 .expect("to always be able to send");
         }
+        self.phase = Some(name);
+        self.event_log.record(event_log::EventKind::Phase(name.to_owned()));
+        if self.last_snapshot.map_or(true, |last| last.elapsed() >= BROKER_SNAPSHOT_INTERVAL) {
+            self.store_snapshot().await;
+        }
+    }
+
+    /// Sets the verified operator session, which survives an orb-core crash,
+    /// and immediately persists a new snapshot.
+    pub async fn set_operator_session(&mut self, operator_data: Option<&OperatorData>) {
+        self.operator_session = operator_data.map(OperatorSession::new);
+        self.store_snapshot().await;
+    }
+
+    /// Returns a snapshot of the current broker state.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        let mut enabled_agents = Vec::new();
+        let mut add = |name: &str, enabled: bool| {
+            if enabled {
+                enabled_agents.push(name.to_owned());
+            }
+        };
+        add("ir_eye_camera", self.ir_eye_camera.is_enabled());
+        add("ir_face_camera", self.ir_face_camera.is_enabled());
+        add("rgb_camera", self.rgb_camera.is_enabled());
+        add("thermal_camera", self.thermal_camera.is_enabled());
+        add("depth_camera", self.depth_camera.is_enabled());
+        add("mega_agent_one", self.mega_agent_one.is_enabled());
+        add("mega_agent_two", self.mega_agent_two.is_enabled());
+        add("ir_auto_focus", self.ir_auto_focus.is_enabled());
+        add("ir_auto_exposure", self.ir_auto_exposure.is_enabled());
+        add("eye_tracker", self.eye_tracker.is_enabled());
+        add("eye_pid_controller", self.eye_pid_controller.is_enabled());
+        add("mirror", self.mirror.is_enabled());
+        add("distance", self.distance.is_enabled());
+        add("imu", self.imu.is_enabled());
+        add("qr_code", self.qr_code.is_enabled());
+        add("data_uploader", self.data_uploader.is_enabled());
+        add("image_notary", self.image_notary.is_enabled());
+        Snapshot::new(self.phase, enabled_agents, self.operator_session.clone())
+    }
+
+    /// Starts recording the broker decisions.
+    pub fn start_event_log(&mut self) {
+        let enabled_agents = self.snapshot().enabled_agents;
+        self.event_log.start(enabled_agents);
+    }
+
+    /// Returns the recorded broker decisions and stops recording.
+    pub fn take_event_log(&mut self) -> Vec<event_log::Event> {
+        self.event_log.take()
+    }
+
+    /// Starts sampling the agent messages for the message trace.
+    pub fn start_message_trace(&mut self, sample_rate: f64) {
+        self.message_trace.start(sample_rate);
+    }
+
+    /// Returns the message trace summary and stops sampling.
+    pub fn take_message_trace(&mut self) -> Option<message_trace::Summary> {
+        self.message_trace.take()
+    }
+
+    /// Starts the signup queue indicator for a signup with `waiting` users
+    /// queued behind it. Zero turns the indicator off.
+    pub fn start_signup_queue(&mut self, waiting: usize) {
+        let update = self.signup_queue.start(waiting);
+        self.update_signup_queue(update);
+    }
+
+    /// Advances the signup queue indicator with the signup `progress` in the
+    /// `0.0..=1.0` range.
+    pub fn signup_queue_progress(&mut self, progress: f64) {
+        let update = self.signup_queue.progress(progress);
+        self.update_signup_queue(update);
+    }
+
+    fn update_signup_queue(&self, update: ui::queue::Update) {
+        match update {
+            ui::queue::Update::Show(indicator) => self.ui.signup_queue(Some(indicator)),
+            ui::queue::Update::Clear => self.ui.signup_queue(None),
+            ui::queue::Update::Unchanged => {}
+        }
+    }
+
+    /// Resets the camera frame drop statistics.
+    pub fn start_frame_drops(&mut self) {
+        self.frame_drops = camera::drops::FrameDrops::default();
+    }
+
+    /// Returns the camera frame drop statistics since the last reset.
+    pub fn take_frame_drops(&mut self) -> camera::drops::FrameDrops {
+        mem::take(&mut self.frame_drops)
+    }
+
+    async fn store_snapshot(&mut self) {
+        self.last_snapshot = Some(Instant::now());
+        if let Err(err) = self.snapshot().store().await {
+            tracing::error!("Failed to store the orb broker snapshot: {err:?}");
+        }
     }
 
     fn send_ir_net_estimate(&mut self, input: ir_net::Input) -> Result<()> {
@@ -1031,13 +1606,19 @@ impl Orb {
         distance::Agent { ui: self.ui.clone() }
     }
 
-    #[allow(clippy::unused_async)]
+    fn init_imu(&mut self) -> imu::Agent {
+        imu::Agent { main_mcu: self.main_mcu.clone() }
+    }
+
     async fn init_qr_code(&mut self) -> Result<qr_code::Agent> {
-        Ok(qr_code::Agent {})
+        Ok(qr_code::Agent { burst_frames: self.config.lock().await.qr_burst_frames })
     }
 
     fn init_data_uploader(&mut self) -> data_uploader::Agent {
-        data_uploader::Agent { config: Arc::clone(&self.config) }
+        data_uploader::Agent {
+            config: Arc::clone(&self.config),
+            control: Arc::clone(&self.data_uploader_control),
+        }
     }
 
     fn handle_ir_eye_camera(
@@ -1045,6 +1626,10 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<camera::ir::Sensor>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("ir_eye_camera", &output, |frame| frame.len());
+        let dirty = self.lens_dirt.push_ir(camera::smudge::Camera::IrEye, &output.value);
+        self.report_lens_dirt(camera::smudge::Camera::IrEye, dirty);
+        self.frame_drops.push(camera::smudge::Camera::IrEye, &output.value.meta());
         #[cfg(feature = "livestream")]
         if let Some(livestream) = self.livestream.enabled() {
             livestream
@@ -1069,6 +1654,7 @@ impl Orb {
                     .tx
                     .send_now(output.chain(ir_auto_focus::Input::Frame(output.value.clone())))?;
             }
+            let capture = self.capture_context();
             if let Some(image_notary) = self.image_notary.enabled() {
                 // Timestamps are generated in the image_notary history, so send there first.
                 image_notary.tx.send_now(port::Input::new(
@@ -1077,6 +1663,7 @@ impl Orb {
                         frame: output.value.clone(),
                         wavelength: self.ir_led_wavelength,
                         target_left_eye: self.target_left_eye,
+                        capture,
                         fps_override: self.ir_eye_save_fps_override,
                         log_metadata_always: true,
                     }),
@@ -1091,17 +1678,23 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<camera::ir::Sensor>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("ir_face_camera", &output, |frame| frame.len());
+        let dirty = self.lens_dirt.push_ir(camera::smudge::Camera::IrFace, &output.value);
+        self.report_lens_dirt(camera::smudge::Camera::IrFace, dirty);
+        self.frame_drops.push(camera::smudge::Camera::IrFace, &output.value.meta());
         #[cfg(feature = "livestream")]
         if let Some(livestream) = self.livestream.enabled() {
             livestream
                 .tx
                 .send_now(output.chain(livestream::Input::IrFaceFrame(output.value.clone())))?;
         }
+        let capture = self.capture_context();
         if let Some(image_notary) = self.image_notary.enabled() {
             image_notary.tx.send_now(port::Input::new(image_notary::Input::SaveIrFaceData(
                 image_notary::SaveIrFaceDataInput {
                     frame: output.value.clone(),
                     wavelength: self.ir_led_wavelength,
+                    capture,
                     fps_override: self.ir_face_save_fps_override,
                     log_metadata_always: true,
                 },
@@ -1115,6 +1708,10 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<camera::rgb::Sensor>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("rgb_camera", &output, |frame| frame.data().len());
+        let dirty = self.lens_dirt.push_rgb(&output.value);
+        self.report_lens_dirt(camera::smudge::Camera::Rgb, dirty);
+        self.frame_drops.push(camera::smudge::Camera::Rgb, &output.value.meta());
         #[cfg(feature = "livestream")]
         if let Some(livestream) = self.livestream.enabled() {
             livestream
@@ -1160,14 +1757,20 @@ impl Orb {
                 .tx
                 .send_now(output.chain(distance::Input::RgbNetEstimate(estimate.clone())))?;
         }
+        if let Some(user_distance) =
+            estimate.primary().map(rgb_net::EstimatePredictionOutput::user_distance)
+        {
+            self.rgb_net_user_distance = Some((user_distance, Instant::now()));
+        }
         Ok(())
     }
 
     fn handle_rgb_net(
         &mut self,
         plan: &mut dyn Plan,
-        output: port::Output<rgb_net::Model>,
+        mut output: port::Output<rgb_net::Model>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("rgb_net", &output, |_| 0);
         macro_rules! restore_frame {
             () => {
                 loop {
@@ -1183,6 +1786,9 @@ impl Orb {
             };
         }
 
+        if let rgb_net::Output::Estimate(estimate) = &mut output.value {
+            self.rgb_net_tracker.update(estimate);
+        }
         let frame = if let rgb_net::Output::Estimate(estimate) = &output.value {
             let frame = restore_frame!();
             self.pre_handle_rgb_net_estimate(&output, estimate)?;
@@ -1215,6 +1821,7 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<face_identifier::Model>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("face_identifier", &output, |_| 0);
         if let face_identifier::Output::IsValidImage(_) = &output.value {
             unreachable!("FaceIdentifier::IsValidImage should only be used with fusions atm.")
         }
@@ -1225,7 +1832,7 @@ impl Orb {
         &mut self,
         plan: &mut dyn Plan,
         source_ts: Instant,
-        rn_output: rgb_net::EstimateOutput,
+        mut rn_output: rgb_net::EstimateOutput,
         fi_output: face_identifier::types::IsValidOutput,
     ) -> Result<BrokerFlow> {
         macro_rules! restore_frame {
@@ -1242,11 +1849,16 @@ impl Orb {
             };
         }
 
-        let rn_port_out =
-            port::Output { value: rgb_net::Output::Estimate(rn_output.clone()), source_ts };
+        self.rgb_net_tracker.update(&mut rn_output);
+        let rn_port_out = port::Output {
+            value: rgb_net::Output::Estimate(rn_output.clone()),
+            source_ts,
+            enqueue_ts: Instant::now(),
+        };
         let fi_port_out = port::Output {
             value: face_identifier::Output::IsValidImage(fi_output.clone()),
             source_ts,
+            enqueue_ts: Instant::now(),
         };
 
         let frame = restore_frame!();
@@ -1278,6 +1890,11 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<camera::thermal::Sensor>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("thermal_camera", &output, |_| 0);
+        self.thermal_aligner.push(output.source_ts, output.value.clone());
+        if let Some(sensor_temperature) = output.value.sensor_temperature() {
+            self.thermal_camera_sensor.push(sensor_temperature);
+        }
         #[cfg(feature = "livestream")]
         if let Some(livestream) = self.livestream.enabled() {
             livestream
@@ -1302,12 +1919,28 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<camera::depth::Sensor>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("depth_camera", &output, |_| 0);
+        if let Some(distance) = self.user_distance() {
+            self.set_depth_camera_range(self.depth_camera_range.for_distance(distance))?;
+        }
         #[cfg(feature = "livestream")]
         if let Some(livestream) = self.livestream.enabled() {
             livestream
                 .tx
                 .send_now(output.chain(livestream::Input::DepthFrame(output.value.clone())))?;
         }
+        // Sampled here already, so the point clouds don't crowd the other
+        // saves out of the image notary queue.
+        #[cfg(feature = "internal-data-acquisition")]
+        if let Some(image_notary) = self.image_notary.enabled() {
+            let timestamp = output.value.timestamp();
+            if sample_at_fps(DEPTH_SAVE_FPS, timestamp, self.depth_save_time) {
+                image_notary.tx.send_now(port::Input::new(image_notary::Input::SaveDepthData(
+                    image_notary::SaveDepthDataInput { frame: output.value.clone() },
+                )))?;
+                self.depth_save_time = timestamp;
+            }
+        }
         plan.handle_depth_camera(self, output)
     }
 
@@ -1316,6 +1949,7 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<ir_net::Model>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("ir_net", &output, |_| 0);
         macro_rules! restore_frame {
             () => {
                 loop {
@@ -1340,6 +1974,7 @@ impl Orb {
                     .tx
                     .send_now(output.chain(livestream::Input::IrNetEstimate(estimate.clone())))?;
             }
+            let capture = self.capture_context();
             if let Some(image_notary) = self.image_notary.enabled() {
                 // Timestamps are generated in the image_notary history, so send there first.
                 image_notary.tx.send_now(port::Input::new(
@@ -1348,6 +1983,7 @@ impl Orb {
                         frame: frame.clone(),
                         wavelength: self.ir_led_wavelength,
                         target_left_eye: self.target_left_eye,
+                        capture,
                         fps_override: self.ir_eye_save_fps_override,
                         log_metadata_always: true,
                     }),
@@ -1356,10 +1992,14 @@ impl Orb {
             if let Some(ir_auto_focus) = self.ir_auto_focus.enabled() {
                 ir_auto_focus.tx.send_now(output.chain(estimate.into()))?;
             }
+            let user_distance = self.user_distance();
             if let Some(eye_pid_controller) = self.eye_pid_controller.enabled() {
-                eye_pid_controller.tx.send_now(
-                    output.chain(eye_pid_controller::Input::IrNetEstimate(estimate.clone())),
-                )?;
+                eye_pid_controller.tx.send_now(output.chain(
+                    eye_pid_controller::Input::IrNetEstimate {
+                        estimate: estimate.clone(),
+                        user_distance,
+                    },
+                ))?;
             }
             if let Some(distance) = self.distance.enabled() {
                 distance
@@ -1376,10 +2016,11 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<mega_agent_one::MegaAgentOne>,
     ) -> Result<BrokerFlow> {
-        let source_ts = output.source_ts;
+        self.message_trace.sample("mega_agent_one", &output, |_| 0);
+        let port::Output { source_ts, enqueue_ts, .. } = output;
         match output.value {
             mega_agent_one::Output::IRNet(value) => {
-                self.handle_ir_net(plan, port::Output { value, source_ts })
+                self.handle_ir_net(plan, port::Output { value, source_ts, enqueue_ts })
             }
             _ => plan.handle_mega_agent_one(self, output),
         }
@@ -1390,13 +2031,14 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<mega_agent_two::MegaAgentTwo>,
     ) -> Result<BrokerFlow> {
-        let source_ts = output.source_ts;
+        self.message_trace.sample("mega_agent_two", &output, |_| 0);
+        let port::Output { source_ts, enqueue_ts, .. } = output;
         match output.value {
             mega_agent_two::Output::RgbNet(value) => {
-                self.handle_rgb_net(plan, port::Output { value, source_ts })
+                self.handle_rgb_net(plan, port::Output { value, source_ts, enqueue_ts })
             }
             mega_agent_two::Output::FaceIdentifier(value) => {
-                self.handle_face_identifier(plan, port::Output { value, source_ts })
+                self.handle_face_identifier(plan, port::Output { value, source_ts, enqueue_ts })
             }
             mega_agent_two::Output::FusionRgbNetFaceIdentifier { rgb_net, face_identifier } => self
                 .handle_fusion_rgb_net_face_identifier(plan, source_ts, rgb_net, face_identifier),
@@ -1404,16 +2046,14 @@ impl Orb {
                 FusionErrors::RgbNetFaceIdentifier(rne, fie) => {
                     // In fusion agents we make the assumption that there is an order in decision making.
                     if let Some(value) = rne {
-                        if let BrokerFlow::Break =
-                            self.handle_rgb_net(plan, port::Output { value, source_ts })?
-                        {
+                        let output = port::Output { value, source_ts, enqueue_ts };
+                        if let BrokerFlow::Break = self.handle_rgb_net(plan, output)? {
                             return Ok(BrokerFlow::Break);
                         }
                     }
                     if let Some(value) = fie {
-                        if let BrokerFlow::Break =
-                            self.handle_face_identifier(plan, port::Output { value, source_ts })?
-                        {
+                        let output = port::Output { value, source_ts, enqueue_ts };
+                        if let BrokerFlow::Break = self.handle_face_identifier(plan, output)? {
                             return Ok(BrokerFlow::Break);
                         }
                     }
@@ -1432,8 +2072,12 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<ir_auto_focus::Agent>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("ir_auto_focus", &output, |_| 0);
         let value = output.value;
-        self.main_mcu.send_now(mcu::main::Input::LiquidLens(Some(value)))?;
+        let command = mcu::main::Input::LiquidLens(Some(value));
+        #[cfg(feature = "livestream")]
+        self.record_mcu_command(&command)?;
+        self.main_mcu.send_now(command)?;
         #[cfg(feature = "livestream")]
         if let Some(livestream) = self.livestream.enabled() {
             livestream.tx.send_now(output.chain(livestream::Input::Focus(value)))?;
@@ -1447,6 +2091,7 @@ impl Orb {
         _plan: &mut dyn Plan,
         output: port::Output<ir_auto_exposure::Agent>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("ir_auto_exposure", &output, |_| 0);
         let ir_auto_exposure::Output { gain, exposure } = output.value;
         if let Some(ir_eye_camera) = self.ir_eye_camera.enabled() {
             ir_eye_camera.tx.send_now(output.chain(camera::ir::Command::SetGain(gain)))?;
@@ -1474,6 +2119,7 @@ impl Orb {
         _plan: &mut dyn Plan,
         output: port::Output<eye_tracker::Agent>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("eye_tracker", &output, |_| 0);
         let mirror_point = output.value;
         self.mirror_point = Some(mirror_point);
         let point = mirror_point + self.mirror_offset.unwrap_or_default();
@@ -1493,6 +2139,7 @@ impl Orb {
         _plan: &mut dyn Plan,
         output: port::Output<eye_pid_controller::Agent>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("eye_pid_controller", &output, |_| 0);
         let mirror_offset = output.value;
         self.mirror_offset = Some(mirror_offset);
         if let Some(mirror_point) = self.mirror_point {
@@ -1514,6 +2161,7 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<mirror::Actuator>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("mirror", &output, |_| 0);
         let (phi, theta) = output.value;
         let (phi, theta) = if identification::HARDWARE_VERSION.contains("Diamond") {
             (
@@ -1526,7 +2174,11 @@ impl Orb {
                 theta.clamp(MIRROR_THETA_MIN_PEARL, MIRROR_THETA_MAX_PEARL),
             )
         };
-        self.main_mcu.send_now(mcu::main::Input::Mirror(phi, theta))?;
+        let command = mcu::main::Input::Mirror(phi, theta);
+        #[cfg(feature = "livestream")]
+        self.record_mcu_command(&command)?;
+        self.main_mcu.send_now(command)?;
+        self.mirror_step_loss.lock().unwrap().command(phi, theta);
         plan.handle_mirror(self, output)
     }
 
@@ -1536,15 +2188,69 @@ impl Orb {
         plan: &mut dyn Plan,
         output: port::Output<distance::Agent>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("distance", &output, |_| 0);
         plan.handle_distance(self, output)
     }
 
+    fn handle_imu(
+        &mut self,
+        plan: &mut dyn Plan,
+        output: port::Output<imu::Agent>,
+    ) -> Result<BrokerFlow> {
+        self.message_trace.sample("imu", &output, |_| 0);
+        let imu::Output { tilt_degrees } = output.value;
+        dd_gauge!("main.gauge.system.imu_tilt_degrees", tilt_degrees.to_string());
+        if output.value.is_bad_mounting() && !self.imu_bad_mounting_reported {
+            tracing::warn!("Orb is tilted by {tilt_degrees:.1}°, it may be mounted at a bad angle");
+            dd_incr!("main.count.global.imu_bad_mounting");
+            self.imu_bad_mounting_reported = true;
+        }
+        plan.handle_imu(self, output)
+    }
+
+    fn handle_cone_present(&mut self, plan: &mut dyn Plan, present: bool) -> Result<BrokerFlow> {
+        if !self.update_cone_present(present) {
+            return Ok(BrokerFlow::Continue);
+        }
+        plan.handle_cone_present(self, present)
+    }
+
+    /// Records the Diamond cone attachment state. Returns `false` if it didn't
+    /// change.
+    fn update_cone_present(&mut self, present: bool) -> bool {
+        if self.cone_present == Some(present) {
+            return false;
+        }
+        self.cone_present = Some(present);
+        if !present {
+            dd_incr!("main.count.global.cone_detached");
+        }
+        self.ui.cone_present(present);
+        true
+    }
+
+    fn handle_mcu_shutdown(
+        &mut self,
+        plan: &mut dyn Plan,
+        shutdown: orb_messages::mcu_main::ShutdownScheduled,
+    ) -> Result<BrokerFlow> {
+        plan.handle_mcu_shutdown(self, shutdown)
+    }
+
+    /// Returns the last reported Diamond cone attachment state, or `None` if
+    /// the MCU hasn't reported it yet.
+    #[must_use]
+    pub fn is_cone_present(&self) -> Option<bool> {
+        self.cone_present
+    }
+
     #[allow(clippy::unused_self, clippy::needless_pass_by_value, clippy::unnecessary_wraps)]
     fn handle_qr_code(
         &mut self,
         plan: &mut dyn Plan,
         output: port::Output<qr_code::Agent>,
     ) -> Result<BrokerFlow> {
+        self.message_trace.sample("qr_code", &output, |_| 0);
         #[cfg(feature = "livestream")]
         if let Some(livestream) = self.livestream.enabled() {
             livestream.tx.send_now(port::Input::new(livestream::Input::QrCode(
@@ -1587,13 +2293,13 @@ impl Orb {
     }
 
     #[cfg(feature = "livestream")]
-    #[allow(clippy::unused_self, clippy::needless_pass_by_value)]
     fn handle_livestream(
         &mut self,
-        _plan: &mut dyn Plan,
+        plan: &mut dyn Plan,
         output: port::Output<livestream::Agent>,
     ) -> Result<BrokerFlow> {
-        match output.value {}
+        self.message_trace.sample("livestream", &output, |_| 0);
+        plan.handle_livestream(self, output)
     }
 
     fn exposure_range(&self) -> RangeInclusive<u16> {
@@ -1603,12 +2309,42 @@ impl Orb {
         }
     }
 
+    fn on_event(&mut self, event: BrokerEvent) {
+        self.event_log.record(event.into());
+    }
+
     fn poll_extra(
         &mut self,
         plan: &mut dyn Plan,
         cx: &mut Context<'_>,
-        _fence: Instant,
+        fence: Instant,
     ) -> Result<Option<Poll<()>>> {
+        self.heartbeat.beat();
+        if self.main_mcu_events_fence.replace(fence) != Some(fence) {
+            // A new plan is running, the buffered events are stale, but the
+            // cone state they carry is still current.
+            while let Some(output) = self.main_mcu_events.rx_mut().try_recv_broadcast()? {
+                if let mcu::main::Output::ConePresent(present) = output {
+                    self.update_cone_present(present);
+                }
+            }
+        }
+        while let Poll::Ready(output) =
+            self.main_mcu_events.rx_mut().next_broadcast().poll_unpin(cx)
+        {
+            let flow = match output? {
+                mcu::main::Output::ConePresent(present) => {
+                    self.handle_cone_present(plan, present)?
+                }
+                mcu::main::Output::Shutdown(shutdown) => {
+                    self.handle_mcu_shutdown(plan, shutdown)?
+                }
+                _ => BrokerFlow::Continue,
+            };
+            if matches!(flow, BrokerFlow::Break) {
+                return Ok(Some(Poll::Ready(())));
+            }
+        }
         if matches!(plan.poll_extra(self, cx)?, BrokerFlow::Break) {
             return Ok(Some(Poll::Ready(())));
         }
@@ -0,0 +1,163 @@
+//! Orb broker state snapshots for crash recovery.
+//!
+//! The orb broker periodically persists a [`Snapshot`] of its state to the SSD.
+//! After an orb-core crash, the master plan restores the operator session from
+//! the latest snapshot, so the operator doesn't have to rescan the QR code. The
+//! restored operator QR code is validated by the backend again before use.
+
+use crate::{
+    backend::operator_status::LocationData,
+    consts::BROKER_SNAPSHOT_PATH,
+    plans::{qr_scan, OperatorData},
+    ssd,
+};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::fs;
+
+/// Orb broker state snapshot.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Time of the snapshot in seconds since the UNIX epoch.
+    pub timestamp: u64,
+    /// Current phase of the orb.
+    pub phase: Option<String>,
+    /// Names of the enabled agents.
+    pub enabled_agents: Vec<String>,
+    /// Verified operator session, if any.
+    pub operator_session: Option<OperatorSession>,
+}
+
+/// Verified operator QR code, which is still pending to be used for signups.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OperatorSession {
+    /// Operator ID.
+    pub user_id: String,
+    /// Hash of the operator data stored in the backend.
+    pub user_data_hash: Option<Vec<u8>>,
+    /// Location data of the operator.
+    pub location_data: LocationData,
+    /// Time of the QR code scan in seconds since the UNIX epoch.
+    pub scanned_at: u64,
+}
+
+impl OperatorSession {
+    /// Creates a new operator session from the verified operator data.
+    #[must_use]
+    pub fn new(operator_data: &OperatorData) -> Self {
+        Self {
+            user_id: operator_data.qr_code.user_id.clone(),
+            user_data_hash: operator_data.qr_code.user_data_hash.clone(),
+            location_data: operator_data.location_data.clone(),
+            scanned_at: unix_now().saturating_sub(operator_data.timestamp.elapsed().as_secs()),
+        }
+    }
+
+    /// Converts the session back to the operator data. Returns `None` if the
+    /// session is older than `expiration_time`.
+    #[must_use]
+    pub fn into_operator_data(self, expiration_time: Duration) -> Option<OperatorData> {
+        let age = Duration::from_secs(unix_now().saturating_sub(self.scanned_at));
+        if age >= expiration_time {
+            return None;
+        }
+        Some(OperatorData {
+            qr_code: qr_scan::user::Data {
+                user_id: self.user_id,
+                signup_extension: false,
+                signup_extension_config: None,
+                user_data_hash: self.user_data_hash,
+            },
+            location_data: self.location_data,
+            timestamp: Instant::now().checked_sub(age)?,
+        })
+    }
+}
+
+impl Snapshot {
+    /// Creates a new snapshot with the current timestamp.
+    #[must_use]
+    pub fn new(
+        phase: Option<&str>,
+        enabled_agents: Vec<String>,
+        operator_session: Option<OperatorSession>,
+    ) -> Self {
+        Self {
+            timestamp: unix_now(),
+            phase: phase.map(ToOwned::to_owned),
+            enabled_agents,
+            operator_session,
+        }
+    }
+
+    /// Persists the snapshot to the SSD.
+    pub async fn store(&self) -> Result<()> {
+        let json = serde_json::to_vec(self)?;
+        let path = Path::new(BROKER_SNAPSHOT_PATH);
+        let tmp_path = path.with_extension("tmp");
+        ssd::perform_async(async {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            // Write to a temporary file first so a crash in the middle doesn't
+            // leave a corrupted snapshot behind.
+            fs::write(&tmp_path, &json).await?;
+            fs::rename(&tmp_path, path).await
+        })
+        .await;
+        Ok(())
+    }
+
+    /// Loads the latest snapshot from the SSD.
+    pub async fn load() -> Result<Option<Self>> {
+        let path = Path::new(BROKER_SNAPSHOT_PATH);
+        let contents = ssd::perform_async(async {
+            if fs::try_exists(path).await? { fs::read(path).await.map(Some) } else { Ok(None) }
+        })
+        .await
+        .flatten();
+        Ok(contents.map(|contents| serde_json::from_slice(&contents)).transpose()?)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |now| now.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operator_data(age: Duration) -> OperatorData {
+        OperatorData {
+            qr_code: qr_scan::user::Data {
+                user_id: "66ad4897-0ca7-4727-8365-ca808348e3cd".to_owned(),
+                user_data_hash: Some(vec![1, 2, 3]),
+                ..Default::default()
+            },
+            location_data: LocationData::default(),
+            timestamp: Instant::now() - age,
+        }
+    }
+
+    #[test]
+    fn test_operator_session_roundtrip() {
+        let session = OperatorSession::new(&operator_data(Duration::from_secs(60)));
+        let json = serde_json::to_string(&session).unwrap();
+        let session: OperatorSession = serde_json::from_str(&json).unwrap();
+        let restored = session.into_operator_data(Duration::from_secs(3600)).unwrap();
+        assert_eq!(restored.qr_code.user_id, "66ad4897-0ca7-4727-8365-ca808348e3cd");
+        assert_eq!(restored.qr_code.user_data_hash, Some(vec![1, 2, 3]));
+        assert!(restored.timestamp.elapsed() >= Duration::from_secs(59));
+    }
+
+    #[test]
+    fn test_expired_operator_session() {
+        let session = OperatorSession::new(&operator_data(Duration::from_secs(120)));
+        assert!(session.into_operator_data(Duration::from_secs(60)).is_none());
+    }
+}
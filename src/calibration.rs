@@ -1,4 +1,7 @@
 //! Calibration data.
+
+pub mod drift;
+
 use crate::{config::Config, dd_gauge};
 use eyre::{bail, Result};
 use serde::{Deserialize, Serialize};
@@ -0,0 +1,148 @@
+//! Calibration drift history.
+//!
+//! Keeps the latest [`CALIBRATION_DRIFT_HISTORY_LEN`] mirror events which hint
+//! that the mirror calibration drifts away, like lost steps or a changed
+//! motor range, so they survive reboots and can be collected for analysis.
+
+use crate::{
+    agents::mirror::step_loss::Motor,
+    consts::{CALIBRATION_DRIFT_FILE_PATH, CALIBRATION_DRIFT_HISTORY_LEN},
+    dd_incr,
+};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::fs;
+
+/// Calibration drift history.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    /// Recorded entries, the oldest first.
+    pub entries: VecDeque<Entry>,
+}
+
+/// Calibration drift history entry.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// UNIX time in seconds.
+    pub timestamp: u64,
+    /// Recorded event.
+    pub event: Event,
+}
+
+/// Calibration drift event.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// Step loss confirmed by the motor range reported after a homing, with
+    /// the angles commanded beyond the maximum slew since the previous
+    /// homing.
+    StepLoss {
+        /// Excess phi angle in degrees.
+        phi_excess_degrees: f64,
+        /// Excess theta angle in degrees.
+        theta_excess_degrees: f64,
+    },
+    /// Motor range reported after a homing deviates from the reference one.
+    RangeChange {
+        /// `phi` or `theta`.
+        motor: String,
+        /// Reference range in microsteps.
+        reference_microsteps: u32,
+        /// Reported range in microsteps.
+        range_microsteps: u32,
+    },
+}
+
+impl Event {
+    /// Creates a new step-loss event.
+    #[must_use]
+    pub fn step_loss(phi_excess_degrees: f64, theta_excess_degrees: f64) -> Self {
+        Self::StepLoss { phi_excess_degrees, theta_excess_degrees }
+    }
+
+    /// Creates a new motor range change event.
+    #[must_use]
+    pub fn range_change(motor: Motor, reference_microsteps: u32, range_microsteps: u32) -> Self {
+        let motor = match motor {
+            Motor::Phi => "phi",
+            Motor::Theta => "theta",
+        };
+        Self::RangeChange { motor: motor.to_owned(), reference_microsteps, range_microsteps }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::StepLoss { .. } => "step_loss",
+            Self::RangeChange { .. } => "range_change",
+        }
+    }
+}
+
+impl History {
+    /// Loads the history from the file system. Returns an empty history if the
+    /// file doesn't exist or is malformed.
+    pub async fn load<P: AsRef<Path>>(path: P) -> Self {
+        let Ok(contents) = fs::read_to_string(path).await else {
+            return Self::default();
+        };
+        serde_json::from_str(&contents).unwrap_or_else(|err| {
+            tracing::error!("Calibration drift history loading error: {err:?}");
+            Self::default()
+        })
+    }
+
+    /// Stores the history to the file system.
+    pub async fn store<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?).await.map_err(Into::into)
+    }
+
+    /// Appends an event, dropping the oldest entries over the limit.
+    pub fn push(&mut self, event: Event) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        self.entries.push_back(Entry { timestamp, event });
+        while self.entries.len() > CALIBRATION_DRIFT_HISTORY_LEN {
+            self.entries.pop_front();
+        }
+    }
+}
+
+/// Records an event to the calibration drift history on the file system.
+pub async fn record(event: Event) -> Result<()> {
+    tracing::warn!("Recording calibration drift event: {event:?}");
+    dd_incr!("main.count.system.calibration.drift", &format!("type:{}", event.name()));
+    let mut history = History::load(CALIBRATION_DRIFT_FILE_PATH).await;
+    history.push(event);
+    history.store(CALIBRATION_DRIFT_FILE_PATH).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_store_and_load_history() {
+        let temp_dir = tempfile::tempdir().expect("to create temp dir");
+        let path = temp_dir.path().join("calibration_drift.json");
+        assert!(History::load(&path).await.entries.is_empty());
+
+        let mut history = History::default();
+        for i in 0..CALIBRATION_DRIFT_HISTORY_LEN + 5 {
+            history.push(Event::range_change(Motor::Phi, 10_000, u32::try_from(i).unwrap()));
+        }
+        history.push(Event::step_loss(3.0, 0.0));
+        history.store(&path).await.expect("to store history");
+
+        let loaded = History::load(&path).await;
+        assert_eq!(loaded.entries.len(), CALIBRATION_DRIFT_HISTORY_LEN);
+        assert_eq!(
+            loaded.entries.front().unwrap().event,
+            Event::range_change(Motor::Phi, 10_000, 6)
+        );
+        assert_eq!(loaded.entries.back().unwrap().event, Event::step_loss(3.0, 0.0));
+    }
+}
@@ -2,7 +2,7 @@
 
 #[cfg(feature = "integration_testing")]
 use crate::plans;
-use clap::StructOpt;
+use clap::{StructOpt, Subcommand};
 use std::path::PathBuf;
 
 /// The rust main program running on the orb and responsible for signup and
@@ -15,6 +15,11 @@ pub struct Cli {
     #[cfg(feature = "livestream")]
     #[structopt(short = 'l', long)]
     pub livestream: bool,
+    /// Record the livestream with a sensor sidecar into the directory.
+    /// Implies `--livestream`.
+    #[cfg(feature = "livestream")]
+    #[structopt(long)]
+    pub livestream_record: Option<PathBuf>,
     /// Provide a custom operator QR code. If an empty string is provided, then
     /// we use an internal testing operator code.
     #[structopt(short = 'o', long)]
@@ -39,7 +44,7 @@ pub struct Cli {
     #[cfg(feature = "allow-plan-mods")]
     #[structopt(short = 'b', long)]
     pub biometric_input: Option<PathBuf>,
-    /// Ignore missing sounds instead of panicking.
+    /// Don't report missing sounds as errors.
     #[structopt(long)]
     pub ignore_missing_sounds: bool,
     /// Various hacks for a signup to pass on hon-human-subjects
@@ -56,4 +61,61 @@ pub struct Cli {
     #[cfg(feature = "internal-data-acquisition")]
     #[structopt(short = 'd', long)]
     pub data_acquisition: bool,
+    /// Record the raw CAN traffic into a candump log.
+    #[structopt(long)]
+    pub can_trace: Option<PathBuf>,
+    /// Replay a candump log against a fake main MCU instead of the hardware.
+    #[structopt(long)]
+    pub can_replay: Option<PathBuf>,
+    /// Feed the Jetson hardware watchdog when not supervised by the systemd
+    /// watchdog.
+    #[structopt(long)]
+    pub hardware_watchdog: bool,
+}
+
+/// Subcommands of the `orb-core` binary.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Inspect and modify the data uploader queues of the running orb-core.
+    #[structopt(subcommand)]
+    Uploads(UploadsCommand),
+    /// Inspect the signup receipts ledger.
+    #[structopt(subcommand)]
+    Receipts(ReceiptsCommand),
+}
+
+/// Data uploader queues subcommands.
+#[derive(Subcommand, Debug)]
+pub enum UploadsCommand {
+    /// List the queued items with their age, size, and upload attempts.
+    List {
+        /// Print the items as JSON.
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Move an item to the front of its queue.
+    Requeue {
+        /// Package tier.
+        tier: u8,
+        /// Item ID.
+        id: u64,
+    },
+    /// Remove an item from its queue without uploading it.
+    Drop {
+        /// Package tier.
+        tier: u8,
+        /// Item ID.
+        id: u64,
+    },
+}
+
+/// Signup receipts ledger subcommands.
+#[derive(Subcommand, Debug)]
+pub enum ReceiptsCommand {
+    /// List the enrolled signups lacking a server-signed receipt.
+    Missing {
+        /// Print the signups as JSON.
+        #[structopt(long)]
+        json: bool,
+    },
 }
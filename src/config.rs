@@ -1,16 +1,27 @@
 //! Orb configuration settings.
 
+pub mod audit;
+pub mod impact;
+
 use crate::{
-    agents::python::face_identifier,
+    agents::{
+        camera::thermal,
+        eye_pid_controller,
+        python::{face_identifier, ir_net},
+    },
     backend,
     consts::{
         CONFIG_DIR, DEFAULT_BIOMETRIC_CAPTURE_TIMEOUT_SELF_SERVE,
-        DEFAULT_BLOCK_SIGNUPS_WHEN_NO_INTERNET, DEFAULT_MAX_FAN_SPEED,
-        DEFAULT_SLOW_INTERNET_PING_THRESHOLD, DEFAULT_SOUND_VOLUME,
-        DEFAULT_THERMAL_CAMERA_PAIRING_STATUS_TIMEOUT, MAX_SOUND_VOLUME, QR_SCAN_TIMEOUT,
+        DEFAULT_BLOCK_SIGNUPS_WHEN_NO_INTERNET, DEFAULT_GPU_STREAM_PRIORITIES,
+        DEFAULT_MAX_FAN_SPEED, DEFAULT_SLOW_INTERNET_PING_THRESHOLD, DEFAULT_SOUND_VOLUME,
+        DEFAULT_THERMAL_CAMERA_PAIRING_STATUS_TIMEOUT, MAX_SOUND_VOLUME, QR_BURST_MAX_FRAMES,
+        QR_SCAN_TIMEOUT,
     },
-    dd_incr, identification,
+    dd_event, dd_incr, debug_report, identification, mcu,
+    monitor::traffic,
     plans::fraud_check,
+    secure_element::{self, Counter},
+    ui,
 };
 use eyre::{eyre, Context, Result};
 use schemars::JsonSchema;
@@ -21,7 +32,7 @@ use std::{
     sync::Arc,
     time::Duration,
 };
-use tokio::{fs, sync::Mutex};
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex, task};
 
 /// Configuration settings that are safe to write to the disk. We still need to write part of the configuration to the
 /// disk as initially the orb might not have internet connection (e.g. on first boot in a new area) so a default or last
@@ -35,6 +46,10 @@ pub struct BasicConfig {
     pub sound_volume: u64,
     /// UI language. If not set, US English is assumed.
     pub language: Option<String>,
+    /// Daily data budgets of the bulk traffic classes, applied at boot before
+    /// the configuration is downloaded.
+    #[serde(default)]
+    pub traffic_budgets: traffic::Budgets,
 }
 
 /// Orb configuration settings.
@@ -70,6 +85,8 @@ pub struct Config {
     pub fraud_check_engine_config: fraud_check::BackendConfig,
     /// IR-Net model configs: Namespaced IR-Net configs.
     pub ir_net_model_configs: Option<HashMap<String, String>>,
+    /// IR-Net score calibration curves by model version.
+    pub ir_net_score_calibrations: Option<HashMap<String, ir_net::ScoreCalibration>>,
     /// Iris model configs: Namespaced Iris config files.
     pub iris_model_configs: Option<HashMap<String, String>>,
     /// Person Classifier config: under-age threshold.
@@ -81,6 +98,8 @@ pub struct Config {
     pub thermal_camera_pairing_status_timeout: Duration,
     /// Whether the thermal camera agent is enabled or not.
     pub thermal_camera: bool,
+    /// Image processing settings of the thermal camera.
+    pub thermal_camera_settings: thermal::Settings,
     /// Whether the depth camera agent is enabled or not.
     pub depth_camera: bool,
     /// Self-serve mode.
@@ -97,12 +116,33 @@ pub struct Config {
     pub self_serve_app_capture_trigger_timeout: Duration,
     /// Biometric capture time-out in self-serve mode.
     pub self_serve_biometric_capture_timeout: Duration,
+    /// Maximum number of users queued ahead of the current one in self-serve
+    /// mode. Zero disables the queue.
+    pub self_serve_user_queue_size: u32,
+    /// How long a queued user QR code stays valid.
+    pub self_serve_user_queue_expiration: Duration,
+    /// How long to keep scanning for the next user QR code to queue.
+    pub self_serve_user_queue_scan_window: Duration,
+    /// Whether to show the queue length and the progress of the current
+    /// signup on the ring to the users waiting in the queue.
+    pub self_serve_queue_indicator: bool,
+    /// Number of failed signups with the same user QR code within
+    /// `signup_rate_limit_window` after which the QR code is rejected for
+    /// `signup_rate_limit_cooldown`. Zero disables the rate limiting.
+    pub signup_rate_limit_max_failures: u32,
+    /// Time window of the failed signups counted by the rate limiting.
+    pub signup_rate_limit_window: Duration,
+    /// How long a rate limited user QR code is rejected.
+    pub signup_rate_limit_cooldown: Duration,
     /// Default phi offset for the mirror if no calibration.json is present.
     pub mirror_default_phi_offset_degrees: f64,
     /// Default theta offset for the mirror if no calibration.json is present.
     pub mirror_default_theta_offset_degrees: f64,
     /// Pruning log messages from process agents.
     pub process_agent_logger_pruning: bool,
+    /// Fraction of the orb broker messages sampled for the per-signup message
+    /// trace. Zero disables the tracing.
+    pub broker_message_sample_rate: f64,
     /// HTTP client to backend: request timeout.
     pub backend_http_request_timeout: Duration,
     /// HTTP client to backend: connect timeout.
@@ -133,6 +173,204 @@ pub struct Config {
     pub orb_relay_announce_orb_id_timeout: Duration,
     /// Expiration time for the operator QR code.
     pub operator_qr_expiration_time: Duration,
+    /// Maximum age of the saved identification images.
+    pub identification_images_max_age: Duration,
+    /// Maximum total size of the saved identification images in bytes.
+    pub identification_images_max_size: u64,
+    /// Maximum age of the saved data-acquisition images.
+    pub data_acquisition_images_max_age: Duration,
+    /// Maximum total size of the saved data-acquisition images in bytes.
+    pub data_acquisition_images_max_size: u64,
+    /// Maximum age of the saved debug images.
+    pub debug_images_max_age: Duration,
+    /// Maximum total size of the saved debug images in bytes.
+    pub debug_images_max_size: u64,
+    /// Minimum age of the data-acquisition images to be recompressed by the
+    /// background compactor.
+    pub data_acquisition_compaction_age: Duration,
+    /// CPU load above which the background image compactor waits.
+    pub data_acquisition_compaction_max_cpu_load: f64,
+    /// Remotely disabled subsystems.
+    pub kill_switches: KillSwitches,
+    /// Skip the remaining objectives for an eye once its capture is of
+    /// exceptional quality.
+    pub biometric_capture_early_exit: bool,
+    /// Minimum IR-Net score of an exceptional-quality capture.
+    pub biometric_capture_early_exit_score: f64,
+    /// Minimum IR-Net sharpness of an exceptional-quality capture.
+    pub biometric_capture_early_exit_sharpness: f64,
+    /// Minimum time spent on an objective before it can exit early.
+    pub biometric_capture_early_exit_min_dwell: Duration,
+    /// Capture both eyes within the same objectives on dual-optics hardware,
+    /// whose IR eye camera sees both eyes.
+    pub biometric_capture_bilateral: bool,
+    /// CUDA stream priorities of the Python agents, by agent name. Lower
+    /// values mean higher priority.
+    pub gpu_stream_priorities: HashMap<String, i32>,
+    /// Display name and venue tag of the orb.
+    pub orb_alias: identification::alias::Alias,
+    /// URL of the venue caching proxy for the config and Orb OS status
+    /// requests.
+    pub venue_cache_url: Option<String>,
+    /// Fixed seed for the biometric pipeline Python agents. When set, the
+    /// agents run in the determinism mode for reproducible outputs.
+    pub pipeline_deterministic_seed: Option<u64>,
+    /// Daily window of the reduced-light night mode for dark venues.
+    pub night_mode: Option<ui::night_mode::Schedule>,
+    /// LED settings of the night mode.
+    pub night_mode_settings: ui::night_mode::NightModeSettings,
+    /// LED theme rules keyed by the operating country and the hardware
+    /// version. The first matching rule applies.
+    pub ui_themes: Vec<ui::theme::Rule>,
+    /// Daily window of the scheduled reboot while idle.
+    pub reboot_window: Option<ui::night_mode::Schedule>,
+    /// Minimum uptime before the scheduled reboot, which prevents rebooting
+    /// twice within the same window.
+    pub reboot_min_uptime: Duration,
+    /// How long before an expected signup the orb starts pre-cooling when the
+    /// Jetson is predicted to throttle. Zero disables the pre-cooling.
+    pub thermal_precool_lead_time: Duration,
+    /// Number of consecutive frames merged when the single-frame QR code
+    /// decoding fails. Values below 2 disable the burst decoding.
+    pub qr_burst_frames: u8,
+    /// Main MCU transport per message class. Moving the LED traffic to UART
+    /// reserves the CAN bus for control and telemetry. Applied on startup.
+    pub mcu_routing: mcu::Routing,
+    /// Main MCU acknowledge timeout and retry policy per message class.
+    /// Applied on startup.
+    pub mcu_ack_policies: mcu::AckPolicies,
+    /// Serial device of the main MCU UART transport. Applied on startup.
+    pub mcu_uart_device: String,
+    /// Confirmation cue for the key signup events played instead of the audio
+    /// cues while the sound volume is zero.
+    pub silent_confirmation: ui::haptics::Mode,
+    /// Opt-in capture of the pipeline intermediate artifacts for a sampled
+    /// fraction of the signups.
+    pub deep_debug: debug_report::artifacts::Settings,
+    /// Eye PID controller gains keyed on the user distance.
+    pub eye_pid_gain_schedule: eye_pid_controller::GainSchedule,
+    /// Staged rollout this configuration belongs to. A configuration of a
+    /// rollout which isn't activated yet is only dry-run on hot-reload.
+    pub config_rollout: Option<impact::Rollout>,
+}
+
+/// Subsystem which can be remotely disabled with a kill switch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Thermal camera.
+    ThermalCamera,
+    /// Depth camera.
+    DepthCamera,
+    /// Livestream.
+    Livestream,
+    /// Overcapture extension of the biometric capture.
+    Overcapture,
+    /// Mirror calibration adjustment after each successful capture.
+    ContinuousCalibration,
+}
+
+/// Remote kill switches. A subsystem with an engaged kill switch is never
+/// enabled by the broker, regardless of the rest of the configuration.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "PascalCase")]
+pub struct KillSwitches {
+    /// Thermal camera is disabled.
+    pub thermal_camera: bool,
+    /// Depth camera is disabled.
+    pub depth_camera: bool,
+    /// Livestream is disabled.
+    pub livestream: bool,
+    /// Overcapture extension is disabled.
+    pub overcapture: bool,
+    /// Continuous calibration is disabled.
+    pub continuous_calibration: bool,
+}
+
+impl Subsystem {
+    /// All subsystems which can be disabled.
+    pub const ALL: [Self; 5] = [
+        Self::ThermalCamera,
+        Self::DepthCamera,
+        Self::Livestream,
+        Self::Overcapture,
+        Self::ContinuousCalibration,
+    ];
+
+    /// Returns the subsystem name as used by the backend.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ThermalCamera => "ThermalCamera",
+            Self::DepthCamera => "DepthCamera",
+            Self::Livestream => "Livestream",
+            Self::Overcapture => "Overcapture",
+            Self::ContinuousCalibration => "ContinuousCalibration",
+        }
+    }
+}
+
+impl KillSwitches {
+    /// Creates kill switches from the list of the disabled subsystem names.
+    /// Unknown names are ignored.
+    #[must_use]
+    pub fn from_names(names: &[String]) -> Self {
+        let mut kill_switches = Self::default();
+        for name in names {
+            if let Some(subsystem) = Subsystem::ALL.into_iter().find(|s| s.name() == name) {
+                *kill_switches.get_mut(subsystem) = true;
+            } else {
+                tracing::warn!("Ignoring unknown kill switch: {name}");
+            }
+        }
+        kill_switches
+    }
+
+    /// Returns `true` if the kill switch for `subsystem` is engaged.
+    #[must_use]
+    pub fn is_engaged(&self, subsystem: Subsystem) -> bool {
+        match subsystem {
+            Subsystem::ThermalCamera => self.thermal_camera,
+            Subsystem::DepthCamera => self.depth_camera,
+            Subsystem::Livestream => self.livestream,
+            Subsystem::Overcapture => self.overcapture,
+            Subsystem::ContinuousCalibration => self.continuous_calibration,
+        }
+    }
+
+    /// Returns the subsystems whose kill switches differ from `previous`.
+    #[must_use]
+    pub fn toggled(&self, previous: &Self) -> Vec<Subsystem> {
+        Subsystem::ALL
+            .into_iter()
+            .filter(|&subsystem| self.is_engaged(subsystem) != previous.is_engaged(subsystem))
+            .collect()
+    }
+
+    /// Logs and reports a Datadog event for every kill switch which differs
+    /// from `previous`.
+    pub fn report_changes(&self, previous: &Self) {
+        for subsystem in self.toggled(previous) {
+            let state = if self.is_engaged(subsystem) { "engaged" } else { "released" };
+            tracing::warn!("Kill switch for {} {state}", subsystem.name());
+            dd_event!(
+                format!("Kill switch {state}"),
+                format!("Kill switch for {} {state}", subsystem.name()),
+                &format!("subsystem:{}", subsystem.name()),
+                &format!("state:{state}")
+            );
+        }
+    }
+
+    fn get_mut(&mut self, subsystem: Subsystem) -> &mut bool {
+        match subsystem {
+            Subsystem::ThermalCamera => &mut self.thermal_camera,
+            Subsystem::DepthCamera => &mut self.depth_camera,
+            Subsystem::Livestream => &mut self.livestream,
+            Subsystem::Overcapture => &mut self.overcapture,
+            Subsystem::ContinuousCalibration => &mut self.continuous_calibration,
+        }
+    }
 }
 
 #[cfg(not(feature = "stage"))]
@@ -166,11 +404,17 @@ impl Config {
                     contact_lens_model_config,
                     fraud_check_engine_config,
                     ir_net_model_configs,
+                    ir_net_score_calibrations,
                     iris_model_configs,
                     child_threshold,
                     face_identifier_model_configs,
                     thermal_camera_pairing_status_timeout,
                     thermal_camera,
+                    thermal_camera_pipeline_mode,
+                    thermal_camera_agc_mode,
+                    thermal_camera_color_palette,
+                    thermal_camera_gradient_correction,
+                    thermal_camera_flat_scene_correction,
                     depth_camera,
                     self_serve,
                     self_serve_button,
@@ -179,9 +423,17 @@ impl Config {
                     self_serve_app_skip_capture_trigger,
                     self_serve_app_capture_trigger_timeout,
                     self_serve_biometric_capture_timeout,
+                    self_serve_user_queue_size,
+                    self_serve_user_queue_expiration,
+                    self_serve_user_queue_scan_window,
+                    self_serve_queue_indicator,
+                    signup_rate_limit_max_failures,
+                    signup_rate_limit_window,
+                    signup_rate_limit_cooldown,
                     mirror_default_phi_offset_degrees,
                     mirror_default_theta_offset_degrees,
                     process_agent_logger_pruning,
+                    broker_message_sample_rate,
                     backend_http_request_timeout,
                     backend_http_connect_timeout,
                     pcp_v3,
@@ -197,7 +449,56 @@ impl Config {
                     orb_relay_announce_orb_id_retries,
                     orb_relay_announce_orb_id_timeout,
                     operator_qr_expiration_time,
+                    identification_images_max_age,
+                    identification_images_max_size,
+                    data_acquisition_images_max_age,
+                    data_acquisition_images_max_size,
+                    debug_images_max_age,
+                    debug_images_max_size,
+                    data_acquisition_compaction_age,
+                    data_acquisition_compaction_max_cpu_load,
+                    kill_switches,
+                    biometric_capture_early_exit,
+                    biometric_capture_early_exit_score,
+                    biometric_capture_early_exit_sharpness,
+                    biometric_capture_early_exit_min_dwell,
+                    biometric_capture_bilateral,
+                    gpu_stream_priorities,
+                    orb_display_name,
+                    venue_tag,
+                    venue_cache_url,
+                    pipeline_deterministic_seed,
+                    night_mode_start,
+                    night_mode_end,
+                    night_mode_utc_offset,
+                    night_mode_brightness_cap,
+                    night_mode_flash_intensity,
+                    night_mode_animation_speed,
+                    ui_themes,
+                    reboot_window_start,
+                    reboot_window_end,
+                    reboot_window_utc_offset,
+                    reboot_min_uptime,
+                    thermal_precool_lead_time,
+                    qr_burst_frames,
+                    mcu_led_transport,
+                    mcu_control_transport,
+                    mcu_led_ack_policy,
+                    mcu_query_ack_policy,
+                    mcu_motion_ack_policy,
+                    mcu_control_ack_policy,
+                    mcu_uart_device,
+                    silent_confirmation,
+                    deep_debug_sample_rate,
+                    deep_debug_artifacts,
+                    traffic_budget_uploader,
+                    traffic_budget_livestream,
+                    traffic_budget_uploader_wifi,
+                    traffic_budget_livestream_wifi,
+                    eye_pid_gain_schedule,
+                    config_rollout,
                     last_updated: _,
+                    last_updated_signature: _,
                 },
         } = status;
         let default = Self::default();
@@ -205,6 +506,16 @@ impl Config {
             basic_config: BasicConfig {
                 sound_volume: sound_volume.clamp(0, MAX_SOUND_VOLUME),
                 language,
+                traffic_budgets: traffic::Budgets {
+                    lte: traffic::LinkBudgets {
+                        uploader: traffic_budget_uploader.unwrap_or(0),
+                        livestream: traffic_budget_livestream.unwrap_or(0),
+                    },
+                    wifi: traffic::LinkBudgets {
+                        uploader: traffic_budget_uploader_wifi.unwrap_or(0),
+                        livestream: traffic_budget_livestream_wifi.unwrap_or(0),
+                    },
+                },
             },
             operation_country: operation_country.or(default.operation_country),
             operation_city: operation_city.or(default.operation_city),
@@ -220,12 +531,25 @@ impl Config {
             contact_lens_model_config,
             fraud_check_engine_config,
             ir_net_model_configs,
+            ir_net_score_calibrations,
             iris_model_configs,
             child_threshold,
             face_identifier_model_configs,
             thermal_camera_pairing_status_timeout: thermal_camera_pairing_status_timeout
                 .map_or(default.thermal_camera_pairing_status_timeout, Duration::from_millis),
             thermal_camera: thermal_camera.unwrap_or(default.thermal_camera),
+            thermal_camera_settings: thermal::Settings {
+                pipeline_mode: thermal_camera_pipeline_mode
+                    .unwrap_or(default.thermal_camera_settings.pipeline_mode),
+                agc_mode: thermal_camera_agc_mode
+                    .unwrap_or(default.thermal_camera_settings.agc_mode),
+                color_palette: thermal_camera_color_palette
+                    .unwrap_or(default.thermal_camera_settings.color_palette),
+                gradient_correction: thermal_camera_gradient_correction
+                    .unwrap_or(default.thermal_camera_settings.gradient_correction),
+                flat_scene_correction: thermal_camera_flat_scene_correction
+                    .unwrap_or(default.thermal_camera_settings.flat_scene_correction),
+            },
             depth_camera: depth_camera.unwrap_or(default.depth_camera),
             self_serve: self_serve.unwrap_or(default.self_serve),
             self_serve_button: self_serve_button.unwrap_or(default.self_serve_button),
@@ -243,12 +567,28 @@ impl Config {
                 .map_or(default.self_serve_app_capture_trigger_timeout, Duration::from_millis),
             self_serve_biometric_capture_timeout: self_serve_biometric_capture_timeout
                 .map_or(default.self_serve_biometric_capture_timeout, Duration::from_millis),
+            self_serve_user_queue_size: self_serve_user_queue_size
+                .unwrap_or(default.self_serve_user_queue_size),
+            self_serve_user_queue_expiration: self_serve_user_queue_expiration
+                .map_or(default.self_serve_user_queue_expiration, Duration::from_millis),
+            self_serve_user_queue_scan_window: self_serve_user_queue_scan_window
+                .map_or(default.self_serve_user_queue_scan_window, Duration::from_millis),
+            self_serve_queue_indicator: self_serve_queue_indicator
+                .unwrap_or(default.self_serve_queue_indicator),
+            signup_rate_limit_max_failures: signup_rate_limit_max_failures
+                .unwrap_or(default.signup_rate_limit_max_failures),
+            signup_rate_limit_window: signup_rate_limit_window
+                .map_or(default.signup_rate_limit_window, Duration::from_millis),
+            signup_rate_limit_cooldown: signup_rate_limit_cooldown
+                .map_or(default.signup_rate_limit_cooldown, Duration::from_millis),
             mirror_default_phi_offset_degrees: mirror_default_phi_offset_degrees
                 .unwrap_or(default.mirror_default_phi_offset_degrees),
             mirror_default_theta_offset_degrees: mirror_default_theta_offset_degrees
                 .unwrap_or(default.mirror_default_theta_offset_degrees),
             process_agent_logger_pruning: process_agent_logger_pruning
                 .unwrap_or(default.process_agent_logger_pruning),
+            broker_message_sample_rate: broker_message_sample_rate
+                .unwrap_or(default.broker_message_sample_rate),
             backend_http_request_timeout: backend_http_request_timeout
                 .map_or(default.backend_http_request_timeout, Duration::from_millis),
             backend_http_connect_timeout: backend_http_connect_timeout
@@ -282,6 +622,95 @@ impl Config {
                 .map_or(default.orb_relay_announce_orb_id_timeout, Duration::from_millis),
             operator_qr_expiration_time: operator_qr_expiration_time
                 .map_or(default.operator_qr_expiration_time, Duration::from_millis),
+            identification_images_max_age: identification_images_max_age
+                .map_or(default.identification_images_max_age, Duration::from_millis),
+            identification_images_max_size: identification_images_max_size
+                .unwrap_or(default.identification_images_max_size),
+            data_acquisition_images_max_age: data_acquisition_images_max_age
+                .map_or(default.data_acquisition_images_max_age, Duration::from_millis),
+            data_acquisition_images_max_size: data_acquisition_images_max_size
+                .unwrap_or(default.data_acquisition_images_max_size),
+            debug_images_max_age: debug_images_max_age
+                .map_or(default.debug_images_max_age, Duration::from_millis),
+            debug_images_max_size: debug_images_max_size.unwrap_or(default.debug_images_max_size),
+            data_acquisition_compaction_age: data_acquisition_compaction_age
+                .map_or(default.data_acquisition_compaction_age, Duration::from_millis),
+            data_acquisition_compaction_max_cpu_load: data_acquisition_compaction_max_cpu_load
+                .unwrap_or(default.data_acquisition_compaction_max_cpu_load),
+            kill_switches: kill_switches
+                .as_deref()
+                .map_or(default.kill_switches, KillSwitches::from_names),
+            biometric_capture_early_exit: biometric_capture_early_exit
+                .unwrap_or(default.biometric_capture_early_exit),
+            biometric_capture_early_exit_score: biometric_capture_early_exit_score
+                .unwrap_or(default.biometric_capture_early_exit_score),
+            biometric_capture_early_exit_sharpness: biometric_capture_early_exit_sharpness
+                .unwrap_or(default.biometric_capture_early_exit_sharpness),
+            biometric_capture_early_exit_min_dwell: biometric_capture_early_exit_min_dwell
+                .map_or(default.biometric_capture_early_exit_min_dwell, Duration::from_millis),
+            biometric_capture_bilateral: biometric_capture_bilateral
+                .unwrap_or(default.biometric_capture_bilateral),
+            gpu_stream_priorities: gpu_stream_priorities.unwrap_or(default.gpu_stream_priorities),
+            orb_alias: identification::alias::Alias { display_name: orb_display_name, venue_tag },
+            venue_cache_url,
+            pipeline_deterministic_seed,
+            night_mode: night_mode_start.zip(night_mode_end).map(|(start_minute, end_minute)| {
+                ui::night_mode::Schedule {
+                    start_minute,
+                    end_minute,
+                    utc_offset_minutes: night_mode_utc_offset.unwrap_or(0),
+                }
+            }),
+            night_mode_settings: ui::night_mode::NightModeSettings {
+                brightness_cap: night_mode_brightness_cap
+                    .map_or(default.night_mode_settings.brightness_cap, |cap| cap.clamp(0.0, 1.0)),
+                flash_intensity: night_mode_flash_intensity
+                    .map_or(default.night_mode_settings.flash_intensity, |intensity| {
+                        intensity.clamp(0.0, 1.0)
+                    }),
+                animation_speed: night_mode_animation_speed
+                    .unwrap_or(default.night_mode_settings.animation_speed),
+            },
+            ui_themes: ui_themes.unwrap_or(default.ui_themes),
+            reboot_window: reboot_window_start.zip(reboot_window_end).map(
+                |(start_minute, end_minute)| ui::night_mode::Schedule {
+                    start_minute,
+                    end_minute,
+                    utc_offset_minutes: reboot_window_utc_offset.unwrap_or(0),
+                },
+            ),
+            reboot_min_uptime: reboot_min_uptime
+                .map_or(default.reboot_min_uptime, Duration::from_millis),
+            thermal_precool_lead_time: thermal_precool_lead_time
+                .map_or(default.thermal_precool_lead_time, Duration::from_millis),
+            qr_burst_frames: qr_burst_frames
+                .map_or(default.qr_burst_frames, |frames| frames.min(QR_BURST_MAX_FRAMES)),
+            mcu_routing: mcu::Routing {
+                led: mcu_led_transport.unwrap_or(default.mcu_routing.led),
+                control: mcu_control_transport.unwrap_or(default.mcu_routing.control),
+            },
+            mcu_ack_policies: {
+                let defaults = default.mcu_ack_policies;
+                mcu::AckPolicies {
+                    led: mcu_led_ack_policy.map_or(defaults.led, |p| p.apply(defaults.led)),
+                    query: mcu_query_ack_policy.map_or(defaults.query, |p| p.apply(defaults.query)),
+                    motion: mcu_motion_ack_policy
+                        .map_or(defaults.motion, |p| p.apply(defaults.motion)),
+                    control: mcu_control_ack_policy
+                        .map_or(defaults.control, |p| p.apply(defaults.control)),
+                }
+            },
+            mcu_uart_device: mcu_uart_device.unwrap_or(default.mcu_uart_device),
+            silent_confirmation: silent_confirmation.unwrap_or(default.silent_confirmation),
+            deep_debug: debug_report::artifacts::Settings {
+                sample_rate: deep_debug_sample_rate.unwrap_or(default.deep_debug.sample_rate),
+                kinds: deep_debug_artifacts.unwrap_or(default.deep_debug.kinds),
+            }
+            .clamped(),
+            eye_pid_gain_schedule: eye_pid_gain_schedule
+                .and_then(eye_pid_controller::GainSchedule::new)
+                .unwrap_or(default.eye_pid_gain_schedule),
+            config_rollout,
         })
         .filter(Self::validate)
     }
@@ -306,6 +735,7 @@ impl Config {
     pub fn propagate_to_ui(&self, ui: &dyn crate::ui::Engine) {
         ui.sound_volume(self.sound_volume());
         ui.sound_language(self.language().clone());
+        ui::theme::update(self, ui);
     }
 
     /// Stores the configuration settings to the file system.
@@ -315,7 +745,13 @@ impl Config {
             .wrap_err("config storing failed due to unserializable format with serde_json")?;
         tracing::info!("Storing configuration settings: {}", json);
         let path = config_file_path();
-        fs::write(path, json).await?;
+        let tmp_path = path.with_extension("tmp");
+        // Write to a temporary file first so a brownout in the middle doesn't
+        // leave a corrupted config behind.
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(json.as_bytes()).await?;
+        file.sync_all().await?;
+        fs::rename(&tmp_path, path).await?;
         Ok(())
     }
 
@@ -336,13 +772,54 @@ impl Config {
     /// Downloads the latest configuration from the backend and updates the
     /// shared configuration object.
     pub async fn download() -> Result<Config> {
-        let res = backend::config::request().await.map_err(|e| {
+        // The current config epoch is reported to the backend, so that a
+        // reverted config can be re-published with a newer epoch.
+        let read = || secure_element::read_counter(Counter::ConfigEpoch);
+        let current_epoch = match task::spawn_blocking(read).await? {
+            Ok(epoch) => Some(epoch),
+            Err(err) => {
+                tracing::error!("Reading the config epoch failed: {err:?}");
+                None
+            }
+        };
+        let res = backend::config::request(current_epoch).await.map_err(|e| {
             tracing::error!("Config request failed: {:?}", e);
             dd_incr!("main.count.http.config_update.error");
             e
         })?;
 
+        // The last update time serves as the config epoch. A replayed stale
+        // config is rejected, but a Secure Element failure doesn't block the
+        // config updates. Only a signed epoch advances the counter, so an
+        // unsigned one can't lock out the later configs.
+        let epoch = res.config.last_updated;
+        let signed = match backend::response_signing_pubkey().and_then(|public_key| {
+            res.config.verify_last_updated(public_key, &identification::ORB_ID)
+        }) {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::warn!("Config epoch {epoch} is unsigned: {err:?}");
+                false
+            }
+        };
         if let Some(config) = Config::from_backend(res) {
+            let check = move || secure_element::check_rollback(Counter::ConfigEpoch, epoch, signed);
+            match task::spawn_blocking(check).await? {
+                Ok(true) => {}
+                Ok(false) => {
+                    dd_incr!("main.count.http.config_rollback.error");
+                    dd_event!(
+                        "Config rollback",
+                        format!(
+                            "Rejected config epoch {epoch}, which is lower than the Secure \
+                             Element counter {}",
+                            current_epoch.map_or_else(|| "?".to_owned(), |e| e.to_string())
+                        )
+                    );
+                    return Err(eyre!("stale config epoch: {epoch}"));
+                }
+                Err(err) => tracing::error!("Config anti-rollback check failed: {err:?}"),
+            }
             dd_incr!("main.count.http.config_update.success");
             Ok(config)
         } else {
@@ -353,13 +830,22 @@ impl Config {
 
     /// Downloads the latest configuration from the backend, updates the shared
     /// configuration object, and stores the updated configuration to the file
-    /// system.
+    /// system. A configuration of a staged rollout which isn't activated yet
+    /// is held back, like on hot-reload.
     pub async fn download_and_store(config: Arc<Mutex<Config>>) -> Result<()> {
-        *config.lock().await = Self::download().await.map_err(|e| {
+        let new_config = Self::download().await.map_err(|e| {
             tracing::error!("Failed to download config: {:?}", e);
             e
         })?;
-        let config_to_store = config.lock().await;
+        let mut config_to_store = config.lock().await;
+        if !Self::stage(&config_to_store, &new_config) {
+            tracing::info!("Downloaded config held back until its rollout is activated");
+            return Ok(());
+        }
+        Self::replace(&mut config_to_store, new_config);
+        if let Err(err) = audit::record(audit::Source::Backend, &config_to_store).await {
+            tracing::error!("Failed to record config changes: {err:?}");
+        }
         tracing::info!("Downloaded latest config: {:?}", config_to_store);
         config_to_store.store().await.map_err(|e| {
             tracing::error!("Config downloaded but failed to be stored: {:?}", e);
@@ -367,6 +853,32 @@ impl Config {
         })
     }
 
+    /// Reports the impact of a downloaded configuration and returns `true` if
+    /// it can replace the current one. A configuration of a staged rollout is
+    /// held back until the backend activates the rollout.
+    pub fn stage(config: &Config, new_config: &Config) -> bool {
+        let rollout = new_config.config_rollout.as_ref();
+        match impact::Report::new(config, new_config) {
+            Ok(report) if !report.is_empty() && impact::should_report(rollout) => {
+                report.send(rollout);
+            }
+            Ok(_) => {}
+            Err(err) => tracing::error!("Failed to compute the config impact: {err:?}"),
+        }
+        impact::is_activated(rollout)
+    }
+
+    /// Replaces the configuration with a freshly downloaded one, reporting the
+    /// toggled kill switches and syncing the orb alias, the venue cache URL,
+    /// and the traffic budgets.
+    pub fn replace(config: &mut Config, new_config: Config) {
+        new_config.kill_switches.report_changes(&config.kill_switches);
+        identification::alias::sync(&new_config.orb_alias);
+        backend::venue_cache::sync(new_config.venue_cache_url.as_deref());
+        traffic::set_budgets(new_config.basic_config.traffic_budgets);
+        *config = new_config;
+    }
+
     /// Returns the sound volume.
     #[must_use]
     pub fn sound_volume(&self) -> u64 {
@@ -378,12 +890,22 @@ impl Config {
     pub fn language(&self) -> &Option<String> {
         &self.basic_config.language
     }
+
+    /// Returns the CUDA stream priority of the Python agent named `agent`.
+    #[must_use]
+    pub fn gpu_stream_priority(&self, agent: &str) -> Option<i32> {
+        self.gpu_stream_priorities.get(agent).copied()
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            basic_config: BasicConfig { sound_volume: DEFAULT_SOUND_VOLUME, language: None },
+            basic_config: BasicConfig {
+                sound_volume: DEFAULT_SOUND_VOLUME,
+                language: None,
+                traffic_budgets: traffic::Budgets::default(),
+            },
             operation_country: if cfg!(feature = "stage") { Some("DEV".to_owned()) } else { None },
             operation_city: if cfg!(feature = "stage") { Some("DEV".to_owned()) } else { None },
             fan_max_speed: Some(DEFAULT_MAX_FAN_SPEED),
@@ -395,6 +917,7 @@ impl Default for Config {
             contact_lens_model_config: None,
             fraud_check_engine_config: fraud_check::BackendConfig {},
             ir_net_model_configs: None,
+            ir_net_score_calibrations: None,
             iris_model_configs: None,
             child_threshold: None,
             face_identifier_model_configs: face_identifier::types::BackendConfig {
@@ -402,6 +925,7 @@ impl Default for Config {
             },
             thermal_camera_pairing_status_timeout: DEFAULT_THERMAL_CAMERA_PAIRING_STATUS_TIMEOUT,
             thermal_camera: false,
+            thermal_camera_settings: thermal::Settings::default(),
             depth_camera: false,
             self_serve: false,
             self_serve_button: false,
@@ -411,6 +935,13 @@ impl Default for Config {
             // TODO: This is for demo purposes, we should reduce this eventually when the video comes before the QR.
             self_serve_app_capture_trigger_timeout: Duration::from_millis(120_000),
             self_serve_biometric_capture_timeout: DEFAULT_BIOMETRIC_CAPTURE_TIMEOUT_SELF_SERVE,
+            self_serve_user_queue_size: 0,
+            self_serve_user_queue_expiration: Duration::from_secs(5 * 60),
+            self_serve_user_queue_scan_window: Duration::from_secs(5),
+            self_serve_queue_indicator: false,
+            signup_rate_limit_max_failures: 3,
+            signup_rate_limit_window: Duration::from_secs(10 * 60),
+            signup_rate_limit_cooldown: Duration::from_secs(5 * 60),
             mirror_default_phi_offset_degrees: if identification::HARDWARE_VERSION
                 .contains("Diamond")
             {
@@ -426,6 +957,7 @@ impl Default for Config {
                 -0.35
             },
             process_agent_logger_pruning: !cfg!(feature = "stage"),
+            broker_message_sample_rate: 0.0,
             backend_http_request_timeout: Duration::from_millis(60_000 * 3),
             backend_http_connect_timeout: Duration::from_millis(30_000),
             pcp_v3: false,
@@ -441,6 +973,41 @@ impl Default for Config {
             orb_relay_announce_orb_id_retries: 3,
             orb_relay_announce_orb_id_timeout: Duration::from_millis(2000),
             operator_qr_expiration_time: Duration::from_secs(60 * 60 * 23),
+            identification_images_max_age: Duration::from_secs(60 * 60 * 24 * 14),
+            identification_images_max_size: 4_000_000_000,
+            data_acquisition_images_max_age: Duration::from_secs(60 * 60 * 24 * 7),
+            data_acquisition_images_max_size: 40_000_000_000,
+            debug_images_max_age: Duration::from_secs(60 * 60 * 24 * 2),
+            debug_images_max_size: 1_000_000_000,
+            data_acquisition_compaction_age: Duration::from_secs(60 * 60 * 24),
+            data_acquisition_compaction_max_cpu_load: 0.5,
+            kill_switches: KillSwitches::default(),
+            biometric_capture_early_exit: false,
+            biometric_capture_early_exit_score: 2.5,
+            biometric_capture_early_exit_sharpness: 2.0,
+            biometric_capture_early_exit_min_dwell: Duration::from_millis(300),
+            biometric_capture_bilateral: false,
+            gpu_stream_priorities: DEFAULT_GPU_STREAM_PRIORITIES
+                .into_iter()
+                .map(|(agent, priority)| (agent.to_owned(), priority))
+                .collect(),
+            orb_alias: identification::alias::Alias::default(),
+            venue_cache_url: None,
+            pipeline_deterministic_seed: None,
+            night_mode: None,
+            night_mode_settings: ui::night_mode::NightModeSettings::default(),
+            ui_themes: Vec::new(),
+            reboot_window: None,
+            reboot_min_uptime: Duration::from_secs(12 * 60 * 60),
+            thermal_precool_lead_time: Duration::from_secs(60),
+            qr_burst_frames: 6,
+            mcu_routing: mcu::Routing::default(),
+            mcu_ack_policies: mcu::AckPolicies::default(),
+            mcu_uart_device: mcu::uart::UART_DEVICE.to_owned(),
+            silent_confirmation: ui::haptics::Mode::default(),
+            deep_debug: debug_report::artifacts::Settings::default(),
+            eye_pid_gain_schedule: eye_pid_controller::GainSchedule::default(),
+            config_rollout: None,
         }
     }
 }
@@ -448,3 +1015,40 @@ impl Default for Config {
 fn config_file_path() -> PathBuf {
     Path::new(CONFIG_DIR).join("config.json")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kill_switches_from_names() {
+        let kill_switches = KillSwitches::from_names(&[
+            "ThermalCamera".to_owned(),
+            "Overcapture".to_owned(),
+            "Unknown".to_owned(),
+        ]);
+        assert!(kill_switches.is_engaged(Subsystem::ThermalCamera));
+        assert!(kill_switches.is_engaged(Subsystem::Overcapture));
+        assert!(!kill_switches.is_engaged(Subsystem::DepthCamera));
+        assert!(!kill_switches.is_engaged(Subsystem::Livestream));
+    }
+
+    #[test]
+    fn test_kill_switches_toggled() {
+        let previous = KillSwitches { livestream: true, ..Default::default() };
+        let current = KillSwitches { depth_camera: true, ..Default::default() };
+        assert_eq!(current.toggled(&previous), [Subsystem::DepthCamera, Subsystem::Livestream]);
+        assert!(current.toggled(&current).is_empty());
+    }
+
+    #[test]
+    fn test_default_gpu_stream_priorities() {
+        use crate::agents::python::ir_net;
+        use agentwire::Agent as _;
+        let config = Config::default();
+        let ir_net = config.gpu_stream_priority(ir_net::Model::NAME).unwrap();
+        let face_identifier = config.gpu_stream_priority(face_identifier::Model::NAME).unwrap();
+        assert!(ir_net < face_identifier);
+        assert_eq!(config.gpu_stream_priority("unknown"), None);
+    }
+}
@@ -0,0 +1,188 @@
+//! Configuration change audit trail.
+//!
+//! Keeps the latest [`CONFIG_AUDIT_LOG_LEN`] per-parameter configuration
+//! changes on the file system. The entries overlapping a signup are attached to
+//! its debug report, so a change in the signup behavior can be traced back to
+//! the configuration changes preceding it.
+
+use super::Config;
+use crate::consts::{CONFIG_AUDIT_FILE_PATH, CONFIG_AUDIT_LOG_LEN};
+use eyre::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::VecDeque,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::fs;
+
+/// Configuration change audit log.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    /// Recorded entries, the oldest first.
+    pub entries: VecDeque<Entry>,
+    /// Parameter values of the last recorded configuration.
+    pub last: Map<String, Value>,
+}
+
+/// A configuration parameter change.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Entry {
+    /// UNIX time in milliseconds.
+    pub timestamp: u64,
+    /// Where the new value came from.
+    pub source: Source,
+    /// Parameter name, as in the serialized configuration.
+    pub parameter: String,
+    /// Previous value, or `None` if the parameter has been added.
+    pub old_value: Option<Value>,
+    /// New value, or `None` if the parameter has been removed.
+    pub new_value: Option<Value>,
+}
+
+/// Source of a configuration change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// Downloaded from the backend.
+    Backend,
+}
+
+impl History {
+    /// Loads the history from the file system. Returns an empty history if the
+    /// file doesn't exist or is malformed.
+    pub async fn load<P: AsRef<Path>>(path: P) -> Self {
+        let Ok(contents) = fs::read_to_string(path).await else {
+            return Self::default();
+        };
+        serde_json::from_str(&contents).unwrap_or_else(|err| {
+            tracing::error!("Config audit log loading error: {err:?}");
+            Self::default()
+        })
+    }
+
+    /// Stores the history to the file system.
+    pub async fn store<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_string(self)?).await.map_err(Into::into)
+    }
+
+    /// Compares `config` with the last recorded one and appends an entry for
+    /// every changed parameter, dropping the oldest entries over the limit.
+    /// Returns the number of changed parameters.
+    pub fn push(
+        &mut self,
+        source: Source,
+        config: &Config,
+        timestamp: SystemTime,
+    ) -> Result<usize> {
+        let Value::Object(current) = serde_json::to_value(config)? else {
+            unreachable!("config is serialized as a map");
+        };
+        let timestamp = unix_millis(timestamp);
+        let added = current.keys().filter(|parameter| !self.last.contains_key(*parameter));
+        let entries = self
+            .last
+            .keys()
+            .chain(added)
+            .filter(|&parameter| self.last.get(parameter) != current.get(parameter))
+            .map(|parameter| Entry {
+                timestamp,
+                source,
+                parameter: parameter.clone(),
+                old_value: self.last.get(parameter).cloned(),
+                new_value: current.get(parameter).cloned(),
+            })
+            .collect::<Vec<_>>();
+        let changes = entries.len();
+        for entry in entries {
+            tracing::info!(
+                "Config parameter {} changed: {} -> {}",
+                entry.parameter,
+                entry.old_value.as_ref().map_or_else(|| "-".to_owned(), Value::to_string),
+                entry.new_value.as_ref().map_or_else(|| "-".to_owned(), Value::to_string)
+            );
+            self.entries.push_back(entry);
+        }
+        while self.entries.len() > CONFIG_AUDIT_LOG_LEN {
+            self.entries.pop_front();
+        }
+        self.last = current;
+        Ok(changes)
+    }
+
+    /// Returns the entries recorded between `start` and `end` inclusive.
+    #[must_use]
+    pub fn overlapping(&self, start: SystemTime, end: SystemTime) -> Vec<Entry> {
+        let range = unix_millis(start)..=unix_millis(end);
+        self.entries.iter().filter(|entry| range.contains(&entry.timestamp)).cloned().collect()
+    }
+}
+
+/// Records the changes of `config` relative to the last recorded configuration
+/// to the audit log on the file system.
+pub async fn record(source: Source, config: &Config) -> Result<()> {
+    let mut history = History::load(CONFIG_AUDIT_FILE_PATH).await;
+    if history.push(source, config, SystemTime::now())? > 0 {
+        history.store(CONFIG_AUDIT_FILE_PATH).await?;
+    }
+    Ok(())
+}
+
+/// Returns the audit log entries recorded between `start` and `end`.
+pub async fn overlapping(start: SystemTime, end: SystemTime) -> Vec<Entry> {
+    History::load(CONFIG_AUDIT_FILE_PATH).await.overlapping(start, end)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn unix_millis(timestamp: SystemTime) -> u64 {
+    timestamp.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_push_and_overlapping() {
+        let temp_dir = tempfile::tempdir().expect("to create temp dir");
+        let path = temp_dir.path().join("config_audit.json");
+        let t0 = UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut history = History::load(&path).await;
+        let mut config = Config::default();
+        let old_country = serde_json::to_value(&config.operation_country).unwrap();
+        assert!(history.push(Source::Backend, &config, t0).unwrap() > 0);
+        history.entries.clear();
+        assert_eq!(history.push(Source::Backend, &config, t0).unwrap(), 0);
+
+        config.operation_country = Some("PT".to_owned());
+        config.basic_config.sound_volume += 1;
+        let t1 = t0 + Duration::from_secs(60);
+        assert_eq!(history.push(Source::Backend, &config, t1).unwrap(), 2);
+        history.store(&path).await.expect("to store history");
+
+        let loaded = History::load(&path).await;
+        let entry = loaded.entries.iter().find(|e| e.parameter == "OperationCountry").unwrap();
+        assert_eq!(entry.old_value, Some(old_country));
+        assert_eq!(entry.new_value, Some(Value::from("PT")));
+        assert_eq!(entry.timestamp, 1_060_000);
+        assert_eq!(loaded.overlapping(t0, t1 - Duration::from_millis(1)), []);
+        assert_eq!(loaded.overlapping(t1, t1).len(), 2);
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut history = History::default();
+        let mut config = Config::default();
+        for i in 0..=CONFIG_AUDIT_LOG_LEN {
+            config.operation_city = Some(i.to_string());
+            history.push(Source::Backend, &config, SystemTime::now()).unwrap();
+        }
+        assert_eq!(history.entries.len(), CONFIG_AUDIT_LOG_LEN);
+        assert_eq!(
+            history.entries.back().unwrap().new_value,
+            Some(Value::from(CONFIG_AUDIT_LOG_LEN.to_string()))
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Configuration change impact report.
+//!
+//! Before a downloaded configuration is hot-reloaded, the changed parameters
+//! are classified into behavioral [`Domain`]s and reported to Datadog. Kill
+//! switches are reported individually. A configuration which is part of a
+//! staged [`Rollout`] is only dry-run: the impact is sent to the backend as the
+//! orb acknowledgment of the diff, and the configuration is activated once the
+//! backend marks the rollout as activated. The configuration is polled until
+//! then, so the dry run is reported once per rollout, or again if the
+//! acknowledgment fails.
+
+use super::Config;
+use crate::{backend, dd_event, dd_incr};
+use eyre::Result;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write as _,
+    sync::Mutex,
+};
+use tokio::task;
+
+/// Serialized name of the [`Config::config_rollout`] parameter, which is not
+/// part of the behavior.
+const ROLLOUT_PARAMETER: &str = "ConfigRollout";
+
+/// Serialized name of the [`Config::kill_switches`] parameter, which is
+/// reported per subsystem.
+const KILL_SWITCHES_PARAMETER: &str = "KillSwitches";
+
+/// Identifiers of the rollouts with a reported dry run.
+static REPORTED_DRY_RUNS: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+
+/// Staged rollout of a remote configuration.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "PascalCase")]
+pub struct Rollout {
+    /// Rollout identifier, reported with the impact.
+    pub id: String,
+    /// Whether the fleet acknowledged the diff and the configuration can be
+    /// activated.
+    pub activated: bool,
+}
+
+/// Behavioral domain of a configuration parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Domain {
+    /// Remotely disabled subsystems.
+    KillSwitches,
+    /// Timeouts, time windows, and intervals.
+    Timeouts,
+    /// Thresholds and limits.
+    Thresholds,
+    /// Everything else: models, UI, and transport settings.
+    Other,
+}
+
+/// Changed configuration parameters grouped by domain.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Serialized names of the changed parameters by domain. A toggled kill
+    /// switch is named `KillSwitches.<subsystem>`.
+    pub changes: BTreeMap<Domain, Vec<String>>,
+}
+
+impl Domain {
+    /// Classifies a parameter by its serialized name.
+    #[must_use]
+    pub fn of(parameter: &str) -> Self {
+        const TIMEOUTS: &[&str] = &[
+            "Timeout",
+            "Window",
+            "Expiration",
+            "Cooldown",
+            "Interval",
+            "MaxAge",
+            "LeadTime",
+            "Wait",
+        ];
+        const THRESHOLDS: &[&str] =
+            &["Threshold", "Score", "Sharpness", "Max", "Min", "Limit", "Budget"];
+        if parameter == KILL_SWITCHES_PARAMETER {
+            Self::KillSwitches
+        } else if TIMEOUTS.iter().any(|pattern| parameter.contains(pattern)) {
+            Self::Timeouts
+        } else if THRESHOLDS.iter().any(|pattern| parameter.contains(pattern)) {
+            Self::Thresholds
+        } else {
+            Self::Other
+        }
+    }
+
+    /// Returns the domain name used in the Datadog tags.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::KillSwitches => "kill_switches",
+            Self::Timeouts => "timeouts",
+            Self::Thresholds => "thresholds",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl Report {
+    /// Computes the impact of replacing `current` with `new`.
+    pub fn new(current: &Config, new: &Config) -> Result<Self> {
+        let (Value::Object(current_map), Value::Object(new_map)) =
+            (serde_json::to_value(current)?, serde_json::to_value(new)?)
+        else {
+            unreachable!("config is serialized as a map");
+        };
+        let mut report = Self::default();
+        let added = new_map.keys().filter(|parameter| !current_map.contains_key(*parameter));
+        for parameter in current_map.keys().chain(added) {
+            let changed = current_map.get(parameter) != new_map.get(parameter);
+            if parameter == ROLLOUT_PARAMETER || !changed {
+                continue;
+            }
+            let parameters = report.changes.entry(Domain::of(parameter)).or_default();
+            if parameter == KILL_SWITCHES_PARAMETER {
+                for subsystem in new.kill_switches.toggled(&current.kill_switches) {
+                    parameters.push(format!("{KILL_SWITCHES_PARAMETER}.{}", subsystem.name()));
+                }
+            } else {
+                parameters.push(parameter.clone());
+            }
+        }
+        Ok(report)
+    }
+
+    /// Returns `true` if no parameter changes.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Logs and reports the impact to Datadog. The dry run of a rollout is
+    /// also acknowledged to the backend in the background.
+    pub fn send(&self, rollout: Option<&Rollout>) {
+        let (rollout_id, stage) = match rollout {
+            Some(rollout) => {
+                (rollout.id.as_str(), if rollout.activated { "activated" } else { "dry_run" })
+            }
+            None => ("none", "immediate"),
+        };
+        let mut text = String::new();
+        for (domain, parameters) in &self.changes {
+            writeln!(text, "{}: {}", domain.name(), parameters.join(", "))
+                .expect("writing to a string can't fail");
+            dd_incr!(
+                "main.count.config.impact",
+                &format!("domain:{}", domain.name()),
+                &format!("stage:{stage}"),
+                &format!("rollout:{rollout_id}")
+            );
+        }
+        tracing::info!("Config impact ({stage}, rollout {rollout_id}):\n{text}");
+        dd_event!(
+            format!("Config impact {stage}"),
+            text,
+            &format!("stage:{stage}"),
+            &format!("rollout:{rollout_id}")
+        );
+        if let Some(rollout) = rollout.filter(|rollout| !rollout.activated) {
+            self.acknowledge(rollout.id.clone());
+        }
+    }
+
+    fn acknowledge(&self, rollout_id: String) {
+        let changes = self
+            .changes
+            .iter()
+            .map(|(domain, parameters)| (domain.name(), parameters.clone()))
+            .collect();
+        task::spawn(async move {
+            let request = backend::config_rollout::Request { rollout_id, changes };
+            if let Err(err) = backend::config_rollout::request(&request).await {
+                tracing::error!("Failed to acknowledge the config rollout: {err:?}");
+                // Report the dry run again on the next poll.
+                REPORTED_DRY_RUNS.lock().unwrap().remove(&request.rollout_id);
+            }
+        });
+    }
+}
+
+/// Returns `true` if the impact of a configuration of `rollout` is to be
+/// reported. A dry run is reported only the first time for each rollout.
+pub fn should_report(rollout: Option<&Rollout>) -> bool {
+    match rollout {
+        Some(rollout) if !rollout.activated => {
+            REPORTED_DRY_RUNS.lock().unwrap().insert(rollout.id.clone())
+        }
+        _ => true,
+    }
+}
+
+/// Returns `true` if the configuration can be activated: it isn't part of a
+/// staged rollout, or the rollout is activated.
+#[must_use]
+pub fn is_activated(rollout: Option<&Rollout>) -> bool {
+    !rollout.is_some_and(|rollout| !rollout.activated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_domain_of() {
+        assert_eq!(Domain::of("KillSwitches"), Domain::KillSwitches);
+        assert_eq!(Domain::of("SelfServeBiometricCaptureTimeout"), Domain::Timeouts);
+        assert_eq!(Domain::of("SignupRateLimitWindow"), Domain::Timeouts);
+        assert_eq!(Domain::of("ChildThreshold"), Domain::Thresholds);
+        assert_eq!(Domain::of("SignupRateLimitMaxFailures"), Domain::Thresholds);
+        assert_eq!(Domain::of("Language"), Domain::Other);
+    }
+
+    #[test]
+    fn test_report() {
+        let current = Config::default();
+        assert!(Report::new(&current, &current).unwrap().is_empty());
+        let mut new = current.clone();
+        new.self_serve_biometric_capture_timeout += Duration::from_secs(1);
+        new.child_threshold = Some(0.5);
+        new.kill_switches.livestream = !new.kill_switches.livestream;
+        new.config_rollout = Some(Rollout { id: "r1".to_owned(), activated: false });
+        let report = Report::new(&current, &new).unwrap();
+        assert_eq!(report.changes[&Domain::Timeouts], ["SelfServeBiometricCaptureTimeout"]);
+        assert_eq!(report.changes[&Domain::Thresholds], ["ChildThreshold"]);
+        assert_eq!(report.changes[&Domain::KillSwitches], ["KillSwitches.Livestream"]);
+        assert!(!report.changes.contains_key(&Domain::Other));
+        assert!(!is_activated(new.config_rollout.as_ref()));
+        assert!(is_activated(None));
+    }
+
+    #[test]
+    fn test_should_report() {
+        let mut rollout = Rollout { id: "test_should_report".to_owned(), activated: false };
+        assert!(should_report(Some(&rollout)));
+        assert!(!should_report(Some(&rollout)));
+        rollout.activated = true;
+        assert!(should_report(Some(&rollout)));
+        assert!(should_report(None));
+    }
+}
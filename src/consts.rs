@@ -30,6 +30,15 @@ pub const DATA_UPLOADER_BASE_DIR: &str = "./tmp/test/data_uploader";
 pub const DATA_UPLOADER_BASE_DIR: &str =
     const_format::formatcp!("{}/{}", SSD_MOUNT_DIR, "orb_tmp/data/data_uploader");
 
+/// Maximal uncompressed size of the files in an image uploader bundle.
+pub const IMAGE_UPLOADER_BUNDLE_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Maximal number of the files in an image uploader bundle.
+pub const IMAGE_UPLOADER_BUNDLE_MAX_FILES: usize = 1000;
+
+/// Files of at least this size are uploaded on their own instead of bundled.
+pub const IMAGE_UPLOADER_BUNDLE_FILE_THRESHOLD: u64 = 1024 * 1024;
+
 #[cfg(test)]
 pub const SSD_STRESS_TEST_FILE: &str = "./tmp/test/ssd_stress.tmp";
 #[cfg(not(test))]
@@ -37,12 +46,72 @@ pub const SSD_STRESS_TEST_FILE: &str = "./tmp/test/ssd_stress.tmp";
 pub const SSD_STRESS_TEST_FILE: &str =
     const_format::formatcp!("{}/{}", SSD_MOUNT_DIR, "orb_tmp/data/ssd_stress.tmp");
 
+#[cfg(test)]
+pub const BROKER_SNAPSHOT_PATH: &str = "./tmp/test/broker_snapshot.json";
+#[cfg(not(test))]
+/// Location on SSD to save the orb broker state snapshot.
+pub const BROKER_SNAPSHOT_PATH: &str =
+    const_format::formatcp!("{}/{}", SSD_MOUNT_DIR, "orb_tmp/state/broker_snapshot.json");
+
+#[cfg(test)]
+pub const SIGNUP_RECEIPTS_LEDGER_PATH: &str = "./tmp/test/signup_receipts.jsonl";
+#[cfg(not(test))]
+/// Location on SSD of the append-only signup receipts ledger.
+pub const SIGNUP_RECEIPTS_LEDGER_PATH: &str =
+    const_format::formatcp!("{}/{}", SSD_MOUNT_DIR, "orb_tmp/state/signup_receipts.jsonl");
+
+/// Minimal interval between the orb broker state snapshots.
+pub const BROKER_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum number of the orb broker decisions recorded during a signup.
+pub const BROKER_EVENT_LOG_CAPACITY: usize = 10_000;
+
+/// Maximum fraction of the signups capturing the pipeline intermediate
+/// artifacts for the debug report.
+pub const DEEP_DEBUG_MAX_SAMPLE_RATE: f64 = 0.05;
+
 /// Path to the configuration directory.
 pub const CONFIG_DIR: &str = "/usr/persistent";
 
+/// Path to the LED animation script overriding the orb-ui animations.
+pub const LED_ANIMATIONS_PATH: &str = const_format::formatcp!("{}/led_animations.json", CONFIG_DIR);
+
+/// Interval of checking [`LED_ANIMATIONS_PATH`] for changes.
+pub const LED_ANIMATIONS_RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Frame rate the scripted LED animations are rendered at.
+pub const LED_ANIMATIONS_FPS: u32 = 30;
+
+/// Path to the configuration change audit log file.
+pub const CONFIG_AUDIT_FILE_PATH: &str =
+    const_format::formatcp!("{}/config_audit.json", CONFIG_DIR);
+
+/// Maximum number of entries kept in the configuration change audit log.
+pub const CONFIG_AUDIT_LOG_LEN: usize = 1000;
+
 /// Path to the mirror calibration configuration file.
 pub const CALIBRATION_FILE_PATH: &str = const_format::formatcp!("{}/calibration.json", CONFIG_DIR);
 
+/// Path to the calibration drift history file.
+pub const CALIBRATION_DRIFT_FILE_PATH: &str =
+    const_format::formatcp!("{}/calibration_drift.json", CONFIG_DIR);
+
+/// Maximum number of events kept in the calibration drift history.
+pub const CALIBRATION_DRIFT_HISTORY_LEN: usize = 100;
+
+/// Path to the health check trend history file.
+pub const HEALTH_CHECK_HISTORY_FILE_PATH: &str =
+    const_format::formatcp!("{}/health_check_history.json", CONFIG_DIR);
+
+/// Maximum number of reports kept in the health check trend history.
+pub const HEALTH_CHECK_HISTORY_LEN: usize = 100;
+
+/// Path to the main MCU firmware image shipped with the Orb OS.
+pub const MCU_MAIN_FIRMWARE_PATH: &str = "/usr/share/orb/mcu/main.signed.bin";
+
+/// Path to the security MCU firmware image shipped with the Orb OS.
+pub const MCU_SEC_FIRMWARE_PATH: &str = "/usr/share/orb/mcu/sec.signed.bin";
+
 /// Path to the configuration directory.
 pub const RGB_CALIBRATION_FILE: &str = "rgb_calibration.json";
 
@@ -58,6 +127,9 @@ pub const DEFAULT_SOUND_VOLUME: u64 = 10;
 /// Maximum sound volume.
 pub const MAX_SOUND_VOLUME: u64 = 100;
 
+/// Maximal time a sound cue can wait in the queue before it's dropped.
+pub const SOUND_MAX_LATENCY: Duration = Duration::from_millis(1500);
+
 /// Time to hold the button to shutdown.
 pub const BUTTON_LONG_PRESS_DURATION: Duration = Duration::from_secs(2);
 
@@ -80,12 +152,26 @@ pub const BATTERY_VOLTAGE_SHUTDOWN_SIGNUP_THRESHOLD_MV: i32 = 12500;
 /// if no shutdown request signal is received.
 pub const GRACEFUL_SHUTDOWN_MAX_DELAY_SECONDS: u8 = 20;
 
+/// Delay left for Jetson to reboot on a scheduled reboot. The microcontroller
+/// will force a power cycle after this delay if no reboot signal is received.
+pub const SCHEDULED_REBOOT_DELAY_SECONDS: u8 = 60;
+
+/// Maximum time to wait for the upload queues to drain before the scheduled
+/// reboot.
+pub const SCHEDULED_REBOOT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Interval of checking whether the scheduled reboot is due while idle.
+pub const SCHEDULED_REBOOT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Time it takes for the shutdown sound to play
 pub const SHUTDOWN_SOUND_DURATION: Duration = Duration::from_millis(2000);
 
 /// Backend config update interval.
 pub const CONFIG_UPDATE_INTERVAL: Duration = Duration::from_secs(10);
 
+/// Night mode schedule evaluation interval.
+pub const NIGHT_MODE_UPDATE_INTERVAL: Duration = Duration::from_secs(30);
+
 /// Backend status update interval.
 pub const STATUS_UPDATE_INTERVAL: Duration = Duration::from_secs(10);
 
@@ -98,10 +184,71 @@ pub const QR_SCAN_REMINDER: Duration = Duration::from_secs(25);
 /// Delay between operator QR code scanning & user QR code scanning.
 pub const QR_SCAN_INTERVAL: Duration = Duration::from_millis(1500);
 
+/// Maximum number of frames merged by the burst QR code decoding.
+pub const QR_BURST_MAX_FRAMES: u8 = 10;
+
+/// Maximum translation in pixels between the burst QR code frames.
+pub const QR_BURST_MAX_SHIFT: usize = 6;
+
+/// How long before the expiration the pre-validated operator QR code is
+/// refreshed.
+pub const OPERATOR_PREVALIDATION_REFRESH_MARGIN: Duration = Duration::from_secs(60 * 10);
+
+/// Retry interval for failed operator QR code pre-validation refreshes.
+pub const OPERATOR_PREVALIDATION_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Distance in meters between the GPS fix and the registered coordinates
+/// after which a stationary orb is considered moved.
+pub const LOCATION_SESSION_MOVEMENT_THRESHOLD: f64 = 200.0;
+
+/// Age after which the location session is registered again.
+pub const LOCATION_SESSION_MAX_AGE: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Retry interval for failed location session registrations.
+pub const LOCATION_SESSION_RETRY_INTERVAL: Duration = Duration::from_secs(60 * 5);
+
+/// Maximal horizontal dilution of precision of a GPS fix used for the
+/// operator location checks.
+pub const GPS_MAX_HDOP: f64 = 5.0;
+
+/// Minimal number of satellites of a GPS fix used for the operator location
+/// checks.
+pub const GPS_MIN_SATELLITES: u8 = 4;
+
+/// Maximal tilt of a correctly mounted orb in degrees, measured by the IMU.
+pub const IMU_MAX_TILT_DEGREES: f64 = 15.0;
+
 /// Face detection timeout.
 pub const DETECT_FACE_TIMEOUT: Duration = Duration::from_secs(20);
 /// Face detection timeout for app-based self-serve mode.
 pub const DETECT_FACE_TIMEOUT_SELF_SERVE: Duration = Duration::from_secs(11);
+/// Maximal number of user QR codes scanned for the self-serve user queue which
+/// don't queue anybody, like the already queued or invalid ones.
+pub const SELF_SERVE_USER_QUEUE_MAX_RESCANS: u32 = 3;
+/// Number of consecutive RGB-Net estimates in which the same person track
+/// must show a face for the face detection to succeed.
+pub const FACE_DETECTION_MIN_TRACK_FRAMES: u32 = 2;
+
+/// Time after which face detection gives up if nobody is present.
+pub const PRESENCE_PRECHECK_DURATION: Duration = Duration::from_millis(1500);
+
+/// ToF distance in mm within which a user is considered present.
+pub const PRESENCE_MAX_DISTANCE_MM: u32 = 2000;
+
+/// ToF distance in mm within which a person is counted as entered the capture
+/// zone for the occupancy analytics.
+pub const OCCUPANCY_ENTER_DISTANCE_MM: u32 = 1500;
+
+/// ToF distance in mm beyond which a person is counted as left the capture
+/// zone for the occupancy analytics.
+pub const OCCUPANCY_LEAVE_DISTANCE_MM: u32 = PRESENCE_MAX_DISTANCE_MM;
+
+/// Time a ToF distance transition must hold to be counted as an occupancy
+/// change.
+pub const OCCUPANCY_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Aggregation window of the occupancy analytics reports.
+pub const OCCUPANCY_REPORT_INTERVAL: Duration = Duration::from_secs(15 * 60);
 
 /// Default IR (infrared) LED duration in microseconds.
 pub const DEFAULT_IR_LED_DURATION: u16 = 350;
@@ -185,14 +332,39 @@ pub const THERMAL_WIDTH: u32 = 156;
 /// Number of rows in raw frames from the thermal camera.
 pub const THERMAL_HEIGHT: u32 = 206;
 
-/// Depth camera FPS.
-pub const DEPTH_USE_CASE: &str = "Mode_5_15fps";
+/// Number of recent thermal frames kept for the alignment with IR frames.
+pub const THERMAL_ALIGNMENT_BUFFER_SIZE: usize = 16;
+
+/// Maximum time distance between an IR frame and a thermal frame used for the
+/// alignment.
+pub const THERMAL_ALIGNMENT_MAX_GAP: Duration = Duration::from_millis(500);
+
+/// Depth camera use case in the long-range mode.
+pub const DEPTH_LONG_RANGE_USE_CASE: &str = "Mode_5_15fps";
+
+/// Depth camera exposure time in microseconds in the long-range mode.
+pub const DEPTH_LONG_RANGE_EXPOSURE_TIME: u32 = 1020;
+
+/// Depth camera FPS in the long-range mode.
+pub const DEPTH_LONG_RANGE_FRAME_RATE: u16 = 15;
 
-/// Depth camera exposure mode.
-pub const DEPTH_EXPOSURE_MANUAL: bool = true;
+/// Depth camera use case in the close-range mode.
+pub const DEPTH_CLOSE_RANGE_USE_CASE: &str = "Mode_5_30fps";
 
-/// Depth camera exposure time in microseconds.
-pub const DEPTH_EXPOSURE_TIME: u32 = 1020;
+/// Depth camera exposure time in microseconds in the close-range mode. Shorter
+/// to avoid saturation of the face in the capture position.
+pub const DEPTH_CLOSE_RANGE_EXPOSURE_TIME: u32 = 300;
+
+/// Depth camera FPS in the close-range mode.
+pub const DEPTH_CLOSE_RANGE_FRAME_RATE: u16 = 30;
+
+/// User distance in mm within which the depth camera switches to the
+/// close-range mode.
+pub const DEPTH_CLOSE_RANGE_ENTER_DISTANCE: f64 = 450.0;
+
+/// User distance in mm beyond which the depth camera switches back to the
+/// long-range mode.
+pub const DEPTH_CLOSE_RANGE_LEAVE_DISTANCE: f64 = 550.0;
 
 /// Number of columns in raw frames from the depth camera.
 pub const DEPTH_WIDTH: u32 = 172;
@@ -212,6 +384,52 @@ pub const DEFAULT_USER_LED_PULSING_SCALE: f32 = 2.0;
 /// Default user led period for the pulsing LED pattern.
 pub const DEFAULT_USER_LED_PULSING_PERIOD: u32 = 4000;
 
+/// Front unit temperature at which the white LEDs start to be derated, in
+/// degrees Celsius.
+pub const WHITE_LED_DERATING_START_TEMPERATURE: f64 = 55.0;
+
+/// Front unit temperature at which the white LEDs are limited to
+/// [`WHITE_LED_DERATING_MIN_BRIGHTNESS`], in degrees Celsius.
+pub const WHITE_LED_DERATING_MAX_TEMPERATURE: f64 = 70.0;
+
+/// Lowest brightness the white LED derating controller can clamp to, in
+/// thousandths.
+pub const WHITE_LED_DERATING_MIN_BRIGHTNESS: u32 = 100;
+
+/// Maximum white LED brightness increase per second while recovering from
+/// derating, in thousandths.
+pub const WHITE_LED_DERATING_RAMP_UP_RATE: f64 = 100.0;
+
+/// Steady-state front unit temperature rise caused by the white LEDs at full
+/// brightness, in degrees Celsius.
+pub const WHITE_LED_THERMAL_RISE_AT_FULL_BRIGHTNESS: f64 = 30.0;
+
+/// Time constant of the white LED thermal model.
+pub const WHITE_LED_THERMAL_TIME_CONSTANT: Duration = Duration::from_secs(120);
+
+/// Front unit temperature assumed by the white LED thermal model until the
+/// first telemetry arrives, in degrees Celsius.
+pub const WHITE_LED_THERMAL_DEFAULT_AMBIENT: f64 = 30.0;
+
+/// Period at which the white LED derating controller re-evaluates the applied
+/// brightness.
+pub const WHITE_LED_DERATING_PERIOD: Duration = Duration::from_secs(1);
+
+/// Every this many consecutive unchanged LED frames one is still sent to the
+/// main MCU.
+pub const LED_FRAMES_RESEND_INTERVAL: u32 = 30;
+
+/// Period at which orb-core sends heartbeats to the main MCU.
+pub const MCU_HEARTBEAT_PERIOD: Duration = Duration::from_secs(5);
+
+/// Time without a heartbeat after which the main MCU starts escalating
+/// towards a Jetson power-cycle.
+pub const MCU_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Number of consecutive unacknowledged heartbeats after which the missed
+/// acknowledges are reported as errors.
+pub const MCU_HEARTBEAT_MISSED_ACKS_ALERT: u32 = 3;
+
 /// Focus lens min setting.
 pub const AUTOFOCUS_MIN: i16 = -400;
 
@@ -221,6 +439,13 @@ pub const AUTOFOCUS_MAX: i16 = 400;
 /// Minimum iris sharpness score to initiate scan.
 pub const IRIS_SHARPNESS_MIN: f64 = 1.00; // TODO: put back 0.68
 
+/// Minimum fraction of valid mask bits for an iris code to be submitted.
+pub const IRIS_CODE_MIN_MASK_COVERAGE: f64 = 0.1;
+
+/// Allowed fraction of set bits among the valid bits of an iris code to be
+/// submitted.
+pub const IRIS_CODE_BIT_BALANCE_RANGE: RangeInclusive<f64> = 0.25..=0.75;
+
 /// Minimum iris sharpness score for sign up.
 pub const IRIS_SCORE_MIN: f64 = 1.70; // TODO: put back 0.68
 
@@ -244,6 +469,10 @@ pub const IR_FOCUS_RANGE: RangeInclusive<f64> = 150.0..=460.0;
 /// Initial focus range.
 pub const IR_FOCUS_RANGE_SMALL: RangeInclusive<f64> = 190.0..=410.0;
 
+/// Maximum age of the RGB-Net user distance used for the eye PID controller
+/// gain scheduling. Older estimates fall back to the ToF distance.
+pub const USER_DISTANCE_MAX_AGE: Duration = Duration::from_millis(500);
+
 /// FPS to save IR (infrared) eye images
 pub const IR_EYE_SAVE_FPS: f32 = 0.5;
 
@@ -256,12 +485,31 @@ pub const RGB_SAVE_FPS: f32 = 0.5;
 /// FPS to save Thermal images
 pub const THERMAL_SAVE_FPS: f32 = 0.5;
 
+/// FPS to save depth point clouds
+pub const DEPTH_SAVE_FPS: f32 = 0.5;
+
 /// Livestream frame width.
 pub const LIVESTREAM_FRAME_WIDTH: u32 = 1920;
 
 /// Livestream frame height.
 pub const LIVESTREAM_FRAME_HEIGHT: u32 = 1080;
 
+/// Path to the token authenticating the livestream remote control commands.
+/// The commands are disabled if the file doesn't exist.
+pub const LIVESTREAM_CONTROL_TOKEN_PATH: &str =
+    const_format::formatcp!("{}/livestream_control_token", CONFIG_DIR);
+
+/// Mirror step of the livestream remote control commands in degrees.
+pub const LIVESTREAM_MIRROR_STEP_DEGREES: f64 = 0.5;
+
+/// Path to the livestream WebRTC transport configuration. The transport is
+/// disabled if the file doesn't exist.
+pub const LIVESTREAM_WEBRTC_CONFIG_PATH: &str =
+    const_format::formatcp!("{}/livestream_webrtc.json", CONFIG_DIR);
+
+/// Delay before reconnecting to the livestream WebRTC signaling server.
+pub const LIVESTREAM_WEBRTC_RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+
 /// Minimal interval between slower, closer or farther sounds.
 pub const IR_VOICE_TIME_INTERVAL: Duration = Duration::from_secs(2);
 
@@ -289,6 +537,21 @@ pub const MIRROR_THETA_MIN_DIAMOND: u32 = 90000 - 20000;
 /// Maximum theta angle for the mirror, on Diamond.
 pub const MIRROR_THETA_MAX_DIAMOND: u32 = 90000 + 20000;
 
+/// Maximum mirror angular speed the motors can follow without losing steps.
+pub const MIRROR_MAX_SLEW_DEGREES_PER_SEC: f64 = 60.0;
+
+/// Accumulated mirror angle commanded beyond the maximum slew, after which
+/// step loss is suspected.
+pub const MIRROR_STEP_LOSS_THRESHOLD_DEGREES: f64 = 2.0;
+
+/// Relative deviation of a mirror motor range from the first one reported
+/// since boot, after which a range change is recorded.
+pub const MIRROR_RANGE_TOLERANCE: f64 = 0.05;
+
+/// Time to wait for a quick mirror rehoming between biometric capture
+/// objectives.
+pub const MIRROR_QUICK_HOMING_DURATION: Duration = Duration::from_millis(1500);
+
 /// Reducer coefficient for continuous calibration. Must be less than or equal
 /// to `1.0`.
 pub const CONTINUOUS_CALIBRATION_REDUCER: f64 = 0.05;
@@ -308,6 +571,20 @@ pub const MINIMUM_FAN_SPEED: f32 = 1.0;
 /// Note: For EV1 (louder fan), this is scaled down via backend configuration
 pub const MAXIMUM_FAN_SPEED: f32 = 80.0;
 
+/// Time window of the brownout prediction.
+pub const BROWNOUT_WINDOW: Duration = Duration::from_secs(30);
+
+/// Relative voltage drop of a rail within [`BROWNOUT_WINDOW`] which predicts a
+/// brownout.
+pub const BROWNOUT_DROP_RATIO: f64 = 0.15;
+
+/// Minimal time between two brownout state flushes.
+pub const BROWNOUT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Delay left for Jetson to flush its state on a predicted brownout. The
+/// microcontroller will hold the power up to this delay before shutting down.
+pub const BROWNOUT_SHUTDOWN_DELAY_SECONDS: u8 = 20;
+
 /// WC Data Encryption Pubkey
 pub const WORLDCOIN_ENCRYPTION_PUBKEY: box_::PublicKey = {
     #[cfg(not(feature = "stage"))]
@@ -328,6 +605,15 @@ pub const WORLDCOIN_ENCRYPTION_PUBKEY: box_::PublicKey = {
     }
 };
 
+/// Path to the raw Ed25519 public key of the backend signing the responses
+/// served through a venue caching proxy, and the signup receipts. The key is
+/// shipped with the Orb OS.
+pub const BACKEND_RESPONSE_SIGNING_PUBKEY_PATH: &str =
+    "/usr/share/orb/backend_response_signing.pub";
+
+/// Maximum age of a signed backend response served by a venue caching proxy.
+pub const VENUE_CACHE_MAX_RESPONSE_AGE: Duration = Duration::from_secs(60 * 60);
+
 /// WC Data Encryption Private key (only for tests!)
 #[cfg(all(feature = "stage", test))]
 pub const WORLDCOIN_ENCRYPTION_SECRETKEY: box_::SecretKey = box_::SecretKey([
@@ -344,13 +630,76 @@ pub const DBUS_SIGNUP_INTERFACE_NAME: &str = "org.worldcoin.OrbCore1.Signup";
 /// The object path under which the broker will advertise the signup interface.
 pub const DBUS_SIGNUP_OBJECT_PATH: &str = "/org/worldcoin/OrbCore1/Signup";
 
+/// The name that the broker will use for the upload queues interface.
+pub const DBUS_UPLOADS_INTERFACE_NAME: &str = "org.worldcoin.OrbCore1.Uploads";
+
+/// The object path under which the broker will advertise the upload queues
+/// interface.
+pub const DBUS_UPLOADS_OBJECT_PATH: &str = "/org/worldcoin/OrbCore1/Uploads";
+
+/// The name that the broker will use for the network interface.
+pub const DBUS_NETWORK_INTERFACE_NAME: &str = "org.worldcoin.OrbCore1.Network";
+
+/// The object path under which the broker will advertise the network
+/// interface.
+pub const DBUS_NETWORK_OBJECT_PATH: &str = "/org/worldcoin/OrbCore1/Network";
+
+/// The name that the broker will use for the identity interface.
+pub const DBUS_IDENTITY_INTERFACE_NAME: &str = "org.worldcoin.OrbCore1.Identity";
+
+/// The object path under which the broker will advertise the identity
+/// interface.
+pub const DBUS_IDENTITY_OBJECT_PATH: &str = "/org/worldcoin/OrbCore1/Identity";
+
+/// The name that the broker will use for the maintenance interface.
+pub const DBUS_MAINTENANCE_INTERFACE_NAME: &str = "org.worldcoin.OrbCore1.Maintenance";
+
+/// The object path under which the broker will advertise the maintenance
+/// interface.
+pub const DBUS_MAINTENANCE_OBJECT_PATH: &str = "/org/worldcoin/OrbCore1/Maintenance";
+
+/// The well known name used by `orb-backend-connect` in the daemon mode.
+pub const DBUS_BACKEND_CONNECT_BUS_NAME: &str = "org.worldcoin.OrbBackendConnect1";
+
+/// The name that `orb-backend-connect` will use for the connectivity interface.
+pub const DBUS_CONNECTIVITY_INTERFACE_NAME: &str = "org.worldcoin.OrbBackendConnect1.Connectivity";
+
+/// The object path under which `orb-backend-connect` will advertise the
+/// connectivity interface.
+pub const DBUS_CONNECTIVITY_OBJECT_PATH: &str = "/org/worldcoin/OrbBackendConnect1/Connectivity";
+
 // TODO: This should be a getter function from ir_net rather than a constant.
 /// Threshold for a valid signup in terms of occlusion 30.
 pub const THRESHOLD_OCCLUSION_30: f64 = 0.85;
 
+/// Iris center offset from the optical axis in millimeters above which the
+/// gaze guidance is shown.
+pub const GAZE_GUIDANCE_DEADZONE_MM: f64 = 3.0;
+
+/// Iris center offset in millimeters at which the gaze guidance reaches full
+/// intensity.
+pub const GAZE_GUIDANCE_MAX_OFFSET_MM: f64 = 12.0;
+
+/// Fraction of [`GAZE_GUIDANCE_DEADZONE_MM`] below which an active gaze
+/// guidance is turned off.
+pub const GAZE_GUIDANCE_HYSTERESIS: f64 = 0.7;
+
+/// Angular length of the gaze guidance ring segment in degrees.
+pub const GAZE_GUIDANCE_ARC_DEGREES: f64 = 60.0;
+
+/// Number of ring segments of the signup queue indicator.
+pub const QUEUE_INDICATOR_SEGMENTS: u32 = 12;
+
 /// Default maximum fan speed.
 pub const DEFAULT_MAX_FAN_SPEED: f32 = 100.0;
 
+/// Fan speed of the silent-mode confirmation pulse.
+pub const HAPTIC_FAN_PULSE_SPEED: f32 = DEFAULT_MAX_FAN_SPEED;
+
+/// Maximum delay of a silent-mode confirmation cue behind its event. Later cues
+/// are dropped.
+pub const HAPTIC_CUE_MAX_DELAY: Duration = Duration::from_secs(2);
+
 /// Length of the across signup face correlation queue.
 pub const ACROSS_SIGNUP_FACE_CORRELATION_QUEUE_LENGTH: usize = 100;
 
@@ -361,6 +710,65 @@ pub const DEFAULT_DELAY_BETWEEN_EYE_CAPTURES: Duration = Duration::from_millis(2
 /// Default image upload delay in seconds.
 pub const DEFAULT_IMAGE_UPLOAD_DELAY: u64 = 60 * 60;
 
+/// Interval between the image retention policy enforcements.
+pub const IMAGE_RETENTION_REAP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Interval between the data-acquisition image compaction runs.
+pub const IMAGE_COMPACTION_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Delay between the CPU load checks while the image compaction is throttled.
+pub const IMAGE_COMPACTION_THROTTLE_DELAY: Duration = Duration::from_secs(5);
+
+/// Jetson CPU/GPU temperature in degrees Celsius at which the clocks start to
+/// throttle.
+pub const THERMAL_THROTTLING_TEMPERATURE: f64 = 80.0;
+
+/// Window of the Jetson temperature samples used to estimate the trend.
+pub const THERMAL_TREND_WINDOW: Duration = Duration::from_secs(120);
+
+/// Throttling predicted within this time after the expected signup start
+/// triggers the pre-cooling. Covers a typical signup.
+pub const THERMAL_THROTTLING_HORIZON: Duration = Duration::from_secs(180);
+
+/// Delay between the checks while the background tasks are deferred by the
+/// pre-cooling.
+pub const THERMAL_PRECOOL_CHECK_DELAY: Duration = Duration::from_secs(1);
+
+/// Clock skew from the backend above which a warning is raised. Signed
+/// backend requests start failing around this skew.
+pub const CLOCK_SKEW_WARNING_THRESHOLD: Duration = Duration::from_secs(60);
+
+/// Thermal camera sensor temperature in Kelvin above which the sensor is
+/// considered overheated.
+pub const THERMAL_CAMERA_OVERHEAT_TEMPERATURE: f32 = 338.15;
+
+/// The thermal camera sensor is considered cooled down once its temperature
+/// drops this many Kelvin below [`THERMAL_CAMERA_OVERHEAT_TEMPERATURE`].
+pub const THERMAL_CAMERA_OVERHEAT_HYSTERESIS: f32 = 5.0;
+
+/// Interval between the thermal camera sensor temperature telemetry reports.
+pub const THERMAL_CAMERA_TEMPERATURE_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Directory where the kernel writes core dumps and where the crash reports
+/// are stored until uploaded.
+pub const CRASH_DIR: &str = const_format::formatcp!("{}/crash", SSD_MOUNT_DIR);
+
+/// Directory with the symbol files shipped with orb-core, named by build ID.
+pub const CRASH_SYMBOLS_DIR: &str = "/usr/share/orb-core/symbols";
+
+/// Core dump size limit in bytes for orb-core and its agents.
+pub const CRASH_CORE_DUMP_LIMIT: u64 = 512 * 1024 * 1024;
+
+/// Number of bytes read from the beginning of a core dump to find the program
+/// headers and the notes.
+pub const CRASH_CORE_HEADER_LIMIT: u64 = 4 * 1024 * 1024;
+
+/// Number of the latest core dumps kept on disk.
+pub const CRASH_MAX_CORE_DUMPS: usize = 3;
+
+/// Interval between the core dump collections.
+pub const CRASH_COLLECT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
 /// Default maximum ping delay in milliseconds for acceptable network connection.
 pub const DEFAULT_SLOW_INTERNET_PING_THRESHOLD: Duration = Duration::from_millis(700);
 
@@ -369,3 +777,39 @@ pub const DEFAULT_BLOCK_SIGNUPS_WHEN_NO_INTERNET: bool = true;
 
 /// Default amount of time to wait until we assume the camera is stuck pairing.
 pub const DEFAULT_THERMAL_CAMERA_PAIRING_STATUS_TIMEOUT: Duration = Duration::from_millis(2000);
+
+/// Default CUDA stream priorities of the Python agents, by agent name. Lower
+/// values mean higher priority. IR-Net is capture-critical and must preempt
+/// the background face identifier work.
+pub const DEFAULT_GPU_STREAM_PRIORITIES: [(&str, i32); 2] =
+    [("ir-net", -5), ("face-identifier", 0)];
+
+/// Interval between the frames sampled by the lens dirt detection.
+pub const LENS_DIRT_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of the samples the lens dirt detection baseline is learned from
+/// before the detection starts.
+pub const LENS_DIRT_BASELINE_SAMPLES: u32 = 300;
+
+/// Minimum mean frame brightness for the lens dirt detection. Darker frames
+/// are skipped.
+pub const LENS_DIRT_MIN_BRIGHTNESS: f64 = 20.0;
+
+/// The lens is flagged as dirty when the short-term sharpness drops below this
+/// fraction of the baseline.
+pub const LENS_DIRT_SHARPNESS_RATIO: f64 = 0.6;
+
+/// The lens is flagged as dirty when the short-term vignetting deviates from
+/// the baseline by more than this value.
+pub const LENS_DIRT_VIGNETTING_DELTA: f64 = 0.15;
+
+/// How long the RGB camera worker waits for the camera device node to be
+/// re-attached after it was removed.
+pub const RGB_REATTACH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Initial delay between the RGB camera pipeline rebuild attempts after the
+/// camera device node was re-attached. Doubles after every failed attempt.
+pub const RGB_REATTACH_BACKOFF: Duration = Duration::from_millis(100);
+
+/// How often a throttled bulk transfer re-checks its daily traffic budget.
+pub const TRAFFIC_THROTTLE_POLL_INTERVAL: Duration = Duration::from_secs(60);
@@ -0,0 +1,360 @@
+//! Minimal ELF64 little-endian parsing for executables and core dumps.
+//!
+//! Only the parts needed for crash reports are implemented: program headers,
+//! notes, the GNU build ID, the crashing thread registers, and the file
+//! mappings of a core dump.
+
+use std::ops::Range;
+
+const EI_CLASS_64: u8 = 2;
+const EI_DATA_LE: u8 = 1;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_GNU_BUILD_ID: u32 = 3;
+const NT_FILE: u32 = 0x4649_4c45;
+
+/// Offset of `pr_cursig` in `struct elf_prstatus`.
+const PRSTATUS_CURSIG_OFFSET: usize = 12;
+/// Offset of `pr_reg` in `struct elf_prstatus`.
+const PRSTATUS_REG_OFFSET: usize = 112;
+
+/// ELF file header fields.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// Target architecture.
+    pub machine: u16,
+    /// Program header table offset.
+    pub phoff: u64,
+    /// Program header entry size.
+    pub phentsize: u16,
+    /// Number of program header entries.
+    pub phnum: u16,
+}
+
+/// Program header fields.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProgramHeader {
+    /// Segment type.
+    pub kind: u32,
+    /// Segment file offset.
+    pub offset: u64,
+    /// Segment virtual address.
+    pub vaddr: u64,
+    /// Segment size in the file.
+    pub filesz: u64,
+}
+
+/// ELF note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Note<'a> {
+    /// Note owner name without the trailing NUL.
+    pub name: &'a [u8],
+    /// Note type.
+    pub kind: u32,
+    /// Note payload.
+    pub desc: &'a [u8],
+}
+
+/// File mapping from the `NT_FILE` core dump note.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mapping {
+    /// Mapped virtual address range.
+    pub range: Range<u64>,
+    /// Offset of the mapping in the file.
+    pub file_offset: u64,
+    /// Path of the mapped file.
+    pub path: String,
+}
+
+/// Crashing thread state from the first `NT_PRSTATUS` core dump note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThreadState {
+    /// Signal which caused the crash.
+    pub signal: u16,
+    /// Program counter.
+    pub pc: u64,
+    /// Link register, if the architecture has one.
+    pub lr: Option<u64>,
+}
+
+/// Parses the ELF file header.
+#[must_use]
+pub fn header(data: &[u8]) -> Option<Header> {
+    if data.get(..4)? != b"\x7fELF" || *data.get(4)? != EI_CLASS_64 || *data.get(5)? != EI_DATA_LE {
+        return None;
+    }
+    Some(Header {
+        machine: u16_at(data, 18)?,
+        phoff: u64_at(data, 32)?,
+        phentsize: u16_at(data, 54)?,
+        phnum: u16_at(data, 56)?,
+    })
+}
+
+/// Parses the program header table. Entries past the end of `data` are
+/// skipped.
+#[must_use]
+pub fn program_headers(data: &[u8], header: &Header) -> Vec<ProgramHeader> {
+    (0..usize::from(header.phnum))
+        .filter_map(|i| {
+            let base = usize::try_from(header.phoff).ok()? + i * usize::from(header.phentsize);
+            Some(ProgramHeader {
+                kind: u32_at(data, base)?,
+                offset: u64_at(data, base + 8)?,
+                vaddr: u64_at(data, base + 16)?,
+                filesz: u64_at(data, base + 32)?,
+            })
+        })
+        .collect()
+}
+
+/// Iterates over the notes in a `PT_NOTE` segment payload.
+pub fn notes(mut data: &[u8]) -> impl Iterator<Item = Note<'_>> {
+    std::iter::from_fn(move || {
+        let namesz = usize::try_from(u32_at(data, 0)?).ok()?;
+        let descsz = usize::try_from(u32_at(data, 4)?).ok()?;
+        let kind = u32_at(data, 8)?;
+        let name_start = 12;
+        let desc_start = name_start + align4(namesz);
+        let next = desc_start + align4(descsz);
+        let name = data.get(name_start..name_start + namesz)?;
+        let desc = data.get(desc_start..desc_start + descsz)?;
+        data = data.get(next..).unwrap_or_default();
+        Some(Note { name: name.strip_suffix(b"\0").unwrap_or(name), kind, desc })
+    })
+}
+
+/// Returns the notes of all `PT_NOTE` segments contained in `data`.
+#[must_use]
+pub fn segment_notes(data: &[u8]) -> Vec<Note<'_>> {
+    let Some(header) = header(data) else { return Vec::new() };
+    program_headers(data, &header)
+        .into_iter()
+        .filter(|phdr| phdr.kind == PT_NOTE)
+        .filter_map(|phdr| slice(data, phdr.offset, phdr.filesz))
+        .flat_map(notes)
+        .collect()
+}
+
+/// Extracts the GNU build ID from the beginning of an ELF image, formatted as
+/// a lowercase hex string.
+#[must_use]
+pub fn build_id(image: &[u8]) -> Option<String> {
+    segment_notes(image)
+        .into_iter()
+        .find(|note| note.kind == NT_GNU_BUILD_ID && note.name == b"GNU")
+        .map(|note| hex::encode(note.desc))
+}
+
+/// Returns the `PT_LOAD` segments of a core dump or an executable.
+#[must_use]
+pub fn load_segments(data: &[u8]) -> Vec<ProgramHeader> {
+    let Some(header) = header(data) else { return Vec::new() };
+    program_headers(data, &header).into_iter().filter(|phdr| phdr.kind == PT_LOAD).collect()
+}
+
+/// Translates a file offset of an executable to its link-time virtual
+/// address through the `PT_LOAD` segments of the executable.
+#[must_use]
+pub fn file_offset_to_vaddr(segments: &[ProgramHeader], offset: u64) -> Option<u64> {
+    segments
+        .iter()
+        .find(|segment| (segment.offset..segment.offset + segment.filesz).contains(&offset))
+        .map(|segment| offset - segment.offset + segment.vaddr)
+}
+
+/// Extracts the crashing thread state from a core dump. The kernel writes the
+/// dumping thread first.
+#[must_use]
+pub fn thread_state(data: &[u8]) -> Option<ThreadState> {
+    let machine = header(data)?.machine;
+    let note = segment_notes(data)
+        .into_iter()
+        .find(|note| note.kind == NT_PRSTATUS && note.name == b"CORE")?;
+    let signal = u16_at(note.desc, PRSTATUS_CURSIG_OFFSET)?;
+    let reg = |index: usize| u64_at(note.desc, PRSTATUS_REG_OFFSET + index * 8);
+    match machine {
+        // `struct user_pt_regs`: x0..x30, sp, pc.
+        EM_AARCH64 => Some(ThreadState { signal, pc: reg(32)?, lr: Some(reg(30)?) }),
+        // `struct user_regs_struct`: rip is the 17th register.
+        EM_X86_64 => Some(ThreadState { signal, pc: reg(16)?, lr: None }),
+        _ => None,
+    }
+}
+
+/// Extracts the file mappings from a core dump.
+#[must_use]
+pub fn mappings(data: &[u8]) -> Vec<Mapping> {
+    segment_notes(data)
+        .into_iter()
+        .find(|note| note.kind == NT_FILE && note.name == b"CORE")
+        .and_then(|note| parse_file_note(note.desc))
+        .unwrap_or_default()
+}
+
+fn parse_file_note(desc: &[u8]) -> Option<Vec<Mapping>> {
+    let count = usize::try_from(u64_at(desc, 0)?).ok()?;
+    let page_size = u64_at(desc, 8)?;
+    let paths_start = 16 + count.checked_mul(24)?;
+    let mut paths = desc.get(paths_start..)?.split(|&byte| byte == 0);
+    (0..count)
+        .map(|i| {
+            let base = 16 + i * 24;
+            Some(Mapping {
+                range: u64_at(desc, base)?..u64_at(desc, base + 8)?,
+                file_offset: u64_at(desc, base + 16)?.checked_mul(page_size)?,
+                path: String::from_utf8_lossy(paths.next()?).into_owned(),
+            })
+        })
+        .collect()
+}
+
+fn slice(data: &[u8], offset: u64, len: u64) -> Option<&[u8]> {
+    let start = usize::try_from(offset).ok()?;
+    data.get(start..start.checked_add(usize::try_from(len).ok()?)?)
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    fn note(name: &[u8], kind: u32, desc: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&u32::try_from(name.len() + 1).unwrap().to_le_bytes());
+        out.extend_from_slice(&u32::try_from(desc.len()).unwrap().to_le_bytes());
+        out.extend_from_slice(&kind.to_le_bytes());
+        out.extend_from_slice(name);
+        out.push(0);
+        out.resize(align4(out.len()), 0);
+        out.extend_from_slice(desc);
+        out.resize(align4(out.len()), 0);
+        out
+    }
+
+    /// Builds an ELF image with a single `PT_NOTE` segment and extra
+    /// `PT_LOAD` segments.
+    pub(in super::super) fn elf(machine: u16, notes: &[u8], loads: &[(u64, u64, u64)]) -> Vec<u8> {
+        let phnum = 1 + loads.len();
+        let notes_offset = 64 + 56 * phnum;
+        let mut out = vec![0; 64];
+        out[..4].copy_from_slice(b"\x7fELF");
+        out[4] = EI_CLASS_64;
+        out[5] = EI_DATA_LE;
+        out[18..20].copy_from_slice(&machine.to_le_bytes());
+        out[32..40].copy_from_slice(&64_u64.to_le_bytes());
+        out[54..56].copy_from_slice(&56_u16.to_le_bytes());
+        out[56..58].copy_from_slice(&u16::try_from(phnum).unwrap().to_le_bytes());
+        let mut phdr = |kind: u32, offset: u64, vaddr: u64, filesz: u64| {
+            let mut entry = vec![0; 56];
+            entry[..4].copy_from_slice(&kind.to_le_bytes());
+            entry[8..16].copy_from_slice(&offset.to_le_bytes());
+            entry[16..24].copy_from_slice(&vaddr.to_le_bytes());
+            entry[32..40].copy_from_slice(&filesz.to_le_bytes());
+            out.extend_from_slice(&entry);
+        };
+        phdr(PT_NOTE, notes_offset as u64, 0, notes.len() as u64);
+        for &(offset, vaddr, filesz) in loads {
+            phdr(PT_LOAD, offset, vaddr, filesz);
+        }
+        out.extend_from_slice(notes);
+        out
+    }
+
+    pub(in super::super) fn build_id_note(id: &[u8]) -> Vec<u8> {
+        note(b"GNU", NT_GNU_BUILD_ID, id)
+    }
+
+    pub(in super::super) fn core_notes(
+        signal: u16,
+        regs: &[(usize, u64)],
+        files: &[(u64, u64, u64, &str)],
+    ) -> Vec<u8> {
+        let mut prstatus = vec![0; PRSTATUS_REG_OFFSET + 34 * 8];
+        prstatus[PRSTATUS_CURSIG_OFFSET..PRSTATUS_CURSIG_OFFSET + 2]
+            .copy_from_slice(&signal.to_le_bytes());
+        for &(index, value) in regs {
+            let offset = PRSTATUS_REG_OFFSET + index * 8;
+            prstatus[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        let mut file = Vec::new();
+        file.extend_from_slice(&(files.len() as u64).to_le_bytes());
+        file.extend_from_slice(&4096_u64.to_le_bytes());
+        for &(start, end, page, _) in files {
+            for value in [start, end, page] {
+                file.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        for &(.., path) in files {
+            file.extend_from_slice(path.as_bytes());
+            file.push(0);
+        }
+        let mut out = note(b"CORE", NT_PRSTATUS, &prstatus);
+        out.extend(note(b"CORE", NT_FILE, &file));
+        out
+    }
+
+    #[test]
+    fn test_build_id() {
+        let image = elf(EM_AARCH64, &build_id_note(&[0xde, 0xad, 0xbe, 0xef]), &[]);
+        assert_eq!(build_id(&image).as_deref(), Some("deadbeef"));
+        assert_eq!(build_id(&image[..70]), None);
+        assert_eq!(build_id(b"not an elf"), None);
+    }
+
+    #[test]
+    fn test_core_dump() {
+        let notes = core_notes(11, &[(30, 0x1234), (32, 0x5678)], &[
+            (0x40_0000, 0x50_0000, 0, "/usr/bin/orb-core"),
+            (0x7f00_0000, 0x7f01_0000, 2, "/usr/lib/libc.so.6"),
+        ]);
+        let core = elf(EM_AARCH64, &notes, &[(0x1000, 0x40_0000, 0x1000)]);
+        assert_eq!(
+            thread_state(&core),
+            Some(ThreadState { signal: 11, pc: 0x5678, lr: Some(0x1234) })
+        );
+        let mappings = mappings(&core);
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[1].range, 0x7f00_0000..0x7f01_0000);
+        assert_eq!(mappings[1].file_offset, 0x2000);
+        assert_eq!(mappings[1].path, "/usr/lib/libc.so.6");
+        assert_eq!(load_segments(&core), vec![ProgramHeader {
+            kind: PT_LOAD,
+            offset: 0x1000,
+            vaddr: 0x40_0000,
+            filesz: 0x1000
+        }]);
+        let x86 = elf(EM_X86_64, &core_notes(6, &[(16, 0x9abc)], &[]), &[]);
+        assert_eq!(thread_state(&x86), Some(ThreadState { signal: 6, pc: 0x9abc, lr: None }));
+    }
+
+    #[test]
+    fn test_file_offset_to_vaddr() {
+        let image = elf(EM_AARCH64, &[], &[(0, 0, 0x800), (0x1000, 0x1_1000, 0x2000)]);
+        let segments = load_segments(&image);
+        assert_eq!(file_offset_to_vaddr(&segments, 0x10), Some(0x10));
+        assert_eq!(file_offset_to_vaddr(&segments, 0x1010), Some(0x1_1010));
+        assert_eq!(file_offset_to_vaddr(&segments, 0x900), None);
+        assert_eq!(file_offset_to_vaddr(&segments, 0x3000), None);
+    }
+}
@@ -0,0 +1,316 @@
+//! Core dump capture and crash reports.
+//!
+//! [`init`] bounds the core dump size for orb-core. Process-based agents are
+//! re-executions of the same binary and inherit the limit. The OS configures
+//! `kernel.core_pattern` to write the dumps into [`CRASH_DIR`] as
+//! `core.<comm>.<pid>.<time>`.
+//!
+//! [`run`] periodically processes new dumps: it extracts the crashing thread
+//! state and the build ID of the crashed executable, symbolizes the top frames
+//! with the symbol file shipped for that build, translating the file offsets
+//! through the program headers of the executable, renames the dump to
+//! `<name>.<build_id>.core`, and stores a compact JSON report next to it. The
+//! reports are then uploaded to the backend and removed, and only the latest
+//! [`CRASH_MAX_CORE_DUMPS`] dumps are kept on disk.
+
+pub mod elf;
+pub mod symbols;
+
+use crate::{
+    backend::upload_crash_report,
+    consts::{
+        CRASH_COLLECT_INTERVAL, CRASH_CORE_DUMP_LIMIT, CRASH_CORE_HEADER_LIMIT, CRASH_DIR,
+        CRASH_MAX_CORE_DUMPS, CRASH_SYMBOLS_DIR,
+    },
+    dd_incr,
+};
+use eyre::{Result, WrapErr as _};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::Read as _,
+    os::unix::fs::FileExt as _,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::{task, time};
+
+const CORE_PREFIX: &str = "core.";
+const CORE_SUFFIX: &str = ".core";
+const REPORT_SUFFIX: &str = ".json";
+const UNKNOWN_BUILD_ID: &str = "unknown";
+
+/// Maximum size of the executable image prefix read to find the build ID.
+const IMAGE_HEADER_LIMIT: u64 = 64 * 1024;
+
+/// Minimal age of a core dump file to be processed, so that dumps which are
+/// still being written are skipped.
+const SETTLE_TIME: Duration = Duration::from_secs(30);
+
+/// Build ID of the running executable.
+pub static BUILD_ID: Lazy<Option<String>> = Lazy::new(|| {
+    let mut image = Vec::new();
+    File::open("/proc/self/exe")
+        .and_then(|file| file.take(IMAGE_HEADER_LIMIT).read_to_end(&mut image))
+        .ok()?;
+    elf::build_id(&image)
+});
+
+/// Compact crash report.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    /// Name of the crashed process or thread.
+    pub process: String,
+    /// PID of the crashed process.
+    pub pid: Option<u32>,
+    /// UNIX timestamp of the crash.
+    pub timestamp: Option<u64>,
+    /// Signal which caused the crash.
+    pub signal: Option<u16>,
+    /// Build ID of the crashed executable.
+    pub build_id: Option<String>,
+    /// Version of the orb-core which produced the report.
+    pub reporter_version: String,
+    /// Top stack frames of the crashing thread, innermost first.
+    pub frames: Vec<Frame>,
+}
+
+/// Stack frame of a crash report.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Frame {
+    /// Absolute address.
+    pub address: u64,
+    /// File name of the module containing the address.
+    pub module: Option<String>,
+    /// Address relative to the module base.
+    pub module_offset: Option<u64>,
+    /// Symbol name with an offset, if symbolized.
+    pub symbol: Option<String>,
+}
+
+/// Bounds the core dump size for this process and its children.
+pub fn init() {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut limit) } != 0 {
+        tracing::warn!("Couldn't get core dump limit: {}", std::io::Error::last_os_error());
+        return;
+    }
+    let max = limit.rlim_max.min(CRASH_CORE_DUMP_LIMIT);
+    let limit = libc::rlimit { rlim_cur: max, rlim_max: max };
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } == 0 {
+        tracing::info!("Core dumps enabled up to {max} bytes, build ID: {:?}", *BUILD_ID);
+    } else {
+        tracing::warn!("Couldn't set core dump limit: {}", std::io::Error::last_os_error());
+    }
+}
+
+/// Processes new core dumps and uploads the crash reports until the program
+/// exits.
+pub async fn run() {
+    let mut interval = time::interval(CRASH_COLLECT_INTERVAL);
+    loop {
+        interval.tick().await;
+        match task::spawn_blocking(|| collect(Path::new(CRASH_DIR))).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => tracing::error!("Crash dump collection failed: {err:?}"),
+            Err(err) => tracing::error!("Crash dump collection panicked: {err:?}"),
+        }
+        if let Err(err) = upload(Path::new(CRASH_DIR)).await {
+            tracing::error!("Crash report upload failed: {err:?}");
+        }
+    }
+}
+
+/// Turns new core dumps in `dir` into crash reports and removes old dumps.
+pub fn collect(dir: &Path) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    for path in entries(dir)? {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else { continue };
+        if !name.starts_with(CORE_PREFIX)
+            || name.ends_with(CORE_SUFFIX)
+            || name.ends_with(REPORT_SUFFIX)
+        {
+            continue;
+        }
+        let modified = path.metadata()?.modified()?;
+        if SystemTime::now().duration_since(modified).unwrap_or_default() < SETTLE_TIME {
+            continue;
+        }
+        let report =
+            analyze_file(&path).wrap_err_with(|| format!("analyzing {}", path.display()))?;
+        tracing::warn!("Found a crash dump: {report:?}");
+        dd_incr!("main.count.global.crash.core_dump", &format!("process:{}", report.process));
+        let stem = format!("{name}.{}", report.build_id.as_deref().unwrap_or(UNKNOWN_BUILD_ID));
+        fs::write(dir.join(format!("{stem}{REPORT_SUFFIX}")), serde_json::to_vec(&report)?)?;
+        fs::rename(&path, dir.join(format!("{stem}{CORE_SUFFIX}")))?;
+    }
+    let mut dumps = entries(dir)?
+        .into_iter()
+        .filter(|path| path.to_str().is_some_and(|path| path.ends_with(CORE_SUFFIX)))
+        .filter_map(|path| Some((path.metadata().ok()?.modified().ok()?, path)))
+        .collect::<Vec<_>>();
+    dumps.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+    for (_, path) in dumps.into_iter().skip(CRASH_MAX_CORE_DUMPS) {
+        tracing::info!("Removing old crash dump {}", path.display());
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Uploads the pending crash reports in `dir`, removing the uploaded ones.
+pub async fn upload(dir: &Path) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    for path in entries(dir)? {
+        if !path.to_str().is_some_and(|path| path.ends_with(REPORT_SUFFIX)) {
+            continue;
+        }
+        let report: Report = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+        upload_crash_report::request(&report).await?;
+        dd_incr!("main.count.global.crash.report_uploaded");
+        tokio::fs::remove_file(&path).await?;
+    }
+    Ok(())
+}
+
+fn entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    Ok(fs::read_dir(dir)?.map(|entry| entry.map(|entry| entry.path())).collect::<Result<_, _>>()?)
+}
+
+fn analyze_file(path: &Path) -> Result<Report> {
+    let file = File::open(path)?;
+    let mut core = Vec::new();
+    (&file).take(CRASH_CORE_HEADER_LIMIT).read_to_end(&mut core)?;
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    Ok(analyze(
+        name,
+        &core,
+        |offset, len| {
+            let mut image = vec![0; usize::try_from(len).ok()?];
+            file.read_exact_at(&mut image, offset).ok()?;
+            Some(image)
+        },
+        |build_id| symbols::Table::load(Path::new(CRASH_SYMBOLS_DIR), build_id).ok(),
+    ))
+}
+
+/// Builds a crash report from the beginning of a core dump named `name`.
+/// `read_at` reads a range of the core dump, and `load_symbols` loads the
+/// symbol table for a build ID.
+fn analyze(
+    name: &str,
+    core: &[u8],
+    read_at: impl FnOnce(u64, u64) -> Option<Vec<u8>>,
+    load_symbols: impl FnOnce(&str) -> Option<symbols::Table>,
+) -> Report {
+    let mut fields = name.strip_prefix(CORE_PREFIX).unwrap_or(name).rsplitn(3, '.');
+    let timestamp = fields.next().and_then(|field| field.parse().ok());
+    let pid = fields.next().and_then(|field| field.parse().ok());
+    let process = fields.next().unwrap_or(name).to_owned();
+
+    let state = elf::thread_state(core);
+    let mappings = elf::mappings(core);
+    // The executable is the first file mapping.
+    let executable = mappings.first().filter(|mapping| mapping.file_offset == 0);
+    let image = executable.and_then(|executable| {
+        let segment = elf::load_segments(core)
+            .into_iter()
+            .find(|segment| segment.vaddr == executable.range.start && segment.filesz > 0)?;
+        read_at(segment.offset, segment.filesz.min(IMAGE_HEADER_LIMIT))
+    });
+    let build_id = image.as_deref().and_then(elf::build_id);
+    // The symbol file has the link-time virtual addresses of the executable.
+    let image_segments = image.as_deref().map(elf::load_segments).unwrap_or_default();
+    let symbols = build_id.as_deref().and_then(load_symbols);
+
+    let frames = state
+        .into_iter()
+        .flat_map(|state| [Some(state.pc), state.lr])
+        .flatten()
+        .map(|address| {
+            let Some(mapping) = mappings.iter().find(|mapping| mapping.range.contains(&address))
+            else {
+                return Frame { address, module: None, module_offset: None, symbol: None };
+            };
+            let module_offset = address - mapping.range.start + mapping.file_offset;
+            let symbol = symbols
+                .as_ref()
+                .filter(|_| Some(&mapping.path) == executable.map(|executable| &executable.path))
+                .and_then(|symbols| {
+                    symbols.resolve(elf::file_offset_to_vaddr(&image_segments, module_offset)?)
+                })
+                .map(|(symbol, offset)| format!("{symbol}+{offset:#x}"));
+            Frame {
+                address,
+                module: Path::new(&mapping.path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned()),
+                module_offset: Some(module_offset),
+                symbol,
+            }
+        })
+        .collect();
+
+    Report {
+        process,
+        pid,
+        timestamp,
+        signal: state.map(|state| state.signal),
+        build_id,
+        reporter_version: env!("GIT_VERSION").to_owned(),
+        frames,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{elf::tests::*, *};
+
+    #[test]
+    fn test_analyze() {
+        let image =
+            elf(183, &build_id_note(&[0xab, 0xcd]), &[(0, 0, 0x800), (0x1000, 0x1_1000, 0x1000)]);
+        let notes = core_notes(11, &[(30, 0x7f00_0010), (32, 0x40_1010)], &[
+            (0x40_0000, 0x50_0000, 0, "/usr/bin/orb-core"),
+            (0x7f00_0000, 0x7f01_0000, 2, "/usr/lib/libc.so.6"),
+        ]);
+        let core = elf(183, &notes, &[(0x1000, 0x40_0000, image.len() as u64)]);
+        let report = analyze(
+            "core.proc-qr-code.1234.1700000000",
+            &core,
+            |offset, len| {
+                assert_eq!(offset, 0x1000);
+                Some(image[..usize::try_from(len).unwrap()].to_vec())
+            },
+            |build_id| {
+                assert_eq!(build_id, "abcd");
+                Some(symbols::Table::parse("0000000000011000 0000000000000100 T orb::main\n"))
+            },
+        );
+        assert_eq!(report.process, "proc-qr-code");
+        assert_eq!(report.pid, Some(1234));
+        assert_eq!(report.timestamp, Some(1_700_000_000));
+        assert_eq!(report.signal, Some(11));
+        assert_eq!(report.build_id.as_deref(), Some("abcd"));
+        assert_eq!(report.frames, vec![
+            Frame {
+                address: 0x40_1010,
+                module: Some("orb-core".to_owned()),
+                module_offset: Some(0x1010),
+                symbol: Some("orb::main+0x10".to_owned()),
+            },
+            Frame {
+                address: 0x7f00_0010,
+                module: Some("libc.so.6".to_owned()),
+                module_offset: Some(0x2010),
+                symbol: None,
+            },
+        ]);
+    }
+}
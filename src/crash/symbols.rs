@@ -0,0 +1,81 @@
+//! Symbol files shipped with the orb-core build.
+//!
+//! The release binaries are stripped, so a symbol file is generated at build
+//! time with `nm --defined-only --print-size --demangle` and installed as
+//! `<build_id>.sym` into [`CRASH_SYMBOLS_DIR`](crate::consts::CRASH_SYMBOLS_DIR).
+
+use std::path::Path;
+
+/// Function symbol table.
+#[derive(Debug, Default)]
+pub struct Table {
+    symbols: Vec<Symbol>,
+}
+
+#[derive(Debug)]
+struct Symbol {
+    start: u64,
+    size: u64,
+    name: String,
+}
+
+impl Table {
+    /// Parses `nm --print-size` output. Lines without a size and non-code
+    /// symbols are skipped.
+    #[must_use]
+    pub fn parse(input: &str) -> Self {
+        let mut symbols = input
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, ' ');
+                let start = u64::from_str_radix(fields.next()?, 16).ok()?;
+                let size = u64::from_str_radix(fields.next()?, 16).ok()?;
+                let kind = fields.next()?;
+                let name = fields.next()?;
+                matches!(kind, "T" | "t" | "W" | "w").then(|| Symbol {
+                    start,
+                    size,
+                    name: name.to_owned(),
+                })
+            })
+            .collect::<Vec<_>>();
+        symbols.sort_unstable_by_key(|symbol| symbol.start);
+        Self { symbols }
+    }
+
+    /// Loads the symbol file for `build_id` from `dir`.
+    pub fn load(dir: &Path, build_id: &str) -> std::io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(dir.join(format!("{build_id}.sym")))?))
+    }
+
+    /// Resolves a link-time virtual address to a symbol name and an offset
+    /// within the symbol.
+    #[must_use]
+    pub fn resolve(&self, address: u64) -> Option<(&str, u64)> {
+        let index =
+            self.symbols.partition_point(|symbol| symbol.start <= address).checked_sub(1)?;
+        let symbol = &self.symbols[index];
+        let offset = address - symbol.start;
+        (offset < symbol.size.max(1)).then_some((symbol.name.as_str(), offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let table = Table::parse(
+            "0000000000001000 0000000000000040 T orb::crash::collect\n0000000000001100 \
+             0000000000000020 t <orb::Foo as core::fmt::Debug>::fmt\n0000000000002000 D \
+             orb::consts::SOMETHING\n0000000000003000 0000000000000010 B orb::STATIC\ngarbage\n",
+        );
+        assert_eq!(table.resolve(0x1000), Some(("orb::crash::collect", 0)));
+        assert_eq!(table.resolve(0x1010), Some(("orb::crash::collect", 0x10)));
+        assert_eq!(table.resolve(0x1050), None);
+        assert_eq!(table.resolve(0x1104), Some(("<orb::Foo as core::fmt::Debug>::fmt", 4)));
+        assert_eq!(table.resolve(0x3004), None);
+        assert_eq!(table.resolve(0x10), None);
+    }
+}
@@ -1,7 +1,9 @@
 //! DBus interfaces that are used by orb core to notify other processes of events.
 
 #![allow(missing_docs)]
-use zbus::{dbus_interface, dbus_proxy, Result, SignalContext};
+use crate::{agents::data_uploader, identification, monitor};
+use tokio::sync::{mpsc, watch, Mutex};
+use zbus::{dbus_interface, dbus_proxy, fdo, Result, SignalContext};
 
 /// `Signup` is a DBus interface that emits signals related to signup events.
 ///
@@ -19,6 +21,214 @@ impl Signup {
     pub async fn signup_finished(ctx: &SignalContext<'_>, success: bool) -> Result<()>;
 }
 
+/// `Uploads` is a DBus interface for inspecting and modifying the data uploader
+/// queues.
+///
+/// The queue items are returned as a JSON array of
+/// [`data_uploader::QueueItem`].
+pub struct Uploads {
+    control: mpsc::Sender<data_uploader::Control>,
+}
+
+impl Uploads {
+    /// Creates a new interface forwarding the requests to the data uploader.
+    #[must_use]
+    pub fn new(control: mpsc::Sender<data_uploader::Control>) -> Self {
+        Self { control }
+    }
+}
+
+#[dbus_interface(name = "org.worldcoin.OrbCore1.Uploads")]
+impl Uploads {
+    /// Lists the queued items as JSON.
+    async fn list(&self) -> fdo::Result<String> {
+        let items = data_uploader::control(&self.control, data_uploader::Control::List)
+            .await
+            .map_err(|err| fdo::Error::Failed(err.to_string()))?;
+        serde_json::to_string(&items).map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+
+    /// Moves the item to the front of its queue.
+    async fn requeue(&self, tier: u8, id: u64) -> fdo::Result<()> {
+        data_uploader::control(&self.control, |tx| data_uploader::Control::Requeue { tier, id, tx })
+            .await
+            .and_then(|result| result)
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+
+    /// Removes the item from its queue.
+    #[dbus_interface(name = "Drop")]
+    async fn drop_item(&self, tier: u8, id: u64) -> fdo::Result<()> {
+        data_uploader::control(&self.control, |tx| data_uploader::Control::Drop { tier, id, tx })
+            .await
+            .and_then(|result| result)
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+}
+
+/// `Network` is a DBus interface exposing the network monitor state to the
+/// operator app.
+///
+/// The reachability matrix is returned as a JSON object of
+/// [`monitor::net::Reachability`], or `null` if it hasn't been probed yet.
+pub struct Network {
+    net_monitor: Mutex<Box<dyn monitor::net::Monitor>>,
+}
+
+impl Network {
+    /// Creates a new interface reading the reports from the network monitor.
+    #[must_use]
+    pub fn new(net_monitor: Box<dyn monitor::net::Monitor>) -> Self {
+        Self { net_monitor: Mutex::new(net_monitor) }
+    }
+}
+
+#[dbus_interface(name = "org.worldcoin.OrbCore1.Network")]
+impl Network {
+    /// Per-endpoint reachability matrix as JSON.
+    #[dbus_interface(property)]
+    async fn reachability(&self) -> fdo::Result<String> {
+        let mut net_monitor = self.net_monitor.lock().await;
+        let reachability = net_monitor
+            .last_report()
+            .map_err(|err| fdo::Error::Failed(err.to_string()))?
+            .and_then(|report| report.reachability);
+        serde_json::to_string(&reachability).map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+}
+
+/// `Identity` is a DBus interface exposing the orb name and the
+/// [alias](identification::alias) synced from the backend.
+pub struct Identity;
+
+#[dbus_interface(name = "org.worldcoin.OrbCore1.Identity")]
+impl Identity {
+    /// Orb name.
+    #[dbus_interface(property)]
+    fn orb_name(&self) -> String {
+        identification::ORB_NAME.clone()
+    }
+
+    /// Display name, or the orb name if the backend provides none.
+    #[dbus_interface(property)]
+    fn display_name(&self) -> String {
+        identification::alias::get().display_name().to_owned()
+    }
+
+    /// Venue tag, or an empty string if the backend provides none.
+    #[dbus_interface(property)]
+    fn venue_tag(&self) -> String {
+        identification::alias::get().venue_tag.unwrap_or_default()
+    }
+}
+
+/// `Maintenance` is a DBus interface for putting the orb into the maintenance
+/// mode before it is physically serviced.
+///
+/// Entering the maintenance mode aborts the biometric capture of the current
+/// signup, or lets the signup finish if the capture is already over. Then the
+/// hardware is parked and new signups are blocked until the mode is cleared.
+pub struct Maintenance {
+    mode: watch::Sender<bool>,
+}
+
+impl Maintenance {
+    /// Creates a new interface publishing the requested mode to the master
+    /// plan.
+    #[must_use]
+    pub fn new(mode: watch::Sender<bool>) -> Self {
+        Self { mode }
+    }
+}
+
+#[dbus_interface(name = "org.worldcoin.OrbCore1.Maintenance")]
+impl Maintenance {
+    /// Enters the maintenance mode if `enabled` is `true`, or leaves it
+    /// otherwise.
+    async fn set_maintenance_mode(
+        &self,
+        enabled: bool,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        if self.mode.send_replace(enabled) != enabled {
+            tracing::info!("Maintenance mode requested through DBus: {enabled}");
+            self.maintenance_mode_changed(&ctxt).await?;
+        }
+        Ok(())
+    }
+
+    /// Whether the maintenance mode is requested.
+    #[dbus_interface(property)]
+    fn maintenance_mode(&self) -> bool {
+        *self.mode.borrow()
+    }
+}
+
+/// State of the `orb-backend-connect` connectivity keeper.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConnectivityState {
+    /// Not checked yet.
+    #[default]
+    Unknown,
+    /// Connected to the backend.
+    Connected,
+    /// The connection is lost, waiting before the next reconnection attempt.
+    Lost,
+    /// Running the WiFi plan to restore the connection.
+    Reconnecting,
+}
+
+/// `Connectivity` is a DBus interface exposing the state of the
+/// `orb-backend-connect` connectivity keeper.
+///
+/// The owner updates the fields through the object server and emits the
+/// property change signals.
+#[derive(Default)]
+pub struct Connectivity {
+    /// Current state.
+    pub state: ConnectivityState,
+    /// Number of reconnection attempts since the daemon start.
+    pub reconnects: u32,
+    /// Delay before the next reconnection attempt in seconds.
+    pub backoff: u64,
+}
+
+#[dbus_interface(name = "org.worldcoin.OrbBackendConnect1.Connectivity")]
+impl Connectivity {
+    /// Current state: `Unknown`, `Connected`, `Lost`, or `Reconnecting`.
+    #[dbus_interface(property)]
+    fn state(&self) -> String {
+        format!("{:?}", self.state)
+    }
+
+    /// Number of reconnection attempts since the daemon start.
+    #[dbus_interface(property)]
+    fn reconnects(&self) -> u32 {
+        self.reconnects
+    }
+
+    /// Delay before the next reconnection attempt in seconds.
+    #[dbus_interface(property)]
+    fn backoff(&self) -> u64 {
+        self.backoff
+    }
+}
+
+/// Client side of the [`Uploads`] interface.
+#[dbus_proxy(
+    default_service = "org.worldcoin.OrbCore1",
+    default_path = "/org/worldcoin/OrbCore1/Uploads",
+    interface = "org.worldcoin.OrbCore1.Uploads"
+)]
+pub trait UploadQueues {
+    fn list(&self) -> zbus::Result<String>;
+
+    fn requeue(&self, tier: u8, id: u64) -> zbus::Result<()>;
+
+    #[dbus_proxy(name = "Drop")]
+    fn drop_item(&self, tier: u8, id: u64) -> zbus::Result<()>;
+}
+
 /// AuthToken is a DBus interface that exposes currently valid backend token via
 /// 'token' property.
 ///
@@ -35,13 +245,38 @@ trait AuthToken {
 
 #[cfg(test)]
 mod tests {
-    use super::Signup;
+    use super::{Connectivity, Identity, Maintenance, Network, Signup, Uploads};
     use zbus::Interface as _;
 
     #[test]
     fn signup_interface_name_matches_const() {
         assert_eq!(crate::consts::DBUS_SIGNUP_INTERFACE_NAME, &*Signup::name());
     }
+
+    #[test]
+    fn uploads_interface_name_matches_const() {
+        assert_eq!(crate::consts::DBUS_UPLOADS_INTERFACE_NAME, &*Uploads::name());
+    }
+
+    #[test]
+    fn network_interface_name_matches_const() {
+        assert_eq!(crate::consts::DBUS_NETWORK_INTERFACE_NAME, &*Network::name());
+    }
+
+    #[test]
+    fn identity_interface_name_matches_const() {
+        assert_eq!(crate::consts::DBUS_IDENTITY_INTERFACE_NAME, &*Identity::name());
+    }
+
+    #[test]
+    fn maintenance_interface_name_matches_const() {
+        assert_eq!(crate::consts::DBUS_MAINTENANCE_INTERFACE_NAME, &*Maintenance::name());
+    }
+
+    #[test]
+    fn connectivity_interface_name_matches_const() {
+        assert_eq!(crate::consts::DBUS_CONNECTIVITY_INTERFACE_NAME, &*Connectivity::name());
+    }
 }
 
 #[dbus_proxy(
@@ -3,9 +3,12 @@
 #![allow(missing_docs)]
 #![allow(clippy::default_trait_access)]
 
+pub mod artifacts;
+mod triage;
+
 use crate::{
     agents::{
-        camera, image_notary,
+        camera, eye_pid_controller, image_notary,
         python::{
             face_identifier::{
                 self,
@@ -22,7 +25,8 @@ use crate::{
         endpoints::BACKEND,
         operator_status::{self, Coordinates},
     },
-    config::Config,
+    brokers,
+    config::{audit, Config},
     consts::{
         AUTOFOCUS_MAX, AUTOFOCUS_MIN, BIOMETRIC_CAPTURE_TIMEOUT, BUTTON_LONG_PRESS_DURATION,
         CONFIG_UPDATE_INTERVAL, CONTINUOUS_CALIBRATION_REDUCER, DEFAULT_IR_LED_DURATION,
@@ -37,10 +41,11 @@ use crate::{
     },
     identification::{GIT_VERSION, ORB_ID, ORB_OS_VERSION},
     mcu::main::IrLed,
+    monitor,
     plans::{
         self,
         biometric_capture::{self, CaptureFailureFeedbackMessage, ExtensionReport},
-        enroll_user,
+        biometric_pipeline, enroll_user,
         fraud_check::{self, PipelineFailureFeedbackMessage},
         qr_scan::{self, user::SignupExtensionConfig},
     },
@@ -58,6 +63,7 @@ use orb_relay_messages::self_serve;
 use orb_wld_data_id::{ImageId, SignupId};
 use schemars::JsonSchema;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 #[cfg(not(test))]
 use std::time::Instant;
 use std::{
@@ -139,11 +145,17 @@ pub struct DebugReport {
     version: String,
     metadata: Metadata,
     pipeline_errors: PipelineErrors,
+    triage: triage::Triage,
     sensor: SensorData,
     hardware_component_config: HardwareComponentConfig,
     tof2d: Vec<Tof2dConfig>,
     internal_state_data: InternalStateData,
     self_custody_bundle: Option<Bundle>,
+    broker_events: Vec<brokers::event_log::Event>,
+    broker_message_trace: Option<brokers::message_trace::Summary>,
+    config_changes: Vec<audit::Entry>,
+    frame_drops: camera::drops::FrameDrops,
+    deep_debug_artifacts: Vec<artifacts::Entry>,
     // Don't move these fields inside the Metadata or nest them, as the AI Team is specially handling long
     // time-series. @tbszlg will be mad at you!
     rgb_camera: Vec<RgbCameraMetadata>,
@@ -273,7 +285,7 @@ pub struct Builder {
     pipeline_errors: PipelineErrors,
     mega_agent_one_config: Option<mega_agent_one::MegaAgentOne>,
     mega_agent_two_config: Option<mega_agent_two::MegaAgentTwo>,
-    biometric_capture_gps_location: Option<(f64, f64)>,
+    biometric_capture_gps_location: Option<monitor::gps::Location>,
     hardware_component_config: HardwareComponentConfig,
     internal_state_data: InternalStateData,
     rgb_camera: Vec<RgbCameraMetadata>,
@@ -282,6 +294,11 @@ pub struct Builder {
     thermal_camera: Vec<ThermalCameraMetadata>,
     self_custody_camera: Vec<SelfCustodyRgbCameraMetadata>,
     self_custody_bundle: Option<Bundle>,
+    broker_events: Vec<brokers::event_log::Event>,
+    broker_message_trace: Option<brokers::message_trace::Summary>,
+    config_changes: Vec<audit::Entry>,
+    frame_drops: camera::drops::FrameDrops,
+    deep_debug: Option<artifacts::Collector>,
     pub self_custody_thumbnail: Option<camera::rgb::Frame>,
     pub left_iris_normalized_image: Option<NormalizedIris>,
     pub right_iris_normalized_image: Option<NormalizedIris>,
@@ -291,6 +308,7 @@ pub struct Builder {
     pub location_data: LocationData,
     pub failure_feedback_capture: Vec<CaptureFailureFeedbackMessage>,
     pub failure_feedback_after_capture: Vec<AfterCaptureFeedbackMessage>,
+    time_sync: Option<monitor::clock::TimeSync>,
 }
 
 impl Builder {
@@ -328,6 +346,11 @@ impl Builder {
             thermal_camera,
             self_custody_camera,
             self_custody_bundle,
+            broker_events,
+            broker_message_trace,
+            config_changes,
+            frame_drops,
+            deep_debug,
             self_custody_thumbnail: _,
             left_iris_normalized_image: _,
             right_iris_normalized_image: _,
@@ -337,6 +360,7 @@ impl Builder {
             location_data,
             failure_feedback_capture,
             failure_feedback_after_capture,
+            time_sync,
         } = self;
         let (is_self_serve, self_serve_biometric_capture_timeout) =
             (backend_config.self_serve, backend_config.self_serve_biometric_capture_timeout);
@@ -391,7 +415,8 @@ impl Builder {
                 extension_report,
                 is_signup_extension: signup_extension_config.is_some(),
             },
-            backend_config,
+            backend_config_sha256: ConfigSnapshot::new(&backend_config).sha256,
+            backend_config: Some(backend_config),
             location: OrbLocation {
                 ip_geolocalisation: ip_geo_info("ip-geolocalisation-cache")
                     .unwrap_or("unknown".to_owned()),
@@ -456,23 +481,37 @@ impl Builder {
             mega_agent_two_config,
             failure_feedback_capture,
             failure_feedback_after_capture,
+            time_sync,
         };
         let sensor = SensorData {
             orbsensor: OrbSensorData {
-                gps_location: biometric_capture_gps_location.unwrap_or((0.0, 0.0)),
+                gps_location: biometric_capture_gps_location
+                    .as_ref()
+                    .map_or((0.0, 0.0), |location| (location.latitude, location.longitude)),
+                gps: biometric_capture_gps_location,
             },
         };
         let tof2d = Default::default();
+        let triage = triage::Triage::new(&metadata, &pipeline_errors, &ir_camera);
         DebugReport {
             signup_id,
             version: DEBUG_REPORT_VERSION.to_string(),
             metadata,
             pipeline_errors,
+            triage,
             sensor,
             hardware_component_config,
             tof2d,
             internal_state_data,
             self_custody_bundle,
+            broker_events,
+            broker_message_trace,
+            config_changes,
+            frame_drops,
+            deep_debug_artifacts: deep_debug
+                .as_ref()
+                .map(|deep_debug| deep_debug.entries().to_vec())
+                .unwrap_or_default(),
             rgb_camera,
             ir_camera,
             ir_face_camera,
@@ -631,8 +670,11 @@ impl Builder {
         self
     }
 
-    pub fn biometric_capture_gps_location(&mut self, latitude: f64, longitude: f64) -> &mut Self {
-        self.biometric_capture_gps_location = Some((latitude, longitude));
+    pub fn biometric_capture_gps_location(
+        &mut self,
+        location: monitor::gps::Location,
+    ) -> &mut Self {
+        self.biometric_capture_gps_location = Some(location);
         self
     }
 
@@ -663,6 +705,13 @@ impl Builder {
         };
         self.internal_state_data.user_distance =
             history.user_distance.user_distance.iter().copied().collect();
+        self.internal_state_data.mirror_eye_tracking_pid.gain_schedule =
+            history.eye_pid_controller.gains.iter().copied().collect();
+        self.internal_state_data.imu = ImuHistory {
+            accel: history.imu.accel.iter().copied().collect(),
+            gyro: history.imu.gyro.iter().copied().collect(),
+            tilt_degrees: history.imu.tilt_degrees.iter().copied().collect(),
+        };
 
         self
     }
@@ -675,6 +724,34 @@ impl Builder {
         self
     }
 
+    pub fn broker_events(&mut self, events: Vec<brokers::event_log::Event>) -> &mut Self {
+        self.broker_events = events;
+        self
+    }
+
+    pub fn broker_message_trace(
+        &mut self,
+        summary: Option<brokers::message_trace::Summary>,
+    ) -> &mut Self {
+        self.broker_message_trace = summary;
+        self
+    }
+
+    pub fn config_changes(&mut self, entries: Vec<audit::Entry>) -> &mut Self {
+        self.config_changes = entries;
+        self
+    }
+
+    pub fn frame_drops(&mut self, frame_drops: camera::drops::FrameDrops) -> &mut Self {
+        self.frame_drops = frame_drops;
+        self
+    }
+
+    pub fn time_sync(&mut self, time_sync: monitor::clock::TimeSync) -> &mut Self {
+        self.time_sync = Some(time_sync);
+        self
+    }
+
     pub fn image_notary_history(&mut self, mut image_notary: image_notary::Log) -> &mut Self {
         self.rgb_camera = (&mut image_notary.rgb_net_metadata).into();
         self.ir_camera = (&mut image_notary.ir_net_metadata).into();
@@ -710,6 +787,48 @@ impl Builder {
         self
     }
 
+    /// Captures the deep debug artifacts of the biometric capture, if the
+    /// signup is sampled.
+    pub fn deep_debug_capture(&mut self, capture: &biometric_capture::Capture) -> &mut Self {
+        if let Some(deep_debug) = &mut self.deep_debug {
+            for (eye, data) in [("left", &capture.eye_left), ("right", &capture.eye_right)] {
+                deep_debug.ir_net_landmarks(
+                    &self.signup_id,
+                    eye,
+                    &data.ir_frame,
+                    &data.ir_net_estimate,
+                );
+                deep_debug.iris_crop(&self.signup_id, eye, &data.ir_frame, &data.ir_net_estimate);
+            }
+        }
+        self
+    }
+
+    /// Captures the deep debug artifacts of the biometric pipeline, if the
+    /// signup is sampled.
+    pub fn deep_debug_pipeline(&mut self, pipeline: &biometric_pipeline::Pipeline) -> &mut Self {
+        if let Some(deep_debug) = &mut self.deep_debug {
+            for (eye, data) in [("left", &pipeline.v2.eye_left), ("right", &pipeline.v2.eye_right)]
+            {
+                let signup_id = &self.signup_id;
+                deep_debug.normalized_iris(signup_id, eye, "", data.iris_normalized_image.as_ref());
+                deep_debug.normalized_iris(
+                    signup_id,
+                    eye,
+                    "_resized",
+                    data.iris_normalized_image_resized.as_ref(),
+                );
+            }
+        }
+        self
+    }
+
+    /// Takes the captured deep debug artifacts for the upload. Their
+    /// descriptions stay in the report.
+    pub fn take_deep_debug_artifacts(&mut self) -> Vec<artifacts::Artifact> {
+        self.deep_debug.as_mut().map(artifacts::Collector::take_artifacts).unwrap_or_default()
+    }
+
     pub fn iris_normalized_images(
         &mut self,
         left: Option<NormalizedIris>,
@@ -839,6 +958,11 @@ impl DebugReport {
             thermal_camera: Vec::new(),
             self_custody_camera: Vec::new(),
             self_custody_bundle: None,
+            broker_events: Vec::new(),
+            broker_message_trace: None,
+            config_changes: Vec::new(),
+            frame_drops: camera::drops::FrameDrops::default(),
+            deep_debug: artifacts::Collector::sample(&backend_config.deep_debug, user_data),
             self_custody_thumbnail: None,
             left_iris_normalized_image: None,
             right_iris_normalized_image: None,
@@ -852,8 +976,51 @@ impl DebugReport {
             ),
             failure_feedback_after_capture: Vec::new(),
             failure_feedback_capture: Vec::new(),
+            time_sync: None,
         }
     }
+
+    /// Returns the snapshot of the inlined backend config, if any.
+    #[must_use]
+    pub fn backend_config_snapshot(&self) -> Option<ConfigSnapshot> {
+        self.metadata.backend_config.as_ref().map(ConfigSnapshot::new)
+    }
+
+    /// Drops the inlined backend config, leaving only its hash. Should be
+    /// called once the config snapshot is known to the backend.
+    pub fn reference_backend_config(&mut self) {
+        self.metadata.backend_config = None;
+    }
+}
+
+/// Content-addressed backend config snapshot.
+///
+/// Consecutive debug reports mostly share the same multi-kilobyte backend
+/// config. The config is uploaded once as a separate blob, and the reports
+/// reference it by the `backend_config_sha256` field.
+#[derive(Debug)]
+pub struct ConfigSnapshot {
+    /// Hex-encoded SHA-256 of `json`.
+    pub sha256: String,
+    /// Canonical JSON of the config.
+    pub json: Vec<u8>,
+}
+
+impl ConfigSnapshot {
+    /// Creates a new snapshot of `config`.
+    ///
+    /// # Panics
+    ///
+    /// If the config fails to serialize, which can't happen for its types.
+    #[must_use]
+    pub fn new(config: &Config) -> Self {
+        // `serde_json::Value` keeps the object keys sorted, so the hash doesn't
+        // depend on the iteration order of the config maps.
+        let value = serde_json::to_value(config).expect("config to be serializable");
+        let json = serde_json::to_vec(&value).expect("JSON value to be serializable");
+        let sha256 = hex::encode(Sha256::digest(&json));
+        Self { sha256, json }
+    }
 }
 
 // TODO: Consider implementing the Serialize trait for TimeSeries<T> instead
@@ -1053,7 +1220,11 @@ pub struct Metadata {
     software_version: SoftwareVersion,
     orb: OrbMetadata,
     experiment_configs: ExperimentConfigs,
-    backend_config: Config,
+    /// Omitted when the config snapshot has already been uploaded separately,
+    /// see [`ConfigSnapshot`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backend_config: Option<Config>,
+    backend_config_sha256: String,
     identification_images: Option<IdentificationImages>,
     rgb_net_left: Option<rgb_net::EstimateOutput>,
     rgb_net_right: Option<rgb_net::EstimateOutput>,
@@ -1066,6 +1237,9 @@ pub struct Metadata {
     mega_agent_two_config: Option<mega_agent_two::MegaAgentTwo>,
     failure_feedback_capture: Vec<CaptureFailureFeedbackMessage>,
     failure_feedback_after_capture: Vec<AfterCaptureFeedbackMessage>,
+    /// Clock skew and chrony time synchronization quality at the end of the
+    /// signup.
+    time_sync: Option<monitor::clock::TimeSync>,
 }
 
 #[derive(Clone, Serialize, JsonSchema, Default)]
@@ -1194,6 +1368,7 @@ struct SensorData {
 #[derive(Serialize, JsonSchema, Default, Debug)]
 struct OrbSensorData {
     gps_location: (f64, f64),
+    gps: Option<monitor::gps::Location>,
 }
 
 ////////////////////////////// HardwareComponentConfig //////////////////////////////
@@ -1361,6 +1536,7 @@ pub struct IrNetMetadata {
     #[schemars(with = "Option<Vec<Vec<f32>>>")]
     landmarks: Option<Array2<f32>>,
     fractional_sharpness_score: f64,
+    fractional_sharpness_score_raw: f64,
     occlusion_30: f64,
     occlusion_90: f64,
     pupil_to_iris_ratio: f64,
@@ -1377,6 +1553,7 @@ pub struct IrNetMetadata {
     valid_for_identification: bool,
     status: i64,
     selection_score: f64,
+    selection_score_raw: f64,
     msg: String,
     mean_brightness_raw: f64,
     target_side: u8,
@@ -1388,6 +1565,7 @@ impl From<ir_net::EstimateOutput> for IrNetMetadata {
         let ir_net::EstimateOutput {
             landmarks,
             sharpness,
+            sharpness_raw,
             occlusion_30,
             occlusion_90,
             pupil_to_iris_ratio,
@@ -1405,6 +1583,7 @@ impl From<ir_net::EstimateOutput> for IrNetMetadata {
             status,
             message,
             score,
+            score_raw,
             mean_brightness_raw,
             target_side,
             perceived_side,
@@ -1412,6 +1591,7 @@ impl From<ir_net::EstimateOutput> for IrNetMetadata {
         Self {
             landmarks: landmarks.map(RkyvNdarray::<_, Ix2>::into_ndarray),
             fractional_sharpness_score: sharpness,
+            fractional_sharpness_score_raw: sharpness_raw,
             occlusion_30,
             occlusion_90,
             pupil_to_iris_ratio,
@@ -1428,6 +1608,7 @@ impl From<ir_net::EstimateOutput> for IrNetMetadata {
             valid_for_identification,
             status,
             selection_score: score,
+            selection_score_raw: score_raw,
             msg: message,
             mean_brightness_raw,
             target_side,
@@ -1470,6 +1651,17 @@ struct InternalStateData {
     autofocus: InternalStateAutofocus,
     mirror_eye_tracking_pid: MirrorEyeTrackingPid,
     user_distance: Vec<Timestamped<f64>>,
+    imu: ImuHistory,
+}
+
+#[derive(Clone, Serialize, JsonSchema, Default, Debug)]
+struct ImuHistory {
+    /// Raw accelerometer samples along the x, y, and z axes.
+    accel: Vec<Timestamped<[i32; 3]>>,
+    /// Raw gyroscope samples around the x, y, and z axes.
+    gyro: Vec<Timestamped<[i32; 3]>>,
+    /// Orb tilt in degrees.
+    tilt_degrees: Vec<Timestamped<f64>>,
 }
 
 #[derive(Clone, Serialize, JsonSchema, Default, Debug)]
@@ -1497,6 +1689,7 @@ struct ValueOffsetTimestamp {
 struct MirrorEyeTrackingPid {
     left: Vec<MirrorOffsetSetting>,
     right: Vec<MirrorOffsetSetting>,
+    gain_schedule: Vec<Timestamped<eye_pid_controller::Gains>>,
 }
 
 #[derive(Clone, Serialize, JsonSchema, Default, Debug)]
@@ -1505,3 +1698,20 @@ struct MirrorOffsetSetting {
     y: f64,
     timestamp: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_snapshot() {
+        let config = Config::default();
+        let snapshot = ConfigSnapshot::new(&config);
+        assert_eq!(snapshot.sha256.len(), 64);
+        assert_eq!(ConfigSnapshot::new(&config.clone()).sha256, snapshot.sha256);
+        let value: serde_json::Value = serde_json::from_slice(&snapshot.json).unwrap();
+        assert_eq!(value["SelfServe"], serde_json::Value::Bool(config.self_serve));
+        let other = Config { self_serve: !config.self_serve, ..config };
+        assert_ne!(ConfigSnapshot::new(&other).sha256, snapshot.sha256);
+    }
+}
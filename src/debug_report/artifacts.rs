@@ -0,0 +1,356 @@
+//! Pipeline intermediate artifacts ("deep debug").
+//!
+//! For a small sampled fraction of the signups, selected intermediate outputs
+//! of the biometric pipeline are rendered to PNG images and uploaded next to
+//! the debug report. It lets the ML teams debug the model regressions on real
+//! field data. The capture is disabled unless the backend config sets a
+//! non-zero sample rate.
+//!
+//! The artifacts contain the user biometrics, so they are captured only on the
+//! internal-data-acquisition builds and only for the users who explicitly
+//! opted in to the full data collection. The uploaded images are sealed with
+//! the Worldcoin encryption key, the same as the images saved by the image
+//! notary.
+
+use crate::{
+    agents::{
+        camera::{self, Frame as _},
+        python::{ir_net, iris::NormalizedIris},
+    },
+    backend::user_status::UserData,
+    consts::DEEP_DEBUG_MAX_SAMPLE_RATE,
+    dd_incr,
+    image::iris,
+};
+use eyre::Result;
+use ndarray::ArrayView2;
+#[cfg(feature = "internal-data-acquisition")]
+use orb_qr_link::DataPolicy;
+use orb_wld_data_id::{ImageId, SignupId};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Side length of the landmark markers in pixels.
+const LANDMARK_MARKER_SIZE: i64 = 5;
+
+/// Intermediate artifact kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// IR eye frame with the IR-Net landmarks drawn over it.
+    IrNetLandmarks,
+    /// Normalized iris images and masks, original and resized.
+    NormalizedIris,
+    /// IR eye frame cropped to the iris region around the IR-Net landmarks.
+    IrisCrop,
+}
+
+/// Deep debug settings.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Settings {
+    /// Fraction of the signups to capture the artifacts for. Zero disables
+    /// the capture.
+    pub sample_rate: f64,
+    /// Artifact kinds to capture.
+    pub kinds: Vec<Kind>,
+}
+
+/// Artifacts captured for a sampled signup.
+#[derive(Clone, Debug)]
+pub struct Collector {
+    kinds: Vec<Kind>,
+    entries: Vec<Entry>,
+    artifacts: Vec<Artifact>,
+}
+
+/// Description of an artifact, included in the debug report.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct Entry {
+    /// Artifact name, e.g. `left_normalized_iris_mask`.
+    pub name: String,
+    /// Artifact kind.
+    pub kind: Kind,
+    /// Image ID the artifact is uploaded under.
+    pub image_id: String,
+    /// Encoded PNG size in bytes.
+    pub size: usize,
+}
+
+/// Encoded artifact pending upload.
+#[derive(Clone, Debug)]
+pub struct Artifact {
+    /// Artifact name.
+    pub name: String,
+    /// Image ID to upload the artifact under.
+    pub image_id: ImageId,
+    /// PNG image data sealed with the Worldcoin encryption key.
+    pub data: Arc<Vec<u8>>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.0,
+            kinds: vec![Kind::IrNetLandmarks, Kind::NormalizedIris, Kind::IrisCrop],
+        }
+    }
+}
+
+impl Settings {
+    /// Returns the settings with the sample rate limited to
+    /// [`DEEP_DEBUG_MAX_SAMPLE_RATE`].
+    #[must_use]
+    pub fn clamped(mut self) -> Self {
+        self.sample_rate = self.sample_rate.clamp(0.0, DEEP_DEBUG_MAX_SAMPLE_RATE);
+        self
+    }
+}
+
+impl Collector {
+    /// Decides whether the signup is sampled for the deep debug. Returns a
+    /// collector if it is. Signups without the user consent are never
+    /// sampled.
+    #[must_use]
+    pub fn sample(settings: &Settings, user_data: &UserData) -> Option<Self> {
+        if settings.sample_rate <= 0.0 || settings.kinds.is_empty() || !has_consent(user_data) {
+            return None;
+        }
+        let sampled = rand::random::<f64>() < settings.sample_rate;
+        dd_incr!("main.count.data_acquisition.deep_debug", &format!("sampled:{sampled}"));
+        sampled.then(|| Self {
+            kinds: settings.kinds.clone(),
+            entries: Vec::new(),
+            artifacts: Vec::new(),
+        })
+    }
+
+    /// Captures the IR-Net landmark overlay of an eye.
+    pub fn ir_net_landmarks(
+        &mut self,
+        signup_id: &SignupId,
+        eye: &str,
+        frame: &camera::ir::Frame,
+        estimate: &ir_net::EstimateOutput,
+    ) {
+        if !self.kinds.contains(&Kind::IrNetLandmarks) {
+            return;
+        }
+        let Some(landmarks) = &estimate.landmarks else {
+            return;
+        };
+        let png = landmark_overlay(frame, landmarks.as_ndarray().view());
+        self.push(signup_id, format!("{eye}_ir_net_landmarks"), Kind::IrNetLandmarks, png);
+    }
+
+    /// Captures the iris crop of an eye, as the canonical
+    /// [`iris::crop_and_normalize`] produces it from the IR-Net landmarks.
+    pub fn iris_crop(
+        &mut self,
+        signup_id: &SignupId,
+        eye: &str,
+        frame: &camera::ir::Frame,
+        estimate: &ir_net::EstimateOutput,
+    ) {
+        if !self.kinds.contains(&Kind::IrisCrop) {
+            return;
+        }
+        let Some(landmarks) = &estimate.landmarks else {
+            return;
+        };
+        let Some(crop) = iris_crop(frame, landmarks.as_ndarray().view()) else {
+            return;
+        };
+        let png = ArrayView2::from_shape((crop.height as usize, crop.width as usize), &crop.data)
+            .map_err(Into::into)
+            .and_then(|image| encode_png(image, png::ColorType::Grayscale));
+        self.push(signup_id, format!("{eye}_iris_crop"), Kind::IrisCrop, png);
+    }
+
+    /// Captures the normalized iris image and mask of an eye. `suffix`
+    /// distinguishes the resized variant.
+    pub fn normalized_iris(
+        &mut self,
+        signup_id: &SignupId,
+        eye: &str,
+        suffix: &str,
+        iris: Option<&NormalizedIris>,
+    ) {
+        if !self.kinds.contains(&Kind::NormalizedIris) {
+            return;
+        }
+        let Some(iris) = iris else {
+            return;
+        };
+        let image = iris.normalized_image.as_ndarray();
+        let mask = iris.normalized_mask.as_ndarray().map(|&valid| if valid { u8::MAX } else { 0 });
+        let image_png = encode_png(image.view(), png::ColorType::Grayscale);
+        let mask_png = encode_png(mask.view(), png::ColorType::Grayscale);
+        self.push(
+            signup_id,
+            format!("{eye}_normalized_iris{suffix}"),
+            Kind::NormalizedIris,
+            image_png,
+        );
+        self.push(
+            signup_id,
+            format!("{eye}_normalized_iris_mask{suffix}"),
+            Kind::NormalizedIris,
+            mask_png,
+        );
+    }
+
+    /// Returns the descriptions of the captured artifacts.
+    #[must_use]
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Takes the artifacts pending upload, leaving their descriptions.
+    pub fn take_artifacts(&mut self) -> Vec<Artifact> {
+        std::mem::take(&mut self.artifacts)
+    }
+
+    fn push(&mut self, signup_id: &SignupId, name: String, kind: Kind, png: Result<Vec<u8>>) {
+        let png = match png {
+            Ok(png) => png,
+            Err(err) => {
+                tracing::warn!("Couldn't encode deep debug artifact {name}: {err:?}");
+                return;
+            }
+        };
+        let image_id = ImageId::new(signup_id, crc32fast::hash(&png));
+        self.entries.push(Entry {
+            name: name.clone(),
+            kind,
+            image_id: image_id.to_string(),
+            size: png.len(),
+        });
+        #[cfg(not(feature = "no-image-encryption"))]
+        let data =
+            sodiumoxide::crypto::sealedbox::seal(&png, &crate::consts::WORLDCOIN_ENCRYPTION_PUBKEY);
+        #[cfg(feature = "no-image-encryption")]
+        let data = png;
+        self.artifacts.push(Artifact { name, image_id, data: Arc::new(data) });
+    }
+}
+
+/// Returns `true` if the user explicitly opted in to the full data collection.
+/// Always `false` outside of the internal-data-acquisition builds.
+#[cfg(feature = "internal-data-acquisition")]
+fn has_consent(user_data: &UserData) -> bool {
+    matches!(user_data.data_policy, DataPolicy::FullDataOptIn)
+}
+
+#[cfg(not(feature = "internal-data-acquisition"))]
+fn has_consent(_user_data: &UserData) -> bool {
+    false
+}
+
+/// Renders the IR frame as RGB with a red marker at each landmark. The
+/// landmarks are in the frame coordinates normalized to `[0, 1]`.
+fn landmark_overlay(frame: &camera::ir::Frame, landmarks: ArrayView2<f32>) -> Result<Vec<u8>> {
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let mut rgb = ndarray::Array3::<u8>::zeros((height, width, 3));
+    for (i, &px) in frame.as_bytes().iter().take(width * height).enumerate() {
+        rgb.slice_mut(ndarray::s![i / width, i % width, ..]).fill(px);
+    }
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_precision_loss
+    )]
+    for point in landmarks.rows() {
+        let (Some(&x), Some(&y)) = (point.get(0), point.get(1)) else {
+            continue;
+        };
+        let (cx, cy) = ((x * width as f32) as i64, (y * height as f32) as i64);
+        for dy in -LANDMARK_MARKER_SIZE / 2..=LANDMARK_MARKER_SIZE / 2 {
+            for dx in -LANDMARK_MARKER_SIZE / 2..=LANDMARK_MARKER_SIZE / 2 {
+                let (px, py) = (cx + dx, cy + dy);
+                if (0..width as i64).contains(&px) && (0..height as i64).contains(&py) {
+                    #[allow(clippy::cast_sign_loss)]
+                    rgb.slice_mut(ndarray::s![py as usize, px as usize, ..])
+                        .assign(&ndarray::arr1(&[u8::MAX, 0, 0]));
+                }
+            }
+        }
+    }
+    let rgb = rgb.into_shape((height, width * 3))?;
+    encode_png(rgb.view(), png::ColorType::RGB)
+}
+
+/// Crops the iris region of the IR frame around the landmarks. The landmarks
+/// are in the frame coordinates normalized to `[0, 1]`.
+fn iris_crop(frame: &camera::ir::Frame, landmarks: ArrayView2<f32>) -> Option<iris::GrayImage> {
+    let (width, height) = (f64::from(frame.width()), f64::from(frame.height()));
+    let landmarks = landmarks
+        .rows()
+        .into_iter()
+        .filter_map(|point| Some((f64::from(*point.get(0)?), f64::from(*point.get(1)?))))
+        .map(|(x, y)| (x * width, y * height))
+        .collect::<Vec<_>>();
+    iris::crop_and_normalize(&frame.into(), &landmarks, &iris::Params::default())
+}
+
+/// Encodes an 8-bit image of `height` rows as PNG.
+fn encode_png(image: ArrayView2<u8>, color: png::ColorType) -> Result<Vec<u8>> {
+    let channels = if color == png::ColorType::RGB { 3 } else { 1 };
+    let (height, row) = image.dim();
+    let mut png = Vec::new();
+    {
+        #[allow(clippy::cast_possible_truncation)]
+        let mut encoder = png::Encoder::new(&mut png, (row / channels) as u32, height as u32);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(png::Compression::Fast);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(image.as_standard_layout().as_slice().unwrap_or_default())?;
+    }
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::arr2;
+    use std::time::Duration;
+
+    #[test]
+    fn test_sample() {
+        let mut user_data = UserData::default();
+        #[cfg(feature = "internal-data-acquisition")]
+        {
+            user_data.data_policy = DataPolicy::FullDataOptIn;
+        }
+        assert!(Collector::sample(&Settings::default(), &user_data).is_none());
+        let settings = Settings { sample_rate: 1.0, ..Settings::default() };
+        assert_eq!(
+            Collector::sample(&settings, &user_data).is_some(),
+            cfg!(feature = "internal-data-acquisition")
+        );
+        assert!(Collector::sample(&settings, &UserData::default()).is_none());
+        assert!((settings.clamped().sample_rate - DEEP_DEBUG_MAX_SAMPLE_RATE).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_landmark_overlay() {
+        let frame = camera::ir::Frame::new(vec![7; 16 * 8], Duration::ZERO, 16, 8, 7);
+        let png = landmark_overlay(&frame, arr2(&[[0.5, 0.5], [2.0, 2.0]]).view()).unwrap();
+        let (info, mut reader) = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        assert_eq!((info.width, info.height, info.color_type), (16, 8, png::ColorType::RGB));
+        let mut buf = vec![0; info.buffer_size()];
+        reader.next_frame(&mut buf).unwrap();
+        assert_eq!(&buf[(4 * 16 + 8) * 3..][..3], &[u8::MAX, 0, 0]);
+        assert_eq!(&buf[..3], &[7, 7, 7]);
+    }
+
+    #[test]
+    fn test_iris_crop() {
+        let frame = camera::ir::Frame::new(vec![7; 16 * 8], Duration::ZERO, 16, 8, 7);
+        let crop = iris_crop(&frame, arr2(&[[0.25, 0.25], [0.75, 0.75]]).view()).unwrap();
+        let size = iris::Params::default().output_size;
+        assert_eq!((crop.width, crop.height), (size, size));
+        assert!(iris_crop(&frame, ndarray::Array2::zeros((0, 2)).view()).is_none());
+    }
+}
@@ -0,0 +1,298 @@
+//! Rule-based failure triage.
+//!
+//! When a debug report is built, the signup statuses, the feedback messages,
+//! the pipeline errors, and the IR-Net history are inspected to attach a
+//! [`ProbableCause`] of a failed signup together with the findings which
+//! support it. This lets the fleet dashboards group failures without a manual
+//! deep dive into every report.
+
+use super::{
+    AfterCaptureFeedbackMessage, IrCameraMetadata, Metadata, PipelineErrors, SignupStatus,
+};
+use crate::plans::{
+    biometric_capture::CaptureFailureFeedbackMessage, enroll_user,
+    fraud_check::PipelineFailureFeedbackMessage,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// IR-Net eye-detection score above which an eye is considered visible.
+const EYE_DETECTED_MIN: f64 = 0.5;
+
+/// Probable cause of a failed signup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbableCause {
+    /// Connectivity to the backend or the orb relay.
+    Network,
+    /// Image quality despite a well positioned user.
+    Optics,
+    /// User distance, pose, or occlusions.
+    UserPositioning,
+    /// Rejection or failure on the backend side.
+    Backend,
+    /// Orb software or pipeline failure.
+    Firmware,
+}
+
+/// Single finding supporting a probable cause.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct Evidence {
+    cause: ProbableCause,
+    finding: String,
+}
+
+/// Result of the failure triage.
+#[derive(Clone, Debug, Default, Serialize, JsonSchema)]
+pub struct Triage {
+    /// Cause of the first finding, or `None` if the signup didn't fail or
+    /// no rule matched.
+    probable_cause: Option<ProbableCause>,
+    /// All findings in the order of their priority.
+    evidence: Vec<Evidence>,
+}
+
+/// Signals extracted from a debug report.
+struct Signals<'a> {
+    signup_status: &'a SignupStatus,
+    enrollment_status: Option<&'a enroll_user::Status>,
+    biometric_capture_succeeded: bool,
+    duration: f64,
+    failure_feedback_capture: &'a [CaptureFailureFeedbackMessage],
+    failure_feedback_after_capture: &'a [AfterCaptureFeedbackMessage],
+    pipeline_errors: Vec<&'static str>,
+    ir_frames: usize,
+    ir_eye_frames: usize,
+    ir_sharp_frames: usize,
+    ir_max_sharpness: Option<f64>,
+}
+
+impl Triage {
+    /// Classifies the failure of the signup described by the report parts.
+    pub(super) fn new(
+        metadata: &Metadata,
+        pipeline_errors: &PipelineErrors,
+        ir_camera: &[IrCameraMetadata],
+    ) -> Self {
+        let irnet = ir_camera.iter().filter_map(|frame| frame.irnet.as_ref());
+        let ir_eye_frames = irnet.clone().filter(|irnet| irnet.eye_detected > EYE_DETECTED_MIN);
+        let PipelineErrors { iris_model_error, occlusion_error, face_identifier_error } =
+            pipeline_errors;
+        let signals = Signals {
+            signup_status: &metadata.signup_status,
+            enrollment_status: metadata.enrollment_status.as_ref(),
+            biometric_capture_succeeded: metadata.biometric_capture_succeeded,
+            duration: metadata.end_timestamp - metadata.start_timestamp,
+            failure_feedback_capture: &metadata.failure_feedback_capture,
+            failure_feedback_after_capture: &metadata.failure_feedback_after_capture,
+            pipeline_errors: [
+                ("iris model", iris_model_error.is_some()),
+                ("occlusion", occlusion_error.is_some()),
+                ("face identifier", face_identifier_error.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(name, failed)| failed.then_some(name))
+            .collect(),
+            ir_frames: ir_camera.len(),
+            ir_eye_frames: ir_eye_frames.clone().count(),
+            ir_sharp_frames: ir_eye_frames.clone().filter(|irnet| irnet.iris_sharp).count(),
+            ir_max_sharpness: ir_eye_frames
+                .map(|irnet| irnet.fractional_sharpness_score)
+                .reduce(f64::max),
+        };
+        classify(&signals)
+    }
+
+    fn push(&mut self, cause: ProbableCause, finding: String) {
+        self.probable_cause.get_or_insert(cause);
+        self.evidence.push(Evidence { cause, finding });
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn classify(signals: &Signals) -> Triage {
+    let mut triage = Triage::default();
+    if matches!(signals.signup_status, SignupStatus::Success | SignupStatus::Fraud) {
+        return triage;
+    }
+
+    // Firmware.
+    if *signals.signup_status == SignupStatus::AppIncompatible {
+        triage.push(ProbableCause::Firmware, "app is incompatible with the orb".to_owned());
+    }
+    match signals.enrollment_status {
+        Some(enroll_user::Status::SoftwareVersionOutdated) => {
+            triage.push(ProbableCause::Firmware, "orb software version is outdated".to_owned());
+        }
+        Some(enroll_user::Status::SoftwareVersionUnknown) => {
+            triage.push(ProbableCause::Firmware, "orb software version is unknown".to_owned());
+        }
+        Some(enroll_user::Status::SignatureCalculationError) => {
+            triage.push(ProbableCause::Firmware, "signup signature calculation failed".to_owned());
+        }
+        Some(enroll_user::Status::InvalidIrisCode(err)) => {
+            triage.push(ProbableCause::Firmware, format!("iris code validation failed: {err}"));
+        }
+        _ => {}
+    }
+    for name in &signals.pipeline_errors {
+        triage.push(ProbableCause::Firmware, format!("{name} pipeline raised an error"));
+    }
+
+    // Network.
+    if *signals.signup_status == SignupStatus::OrbRelayFailure {
+        triage.push(ProbableCause::Network, "orb relay communication failed".to_owned());
+    } else if matches!(signals.enrollment_status, Some(enroll_user::Status::Error)) {
+        triage.push(ProbableCause::Network, "enrollment request failed to complete".to_owned());
+    }
+
+    // Backend.
+    if *signals.signup_status == SignupStatus::ServerFailure {
+        triage.push(ProbableCause::Backend, "backend reported a signup failure".to_owned());
+    }
+    match signals.enrollment_status {
+        Some(enroll_user::Status::ServerError) => {
+            triage.push(ProbableCause::Backend, "enrollment returned a server error".to_owned());
+        }
+        Some(enroll_user::Status::SignupVerificationNotSuccessful) => {
+            triage
+                .push(ProbableCause::Backend, "signup verification was not successful".to_owned());
+        }
+        _ => {}
+    }
+    if signals
+        .failure_feedback_after_capture
+        .iter()
+        .any(|message| matches!(message, AfterCaptureFeedbackMessage::ServerError))
+    {
+        triage.push(ProbableCause::Backend, "server error feedback was sent to the app".to_owned());
+    }
+
+    // Optics.
+    if !signals.biometric_capture_succeeded
+        && signals.ir_eye_frames > 0
+        && signals.ir_sharp_frames == 0
+    {
+        triage.push(
+            ProbableCause::Optics,
+            format!(
+                "eyes were detected in {} IR frames, but none was sharp (best sharpness {:.2})",
+                signals.ir_eye_frames,
+                signals.ir_max_sharpness.unwrap_or_default()
+            ),
+        );
+    }
+    for message in signals.failure_feedback_after_capture {
+        if let AfterCaptureFeedbackMessage::Pipeline(
+            PipelineFailureFeedbackMessage::LowImageQuality,
+        ) = message
+        {
+            triage.push(ProbableCause::Optics, "pipeline reported low image quality".to_owned());
+        }
+    }
+
+    // User positioning.
+    for message in signals.failure_feedback_capture {
+        let finding = match message {
+            CaptureFailureFeedbackMessage::TooFar => "user was too far from the orb",
+            CaptureFailureFeedbackMessage::TooClose => "user was too close to the orb",
+            CaptureFailureFeedbackMessage::EyesOcclusion => "eyes were occluded during capture",
+            CaptureFailureFeedbackMessage::FaceOcclusionOrPoorLighting => {
+                "face was occluded or poorly lit during capture"
+            }
+        };
+        triage.push(ProbableCause::UserPositioning, finding.to_owned());
+    }
+    for message in signals.failure_feedback_after_capture {
+        let AfterCaptureFeedbackMessage::Pipeline(message) = message else {
+            continue;
+        };
+        let finding = match message {
+            PipelineFailureFeedbackMessage::HeadPose => "head pose was not straight",
+            PipelineFailureFeedbackMessage::EyesOcclusion => "eyes were occluded",
+            PipelineFailureFeedbackMessage::FaceOcclusion
+            | PipelineFailureFeedbackMessage::Mask
+            | PipelineFailureFeedbackMessage::EyeGlasses => "face was occluded",
+            PipelineFailureFeedbackMessage::MultipleFaces => "multiple faces were in view",
+            PipelineFailureFeedbackMessage::ContactLenses
+            | PipelineFailureFeedbackMessage::Underaged
+            | PipelineFailureFeedbackMessage::LowImageQuality => continue,
+        };
+        triage.push(ProbableCause::UserPositioning, format!("pipeline reported {finding}"));
+    }
+    if !signals.biometric_capture_succeeded && signals.ir_eye_frames == 0 {
+        triage.push(
+            ProbableCause::UserPositioning,
+            format!(
+                "no eye was detected in {} IR frames during {:.1}s",
+                signals.ir_frames, signals.duration
+            ),
+        );
+    }
+    triage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signals(signup_status: &SignupStatus) -> Signals<'_> {
+        Signals {
+            signup_status,
+            enrollment_status: None,
+            biometric_capture_succeeded: true,
+            duration: 30.0,
+            failure_feedback_capture: &[],
+            failure_feedback_after_capture: &[],
+            pipeline_errors: Vec::new(),
+            ir_frames: 100,
+            ir_eye_frames: 80,
+            ir_sharp_frames: 20,
+            ir_max_sharpness: Some(0.9),
+        }
+    }
+
+    #[test]
+    fn test_success_is_not_triaged() {
+        let triage = classify(&signals(&SignupStatus::Success));
+        assert!(triage.probable_cause.is_none());
+        assert!(triage.evidence.is_empty());
+    }
+
+    #[test]
+    fn test_network() {
+        let status = enroll_user::Status::Error;
+        let triage = classify(&Signals {
+            enrollment_status: Some(&status),
+            ..signals(&SignupStatus::OrbFailure)
+        });
+        assert_eq!(triage.probable_cause, Some(ProbableCause::Network));
+    }
+
+    #[test]
+    fn test_optics_before_positioning() {
+        let feedback = [CaptureFailureFeedbackMessage::TooFar];
+        let triage = classify(&Signals {
+            biometric_capture_succeeded: false,
+            failure_feedback_capture: &feedback,
+            ir_sharp_frames: 0,
+            ir_max_sharpness: Some(0.3),
+            ..signals(&SignupStatus::OrbFailure)
+        });
+        assert_eq!(triage.probable_cause, Some(ProbableCause::Optics));
+        assert_eq!(triage.evidence.len(), 2);
+        assert_eq!(triage.evidence[1].cause, ProbableCause::UserPositioning);
+    }
+
+    #[test]
+    fn test_no_eyes() {
+        let triage = classify(&Signals {
+            biometric_capture_succeeded: false,
+            ir_eye_frames: 0,
+            ir_sharp_frames: 0,
+            ir_max_sharpness: None,
+            ..signals(&SignupStatus::OrbFailure)
+        });
+        assert_eq!(triage.probable_cause, Some(ProbableCause::UserPositioning));
+    }
+}
@@ -1,5 +1,9 @@
 //! Digital Signal Processing.
 
 mod lagging;
+mod spectrum;
 
-pub use self::lagging::Lagging;
+pub use self::{
+    lagging::Lagging,
+    spectrum::{goertzel, Spectrum},
+};
@@ -0,0 +1,153 @@
+use std::{f64::consts::PI, ops::RangeInclusive};
+
+/// Returns the amplitude of the `frequency` component of the uniformly sampled
+/// signal, computed with the Goertzel algorithm. The signal mean is removed
+/// first.
+#[must_use]
+pub fn goertzel(samples: &[f64], sample_rate: f64, frequency: f64) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let mean = mean(samples);
+    let coefficient = 2.0 * (2.0 * PI * frequency / sample_rate).cos();
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for sample in samples {
+        let s0 = sample - mean + coefficient * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    let power = s1 * s1 + s2 * s2 - coefficient * s1 * s2;
+    #[allow(clippy::cast_precision_loss)]
+    let len = samples.len() as f64;
+    2.0 * power.max(0.0).sqrt() / len
+}
+
+/// Amplitude spectrum of a uniformly sampled signal.
+#[derive(Clone, Debug)]
+pub struct Spectrum {
+    amplitudes: Vec<f64>,
+    resolution: f64,
+}
+
+impl Spectrum {
+    /// Computes the amplitude spectrum with a radix-2 FFT. The signal mean is
+    /// removed, and the signal is zero-padded to the next power of two.
+    #[must_use]
+    pub fn new(samples: &[f64], sample_rate: f64) -> Self {
+        let len = samples.len().next_power_of_two();
+        let mean = mean(samples);
+        let mut re = samples.iter().map(|sample| sample - mean).collect::<Vec<_>>();
+        re.resize(len, 0.0);
+        let mut im = vec![0.0; len];
+        fft(&mut re, &mut im);
+        #[allow(clippy::cast_precision_loss)]
+        let scale = 2.0 / samples.len().max(1) as f64;
+        let amplitudes =
+            re.iter().zip(&im).take(len / 2 + 1).map(|(re, im)| re.hypot(*im) * scale).collect();
+        #[allow(clippy::cast_precision_loss)]
+        let resolution = sample_rate / len as f64;
+        Self { amplitudes, resolution }
+    }
+
+    /// Returns the frequency step between the bins in Hz.
+    #[must_use]
+    pub fn resolution(&self) -> f64 {
+        self.resolution
+    }
+
+    /// Returns the frequency and the amplitude of the strongest component
+    /// within `band` in Hz.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn peak(&self, band: RangeInclusive<f64>) -> Option<(f64, f64)> {
+        self.amplitudes
+            .iter()
+            .enumerate()
+            .map(|(i, amplitude)| (i as f64 * self.resolution, *amplitude))
+            .filter(|(frequency, _)| band.contains(frequency))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// In-place iterative radix-2 FFT. The length must be a power of two.
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let len = re.len();
+    if len < 2 {
+        return;
+    }
+    let bits = len.trailing_zeros();
+    for i in 0..len {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut size = 2;
+    while size <= len {
+        #[allow(clippy::cast_precision_loss)]
+        let angle = -2.0 * PI / size as f64;
+        for start in (0..len).step_by(size) {
+            for k in 0..size / 2 {
+                #[allow(clippy::cast_precision_loss)]
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + size / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        size *= 2;
+    }
+}
+
+fn mean(samples: &[f64]) -> f64 {
+    #[allow(clippy::cast_precision_loss)]
+    let len = samples.len().max(1) as f64;
+    samples.iter().sum::<f64>() / len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 400.0;
+
+    fn signal(len: usize, components: &[(f64, f64)]) -> Vec<f64> {
+        (0..len)
+            .map(|i| {
+                #[allow(clippy::cast_precision_loss)]
+                let t = i as f64 / SAMPLE_RATE;
+                1.0 + components
+                    .iter()
+                    .map(|(frequency, amplitude)| amplitude * (2.0 * PI * frequency * t).sin())
+                    .sum::<f64>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_goertzel() {
+        let samples = signal(400, &[(25.0, 0.5), (60.0, 0.2)]);
+        assert!((goertzel(&samples, SAMPLE_RATE, 25.0) - 0.5).abs() < 1e-6);
+        assert!((goertzel(&samples, SAMPLE_RATE, 60.0) - 0.2).abs() < 1e-6);
+        assert!(goertzel(&samples, SAMPLE_RATE, 90.0) < 1e-6);
+        assert!(goertzel(&[], SAMPLE_RATE, 25.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_spectrum() {
+        let samples = signal(512, &[(25.0, 0.5), (125.0, 0.3)]);
+        let spectrum = Spectrum::new(&samples, SAMPLE_RATE);
+        assert!((spectrum.resolution() - SAMPLE_RATE / 512.0).abs() < f64::EPSILON);
+        let (frequency, amplitude) = spectrum.peak(100.0..=150.0).unwrap();
+        assert!((frequency - 125.0).abs() <= spectrum.resolution());
+        assert!((amplitude - 0.3).abs() < 0.05);
+        let (frequency, _) = spectrum.peak(0.0..=200.0).unwrap();
+        assert!((frequency - 25.0).abs() <= spectrum.resolution());
+        assert!(spectrum.peak(300.0..=400.0).is_none());
+    }
+}
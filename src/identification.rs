@@ -1,4 +1,7 @@
 //! Orb identification.
+
+pub mod alias;
+
 use crate::process::Command;
 #[cfg(not(test))]
 use crate::versions_json::VersionsJson;
@@ -56,6 +59,12 @@ pub static ODM_PRODUCTION_MODE: Lazy<String> = Lazy::new(read_odm_production_mod
 /// Orb OS version for the current slot.
 pub static ORB_OS_VERSION: Lazy<String> = Lazy::new(|| orb_os_version().unwrap());
 
+/// Main MCU firmware release shipped with the Orb OS, if listed.
+pub static MCU_MAIN_RELEASE: Lazy<Option<String>> = Lazy::new(mcu_main_release);
+
+/// Security MCU firmware release shipped with the Orb OS, if listed.
+pub static MCU_SEC_RELEASE: Lazy<Option<String>> = Lazy::new(mcu_sec_release);
+
 /// The release type for the current slot.
 pub static RELEASE_TYPE: Lazy<String> = Lazy::new(|| current_release_type().unwrap());
 
@@ -152,6 +161,26 @@ fn orb_os_version() -> Result<String> {
     Ok("test".into())
 }
 
+#[cfg(not(test))]
+fn mcu_main_release() -> Option<String> {
+    read_versions_json().mcu.map(|mcu| mcu.main)
+}
+
+#[cfg(test)]
+fn mcu_main_release() -> Option<String> {
+    None
+}
+
+#[cfg(not(test))]
+fn mcu_sec_release() -> Option<String> {
+    read_versions_json().mcu.and_then(|mcu| mcu.sec)
+}
+
+#[cfg(test)]
+fn mcu_sec_release() -> Option<String> {
+    None
+}
+
 fn current_release_type() -> Result<String> {
     let output = Command::new("/usr/local/bin/release-type").arg(&*CURRENT_BOOT_SLOT).output()?;
     Ok(String::from_utf8(output.stdout)?.trim().into())
@@ -0,0 +1,110 @@
+//! Friendly orb aliases.
+//!
+//! Operators refer to orbs by display names rather than by the fused orb-id.
+//! The backend provides the display name and the venue tag of the orb as part
+//! of the orb config. The latest alias is cached on the persistent partition,
+//! so it is known right after boot even without connectivity.
+//!
+//! The alias is shown on the livestream dashboard, exposed over DBus, and
+//! attached to the Datadog metrics as the `orb_name` and `venue` tags. The
+//! Datadog tags are taken from the cached alias when the Datadog client is
+//! initialized, so an alias change is reflected there after a restart.
+
+use super::ORB_NAME;
+use eyre::Result;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{fs, sync::RwLock};
+
+const ALIAS_CACHE_PATH: &str = "/usr/persistent/orb-alias.json";
+
+static ALIAS: Lazy<RwLock<Alias>> = Lazy::new(|| RwLock::new(load()));
+
+/// Backend-provided alias of the orb.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct Alias {
+    /// Display name used by the operators.
+    pub display_name: Option<String>,
+    /// Tag of the venue the orb is assigned to.
+    pub venue_tag: Option<String>,
+}
+
+impl Alias {
+    /// Returns the display name, falling back to the orb name.
+    #[must_use]
+    pub fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&ORB_NAME)
+    }
+
+    /// Returns the Datadog tags for the alias.
+    #[must_use]
+    pub fn datadog_tags(&self) -> Vec<String> {
+        let mut tags = Vec::new();
+        if let Some(display_name) = &self.display_name {
+            tags.push(format!("orb_name:{display_name}"));
+        }
+        if let Some(venue_tag) = &self.venue_tag {
+            tags.push(format!("venue:{venue_tag}"));
+        }
+        tags
+    }
+}
+
+/// Returns the current alias of the orb.
+///
+/// # Panics
+///
+/// If RW lock is poisoned
+#[must_use]
+pub fn get() -> Alias {
+    ALIAS.read().unwrap().clone()
+}
+
+/// Updates the alias with the one received from the backend, and caches it if
+/// it has changed.
+///
+/// # Panics
+///
+/// If RW lock is poisoned
+pub fn sync(alias: &Alias) {
+    {
+        let mut current = ALIAS.write().unwrap();
+        if *current == *alias {
+            return;
+        }
+        tracing::info!("Orb alias changed from {current:?} to {alias:?}");
+        current.clone_from(alias);
+    }
+    if let Err(err) = store(alias) {
+        tracing::error!("Failed to cache the orb alias: {err:?}");
+    }
+}
+
+fn load() -> Alias {
+    fs::read_to_string(ALIAS_CACHE_PATH)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn store(alias: &Alias) -> Result<()> {
+    fs::write(ALIAS_CACHE_PATH, serde_json::to_string(alias)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_datadog_tags() {
+        assert!(Alias::default().datadog_tags().is_empty());
+        let alias = Alias {
+            display_name: Some("berlin-3".to_owned()),
+            venue_tag: Some("mitte-hub".to_owned()),
+        };
+        assert_eq!(alias.datadog_tags(), ["orb_name:berlin-3", "venue:mitte-hub"]);
+        assert_eq!(alias.display_name(), "berlin-3");
+    }
+}
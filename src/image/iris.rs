@@ -0,0 +1,239 @@
+//! Iris region crop and normalization.
+//!
+//! The iris region is a square centered on the centroid of the iris landmarks,
+//! with a side of the landmarks diameter extended by a relative margin. Parts
+//! of the region outside of the frame are padded with a constant value. The
+//! crop is then contrast-stretched and resized with bilinear interpolation,
+//! which matches OpenCV `INTER_LINEAR`: pixel centers are aligned at half-pixel
+//! offsets, and samples beyond the border are clamped to the edge pixels.
+
+use crate::agents::camera::{self, Frame as _};
+
+/// 8-bit grayscale image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrayImage {
+    /// Image width.
+    pub width: u32,
+    /// Image height.
+    pub height: u32,
+    /// Row-major pixel data.
+    pub data: Vec<u8>,
+}
+
+/// Square crop region in source pixel coordinates. The region may extend
+/// beyond the frame borders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    /// Horizontal coordinate of the top-left corner.
+    pub x: i64,
+    /// Vertical coordinate of the top-left corner.
+    pub y: i64,
+    /// Side of the square.
+    pub size: u32,
+}
+
+/// Iris crop parameters.
+#[derive(Clone, Copy, Debug)]
+pub struct Params {
+    /// Margin around the landmarks relative to the landmarks diameter.
+    pub margin: f64,
+    /// Side of the resulting square image.
+    pub output_size: u32,
+    /// Value of the pixels outside of the frame.
+    pub pad_value: u8,
+    /// Whether to stretch the crop contrast to the full range.
+    pub normalize: bool,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self { margin: 0.25, output_size: 256, pad_value: 0, normalize: true }
+    }
+}
+
+impl GrayImage {
+    /// Creates a new image filled with `value`.
+    #[must_use]
+    pub fn filled(width: u32, height: u32, value: u8) -> Self {
+        Self { width, height, data: vec![value; width as usize * height as usize] }
+    }
+
+    /// Returns the pixel at `(x, y)`.
+    #[must_use]
+    pub fn get(&self, x: u32, y: u32) -> u8 {
+        self.data[y as usize * self.width as usize + x as usize]
+    }
+}
+
+impl From<&camera::ir::Frame> for GrayImage {
+    fn from(frame: &camera::ir::Frame) -> Self {
+        Self { width: frame.width(), height: frame.height(), data: frame.as_bytes().to_vec() }
+    }
+}
+
+/// Returns the iris region for the landmarks in pixel coordinates. Returns
+/// `None` if there are no landmarks.
+#[must_use]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+pub fn region_from_landmarks(landmarks: &[(f64, f64)], margin: f64) -> Option<Region> {
+    if landmarks.is_empty() {
+        return None;
+    }
+    let count = landmarks.len() as f64;
+    let cx = landmarks.iter().map(|(x, _)| x).sum::<f64>() / count;
+    let cy = landmarks.iter().map(|(_, y)| y).sum::<f64>() / count;
+    let radius = landmarks.iter().map(|(x, y)| (x - cx).hypot(y - cy)).fold(0.0, f64::max);
+    let size = (2.0 * radius * (1.0 + margin)).ceil().max(1.0);
+    Some(Region {
+        x: (cx - size / 2.0).round() as i64,
+        y: (cy - size / 2.0).round() as i64,
+        size: size as u32,
+    })
+}
+
+/// Crops the region from the image, padding the parts outside of the image
+/// with `pad_value`.
+#[must_use]
+pub fn crop(image: &GrayImage, region: Region, pad_value: u8) -> GrayImage {
+    let mut output = GrayImage::filled(region.size, region.size, pad_value);
+    for oy in 0..region.size {
+        let Ok(y) = u32::try_from(region.y + i64::from(oy)) else { continue };
+        if y >= image.height {
+            continue;
+        }
+        for ox in 0..region.size {
+            let Ok(x) = u32::try_from(region.x + i64::from(ox)) else { continue };
+            if x >= image.width {
+                continue;
+            }
+            output.data[oy as usize * region.size as usize + ox as usize] = image.get(x, y);
+        }
+    }
+    output
+}
+
+/// Stretches the image contrast linearly to the full `0..=255` range. Flat
+/// images are left unchanged.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn normalize(image: &mut GrayImage) {
+    let (Some(&min), Some(&max)) = (image.data.iter().min(), image.data.iter().max()) else {
+        return;
+    };
+    if min == max {
+        return;
+    }
+    let scale = 255.0 / f64::from(max - min);
+    for pixel in &mut image.data {
+        *pixel = (f64::from(*pixel - min) * scale).round() as u8;
+    }
+}
+
+/// Resizes the image with bilinear interpolation.
+#[must_use]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn resize(image: &GrayImage, width: u32, height: u32) -> GrayImage {
+    let mut output = GrayImage::filled(width, height, 0);
+    if image.width == 0 || image.height == 0 {
+        return output;
+    }
+    let scale_x = f64::from(image.width) / f64::from(width);
+    let scale_y = f64::from(image.height) / f64::from(height);
+    let max_x = f64::from(image.width - 1);
+    let max_y = f64::from(image.height - 1);
+    for oy in 0..height {
+        let sy = ((f64::from(oy) + 0.5) * scale_y - 0.5).clamp(0.0, max_y);
+        let (y0, fy) = (sy.floor() as u32, sy.fract());
+        let y1 = (y0 + 1).min(image.height - 1);
+        for ox in 0..width {
+            let sx = ((f64::from(ox) + 0.5) * scale_x - 0.5).clamp(0.0, max_x);
+            let (x0, fx) = (sx.floor() as u32, sx.fract());
+            let x1 = (x0 + 1).min(image.width - 1);
+            let top = f64::from(image.get(x0, y0)) * (1.0 - fx) + f64::from(image.get(x1, y0)) * fx;
+            let bottom =
+                f64::from(image.get(x0, y1)) * (1.0 - fx) + f64::from(image.get(x1, y1)) * fx;
+            output.data[oy as usize * width as usize + ox as usize] =
+                (top * (1.0 - fy) + bottom * fy).round() as u8;
+        }
+    }
+    output
+}
+
+/// Crops, pads, normalizes, and resizes the iris region around the
+/// landmarks. Returns `None` if there are no landmarks.
+#[must_use]
+pub fn crop_and_normalize(
+    image: &GrayImage,
+    landmarks: &[(f64, f64)],
+    params: &Params,
+) -> Option<GrayImage> {
+    let region = region_from_landmarks(landmarks, params.margin)?;
+    let mut cropped = crop(image, region, params.pad_value);
+    if params.normalize {
+        normalize(&mut cropped);
+    }
+    Some(resize(&cropped, params.output_size, params.output_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> GrayImage {
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| u8::try_from(x * 10 + y).unwrap()))
+            .collect();
+        GrayImage { width, height, data }
+    }
+
+    #[test]
+    fn test_region_from_landmarks() {
+        let landmarks = [(10.0, 20.0), (14.0, 20.0), (12.0, 18.0), (12.0, 22.0)];
+        assert_eq!(region_from_landmarks(&landmarks, 0.5), Some(Region { x: 9, y: 17, size: 6 }));
+        assert_eq!(region_from_landmarks(&[], 0.5), None);
+    }
+
+    #[test]
+    fn test_crop_pads_outside() {
+        let image = gradient(4, 4);
+        let cropped = crop(&image, Region { x: -1, y: 2, size: 3 }, 255);
+        #[rustfmt::skip]
+        assert_eq!(cropped.data, [
+            255, 2, 12,
+            255, 3, 13,
+            255, 255, 255,
+        ]);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut image = GrayImage { width: 3, height: 1, data: vec![10, 20, 30] };
+        normalize(&mut image);
+        assert_eq!(image.data, [0, 128, 255]);
+        let mut flat = GrayImage::filled(2, 2, 7);
+        normalize(&mut flat);
+        assert_eq!(flat.data, [7; 4]);
+    }
+
+    #[test]
+    fn test_resize_golden() {
+        let image = GrayImage { width: 2, height: 2, data: vec![0, 100, 200, 40] };
+        let upscaled = resize(&image, 4, 4);
+        #[rustfmt::skip]
+        assert_eq!(upscaled.data, [
+            0, 25, 75, 100,
+            50, 59, 76, 85,
+            150, 126, 79, 55,
+            200, 160, 80, 40,
+        ]);
+        assert_eq!(resize(&upscaled, 2, 2).data, [34, 84, 159, 64]);
+    }
+
+    #[test]
+    fn test_crop_and_normalize_golden() {
+        let image = gradient(8, 8);
+        let landmarks = [(2.0, 4.0), (6.0, 4.0), (4.0, 2.0), (4.0, 6.0)];
+        let params = Params { margin: 0.0, output_size: 2, pad_value: 0, normalize: true };
+        let output = crop_and_normalize(&image, &landmarks, &params).unwrap();
+        assert_eq!(output.data, [43, 197, 58, 213]);
+    }
+}
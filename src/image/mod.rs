@@ -1,3 +1,5 @@
 //! Image processing module.
 
 pub mod fisheye;
+pub mod iris;
+pub mod phash;
@@ -0,0 +1,170 @@
+//! Perceptual image hash.
+//!
+//! The hash is a 64-bit difference hash (dHash): the image luma is
+//! box-averaged down to a 9x8 grid, and each bit records whether a cell is
+//! brighter than its right neighbour. Unlike a cryptographic digest, the hash
+//! survives rescaling, re-encoding and small brightness changes, so the
+//! Hamming distance between the hashes of two images measures their visual
+//! similarity. It is used to deduplicate frames across captures and to detect
+//! images substituted after the capture. The hashes of the identification
+//! images are stored in the `info.json` of the personal custody package, which
+//! is produced for every signup.
+
+use crate::agents::camera::Frame;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+const GRID_WIDTH: usize = 9;
+const GRID_HEIGHT: usize = 8;
+
+/// Maximal Hamming distance between the hashes of visually identical images.
+pub const SIMILARITY_THRESHOLD: u32 = 5;
+
+/// 64-bit perceptual difference hash.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PerceptualHash(pub u64);
+
+impl PerceptualHash {
+    /// Computes the hash of an interleaved 8-bit image with `channels` samples
+    /// per pixel. Multi-channel pixels are averaged.
+    ///
+    /// # Panics
+    ///
+    /// If `data` is shorter than `width * height * channels`.
+    #[must_use]
+    pub fn from_pixels(width: usize, height: usize, channels: usize, data: &[u8]) -> Self {
+        if width == 0 || height == 0 || channels == 0 {
+            return Self::default();
+        }
+        assert!(data.len() >= width * height * channels, "image data is too short");
+        let mut grid = [[0.0; GRID_WIDTH]; GRID_HEIGHT];
+        for (cy, row) in grid.iter_mut().enumerate() {
+            let (y0, y1) = cell_range(cy, GRID_HEIGHT, height);
+            for (cx, cell) in row.iter_mut().enumerate() {
+                let (x0, x1) = cell_range(cx, GRID_WIDTH, width);
+                let mut sum = 0_u64;
+                for y in y0..y1 {
+                    let line = &data[(y * width + x0) * channels..(y * width + x1) * channels];
+                    sum += line.iter().map(|&sample| u64::from(sample)).sum::<u64>();
+                }
+                let count = (y1 - y0) * (x1 - x0) * channels;
+                #[allow(clippy::cast_precision_loss)]
+                {
+                    *cell = sum as f64 / count as f64;
+                }
+            }
+        }
+        let mut hash = 0;
+        for row in &grid {
+            for pair in row.windows(2) {
+                hash = hash << 1 | u64::from(pair[0] > pair[1]);
+            }
+        }
+        Self(hash)
+    }
+
+    /// Computes the hash of a camera frame.
+    #[must_use]
+    pub fn from_frame(frame: &impl Frame) -> Self {
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+        let data = frame.as_bytes();
+        let channels = if width * height == 0 { 0 } else { data.len() / (width * height) };
+        Self::from_pixels(width, height, channels, data)
+    }
+
+    /// Returns the Hamming distance between two hashes.
+    #[must_use]
+    pub fn distance(self, other: Self) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+
+    /// Returns `true` if the hashes belong to visually identical images.
+    #[must_use]
+    pub fn is_similar(self, other: Self) -> bool {
+        self.distance(other) <= SIMILARITY_THRESHOLD
+    }
+}
+
+/// Returns the pixel range of the `index`-th of `cells` cells along an axis of
+/// `len` pixels. Each cell covers at least one pixel.
+fn cell_range(index: usize, cells: usize, len: usize) -> (usize, usize) {
+    let start = (index * len / cells).min(len - 1);
+    let end = ((index + 1) * len / cells).clamp(start + 1, len);
+    (start, end)
+}
+
+impl fmt::Display for PerceptualHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for PerceptualHash {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16).map(Self)
+    }
+}
+
+impl Serialize for PerceptualHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PerceptualHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: usize, height: usize, offset: u8) -> Vec<u8> {
+        (0..height)
+            .flat_map(|y| {
+                (0..width).map(move |x| {
+                    let value = (x * 7 + y * 3) % 200 + usize::from(offset);
+                    u8::try_from(value.min(255)).unwrap()
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_perceptual_hash() {
+        let image = gradient(160, 120, 0);
+        let hash = PerceptualHash::from_pixels(160, 120, 1, &image);
+        assert_ne!(hash, PerceptualHash::default());
+
+        let brighter = gradient(160, 120, 20);
+        assert!(hash.is_similar(PerceptualHash::from_pixels(160, 120, 1, &brighter)));
+
+        let downscaled = (0..60)
+            .flat_map(|y| (0..80).map(move |x| y * 2 * 160 + x * 2))
+            .map(|i| {
+                let sum = [i, i + 1, i + 160, i + 161]
+                    .into_iter()
+                    .map(|i| u16::from(image[i]))
+                    .sum::<u16>();
+                u8::try_from(sum / 4).unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(hash.is_similar(PerceptualHash::from_pixels(80, 60, 1, &downscaled)));
+
+        let rgb = image.iter().flat_map(|&v| [v, v, v]).collect::<Vec<_>>();
+        assert_eq!(PerceptualHash::from_pixels(160, 120, 3, &rgb), hash);
+
+        let mirrored =
+            image.chunks(160).flat_map(|row| row.iter().rev().copied()).collect::<Vec<_>>();
+        assert!(!hash.is_similar(PerceptualHash::from_pixels(160, 120, 1, &mirrored)));
+
+        assert_eq!(hash.to_string().parse::<PerceptualHash>().unwrap(), hash);
+        assert_eq!(PerceptualHash::from_pixels(4, 2, 1, &[0; 8]), PerceptualHash(0));
+    }
+}
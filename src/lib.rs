@@ -32,6 +32,7 @@ pub mod calibration;
 pub mod cli;
 pub mod config;
 pub mod consts;
+pub mod crash;
 pub mod dbus;
 pub mod debug_report;
 pub mod dsp;
@@ -47,6 +48,7 @@ pub mod plans;
 pub mod process;
 pub mod secure_element;
 pub mod short_lived_token;
+pub mod sound;
 pub mod ssd;
 pub mod time_series;
 pub mod timestamped;
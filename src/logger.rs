@@ -1,7 +1,7 @@
 //! Logging support.
 
-use crate::agents::PROCESS_DOGSTATSD_ENV;
-use dogstatsd::{Client, Options};
+use crate::{agents::PROCESS_DOGSTATSD_ENV, identification};
+use dogstatsd::{Client, OptionsBuilder};
 use eyre::Result;
 use flexi_logger::{
     filter::{LogLineFilter, LogLineWriter},
@@ -120,6 +120,26 @@ macro_rules! dd_count {
     };
 }
 
+/// Helper macro to send a datadog event.
+#[macro_export]
+macro_rules! dd_event {
+    (
+        $title:expr,
+        $text:expr
+        $(, $tag:expr)*
+        $(; $tags:expr)?
+    ) => {
+        if !$crate::logger::DATADOG_SUPPRESS.load(std::sync::atomic::Ordering::Relaxed) {
+            #[allow(unused_variables)]
+            let tags: &[&str] = &[$($tag),*];
+            $(let tags = $tags;)?
+            if let Err(err) = $crate::logger::DATADOG.event($title, $text, tags) {
+                ::tracing::error!("Datadog event reporting failed with error: {err:#?}");
+            }
+        }
+    };
+}
+
 /// Orb identification code.
 pub static DATADOG: Lazy<Client> = Lazy::new(init_datadog_client);
 
@@ -132,10 +152,15 @@ fn try_create_datadog_client_from_socket() -> Option<Client> {
 }
 
 /// This should only be used before forking a new process-agent. Creates a default datadog client. This default datadog client creates a new FD socket to connect to the actual datadog daemon. This new open socket can be consecutively used by orb-core's process-agents that are inside a network namespace.
+///
+/// The client tags every metric with the cached [orb alias](identification::alias).
 #[must_use]
 pub fn create_default_datadog_client() -> Client {
-    let datadog_options = Options::default();
-    Client::new(datadog_options).unwrap()
+    let mut datadog_options = OptionsBuilder::new();
+    for tag in identification::alias::get().datadog_tags() {
+        datadog_options.default_tag(tag);
+    }
+    Client::new(datadog_options.build()).unwrap()
 }
 
 /// We currently have two methods for establishing a connection to the Datadog daemon:
@@ -1,7 +1,7 @@
 //! CAN MCU interface.
 
-use super::{Interface, ResultSender};
-use crate::utils::spawn_named_thread;
+use super::{protocol::Protocol, Ack, AckPolicies, AckTimeout, Interface, ResultSender};
+use crate::dd_incr;
 use eyre::{bail, Error, Result};
 use futures::{
     channel::mpsc,
@@ -10,22 +10,55 @@ use futures::{
 };
 use libc::CAN_EFF_FLAG;
 use nmea_parser::NmeaParser;
-use orb_can::fd;
-use orb_messages;
-use prost::Message;
-use std::{marker::PhantomData, time::Duration};
-use tokio::{sync::broadcast, task, time::timeout};
+use once_cell::sync::OnceCell;
+use orb_can::{
+    fd::{self, BusState},
+    trace,
+};
+use std::{
+    marker::PhantomData,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::broadcast,
+    task,
+    time::{sleep, sleep_until, timeout},
+};
 
 const ASYNC_TX_CAPACITY: usize = 100;
 const ASYNC_RX_CAPACITY: usize = 100;
 const ACK_CAPACITY: usize = 100;
-const CAN_SOCKET: &str = "can0";
-const CAN_FD_ADDR_JETSON: u32 = 0x80 | CAN_EFF_FLAG;
-const TIMEOUT: Duration = Duration::from_millis(300);
+/// CAN interface the microcontrollers are connected to.
+pub const CAN_SOCKET: &str = "can0";
+/// CAN FD address of the Jetson.
+pub const CAN_FD_ADDR_JETSON: u32 = 0x80 | CAN_EFF_FLAG;
+/// Minimal interval between automatic restarts of the CAN interface after a
+/// bus-off. A failed restart is retried after this interval.
+const BUS_OFF_RESTART_INTERVAL: Duration = Duration::from_secs(1);
+/// Size of the CAN trace log before it's rotated.
+const TRACE_MAX_SIZE: u64 = 64 * 1024 * 1024;
+/// Delay before receiving again after a socket error, e.g. while the interface
+/// is down.
+const RECV_ERROR_BACKOFF: Duration = Duration::from_millis(10);
+
+static TRACE: OnceCell<trace::Recorder> = OnceCell::new();
 
 /// CAN interface.
 pub struct Can<I: Interface>(PhantomData<I>);
 
+/// Records all CAN frames sent and received by the microcontroller interfaces
+/// to a candump log at `path`, rotated at [`TRACE_MAX_SIZE`]. Must be called
+/// before spawning the interfaces.
+pub fn trace_to(path: &Path) -> Result<()> {
+    let recorder = trace::Recorder::create(path, TRACE_MAX_SIZE)?;
+    if TRACE.set(recorder).is_err() {
+        bail!("CAN trace is already enabled");
+    }
+    tracing::info!("Recording CAN traffic to {}", path.display());
+    Ok(())
+}
+
 /// Create a unique ack number
 ///
 /// - prefix with process ID
@@ -34,7 +67,7 @@ pub struct Can<I: Interface>(PhantomData<I>);
 /// this added piece of information in the ack number is not strictly necessary
 /// but helps filter out acks that are not for us (e.g. acks for other processes)
 #[inline]
-fn create_ack(counter: u16) -> u32 {
+pub(super) fn create_ack(counter: u16) -> u32 {
     std::process::id() << 16 | u32::from(counter)
 }
 
@@ -45,21 +78,38 @@ fn is_ack_for_us(ack_number: u32) -> bool {
 }
 
 impl<I: Interface> Can<I> {
-    /// Spawns a new CAN interface.
+    /// Spawns a new CAN interface. The protocol version is negotiated with
+    /// the firmware on the first received message.
     pub fn spawn(
         input_rx: mpsc::Receiver<(I::Input, Option<ResultSender>)>,
         output_tx: broadcast::Sender<I::Output>,
+        protocol: Protocol,
     ) -> Result<()> {
-        let (tx, rx) = fd::open(CAN_SOCKET)?;
-        let tx = Self::async_tx(tx);
-        let rx = Self::async_rx(rx);
+        Self::spawn_on(CAN_SOCKET, input_rx, output_tx, protocol, AckPolicies::default())
+    }
+
+    /// Spawns a new CAN interface on the network interface `interface`, e.g. a
+    /// virtual CAN interface in tests.
+    pub fn spawn_on(
+        interface: &str,
+        input_rx: mpsc::Receiver<(I::Input, Option<ResultSender>)>,
+        output_tx: broadcast::Sender<I::Output>,
+        protocol: Protocol,
+        ack_policies: AckPolicies,
+    ) -> Result<()> {
+        let (tx, rx) = fd::open_async(interface)?;
+        let tx = Self::async_tx(tx, interface.to_owned(), protocol.clone());
+        let rx = Self::async_rx(rx, interface.to_owned(), protocol);
         let (ack_tx, ack_rx) = mpsc::channel(ACK_CAPACITY);
         task::spawn(async move {
-            let input_fut = Self::handle_input(tx, input_rx, ack_rx, output_tx.clone());
+            let input_fut =
+                Self::handle_input(tx, input_rx, ack_rx, output_tx.clone(), ack_policies);
             let output_fut = Self::handle_output(rx, output_tx, ack_tx);
-            match future::try_join(input_fut, output_fut).await {
-                Ok(((), ())) => {}
-                Err(err) => {
+            // The input ends once all the interface handles are dropped, which
+            // also stops the output and releases the socket.
+            match future::try_select(Box::pin(input_fut), Box::pin(output_fut)).await {
+                Ok(_) => {}
+                Err(Either::Left((err, _)) | Either::Right((err, _))) => {
                     tracing::error!("MCU task failed: {:?}", err);
                 }
             }
@@ -68,11 +118,52 @@ impl<I: Interface> Can<I> {
         Ok(())
     }
 
-    async fn handle_input(
-        mcu_tx: tokio::sync::mpsc::Sender<orb_messages::mcu_main::mcu_message::Message>,
+    /// Replays the frames sent by the microcontroller in a candump log to
+    /// `output_tx`, preserving the original timing. Acknowledges are skipped,
+    /// as there are no inputs to match them with.
+    pub fn replay(
+        entries: Vec<trace::Entry>,
+        output_tx: broadcast::Sender<I::Output>,
+        protocol: Protocol,
+    ) {
+        let (mcu_tx, mcu_rx) = tokio::sync::mpsc::channel(ASYNC_RX_CAPACITY);
+        let (ack_tx, _) = mpsc::channel(ACK_CAPACITY);
+        task::spawn(async move {
+            let mut prev_timestamp = None;
+            for entry in entries {
+                if entry.can_id != CAN_FD_ADDR_JETSON || entry.fd_flags.is_none() {
+                    continue;
+                }
+                if let Some(prev_timestamp) = prev_timestamp {
+                    sleep(entry.timestamp.saturating_sub(prev_timestamp)).await;
+                }
+                prev_timestamp = Some(entry.timestamp);
+                match decode_message::<I>(&entry.data, &protocol) {
+                    Some(payload) if I::payload_to_ack(&payload).is_none() => {
+                        if mcu_tx.send(payload).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(_) | None => {}
+                }
+            }
+            tracing::info!("CAN replay finished");
+        });
+        task::spawn(async move {
+            if let Err(err) = Self::handle_output(mcu_rx, output_tx, ack_tx).await {
+                tracing::error!("MCU replay task failed: {:?}", err);
+            }
+        });
+    }
+
+    /// Sends the input messages to the microcontroller and waits for their
+    /// acknowledges. Shared with the [UART transport](super::uart).
+    pub(super) async fn handle_input(
+        mcu_tx: tokio::sync::mpsc::Sender<I::Message>,
         mut input_rx: mpsc::Receiver<(I::Input, Option<ResultSender>)>,
-        mut ack_rx: mpsc::Receiver<orb_messages::mcu_main::Ack>,
+        mut ack_rx: mpsc::Receiver<Ack>,
         output_tx: broadcast::Sender<I::Output>,
+        ack_policies: AckPolicies,
     ) -> Result<()> {
         let mut counter: u16 = 0;
         loop {
@@ -80,6 +171,7 @@ impl<I: Interface> Can<I> {
                 Either::Left((None, _)) | Either::Right((None, _)) => break,
                 Either::Left((Some((input, completion_tx)), _)) => {
                     let mut completion_result = Ok(());
+                    let ack_timeout = I::ack_policy(&input, &ack_policies).timeout;
                     let ack_number = create_ack(counter);
                     counter += 1;
                     if let Some(message) = I::input_to_message(&input, ack_number) {
@@ -87,7 +179,8 @@ impl<I: Interface> Can<I> {
                         let time_start = std::time::Instant::now();
                         'ack_number_match: loop {
                             // decrease timeout each iteration
-                            let time_until_timeout = TIMEOUT - time_start.elapsed();
+                            let time_until_timeout =
+                                ack_timeout.saturating_sub(time_start.elapsed());
                             match timeout(time_until_timeout, ack_rx.next()).await {
                                 Ok(Some(ack)) => {
                                     if ack_number != ack.ack_number {
@@ -103,26 +196,17 @@ impl<I: Interface> Can<I> {
                                                  {}.\nMessage: {}\nDiscarding acknowledge..",
                                                 ack_number,
                                                 ack.ack_number,
-                                                orb_messages::mcu_main::ack::ErrorCode::try_from(
-                                                    ack.error
-                                                )
-                                                .map_or_else(
-                                                    |_| ack.error.to_string(),
-                                                    |error| error.to_string()
-                                                )
+                                                I::ack_error_name(ack.error)
+                                                    .unwrap_or_else(|| ack.error.to_string())
                                             );
                                         }
                                         continue 'ack_number_match;
-                                    } else if ack.error
-                                        == orb_messages::mcu_main::ack::ErrorCode::Success as i32
-                                    {
+                                    } else if ack.error == I::ACK_SUCCESS {
                                         #[allow(let_underscore_drop)]
                                         let _ =
                                             output_tx.send(I::success_ack_output_from_input(input));
                                     // TODO: return Error on MCU Errors and add better Error handling for the callers (f.e. on arguments out of range)
-                                    } else if let Ok(error) =
-                                        orb_messages::mcu_main::ack::ErrorCode::try_from(ack.error)
-                                    {
+                                    } else if let Some(error) = I::ack_error_name(ack.error) {
                                         tracing::error!(
                                             "MCU error: {error}, original message: {message:#?}"
                                         );
@@ -145,7 +229,8 @@ impl<I: Interface> Can<I> {
                                          number: {}",
                                         ack_number
                                     );
-                                    completion_result = Err(Error::msg("µC Timeout"));
+                                    completion_result =
+                                        Err(AckTimeout { timeout: ack_timeout }.into());
                                 }
                             }
                             // Default is to not match next incoming ack_number
@@ -162,85 +247,94 @@ impl<I: Interface> Can<I> {
         Ok(())
     }
 
-    async fn handle_output(
-        mut mcu_rx: tokio::sync::mpsc::Receiver<orb_messages::mcu_main::mcu_to_jetson::Payload>,
+    /// Dispatches the messages received from the microcontroller. Shared with
+    /// the [UART transport](super::uart).
+    pub(super) async fn handle_output(
+        mut mcu_rx: tokio::sync::mpsc::Receiver<I::Payload>,
         output_tx: broadcast::Sender<I::Output>,
-        mut ack_tx: mpsc::Sender<orb_messages::mcu_main::Ack>,
+        mut ack_tx: mpsc::Sender<Ack>,
     ) -> Result<()> {
         let mut nmea_parser = NmeaParser::new();
         let mut nmea_prev_part = None;
-        while let Some(output) = mcu_rx.recv().await {
-            match output {
-                orb_messages::mcu_main::mcu_to_jetson::Payload::Ack(ack) => {
-                    ack_tx.send(ack).await?;
-                }
-                message => {
-                    if let Some(output) =
-                        I::output_from_message(message, &mut nmea_parser, &mut nmea_prev_part)
-                    {
-                        #[allow(let_underscore_drop)]
-                        let _ = output_tx.send(output);
-                    }
-                }
+        while let Some(message) = mcu_rx.recv().await {
+            if let Some(ack) = I::payload_to_ack(&message) {
+                ack_tx.send(ack).await?;
+            } else if let Some(output) =
+                I::output_from_message(message, &mut nmea_parser, &mut nmea_prev_part)
+            {
+                #[allow(let_underscore_drop)]
+                let _ = output_tx.send(output);
             }
         }
         Ok(())
     }
 
     fn async_tx(
-        socket: fd::Tx,
-    ) -> tokio::sync::mpsc::Sender<orb_messages::mcu_main::mcu_message::Message> {
+        socket: fd::AsyncTx,
+        interface: String,
+        protocol: Protocol,
+    ) -> tokio::sync::mpsc::Sender<I::Message> {
         let (tx, mut rx) = tokio::sync::mpsc::channel(ASYNC_TX_CAPACITY);
-        spawn_named_thread("mcu-tx", move || {
-            while let Some(message) = rx.blocking_recv() {
-                let message = orb_messages::mcu_main::McuMessage {
-                    version: I::PROTOCOL_VERSION,
-                    message: Some(message),
-                };
-                let bytes = message.encode_length_delimited_to_vec();
-                socket
-                    .send(I::CAN_ADDRESS, &bytes)
-                    .expect("failed to write bytes to the CAN FD socket");
+        task::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let bytes = encode_message::<I>(message, &protocol);
+                if let Some(recorder) = TRACE.get() {
+                    recorder.record_sent(&interface, I::CAN_ADDRESS, &bytes);
+                }
+                if let Err(err) = socket.send(I::CAN_ADDRESS, &bytes).await {
+                    // Sending fails while the controller is in the bus-off
+                    // state, the receiver task restarts the interface.
+                    tracing::error!(
+                        "Error sending to CAN socket ({:?}): {err:?}",
+                        socket.bus_state()
+                    );
+                    dd_incr!("main.count.global.mcu_can.send_error");
+                }
             }
         });
         tx
     }
 
     fn async_rx(
-        socket: fd::Rx,
-    ) -> tokio::sync::mpsc::Receiver<orb_messages::mcu_main::mcu_to_jetson::Payload> {
+        socket: fd::AsyncRx,
+        interface: String,
+        protocol: Protocol,
+    ) -> tokio::sync::mpsc::Receiver<I::Payload> {
         let (tx, rx) = tokio::sync::mpsc::channel(ASYNC_RX_CAPACITY);
-        spawn_named_thread("mcu-rx", move || {
+        task::spawn(async move {
+            let mut bus_state = BusState::ErrorActive;
+            let mut last_restart: Option<Instant> = None;
+            // Deadline to retry a failed restart at.
+            let mut restart_retry: Option<Instant> = None;
             loop {
-                match socket.recv() {
+                let received = match restart_retry {
+                    Some(deadline) => {
+                        tokio::select! {
+                            received = socket.recv() => received,
+                            () = sleep_until(deadline.into()) => {
+                                last_restart = Some(Instant::now());
+                                restart_retry = restart(&socket, &interface).await;
+                                if restart_retry.is_none() {
+                                    bus_state = BusState::ErrorActive;
+                                }
+                                continue;
+                            }
+                        }
+                    }
+                    None => socket.recv().await,
+                };
+                match received {
                     Ok(frame) => {
+                        if let Some(recorder) = TRACE.get() {
+                            recorder.record_received(&interface, &frame);
+                        }
                         if frame.can_id != CAN_FD_ADDR_JETSON {
                             continue;
                         }
                         let data = &frame.data[0..usize::from(frame.len)];
-                        match orb_messages::mcu_main::McuMessage::decode_length_delimited(data) {
-                            Ok(orb_messages::mcu_main::McuMessage { version, .. })
-                                if version != I::PROTOCOL_VERSION =>
-                            {
-                                tracing::warn!("Received protobuf of unsupported version");
-                            }
-                            Ok(orb_messages::mcu_main::McuMessage {
-                                version: _,
-                                message:
-                                    Some(orb_messages::mcu_main::mcu_message::Message::MMessage(
-                                        orb_messages::mcu_main::McuToJetson {
-                                            payload: Some(payload),
-                                        },
-                                    )),
-                            }) => match tx.blocking_send(payload) {
-                                Ok(()) => {}
-                                Err(_) => break,
-                            },
-                            Ok(other) => {
-                                tracing::warn!("Received unhandled payload from MCU: {:#?}", other);
-                            }
-                            Err(err) => {
-                                tracing::error!("Failed to decode protobuf from MCU: {err}");
+                        if let Some(payload) = decode_message::<I>(data, &protocol) {
+                            if tx.send(payload).await.is_err() {
+                                break;
                             }
                         }
                     }
@@ -248,8 +342,42 @@ impl<I: Interface> Can<I> {
                         // That could be an ISO-TP frame.
                         continue;
                     }
+                    Err(fd::RecvError::Bus(error_frame)) => {
+                        if let Some(recorder) = TRACE.get() {
+                            recorder.record_error(&interface, &error_frame);
+                        }
+                        let new_bus_state = socket.bus_state();
+                        if new_bus_state != bus_state {
+                            tracing::warn!(
+                                "CAN bus state changed from {bus_state:?} to {new_bus_state:?}: \
+                                 {error_frame:?}"
+                            );
+                            dd_incr!(
+                                "main.count.global.mcu_can.bus_state",
+                                &format!("state:{new_bus_state:?}")
+                            );
+                            bus_state = new_bus_state;
+                        }
+                        if bus_state == BusState::BusOff
+                            && restart_retry.is_none()
+                            && last_restart
+                                .map_or(true, |t| t.elapsed() >= BUS_OFF_RESTART_INTERVAL)
+                        {
+                            last_restart = Some(Instant::now());
+                            restart_retry = restart(&socket, &interface).await;
+                            if restart_retry.is_none() {
+                                bus_state = BusState::ErrorActive;
+                            }
+                        }
+                    }
+                    Err(fd::RecvError::Io(err)) if err.raw_os_error() == Some(libc::ENETDOWN) => {
+                        // The interface is being restarted.
+                        tracing::debug!("CAN interface {interface} is down");
+                        sleep(RECV_ERROR_BACKOFF).await;
+                    }
                     Err(err) => {
                         tracing::error!("Error receiving from CAN socket: {err:?}");
+                        sleep(RECV_ERROR_BACKOFF).await;
                     }
                 }
             }
@@ -257,3 +385,46 @@ impl<I: Interface> Can<I> {
         rx
     }
 }
+
+/// Restarts the CAN interface after a bus-off. Returns the deadline to retry
+/// at if the restart failed.
+async fn restart(socket: &fd::AsyncRx, interface: &str) -> Option<Instant> {
+    tracing::warn!("Restarting CAN interface {interface} after bus-off");
+    dd_incr!("main.count.global.mcu_can.restart");
+    let name = interface.to_owned();
+    match task::spawn_blocking(move || fd::restart(&name)).await {
+        Ok(Ok(())) => {
+            socket.reset_bus_state();
+            return None;
+        }
+        Ok(Err(err)) => tracing::error!("Couldn't restart CAN interface {interface}: {err}"),
+        Err(err) => tracing::error!("CAN interface {interface} restart task failed: {err}"),
+    }
+    Some(Instant::now() + BUS_OFF_RESTART_INTERVAL)
+}
+
+/// Encodes a message in the protocol envelope.
+pub(super) fn encode_message<I: Interface>(message: I::Message, protocol: &Protocol) -> Vec<u8> {
+    I::encode_message(message, protocol.encoding_version())
+}
+
+/// Decodes a message from the microcontroller. Returns `None` if the message
+/// can't be handled.
+pub(super) fn decode_message<I: Interface>(data: &[u8], protocol: &Protocol) -> Option<I::Payload> {
+    match I::decode_message(data) {
+        Ok((version, _)) if !protocol.accept(version) => {
+            tracing::warn!("Received protobuf of unsupported version {version}");
+            dd_incr!("main.count.global.mcu_protocol.dropped");
+            None
+        }
+        Ok((_, Some(payload))) => Some(payload),
+        Ok((version, None)) => {
+            tracing::warn!("Received unhandled message from MCU (protocol version {version})");
+            None
+        }
+        Err(err) => {
+            tracing::error!("Failed to decode protobuf from MCU: {err}");
+            None
+        }
+    }
+}
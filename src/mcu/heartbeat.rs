@@ -0,0 +1,141 @@
+//! Jetson heartbeat to the main MCU.
+//!
+//! orb-core sends [`Input::Heartbeat`] to the main MCU every
+//! [`MCU_HEARTBEAT_PERIOD`]. Each heartbeat carries
+//! [`MCU_HEARTBEAT_TIMEOUT`], and the firmware is expected to escalate as
+//! follows when no heartbeat arrives in time:
+//!
+//! 1. No heartbeat for the timeout: the MCU logs the event and asks the Jetson
+//!    for a graceful shutdown.
+//! 2. Still no heartbeat after another timeout: the MCU power-cycles the
+//!    Jetson.
+//!
+//! The firmware doesn't start the countdown before the first heartbeat, so
+//! orb-core versions without heartbeat support are unaffected.
+//!
+//! On the Jetson side, [`Monitor`] tracks the heartbeat acknowledges. A missed
+//! acknowledge means either the CAN bus or the MCU is unhealthy, and in that
+//! case the MCU can't be relied upon to reset a hung Jetson, so it is reported.
+
+use super::{main::Input, ResultSender};
+use crate::{
+    consts::{MCU_HEARTBEAT_MISSED_ACKS_ALERT, MCU_HEARTBEAT_PERIOD},
+    dd_gauge, dd_incr,
+};
+use futures::{
+    channel::{mpsc, oneshot},
+    prelude::*,
+};
+use std::time::Instant;
+use tokio::time;
+
+/// Tracks heartbeat sequence numbers and missed acknowledges.
+#[derive(Debug, Default)]
+pub struct Monitor {
+    seq: u32,
+    missed: u32,
+    last_ack: Option<Instant>,
+}
+
+/// Outcome of a heartbeat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// The heartbeat was acknowledged.
+    Acked,
+    /// The heartbeat was acknowledged after a number of missed acknowledges.
+    Recovered {
+        /// Number of consecutive missed acknowledges before the recovery.
+        missed: u32,
+    },
+    /// The heartbeat was not acknowledged.
+    Missed {
+        /// Number of consecutive missed acknowledges.
+        consecutive: u32,
+    },
+}
+
+impl Monitor {
+    /// Returns the sequence number for the next heartbeat.
+    pub fn next_seq(&mut self) -> u32 {
+        self.seq = self.seq.wrapping_add(1);
+        self.seq
+    }
+
+    /// Records the outcome of the latest heartbeat.
+    pub fn record(&mut self, acked: bool, now: Instant) -> Status {
+        if acked {
+            self.last_ack = Some(now);
+            match std::mem::take(&mut self.missed) {
+                0 => Status::Acked,
+                missed => Status::Recovered { missed },
+            }
+        } else {
+            self.missed += 1;
+            Status::Missed { consecutive: self.missed }
+        }
+    }
+
+    /// Returns the time of the last acknowledged heartbeat.
+    #[must_use]
+    pub fn last_ack(&self) -> Option<Instant> {
+        self.last_ack
+    }
+}
+
+/// Sends heartbeats to the main MCU until the input channel is closed.
+pub async fn run(mut input_tx: mpsc::Sender<(Input, Option<ResultSender>)>) {
+    let mut monitor = Monitor::default();
+    let mut interval = time::interval(MCU_HEARTBEAT_PERIOD);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let seq = monitor.next_seq();
+        let (completion_tx, completion_rx) = oneshot::channel();
+        if input_tx.send((Input::Heartbeat(seq), Some(completion_tx))).await.is_err() {
+            break;
+        }
+        let acked = matches!(completion_rx.await, Ok(Ok(())));
+        match monitor.record(acked, Instant::now()) {
+            Status::Acked => {}
+            Status::Recovered { missed } => {
+                tracing::info!("MCU heartbeat acknowledged again after {missed} missed");
+            }
+            Status::Missed { consecutive } => {
+                dd_incr!("main.count.global.mcu_heartbeat.missed_ack");
+                dd_gauge!("main.gauge.global.mcu_heartbeat.missed_acks", consecutive.to_string());
+                if consecutive >= MCU_HEARTBEAT_MISSED_ACKS_ALERT {
+                    let last_ack = monitor.last_ack().map_or_else(
+                        || "never".to_owned(),
+                        |last_ack| format!("{:?} ago", last_ack.elapsed()),
+                    );
+                    tracing::error!(
+                        "MCU heartbeat #{seq} not acknowledged, {consecutive} consecutive misses, \
+                         last acknowledge: {last_ack}; the MCU may be unable to reset a hung \
+                         Jetson"
+                    );
+                } else {
+                    tracing::warn!("MCU heartbeat #{seq} not acknowledged");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monitor() {
+        let now = Instant::now();
+        let mut monitor = Monitor::default();
+        assert_eq!(monitor.next_seq(), 1);
+        assert_eq!(monitor.next_seq(), 2);
+        assert_eq!(monitor.record(true, now), Status::Acked);
+        assert_eq!(monitor.record(false, now), Status::Missed { consecutive: 1 });
+        assert_eq!(monitor.record(false, now), Status::Missed { consecutive: 2 });
+        assert_eq!(monitor.record(true, now), Status::Recovered { missed: 2 });
+        assert_eq!(monitor.record(false, now), Status::Missed { consecutive: 1 });
+        assert_eq!(monitor.last_ack(), Some(now));
+    }
+}
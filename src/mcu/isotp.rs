@@ -0,0 +1,76 @@
+//! ISO-TP MCU interface.
+//!
+//! Bulk transfers, like the firmware image blocks, don't fit in a single CAN
+//! FD frame and are segmented by the kernel ISO-TP stack instead. Messages use
+//! the same protocol envelope as the [CAN interface](super::can), and the
+//! acknowledges arrive on the same ISO-TP channel. The interface is blocking
+//! and, unlike the CAN interface, fails the send on an error acknowledge.
+
+use super::{can, protocol::Protocol, Interface};
+use eyre::{bail, eyre, Result, WrapErr};
+use orb_can::isotp;
+use std::{
+    io::{self, Read, Write},
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+/// Flow control block size. Zero lets the sender send all consecutive frames
+/// without waiting.
+const BLOCK_SIZE: u8 = 0;
+const TIMEOUT: Duration = Duration::from_secs(1);
+const READ_BUFFER_SIZE: usize = 4096;
+
+/// ISO-TP interface.
+pub struct IsoTp<I: Interface> {
+    socket: isotp::Socket,
+    protocol: Protocol,
+    counter: u16,
+    _interface: PhantomData<I>,
+}
+
+impl<I: Interface> IsoTp<I> {
+    /// Opens an ISO-TP channel to the microcontroller on the CAN network
+    /// interface `interface`.
+    pub fn open(interface: &str, protocol: Protocol) -> Result<Self> {
+        let mut socket = isotp::Socket::new(BLOCK_SIZE)?;
+        socket
+            .bind(interface, I::ISOTP_TX_ID, I::ISOTP_RX_ID)
+            .wrap_err_with(|| format!("binding ISO-TP socket on {interface}"))?;
+        socket.set_read_timeout(TIMEOUT)?;
+        Ok(Self { socket, protocol, counter: 0, _interface: PhantomData })
+    }
+
+    /// Sends a message to the microcontroller and waits for the acknowledge.
+    pub fn send(&mut self, input: &I::Input) -> Result<()> {
+        let ack_number = can::create_ack(self.counter);
+        self.counter = self.counter.wrapping_add(1);
+        let message = I::input_to_message(input, ack_number)
+            .ok_or_else(|| eyre!("input has no protocol message"))?;
+        self.socket.write_all(&can::encode_message::<I>(message, &self.protocol))?;
+        let deadline = Instant::now() + TIMEOUT;
+        let mut buf = [0; READ_BUFFER_SIZE];
+        while Instant::now() < deadline {
+            let len = match self.socket.read(&mut buf) {
+                Ok(len) => len,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.into()),
+            };
+            let Some(ack) = can::decode_message::<I>(&buf[..len], &self.protocol)
+                .as_ref()
+                .and_then(I::payload_to_ack)
+            else {
+                continue;
+            };
+            if ack.ack_number != ack_number {
+                continue;
+            }
+            if ack.error == I::ACK_SUCCESS {
+                return Ok(());
+            }
+            let error = I::ack_error_name(ack.error).unwrap_or_else(|| ack.error.to_string());
+            bail!("MCU error: {error}");
+        }
+        bail!("Timed out waiting response from µC with acknowledge number: {ack_number}")
+    }
+}
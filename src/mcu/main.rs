@@ -1,19 +1,43 @@
 //! Main microcontroller interface.
 
-use super::{can::Can, Interface, Mcu, ResultSender};
+pub mod brownout;
+pub mod led_frames;
+
+use super::{
+    can::{self, Can},
+    heartbeat,
+    protocol::Protocol,
+    route,
+    uart::{self, Uart},
+    white_led, Ack, AckPolicies, AckPolicy, Interface, Mcu, MessageClass, ResultSender, Routing,
+    Transport,
+};
 use crate::{
-    consts::{DEFAULT_USER_LED_PULSING_PERIOD, DEFAULT_USER_LED_PULSING_SCALE},
+    consts::{
+        DEFAULT_USER_LED_PULSING_PERIOD, DEFAULT_USER_LED_PULSING_SCALE, MCU_HEARTBEAT_TIMEOUT,
+        WHITE_LED_DERATING_PERIOD,
+    },
     time_series::TimeSeries,
 };
-use eyre::Result;
+use eyre::{eyre, Result, WrapErr};
 use futures::{channel::mpsc, prelude::*, stream::Fuse};
 use libc::CAN_EFF_FLAG;
 use nmea_parser::NmeaParser;
 use orb_messages::mcu_main::MirrorAngleType;
+use prost::{DecodeError, Message as _};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::fmt::{self, Debug};
-use tokio::sync::broadcast;
+use std::{
+    fmt::{self, Debug},
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{broadcast, broadcast::error::RecvError},
+    task, time,
+};
 use tokio_stream::wrappers::BroadcastStream;
 
 /// Number of ring LEDs.
@@ -65,6 +89,11 @@ pub struct Jetson {
     input_tx: mpsc::Sender<(Input, Option<ResultSender>)>,
     output_tx: broadcast::Sender<Output>,
     output_rx: Fuse<BroadcastStream<Output>>,
+    white_led_derating: Arc<Mutex<white_led::Derating>>,
+    led_frames: Arc<Mutex<led_frames::Filters>>,
+    protocol: Protocol,
+    uart_tx: Option<mpsc::Sender<(Input, Option<ResultSender>)>>,
+    ack_policies: AckPolicies,
 }
 
 /// Main microcontroller interface which does nothing.
@@ -134,6 +163,21 @@ pub enum Input {
     TofCalibration(u16),
     /// Shutdown the uC after the delay.
     Shutdown(u8),
+    /// Power cycle the Jetson after the delay in seconds.
+    Reboot(u8),
+    /// Firmware image block for the secondary slot.
+    FirmwareBlock {
+        /// Block number, starting from zero.
+        number: u32,
+        /// Total number of blocks in the image.
+        count: u32,
+        /// Block data.
+        data: Vec<u8>,
+    },
+    /// Check the CRC-32 of the firmware image in the secondary slot.
+    FirmwareImageCheck(u32),
+    /// Activate the firmware in the secondary slot and reboot the uC.
+    FirmwareActivateSecondary,
     /// Set liquid lens current.
     LiquidLens(Option<i16>),
     /// Frame rate in frames per second.
@@ -183,6 +227,9 @@ pub enum Input {
     IrEyeCameraMirrorSweepValuesPolynomial(MirrorSweepPolynomial),
     /// Perform a mirror sweep.
     PerformIrEyeCameraMirrorSweep,
+    /// Jetson liveness heartbeat with a sequence number. See
+    /// [`heartbeat`](super::heartbeat) for the MCU-side escalation.
+    Heartbeat(u32),
 }
 
 /// Message received from the Main microcontroller.
@@ -236,6 +283,23 @@ pub enum Output {
     TofDistance(u32),
     /// State of hardware component
     HardwareDiag(orb_messages::mcu_main::HardwareDiagnostic),
+    /// IMU sample.
+    Imu(ImuSample),
+    /// Diamond cone attachment state change. `true` if attached.
+    ConePresent(bool),
+    /// Imminent shutdown announced by the MCU.
+    Shutdown(orb_messages::mcu_main::ShutdownScheduled),
+}
+
+/// IMU sample.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImuSample {
+    /// MCU sample time in microseconds.
+    pub timestamp_us: u64,
+    /// Acceleration along the x, y, and z axes in raw sensor units.
+    pub accel: [i32; 3],
+    /// Angular rate around the x, y, and z axes in raw sensor units.
+    pub gyro: [i32; 3],
 }
 
 /// This message provides coefficients for evaluating the formula:
@@ -459,6 +523,45 @@ pub struct Version {
     commit_hash: u32,
 }
 
+impl Version {
+    /// Creates a new version from its components.
+    #[must_use]
+    pub const fn new(major: u32, minor: u32, patch: u32, commit_hash: u32) -> Self {
+        Self { major, minor, patch, commit_hash }
+    }
+
+    /// Returns the version as a single number, ordered by major, minor, and
+    /// patch components. Used for the Secure Element anti-rollback counter.
+    #[must_use]
+    pub fn counter_value(&self) -> u64 {
+        const MASK: u64 = (1 << 20) - 1;
+        (u64::from(self.major) & MASK) << 40
+            | (u64::from(self.minor) & MASK) << 20
+            | u64::from(self.patch) & MASK
+    }
+
+    /// Returns `true` if both versions are the same release, ignoring the
+    /// commit hash.
+    #[must_use]
+    pub fn same_release(&self, other: &Self) -> bool {
+        (self.major, self.minor, self.patch) == (other.major, other.minor, other.patch)
+    }
+}
+
+impl FromStr for Version {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.trim().trim_start_matches('v').splitn(3, '.').map(str::parse);
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch))) => {
+                Ok(Self { major, minor, patch, commit_hash: 0 })
+            }
+            _ => Err(eyre!("invalid MCU firmware version: {s:?}")),
+        }
+    }
+}
+
 impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "v{}.{}.{}/{}", self.major, self.minor, self.patch, self.commit_hash)
@@ -493,10 +596,19 @@ impl From<&orb_messages::mcu_main::Versions> for Versions {
 impl Interface for Main {
     type Input = Input;
     type Log = Log;
+    type Message = orb_messages::mcu_main::mcu_message::Message;
     type Output = Output;
+    type Payload = orb_messages::mcu_main::mcu_to_jetson::Payload;
 
+    const ACK_SUCCESS: i32 = orb_messages::mcu_main::ack::ErrorCode::Success as i32;
     const CAN_ADDRESS: u32 = 0x01 | CAN_EFF_FLAG;
+    // ISO-TP identifiers are `0x800 | source << 4 | destination`, with 8 for
+    // the Jetson and 1 for the main MCU.
+    const ISOTP_RX_ID: u32 = 0x818;
+    const ISOTP_TX_ID: u32 = 0x881;
     const PROTOCOL_VERSION: i32 = orb_messages::mcu_main::Version::Version0 as i32;
+    const SUPPORTED_PROTOCOL_VERSIONS: &'static [i32] =
+        &[orb_messages::mcu_main::Version::Version0 as i32];
 
     fn log_input(log: &mut Log, input: &Input) {
         match *input {
@@ -554,6 +666,10 @@ impl Interface for Main {
             | Input::TofTiming(..)
             | Input::TofCalibration(..)
             | Input::Shutdown(..)
+            | Input::Reboot(..)
+            | Input::FirmwareBlock { .. }
+            | Input::FirmwareImageCheck(_)
+            | Input::FirmwareActivateSecondary
             | Input::Version
             | Input::Temperature(..)
             | Input::OperatorLedBrightness(_)
@@ -563,16 +679,14 @@ impl Interface for Main {
             | Input::PerformIrEyeCameraFocusSweep
             | Input::IrEyeCameraMirrorSweepValuesPolynomial(_)
             | Input::PerformIrEyeCameraMirrorSweep
+            | Input::Heartbeat(_)
             | Input::WhiteLedBrightness(_)
             | Input::ConeLedPattern(_) => {}
         }
     }
 
     #[allow(clippy::too_many_lines)]
-    fn input_to_message(
-        input: &Input,
-        ack_number: u32,
-    ) -> Option<orb_messages::mcu_main::mcu_message::Message> {
+    fn input_to_message(input: &Input, ack_number: u32) -> Option<Self::Message> {
         use orb_messages::mcu_main::jetson_to_mcu::Payload as P;
         let payload = match input {
             Input::IrLed(ir_led) => {
@@ -700,6 +814,22 @@ impl Interface for Main {
                     delay_s: u32::from(*delay),
                 })
             }
+            Input::Reboot(delay) => {
+                P::Reboot(orb_messages::mcu_main::RebootWithDelay { delay: u32::from(*delay) })
+            }
+            Input::FirmwareBlock { number, count, data } => {
+                P::DfuBlock(orb_messages::mcu_main::FirmwareUpdateData {
+                    block_number: *number,
+                    block_count: *count,
+                    image_block: data.clone(),
+                })
+            }
+            Input::FirmwareImageCheck(crc32) => {
+                P::FwImageCheck(orb_messages::mcu_main::FirmwareImageCheck { crc32: *crc32 })
+            }
+            Input::FirmwareActivateSecondary => P::FwImageSecondaryActivate(
+                orb_messages::mcu_main::FirmwareActivateSecondary { force_permanent: false },
+            ),
             Input::Temperature(_temperature) => {
                 // P::Temperature(orb_messages::mcu_main::Temperature {
                 //     source: orb_messages::mcu_main::temperature::TemperatureSource::Jetson as i32,
@@ -898,6 +1028,8 @@ impl Interface for Main {
                     },
                 )
             }
+            // The pinned orb-messages revision has no ToF timing budget or
+            // calibration payloads.
             Input::TofTiming(_)
             | Input::TofCalibration(_) => {
                 return None;
@@ -960,6 +1092,13 @@ impl Interface for Main {
                     orb_messages::mcu_main::PerformIrEyeCameraMirrorSweep {},
                 )
             }
+            Input::Heartbeat(_seq) => {
+                // The sequence number is only tracked on the Jetson side; the
+                // acknowledge is matched by `ack_number`.
+                P::Heartbeat(orb_messages::mcu_main::Heartbeat {
+                    timeout_seconds: MCU_HEARTBEAT_TIMEOUT.as_secs().try_into().unwrap_or(u32::MAX),
+                })
+            }
         };
         Some(orb_messages::mcu_main::mcu_message::Message::JMessage(
             orb_messages::mcu_main::JetsonToMcu { ack_number, payload: Some(payload) },
@@ -967,7 +1106,7 @@ impl Interface for Main {
     }
 
     fn output_from_message(
-        message: orb_messages::mcu_main::mcu_to_jetson::Payload,
+        message: Self::Payload,
         nmea_parser: &mut NmeaParser,
         nmea_prev_part: &mut Option<(u32, String)>,
     ) -> Option<Output> {
@@ -1020,28 +1159,211 @@ impl Interface for Main {
             P::FrontAls(als) => Some(Output::AmbientLight(als)),
             P::FatalError(error) => Some(Output::FatalError(error)),
             P::HardwareDiag(diag) => Some(Output::HardwareDiag(diag)),
-            P::Ack(_)
-            | P::ImuData(_)
-            | P::Hardware(_)
-            | P::ConePresent(_)
-            | P::MemfaultEvent(_)
-            | P::Shutdown(_) => None,
+            P::ImuData(imu) => Some(Output::Imu(ImuSample {
+                timestamp_us: imu.timestamp_us.into(),
+                accel: imu.accel.map_or([0; 3], |accel| [accel.x, accel.y, accel.z]),
+                gyro: imu.gyro.map_or([0; 3], |gyro| [gyro.x, gyro.y, gyro.z]),
+            })),
+            P::ConePresent(orb_messages::mcu_main::ConePresent { cone_present }) => {
+                Some(Output::ConePresent(cone_present))
+            }
+            P::Shutdown(shutdown) => Some(Output::Shutdown(shutdown)),
+            P::Ack(_) | P::Hardware(_) | P::MemfaultEvent(_) => None,
+        }
+    }
+
+    fn encode_message(message: Self::Message, version: i32) -> Vec<u8> {
+        orb_messages::mcu_main::McuMessage { version, message: Some(message) }
+            .encode_length_delimited_to_vec()
+    }
+
+    fn decode_message(data: &[u8]) -> Result<(i32, Option<Self::Payload>), DecodeError> {
+        let orb_messages::mcu_main::McuMessage { version, message } =
+            orb_messages::mcu_main::McuMessage::decode_length_delimited(data)?;
+        let payload = match message {
+            Some(orb_messages::mcu_main::mcu_message::Message::MMessage(
+                orb_messages::mcu_main::McuToJetson { payload },
+            )) => payload,
+            _ => None,
+        };
+        Ok((version, payload))
+    }
+
+    fn payload_to_ack(payload: &Self::Payload) -> Option<Ack> {
+        match payload {
+            orb_messages::mcu_main::mcu_to_jetson::Payload::Ack(ack) => {
+                Some(Ack { ack_number: ack.ack_number, error: ack.error })
+            }
+            _ => None,
         }
     }
 
+    fn ack_error_name(error: i32) -> Option<String> {
+        orb_messages::mcu_main::ack::ErrorCode::try_from(error).ok().map(|error| error.to_string())
+    }
+
     fn success_ack_output_from_input(input: Input) -> Output {
         Output::SuccessAck(input)
     }
+
+    fn input_class(input: &Input) -> MessageClass {
+        match input {
+            Input::RingLeds(_)
+            | Input::CenterLeds(_)
+            | Input::OperatorLeds(_)
+            | Input::UserLedBrightness(_)
+            | Input::UserLedPattern(_)
+            | Input::OperatorLedBrightness(_)
+            | Input::OperatorLedPattern(_)
+            | Input::ConeLedPattern(_)
+            | Input::WhiteLedBrightness(_) => MessageClass::Led,
+            Input::Version | Input::VoltageRequest | Input::ValueGet(_) => MessageClass::Query,
+            Input::PerformMirrorHoming(..)
+            | Input::PerformIrEyeCameraFocusSweep
+            | Input::PerformIrEyeCameraMirrorSweep => MessageClass::Motion,
+            _ => MessageClass::Control,
+        }
+    }
+
+    fn ack_policy(input: &Input, policies: &AckPolicies) -> AckPolicy {
+        match input {
+            // A missed heartbeat is accounted by the heartbeat monitor.
+            Input::Heartbeat(_) => AckPolicy { retries: 0, ..policies.control },
+            // The MCU reboots into the new firmware, possibly before the
+            // acknowledge gets through.
+            Input::FirmwareActivateSecondary => {
+                AckPolicy { timeout: Duration::from_secs(1), ..AckPolicy::NO_RETRY }
+            }
+            _ => policies.get(Self::input_class(input)),
+        }
+    }
 }
 
 impl Jetson {
     /// Spawns a new microcontroller interface.
     pub fn spawn() -> Result<Self> {
-        let (input_tx, input_rx) = mpsc::channel(INPUT_CAPACITY);
+        Self::spawn_on(can::CAN_SOCKET)
+    }
+
+    /// Spawns a new microcontroller interface on the CAN network interface
+    /// `interface`.
+    pub fn spawn_on(interface: &str) -> Result<Self> {
+        Self::spawn_routed(interface, Routing::default(), AckPolicies::default(), uart::UART_DEVICE)
+    }
+
+    /// Spawns a new microcontroller interface, sending each message class
+    /// over the transport selected by `routing` with the acknowledge policy
+    /// selected by `ack_policies`. The `uart_device` is opened only if some
+    /// message class is routed over it, and CAN is used if it can't be opened.
+    pub fn spawn_with_routing(
+        routing: Routing,
+        ack_policies: AckPolicies,
+        uart_device: &str,
+    ) -> Result<Self> {
+        Self::spawn_routed(can::CAN_SOCKET, routing, ack_policies, uart_device)
+    }
+
+    fn spawn_routed(
+        interface: &str,
+        mut routing: Routing,
+        ack_policies: AckPolicies,
+        uart_device: &str,
+    ) -> Result<Self> {
+        let (mut input_tx, input_rx) = mpsc::channel(INPUT_CAPACITY);
         let (output_tx, output_rx) = broadcast::channel(OUTPUT_CAPACITY);
         let output_rx = BroadcastStream::new(output_rx).fuse();
-        Can::<Main>::spawn(input_rx, output_tx.clone())?;
-        Ok(Self { log: None, input_tx, output_tx, output_rx })
+        let protocol = Protocol::new(Main::PROTOCOL_VERSION, Main::SUPPORTED_PROTOCOL_VERSIONS);
+        let (can_tx, can_rx) = mpsc::channel(INPUT_CAPACITY);
+        Can::<Main>::spawn_on(
+            interface,
+            can_rx,
+            output_tx.clone(),
+            protocol.clone(),
+            ack_policies,
+        )?;
+        let mut uart_tx = None;
+        if routing.uses(Transport::Uart) {
+            let (tx, uart_rx) = mpsc::channel(INPUT_CAPACITY);
+            match Uart::<Main>::spawn_on(
+                uart_device,
+                uart_rx,
+                output_tx.clone(),
+                protocol.clone(),
+                ack_policies,
+            ) {
+                Ok(()) => uart_tx = Some(tx),
+                Err(err) => {
+                    tracing::error!("Failed to open the MCU UART, falling back to CAN: {err:?}");
+                    routing = Routing::default();
+                }
+            }
+        }
+        tracing::info!("MCU message routing: {routing:?}");
+        task::spawn(route::<Main>(
+            input_rx,
+            routing,
+            can_tx.clone(),
+            uart_tx.clone().unwrap_or(can_tx),
+        ));
+        // Any response from the firmware completes the protocol negotiation.
+        if let Err(err) = input_tx.try_send((Input::Version, None)) {
+            tracing::error!("Failed to request the MCU firmware versions: {err}");
+        }
+        let white_led_derating = Arc::new(Mutex::new(white_led::Derating::default()));
+        task::spawn(run_white_led_derating(
+            Arc::clone(&white_led_derating),
+            input_tx.clone(),
+            output_tx.subscribe(),
+        ));
+        let led_frames = Arc::new(Mutex::new(led_frames::Filters::default()));
+        task::spawn(heartbeat::run(input_tx.clone()));
+        Ok(Self {
+            log: None,
+            input_tx,
+            output_tx,
+            output_rx,
+            white_led_derating,
+            led_frames,
+            protocol,
+            uart_tx,
+            ack_policies,
+        })
+    }
+}
+
+/// Feeds the front unit temperature telemetry to the white LED derating
+/// controller and sends the derated brightness to the MCU.
+async fn run_white_led_derating(
+    derating: Arc<Mutex<white_led::Derating>>,
+    mut input_tx: mpsc::Sender<(Input, Option<ResultSender>)>,
+    mut output_rx: broadcast::Receiver<Output>,
+) {
+    let mut interval = time::interval(WHITE_LED_DERATING_PERIOD);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let brightness = derating.lock().unwrap().tick(Instant::now());
+                if let Some(brightness) = brightness {
+                    if input_tx.send((Input::WhiteLedBrightness(brightness), None)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            output = output_rx.recv() => match output {
+                Ok(Output::Temperature(temperature))
+                    if temperature.source
+                        == orb_messages::mcu_main::temperature::TemperatureSource::FrontUnitWhiteTop
+                            as i32 =>
+                {
+                    derating
+                        .lock()
+                        .unwrap()
+                        .update_temperature(f64::from(temperature.temperature_c), Instant::now());
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+        }
     }
 }
 
@@ -1052,9 +1374,31 @@ impl Mcu<Main> for Jetson {
             input_tx: self.input_tx.clone(),
             output_tx: self.output_tx.clone(),
             output_rx: BroadcastStream::new(self.output_tx.subscribe()).fuse(),
+            white_led_derating: Arc::clone(&self.white_led_derating),
+            led_frames: Arc::clone(&self.led_frames),
+            protocol: self.protocol.clone(),
+            uart_tx: self.uart_tx.clone(),
+            ack_policies: self.ack_policies,
         })
     }
 
+    fn ack_policies(&self) -> AckPolicies {
+        self.ack_policies
+    }
+
+    fn adjust_input(&mut self, input: Input) -> Option<Input> {
+        match input {
+            Input::WhiteLedBrightness(brightness) => Some(Input::WhiteLedBrightness(
+                self.white_led_derating.lock().unwrap().request(brightness, Instant::now()),
+            )),
+            input => self.led_frames.lock().unwrap().check(&input).then_some(input),
+        }
+    }
+
+    fn input_sent(&mut self, input: &Input) {
+        self.led_frames.lock().unwrap().sent(input);
+    }
+
     fn tx(&self) -> &mpsc::Sender<(Input, Option<ResultSender>)> {
         &self.input_tx
     }
@@ -1074,6 +1418,48 @@ impl Mcu<Main> for Jetson {
     fn log_mut(&mut self) -> &mut Option<Log> {
         &mut self.log
     }
+
+    fn protocol(&self) -> Option<&Protocol> {
+        Some(&self.protocol)
+    }
+
+    fn send_uart(&mut self, input: Input) -> Result<()> {
+        let Some(input) = self.adjust_input(input) else {
+            return Ok(());
+        };
+        if let Some(log) = self.log_mut() {
+            Main::log_input(log, &input);
+        }
+        let uart_tx = self.uart_tx.as_mut().ok_or_else(|| eyre!("MCU UART is not available"))?;
+        uart_tx.try_send((input.clone(), None))?;
+        self.input_sent(&input);
+        Ok(())
+    }
+}
+
+impl Fake {
+    /// Creates a fake interface which replays the messages of the main
+    /// microcontroller recorded in the candump log at `path`. All inputs are
+    /// acknowledged immediately.
+    pub fn replay(path: &Path) -> Result<Self> {
+        let entries = orb_can::trace::open(path)
+            .wrap_err_with(|| format!("opening CAN log {}", path.display()))?
+            .collect::<Result<Vec<_>, _>>()?;
+        tracing::info!("Replaying {} CAN frames from {}", entries.len(), path.display());
+        let (input_tx, mut input_rx) = mpsc::channel(INPUT_CAPACITY);
+        let (output_tx, output_rx) = broadcast::channel(OUTPUT_CAPACITY);
+        let output_rx = BroadcastStream::new(output_rx).fuse();
+        task::spawn(async move {
+            while let Some((_, completion_tx)) = input_rx.next().await {
+                if let Some(completion_tx) = completion_tx {
+                    completion_tx.send(Ok(())).ok();
+                }
+            }
+        });
+        let protocol = Protocol::new(Main::PROTOCOL_VERSION, Main::SUPPORTED_PROTOCOL_VERSIONS);
+        Can::<Main>::replay(entries, output_tx.clone(), protocol);
+        Ok(Self { log: None, input_tx, output_tx, output_rx })
+    }
 }
 
 impl Default for Fake {
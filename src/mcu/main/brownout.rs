@@ -0,0 +1,101 @@
+//! Brownout prediction.
+//!
+//! A battery pull or a failing battery shows up in the periodic
+//! [`Voltage`](orb_messages::mcu_main::Voltage) outputs of the main MCU as a
+//! rapid drop of the battery and 12V rails. [`Predictor`] watches these rails
+//! and reports an [`Anomaly`] as soon as a rail drops by more than
+//! [`BROWNOUT_DROP_RATIO`] within [`BROWNOUT_WINDOW`], so the observer can
+//! flush its state to disk before the power is gone.
+
+use crate::consts::{BROWNOUT_COOLDOWN, BROWNOUT_DROP_RATIO, BROWNOUT_WINDOW};
+use orb_messages::mcu_main::{voltage::VoltageSource, Voltage};
+use std::{collections::VecDeque, time::Instant};
+
+/// Rails watched for a brownout.
+const RAILS: [(VoltageSource, &str); 3] = [
+    (VoltageSource::Vbat, "vbat"),
+    (VoltageSource::VbatSw, "vbat_sw"),
+    (VoltageSource::Supply12v, "supply_12v"),
+];
+
+/// Brownout predictor.
+#[derive(Debug, Default)]
+pub struct Predictor {
+    samples: [VecDeque<(Instant, i32)>; RAILS.len()],
+    last_anomaly: Option<Instant>,
+}
+
+/// Rapid voltage drop of a rail.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Anomaly {
+    /// Name of the rail.
+    pub rail: &'static str,
+    /// Highest voltage within the window in mV.
+    pub peak_mv: i32,
+    /// Latest voltage in mV.
+    pub current_mv: i32,
+}
+
+impl Predictor {
+    /// Feeds a voltage output. Returns an anomaly if the rail dropped rapidly,
+    /// at most once per [`BROWNOUT_COOLDOWN`].
+    pub fn update(&mut self, voltage: &Voltage) -> Option<Anomaly> {
+        self.update_at(Instant::now(), voltage.source, voltage.voltage_current_mv)
+    }
+
+    fn update_at(&mut self, now: Instant, source: i32, current_mv: i32) -> Option<Anomaly> {
+        let index = RAILS.iter().position(|(rail, _)| *rail as i32 == source)?;
+        let samples = &mut self.samples[index];
+        while samples.front().is_some_and(|(t, _)| now.duration_since(*t) > BROWNOUT_WINDOW) {
+            samples.pop_front();
+        }
+        samples.push_back((now, current_mv));
+        let peak_mv = samples.iter().map(|(_, mv)| *mv).max()?;
+        if peak_mv <= 0
+            || f64::from(peak_mv - current_mv) <= f64::from(peak_mv) * BROWNOUT_DROP_RATIO
+        {
+            return None;
+        }
+        if self.last_anomaly.is_some_and(|t| now.duration_since(t) < BROWNOUT_COOLDOWN) {
+            return None;
+        }
+        self.last_anomaly = Some(now);
+        Some(Anomaly { rail: RAILS[index].1, peak_mv, current_mv })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rapid_drop() {
+        let vbat = VoltageSource::Vbat as i32;
+        let start = Instant::now();
+        let mut predictor = Predictor::default();
+        assert!(predictor.update_at(start, vbat, 15_000).is_none());
+        assert!(predictor.update_at(start + Duration::from_secs(1), vbat, 14_800).is_none());
+        let anomaly = predictor.update_at(start + Duration::from_secs(2), vbat, 11_000).unwrap();
+        assert_eq!(anomaly, Anomaly { rail: "vbat", peak_mv: 15_000, current_mv: 11_000 });
+        // Cooldown.
+        assert!(predictor.update_at(start + Duration::from_secs(3), vbat, 10_000).is_none());
+        // Unwatched rail.
+        let supply_5v = VoltageSource::Supply5v as i32;
+        assert!(predictor.update_at(start, supply_5v, 5_000).is_none());
+        assert!(predictor.update_at(start, supply_5v, 1_000).is_none());
+    }
+
+    #[test]
+    fn test_slow_discharge() {
+        let vbat = VoltageSource::Vbat as i32;
+        let start = Instant::now();
+        let mut predictor = Predictor::default();
+        for i in 0..100 {
+            let t = start + BROWNOUT_WINDOW * i;
+            assert!(predictor
+                .update_at(t, vbat, 16_000 - 100 * i32::try_from(i).unwrap())
+                .is_none());
+        }
+    }
+}
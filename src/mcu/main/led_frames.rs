@@ -0,0 +1,126 @@
+//! Unchanged LED frames filter.
+//!
+//! The LED frames are sent on every animation tick even if no LED changes,
+//! e.g. during the static idle animations. A full ring frame alone is
+//! [`RING_LED_COUNT`](super::RING_LED_COUNT) × 3 bytes. [`Filter`] drops the
+//! frames equal to the previous one. Every [`LED_FRAMES_RESEND_INTERVAL`]-th
+//! unchanged frame is still sent, so the LEDs recover from a lost frame or an
+//! MCU reset. A frame is compared against the last one which was actually
+//! sent, so a frame dropped by a full queue is sent again.
+
+use super::{CenterLedsSequence, Input, OperatorLedsSequence, RingLedsSequence};
+use crate::consts::LED_FRAMES_RESEND_INTERVAL;
+
+/// Unchanged LED frames filters of the ring, center, and operator LEDs.
+#[derive(Debug, Default)]
+pub struct Filters {
+    ring: Filter<RingLedsSequence>,
+    center: Filter<CenterLedsSequence>,
+    operator: Filter<OperatorLedsSequence>,
+}
+
+/// Unchanged LED frames filter.
+#[derive(Debug)]
+pub struct Filter<T> {
+    previous: Option<T>,
+    skipped: u32,
+}
+
+impl Filters {
+    /// Returns `false` if the `input` is an unchanged LED frame which can be
+    /// dropped.
+    pub fn check(&mut self, input: &Input) -> bool {
+        match input {
+            Input::RingLeds(frame) => self.ring.check(frame),
+            Input::CenterLeds(frame) => self.center.check(frame),
+            Input::OperatorLeds(frame) => self.operator.check(frame),
+            _ => true,
+        }
+    }
+
+    /// Records the `input` LED frame which was sent.
+    pub fn sent(&mut self, input: &Input) {
+        match input {
+            Input::RingLeds(frame) => self.ring.sent(frame),
+            Input::CenterLeds(frame) => self.center.sent(frame),
+            Input::OperatorLeds(frame) => self.operator.sent(frame),
+            _ => {}
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> Filter<T> {
+    /// Returns `true` if the frame is to be sent.
+    pub fn check(&mut self, frame: &T) -> bool {
+        if self.previous.as_ref() == Some(frame) && self.skipped + 1 < LED_FRAMES_RESEND_INTERVAL {
+            self.skipped += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Records the frame which was sent.
+    pub fn sent(&mut self, frame: &T) {
+        self.skipped = 0;
+        self.previous = Some(frame.clone());
+    }
+}
+
+impl<T> Default for Filter<T> {
+    fn default() -> Self {
+        Self { previous: None, skipped: 0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::Rgb, *};
+
+    fn send(filters: &mut Filters, input: &Input) -> bool {
+        let send = filters.check(input);
+        if send {
+            filters.sent(input);
+        }
+        send
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut filters = Filters::default();
+        let mut frame = vec![Rgb(1, 2, 3, None); 4];
+        assert!(send(&mut filters, &Input::RingLeds(frame.clone())));
+        for _ in 1..LED_FRAMES_RESEND_INTERVAL {
+            assert!(!send(&mut filters, &Input::RingLeds(frame.clone())));
+        }
+        assert!(send(&mut filters, &Input::RingLeds(frame.clone())));
+        assert!(!send(&mut filters, &Input::RingLeds(frame.clone())));
+        frame[1] = Rgb(255, 0, 0, None);
+        assert!(send(&mut filters, &Input::RingLeds(frame.clone())));
+        assert!(!send(&mut filters, &Input::RingLeds(frame)));
+    }
+
+    #[test]
+    fn test_dropped_frame() {
+        let mut filters = Filters::default();
+        assert!(send(&mut filters, &Input::RingLeds(vec![Rgb(1, 2, 3, None); 4])));
+        let changed = Input::RingLeds(vec![Rgb(255, 0, 0, None); 4]);
+        // The changed frame is dropped before reaching the MCU.
+        assert!(filters.check(&changed));
+        assert!(filters.check(&changed));
+        filters.sent(&changed);
+        assert!(!filters.check(&changed));
+    }
+
+    #[test]
+    fn test_independent_leds() {
+        let mut filters = Filters::default();
+        let center = Input::CenterLeds([Rgb(1, 2, 3, None); 9]);
+        let operator = Input::OperatorLeds([Rgb(1, 2, 3, None); 5]);
+        assert!(send(&mut filters, &center));
+        assert!(send(&mut filters, &operator));
+        assert!(!send(&mut filters, &center));
+        assert!(!send(&mut filters, &operator));
+        assert!(send(&mut filters, &Input::WhiteLedBrightness(10)));
+        assert!(send(&mut filters, &Input::WhiteLedBrightness(10)));
+    }
+}
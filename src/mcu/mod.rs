@@ -3,26 +3,196 @@
 #![allow(clippy::non_ascii_literal)]
 
 pub mod can;
+pub mod heartbeat;
+pub mod isotp;
 pub mod main;
+pub mod protocol;
+pub mod sec;
+pub mod uart;
+pub mod white_led;
 
-use std::pin::Pin;
+use std::{collections::VecDeque, fmt, pin::Pin, task::Poll, time::Duration};
 
-pub use self::main::Main;
+pub use self::{main::Main, sec::Sec};
 
-use crate::ext::mpsc::SenderExt as _;
-use eyre::{Error, Result};
+use crate::{dd_incr, ext::mpsc::SenderExt as _};
+use eyre::{eyre, Error, Result};
 use futures::{
     channel::{mpsc, oneshot},
+    future,
     prelude::*,
     stream::Fuse,
 };
 use nmea_parser::NmeaParser;
+use prost::DecodeError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::time;
 use tokio_stream::wrappers::BroadcastStream;
 
-const SEND_RETRY_COUNT: u8 = 3;
+/// Maximal time an input message may wait in the transport queue on top of
+/// its acknowledge timeout, before the send gives up.
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximal number of the input messages held back for a congested transport,
+/// after which the new messages for it are rejected.
+const ROUTE_BACKLOG: usize = 100;
 
 type ResultSender = oneshot::Sender<Result<(), Error>>;
 
+/// Class of the messages sent to a microcontroller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageClass {
+    /// High-rate LED frames and patterns.
+    Led,
+    /// Requests for the microcontroller state, answered with an output
+    /// message.
+    Query,
+    /// Mirror and camera motions, which are acknowledged slowly and restarted
+    /// by a retry.
+    Motion,
+    /// Everything else: actuators, sensors, and power management.
+    Control,
+}
+
+/// Acknowledge timeout and retry policy of an input message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct AckPolicy {
+    /// Time to wait for the acknowledge of one attempt.
+    pub timeout: Duration,
+    /// Number of attempts after the first one.
+    pub retries: u8,
+    /// Delay before the first retry, doubled for each next retry.
+    pub backoff: Duration,
+}
+
+impl AckPolicy {
+    /// Policy of the messages without a specific one.
+    pub const DEFAULT: Self = Self {
+        timeout: Duration::from_millis(300),
+        retries: 3,
+        backoff: Duration::from_millis(50),
+    };
+    /// Policy of the messages superseded by the next ones, like LED frames,
+    /// which aren't worth retrying.
+    pub const NO_RETRY: Self = Self { retries: 0, ..Self::DEFAULT };
+
+    /// Returns the delay before the retry following the failed `attempt`,
+    /// counted from zero.
+    #[must_use]
+    pub fn backoff(&self, attempt: u8) -> Duration {
+        self.backoff.saturating_mul(1 << attempt.min(16))
+    }
+}
+
+/// Acknowledge timeout and retry policy per message class.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct AckPolicies {
+    /// Policy of the LED messages.
+    pub led: AckPolicy,
+    /// Policy of the query messages.
+    pub query: AckPolicy,
+    /// Policy of the motion messages.
+    pub motion: AckPolicy,
+    /// Policy of the control messages.
+    pub control: AckPolicy,
+}
+
+impl Default for AckPolicies {
+    fn default() -> Self {
+        Self {
+            // A late LED frame is superseded by the next one.
+            led: AckPolicy::NO_RETRY,
+            query: AckPolicy::DEFAULT,
+            // Retrying a motion too early restarts it.
+            motion: AckPolicy { timeout: Duration::from_secs(3), ..AckPolicy::NO_RETRY },
+            control: AckPolicy::DEFAULT,
+        }
+    }
+}
+
+impl AckPolicies {
+    /// Returns the policy of the message class.
+    #[must_use]
+    pub fn get(&self, class: MessageClass) -> AckPolicy {
+        match class {
+            MessageClass::Led => self.led,
+            MessageClass::Query => self.query,
+            MessageClass::Motion => self.motion,
+            MessageClass::Control => self.control,
+        }
+    }
+}
+
+impl MessageClass {
+    /// Returns the class name used in the Datadog tags.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Led => "led",
+            Self::Query => "query",
+            Self::Motion => "motion",
+            Self::Control => "control",
+        }
+    }
+}
+
+/// The microcontroller didn't acknowledge a message in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("µC Timeout: no acknowledge within {timeout:?}")]
+pub struct AckTimeout {
+    /// Time waited for the acknowledge.
+    pub timeout: Duration,
+}
+
+/// Acknowledge of an input message, common to the microcontroller protocols.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ack {
+    /// Acknowledge number of the input message.
+    pub ack_number: u32,
+    /// Protocol error code.
+    pub error: i32,
+}
+
+/// Link used to send messages to a microcontroller.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    /// CAN FD bus.
+    #[default]
+    Can,
+    /// UART serial line.
+    Uart,
+}
+
+/// Transport selection per message class. The messages of different classes
+/// may be reordered relative to each other. The queries and the motions share
+/// the transport of the control messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Routing {
+    /// Transport of the LED messages.
+    pub led: Transport,
+    /// Transport of the control messages.
+    pub control: Transport,
+}
+
+impl Routing {
+    /// Returns the transport for the message class.
+    #[must_use]
+    pub fn transport(&self, class: MessageClass) -> Transport {
+        match class {
+            MessageClass::Led => self.led,
+            MessageClass::Query | MessageClass::Motion | MessageClass::Control => self.control,
+        }
+    }
+
+    /// Returns `true` if any message class is routed over `transport`.
+    #[must_use]
+    pub fn uses(&self, transport: Transport) -> bool {
+        self.led == transport || self.control == transport
+    }
+}
+
 /// General microcontroller interface.
 pub trait Interface {
     /// Input message.
@@ -34,30 +204,70 @@ pub trait Interface {
     /// Configuration history.
     type Log: Default;
 
+    /// Protocol message to the microcontroller.
+    type Message: Clone + fmt::Debug + Send + 'static;
+
+    /// Protocol message from the microcontroller.
+    type Payload: fmt::Debug + Send + 'static;
+
     /// CAN-bus address of the microcontroller.
     const CAN_ADDRESS: u32;
 
-    /// CAN protocol version.
+    /// ISO-TP CAN identifier of the messages from the microcontroller.
+    const ISOTP_RX_ID: u32;
+
+    /// ISO-TP CAN identifier of the messages to the microcontroller.
+    const ISOTP_TX_ID: u32;
+
+    /// Preferred CAN protocol version.
     const PROTOCOL_VERSION: i32;
 
+    /// CAN protocol versions which can be encoded, in order of preference.
+    const SUPPORTED_PROTOCOL_VERSIONS: &'static [i32];
+
+    /// Acknowledge error code of a successfully handled message.
+    const ACK_SUCCESS: i32;
+
     /// Saves the input message to the log.
     fn log_input(log: &mut Self::Log, input: &Self::Input);
 
     /// Converts an input message to a CAN protocol message.
-    fn input_to_message(
-        input: &Self::Input,
-        ack_number: u32,
-    ) -> Option<orb_messages::mcu_main::mcu_message::Message>;
+    fn input_to_message(input: &Self::Input, ack_number: u32) -> Option<Self::Message>;
 
     /// Converts a CAN protocol message into an output message.
     fn output_from_message(
-        message: orb_messages::mcu_main::mcu_to_jetson::Payload,
+        message: Self::Payload,
         nmea_parser: &mut NmeaParser,
         nmea_prev_part: &mut Option<(u32, String)>,
     ) -> Option<Self::Output>;
 
+    /// Wraps a protocol message in the envelope of protocol `version` and
+    /// encodes it.
+    fn encode_message(message: Self::Message, version: i32) -> Vec<u8>;
+
+    /// Decodes a protocol envelope. Returns the protocol version and the
+    /// payload, if the envelope carries one from the microcontroller.
+    fn decode_message(data: &[u8]) -> Result<(i32, Option<Self::Payload>), DecodeError>;
+
+    /// Returns the acknowledge carried by a protocol message, if any.
+    fn payload_to_ack(payload: &Self::Payload) -> Option<Ack>;
+
+    /// Returns the name of a known acknowledge error code.
+    fn ack_error_name(error: i32) -> Option<String>;
+
     /// Converts an input message into an SuccessAck output message.
     fn success_ack_output_from_input(input: Self::Input) -> Self::Output;
+
+    /// Returns the class of an input message, which selects its transport.
+    fn input_class(_input: &Self::Input) -> MessageClass {
+        MessageClass::Control
+    }
+
+    /// Returns the acknowledge timeout and retry policy of an input message
+    /// from the per-class `policies`.
+    fn ack_policy(input: &Self::Input, policies: &AckPolicies) -> AckPolicy {
+        policies.get(Self::input_class(input))
+    }
 }
 
 /// General microcontroller trait.
@@ -83,24 +293,73 @@ pub trait Mcu<I: Interface>: Send {
     /// Returns a mutable reference to the configuration history.
     fn log_mut(&mut self) -> &mut Option<I::Log>;
 
-    /// Sends a message to the microcontroller and waits for the acknowledge.
+    /// Returns the acknowledge policies of the message classes.
+    fn ack_policies(&self) -> AckPolicies {
+        AckPolicies::default()
+    }
+
+    /// Returns the CAN protocol version negotiation state, if the
+    /// microcontroller is connected over CAN.
+    fn protocol(&self) -> Option<&protocol::Protocol> {
+        None
+    }
+
+    /// Adjusts an input message right before it is sent to the
+    /// microcontroller. Returns `None` if the message doesn't need to be sent.
+    fn adjust_input(&mut self, input: I::Input) -> Option<I::Input> {
+        Some(input)
+    }
+
+    /// Called after an input message was acknowledged by the microcontroller,
+    /// or queued to the transport if it isn't waiting for the acknowledge.
+    fn input_sent(&mut self, _input: &I::Input) {}
+
+    /// Sends a message to the microcontroller and waits for the acknowledge,
+    /// retrying according to the [`AckPolicy`] of the message.
+    ///
+    /// Fails with [`AckTimeout`] if the microcontroller doesn't acknowledge
+    /// any attempt, so the callers can degrade gracefully.
     fn send(&mut self, input: I::Input) -> Pin<Box<dyn Future<Output = Result<()>> + Send + '_>> {
         Box::pin(async move {
-            let mut retries = SEND_RETRY_COUNT;
-            'retry: loop {
+            let Some(input) = self.adjust_input(input) else {
+                return Ok(());
+            };
+            let class = I::input_class(&input);
+            let class_tag = format!("class:{}", class.name());
+            let policy = I::ack_policy(&input, &self.ack_policies());
+            let mut attempt = 0;
+            loop {
                 let (completion_tx, completion_rx) = oneshot::channel();
                 self.tx_mut().send((input.clone(), Some(completion_tx))).await?;
-                if let Err(error) = completion_rx.await? {
-                    if retries > 0 {
-                        tracing::warn!("Retrying last µC message... [{}]", retries);
-                        retries -= 1;
-                        continue 'retry;
-                    }
-                    tracing::error!("Maximum µC send retries reached, aborting with Error");
-                    return Err(error);
+                let Ok(completion) =
+                    time::timeout(policy.timeout + QUEUE_TIMEOUT, completion_rx).await
+                else {
+                    // The transport is stuck, retrying would only queue more.
+                    tracing::error!("µC message wasn't sent within {QUEUE_TIMEOUT:?}");
+                    dd_incr!("main.count.global.mcu_ack_timeout", "stage:queue", &class_tag);
+                    return Err(AckTimeout { timeout: policy.timeout + QUEUE_TIMEOUT }.into());
                 };
-                break 'retry;
+                let Err(error) = completion? else {
+                    break;
+                };
+                if attempt < policy.retries {
+                    tracing::warn!(
+                        "Retrying last µC {} message... [{}]",
+                        class.name(),
+                        policy.retries - attempt
+                    );
+                    dd_incr!("main.count.global.mcu_retry", &class_tag);
+                    time::sleep(policy.backoff(attempt)).await;
+                    attempt += 1;
+                    continue;
+                }
+                if error.is::<AckTimeout>() {
+                    dd_incr!("main.count.global.mcu_ack_timeout", "stage:ack", &class_tag);
+                }
+                tracing::error!("Maximum µC send retries reached, aborting with Error");
+                return Err(error);
             }
+            self.input_sent(&input);
             if let Some(log) = self.log_mut() {
                 I::log_input(log, &input);
             }
@@ -111,10 +370,14 @@ pub trait Mcu<I: Interface>: Send {
     /// Attempts to send a message to the microcontroller without waiting for
     /// the acknowledge.
     fn send_now(&mut self, input: I::Input) -> Result<()> {
+        let Some(input) = self.adjust_input(input) else {
+            return Ok(());
+        };
         if let Some(log) = self.log_mut() {
             I::log_input(log, &input);
         }
-        self.tx_mut().send_now((input, None))?;
+        self.tx_mut().send_now((input.clone(), None))?;
+        self.input_sent(&input);
         Ok(())
     }
 
@@ -133,3 +396,93 @@ pub trait Mcu<I: Interface>: Send {
         self.log_mut().take().unwrap_or_default()
     }
 }
+
+/// Forwards the input messages to the transports selected by `routing`. A
+/// congested transport doesn't hold back the messages for the other one: its
+/// messages are queued up to [`ROUTE_BACKLOG`].
+async fn route<I: Interface>(
+    mut input_rx: mpsc::Receiver<(I::Input, Option<ResultSender>)>,
+    routing: Routing,
+    can_tx: mpsc::Sender<(I::Input, Option<ResultSender>)>,
+    uart_tx: mpsc::Sender<(I::Input, Option<ResultSender>)>,
+) {
+    let mut can = (can_tx, VecDeque::new());
+    let mut uart = (uart_tx, VecDeque::new());
+    future::poll_fn(|cx| loop {
+        for (tx, backlog) in [&mut can, &mut uart] {
+            while !backlog.is_empty() {
+                match tx.poll_ready(cx) {
+                    Poll::Ready(Ok(())) => {
+                        if tx.start_send(backlog.pop_front().unwrap()).is_err() {
+                            return Poll::Ready(());
+                        }
+                    }
+                    Poll::Ready(Err(_)) => return Poll::Ready(()),
+                    Poll::Pending => break,
+                }
+            }
+        }
+        let Some((input, completion_tx)) = futures::ready!(input_rx.poll_next_unpin(cx)) else {
+            return Poll::Ready(());
+        };
+        let transport = routing.transport(I::input_class(&input));
+        let (_, backlog) = match transport {
+            Transport::Can => &mut can,
+            Transport::Uart => &mut uart,
+        };
+        if backlog.len() < ROUTE_BACKLOG {
+            backlog.push_back((input, completion_tx));
+        } else {
+            tracing::error!("µC {transport:?} transport is congested, dropping a message");
+            if let Some(completion_tx) = completion_tx {
+                let _ = completion_tx.send(Err(eyre!("µC {transport:?} transport is congested")));
+            }
+        }
+    })
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ack_policy_backoff() {
+        let policy = AckPolicy::DEFAULT;
+        assert_eq!(policy.backoff(0), Duration::from_millis(50));
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(AckPolicy::NO_RETRY.retries, 0);
+    }
+
+    #[test]
+    fn test_class_routing() {
+        let routing = Routing { led: Transport::Uart, control: Transport::Can };
+        assert_eq!(routing.transport(MessageClass::Led), Transport::Uart);
+        assert_eq!(routing.transport(MessageClass::Query), Transport::Can);
+        assert_eq!(routing.transport(MessageClass::Motion), Transport::Can);
+    }
+
+    #[tokio::test]
+    async fn test_route_congested_transport() {
+        let (mut input_tx, input_rx) = mpsc::channel(10);
+        let (can_tx, _can_rx) = mpsc::channel(0);
+        let (uart_tx, mut uart_rx) = mpsc::channel(0);
+        let routing = Routing { led: Transport::Uart, control: Transport::Can };
+        tokio::spawn(route::<Main>(input_rx, routing, can_tx, uart_tx));
+        for _ in 0..3 {
+            input_tx.send((main::Input::Version, None)).await.unwrap();
+        }
+        input_tx.send((main::Input::WhiteLedBrightness(100), None)).await.unwrap();
+        let (input, _) =
+            time::timeout(Duration::from_secs(1), uart_rx.next()).await.unwrap().unwrap();
+        assert!(matches!(input, main::Input::WhiteLedBrightness(100)));
+    }
+
+    #[test]
+    fn test_ack_timeout_downcast() {
+        let error: Error = AckTimeout { timeout: Duration::from_millis(300) }.into();
+        assert!(error.is::<AckTimeout>());
+        assert!(!Error::msg("µC Timeout").is::<AckTimeout>());
+    }
+}
@@ -0,0 +1,162 @@
+//! CAN protocol version negotiation.
+//!
+//! Every CAN message is wrapped into an envelope carrying the protocol version
+//! of the sender. The version of the first message received from the firmware
+//! selects the encoding used for the outgoing messages. If orb-core can't speak
+//! the firmware protocol version, the negotiation results in
+//! [`IncompatibleFirmware`], which is reported by the health check, instead of
+//! silently dropping every message.
+
+use crate::dd_incr;
+use std::sync::{Arc, Mutex};
+
+/// The firmware speaks a protocol version which orb-core doesn't support.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "incompatible MCU firmware: firmware protocol version {firmware}, orb-core supports \
+     {supported:?}"
+)]
+pub struct IncompatibleFirmware {
+    /// Protocol version of the firmware.
+    pub firmware: i32,
+    /// Protocol versions supported by orb-core.
+    pub supported: &'static [i32],
+}
+
+/// Result of the protocol version negotiation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Negotiation {
+    /// No message has been received from the firmware yet.
+    Pending,
+    /// The firmware protocol version is supported and used for encoding.
+    Compatible {
+        /// Protocol version of the firmware.
+        firmware: i32,
+    },
+    /// The firmware protocol version is not supported.
+    Incompatible(IncompatibleFirmware),
+}
+
+/// Shared protocol version negotiation state of a microcontroller.
+#[derive(Clone, Debug)]
+pub struct Protocol {
+    preferred: i32,
+    supported: &'static [i32],
+    negotiation: Arc<Mutex<Negotiation>>,
+}
+
+impl Protocol {
+    /// Creates a new negotiation state. `supported` lists the protocol
+    /// versions orb-core can encode, and `preferred` is used until the
+    /// negotiation completes.
+    #[must_use]
+    pub fn new(preferred: i32, supported: &'static [i32]) -> Self {
+        Self { preferred, supported, negotiation: Arc::new(Mutex::new(Negotiation::Pending)) }
+    }
+
+    /// Returns the protocol version preferred by orb-core.
+    #[must_use]
+    pub fn preferred(&self) -> i32 {
+        self.preferred
+    }
+
+    /// Returns the current negotiation result.
+    #[must_use]
+    pub fn negotiation(&self) -> Negotiation {
+        *self.negotiation.lock().unwrap()
+    }
+
+    /// Returns the protocol version to encode outgoing messages with.
+    #[must_use]
+    pub fn encoding_version(&self) -> i32 {
+        match self.negotiation() {
+            Negotiation::Compatible { firmware } => firmware,
+            Negotiation::Pending | Negotiation::Incompatible(_) => self.preferred,
+        }
+    }
+
+    /// Handles the protocol version of an incoming message. Negotiates again
+    /// if the firmware version has changed, e.g. after a firmware update.
+    /// Returns `true` if the message can be decoded.
+    pub fn accept(&self, firmware: i32) -> bool {
+        let mut negotiation = self.negotiation.lock().unwrap();
+        match *negotiation {
+            Negotiation::Compatible { firmware: selected } if selected == firmware => return true,
+            Negotiation::Incompatible(IncompatibleFirmware { firmware: selected, .. })
+                if selected == firmware =>
+            {
+                return false;
+            }
+            _ => {}
+        }
+        *negotiation = negotiate(self.supported, firmware);
+        self.report(*negotiation);
+        matches!(*negotiation, Negotiation::Compatible { .. })
+    }
+
+    fn report(&self, negotiation: Negotiation) {
+        let orb_core_tag = format!("orb_core:{}", self.preferred);
+        match negotiation {
+            Negotiation::Pending => {}
+            Negotiation::Compatible { firmware } => {
+                if firmware == self.preferred {
+                    tracing::info!("MCU protocol version {firmware} negotiated");
+                } else {
+                    tracing::warn!(
+                        "MCU protocol downgraded from version {} to version {firmware}",
+                        self.preferred
+                    );
+                }
+                dd_incr!(
+                    "main.count.global.mcu_protocol",
+                    &orb_core_tag,
+                    &format!("firmware:{firmware}"),
+                    "compatible:true"
+                );
+            }
+            Negotiation::Incompatible(err) => {
+                tracing::error!("{err}");
+                dd_incr!(
+                    "main.count.global.mcu_protocol",
+                    &orb_core_tag,
+                    &format!("firmware:{}", err.firmware),
+                    "compatible:false"
+                );
+            }
+        }
+    }
+}
+
+fn negotiate(supported: &'static [i32], firmware: i32) -> Negotiation {
+    if supported.contains(&firmware) {
+        Negotiation::Compatible { firmware }
+    } else {
+        Negotiation::Incompatible(IncompatibleFirmware { firmware, supported })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiation() {
+        let protocol = Protocol::new(1, &[1, 0]);
+        assert_eq!(protocol.negotiation(), Negotiation::Pending);
+        assert_eq!(protocol.encoding_version(), 1);
+
+        assert!(protocol.accept(0));
+        assert_eq!(protocol.negotiation(), Negotiation::Compatible { firmware: 0 });
+        assert_eq!(protocol.encoding_version(), 0);
+
+        assert!(!protocol.accept(2));
+        assert_eq!(
+            protocol.negotiation(),
+            Negotiation::Incompatible(IncompatibleFirmware { firmware: 2, supported: &[1, 0] })
+        );
+        assert_eq!(protocol.encoding_version(), 1);
+
+        assert!(protocol.accept(1));
+        assert_eq!(protocol.negotiation(), Negotiation::Compatible { firmware: 1 });
+    }
+}
@@ -0,0 +1,258 @@
+//! Security microcontroller interface.
+//!
+//! The security MCU guards the tamper switches, keeps the battery-backed RTC,
+//! and verifies the firmware at boot. It shares the CAN transport and the
+//! acknowledge protocol with the main MCU, but speaks its own set of messages.
+//!
+//! Only the firmware versions, the logs, and the firmware update messages are
+//! covered. The pinned `orb-messages` revision doesn't define the tamper,
+//! secure boot, and RTC messages yet.
+
+use super::{
+    can::{self, Can},
+    main::{Version, Versions},
+    protocol::Protocol,
+    Ack, AckPolicies, AckPolicy, Interface, Mcu, ResultSender,
+};
+use eyre::Result;
+use futures::{channel::mpsc, prelude::*, stream::Fuse};
+use libc::CAN_EFF_FLAG;
+use nmea_parser::NmeaParser;
+use prost::{DecodeError, Message as _};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+
+const INPUT_CAPACITY: usize = 100;
+const OUTPUT_CAPACITY: usize = 100;
+
+/// Security microcontroller interface.
+pub struct Sec;
+
+/// Security microcontroller interface for the Orb hardware.
+#[derive(Debug)]
+pub struct Jetson {
+    log: Option<()>,
+    input_tx: mpsc::Sender<(Input, Option<ResultSender>)>,
+    output_tx: broadcast::Sender<Output>,
+    output_rx: Fuse<BroadcastStream<Output>>,
+    protocol: Protocol,
+}
+
+/// Message to be sent to the Security microcontroller.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "mcu_input", content = "value")]
+pub enum Input {
+    /// Request the firmware versions.
+    Version,
+    /// Firmware image block for the secondary slot.
+    FirmwareBlock {
+        /// Block number, starting from zero.
+        number: u32,
+        /// Total number of blocks in the image.
+        count: u32,
+        /// Block data.
+        data: Vec<u8>,
+    },
+    /// Check the CRC-32 of the firmware image in the secondary slot.
+    FirmwareImageCheck(u32),
+    /// Activate the firmware in the secondary slot and reboot the uC.
+    FirmwareActivateSecondary,
+}
+
+/// Message received from the Security microcontroller.
+#[derive(Clone, Debug)]
+pub enum Output {
+    /// Successful acknowledge for certain Input.
+    SuccessAck(Input),
+    /// Firmware versions in primary and secondary slots.
+    Versions(Versions),
+    /// MCU Logs.
+    Logs(String),
+}
+
+impl From<&orb_messages::mcu_sec::FirmwareVersion> for Version {
+    fn from(version: &orb_messages::mcu_sec::FirmwareVersion) -> Self {
+        let orb_messages::mcu_sec::FirmwareVersion { major, minor, patch, commit_hash } = *version;
+        Self::new(major, minor, patch, commit_hash)
+    }
+}
+
+impl From<&orb_messages::mcu_sec::Versions> for Versions {
+    fn from(app_versions: &orb_messages::mcu_sec::Versions) -> Self {
+        Self {
+            primary: app_versions.primary_app.as_ref().map(Into::into).unwrap_or_default(),
+            secondary: app_versions.secondary_app.as_ref().map(Into::into).unwrap_or_default(),
+        }
+    }
+}
+
+impl Interface for Sec {
+    type Input = Input;
+    type Log = ();
+    type Message = orb_messages::mcu_sec::mcu_message::Message;
+    type Output = Output;
+    type Payload = orb_messages::mcu_sec::sec_to_jetson::Payload;
+
+    const ACK_SUCCESS: i32 = orb_messages::mcu_sec::ack::ErrorCode::Success as i32;
+    const CAN_ADDRESS: u32 = 0x02 | CAN_EFF_FLAG;
+    // ISO-TP identifiers are `0x800 | source << 4 | destination`, with 8 for
+    // the Jetson and 2 for the security MCU.
+    const ISOTP_RX_ID: u32 = 0x828;
+    const ISOTP_TX_ID: u32 = 0x882;
+    const PROTOCOL_VERSION: i32 = orb_messages::mcu_sec::Version::Version0 as i32;
+    const SUPPORTED_PROTOCOL_VERSIONS: &'static [i32] =
+        &[orb_messages::mcu_sec::Version::Version0 as i32];
+
+    fn log_input(_log: &mut (), _input: &Input) {}
+
+    fn input_to_message(input: &Input, ack_number: u32) -> Option<Self::Message> {
+        use orb_messages::mcu_sec::{jetson_to_sec::Payload as P, value_get::Value};
+        let payload = match input {
+            Input::Version => P::ValueGet(orb_messages::mcu_sec::ValueGet {
+                value: Value::FirmwareVersions as i32,
+            }),
+            Input::FirmwareBlock { number, count, data } => {
+                P::DfuBlock(orb_messages::mcu_sec::FirmwareUpdateData {
+                    block_number: *number,
+                    block_count: *count,
+                    image_block: data.clone(),
+                })
+            }
+            Input::FirmwareImageCheck(crc32) => {
+                P::FwImageCheck(orb_messages::mcu_sec::FirmwareImageCheck { crc32: *crc32 })
+            }
+            Input::FirmwareActivateSecondary => {
+                P::FwImageSecondaryActivate(orb_messages::mcu_sec::FirmwareActivateSecondary {
+                    force_permanent: false,
+                })
+            }
+        };
+        Some(orb_messages::mcu_sec::mcu_message::Message::JMessage(
+            orb_messages::mcu_sec::JetsonToSec { ack_number, payload: Some(payload) },
+        ))
+    }
+
+    fn output_from_message(
+        message: Self::Payload,
+        _nmea_parser: &mut NmeaParser,
+        _nmea_prev_part: &mut Option<(u32, String)>,
+    ) -> Option<Output> {
+        use orb_messages::mcu_sec::sec_to_jetson::Payload as P;
+        match message {
+            P::Versions(versions) => Some(Output::Versions(Versions::from(&versions))),
+            P::Log(orb_messages::mcu_sec::Log { log }) => Some(Output::Logs(log)),
+            _ => None,
+        }
+    }
+
+    fn encode_message(message: Self::Message, version: i32) -> Vec<u8> {
+        orb_messages::mcu_sec::McuMessage { version, message: Some(message) }
+            .encode_length_delimited_to_vec()
+    }
+
+    fn decode_message(data: &[u8]) -> Result<(i32, Option<Self::Payload>), DecodeError> {
+        let orb_messages::mcu_sec::McuMessage { version, message } =
+            orb_messages::mcu_sec::McuMessage::decode_length_delimited(data)?;
+        let payload = match message {
+            Some(orb_messages::mcu_sec::mcu_message::Message::SecMessage(
+                orb_messages::mcu_sec::SecToJetson { payload },
+            )) => payload,
+            _ => None,
+        };
+        Ok((version, payload))
+    }
+
+    fn payload_to_ack(payload: &Self::Payload) -> Option<Ack> {
+        match payload {
+            orb_messages::mcu_sec::sec_to_jetson::Payload::Ack(ack) => {
+                Some(Ack { ack_number: ack.ack_number, error: ack.error })
+            }
+            _ => None,
+        }
+    }
+
+    fn ack_error_name(error: i32) -> Option<String> {
+        orb_messages::mcu_sec::ack::ErrorCode::try_from(error).ok().map(|error| error.to_string())
+    }
+
+    fn success_ack_output_from_input(input: Input) -> Output {
+        Output::SuccessAck(input)
+    }
+
+    fn ack_policy(input: &Input, policies: &AckPolicies) -> AckPolicy {
+        match input {
+            // The MCU reboots into the new firmware, possibly before the
+            // acknowledge gets through.
+            Input::FirmwareActivateSecondary => {
+                AckPolicy { timeout: Duration::from_secs(1), ..AckPolicy::NO_RETRY }
+            }
+            _ => policies.get(Self::input_class(input)),
+        }
+    }
+}
+
+impl Jetson {
+    /// Spawns a new microcontroller interface.
+    pub fn spawn() -> Result<Self> {
+        Self::spawn_on(can::CAN_SOCKET)
+    }
+
+    /// Spawns a new microcontroller interface on the CAN network interface
+    /// `interface`.
+    pub fn spawn_on(interface: &str) -> Result<Self> {
+        let (mut input_tx, input_rx) = mpsc::channel(INPUT_CAPACITY);
+        let (output_tx, output_rx) = broadcast::channel(OUTPUT_CAPACITY);
+        let output_rx = BroadcastStream::new(output_rx).fuse();
+        let protocol = Protocol::new(Sec::PROTOCOL_VERSION, Sec::SUPPORTED_PROTOCOL_VERSIONS);
+        Can::<Sec>::spawn_on(
+            interface,
+            input_rx,
+            output_tx.clone(),
+            protocol.clone(),
+            AckPolicies::default(),
+        )?;
+        // Any response from the firmware completes the protocol negotiation.
+        if let Err(err) = input_tx.try_send((Input::Version, None)) {
+            tracing::error!("Failed to request the security MCU firmware versions: {err}");
+        }
+        Ok(Self { log: None, input_tx, output_tx, output_rx, protocol })
+    }
+}
+
+impl Mcu<Sec> for Jetson {
+    fn clone(&self) -> Box<dyn Mcu<Sec>> {
+        Box::new(Self {
+            log: None,
+            input_tx: self.input_tx.clone(),
+            output_tx: self.output_tx.clone(),
+            output_rx: BroadcastStream::new(self.output_tx.subscribe()).fuse(),
+            protocol: self.protocol.clone(),
+        })
+    }
+
+    fn tx(&self) -> &mpsc::Sender<(Input, Option<ResultSender>)> {
+        &self.input_tx
+    }
+
+    fn tx_mut(&mut self) -> &mut mpsc::Sender<(Input, Option<ResultSender>)> {
+        &mut self.input_tx
+    }
+
+    fn rx(&self) -> &Fuse<BroadcastStream<Output>> {
+        &self.output_rx
+    }
+
+    fn rx_mut(&mut self) -> &mut Fuse<BroadcastStream<Output>> {
+        &mut self.output_rx
+    }
+
+    fn log_mut(&mut self) -> &mut Option<()> {
+        &mut self.log
+    }
+
+    fn protocol(&self) -> Option<&Protocol> {
+        Some(&self.protocol)
+    }
+}
@@ -0,0 +1,275 @@
+//! UART MCU interface.
+//!
+//! An alternative to the [CAN interface](super::can) with the same semantics:
+//! the same protocol envelope, acknowledges, and timeouts, and the retries of
+//! [`Mcu::send`](super::Mcu::send). Heavy traffic like the LED frames can be
+//! moved to the UART line, see [`Routing`](super::Routing), to reserve the CAN
+//! bus for control and telemetry.
+//!
+//! Each message is sent in a frame:
+//!
+//! | Bytes | Content                                   |
+//! |-------|-------------------------------------------|
+//! | 2     | [`SYNC`] marker                           |
+//! | 2     | payload length, little-endian             |
+//! | N     | length-delimited protobuf message         |
+//! | 4     | CRC-32 of the payload, little-endian      |
+//!
+//! The receiver resynchronizes on the next marker after a corrupted frame.
+//!
+//! The serial device is opened in the non-blocking mode and polled with the
+//! tokio reactor, so the transport doesn't occupy any threads.
+
+use super::{
+    can::{self, Can},
+    protocol::Protocol,
+    AckPolicies, Interface, ResultSender,
+};
+use crate::dd_incr;
+use eyre::{Error, Result, WrapErr};
+use futures::{channel::mpsc, future};
+use nix::sys::termios::{self, BaudRate, SetArg};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    marker::PhantomData,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    sync::Arc,
+};
+use tokio::{io::unix::AsyncFd, sync::broadcast, task};
+
+const ASYNC_TX_CAPACITY: usize = 100;
+const ASYNC_RX_CAPACITY: usize = 100;
+const ACK_CAPACITY: usize = 100;
+const READ_BUFFER_SIZE: usize = 1024;
+const HEADER_SIZE: usize = SYNC.len() + 2;
+const CRC_SIZE: usize = 4;
+
+/// Default serial device the main microcontroller is connected to.
+pub const UART_DEVICE: &str = "/dev/ttyTHS0";
+/// Frame start marker.
+pub const SYNC: [u8; 2] = [0x8E, 0xAD];
+/// Maximum payload size of a frame.
+pub const MAX_PAYLOAD_SIZE: usize = 2048;
+
+/// UART interface.
+pub struct Uart<I: Interface>(PhantomData<I>);
+
+/// Incremental frame decoder.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl<I: Interface> Uart<I> {
+    /// Spawns a new UART interface on the serial device `path`.
+    ///
+    /// # Panics
+    ///
+    /// If called outside of a tokio runtime.
+    pub fn spawn_on(
+        path: &str,
+        input_rx: mpsc::Receiver<(I::Input, Option<ResultSender>)>,
+        output_tx: broadcast::Sender<I::Output>,
+        protocol: Protocol,
+        ack_policies: AckPolicies,
+    ) -> Result<()> {
+        let device =
+            Arc::new(AsyncFd::new(open(path).wrap_err_with(|| format!("opening {path}"))?)?);
+        let tx = Self::async_tx(Arc::clone(&device), protocol.clone());
+        let rx = Self::async_rx(device, protocol);
+        let (ack_tx, ack_rx) = mpsc::channel(ACK_CAPACITY);
+        task::spawn(async move {
+            let input_fut =
+                Can::<I>::handle_input(tx, input_rx, ack_rx, output_tx.clone(), ack_policies);
+            let output_fut = Can::<I>::handle_output(rx, output_tx, ack_tx);
+            match future::try_join(input_fut, output_fut).await {
+                Ok(((), ())) => {}
+                Err(err) => {
+                    tracing::error!("MCU UART task failed: {:?}", err);
+                }
+            }
+            Ok::<(), Error>(())
+        });
+        Ok(())
+    }
+
+    fn async_tx(
+        device: Arc<AsyncFd<File>>,
+        protocol: Protocol,
+    ) -> tokio::sync::mpsc::Sender<I::Message> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(ASYNC_TX_CAPACITY);
+        task::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let payload = can::encode_message::<I>(message, &protocol);
+                let Some(frame) = encode_frame(&payload) else {
+                    tracing::error!("MCU message of {} bytes is too large for UART", payload.len());
+                    continue;
+                };
+                if let Err(err) = write_all(&device, &frame).await {
+                    tracing::error!("Error writing to UART device: {err:?}");
+                    return;
+                }
+            }
+        });
+        tx
+    }
+
+    fn async_rx(
+        device: Arc<AsyncFd<File>>,
+        protocol: Protocol,
+    ) -> tokio::sync::mpsc::Receiver<I::Payload> {
+        let (tx, rx) = tokio::sync::mpsc::channel(ASYNC_RX_CAPACITY);
+        task::spawn(async move {
+            let mut decoder = FrameDecoder::default();
+            let mut buffer = [0; READ_BUFFER_SIZE];
+            loop {
+                let count = match read(&device, &mut buffer).await {
+                    Ok(0) => {
+                        tracing::error!("UART device closed");
+                        return;
+                    }
+                    Ok(count) => count,
+                    Err(err) => {
+                        tracing::error!("Error reading from UART device: {err:?}");
+                        return;
+                    }
+                };
+                for payload in decoder.push(&buffer[..count]) {
+                    if let Some(payload) = can::decode_message::<I>(&payload, &protocol) {
+                        if tx.send(payload).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+        rx
+    }
+}
+
+/// Reads from the device, waiting for it to become readable.
+async fn read(device: &AsyncFd<File>, buffer: &mut [u8]) -> io::Result<usize> {
+    loop {
+        let mut guard = device.readable().await?;
+        if let Ok(result) = guard.try_io(|device| device.get_ref().read(buffer)) {
+            break result;
+        }
+    }
+}
+
+/// Writes the whole `bytes` to the device, waiting for it to become writable.
+async fn write_all(device: &AsyncFd<File>, mut bytes: &[u8]) -> io::Result<()> {
+    while !bytes.is_empty() {
+        let mut guard = device.writable().await?;
+        if let Ok(result) = guard.try_io(|device| device.get_ref().write(bytes)) {
+            match result? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                written => bytes = &bytes[written..],
+            }
+        }
+    }
+    Ok(())
+}
+
+impl FrameDecoder {
+    /// Feeds received bytes. Returns the payloads of the completed frames.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut payloads = Vec::new();
+        loop {
+            let Some(start) = self.buffer.windows(SYNC.len()).position(|window| window == SYNC)
+            else {
+                // Keep a possible partial marker at the end.
+                let keep = usize::from(self.buffer.last() == Some(&SYNC[0]));
+                self.buffer.drain(..self.buffer.len() - keep);
+                break;
+            };
+            self.buffer.drain(..start);
+            if self.buffer.len() < HEADER_SIZE {
+                break;
+            }
+            let len = usize::from(u16::from_le_bytes([self.buffer[2], self.buffer[3]]));
+            if len > MAX_PAYLOAD_SIZE {
+                self.resync();
+                continue;
+            }
+            if self.buffer.len() < HEADER_SIZE + len + CRC_SIZE {
+                break;
+            }
+            let payload = &self.buffer[HEADER_SIZE..HEADER_SIZE + len];
+            let crc = &self.buffer[HEADER_SIZE + len..HEADER_SIZE + len + CRC_SIZE];
+            if crc32fast::hash(payload).to_le_bytes() != crc {
+                self.resync();
+                continue;
+            }
+            payloads.push(payload.to_vec());
+            self.buffer.drain(..HEADER_SIZE + len + CRC_SIZE);
+        }
+        payloads
+    }
+
+    /// Skips the current marker to look for the next frame.
+    fn resync(&mut self) {
+        tracing::warn!("Corrupted UART frame from MCU");
+        dd_incr!("main.count.global.mcu_uart.corrupted_frame");
+        self.buffer.drain(..SYNC.len());
+    }
+}
+
+/// Wraps a payload into a frame. Returns `None` if the payload is too large.
+#[must_use]
+pub fn encode_frame(payload: &[u8]) -> Option<Vec<u8>> {
+    if payload.len() > MAX_PAYLOAD_SIZE {
+        return None;
+    }
+    let len = u16::try_from(payload.len()).ok()?;
+    let mut frame = Vec::with_capacity(HEADER_SIZE + payload.len() + CRC_SIZE);
+    frame.extend_from_slice(&SYNC);
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    Some(frame)
+}
+
+/// Opens the serial device in the raw non-blocking mode.
+fn open(path: &str) -> Result<File> {
+    let device = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY)
+        .open(path)?;
+    let mut attrs = termios::tcgetattr(device.as_raw_fd())?;
+    termios::cfmakeraw(&mut attrs);
+    termios::cfsetspeed(&mut attrs, BaudRate::B1000000)?;
+    termios::tcsetattr(device.as_raw_fd(), SetArg::TCSANOW, &attrs)?;
+    Ok(device)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_round_trip() {
+        let mut decoder = FrameDecoder::default();
+        let first = encode_frame(b"hello").unwrap();
+        let second = encode_frame(&[SYNC[0], SYNC[1], 0, 1]).unwrap();
+        let mut stream = vec![0x00, SYNC[0], 0x42];
+        stream.extend_from_slice(&first);
+        stream.extend_from_slice(&second);
+        let (head, tail) = stream.split_at(7);
+        assert!(decoder.push(head).is_empty());
+        assert_eq!(decoder.push(tail), vec![b"hello".to_vec(), vec![SYNC[0], SYNC[1], 0, 1]]);
+        assert!(encode_frame(&[0; MAX_PAYLOAD_SIZE + 1]).is_none());
+    }
+
+    #[test]
+    fn test_frame_corruption() {
+        let mut decoder = FrameDecoder::default();
+        let mut corrupted = encode_frame(b"corrupted").unwrap();
+        corrupted[6] ^= 0xFF;
+        corrupted.extend_from_slice(&encode_frame(b"valid").unwrap());
+        assert_eq!(decoder.push(&corrupted), vec![b"valid".to_vec()]);
+    }
+}
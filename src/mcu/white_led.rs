@@ -0,0 +1,227 @@
+//! White LED thermal derating.
+//!
+//! Sustained high white LED brightness overheats the front unit on Diamond
+//! hardware. [`Derating`] tracks the commanded brightness against a
+//! first-order thermal model, which is corrected with the front unit
+//! temperature telemetry, and clamps or ramps the brightness which is actually
+//! sent to the main MCU.
+
+use crate::{
+    consts::{
+        WHITE_LED_DERATING_MAX_TEMPERATURE, WHITE_LED_DERATING_MIN_BRIGHTNESS,
+        WHITE_LED_DERATING_RAMP_UP_RATE, WHITE_LED_DERATING_START_TEMPERATURE,
+        WHITE_LED_THERMAL_DEFAULT_AMBIENT, WHITE_LED_THERMAL_RISE_AT_FULL_BRIGHTNESS,
+        WHITE_LED_THERMAL_TIME_CONSTANT,
+    },
+    dd_incr,
+};
+use std::time::Instant;
+
+/// Full white LED brightness, in thousandths.
+pub const MAX_BRIGHTNESS: u32 = 1000;
+
+/// White LED derating controller.
+#[derive(Debug)]
+pub struct Derating {
+    requested: u32,
+    applied: u32,
+    heat: f64,
+    ambient: f64,
+    derating: bool,
+    last_update: Option<Instant>,
+}
+
+impl Default for Derating {
+    fn default() -> Self {
+        Self {
+            requested: 0,
+            applied: 0,
+            heat: 0.0,
+            ambient: WHITE_LED_THERMAL_DEFAULT_AMBIENT,
+            derating: false,
+            last_update: None,
+        }
+    }
+}
+
+impl Derating {
+    /// Registers a new brightness request and returns the brightness which
+    /// should be sent to the MCU.
+    ///
+    /// Decreases are applied immediately. Increases are clamped to the current
+    /// thermal limit, and while derating is active, they are postponed to be
+    /// ramped up by [`Self::tick`].
+    pub fn request(&mut self, requested: u32, now: Instant) -> u32 {
+        self.advance(now);
+        self.requested = requested.min(MAX_BRIGHTNESS);
+        let mut target = self.requested.min(self.limit());
+        if self.derating {
+            target = target.min(self.applied);
+        }
+        self.set_applied(target);
+        self.applied
+    }
+
+    /// Re-evaluates the applied brightness. Returns a new brightness if it has
+    /// to be changed.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn tick(&mut self, now: Instant) -> Option<u32> {
+        let dt = self.advance(now);
+        let mut target = self.requested.min(self.limit());
+        if self.derating {
+            let step = (WHITE_LED_DERATING_RAMP_UP_RATE * dt).max(1.0) as u32;
+            target = target.min(self.applied.saturating_add(step));
+        }
+        let previous = self.applied;
+        self.set_applied(target);
+        (self.applied != previous).then_some(self.applied)
+    }
+
+    /// Updates the model with the front unit temperature telemetry.
+    pub fn update_temperature(&mut self, temperature: f64, now: Instant) {
+        self.advance(now);
+        // The telemetry already includes the heat of the LEDs, the rest is
+        // attributed to the environment.
+        self.ambient = temperature - self.heat;
+    }
+
+    /// Returns the estimated front unit temperature, in degrees Celsius.
+    #[must_use]
+    pub fn estimated_temperature(&self) -> f64 {
+        self.ambient + self.heat
+    }
+
+    /// Returns the brightness which is currently applied, in thousandths.
+    #[must_use]
+    pub fn applied(&self) -> u32 {
+        self.applied
+    }
+
+    /// Returns `true` if the applied brightness is currently lower than the
+    /// requested one.
+    #[must_use]
+    pub fn is_derating(&self) -> bool {
+        self.derating
+    }
+
+    /// Returns the maximum brightness allowed at the estimated temperature.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn limit(&self) -> u32 {
+        let temperature = self.estimated_temperature();
+        if temperature <= WHITE_LED_DERATING_START_TEMPERATURE {
+            return MAX_BRIGHTNESS;
+        }
+        if temperature >= WHITE_LED_DERATING_MAX_TEMPERATURE {
+            return WHITE_LED_DERATING_MIN_BRIGHTNESS;
+        }
+        let ratio = (temperature - WHITE_LED_DERATING_START_TEMPERATURE)
+            / (WHITE_LED_DERATING_MAX_TEMPERATURE - WHITE_LED_DERATING_START_TEMPERATURE);
+        let range = f64::from(MAX_BRIGHTNESS - WHITE_LED_DERATING_MIN_BRIGHTNESS);
+        MAX_BRIGHTNESS - (range * ratio) as u32
+    }
+
+    /// Advances the thermal model to `now` with the applied brightness.
+    /// Returns the elapsed time in seconds.
+    fn advance(&mut self, now: Instant) -> f64 {
+        let dt = self
+            .last_update
+            .replace(now)
+            .map_or(0.0, |last_update| now.saturating_duration_since(last_update).as_secs_f64());
+        let steady_state = WHITE_LED_THERMAL_RISE_AT_FULL_BRIGHTNESS * f64::from(self.applied)
+            / f64::from(MAX_BRIGHTNESS);
+        let alpha = 1.0 - (-dt / WHITE_LED_THERMAL_TIME_CONSTANT.as_secs_f64()).exp();
+        self.heat += (steady_state - self.heat) * alpha;
+        dt
+    }
+
+    fn set_applied(&mut self, applied: u32) {
+        self.applied = applied;
+        let derating = self.applied < self.requested;
+        if derating && !self.derating {
+            tracing::warn!(
+                "White LED derating engaged: requested brightness {}, applied {}, estimated front \
+                 temperature {:.1}°C",
+                self.requested,
+                self.applied,
+                self.estimated_temperature()
+            );
+            dd_incr!("main.count.hardware.white_led.derating_engaged");
+        } else if !derating && self.derating {
+            tracing::info!(
+                "White LED derating released: brightness {}, estimated front temperature {:.1}°C",
+                self.applied,
+                self.estimated_temperature()
+            );
+        }
+        self.derating = derating;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pass_through_when_cool() {
+        let start = Instant::now();
+        let mut derating = Derating::default();
+        assert_eq!(derating.request(800, start), 800);
+        assert!(!derating.is_derating());
+        assert_eq!(derating.tick(start + Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn test_sustained_brightness_is_derated() {
+        let start = Instant::now();
+        let mut derating = Derating::default();
+        derating.update_temperature(40.0, start);
+        assert_eq!(derating.request(MAX_BRIGHTNESS, start), MAX_BRIGHTNESS);
+        let mut now = start;
+        for _ in 0..1200 {
+            now += Duration::from_secs(1);
+            derating.tick(now);
+        }
+        assert!(derating.is_derating());
+        assert!(derating.applied() < MAX_BRIGHTNESS);
+        assert!(derating.estimated_temperature() < WHITE_LED_DERATING_MAX_TEMPERATURE);
+    }
+
+    #[test]
+    fn test_hot_telemetry_clamps_immediately() {
+        let start = Instant::now();
+        let mut derating = Derating::default();
+        derating.update_temperature(WHITE_LED_DERATING_MAX_TEMPERATURE + 5.0, start);
+        assert_eq!(derating.request(MAX_BRIGHTNESS, start), WHITE_LED_DERATING_MIN_BRIGHTNESS);
+        assert!(derating.is_derating());
+    }
+
+    #[test]
+    fn test_recovery_is_ramped() {
+        let start = Instant::now();
+        let mut derating = Derating::default();
+        derating.update_temperature(WHITE_LED_DERATING_MAX_TEMPERATURE + 5.0, start);
+        derating.request(MAX_BRIGHTNESS, start);
+        derating.update_temperature(25.0, start + Duration::from_secs(1));
+        let brightness = derating.tick(start + Duration::from_secs(2)).unwrap();
+        assert!(brightness > WHITE_LED_DERATING_MIN_BRIGHTNESS);
+        assert!(brightness < MAX_BRIGHTNESS);
+        let mut now = start + Duration::from_secs(2);
+        for _ in 0..20 {
+            now += Duration::from_secs(1);
+            derating.tick(now);
+        }
+        assert_eq!(derating.applied(), MAX_BRIGHTNESS);
+        assert!(!derating.is_derating());
+    }
+
+    #[test]
+    fn test_decrease_is_immediate() {
+        let start = Instant::now();
+        let mut derating = Derating::default();
+        derating.update_temperature(WHITE_LED_DERATING_MAX_TEMPERATURE + 5.0, start);
+        derating.request(MAX_BRIGHTNESS, start);
+        assert_eq!(derating.request(50, start + Duration::from_secs(1)), 50);
+        assert!(!derating.is_derating());
+    }
+}
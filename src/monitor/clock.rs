@@ -0,0 +1,283 @@
+//! Clock health.
+//!
+//! Signed backend requests fail in confusing ways when the RTC drifts. The
+//! clock skew is estimated from the `Date` headers of the backend responses
+//! passing through [`traffic::send`](super::traffic::send), and a warning is
+//! raised once it exceeds [`CLOCK_SKEW_WARNING_THRESHOLD`]. The time
+//! synchronization quality is probed from chrony, so the debug report can
+//! record it along with the skew.
+
+use crate::{consts::CLOCK_SKEW_WARNING_THRESHOLD, dd_gauge, dd_incr};
+use eyre::{bail, Result, WrapErr};
+use once_cell::sync::Lazy;
+use reqwest::{header::DATE, Response};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{
+    str,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+use tokio::process::Command;
+
+const CHRONYC_BIN: &str = "chronyc";
+
+/// Number of fields in the CSV output of `chronyc -c tracking`.
+const TRACKING_FIELDS: usize = 14;
+
+static SKEW: Lazy<Mutex<Skew>> = Lazy::new(|| Mutex::new(Skew::default()));
+
+/// Clock skew tracker.
+#[derive(Debug, Default)]
+pub struct Skew {
+    seconds: Option<f64>,
+    warning: bool,
+}
+
+/// Time synchronization quality snapshot.
+#[derive(Clone, Debug, Default, Serialize, JsonSchema)]
+pub struct TimeSync {
+    /// Latest estimated clock skew in seconds, positive if the local clock is
+    /// ahead of the backend.
+    pub skew: Option<f64>,
+    /// Whether the skew exceeds [`CLOCK_SKEW_WARNING_THRESHOLD`].
+    pub skew_warning: bool,
+    /// Chrony tracking report, if chrony is reachable.
+    pub chrony: Option<Tracking>,
+}
+
+/// Chrony tracking report.
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct Tracking {
+    /// Name or address of the reference source.
+    pub reference: String,
+    /// Number of hops to a reference clock. Zero if not synchronized.
+    pub stratum: u8,
+    /// Current offset of the system clock from the NTP time in seconds.
+    pub system_time_offset: f64,
+    /// Offset measured at the last clock update in seconds.
+    pub last_offset: f64,
+    /// Long-term average of the offset in seconds.
+    pub rms_offset: f64,
+    /// Frequency error of the system clock in ppm.
+    pub frequency: f64,
+    /// Estimated error bound of the frequency in ppm.
+    pub skew: f64,
+    /// Total network path delay to the stratum-1 source in seconds.
+    pub root_delay: f64,
+    /// Total dispersion accumulated to the stratum-1 source in seconds.
+    pub root_dispersion: f64,
+    /// Interval between the last two clock updates in seconds.
+    pub update_interval: f64,
+    /// Leap status, e.g. `Normal` or `Not synchronised`.
+    pub leap_status: String,
+}
+
+impl Skew {
+    /// Records a backend `date` of a response to a request sent at `sent` and
+    /// answered at `received`. Returns `Some(true)` when the skew has just
+    /// exceeded the threshold, and `Some(false)` when it has just recovered.
+    pub fn push(
+        &mut self,
+        date: SystemTime,
+        sent: SystemTime,
+        received: SystemTime,
+    ) -> Option<bool> {
+        let round_trip = received.duration_since(sent).unwrap_or_default();
+        let local = sent + round_trip / 2;
+        // The `Date` header has a resolution of one second and is truncated.
+        let remote = date + Duration::from_millis(500);
+        let skew = signed_seconds(local, remote);
+        self.seconds = Some(skew);
+        let threshold = CLOCK_SKEW_WARNING_THRESHOLD.as_secs_f64();
+        let warning =
+            if self.warning { skew.abs() > threshold / 2.0 } else { skew.abs() > threshold };
+        if warning == self.warning {
+            return None;
+        }
+        self.warning = warning;
+        Some(warning)
+    }
+
+    /// Returns the latest clock skew in seconds, positive if the local clock
+    /// is ahead of the backend.
+    #[must_use]
+    pub fn seconds(&self) -> Option<f64> {
+        self.seconds
+    }
+
+    /// Returns `true` if the skew exceeds the warning threshold.
+    #[must_use]
+    pub fn is_warning(&self) -> bool {
+        self.warning
+    }
+}
+
+impl Tracking {
+    /// Returns `true` if chrony is synchronized to a source.
+    #[must_use]
+    pub fn is_synchronized(&self) -> bool {
+        self.stratum > 0 && self.leap_status != "Not synchronised"
+    }
+
+    /// Parses the CSV output of `chronyc -c tracking`.
+    pub fn parse(output: &str) -> Result<Self> {
+        let fields = output.trim().split(',').collect::<Vec<_>>();
+        if fields.len() != TRACKING_FIELDS {
+            bail!("unexpected number of chrony tracking fields: {}", fields.len());
+        }
+        let float = |index: usize| -> Result<f64> {
+            let field = fields[index];
+            field.parse().wrap_err_with(|| format!("parsing chrony tracking field `{field}`"))
+        };
+        Ok(Self {
+            reference: fields[1].to_owned(),
+            stratum: fields[2].parse()?,
+            system_time_offset: float(4)?,
+            last_offset: float(5)?,
+            rms_offset: float(6)?,
+            frequency: float(7)?,
+            skew: float(9)?,
+            root_delay: float(10)?,
+            root_dispersion: float(11)?,
+            update_interval: float(12)?,
+            leap_status: fields[13].to_owned(),
+        })
+    }
+}
+
+/// Records the `Date` header of a backend response to a request sent at
+/// `sent`.
+///
+/// # Panics
+///
+/// If the mutex is poisoned
+pub fn observe_response(response: &Response, sent: SystemTime) {
+    let Some(date) = response
+        .headers()
+        .get(DATE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| httpdate::parse_http_date(date).ok())
+    else {
+        return;
+    };
+    let mut skew = SKEW.lock().unwrap();
+    let Some(warning) = skew.push(date, sent, SystemTime::now()) else {
+        return;
+    };
+    let seconds = skew.seconds().unwrap_or_default();
+    if warning {
+        tracing::warn!(
+            "Clock skew of {seconds:.0}s from the backend, signed requests may fail; check the \
+             time synchronization"
+        );
+    } else {
+        tracing::info!("Clock skew recovered: {seconds:.0}s");
+    }
+    dd_incr!("main.count.system.clock.skew_warning", &format!("warning:{warning}"));
+}
+
+/// Returns the latest clock skew in seconds, positive if the local clock is
+/// ahead of the backend.
+///
+/// # Panics
+///
+/// If the mutex is poisoned
+#[must_use]
+pub fn skew() -> Option<f64> {
+    SKEW.lock().unwrap().seconds()
+}
+
+/// Returns `true` if the clock skew exceeds [`CLOCK_SKEW_WARNING_THRESHOLD`].
+///
+/// # Panics
+///
+/// If the mutex is poisoned
+#[must_use]
+pub fn is_skewed() -> bool {
+    SKEW.lock().unwrap().is_warning()
+}
+
+/// Reports the latest clock skew.
+pub fn report() {
+    if let Some(skew) = skew() {
+        dd_gauge!("main.gauge.system.clock.skew", skew.to_string());
+    }
+}
+
+/// Probes the chrony tracking report.
+pub async fn chrony_tracking() -> Result<Tracking> {
+    let output = Command::new(CHRONYC_BIN)
+        .args(["-c", "tracking"])
+        .output()
+        .await
+        .wrap_err("running `chronyc`")?;
+    if !output.status.success() {
+        bail!("`chronyc` terminated unsuccessfully");
+    }
+    Tracking::parse(str::from_utf8(&output.stdout).wrap_err("parsing `chronyc` output")?)
+}
+
+/// Returns the current time synchronization quality.
+pub async fn time_sync() -> TimeSync {
+    let chrony = chrony_tracking()
+        .await
+        .inspect_err(|err| tracing::debug!("Couldn't probe chrony: {err:?}"))
+        .ok();
+    TimeSync { skew: skew(), skew_warning: is_skewed(), chrony }
+}
+
+fn signed_seconds(a: SystemTime, b: SystemTime) -> f64 {
+    match a.duration_since(b) {
+        Ok(duration) => duration.as_secs_f64(),
+        Err(err) => -err.duration().as_secs_f64(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skew() {
+        let backend = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut skew = Skew::default();
+        let sent = backend + Duration::from_millis(200);
+        assert_eq!(skew.push(backend, sent, sent + Duration::from_millis(600)), None);
+        assert!(skew.seconds().unwrap().abs() < 1.0);
+
+        let sent = backend + CLOCK_SKEW_WARNING_THRESHOLD * 2;
+        assert_eq!(skew.push(backend, sent, sent), Some(true));
+        assert!(skew.is_warning());
+        assert!(skew.seconds().unwrap() > 0.0);
+
+        let sent = backend + CLOCK_SKEW_WARNING_THRESHOLD * 3 / 4;
+        assert_eq!(skew.push(backend, sent, sent), None);
+        assert!(skew.is_warning());
+
+        let sent = backend - CLOCK_SKEW_WARNING_THRESHOLD / 4;
+        assert_eq!(skew.push(backend, sent, sent), Some(false));
+        assert!(skew.seconds().unwrap() < 0.0);
+    }
+
+    #[test]
+    fn test_parse_chrony_tracking() {
+        let tracking = Tracking::parse(
+            "C0A80001,192.168.0.1,3,1585921830.429939222,0.000007002,0.000007141,0.000091025,-10.\
+             123,-0.001,0.025,0.003282165,0.000408211,128.9,Normal\n",
+        )
+        .unwrap();
+        assert_eq!(tracking.reference, "192.168.0.1");
+        assert_eq!(tracking.stratum, 3);
+        assert!((tracking.frequency + 10.123).abs() < f64::EPSILON);
+        assert!(tracking.is_synchronized());
+
+        let tracking = Tracking::parse(
+            "00000000,,0,0.000000000,0.000000000,0.000000000,0.000000000,0.000,0.000,0.000,1.\
+             000000000,1.000000000,0.0,Not synchronised",
+        )
+        .unwrap();
+        assert!(!tracking.is_synchronized());
+        assert!(Tracking::parse("garbage").is_err());
+    }
+}
@@ -0,0 +1,395 @@
+//! GPS fix tracking.
+//!
+//! The main MCU forwards the NMEA sentences of the GPS receiver. The
+//! [`Tracker`] folds them into the fix quality, the HDOP, the satellite count,
+//! and the position averaged over the last [`WINDOW`] fixes. The fix validity
+//! comes from the GGA quality, the GNS mode, the RMC status, and the GLL data
+//! validity, and the sentences reporting the same epoch count as a single fix.
+//! Fixes farther than [`OUTLIER_DISTANCE`] from the median position are
+//! rejected as multipath outliers. When the fix is lost, the last position is dead-reckoned from the
+//! last known speed and course for up to [`DEAD_RECKONING_TIMEOUT`].
+
+use crate::{
+    backend::operator_status::Coordinates,
+    consts::{GPS_MAX_HDOP, GPS_MIN_SATELLITES},
+};
+use nmea_parser::{
+    gnss::{GgaQualityIndicator, GnsModeIndicator},
+    ParsedMessage,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Number of the latest fixes to average.
+pub const WINDOW: usize = 120;
+
+/// Distance in meters from the median position after which a fix is rejected.
+pub const OUTLIER_DISTANCE: f64 = 50.0;
+
+/// Time without a fix after which the position is dead-reckoned.
+pub const FIX_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time without a fix after which no location is reported.
+pub const DEAD_RECKONING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Speed in meters per second below which the orb is considered stationary.
+const STATIONARY_SPEED: f64 = 0.5;
+
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+const METERS_PER_SECOND_PER_KNOT: f64 = 0.514_444;
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+/// GPS fix tracker.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    fixes: VecDeque<Coordinates>,
+    last_fix: Option<Instant>,
+    /// UTC time of day in milliseconds of the latest averaged fix.
+    last_epoch: Option<i64>,
+    fix_quality: FixQuality,
+    hdop: Option<f64>,
+    satellite_count: Option<u8>,
+    /// Speed in meters per second and course in degrees from the true north.
+    velocity: Option<(f64, f64)>,
+}
+
+/// Quality of the GPS fix.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FixQuality {
+    /// No fix.
+    #[default]
+    None,
+    /// Standalone GPS fix.
+    Gps,
+    /// Differential GPS fix.
+    Differential,
+    /// Real-time kinematic fix.
+    Rtk,
+    /// Position estimated by the receiver, entered manually, or simulated.
+    Estimated,
+}
+
+/// GPS location snapshot.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct Location {
+    /// Averaged latitude in degrees.
+    pub latitude: f64,
+    /// Averaged longitude in degrees.
+    pub longitude: f64,
+    /// Quality of the latest fix.
+    pub fix_quality: FixQuality,
+    /// Latest horizontal dilution of precision.
+    pub hdop: Option<f64>,
+    /// Latest number of satellites in use.
+    pub satellite_count: Option<u8>,
+    /// Number of the averaged fixes.
+    pub samples: usize,
+    /// Number of the fixes rejected as outliers.
+    pub rejected: usize,
+    /// Time since the latest fix in seconds.
+    pub age: f64,
+    /// Whether the position is dead-reckoned because the fix is lost.
+    pub dead_reckoning: bool,
+}
+
+impl Tracker {
+    /// Updates the tracker with an NMEA message.
+    pub fn push(&mut self, message: &ParsedMessage, now: Instant) {
+        let (timestamp, latitude, longitude) = match message {
+            ParsedMessage::Gga(message) => {
+                self.fix_quality = message.quality.into();
+                self.hdop = message.hdop;
+                self.satellite_count = message.satellite_count;
+                (message.timestamp, message.latitude, message.longitude)
+            }
+            ParsedMessage::Gns(message) => {
+                self.fix_quality = match message.gps_mode {
+                    GnsModeIndicator::Invalid => message.glonass_mode,
+                    mode => mode,
+                }
+                .into();
+                self.hdop = message.hdop.or(self.hdop);
+                self.satellite_count = message.satellite_count.or(self.satellite_count);
+                (message.timestamp, message.latitude, message.longitude)
+            }
+            ParsedMessage::Rmc(message) => {
+                self.update_validity(message.status_active);
+                if let (Some(speed), Some(course)) = (message.sog_knots, message.bearing) {
+                    self.velocity = Some((speed * METERS_PER_SECOND_PER_KNOT, course));
+                }
+                (message.timestamp, message.latitude, message.longitude)
+            }
+            ParsedMessage::Gll(message) => {
+                self.update_validity(message.data_valid);
+                (message.timestamp, message.latitude, message.longitude)
+            }
+            _ => return,
+        };
+        if self.fix_quality == FixQuality::None {
+            return;
+        }
+        if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+            // The receiver reports the same epoch in several sentences. Only the
+            // first one is averaged, the rest only refresh the fix age.
+            let epoch = timestamp.map(|timestamp| timestamp.timestamp_millis() % MILLIS_PER_DAY);
+            if epoch.is_some() && epoch == self.last_epoch {
+                self.last_fix = Some(now);
+                return;
+            }
+            self.last_epoch = epoch;
+            self.push_fix(Coordinates { latitude, longitude }, now);
+        }
+    }
+
+    /// Updates the fix quality from the status flag of the sentences without
+    /// the quality indicator.
+    fn update_validity(&mut self, valid: Option<bool>) {
+        match valid {
+            Some(false) => self.fix_quality = FixQuality::None,
+            Some(true) if self.fix_quality == FixQuality::None => {
+                self.fix_quality = FixQuality::Gps;
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the current location, or `None` if there was no fix within
+    /// [`DEAD_RECKONING_TIMEOUT`].
+    #[must_use]
+    pub fn location(&self, now: Instant) -> Option<Location> {
+        let age = now.saturating_duration_since(self.last_fix?);
+        if age > DEAD_RECKONING_TIMEOUT {
+            return None;
+        }
+        let (mut position, samples) = self.average()?;
+        let dead_reckoning = age > FIX_TIMEOUT;
+        if dead_reckoning {
+            if let Some((speed, course)) =
+                self.velocity.filter(|(speed, _)| *speed >= STATIONARY_SPEED)
+            {
+                position = displace(&position, speed * age.as_secs_f64(), course);
+            }
+        }
+        Some(Location {
+            latitude: position.latitude,
+            longitude: position.longitude,
+            fix_quality: if dead_reckoning { FixQuality::None } else { self.fix_quality },
+            hdop: self.hdop,
+            satellite_count: self.satellite_count,
+            samples,
+            rejected: self.fixes.len() - samples,
+            age: age.as_secs_f64(),
+            dead_reckoning,
+        })
+    }
+
+    fn push_fix(&mut self, fix: Coordinates, now: Instant) {
+        if self.fixes.len() == WINDOW {
+            self.fixes.pop_front();
+        }
+        self.fixes.push_back(fix);
+        self.last_fix = Some(now);
+    }
+
+    /// Returns the mean of the fixes within [`OUTLIER_DISTANCE`] from the
+    /// median, and the number of such fixes.
+    fn average(&self) -> Option<(Coordinates, usize)> {
+        let median = Coordinates {
+            latitude: median(self.fixes.iter().map(|fix| fix.latitude))?,
+            longitude: median(self.fixes.iter().map(|fix| fix.longitude))?,
+        };
+        let inliers = self
+            .fixes
+            .iter()
+            .filter(|fix| fix.distance(&median) <= OUTLIER_DISTANCE)
+            .collect::<Vec<_>>();
+        if inliers.is_empty() {
+            return Some((median, 0));
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let len = inliers.len() as f64;
+        let average = Coordinates {
+            latitude: inliers.iter().map(|fix| fix.latitude).sum::<f64>() / len,
+            longitude: inliers.iter().map(|fix| fix.longitude).sum::<f64>() / len,
+        };
+        Some((average, inliers.len()))
+    }
+}
+
+impl Location {
+    /// Returns `true` if the location comes from a current fix precise enough
+    /// for the operator location checks.
+    #[must_use]
+    pub fn is_reliable(&self) -> bool {
+        !self.dead_reckoning
+            && matches!(
+                self.fix_quality,
+                FixQuality::Gps | FixQuality::Differential | FixQuality::Rtk
+            )
+            && !self.hdop.is_some_and(|hdop| hdop > GPS_MAX_HDOP)
+            && !self.satellite_count.is_some_and(|count| count < GPS_MIN_SATELLITES)
+    }
+}
+
+impl From<GgaQualityIndicator> for FixQuality {
+    fn from(quality: GgaQualityIndicator) -> Self {
+        match quality {
+            GgaQualityIndicator::Invalid => Self::None,
+            GgaQualityIndicator::GpsFix => Self::Gps,
+            GgaQualityIndicator::DGpsFix | GgaQualityIndicator::PpsFix => Self::Differential,
+            GgaQualityIndicator::RealTimeKinematic
+            | GgaQualityIndicator::RealTimeKinematicFloat => Self::Rtk,
+            GgaQualityIndicator::DeadReckoning
+            | GgaQualityIndicator::ManualInputMode
+            | GgaQualityIndicator::SimulationMode => Self::Estimated,
+        }
+    }
+}
+
+impl From<GnsModeIndicator> for FixQuality {
+    fn from(mode: GnsModeIndicator) -> Self {
+        match mode {
+            GnsModeIndicator::Invalid => Self::None,
+            GnsModeIndicator::Autonomous => Self::Gps,
+            GnsModeIndicator::Differential | GnsModeIndicator::Precise => Self::Differential,
+            GnsModeIndicator::RealTimeKinematic | GnsModeIndicator::RealTimeKinematicFloat => {
+                Self::Rtk
+            }
+            GnsModeIndicator::DeadReckoning
+            | GnsModeIndicator::ManualInputMode
+            | GnsModeIndicator::SimulationMode => Self::Estimated,
+        }
+    }
+}
+
+fn median(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let mut values = values.collect::<Vec<_>>();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    Some(if values.len() % 2 == 0 { (values[mid - 1] + values[mid]) / 2.0 } else { values[mid] })
+}
+
+/// Moves the position by `distance` meters along the `course` in degrees.
+fn displace(position: &Coordinates, distance: f64, course: f64) -> Coordinates {
+    let (sin, cos) = course.to_radians().sin_cos();
+    let latitude = position.latitude + (distance * cos / EARTH_RADIUS).to_degrees();
+    let longitude = position.longitude
+        + (distance * sin / (EARTH_RADIUS * position.latitude.to_radians().cos())).to_degrees();
+    Coordinates { latitude, longitude }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nmea_parser::NmeaParser;
+
+    fn parse(sentence: &str) -> ParsedMessage {
+        NmeaParser::new().parse_sentence(sentence).unwrap()
+    }
+
+    #[test]
+    fn test_push_gga() {
+        let now = Instant::now();
+        let mut tracker = Tracker::default();
+        assert!(tracker.location(now).is_none());
+        tracker
+            .push(&parse("$GPGGA,123519,5231.200,N,01324.300,E,1,08,0.9,545.4,M,46.9,M,,*45"), now);
+        let location = tracker.location(now).unwrap();
+        assert!((location.latitude - 52.52).abs() < 1e-9);
+        assert!((location.longitude - 13.405).abs() < 1e-9);
+        assert_eq!(location.fix_quality, FixQuality::Gps);
+        assert_eq!(location.satellite_count, Some(8));
+        assert!(location.is_reliable());
+
+        tracker.push(&parse("$GPGGA,123520,,,,,0,00,,,M,,M,,*61"), now);
+        let location = tracker.location(now).unwrap();
+        assert_eq!(location.fix_quality, FixQuality::None);
+        assert_eq!(location.samples, 1);
+        assert!(!location.is_reliable());
+    }
+
+    #[test]
+    fn test_epoch_deduplication() {
+        let now = Instant::now();
+        let mut tracker = Tracker::default();
+        tracker
+            .push(&parse("$GPGGA,123519,5231.200,N,01324.300,E,1,08,0.9,545.4,M,46.9,M,,*45"), now);
+        tracker.push(
+            &parse("$GPRMC,123519,A,5231.200,N,01324.300,E,000.0,000.0,230394,003.1,W*64"),
+            now,
+        );
+        tracker.push(&parse("$GPGLL,5231.200,N,01324.300,E,123519,A,A*4A"), now);
+        assert_eq!(tracker.location(now).unwrap().samples, 1);
+    }
+
+    #[test]
+    fn test_status_validity() {
+        let now = Instant::now();
+        let mut tracker = Tracker::default();
+        tracker.push(&parse("$GNGNS,123521,5231.200,N,01324.300,E,AN,08,0.9,545.4,46.9,,*75"), now);
+        let location = tracker.location(now).unwrap();
+        assert_eq!(location.fix_quality, FixQuality::Gps);
+        assert!(location.is_reliable());
+
+        tracker.push(&parse("$GPRMC,123520,V,5231.200,N,01324.300,E,,,230394,003.1,W*79"), now);
+        let location = tracker.location(now).unwrap();
+        assert_eq!(location.fix_quality, FixQuality::None);
+        assert_eq!(location.samples, 1);
+        assert!(!location.is_reliable());
+    }
+
+    #[test]
+    fn test_outlier_rejection() {
+        let now = Instant::now();
+        let mut tracker = Tracker::default();
+        for i in 0..10 {
+            let offset = if i % 2 == 0 { 0.00001 } else { -0.00001 };
+            tracker.push_fix(Coordinates { latitude: 52.52 + offset, longitude: 13.405 }, now);
+        }
+        tracker.push_fix(Coordinates { latitude: 52.6, longitude: 13.405 }, now);
+        let location = tracker.location(now).unwrap();
+        assert_eq!(location.samples, 10);
+        assert_eq!(location.rejected, 1);
+        assert!((location.latitude - 52.52).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_dead_reckoning() {
+        let now = Instant::now();
+        let mut tracker = Tracker::default();
+        tracker.push_fix(Coordinates { latitude: 52.52, longitude: 13.405 }, now);
+        tracker.velocity = Some((10.0, 0.0));
+        assert!(!tracker.location(now).unwrap().dead_reckoning);
+        let later = now + Duration::from_secs(10);
+        let location = tracker.location(later).unwrap();
+        assert!(location.dead_reckoning);
+        assert!(!location.is_reliable());
+        let start = Coordinates { latitude: 52.52, longitude: 13.405 };
+        let moved = Coordinates { latitude: location.latitude, longitude: location.longitude };
+        assert!((start.distance(&moved) - 100.0).abs() < 1.0);
+        assert!(tracker.location(now + DEAD_RECKONING_TIMEOUT * 2).is_none());
+    }
+
+    #[test]
+    fn test_stationary_dead_reckoning() {
+        let now = Instant::now();
+        let mut tracker = Tracker::default();
+        tracker.push(
+            &parse("$GPRMC,123519,A,5231.200,N,01324.300,E,000.0,000.0,230394,003.1,W*64"),
+            now,
+        );
+        let location = tracker.location(now + Duration::from_secs(30)).unwrap();
+        assert!(location.dead_reckoning);
+        assert!((location.latitude - 52.52).abs() < 1e-9);
+    }
+}
@@ -1,4 +1,8 @@
 //! Resource monitors.
 
+pub mod clock;
 pub mod cpu;
+pub mod gps;
 pub mod net;
+pub mod thermal;
+pub mod traffic;
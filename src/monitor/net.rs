@@ -1,15 +1,23 @@
 //! Network monitor.
 //! Ping remote host using ICMP PING packets and report the round-trip time.
 //! Use ICMP PING sockets <https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/commit/?id=c319b4d76b9e583a5d88d6bf190e079c4e43213d>
+//!
+//! Additionally probes DNS, generic internet, the relay, and the signup backend
+//! separately to tell "no internet" from "backend unreachable", see
+//! [`Reachability`].
 use crate::{
-    backend::endpoints::NETWORK_MONITOR_HOST,
+    backend::{
+        self,
+        endpoints::{NETWORK_MONITOR_HOST, RELAY_BACKEND_URL, SIGNUP_BACKEND_URL},
+    },
     config::Config,
+    monitor::{clock, traffic},
     network::WPA_SUPPLICANT_INTERFACE_BIN,
     pid::{derivative::LowPassFilter, InstantTimer, Timer},
     process::Command,
     utils::spawn_named_thread,
 };
-use eyre::{bail, Result, WrapErr};
+use eyre::{bail, eyre, Result, WrapErr};
 use futures::{channel::oneshot, prelude::*, ready};
 use pnet::{
     datalink,
@@ -27,9 +35,10 @@ use pnet::{
         Packet,
     },
 };
+use serde::{Deserialize, Serialize};
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use std::{
-    io,
+    fs, io,
     io::Read,
     net::{IpAddr, SocketAddr, ToSocketAddrs},
     pin::Pin,
@@ -55,6 +64,11 @@ const SSID_POLLING_DIVIDER: u16 = 5;
 const REPORT_CAPACITY: usize = 10;
 const LAG_FILTER_RC: f64 = 2.0;
 const RSSI_FILTER_RC: f64 = 1.5;
+const REACHABILITY_POLLING_DIVIDER: u16 = 30;
+const TRAFFIC_REPORT_DIVIDER: u16 = 60;
+const REACHABILITY_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+const INTERNET_PROBE_URL: &str = "http://connectivitycheck.gstatic.com/generate_204";
+const ROUTE_TABLE_PATH: &str = "/proc/net/route";
 
 /// Network monitor trait.
 pub trait Monitor: Stream<Item = Report> + Send + Unpin {
@@ -89,6 +103,38 @@ pub struct Report {
     pub ssid: String,
     /// Mac address of the WiFi interface.
     pub mac_address: String,
+    /// Latest per-endpoint reachability matrix, if probed yet.
+    pub reachability: Option<Reachability>,
+}
+
+/// Per-endpoint reachability matrix.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reachability {
+    /// The signup backend host name resolves.
+    pub dns: bool,
+    /// The generic internet connectivity check endpoint returns 204.
+    pub internet: bool,
+    /// The relay backend responds.
+    pub relay: bool,
+    /// The signup backend responds.
+    pub signup_backend: bool,
+}
+
+/// The most specific network failure derived from a [`Reachability`] matrix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Diagnosis {
+    /// All endpoints are reachable.
+    Ok,
+    /// Neither DNS nor the generic internet endpoint work.
+    NoInternet,
+    /// The internet works, but the backend host name doesn't resolve.
+    DnsFailure,
+    /// The internet works, but the signup backend doesn't respond.
+    BackendUnreachable,
+    /// The signup backend works, but the relay doesn't respond.
+    RelayUnreachable,
 }
 
 impl Monitor for Jetson {
@@ -222,6 +268,26 @@ impl Report {
     }
 }
 
+impl Reachability {
+    /// Returns the most specific failure of the matrix.
+    #[must_use]
+    pub fn diagnosis(&self) -> Diagnosis {
+        if !self.internet && !self.dns {
+            Diagnosis::NoInternet
+        } else if !self.dns {
+            Diagnosis::DnsFailure
+        } else if !self.internet && !self.signup_backend {
+            Diagnosis::NoInternet
+        } else if !self.signup_backend {
+            Diagnosis::BackendUnreachable
+        } else if !self.relay {
+            Diagnosis::RelayUnreachable
+        } else {
+            Diagnosis::Ok
+        }
+    }
+}
+
 /// Makes a single ping-pong with the backend server.
 pub fn ping(remote: &str) -> io::Result<f64> {
     let mut socket = PingSocket::new(remote)?;
@@ -235,6 +301,7 @@ fn main_loop(report_tx: &broadcast::Sender<Report>, config: &Arc<Mutex<Config>>)
     let mut rssi_timer = InstantTimer::default();
     let mut rssi_filter = LowPassFilter::default();
     let mut ssid = String::new();
+    let mut reachability = None;
     let mut sequence_number: u16 = 0;
 
     let mut socket = loop {
@@ -272,6 +339,21 @@ fn main_loop(report_tx: &broadcast::Sender<Report>, config: &Arc<Mutex<Config>>)
                 tracing::debug!("Couldn't poll SSID: {err}");
                 String::new()
             });
+            match poll_link() {
+                Ok(link) => traffic::set_link(link),
+                Err(err) => tracing::debug!("Couldn't poll the default route: {err}"),
+            }
+        }
+        if sequence_number % REACHABILITY_POLLING_DIVIDER == 0 {
+            let matrix = probe_reachability(&rt);
+            if reachability != Some(matrix) {
+                tracing::info!("Network reachability: {matrix:?} ({:?})", matrix.diagnosis());
+            }
+            reachability = Some(matrix);
+        }
+        if sequence_number % TRAFFIC_REPORT_DIVIDER == 0 {
+            traffic::report();
+            clock::report();
         }
 
         // Get what we want from the config and drop the mutex fast.
@@ -283,6 +365,7 @@ fn main_loop(report_tx: &broadcast::Sender<Report>, config: &Arc<Mutex<Config>>)
                 rssi,
                 ssid: ssid.clone(),
                 mac_address: mac_address().unwrap_or_default(),
+                reachability,
             })
             .is_err()
         {
@@ -293,6 +376,36 @@ fn main_loop(report_tx: &broadcast::Sender<Report>, config: &Arc<Mutex<Config>>)
     }
 }
 
+fn probe_reachability(rt: &runtime::Runtime) -> Reachability {
+    let dns = resolve_addr(&NETWORK_MONITOR_HOST).is_ok();
+    let (internet, relay, signup_backend) = rt.block_on(async {
+        let generic_client = reqwest::Client::builder().timeout(REACHABILITY_PROBE_TIMEOUT).build();
+        let backend_client =
+            backend::client_with_timeouts(REACHABILITY_PROBE_TIMEOUT, REACHABILITY_PROBE_TIMEOUT);
+        let internet = async {
+            let response = generic_client.ok()?.get(INTERNET_PROBE_URL).send().await.ok()?;
+            Some(response.status() == reqwest::StatusCode::NO_CONTENT)
+        };
+        let backend = |url: &'static str| {
+            let client = backend_client.as_ref().ok().cloned();
+            // Any HTTP response means the backend is reachable.
+            async move { client?.get(url).send().await.ok().map(|_| true) }
+        };
+        future::join3(
+            internet,
+            backend(RELAY_BACKEND_URL.as_str()),
+            backend(SIGNUP_BACKEND_URL.as_str()),
+        )
+        .await
+    });
+    Reachability {
+        dns,
+        internet: internet.unwrap_or(false),
+        relay: relay.unwrap_or(false),
+        signup_backend: signup_backend.unwrap_or(false),
+    }
+}
+
 fn poll_rssi() -> Result<i64> {
     let output = Command::new(WPA_SUPPLICANT_INTERFACE_BIN)
         .arg("signal")
@@ -431,6 +544,28 @@ fn resolve_addr(remote: &str) -> io::Result<SocketAddr> {
     }
 }
 
+fn poll_link() -> Result<traffic::Link> {
+    let routes = fs::read_to_string(ROUTE_TABLE_PATH)?;
+    let interface = default_route_interface(&routes).ok_or_else(|| eyre!("no default route"))?;
+    Ok(traffic::Link::of_interface(interface))
+}
+
+/// Returns the interface of the default route with the lowest metric from the
+/// kernel routing table.
+fn default_route_interface(routes: &str) -> Option<&str> {
+    routes
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let (interface, destination, metric) =
+                (fields.first()?, fields.get(1)?, fields.get(6)?);
+            (*destination == "00000000").then_some((metric.parse::<u32>().ok()?, *interface))
+        })
+        .min()
+        .map(|(_, interface)| interface)
+}
+
 fn mac_address() -> Option<String> {
     datalink::interfaces()
         .iter()
@@ -444,7 +579,10 @@ fn mac_address() -> Option<String> {
 //
 #[cfg(test)]
 mod tests {
-    use crate::{backend::endpoints::NETWORK_MONITOR_HOST, monitor::net::ping};
+    use crate::{
+        backend::endpoints::NETWORK_MONITOR_HOST,
+        monitor::net::{default_route_interface, ping, Diagnosis, Reachability},
+    };
 
     /// IPv4 has no *official* blackhole address, use a IP range reserved for documentation (TEST-NET-3)
     /// https://datatracker.ietf.org/doc/html/rfc5735#section-4
@@ -485,4 +623,37 @@ mod tests {
         assert!(ret.is_err());
         assert_eq!(ret.err().unwrap().kind(), std::io::ErrorKind::WouldBlock);
     }
+
+    #[test]
+    fn test_default_route_interface() {
+        let routes = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wwan0\t00000000\t0100A8C0\t0003\t0\t0\t700\t00000000\t0\t0\t0
+wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+wlan0\t0001A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0
+";
+        assert_eq!(default_route_interface(routes), Some("wlan0"));
+        let (lte_only, _) = routes.split_at(routes.find("wlan0").unwrap());
+        assert_eq!(default_route_interface(lte_only), Some("wwan0"));
+        assert_eq!(default_route_interface(""), None);
+    }
+
+    #[test]
+    fn test_reachability_diagnosis() {
+        let ok = Reachability { dns: true, internet: true, relay: true, signup_backend: true };
+        assert_eq!(ok.diagnosis(), Diagnosis::Ok);
+        assert_eq!(Reachability::default().diagnosis(), Diagnosis::NoInternet);
+        assert_eq!(Reachability { dns: false, ..ok }.diagnosis(), Diagnosis::DnsFailure);
+        assert_eq!(
+            Reachability { internet: false, signup_backend: false, ..ok }.diagnosis(),
+            Diagnosis::NoInternet
+        );
+        assert_eq!(
+            Reachability { signup_backend: false, relay: false, ..ok }.diagnosis(),
+            Diagnosis::BackendUnreachable
+        );
+        assert_eq!(Reachability { relay: false, ..ok }.diagnosis(), Diagnosis::RelayUnreachable);
+        // A blocked generic endpoint alone doesn't matter for the orb.
+        assert_eq!(Reachability { internet: false, ..ok }.diagnosis(), Diagnosis::Ok);
+    }
 }
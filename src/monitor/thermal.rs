@@ -0,0 +1,245 @@
+//! Thermal throttling predictor.
+//!
+//! In hot venues the Jetson CPU and GPU clocks throttle in the middle of a
+//! signup, which makes the biometric pipeline latency unpredictable. The
+//! [`Predictor`] fits a linear trend to the recent Jetson thermal zone readings
+//! and estimates when [`THERMAL_THROTTLING_TEMPERATURE`] will be reached. When
+//! a signup is expected, i.e. a user approaches the orb, and the throttling is
+//! predicted during it, the orb pre-cools for `thermal_precool_lead_time`: the
+//! fan runs at the maximum speed, and the background tasks, like the image
+//! uploads and the compaction, are deferred.
+//!
+//! The [`CameraSensor`] tracks the temperature of the thermal camera sensor,
+//! which sits inside the enclosure, and alerts when it overheats.
+
+use crate::{
+    consts::{
+        THERMAL_CAMERA_OVERHEAT_HYSTERESIS, THERMAL_CAMERA_OVERHEAT_TEMPERATURE,
+        THERMAL_CAMERA_TEMPERATURE_REPORT_INTERVAL, THERMAL_PRECOOL_CHECK_DELAY,
+        THERMAL_THROTTLING_HORIZON, THERMAL_THROTTLING_TEMPERATURE, THERMAL_TREND_WINDOW,
+    },
+    dd_gauge, dd_incr,
+};
+use once_cell::sync::Lazy;
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Minimal number of samples for a trend estimate.
+const MIN_SAMPLES: usize = 10;
+
+static PRE_COOLING_UNTIL: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+
+/// Jetson temperature trend tracker.
+#[derive(Debug, Default)]
+pub struct Predictor {
+    samples: VecDeque<(Instant, f64)>,
+}
+
+impl Predictor {
+    /// Records the latest CPU and GPU temperatures in degrees Celsius.
+    pub fn push(&mut self, cpu: i16, gpu: i16) {
+        self.push_at(Instant::now(), cpu, gpu);
+    }
+
+    /// Returns the estimated time until the throttling starts. Returns `None`
+    /// if the temperature doesn't rise or there is not enough data.
+    #[must_use]
+    pub fn time_to_throttling(&self) -> Option<Duration> {
+        self.time_to_throttling_at(Instant::now())
+    }
+
+    /// Starts the pre-cooling if the throttling is predicted during a signup
+    /// expected in `lead_time`. Returns `true` if the pre-cooling has just
+    /// started.
+    pub fn signup_expected(&self, lead_time: Duration) -> bool {
+        if lead_time.is_zero() || is_pre_cooling() {
+            return false;
+        }
+        let Some(time_to_throttling) = self.time_to_throttling() else {
+            return false;
+        };
+        dd_gauge!(
+            "main.gauge.system.thermal.time_to_throttling",
+            time_to_throttling.as_secs().to_string()
+        );
+        if time_to_throttling > lead_time + THERMAL_THROTTLING_HORIZON {
+            return false;
+        }
+        tracing::info!(
+            "Jetson throttling predicted in {}s, pre-cooling for {}s",
+            time_to_throttling.as_secs(),
+            lead_time.as_secs()
+        );
+        dd_incr!("main.count.system.thermal.precool");
+        *PRE_COOLING_UNTIL.lock().unwrap() = Some(Instant::now() + lead_time);
+        true
+    }
+
+    fn push_at(&mut self, now: Instant, cpu: i16, gpu: i16) {
+        // Unreadable sensors are reported as `i16::MIN`.
+        let temperature = cpu.max(gpu);
+        if temperature == i16::MIN {
+            return;
+        }
+        self.samples.push_back((now, f64::from(temperature)));
+        while self
+            .samples
+            .front()
+            .is_some_and(|&(t, _)| now.duration_since(t) > THERMAL_TREND_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn time_to_throttling_at(&self, now: Instant) -> Option<Duration> {
+        if self.samples.len() < MIN_SAMPLES {
+            return None;
+        }
+        let (origin, _) = *self.samples.front()?;
+        let n = self.samples.len() as f64;
+        let x = |t: Instant| t.duration_since(origin).as_secs_f64();
+        let mean_x = self.samples.iter().map(|&(t, _)| x(t)).sum::<f64>() / n;
+        let mean_y = self.samples.iter().map(|&(_, y)| y).sum::<f64>() / n;
+        let (covariance, variance) =
+            self.samples.iter().fold((0.0, 0.0), |(covariance, variance), &(t, y)| {
+                let dx = x(t) - mean_x;
+                (covariance + dx * (y - mean_y), variance + dx * dx)
+            });
+        if variance <= 0.0 {
+            return None;
+        }
+        let slope = covariance / variance;
+        let current = mean_y + slope * (x(now) - mean_x);
+        if current >= THERMAL_THROTTLING_TEMPERATURE {
+            return Some(Duration::ZERO);
+        }
+        (slope > 0.0)
+            .then(|| Duration::from_secs_f64((THERMAL_THROTTLING_TEMPERATURE - current) / slope))
+    }
+}
+
+/// Thermal camera sensor temperature tracker.
+#[derive(Debug, Default)]
+pub struct CameraSensor {
+    temperature: Option<f32>,
+    overheated: bool,
+    last_report: Option<Instant>,
+}
+
+impl CameraSensor {
+    /// Records the latest sensor temperature in Kelvin. Returns `Some(true)`
+    /// when the sensor has just overheated, and `Some(false)` when it has just
+    /// cooled down.
+    pub fn push(&mut self, temperature: f32) -> Option<bool> {
+        self.push_at(Instant::now(), temperature)
+    }
+
+    /// Returns the latest sensor temperature in Kelvin.
+    #[must_use]
+    pub fn temperature(&self) -> Option<f32> {
+        self.temperature
+    }
+
+    /// Returns `true` if the sensor is overheated.
+    #[must_use]
+    pub fn is_overheated(&self) -> bool {
+        self.overheated
+    }
+
+    fn push_at(&mut self, now: Instant, temperature: f32) -> Option<bool> {
+        self.temperature = Some(temperature);
+        if self.last_report.map_or(true, |last| {
+            now.duration_since(last) >= THERMAL_CAMERA_TEMPERATURE_REPORT_INTERVAL
+        }) {
+            self.last_report = Some(now);
+            dd_gauge!("main.gauge.system.thermal.camera_sensor", temperature.to_string());
+        }
+        let overheated = if self.overheated {
+            temperature > THERMAL_CAMERA_OVERHEAT_TEMPERATURE - THERMAL_CAMERA_OVERHEAT_HYSTERESIS
+        } else {
+            temperature > THERMAL_CAMERA_OVERHEAT_TEMPERATURE
+        };
+        if overheated == self.overheated {
+            return None;
+        }
+        self.overheated = overheated;
+        if overheated {
+            tracing::warn!("Thermal camera sensor overheated: {temperature:.1}K");
+        } else {
+            tracing::info!("Thermal camera sensor cooled down: {temperature:.1}K");
+        }
+        dd_incr!("main.count.system.thermal.camera_sensor", &format!("overheated:{overheated}"));
+        Some(overheated)
+    }
+}
+
+/// Returns `true` while the orb is pre-cooling.
+///
+/// # Panics
+///
+/// If the mutex is poisoned
+#[must_use]
+pub fn is_pre_cooling() -> bool {
+    PRE_COOLING_UNTIL.lock().unwrap().is_some_and(|until| Instant::now() < until)
+}
+
+/// Waits until the pre-cooling is over. Should be called by the background
+/// tasks before each unit of work.
+pub async fn defer_while_pre_cooling() {
+    while is_pre_cooling() {
+        tokio::time::sleep(THERMAL_PRECOOL_CHECK_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_to_throttling() {
+        let start = Instant::now();
+        let mut predictor = Predictor::default();
+        for i in 0..20 {
+            predictor.push_at(start + Duration::from_secs(i), 60, 55);
+        }
+        let now = start + Duration::from_secs(19);
+        assert_eq!(predictor.time_to_throttling_at(now), None);
+
+        let mut predictor = Predictor::default();
+        for i in 0..20 {
+            // Rising by 0.25 degrees per second, reaching 69 at the last sample.
+            let temperature = 60 + i16::try_from(i).unwrap() / 2;
+            predictor.push_at(start + Duration::from_secs(2 * i), temperature, i16::MIN);
+        }
+        let now = start + Duration::from_secs(38);
+        let time_to_throttling = predictor.time_to_throttling_at(now).unwrap().as_secs_f64();
+        assert!((40.0..47.0).contains(&time_to_throttling), "{time_to_throttling}");
+
+        let mut predictor = Predictor::default();
+        for i in 0..20 {
+            predictor.push_at(start + Duration::from_secs(i), 70, 85);
+        }
+        let now = start + Duration::from_secs(19);
+        assert_eq!(predictor.time_to_throttling_at(now), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_camera_sensor_overheat() {
+        let start = Instant::now();
+        let mut sensor = CameraSensor::default();
+        assert_eq!(sensor.push_at(start, 310.0), None);
+        assert_eq!(sensor.push_at(start, THERMAL_CAMERA_OVERHEAT_TEMPERATURE + 1.0), Some(true));
+        assert!(sensor.is_overheated());
+        let within_hysteresis =
+            THERMAL_CAMERA_OVERHEAT_TEMPERATURE - THERMAL_CAMERA_OVERHEAT_HYSTERESIS / 2.0;
+        assert_eq!(sensor.push_at(start, within_hysteresis), None);
+        assert!(sensor.is_overheated());
+        assert_eq!(sensor.push_at(start, 310.0), Some(false));
+        assert!(!sensor.is_overheated());
+        assert_eq!(sensor.temperature(), Some(310.0));
+    }
+}
@@ -0,0 +1,338 @@
+//! Network traffic accounting.
+//!
+//! Over a cellular connection the data is metered, so the traffic of each
+//! subsystem ([`Class`]) is accounted and aggregated into daily counters per
+//! [`Link`] of the default route, which reset at the UTC midnight. The HTTP
+//! traffic is accounted by the request and response body sizes, and the
+//! livestream by the bytes sent through its UDP socket, so the protocol
+//! overhead is not included. Once a bulk class exceeds its daily budget for the
+//! current link from the backend config, it is throttled until the next day or
+//! until the link changes. The bulk HTTP requests sent through [`send`] wait
+//! for the budget. The counters are kept only in memory.
+
+use super::clock;
+use crate::{consts::TRAFFIC_THROTTLE_POLL_INTERVAL, dd_count, dd_gauge, dd_incr};
+use once_cell::sync::Lazy;
+use reqwest::{header::CONTENT_LENGTH, RequestBuilder, Response};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::time;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+static ACCOUNTING: Lazy<Mutex<Accounting>> = Lazy::new(|| Mutex::new(Accounting::default()));
+
+/// Traffic class of a subsystem.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Class {
+    /// Background uploads of the signup data and reports.
+    Uploader,
+    /// Livestream video.
+    Livestream,
+    /// Periodic status reports.
+    Telemetry,
+    /// Backend requests controlling the signups and the orb.
+    Control,
+}
+
+/// Network link of the default route.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Link {
+    /// WiFi or any other unmetered link.
+    #[default]
+    Wifi,
+    /// Cellular modem.
+    Lte,
+}
+
+/// Traffic of a class in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Usage {
+    /// Bytes sent.
+    pub sent: u64,
+    /// Bytes received.
+    pub received: u64,
+}
+
+/// Daily traffic budgets of the bulk classes per link.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct Budgets {
+    /// Budgets over the cellular modem.
+    pub lte: LinkBudgets,
+    /// Budgets over WiFi.
+    pub wifi: LinkBudgets,
+}
+
+/// Daily traffic budgets of the bulk classes over a link in bytes. Zero
+/// disables the budget.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct LinkBudgets {
+    /// Budget of [`Class::Uploader`].
+    pub uploader: u64,
+    /// Budget of [`Class::Livestream`].
+    pub livestream: u64,
+}
+
+/// Daily traffic counters.
+#[derive(Debug, Default)]
+pub struct Accounting {
+    day: u64,
+    link: Link,
+    usage: [[Usage; Class::ALL.len()]; Link::ALL.len()],
+    budgets: Budgets,
+}
+
+impl Link {
+    /// All links.
+    pub const ALL: [Self; 2] = [Self::Wifi, Self::Lte];
+
+    /// Returns the link of the network interface `name`.
+    #[must_use]
+    pub fn of_interface(name: &str) -> Self {
+        if ["wwan", "rmnet", "ppp", "usb"].iter().any(|prefix| name.starts_with(prefix)) {
+            Self::Lte
+        } else {
+            Self::Wifi
+        }
+    }
+
+    /// Returns the metrics tag value of the link.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Wifi => "wifi",
+            Self::Lte => "lte",
+        }
+    }
+}
+
+impl Class {
+    /// All traffic classes.
+    pub const ALL: [Self; 4] = [Self::Uploader, Self::Livestream, Self::Telemetry, Self::Control];
+
+    /// Returns the metrics tag value of the class.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Uploader => "uploader",
+            Self::Livestream => "livestream",
+            Self::Telemetry => "telemetry",
+            Self::Control => "control",
+        }
+    }
+
+    /// Returns `true` if the class can be throttled.
+    #[must_use]
+    pub fn is_bulk(self) -> bool {
+        matches!(self, Self::Uploader | Self::Livestream)
+    }
+}
+
+impl Usage {
+    /// Returns the total bytes sent and received.
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.sent.saturating_add(self.received)
+    }
+}
+
+impl Accounting {
+    /// Updates the budgets.
+    pub fn set_budgets(&mut self, budgets: Budgets) {
+        self.budgets = budgets;
+    }
+
+    /// Updates the link the traffic goes through.
+    pub fn set_link(&mut self, link: Link) {
+        self.link = link;
+    }
+
+    /// Adds traffic of the class over the current link on the `day` since the
+    /// Unix epoch. Returns `true` if the class has just exceeded its budget.
+    pub fn add(&mut self, class: Class, sent: u64, received: u64, day: u64) -> bool {
+        self.roll_over(day);
+        let was_throttled = self.is_over_budget(class);
+        let usage = &mut self.usage[self.link as usize][class as usize];
+        usage.sent = usage.sent.saturating_add(sent);
+        usage.received = usage.received.saturating_add(received);
+        !was_throttled && self.is_over_budget(class)
+    }
+
+    /// Returns `true` if the class is throttled over the current link on the
+    /// `day` since the Unix epoch.
+    pub fn is_throttled(&mut self, class: Class, day: u64) -> bool {
+        self.roll_over(day);
+        self.is_over_budget(class)
+    }
+
+    /// Returns the traffic of the class over the link on the current day.
+    #[must_use]
+    pub fn usage(&self, link: Link, class: Class) -> Usage {
+        self.usage[link as usize][class as usize]
+    }
+
+    fn budget(&self, class: Class) -> u64 {
+        let budgets = match self.link {
+            Link::Wifi => &self.budgets.wifi,
+            Link::Lte => &self.budgets.lte,
+        };
+        match class {
+            Class::Uploader => budgets.uploader,
+            Class::Livestream => budgets.livestream,
+            Class::Telemetry | Class::Control => 0,
+        }
+    }
+
+    fn is_over_budget(&self, class: Class) -> bool {
+        let budget = self.budget(class);
+        class.is_bulk() && budget > 0 && self.usage(self.link, class).total() >= budget
+    }
+
+    fn roll_over(&mut self, day: u64) {
+        if day != self.day {
+            self.day = day;
+            self.usage = Default::default();
+        }
+    }
+}
+
+/// Updates the daily budgets from the configuration.
+pub fn set_budgets(budgets: Budgets) {
+    ACCOUNTING.lock().unwrap().set_budgets(budgets);
+}
+
+/// Updates the link of the default route.
+pub fn set_link(link: Link) {
+    let mut accounting = ACCOUNTING.lock().unwrap();
+    if accounting.link != link {
+        tracing::info!("Network traffic goes over {}", link.name());
+        accounting.set_link(link);
+    }
+}
+
+/// Accounts traffic of the class.
+pub fn record(class: Class, sent: u64, received: u64) {
+    let exceeded = ACCOUNTING.lock().unwrap().add(class, sent, received, today());
+    let tag = format!("class:{}", class.name());
+    dd_count!("main.count.net.traffic.sent", i64::try_from(sent).unwrap_or(i64::MAX), &tag);
+    dd_count!("main.count.net.traffic.received", i64::try_from(received).unwrap_or(i64::MAX), &tag);
+    if exceeded {
+        tracing::warn!("Daily {} traffic budget exceeded, throttling until tomorrow", class.name());
+        let link = format!("link:{}", ACCOUNTING.lock().unwrap().link.name());
+        dd_incr!("main.count.net.traffic.budget_exceeded", &tag, &link);
+    }
+}
+
+/// Returns `true` if the class exceeded its daily budget.
+#[must_use]
+pub fn is_throttled(class: Class) -> bool {
+    ACCOUNTING.lock().unwrap().is_throttled(class, today())
+}
+
+/// Waits until the class is back within its daily budget.
+pub async fn wait_budget(class: Class) {
+    if !is_throttled(class) {
+        return;
+    }
+    tracing::info!("{} traffic paused by the daily budget", class.name());
+    while is_throttled(class) {
+        time::sleep(TRAFFIC_THROTTLE_POLL_INTERVAL).await;
+    }
+}
+
+/// Reports the daily traffic of all classes.
+pub fn report() {
+    let mut accounting = ACCOUNTING.lock().unwrap();
+    accounting.roll_over(today());
+    for link in Link::ALL {
+        for class in Class::ALL {
+            let usage = accounting.usage(link, class);
+            dd_gauge!(
+                "main.gauge.net.traffic_daily",
+                usage.total().to_string(),
+                &format!("class:{}", class.name()),
+                &format!("link:{}", link.name())
+            );
+        }
+    }
+}
+
+/// Sends an HTTP request, accounting its traffic to the class. A request of a
+/// bulk class waits for the daily budget. The response date is fed to the
+/// [clock skew](clock) estimator, and a rejected request is flagged while the
+/// clock is skewed.
+pub async fn send(class: Class, request: RequestBuilder) -> reqwest::Result<Response> {
+    wait_budget(class).await;
+    let (client, request) = request.build_split();
+    let request = request?;
+    let sent = request
+        .body()
+        .and_then(reqwest::Body::as_bytes)
+        .map(|body| body.len() as u64)
+        .or_else(|| request.headers().get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok())
+        .unwrap_or(0);
+    let sent_at = SystemTime::now();
+    let response = client.execute(request).await?;
+    clock::observe_response(&response, sent_at);
+    if response.status().is_client_error() && clock::is_skewed() {
+        tracing::warn!(
+            "Request rejected with {} while the clock is skewed by {:.0}s",
+            response.status(),
+            clock::skew().unwrap_or_default()
+        );
+        dd_incr!("main.count.system.clock.skewed_rejection", &format!("class:{}", class.name()));
+    }
+    record(class, sent, response.content_length().unwrap_or(0));
+    Ok(response)
+}
+
+fn today() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs() / SECONDS_PER_DAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accounting() {
+        let mut accounting = Accounting::default();
+        accounting.set_budgets(Budgets {
+            lte: LinkBudgets { uploader: 1000, livestream: 0 },
+            wifi: LinkBudgets::default(),
+        });
+        accounting.set_link(Link::Lte);
+        assert!(!accounting.add(Class::Uploader, 600, 100, 1));
+        assert!(!accounting.add(Class::Livestream, 5000, 0, 1));
+        assert!(!accounting.add(Class::Control, 5000, 5000, 1));
+        assert!(!accounting.is_throttled(Class::Uploader, 1));
+        assert!(accounting.add(Class::Uploader, 300, 0, 1));
+        assert!(!accounting.add(Class::Uploader, 300, 0, 1));
+        assert!(accounting.is_throttled(Class::Uploader, 1));
+        assert!(!accounting.is_throttled(Class::Livestream, 1));
+        assert!(!accounting.is_throttled(Class::Control, 1));
+        let usage = accounting.usage(Link::Lte, Class::Uploader);
+        assert_eq!(usage, Usage { sent: 1200, received: 100 });
+        accounting.set_link(Link::Wifi);
+        assert!(!accounting.is_throttled(Class::Uploader, 1));
+        assert!(!accounting.add(Class::Uploader, 5000, 0, 1));
+        assert_eq!(accounting.usage(Link::Lte, Class::Uploader).total(), 1300);
+        accounting.set_link(Link::Lte);
+        assert!(accounting.is_throttled(Class::Uploader, 1));
+        assert!(!accounting.is_throttled(Class::Uploader, 2));
+        assert_eq!(accounting.usage(Link::Lte, Class::Uploader), Usage::default());
+    }
+
+    #[test]
+    fn test_link_of_interface() {
+        assert_eq!(Link::of_interface("wlan0"), Link::Wifi);
+        assert_eq!(Link::of_interface("eth0"), Link::Wifi);
+        assert_eq!(Link::of_interface("wwan0"), Link::Lte);
+        assert_eq!(Link::of_interface("rmnet_data0"), Link::Lte);
+    }
+}
@@ -238,7 +238,7 @@ impl Plan {
         orb.enable_mirror()?;
         orb.enable_distance()?;
         orb.enable_eye_tracker()?;
-        orb.enable_eye_pid_controller()?;
+        orb.start_eye_pid_controller().await?;
         orb.disable_image_notary();
         orb.main_mcu.send(mcu::main::Input::FrameRate(IR_CAMERA_FRAME_RATE)).await?;
         orb.main_mcu.send(mcu::main::Input::TriggeringIrEyeCamera(true)).await?;
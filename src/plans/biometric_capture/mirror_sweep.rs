@@ -1,17 +1,16 @@
 //! Mirror Sweep extension.
+
+pub mod fit;
+
 use super::ExtensionReport;
 use crate::{
     agents::{
-        camera, mirror,
+        camera,
+        mirror::{self, step_loss::Motor},
         python::{ir_net, rgb_net},
     },
     brokers::{Orb, OrbPlan},
-    consts::{
-        IR_CAMERA_FRAME_RATE, MIRROR_PHI_MAX_DIAMOND, MIRROR_PHI_MAX_PEARL, MIRROR_PHI_MIN_DIAMOND,
-        MIRROR_PHI_MIN_PEARL, MIRROR_THETA_MAX_DIAMOND, MIRROR_THETA_MAX_PEARL,
-        MIRROR_THETA_MIN_DIAMOND, MIRROR_THETA_MIN_PEARL,
-    },
-    identification,
+    consts::IR_CAMERA_FRAME_RATE,
     mcu::{self, main::IrLed},
     plans::{biometric_capture, biometric_capture::Output},
 };
@@ -82,6 +81,8 @@ pub struct SweepMetadata {
     spiral_center: (u32, u32),
     /// Calculated polynomial coefficients for sweep.
     sweep_polynomial: mcu::main::MirrorSweepPolynomial,
+    /// Inputs and quality of the polynomial fitting.
+    sweep_fit: fit::Fit,
 }
 
 /// Mirror sweep extension to biometric capture plan.
@@ -229,8 +230,8 @@ impl Plan {
         orb.ir_eye_save_fps_override = Some(f32::INFINITY);
         orb.ir_face_save_fps_override = Some(f32::INFINITY);
         orb.thermal_save_fps_override = Some(f32::INFINITY);
-        let polynomial = make_polynomial();
-        tracing::info!("Mirror Sweep polynomial: {polynomial:?}");
+        let (polynomial, sweep_fit) = self.fit_polynomial(orb);
+        tracing::info!("Mirror Sweep polynomial: {polynomial:?}, fit: {sweep_fit:?}");
         orb.main_mcu
             .send(mcu::main::Input::IrEyeCameraMirrorSweepValuesPolynomial(polynomial.clone()))
             .await?;
@@ -250,7 +251,7 @@ impl Plan {
         orb.enable_mirror()?;
         orb.enable_distance()?;
         orb.enable_eye_tracker()?;
-        orb.enable_eye_pid_controller()?;
+        orb.start_eye_pid_controller().await?;
         orb.disable_image_notary();
         orb.main_mcu.send(mcu::main::Input::FrameRate(IR_CAMERA_FRAME_RATE)).await?;
         orb.main_mcu.send(mcu::main::Input::TriggeringIrEyeCamera(true)).await?;
@@ -269,27 +270,36 @@ impl Plan {
             is_left_eye: !orb.target_left_eye(),
             spiral_center: self.last_point,
             sweep_polynomial: polynomial,
+            sweep_fit,
         };
         self.report.sweep_metadata.push(metadata);
 
         self.frame_counter = 0;
         self.timeout = Fuse::terminated();
-        let (phi, theta) = self.last_point;
-        let (phi, theta) = if identification::HARDWARE_VERSION.contains("Diamond") {
-            (
-                phi.clamp(MIRROR_PHI_MIN_DIAMOND, MIRROR_PHI_MAX_DIAMOND),
-                theta.clamp(MIRROR_THETA_MIN_DIAMOND, MIRROR_THETA_MAX_DIAMOND),
-            )
-        } else {
-            (
-                phi.clamp(MIRROR_PHI_MIN_PEARL, MIRROR_PHI_MAX_PEARL),
-                theta.clamp(MIRROR_THETA_MIN_PEARL, MIRROR_THETA_MAX_PEARL),
-            )
-        };
+        let (phi, theta) = fit::Limits::for_hardware().clamp(self.last_point);
         orb.main_mcu.send_now(mcu::main::Input::Mirror(phi, theta))?;
         Ok(())
     }
 
+    /// Fits the sweep polynomial to the room around the last mirror position.
+    fn fit_polynomial(&self, orb: &Orb) -> (mcu::main::MirrorSweepPolynomial, fit::Fit) {
+        let limits = fit::Limits::for_hardware();
+        // The mirror agent hasn't reported any position yet.
+        let center = if self.last_point == (0, 0) {
+            limits.calibrated_center(orb.calibration())
+        } else {
+            self.last_point
+        };
+        let range_ratio = {
+            let step_loss = orb.mirror_step_loss.lock().unwrap();
+            [step_loss.range_ratio(Motor::Phi), step_loss.range_ratio(Motor::Theta)]
+        };
+        // The initial angle is random to introduce an additional source of
+        // variation in the collected data.
+        let initial_angle = rand::thread_rng().gen::<f32>() * 2.0 * PI;
+        fit::fit(center, &limits, range_ratio, initial_angle)
+    }
+
     /// Check if extension execution has finished, i.e. all configured wavelengths
     /// have run.
     async fn extension_finished(&mut self, orb: &mut Orb) -> Result<bool> {
@@ -345,26 +355,3 @@ impl SweepConfiguration {
         wavelength_vec
     }
 }
-
-#[allow(clippy::cast_precision_loss)]
-fn make_polynomial() -> mcu::main::MirrorSweepPolynomial {
-    // Gimbal sweeps are defined as an archimedian spiral defining
-    // the offset in radial and angular direction compared to the
-    // last applied gimbal position.
-    //
-    // Calculating the delta to be applied instead of the absolute values
-    // makes the math much easier by requiring us to only define the
-    // archimedian spiral around the coordinate origin.
-    //
-    // The initial angle is determined as a random number to introduce an
-    // additional source of variation in our collected data.
-    mcu::main::MirrorSweepPolynomial {
-        radius_coef_a: 10.0,
-        radius_coef_b: DELTA_ROT * N_ROTATIONS / SWEEP_FRAMES as f32,
-        radius_coef_c: 0.0,
-        angle_coef_a: rand::thread_rng().gen::<f32>() * 2.0 * PI,
-        angle_coef_b: 2.0 * PI * N_ROTATIONS / SWEEP_FRAMES as f32,
-        angle_coef_c: 0.0,
-        number_of_frames: SWEEP_FRAMES,
-    }
-}
@@ -0,0 +1,291 @@
+//! Per-orb mirror sweep polynomial fitting.
+//!
+//! The MCU moves the mirror along a spiral around the last mirror position,
+//! with the radius and the angle given by quadratic polynomials of the frame
+//! number. Static coefficients either waste the mirror range on orbs with
+//! plenty of room, or drive the mirror into the end stops near the edges.
+//!
+//! Before each sweep the radial polynomial is least-squares fitted to an
+//! equal-area spiral, which samples the covered disk uniformly. The spiral is
+//! bounded by the room left around its center, which is derived from the
+//! mirror limits, the stored mirror calibration, and the latest motor ranges
+//! reported by the MCU after homing.
+
+use super::{DELTA_ROT, N_ROTATIONS, SWEEP_FRAMES};
+use crate::{
+    agents::mirror,
+    calibration::Calibration,
+    consts::{
+        MIRROR_PHI_MAX_DIAMOND, MIRROR_PHI_MAX_PEARL, MIRROR_PHI_MIN_DIAMOND, MIRROR_PHI_MIN_PEARL,
+        MIRROR_THETA_MAX_DIAMOND, MIRROR_THETA_MAX_PEARL, MIRROR_THETA_MIN_DIAMOND,
+        MIRROR_THETA_MIN_PEARL,
+    },
+    identification,
+    mcu::main::MirrorSweepPolynomial,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{f32::consts::PI, ops::RangeInclusive};
+
+/// Sweep radius unit in millidegrees, as evaluated by the MCU.
+const RADIUS_UNIT_MILLIDEGREES: f64 = 100.0;
+
+/// Sweep radius at the first frame.
+const START_RADIUS: f64 = 10.0;
+
+/// Mirror command limits in millidegrees.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Phi limits.
+    pub phi: RangeInclusive<u32>,
+    /// Theta limits.
+    pub theta: RangeInclusive<u32>,
+}
+
+/// Inputs and quality of a fitted sweep polynomial.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct Fit {
+    /// Spiral center in millidegrees.
+    pub center: (u32, u32),
+    /// Latest phi and theta motor ranges relative to the first ones reported
+    /// since boot.
+    pub range_ratio: (Option<f64>, Option<f64>),
+    /// Room around the spiral center in radius units.
+    pub room: f64,
+    /// Target radius at the last frame.
+    pub end_radius: f64,
+    /// Root-mean-square deviation of the fitted radius from the equal-area
+    /// spiral.
+    pub residual: f64,
+}
+
+impl Limits {
+    /// Returns the limits for the current hardware version.
+    #[must_use]
+    pub fn for_hardware() -> Self {
+        if identification::HARDWARE_VERSION.contains("Diamond") {
+            Self {
+                phi: MIRROR_PHI_MIN_DIAMOND..=MIRROR_PHI_MAX_DIAMOND,
+                theta: MIRROR_THETA_MIN_DIAMOND..=MIRROR_THETA_MAX_DIAMOND,
+            }
+        } else {
+            Self {
+                phi: MIRROR_PHI_MIN_PEARL..=MIRROR_PHI_MAX_PEARL,
+                theta: MIRROR_THETA_MIN_PEARL..=MIRROR_THETA_MAX_PEARL,
+            }
+        }
+    }
+
+    /// Clamps a mirror point into the limits.
+    #[must_use]
+    pub fn clamp(&self, (phi, theta): (u32, u32)) -> (u32, u32) {
+        (
+            phi.clamp(*self.phi.start(), *self.phi.end()),
+            theta.clamp(*self.theta.start(), *self.theta.end()),
+        )
+    }
+
+    /// Returns the mirror point of the calibrated neutral position.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    #[must_use]
+    pub fn calibrated_center(&self, calibration: &Calibration) -> (u32, u32) {
+        let middle = |range: &RangeInclusive<u32>, offset_degrees: f64| {
+            let middle = (f64::from(*range.start()) + f64::from(*range.end())) / 2.0;
+            (middle + offset_degrees * 1000.0).max(0.0).round() as u32
+        };
+        self.clamp((
+            middle(&self.phi, calibration.mirror.phi_offset_degrees),
+            middle(&self.theta, calibration.mirror.theta_offset_degrees),
+        ))
+    }
+
+    /// Clamps a mirror agent point, which is relative to the `calibration`, so
+    /// that the commanded mirror angles stay within the limits.
+    #[must_use]
+    pub fn clamp_point(&self, point: mirror::Point, calibration: &Calibration) -> mirror::Point {
+        let clamp = |range: &RangeInclusive<u32>, degrees: f64, offset_degrees: f64| {
+            let (start, end) =
+                (f64::from(*range.start()) / 1000.0, f64::from(*range.end()) / 1000.0);
+            (degrees + offset_degrees).clamp(start, end) - offset_degrees
+        };
+        mirror::Point {
+            phi_degrees: clamp(&self.phi, point.phi_degrees, calibration.mirror.phi_offset_degrees),
+            theta_degrees: clamp(
+                &self.theta,
+                point.theta_degrees,
+                calibration.mirror.theta_offset_degrees,
+            ),
+        }
+    }
+
+    /// Returns the distance in millidegrees from `center` to the nearest
+    /// limit. The limits are shrunk around their middle by the motor range
+    /// ratios below one.
+    fn room(&self, center: (u32, u32), range_ratio: [Option<f64>; 2]) -> f64 {
+        let room = |range: &RangeInclusive<u32>, center: u32, ratio: Option<f64>| {
+            let (start, end) = (f64::from(*range.start()), f64::from(*range.end()));
+            let middle = (start + end) / 2.0;
+            let half = (end - start) / 2.0 * ratio.unwrap_or(1.0).clamp(0.0, 1.0);
+            let center = f64::from(center);
+            (center - (middle - half)).min(middle + half - center).max(0.0)
+        };
+        room(&self.phi, center.0, range_ratio[0]).min(room(&self.theta, center.1, range_ratio[1]))
+    }
+}
+
+/// Fits the sweep polynomial for a spiral around `center`, starting at
+/// `initial_angle` radians.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+#[must_use]
+pub fn fit(
+    center: (u32, u32),
+    limits: &Limits,
+    range_ratio: [Option<f64>; 2],
+    initial_angle: f32,
+) -> (MirrorSweepPolynomial, Fit) {
+    let frames = f64::from(SWEEP_FRAMES);
+    let room = limits.room(center, range_ratio) / RADIUS_UNIT_MILLIDEGREES;
+    let end_radius = (START_RADIUS + f64::from(DELTA_ROT * N_ROTATIONS)).min(room);
+    let start_radius = START_RADIUS.min(end_radius);
+    // Equal-area spiral over the normalized frame number `t` in [0, 1].
+    let target =
+        |t: f64| (start_radius.powi(2) + (end_radius.powi(2) - start_radius.powi(2)) * t).sqrt();
+    let samples = (0..=SWEEP_FRAMES).map(|n| f64::from(n) / frames).collect::<Vec<_>>();
+    let [mut a, mut b, mut c] = fit_quadratic(&samples, target);
+    // Keep the fitted spiral within the room.
+    let eval = |t: f64| a + b * t + c * t * t;
+    let mut peak = eval(0.0).max(eval(1.0));
+    if c < 0.0 && (0.0..=1.0).contains(&(-b / (2.0 * c))) {
+        peak = peak.max(eval(-b / (2.0 * c)));
+    }
+    if peak > room {
+        let scale = if peak > 0.0 { room / peak } else { 0.0 };
+        a *= scale;
+        b *= scale;
+        c *= scale;
+    }
+    let eval = |t: f64| a + b * t + c * t * t;
+    let residual = (samples.iter().map(|&t| (eval(t) - target(t)).powi(2)).sum::<f64>()
+        / samples.len() as f64)
+        .sqrt();
+    let polynomial = MirrorSweepPolynomial {
+        radius_coef_a: a as f32,
+        radius_coef_b: (b / frames) as f32,
+        radius_coef_c: (c / frames.powi(2)) as f32,
+        angle_coef_a: initial_angle,
+        angle_coef_b: 2.0 * PI * N_ROTATIONS / SWEEP_FRAMES as f32,
+        angle_coef_c: 0.0,
+        number_of_frames: SWEEP_FRAMES,
+    };
+    let fit =
+        Fit { center, range_ratio: (range_ratio[0], range_ratio[1]), room, end_radius, residual };
+    (polynomial, fit)
+}
+
+/// Least-squares fits `a + b*t + c*t^2` to `f` at the sample points.
+fn fit_quadratic(samples: &[f64], f: impl Fn(f64) -> f64) -> [f64; 3] {
+    // Normal equations of the Vandermonde system.
+    let mut m = [[0.0; 4]; 3];
+    for &t in samples {
+        let basis = [1.0, t, t * t];
+        let y = f(t);
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, &value) in basis.iter().enumerate() {
+                row[j] += basis[i] * value;
+            }
+            row[3] += basis[i] * y;
+        }
+    }
+    // Gaussian elimination with partial pivoting.
+    for col in 0..3 {
+        let pivot = (col..3)
+            .max_by(|&i, &j| m[i][col].abs().total_cmp(&m[j][col].abs()))
+            .expect("non-empty range");
+        m.swap(col, pivot);
+        if m[col][col].abs() < f64::EPSILON {
+            return [0.0; 3];
+        }
+        let pivot_row = m[col];
+        for row in m.iter_mut().skip(col + 1) {
+            let factor = row[col] / pivot_row[col];
+            for (value, pivot) in row.iter_mut().zip(pivot_row).skip(col) {
+                *value -= factor * pivot;
+            }
+        }
+    }
+    let mut x = [0.0; 3];
+    for row in (0..3).rev() {
+        let sum = (row + 1..3).map(|k| m[row][k] * x[k]).sum::<f64>();
+        x[row] = (m[row][3] - sum) / m[row][row];
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration;
+
+    fn limits() -> Limits {
+        Limits {
+            phi: MIRROR_PHI_MIN_PEARL..=MIRROR_PHI_MAX_PEARL,
+            theta: MIRROR_THETA_MIN_PEARL..=MIRROR_THETA_MAX_PEARL,
+        }
+    }
+
+    fn radius(polynomial: &MirrorSweepPolynomial, n: u32) -> f64 {
+        let n = f64::from(n);
+        f64::from(polynomial.radius_coef_a)
+            + f64::from(polynomial.radius_coef_b) * n
+            + f64::from(polynomial.radius_coef_c) * n * n
+    }
+
+    #[test]
+    fn test_fit_quadratic() {
+        let samples = (0..=10).map(|i| f64::from(i) / 10.0).collect::<Vec<_>>();
+        let [a, b, c] = fit_quadratic(&samples, |t| 1.0 - 2.0 * t + 3.0 * t * t);
+        assert!((a - 1.0).abs() < 1e-9 && (b + 2.0).abs() < 1e-9 && (c - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fit_with_room() {
+        let (polynomial, report) = fit((45_000, 90_000), &limits(), [None, None], 1.0);
+        assert!((report.end_radius - 16.0).abs() < 1e-6);
+        assert!((radius(&polynomial, 0) - START_RADIUS).abs() < 0.5);
+        assert!((radius(&polynomial, SWEEP_FRAMES) - 16.0).abs() < 0.5);
+        assert!(report.residual < 0.2);
+        assert!((0..SWEEP_FRAMES).all(|n| radius(&polynomial, n) <= radius(&polynomial, n + 1)));
+    }
+
+    #[test]
+    fn test_clamp_point() {
+        let calibration = Calibration {
+            mirror: calibration::Mirror {
+                phi_offset_degrees: 1.0,
+                theta_offset_degrees: -2.0,
+                version: String::new(),
+            },
+        };
+        let inside = mirror::Point { phi_degrees: 50.0, theta_degrees: 100.0 };
+        let clamped = limits().clamp_point(inside, &calibration);
+        assert!((clamped.phi_degrees - 50.0).abs() < 1e-9);
+        assert!((clamped.theta_degrees - 100.0).abs() < 1e-9);
+        let outside = mirror::Point { phi_degrees: 60.0, theta_degrees: 60.0 };
+        let clamped = limits().clamp_point(outside, &calibration);
+        assert!((clamped.phi_degrees - 53.5).abs() < 1e-9);
+        assert!((clamped.theta_degrees - 74.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fit_near_limits() {
+        let center = (MIRROR_PHI_MAX_PEARL - 1_200, 90_000);
+        let (polynomial, report) = fit(center, &limits(), [None, None], 1.0);
+        assert!((report.room - 12.0).abs() < 1e-6);
+        assert!((0..=SWEEP_FRAMES).all(|n| radius(&polynomial, n) <= report.room + 1e-3));
+
+        // A shrunk phi range leaves no room at the same center.
+        let (polynomial, report) = fit(center, &limits(), [Some(0.8), Some(1.0)], 1.0);
+        assert!(report.room.abs() < f64::EPSILON);
+        assert!((0..=SWEEP_FRAMES).all(|n| radius(&polynomial, n).abs() < 1e-3));
+    }
+}
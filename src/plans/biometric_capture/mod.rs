@@ -1,4 +1,16 @@
 //! Biometric capture.
+//!
+//! On the single-optics hardware the eyes are captured one after the other by
+//! steering the IR eye camera to each eye in turn ([`CaptureMode::Sequential`]).
+//! When the IR eye camera sees both eyes at once, the objectives for the left
+//! and the right eye run concurrently ([`CaptureMode::Bilateral`]): every
+//! IR-Net estimate is attributed to the eye it perceives, the best frame is
+//! selected for each eye independently, and the RGB stream is shared by both
+//! eyes. There is still a single IR eye stream, and the mirror and the eye PID
+//! controller keep tracking the first eye, so the second eye is only captured
+//! if it is within the field of view of the IR eye camera. That is why the
+//! bilateral mode requires dual-optics hardware, listed in
+//! [`DUAL_OPTICS_HARDWARE_VERSIONS`], on top of the configuration flag.
 
 pub mod focus_sweep;
 pub mod mirror_sweep;
@@ -9,7 +21,7 @@ pub mod pupil_contraction;
 use super::qr_scan;
 use crate::{
     agents::{
-        camera, distance, mirror,
+        camera, distance, eye_pid_controller, imu, ir_auto_focus, mirror,
         python::{
             face_identifier,
             ir_net::{self, EstimateOutput},
@@ -17,7 +29,7 @@ use crate::{
         },
     },
     brokers::{Orb, OrbPlan},
-    config::Config,
+    config::{Config, Subsystem},
     consts::{
         CALIBRATION_FILE_PATH, CONTINUOUS_CALIBRATION_REDUCER, DEFAULT_DELAY_BETWEEN_EYE_CAPTURES,
         IRIS_BRIGHTNESS_RANGE, IRIS_SCORE_MIN, IRIS_SHARPNESS_MIN, IR_FOCUS_RANGE, RGB_FPS,
@@ -25,12 +37,17 @@ use crate::{
     },
     dd_gauge, dd_incr,
     ext::broadcast::ReceiverExt as _,
+    identification::HARDWARE_VERSION,
     mcu::{self, main::IrLed},
+    monitor,
     pid::{derivative::LowPassFilter, InstantTimer, Timer},
+    ui::gaze,
+    utils::RkyvNdarray,
 };
 use agentwire::{port, BrokerFlow};
 use eyre::Result;
 use futures::{future::Fuse, prelude::*};
+use ndarray::Ix2;
 use ordered_float::OrderedFloat;
 use rand::random;
 use schemars::JsonSchema;
@@ -43,6 +60,7 @@ use std::{
     time::{Duration, Instant},
 };
 use tokio::time;
+use tokio_stream::wrappers::WatchStream;
 
 /// Minimal viable sharpness.
 pub const MIN_SHARPNESS: f64 = 1.2;
@@ -97,10 +115,21 @@ pub struct Capture {
     pub face_ir: Option<camera::ir::Frame>,
     /// Thermal camera frame.
     pub thermal: Option<camera::thermal::Frame>,
-    /// Average GPS latitude during capture.
-    pub latitude: Option<f64>,
-    /// Average GPS longitude during capture.
-    pub longitude: Option<f64>,
+    /// GPS location at the end of the capture.
+    pub location: Option<monitor::gps::Location>,
+}
+
+impl Capture {
+    /// Returns `true` if the primary RGB-Net predictions of the two eye
+    /// captures belong to different person tracks.
+    #[must_use]
+    pub fn is_rgb_net_identity_switched(&self) -> bool {
+        let track_id = |eye: &EyeCapture| eye.rgb_net_estimate.primary()?.track_id;
+        matches!(
+            (track_id(&self.eye_left), track_id(&self.eye_right)),
+            (Some(left), Some(right)) if left != right
+        )
+    }
 }
 
 /// Configuration history of the biometric capture.
@@ -116,6 +145,10 @@ pub struct Log {
     pub mirror: mirror::Log,
     /// User distance history.
     pub user_distance: distance::Log,
+    /// Eye PID controller gain schedule history.
+    pub eye_pid_controller: eye_pid_controller::Log,
+    /// IMU history.
+    pub imu: imu::Log,
 }
 
 /// Report of an extension configuration and metadata.
@@ -159,30 +192,58 @@ pub struct Output {
 #[allow(missing_docs, clippy::struct_excessive_bools)]
 pub struct Plan {
     pub objectives: VecDeque<Objective>,
+    mode: CaptureMode,
     target_left_eye: bool,
     valid_capture_after: Instant,
     timeout: Fuse<Pin<Box<time::Sleep>>>,
     timed_out: bool,
+    maintenance_mode: Option<WatchStream<bool>>,
+    /// Reason the capture was aborted for.
+    aborted: Option<&'static str>,
     left_ir: Option<FrameInfoIr>,
     left_rgb: Option<FrameInfoRgb>,
     right_ir: Option<FrameInfoIr>,
     right_rgb: Option<FrameInfoRgb>,
+    last_rgb: Option<FrameInfoRgb>,
     self_custody_candidate_rgb: Option<FrameInfoSelfCustodyCandidate>,
     face_ir: Option<camera::ir::Frame>,
     thermal: Option<camera::thermal::Frame>,
     last_face_ir: Option<camera::ir::Frame>,
+    last_face_ir_ts: Option<Instant>,
     last_thermal: Option<camera::thermal::Frame>,
-    latitude: Option<f64>,
-    longitude: Option<f64>,
-    gps_points: usize,
+    gps: monitor::gps::Tracker,
     max_sharpness: f64,
     total_objectives: usize,
     occlusion_center_led_timer: InstantTimer,
     occlusion_30_filter: LowPassFilter,
     occlusion_indicator_on_time: Option<Instant>,
+    gaze_guide: gaze::Guide,
     signup_extension_config: Option<qr_scan::user::SignupExtensionConfig>,
     delay_between_eye_captures: Duration,
     mirror_offsets: Vec<mirror::Point>,
+    early_exit: Option<EarlyExit>,
+    objective_started_at: Instant,
+}
+
+/// Order in which the eyes are captured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// One eye after the other, with a single IR eye camera.
+    Sequential,
+    /// Both eyes within the same objectives, from the single IR eye stream
+    /// and a shared RGB stream.
+    Bilateral,
+}
+
+/// Thresholds for finishing an eye early on an exceptional-quality capture.
+#[derive(Clone, Copy, Debug)]
+pub struct EarlyExit {
+    /// Minimum IR-Net score.
+    pub score: f64,
+    /// Minimum IR-Net sharpness.
+    pub sharpness: f64,
+    /// Minimum time spent on the objective before the capture.
+    pub min_dwell: Duration,
 }
 
 /// Biometric capture objective.
@@ -200,6 +261,7 @@ type FrameInfoRgb = FrameInfo<rgb_net::EstimateOutput, camera::rgb::Frame>;
 type FrameInfoSelfCustodyCandidate =
     FrameInfo<face_identifier::types::IsValidOutput, camera::rgb::Frame>;
 
+#[derive(Clone)]
 struct FrameInfo<T, U> {
     #[allow(dead_code)]
     timestamp: Instant,
@@ -223,17 +285,25 @@ impl OrbPlan for Plan {
         match output.value {
             ir_net::Output::Estimate(estimate) => {
                 self.update_occlusion(orb, &estimate);
-                if let Some(perceived_side) = estimate.perceived_side {
-                    if perceived_side != i32::from(!self.target_left_eye) {
-                        tracing::debug!("Skipping frame due to target and perceived side mismatch");
-                        return Ok(BrokerFlow::Continue);
-                    }
-                } else {
+                let Some(perceived_side) = estimate.perceived_side else {
                     tracing::debug!("IRNet perceived_side=None, skipping frame");
                     return Ok(BrokerFlow::Continue);
-                }
+                };
+                let left_eye = match self.mode {
+                    CaptureMode::Sequential => {
+                        if perceived_side != i32::from(!self.target_left_eye) {
+                            tracing::debug!(
+                                "Skipping frame due to target and perceived side mismatch"
+                            );
+                            return Ok(BrokerFlow::Continue);
+                        }
+                        self.target_left_eye
+                    }
+                    CaptureMode::Bilateral => perceived_side == 0,
+                };
 
                 self.update_ux(orb, estimate.sharpness);
+                self.update_gaze_guidance(orb, &estimate);
 
                 let frame = frame.expect("frame must be set for an estimate output");
                 let valid_capture = estimate.score >= IRIS_SCORE_MIN
@@ -242,23 +312,38 @@ impl OrbPlan for Plan {
                     && self.valid_capture_after <= Instant::now();
 
                 if valid_capture {
-                    let slot =
-                        if self.target_left_eye { &mut self.left_ir } else { &mut self.right_ir };
+                    let (slot, rgb_slot) = if left_eye {
+                        (&mut self.left_ir, &mut self.left_rgb)
+                    } else {
+                        (&mut self.right_ir, &mut self.right_rgb)
+                    };
                     if slot.is_none() {
                         dd_incr!(
                             "main.count.signup.during.biometric_capture.\
                              first_side_sharp_iris_detected",
-                            &format!(
-                                "side:{}",
-                                if self.target_left_eye { "left" } else { "right" }
-                            )
+                            &format!("side:{}", if left_eye { "left" } else { "right" })
                         );
                     }
+                    if self.mode == CaptureMode::Bilateral {
+                        // Keep the best frame of each eye, as both eyes are
+                        // captured within the same objectives.
+                        if slot.as_ref().is_some_and(|best| best.estimate.score >= estimate.score) {
+                            return Ok(BrokerFlow::Continue);
+                        }
+                        if let Some(rgb) = &self.last_rgb {
+                            *rgb_slot = Some(rgb.clone());
+                        }
+                    }
                     tracing::debug!("Found sharp iris: {}", estimate.score);
                     *slot = Some(FrameInfoIr::new(estimate, frame));
+                    if let Some(ir_auto_focus) = orb.ir_auto_focus.enabled() {
+                        ir_auto_focus
+                            .tx
+                            .send_now(port::Input::new(ir_auto_focus::Input::Captured))?;
+                    }
                 }
             }
-            ir_net::Output::Version(_) => {}
+            ir_net::Output::Version(_) | ir_net::Output::Cancelled => {}
             ir_net::Output::Error => {
                 tracing::error!("IR-Net failed during biometric capture phase");
             }
@@ -275,11 +360,32 @@ impl OrbPlan for Plan {
     ) -> Result<BrokerFlow> {
         if let rgb_net::Output::Estimate(estimate) = output.value {
             if let Some(prediction) = estimate.primary() {
-                if prediction.bbox.coordinates.is_correct() {
+                if prediction.smoothed_bbox().is_correct() {
                     let frame = frame.expect("frame must be set for an estimate output");
-                    let slot =
-                        if self.target_left_eye { &mut self.left_rgb } else { &mut self.right_rgb };
-                    *slot = Some(FrameInfoRgb::new(estimate, frame));
+                    match self.mode {
+                        CaptureMode::Sequential => {
+                            let slot = if self.target_left_eye {
+                                &mut self.left_rgb
+                            } else {
+                                &mut self.right_rgb
+                            };
+                            *slot = Some(FrameInfoRgb::new(estimate, frame));
+                        }
+                        CaptureMode::Bilateral => {
+                            // The RGB stream is shared, so it is paired with
+                            // the IR frames as they are selected.
+                            let rgb = FrameInfoRgb::new(estimate, frame);
+                            for (ir, slot) in [
+                                (&self.left_ir, &mut self.left_rgb),
+                                (&self.right_ir, &mut self.right_rgb),
+                            ] {
+                                if ir.is_some() && slot.is_none() {
+                                    *slot = Some(rgb.clone());
+                                }
+                            }
+                            self.last_rgb = Some(rgb);
+                        }
+                    }
                 }
             }
         }
@@ -313,7 +419,12 @@ impl OrbPlan for Plan {
                         frame.expect("frame must be set for FaceIdentifier::IsValidImage"),
                     ));
                     self.face_ir = self.last_face_ir.take();
-                    self.thermal = self.last_thermal.take();
+                    // Prefer the thermal frame closest in time to the IR face frame.
+                    let aligned = self
+                        .last_face_ir_ts
+                        .and_then(|source_ts| orb.aligned_thermal_frame(source_ts, false));
+                    self.thermal =
+                        aligned.map(|aligned| aligned.frame).or_else(|| self.last_thermal.take());
                 }
 
                 orb.only_rgb_net_frames = true;
@@ -328,6 +439,7 @@ impl OrbPlan for Plan {
         output: port::Output<camera::ir::Sensor>,
     ) -> Result<BrokerFlow> {
         self.last_face_ir = Some(output.value);
+        self.last_face_ir_ts = Some(output.source_ts);
         Ok(BrokerFlow::Continue)
     }
 
@@ -340,24 +452,28 @@ impl OrbPlan for Plan {
         Ok(BrokerFlow::Continue)
     }
 
+    fn handle_mcu_shutdown(
+        &mut self,
+        _orb: &mut Orb,
+        shutdown: orb_messages::mcu_main::ShutdownScheduled,
+    ) -> Result<BrokerFlow> {
+        tracing::warn!("MCU announced a shutdown during the biometric capture: {shutdown:?}");
+        self.aborted = Some("mcu_shutdown");
+        Ok(BrokerFlow::Break)
+    }
+
     fn poll_extra(&mut self, orb: &mut Orb, cx: &mut Context<'_>) -> Result<BrokerFlow> {
         while let Poll::Ready(output) = orb.main_mcu.rx_mut().next_broadcast().poll_unpin(cx) {
             if let mcu::main::Output::Gps(message) = output? {
-                self.track_gps(message);
+                self.gps.push(&message, Instant::now());
             }
         }
 
-        let (rgb, ir) = if self.target_left_eye {
-            (&self.left_rgb, &self.left_ir)
-        } else {
-            (&self.right_rgb, &self.right_ir)
-        };
-
         // TODO: Maybe we can refactor the following into "objectives termination conditions"? When we switch objectives
         // we can call a function to check if we have completed the objective.
 
         // Check if we have both the iris and the face.
-        if let (Some(_rgb), Some(_ir)) = (rgb, ir) {
+        if self.is_objective_captured() {
             if !self.is_last_objective() {
                 // We have completed scanning one side. It's ok for us to move forward even if we don't have the
                 // self-custody frame, as still have 1 more eye to capture.
@@ -374,6 +490,14 @@ impl OrbPlan for Plan {
             self.timed_out = true;
             return Ok(BrokerFlow::Break);
         }
+        if let Some(maintenance_mode) = &mut self.maintenance_mode {
+            while let Poll::Ready(Some(enabled)) = maintenance_mode.poll_next_unpin(cx) {
+                if enabled {
+                    self.aborted = Some("maintenance_mode");
+                    return Ok(BrokerFlow::Break);
+                }
+            }
+        }
         Ok(BrokerFlow::Continue)
     }
 }
@@ -385,51 +509,45 @@ impl Plan {
         wavelengths: &[(IrLed, u16)],
         timeout: Option<Duration>,
         signup_extension_config: Option<qr_scan::user::SignupExtensionConfig>,
-        _config: &Config,
+        config: &Config,
     ) -> Self {
-        let target_left_eye: bool = random();
-        let mut objectives = VecDeque::new();
-        for (target_left_eye, only_rgb_net_frames) in
-            [(target_left_eye, true), (!target_left_eye, false)]
-        {
-            for &(ir_led_wavelength, ir_led_duration) in wavelengths {
-                objectives.push_back(Objective {
-                    target_left_eye,
-                    ir_led_wavelength,
-                    ir_led_duration,
-                    only_rgb_net_frames,
-                });
-            }
-        }
+        let mode = CaptureMode::for_hardware(config, &HARDWARE_VERSION);
+        let objectives = objectives(mode, wavelengths, random());
         let total_objectives = objectives.len();
         tracing::debug!("OBJECTIVES {:?}", objectives);
         Self {
             objectives,
+            mode,
             target_left_eye: false,
             valid_capture_after: Instant::now(),
             timeout: timeout
                 .map_or_else(Fuse::terminated, |timeout| Box::pin(time::sleep(timeout)).fuse()),
             timed_out: false,
+            maintenance_mode: None,
+            aborted: None,
             left_ir: None,
             left_rgb: None,
             right_ir: None,
             right_rgb: None,
+            last_rgb: None,
             self_custody_candidate_rgb: None,
             face_ir: None,
             thermal: None,
             last_face_ir: None,
+            last_face_ir_ts: None,
             last_thermal: None,
-            latitude: None,
-            longitude: None,
-            gps_points: 0,
+            gps: monitor::gps::Tracker::default(),
             max_sharpness: 0.0,
             total_objectives,
             occlusion_center_led_timer: InstantTimer::default(),
             occlusion_30_filter: LowPassFilter::default(),
             occlusion_indicator_on_time: None,
+            gaze_guide: gaze::Guide::default(),
             signup_extension_config,
             delay_between_eye_captures: DEFAULT_DELAY_BETWEEN_EYE_CAPTURES,
             mirror_offsets: Vec::new(),
+            early_exit: EarlyExit::from_config(config),
+            objective_started_at: Instant::now(),
         }
     }
 
@@ -451,6 +569,7 @@ impl Plan {
 
     pub(crate) async fn run_pre(&mut self, orb: &mut Orb) -> Result<()> {
         orb.main_mcu.rx_mut().clear()?;
+        self.maintenance_mode = Some(orb.maintenance_mode_stream());
         orb.main_mcu.log_start();
         orb.enable_ir_net().await?;
         orb.enable_rgb_net(false).await?; // Forward RGB frames to both RGB-Net and FaceIdentifier.
@@ -465,12 +584,17 @@ impl Plan {
         }
         orb.enable_mirror()?;
         orb.enable_distance()?;
+        orb.enable_imu()?;
         orb.start_ir_auto_focus(MIN_SHARPNESS, true).await?;
         orb.enable_eye_tracker()?;
-        orb.enable_eye_pid_controller()?;
+        orb.start_eye_pid_controller().await?;
         orb.start_ir_auto_exposure(IR_TARGET_MEAN).await?;
         orb.set_fisheye(RGB_REDUCED_WIDTH, RGB_REDUCED_HEIGHT, false).await?;
-        tracing::info!("Starting biometric capture with {} objectives", self.objectives.len());
+        tracing::info!(
+            "Starting {:?} biometric capture with {} objectives",
+            self.mode,
+            self.objectives.len()
+        );
         assert!(self.set_next_objective(orb).await?, "given no wavelengths");
         // Start with negative occlusion.
         self.occlusion_30_filter.reset();
@@ -488,15 +612,29 @@ impl Plan {
         }
         if self.timed_out {
             tracing::info!("Biometric capture timeout");
+            orb.cancel_python_calls();
             return Ok(true);
         }
+        if let Some(reason) = self.aborted {
+            tracing::info!("Biometric capture aborted: {reason}");
+            dd_incr!(
+                "main.count.signup.during.biometric_capture.maintenance_abort",
+                &format!("reason:{reason}")
+            );
+            orb.cancel_python_calls();
+            return Ok(true);
+        }
+        self.apply_early_exit();
         if !self.set_next_objective(orb).await? {
             dd_incr!("main.count.signup.during.biometric_capture.both_eye_captured");
             tracing::info!("All objectives achieved");
             orb.ui.biometric_capture_progress(1.1);
+            orb.signup_queue_progress(0.5);
             return Ok(true);
         }
-        self.valid_capture_after = Instant::now() + self.delay_between_eye_captures;
+        if self.mode == CaptureMode::Sequential {
+            self.valid_capture_after = Instant::now() + self.delay_between_eye_captures;
+        }
         Ok(false)
     }
 
@@ -508,11 +646,15 @@ impl Plan {
         orb.disable_ir_net();
         orb.disable_rgb_net();
         orb.disable_ir_auto_exposure();
+        if self.gaze_guide.update(None) == gaze::Update::Clear {
+            orb.ui.biometric_capture_gaze(None);
+        }
         orb.try_enable_eye_tracker();
         orb.stop_eye_tracker().await?;
         orb.try_enable_ir_auto_focus();
         orb.stop_ir_auto_focus().await?;
         let mut log_user_distance = orb.stop_distance().await?;
+        let log_imu = orb.stop_imu().await?;
         if orb.thermal_camera.is_enabled() {
             orb.stop_thermal_camera().await?;
         }
@@ -521,6 +663,7 @@ impl Plan {
         }
         orb.stop_rgb_camera().await?;
         orb.try_enable_eye_pid_controller();
+        let log_eye_pid_controller = orb.take_eye_pid_controller_log().await?;
         orb.stop_eye_pid_controller().await?;
 
         let log_ir_eye_camera = orb.stop_ir_eye_camera().await?;
@@ -538,7 +681,7 @@ impl Plan {
         let capture_failure_feedback_messages = self.failure_feedback(&mut log_user_distance);
 
         let mirror_offsets = take(&mut self.mirror_offsets);
-        let capture = self.into_capture();
+        let capture = if self.aborted.is_some() { None } else { self.into_capture() };
         if capture.is_some() {
             continuous_calibration(orb, mirror_offsets).await?;
         }
@@ -549,6 +692,8 @@ impl Plan {
             main_mcu: log_main_mcu,
             mirror: orb.stop_mirror().await?,
             user_distance: log_user_distance,
+            eye_pid_controller: log_eye_pid_controller,
+            imu: log_imu,
         };
 
         Ok(Output { capture, log, capture_failure_feedback_messages, extension_report })
@@ -589,8 +734,7 @@ impl Plan {
             eye_right,
             face_ir: self.face_ir,
             thermal: self.thermal,
-            latitude: self.latitude,
-            longitude: self.longitude,
+            location: self.gps.location(Instant::now()),
             face_self_custody_candidate: SelfCustodyCandidate {
                 rgb_frame: self_custody_candidate_rgb_frame,
                 rgb_net_eye_landmarks: face_identifier_output.rgb_net_eye_landmarks,
@@ -602,7 +746,9 @@ impl Plan {
     async fn set_next_objective(&mut self, orb: &mut Orb) -> Result<bool> {
         if let Some(objective) = self.objectives.pop_front() {
             tracing::info!("Biometric capture objective: {objective:?}");
+            orb.rehome_mirror_on_step_loss().await?;
             self.max_sharpness = 0.0;
+            self.objective_started_at = Instant::now();
             self.target_left_eye = objective.target_left_eye;
             orb.set_target_left_eye(objective.target_left_eye).await?;
             orb.set_ir_wavelength(objective.ir_led_wavelength).await?;
@@ -618,6 +764,77 @@ impl Plan {
         self.objectives.is_empty()
     }
 
+    /// Returns `true` if the iris and the face are captured for the eyes
+    /// targeted by the current objective.
+    fn is_objective_captured(&self) -> bool {
+        match self.mode {
+            CaptureMode::Sequential => {
+                if self.target_left_eye {
+                    self.left_ir.is_some() && self.left_rgb.is_some()
+                } else {
+                    self.right_ir.is_some() && self.right_rgb.is_some()
+                }
+            }
+            CaptureMode::Bilateral => {
+                self.left_ir.is_some()
+                    && self.left_rgb.is_some()
+                    && self.right_ir.is_some()
+                    && self.right_rgb.is_some()
+            }
+        }
+    }
+
+    /// Drops the remaining objectives for the current eye if it already has an
+    /// exceptional-quality capture.
+    fn apply_early_exit(&mut self) {
+        let Some(early_exit) = self.early_exit else {
+            return;
+        };
+        if self.mode == CaptureMode::Bilateral {
+            let is_exceptional = |ir: &Option<FrameInfoIr>| {
+                ir.as_ref().is_some_and(|ir| {
+                    let dwell = ir.timestamp.saturating_duration_since(self.objective_started_at);
+                    early_exit.is_exceptional(&ir.estimate, dwell)
+                })
+            };
+            if is_exceptional(&self.left_ir)
+                && is_exceptional(&self.right_ir)
+                && !self.objectives.is_empty()
+            {
+                tracing::info!(
+                    "Exceptional capture of both eyes, skipping {} objectives",
+                    self.objectives.len()
+                );
+                dd_incr!("main.count.signup.during.biometric_capture.early_exit", "side:both");
+                self.objectives.clear();
+            }
+            return;
+        }
+        let ir = if self.target_left_eye { &self.left_ir } else { &self.right_ir };
+        let Some(ir) = ir else {
+            return;
+        };
+        let dwell = ir.timestamp.saturating_duration_since(self.objective_started_at);
+        if !early_exit.is_exceptional(&ir.estimate, dwell) {
+            return;
+        }
+        let target_left_eye = self.target_left_eye;
+        let remaining = self.objectives.len();
+        self.objectives.retain(|objective| objective.target_left_eye != target_left_eye);
+        let skipped = remaining - self.objectives.len();
+        if skipped > 0 {
+            tracing::info!(
+                "Exceptional capture (score: {}, sharpness: {}), skipping {skipped} objectives",
+                ir.estimate.score,
+                ir.estimate.sharpness
+            );
+            dd_incr!(
+                "main.count.signup.during.biometric_capture.early_exit",
+                &format!("side:{}", if target_left_eye { "left" } else { "right" })
+            );
+        }
+    }
+
     #[allow(clippy::cast_precision_loss)]
     fn update_ux(&mut self, orb: &mut Orb, sharpness: f64) {
         const MAX_PROGRESS: f64 = 0.8;
@@ -641,25 +858,19 @@ impl Plan {
             orb.ui.biometric_capture_all_objectives_completed();
         }
         orb.ui.biometric_capture_progress(progress);
+        orb.signup_queue_progress(progress / 2.0);
     }
 
-    #[allow(clippy::cast_precision_loss)]
-    fn track_gps(&mut self, message: nmea_parser::ParsedMessage) {
-        let (latitude, longitude) = match message {
-            nmea_parser::ParsedMessage::Gga(message) => (message.latitude, message.longitude),
-            nmea_parser::ParsedMessage::Gll(message) => (message.latitude, message.longitude),
-            nmea_parser::ParsedMessage::Gns(message) => (message.latitude, message.longitude),
-            nmea_parser::ParsedMessage::Rmc(message) => (message.latitude, message.longitude),
-            _ => (None, None),
-        };
-        if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
-            let prev_latitude = self.latitude.unwrap_or(0.0);
-            let prev_longitude = self.longitude.unwrap_or(0.0);
-            self.gps_points += 1;
-            self.latitude =
-                Some(prev_latitude + (latitude - prev_latitude) / self.gps_points as f64);
-            self.longitude =
-                Some(prev_longitude + (longitude - prev_longitude) / self.gps_points as f64);
+    fn update_gaze_guidance(&mut self, orb: &mut Orb, estimate: &EstimateOutput) {
+        let offset = (estimate.sharpness >= IRIS_SHARPNESS_MIN)
+            .then_some(estimate.landmarks.as_ref())
+            .flatten()
+            .map(RkyvNdarray::<_, Ix2>::as_ndarray)
+            .and_then(eye_pid_controller::iris_center_from_landmarks);
+        match self.gaze_guide.update(offset) {
+            gaze::Update::Show(guidance) => orb.ui.biometric_capture_gaze(Some(guidance)),
+            gaze::Update::Clear => orb.ui.biometric_capture_gaze(None),
+            gaze::Update::Unchanged => {}
         }
     }
 
@@ -737,6 +948,77 @@ impl Plan {
     }
 }
 
+/// Hardware versions with dual optics, whose IR eye camera sees both eyes at
+/// once. None of the released hardware has them yet.
+pub const DUAL_OPTICS_HARDWARE_VERSIONS: &[&str] = &[];
+
+impl CaptureMode {
+    /// Returns the capture mode for the hardware revision. Bilateral capture
+    /// is only available on dual-optics hardware and is enabled in the
+    /// configuration.
+    #[must_use]
+    pub fn for_hardware(config: &Config, hardware_version: &str) -> Self {
+        Self::for_optics(config, DUAL_OPTICS_HARDWARE_VERSIONS.contains(&hardware_version.trim()))
+    }
+
+    fn for_optics(config: &Config, dual_optics: bool) -> Self {
+        if config.biometric_capture_bilateral && dual_optics {
+            Self::Bilateral
+        } else {
+            Self::Sequential
+        }
+    }
+}
+
+/// Returns the capture objectives, one per wavelength and per eye for the
+/// sequential mode, starting with the left eye if `first_left_eye` is set, and
+/// one per wavelength for both eyes in the bilateral mode.
+fn objectives(
+    mode: CaptureMode,
+    wavelengths: &[(IrLed, u16)],
+    first_left_eye: bool,
+) -> VecDeque<Objective> {
+    let eyes = match mode {
+        // Face self-custody frames are collected while capturing the second
+        // eye only.
+        CaptureMode::Sequential => vec![(first_left_eye, true), (!first_left_eye, false)],
+        CaptureMode::Bilateral => vec![(first_left_eye, false)],
+    };
+    let mut objectives = VecDeque::new();
+    for (target_left_eye, only_rgb_net_frames) in eyes {
+        for &(ir_led_wavelength, ir_led_duration) in wavelengths {
+            objectives.push_back(Objective {
+                target_left_eye,
+                ir_led_wavelength,
+                ir_led_duration,
+                only_rgb_net_frames,
+            });
+        }
+    }
+    objectives
+}
+
+impl EarlyExit {
+    /// Returns the early-exit thresholds if enabled in the configuration.
+    #[must_use]
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.biometric_capture_early_exit.then_some(Self {
+            score: config.biometric_capture_early_exit_score,
+            sharpness: config.biometric_capture_early_exit_sharpness,
+            min_dwell: config.biometric_capture_early_exit_min_dwell,
+        })
+    }
+
+    /// Returns `true` if the estimate is good enough to finish the eye after
+    /// spending `dwell` on the objective.
+    #[must_use]
+    pub fn is_exceptional(&self, estimate: &EstimateOutput, dwell: Duration) -> bool {
+        dwell >= self.min_dwell
+            && estimate.score >= self.score
+            && estimate.sharpness >= self.sharpness
+    }
+}
+
 /// Performs light re-calibration at the end of each successful biometric
 /// capture.
 ///
@@ -751,7 +1033,7 @@ pub async fn continuous_calibration(
     mirror_offsets: Vec<mirror::Point>,
 ) -> Result<()> {
     tracing::info!("Mirror offsets after successful capture: {mirror_offsets:?}");
-    if mirror_offsets.len() < 2 {
+    if mirror_offsets.len() < 2 || orb.is_killed(Subsystem::ContinuousCalibration).await {
         return Ok(());
     }
     let phi_degrees = mirror_offsets
@@ -773,3 +1055,45 @@ pub async fn continuous_calibration(
     orb.recalibrate(calibration).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_early_exit_is_exceptional() {
+        let early_exit =
+            EarlyExit { score: 2.5, sharpness: 2.0, min_dwell: Duration::from_millis(300) };
+        let estimate = EstimateOutput { score: 2.6, sharpness: 2.1, ..Default::default() };
+        assert!(early_exit.is_exceptional(&estimate, Duration::from_millis(300)));
+        assert!(!early_exit.is_exceptional(&estimate, Duration::from_millis(299)));
+        let blurry = EstimateOutput { score: 2.6, sharpness: 1.9, ..Default::default() };
+        assert!(!early_exit.is_exceptional(&blurry, Duration::from_secs(1)));
+        let low_score = EstimateOutput { score: 2.4, sharpness: 2.1, ..Default::default() };
+        assert!(!early_exit.is_exceptional(&low_score, Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_capture_mode_for_hardware() {
+        let mut config = Config::default();
+        assert_eq!(CaptureMode::for_optics(&config, true), CaptureMode::Sequential);
+        config.biometric_capture_bilateral = true;
+        assert_eq!(CaptureMode::for_optics(&config, true), CaptureMode::Bilateral);
+        assert_eq!(CaptureMode::for_optics(&config, false), CaptureMode::Sequential);
+        // The single-optics hardware never captures bilaterally.
+        assert_eq!(CaptureMode::for_hardware(&config, "Diamond_EVT"), CaptureMode::Sequential);
+        assert_eq!(CaptureMode::for_hardware(&config, "EVT4"), CaptureMode::Sequential);
+    }
+
+    #[test]
+    fn test_objectives() {
+        let wavelengths = [(IrLed::L850, 100), (IrLed::L940, 100)];
+        let sequential = objectives(CaptureMode::Sequential, &wavelengths, true);
+        assert_eq!(sequential.len(), 4);
+        assert!(sequential.iter().take(2).all(|o| o.target_left_eye && o.only_rgb_net_frames));
+        assert!(sequential.iter().skip(2).all(|o| !o.target_left_eye && !o.only_rgb_net_frames));
+        let bilateral = objectives(CaptureMode::Bilateral, &wavelengths, false);
+        assert_eq!(bilateral.len(), 2);
+        assert!(bilateral.iter().all(|o| !o.only_rgb_net_frames));
+    }
+}
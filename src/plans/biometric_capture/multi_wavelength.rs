@@ -163,7 +163,7 @@ impl Plan {
 
         orb.enable_ir_net().await?;
         orb.enable_ir_auto_focus()?;
-        orb.enable_eye_pid_controller()?;
+        orb.start_eye_pid_controller().await?;
         self.state = State::Normal;
         Ok(())
     }
@@ -174,7 +174,7 @@ impl Plan {
         orb.enable_ir_auto_focus()?;
         orb.enable_mirror()?;
         orb.enable_eye_tracker()?;
-        orb.enable_eye_pid_controller()?;
+        orb.start_eye_pid_controller().await?;
         orb.ir_eye_save_fps_override = None;
         orb.ir_face_save_fps_override = None;
         orb.thermal_save_fps_override = None;
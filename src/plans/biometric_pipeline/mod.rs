@@ -465,6 +465,7 @@ impl Plan {
             orb.ui.biometric_pipeline_progress(
                 MIN_PROGRESS + progress * (MAX_PROGRESS - MIN_PROGRESS),
             );
+            orb.signup_queue_progress(0.5 + progress / 2.0);
         }
 
         orb.disable_mega_agent_one();
@@ -1,9 +1,23 @@
 //! Face detection.
+//!
+//! The face detection is gated on a [presence precheck](presence): if the ToF
+//! and the RGB camera show nobody in front of the orb after
+//! [`PRESENCE_PRECHECK_DURATION`], the plan finishes with
+//! [`Outcome::NobodyPresent`] instead of waiting for the full timeout.
+//!
+//! A face is reported only once the same RGB-Net person track shows a face in
+//! [`FACE_DETECTION_MIN_TRACK_FRAMES`] consecutive estimates, so a single
+//! spurious detection or a passer-by doesn't start the signup.
+
+pub mod presence;
 
 use crate::{
     agents::{camera, python},
     brokers::{Orb, OrbPlan},
-    consts::{RGB_FPS, RGB_REDUCED_HEIGHT, RGB_REDUCED_WIDTH},
+    consts::{
+        FACE_DETECTION_MIN_TRACK_FRAMES, PRESENCE_PRECHECK_DURATION, RGB_FPS, RGB_REDUCED_HEIGHT,
+        RGB_REDUCED_WIDTH,
+    },
 };
 use agentwire::{port, BrokerFlow};
 use eyre::Result;
@@ -17,10 +31,41 @@ use tokio::time;
 /// Face detection plan.
 pub struct Plan {
     timeout: Pin<Box<time::Sleep>>,
-    face_detected: bool,
+    precheck: Pin<Box<time::Sleep>>,
+    precheck_done: bool,
+    motion_detector: presence::MotionDetector,
+    presence: presence::Verdict,
+    presence_confirmed: bool,
+    face_track: Option<(u64, u32)>,
+    outcome: Outcome,
+}
+
+/// Face detection outcome.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// A face was detected.
+    FaceDetected,
+    /// The presence precheck showed nobody in front of the orb.
+    NobodyPresent,
+    /// No face was detected before the timeout.
+    TimedOut,
 }
 
 impl OrbPlan for Plan {
+    fn handle_rgb_camera(
+        &mut self,
+        orb: &mut Orb,
+        output: port::Output<camera::rgb::Sensor>,
+    ) -> Result<BrokerFlow> {
+        let motion = self.motion_detector.update(&output.value);
+        self.presence = presence::Verdict::new(orb.tof_distance.latest(), motion);
+        if self.presence == presence::Verdict::Present {
+            self.presence_confirmed = true;
+            orb.signup_queue_progress(0.0);
+        }
+        Ok(BrokerFlow::Continue)
+    }
+
     fn handle_rgb_net(
         &mut self,
         _orb: &mut Orb,
@@ -30,17 +75,37 @@ impl OrbPlan for Plan {
         #[allow(clippy::match_wildcard_for_single_variants)]
         match output.value {
             python::rgb_net::Output::Estimate(estimate) => {
-                self.face_detected = match estimate.primary() {
-                    Some(prediction) => prediction.is_face_detected(),
-                    None => false,
-                };
-                if self.face_detected { Ok(BrokerFlow::Break) } else { Ok(BrokerFlow::Continue) }
+                let face_track = estimate
+                    .primary()
+                    .filter(|prediction| prediction.is_face_detected())
+                    .and_then(|prediction| prediction.track_id);
+                self.face_track = face_track.map(|track_id| match self.face_track {
+                    Some((id, frames)) if id == track_id => (id, frames + 1),
+                    _ => (track_id, 1),
+                });
+                if self
+                    .face_track
+                    .is_some_and(|(_, frames)| frames >= FACE_DETECTION_MIN_TRACK_FRAMES)
+                {
+                    self.outcome = Outcome::FaceDetected;
+                    Ok(BrokerFlow::Break)
+                } else {
+                    Ok(BrokerFlow::Continue)
+                }
             }
             _ => Ok(BrokerFlow::Continue),
         }
     }
 
     fn poll_extra(&mut self, _orb: &mut Orb, cx: &mut Context<'_>) -> Result<BrokerFlow> {
+        if !self.precheck_done && self.precheck.poll_unpin(cx).is_ready() {
+            self.precheck_done = true;
+            if !self.presence_confirmed && self.presence == presence::Verdict::Absent {
+                tracing::info!("Presence precheck: nobody in front of the orb");
+                self.outcome = Outcome::NobodyPresent;
+                return Ok(BrokerFlow::Break);
+            }
+        }
         if let Poll::Ready(()) = self.timeout.poll_unpin(cx) {
             tracing::info!("Face detection timed out");
             return Ok(BrokerFlow::Break);
@@ -53,19 +118,28 @@ impl Plan {
     /// Creates a new face detection plan.
     #[must_use]
     pub fn new(timeout: time::Duration) -> Self {
-        Self { timeout: Box::pin(time::sleep(timeout)), face_detected: false }
+        Self {
+            timeout: Box::pin(time::sleep(timeout)),
+            precheck: Box::pin(time::sleep(PRESENCE_PRECHECK_DURATION)),
+            precheck_done: false,
+            motion_detector: presence::MotionDetector::default(),
+            presence: presence::Verdict::Unknown,
+            presence_confirmed: false,
+            face_track: None,
+            outcome: Outcome::TimedOut,
+        }
     }
 }
 
 impl Plan {
     /// Runs the face detection plan.
-    pub async fn run(&mut self, orb: &mut Orb) -> Result<bool> {
+    pub async fn run(&mut self, orb: &mut Orb) -> Result<Outcome> {
         orb.start_rgb_camera(RGB_FPS).await?;
         orb.enable_rgb_net(true).await?;
         orb.set_fisheye(RGB_REDUCED_WIDTH, RGB_REDUCED_HEIGHT, false).await?;
         orb.run(self).await?;
         orb.stop_rgb_camera().await?;
         orb.disable_rgb_net();
-        Ok(self.face_detected)
+        Ok(self.outcome)
     }
 }
@@ -0,0 +1,145 @@
+//! Presence precheck.
+//!
+//! Fuses the 1D ToF distance reported by the main MCU with a coarse motion
+//! estimate of the RGB camera frames. When neither of them shows anybody in
+//! front of the orb, the face detection can give up early instead of waiting
+//! for the full timeout.
+
+use crate::{
+    agents::camera::{self, Frame as _},
+    consts::PRESENCE_MAX_DISTANCE_MM,
+};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// ToF readings older than this are ignored.
+const TOF_MAX_AGE: Duration = Duration::from_secs(1);
+
+/// Distance in pixels between the samples of the motion estimate.
+const MOTION_SAMPLE_STEP: usize = 8;
+
+/// Mean absolute difference of the sampled pixels above which the scene is
+/// considered moving.
+const MOTION_THRESHOLD: f64 = 6.0;
+
+/// Latest 1D ToF distance shared between the observer and the orb brokers.
+#[derive(Default, Debug)]
+pub struct TofDistance {
+    latest: Mutex<Option<(u32, Instant)>>,
+}
+
+/// Presence verdict.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// Somebody is within the presence distance or moving in view.
+    Present,
+    /// The ToF sees nobody nearby and the scene is still.
+    Absent,
+    /// No recent ToF reading.
+    Unknown,
+}
+
+/// Coarse motion detector comparing consecutive RGB frames.
+#[derive(Default)]
+pub struct MotionDetector {
+    previous: Option<Vec<u8>>,
+}
+
+impl TofDistance {
+    /// Records a new ToF distance in millimeters.
+    ///
+    /// # Panics
+    ///
+    /// If the mutex is poisoned
+    pub fn record(&self, distance_mm: u32) {
+        *self.latest.lock().unwrap() = Some((distance_mm, Instant::now()));
+    }
+
+    /// Returns the latest ToF distance in millimeters, if it is recent.
+    ///
+    /// # Panics
+    ///
+    /// If the mutex is poisoned
+    #[must_use]
+    pub fn latest(&self) -> Option<u32> {
+        self.latest
+            .lock()
+            .unwrap()
+            .filter(|(_, timestamp)| timestamp.elapsed() <= TOF_MAX_AGE)
+            .map(|(distance_mm, _)| distance_mm)
+    }
+}
+
+impl Verdict {
+    /// Fuses the ToF distance and the motion estimate.
+    #[must_use]
+    pub fn new(tof_distance_mm: Option<u32>, motion: bool) -> Self {
+        match tof_distance_mm {
+            _ if motion => Self::Present,
+            Some(distance_mm) if distance_mm <= PRESENCE_MAX_DISTANCE_MM => Self::Present,
+            Some(_) => Self::Absent,
+            None => Self::Unknown,
+        }
+    }
+}
+
+impl MotionDetector {
+    /// Returns `true` if the frame differs from the previous one.
+    pub fn update(&mut self, frame: &camera::rgb::Frame) -> bool {
+        let samples = sample(frame.as_bytes(), frame.width() as usize, frame.height() as usize);
+        let motion = self
+            .previous
+            .as_ref()
+            .filter(|previous| previous.len() == samples.len())
+            .is_some_and(|previous| mean_difference(previous, &samples) > MOTION_THRESHOLD);
+        self.previous = Some(samples);
+        motion
+    }
+}
+
+/// Samples the green channel of an RGB image on a sparse grid.
+fn sample(data: &[u8], width: usize, height: usize) -> Vec<u8> {
+    (0..height)
+        .step_by(MOTION_SAMPLE_STEP)
+        .flat_map(|y| (0..width).step_by(MOTION_SAMPLE_STEP).map(move |x| (y * width + x) * 3 + 1))
+        .filter_map(|i| data.get(i).copied())
+        .collect()
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn mean_difference(a: &[u8], b: &[u8]) -> f64 {
+    if a.is_empty() {
+        return 0.0;
+    }
+    let sum: u64 = a.iter().zip(b).map(|(a, b)| u64::from(a.abs_diff(*b))).sum();
+    sum as f64 / a.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(value: u8) -> camera::rgb::Frame {
+        camera::rgb::Frame::from_vec(vec![value; 64 * 32 * 3], Duration::ZERO, 64, 32)
+    }
+
+    #[test]
+    fn test_verdict() {
+        assert_eq!(Verdict::new(None, false), Verdict::Unknown);
+        assert_eq!(Verdict::new(None, true), Verdict::Present);
+        assert_eq!(Verdict::new(Some(PRESENCE_MAX_DISTANCE_MM), false), Verdict::Present);
+        assert_eq!(Verdict::new(Some(PRESENCE_MAX_DISTANCE_MM + 1), false), Verdict::Absent);
+        assert_eq!(Verdict::new(Some(PRESENCE_MAX_DISTANCE_MM + 1), true), Verdict::Present);
+    }
+
+    #[test]
+    fn test_motion() {
+        let mut detector = MotionDetector::default();
+        assert!(!detector.update(&frame(100)));
+        assert!(!detector.update(&frame(102)));
+        assert!(detector.update(&frame(150)));
+        assert!(!detector.update(&frame(150)));
+    }
+}
@@ -1,5 +1,7 @@
 //! User enrollment.
 
+pub mod validation;
+
 use super::{
     biometric_capture::Capture, biometric_pipeline::Pipeline, notify_failed_signup, qr_scan,
 };
@@ -7,6 +9,7 @@ use crate::{
     backend::{
         log_decoding_error, signup_poll,
         signup_post::{self, SignupReason},
+        signup_receipt,
     },
     brokers::Orb,
     dd_incr,
@@ -44,6 +47,8 @@ pub enum Status {
     ServerError,
     /// User enrollment failed due to a network or other internal error.
     Error,
+    /// User enrollment was aborted due to an invalid iris code.
+    InvalidIrisCode(validation::Error),
 }
 
 impl Status {
@@ -70,6 +75,17 @@ impl Plan<'_> {
     /// Runs the user enrollment plan.
     #[allow(clippy::too_many_lines)]
     pub async fn run(self, orb: &mut Orb) -> Status {
+        if let Some(pipeline) = self.pipeline {
+            if let Err(err) = validation::validate(&pipeline.v2) {
+                tracing::error!("SIGNUP ABORTED: invalid iris code: {err}");
+                dd_incr!(
+                    "main.count.signup.result.failure.user_enrollment",
+                    "type:internal_error",
+                    &format!("subtype:{}", err.subtype())
+                );
+                return Status::InvalidIrisCode(err);
+            }
+        }
         let user_qr_code = self.user_qr_code.clone();
         let signature = if let Some(p) = self.pipeline.cloned() {
             match task::spawn_blocking(move || make_signature(&user_qr_code, &p)).await {
@@ -152,6 +168,7 @@ impl Plan<'_> {
                                 tracing::info!("SIGNUP SUCCESS");
                                 dd_incr!("main.count.http.user_enrollment.success.success_unique");
                                 dd_incr!("main.count.http.user_enrollment.success.success");
+                                task::spawn(signup_receipt::fetch_and_store(signup_id));
                                 return Status::Success;
                             }
                             Ok(signup_poll::Response {
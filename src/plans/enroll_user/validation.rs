@@ -0,0 +1,235 @@
+//! Iris code sanity validation.
+//!
+//! Malformed iris codes are rejected by the backend only after the signup has
+//! been submitted, with an opaque error. The codes, their masks and their
+//! shares are checked locally before the submission, so the signup can be
+//! aborted with a precise classification instead.
+
+use crate::{
+    consts::{IRIS_CODE_BIT_BALANCE_RANGE, IRIS_CODE_MIN_MASK_COVERAGE},
+    plans::biometric_pipeline::{EyePipeline, PipelineV2},
+};
+use data_encoding::BASE64;
+use iris_mpc::iris_db::iris::IrisCodeArray;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt;
+use thiserror::Error;
+
+/// Iris code validation error.
+#[allow(missing_docs)]
+#[derive(Error, Clone, Copy, Debug, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Error {
+    #[error("{eye} {code} code is malformed")]
+    MalformedCode { eye: Eye, code: Code },
+    #[error("{eye} {code} code share {share} is malformed")]
+    MalformedShare { eye: Eye, code: Code, share: usize },
+    #[error("{code} code shares have inconsistent lengths")]
+    ShareLengthMismatch { code: Code },
+    #[error("{eye} mask code covers only {coverage:.3} of the iris")]
+    MaskCoverage { eye: Eye, coverage: f64 },
+    #[error("{eye} iris code bit balance {balance:.3} is out of bounds")]
+    BitBalance { eye: Eye, balance: f64 },
+    #[error("{eye} iris code version is missing")]
+    MissingVersion { eye: Eye },
+    #[error("iris code versions differ between the eyes")]
+    VersionMismatch,
+}
+
+/// The eye an iris code belongs to.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+/// Iris code kind.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Code {
+    Iris,
+    Mask,
+}
+
+impl Error {
+    /// Returns the error classification used as a metric tag.
+    #[must_use]
+    pub fn subtype(&self) -> &'static str {
+        match self {
+            Self::MalformedCode { .. } => "malformed_code",
+            Self::MalformedShare { .. } => "malformed_share",
+            Self::ShareLengthMismatch { .. } => "share_length_mismatch",
+            Self::MaskCoverage { .. } => "mask_coverage",
+            Self::BitBalance { .. } => "bit_balance",
+            Self::MissingVersion { .. } => "missing_version",
+            Self::VersionMismatch => "version_mismatch",
+        }
+    }
+}
+
+impl fmt::Display for Eye {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Left => "left",
+            Self::Right => "right",
+        })
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Iris => "iris",
+            Self::Mask => "mask",
+        })
+    }
+}
+
+/// Checks the iris codes and their shares of both eyes before the submission.
+pub fn validate(pipeline: &PipelineV2) -> Result<(), Error> {
+    let left = validate_eye(Eye::Left, &pipeline.eye_left)?;
+    let right = validate_eye(Eye::Right, &pipeline.eye_right)?;
+    for (code, left, right) in [(Code::Iris, left.0, right.0), (Code::Mask, left.1, right.1)] {
+        if left != right {
+            return Err(Error::ShareLengthMismatch { code });
+        }
+    }
+    if pipeline.eye_left.iris_code_version != pipeline.eye_right.iris_code_version {
+        tracing::error!(
+            "Iris code versions differ: left {}, right {}",
+            pipeline.eye_left.iris_code_version,
+            pipeline.eye_right.iris_code_version
+        );
+        return Err(Error::VersionMismatch);
+    }
+    Ok(())
+}
+
+/// Checks the iris code of one eye. Returns the decoded iris and mask code
+/// share lengths.
+#[allow(clippy::cast_precision_loss)]
+fn validate_eye(eye: Eye, data: &EyePipeline) -> Result<(usize, usize), Error> {
+    if data.iris_code_version.trim().is_empty() {
+        return Err(Error::MissingVersion { eye });
+    }
+    let iris = decode_code(eye, Code::Iris, &data.iris_code)?;
+    let mask = decode_code(eye, Code::Mask, &data.mask_code)?;
+    let valid = mask.iter().map(|byte| byte.count_ones()).sum::<u32>();
+    let coverage = f64::from(valid) / (mask.len() * 8) as f64;
+    if coverage < IRIS_CODE_MIN_MASK_COVERAGE {
+        return Err(Error::MaskCoverage { eye, coverage });
+    }
+    let ones = iris.iter().zip(&mask).map(|(iris, mask)| (iris & mask).count_ones()).sum::<u32>();
+    let balance = f64::from(ones) / f64::from(valid);
+    if !IRIS_CODE_BIT_BALANCE_RANGE.contains(&balance) {
+        return Err(Error::BitBalance { eye, balance });
+    }
+    Ok((
+        share_len(eye, Code::Iris, &data.iris_code_shares)?,
+        share_len(eye, Code::Mask, &data.mask_code_shares)?,
+    ))
+}
+
+fn decode_code(eye: Eye, code: Code, encoded: &str) -> Result<Vec<u8>, Error> {
+    // Parsing as an MPC iris code array checks the code length.
+    if IrisCodeArray::from_base64(encoded).is_err() {
+        return Err(Error::MalformedCode { eye, code });
+    }
+    BASE64.decode(encoded.as_bytes()).map_err(|_| Error::MalformedCode { eye, code })
+}
+
+/// Returns the decoded length of the shares, which consist of 16-bit
+/// coefficients and must have equal lengths.
+fn share_len(eye: Eye, code: Code, shares: &[String; 3]) -> Result<usize, Error> {
+    let mut len = None;
+    for (share, encoded) in shares.iter().enumerate() {
+        let decoded = BASE64.decode(encoded.as_bytes()).map_err(|_| Error::MalformedShare {
+            eye,
+            code,
+            share,
+        })?;
+        if decoded.is_empty() || decoded.len() % 2 != 0 {
+            return Err(Error::MalformedShare { eye, code, share });
+        }
+        if *len.get_or_insert(decoded.len()) != decoded.len() {
+            return Err(Error::ShareLengthMismatch { code });
+        }
+    }
+    Ok(len.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packed size of a 12800-bit iris code.
+    const CODE_BYTES: usize = 1600;
+
+    fn eye(iris: u8, mask: u8) -> EyePipeline {
+        EyePipeline {
+            iris_code_shares: [(); 3].map(|()| BASE64.encode(&[1; 32])),
+            mask_code_shares: [(); 3].map(|()| BASE64.encode(&[2; 16])),
+            iris_code: BASE64.encode(&[iris; CODE_BYTES]),
+            mask_code: BASE64.encode(&[mask; CODE_BYTES]),
+            iris_code_version: "v2.1".to_owned(),
+            ..EyePipeline::default()
+        }
+    }
+
+    fn pipeline() -> PipelineV2 {
+        PipelineV2 {
+            eye_left: eye(0b0101_0101, 0b1111_1111),
+            eye_right: eye(0b0011_1100, 0b1111_0000),
+            ..PipelineV2::default()
+        }
+    }
+
+    #[test]
+    fn test_valid() {
+        assert_eq!(validate(&pipeline()), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_codes() {
+        let mut p = pipeline();
+        p.eye_right.mask_code = BASE64.encode(&[0xFF; CODE_BYTES - 8]);
+        assert_eq!(validate(&p), Err(Error::MalformedCode { eye: Eye::Right, code: Code::Mask }));
+
+        let mut p = pipeline();
+        p.eye_left.mask_code = BASE64.encode(&[0; CODE_BYTES]);
+        assert!(matches!(validate(&p), Err(Error::MaskCoverage { eye: Eye::Left, .. })));
+
+        let mut p = pipeline();
+        p.eye_right.iris_code = BASE64.encode(&[0b1111_0000; CODE_BYTES]);
+        assert!(matches!(validate(&p), Err(Error::BitBalance { eye: Eye::Right, .. })));
+    }
+
+    #[test]
+    fn test_invalid_shares() {
+        let mut p = pipeline();
+        p.eye_left.iris_code_shares[2] = "not base64".to_owned();
+        assert_eq!(
+            validate(&p),
+            Err(Error::MalformedShare { eye: Eye::Left, code: Code::Iris, share: 2 })
+        );
+
+        let mut p = pipeline();
+        p.eye_right.mask_code_shares = [(); 3].map(|()| BASE64.encode(&[2; 18]));
+        assert_eq!(validate(&p), Err(Error::ShareLengthMismatch { code: Code::Mask }));
+    }
+
+    #[test]
+    fn test_versions() {
+        let mut p = pipeline();
+        p.eye_right.iris_code_version = String::new();
+        assert_eq!(validate(&p), Err(Error::MissingVersion { eye: Eye::Right }));
+
+        let mut p = pipeline();
+        p.eye_right.iris_code_version = "v2.0".to_owned();
+        assert_eq!(validate(&p), Err(Error::VersionMismatch));
+    }
+}
@@ -1,5 +1,6 @@
 //! IR camera FPS check.
 
+use super::{Check, FailureClass};
 use crate::{
     agents::camera,
     brokers::{Orb, OrbPlan},
@@ -8,6 +9,7 @@ use agentwire::{port, BrokerFlow};
 use eyre::Result;
 use futures::prelude::*;
 use std::{
+    collections::BTreeMap,
     pin::Pin,
     task::{Context, Poll},
 };
@@ -58,7 +60,7 @@ impl Default for Plan {
 
 impl Plan {
     /// Runs the IR camera FPS check plan.
-    pub async fn run(&mut self, orb: &mut Orb) -> Result<bool> {
+    pub async fn run(&mut self, orb: &mut Orb) -> Result<Check> {
         tracing::info!("IR camera FPS check: running");
 
         orb.start_ir_eye_camera().await?;
@@ -79,6 +81,15 @@ impl Plan {
 
         let success = eye_fps > MIN_FPS && face_fps > MIN_FPS;
         tracing::info!("IR camera FPS check: {}", if success { "OK!" } else { "FAILURE!" });
-        Ok(success)
+        Ok(Check {
+            name: "ir_camera_fps".to_owned(),
+            success,
+            failure_class: (!success).then_some(FailureClass::Camera),
+            measurements: BTreeMap::from([
+                ("ir_eye_camera_fps".to_owned(), f64::from(eye_fps)),
+                ("ir_face_camera_fps".to_owned(), f64::from(face_fps)),
+            ]),
+            message: (!success).then(|| format!("IR camera FPS below {MIN_FPS}")),
+        })
     }
 }
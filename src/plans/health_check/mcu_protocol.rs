@@ -0,0 +1,61 @@
+//! MCU protocol version check.
+
+use super::{Check, FailureClass};
+use crate::{brokers::Orb, mcu::protocol::Negotiation};
+use eyre::Result;
+use std::collections::BTreeMap;
+use tokio::time::{sleep, Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// MCU protocol version check plan.
+#[derive(Default)]
+pub struct Plan;
+
+impl Plan {
+    /// Runs the MCU protocol version check plan.
+    pub async fn run(&mut self, orb: &mut Orb) -> Result<Check> {
+        tracing::info!("MCU protocol check: running");
+        let Some(protocol) = orb.main_mcu.protocol().cloned() else {
+            tracing::info!("MCU protocol check: skipped for a non-CAN MCU");
+            return Ok(check(true, None, BTreeMap::new(), None));
+        };
+        let start = Instant::now();
+        let mut negotiation = protocol.negotiation();
+        while negotiation == Negotiation::Pending && start.elapsed() < TIMEOUT {
+            sleep(POLL_INTERVAL).await;
+            negotiation = protocol.negotiation();
+        }
+        let mut measurements = BTreeMap::from([(
+            "orb_core_protocol_version".to_owned(),
+            f64::from(protocol.preferred()),
+        )]);
+        let (failure_class, message) = match negotiation {
+            Negotiation::Pending => {
+                (Some(FailureClass::Setup), Some("no response from the MCU".to_owned()))
+            }
+            Negotiation::Compatible { firmware } => {
+                measurements.insert("firmware_protocol_version".to_owned(), f64::from(firmware));
+                (None, None)
+            }
+            Negotiation::Incompatible(err) => {
+                measurements
+                    .insert("firmware_protocol_version".to_owned(), f64::from(err.firmware));
+                (Some(FailureClass::IncompatibleFirmware), Some(err.to_string()))
+            }
+        };
+        let success = failure_class.is_none();
+        tracing::info!("MCU protocol check: {}", if success { "OK!" } else { "FAILURE!" });
+        Ok(check(success, failure_class, measurements, message))
+    }
+}
+
+fn check(
+    success: bool,
+    failure_class: Option<FailureClass>,
+    measurements: BTreeMap<String, f64>,
+    message: Option<String>,
+) -> Check {
+    Check { name: "mcu_protocol".to_owned(), success, failure_class, measurements, message }
+}
@@ -1,21 +1,232 @@
 //! Health check.
+//!
+//! The results are collected into a [`Report`], which can be printed as JSON
+//! for factory test stations. The JSON schema of the report is exported by the
+//! `debug-report-schema` tool.
 
 pub mod ir_camera_fps;
+pub mod mcu_protocol;
+pub mod optics_mtf;
+pub mod security_mcu;
+pub mod tof_accuracy;
+pub mod trend;
+pub mod vibration;
 
 use crate::brokers::Orb;
 use eyre::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Version of the [`Report`] format. Bump it on any incompatible change of the
+/// field names or semantics.
+pub const REPORT_VERSION: u32 = 1;
 
 /// Health check plan.
 #[derive(Default)]
 pub struct Plan {
+    mcu_protocol: mcu_protocol::Plan,
     ir_camera_fps: ir_camera_fps::Plan,
+    optics_mtf: Option<optics_mtf::Plan>,
+    tof_accuracy: Option<tof_accuracy::Plan>,
+    vibration: Option<vibration::Plan>,
+    security_mcu: Option<security_mcu::Plan>,
+}
+
+/// Machine-readable health check report.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Report {
+    /// Version of the report format.
+    pub version: u32,
+    /// Whether all checks passed.
+    pub success: bool,
+    /// Class of the first failure, if any.
+    pub failure_class: Option<FailureClass>,
+    /// Process exit code.
+    pub exit_code: i32,
+    /// Results of the individual checks.
+    pub checks: Vec<Check>,
+}
+
+/// Result of a single check.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Check {
+    /// Stable name of the check.
+    pub name: String,
+    /// Whether the check passed.
+    pub success: bool,
+    /// Class of the failure if the check didn't pass.
+    pub failure_class: Option<FailureClass>,
+    /// Measured values keyed by a stable name.
+    pub measurements: BTreeMap<String, f64>,
+    /// Human-readable description of the failure.
+    pub message: Option<String>,
+}
+
+/// Class of a health check failure. Each class maps to a distinct exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureClass {
+    /// The hardware or the broker couldn't be initialized.
+    Setup,
+    /// A camera didn't meet its requirements.
+    Camera,
+    /// The MCU firmware speaks an unsupported protocol version.
+    IncompatibleFirmware,
+    /// The optics didn't meet the sharpness requirements.
+    Optics,
+    /// The 1D ToF sensor didn't meet the accuracy requirements.
+    Tof,
+    /// The fan or the mirror vibrations indicate mechanical wear.
+    Mechanical,
+    /// The security MCU doesn't run the firmware release shipped with the Orb
+    /// OS.
+    Security,
+    /// The health check was interrupted before completion.
+    Interrupted,
+}
+
+impl FailureClass {
+    /// Returns the process exit code for this failure class.
+    #[must_use]
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Setup => 2,
+            Self::Camera => 3,
+            Self::IncompatibleFirmware => 4,
+            Self::Optics => 5,
+            Self::Tof => 6,
+            Self::Mechanical => 7,
+            Self::Security => 8,
+            Self::Interrupted => 130,
+        }
+    }
+}
+
+impl Report {
+    /// Creates a new report from the individual check results.
+    #[must_use]
+    pub fn new(checks: Vec<Check>) -> Self {
+        let failure_class = checks.iter().find_map(|check| check.failure_class);
+        Self {
+            version: REPORT_VERSION,
+            success: failure_class.is_none(),
+            failure_class,
+            exit_code: failure_class.map_or(0, FailureClass::exit_code),
+            checks,
+        }
+    }
+
+    /// Creates a report for a health check which couldn't complete.
+    #[must_use]
+    pub fn failure(name: &str, failure_class: FailureClass, message: String) -> Self {
+        Self::new(vec![Check {
+            name: name.to_owned(),
+            success: false,
+            failure_class: Some(failure_class),
+            measurements: BTreeMap::new(),
+            message: Some(message),
+        }])
+    }
 }
 
 impl Plan {
-    /// Runs the health check plan.
-    pub async fn run(&mut self, orb: &mut Orb) -> Result<bool> {
-        let mut success = true;
-        success = success && self.ir_camera_fps.run(orb).await?;
-        Ok(success)
+    /// Enables the guided optics check. The operator must hold the
+    /// slanted-edge test target at the marked distance.
+    #[must_use]
+    pub fn with_optics_mtf(mut self) -> Self {
+        self.optics_mtf = Some(optics_mtf::Plan::default());
+        self
+    }
+
+    /// Enables the guided 1D ToF accuracy check. The operator must place the
+    /// reference target at the marked distance.
+    #[must_use]
+    pub fn with_tof_accuracy(mut self) -> Self {
+        self.tof_accuracy = Some(tof_accuracy::Plan::default());
+        self
+    }
+
+    /// Enables the fan and mirror vibration check. Requires an MCU firmware
+    /// streaming the IMU samples.
+    #[must_use]
+    pub fn with_vibration(mut self) -> Self {
+        self.vibration = Some(vibration::Plan::default());
+        self
+    }
+
+    /// Enables the security MCU check.
+    #[must_use]
+    pub fn with_security_mcu(mut self) -> Self {
+        self.security_mcu = Some(security_mcu::Plan);
+        self
+    }
+
+    /// Runs the health check plan and records the results to the trend
+    /// history.
+    pub async fn run(&mut self, orb: &mut Orb) -> Result<Report> {
+        let mut checks =
+            vec![self.mcu_protocol.run(orb).await?, self.ir_camera_fps.run(orb).await?];
+        if let Some(optics_mtf) = &mut self.optics_mtf {
+            checks.push(optics_mtf.run(orb).await?);
+        }
+        if let Some(tof_accuracy) = &mut self.tof_accuracy {
+            checks.push(tof_accuracy.run(orb).await?);
+        }
+        if let Some(vibration) = &mut self.vibration {
+            checks.push(vibration.run(orb).await?);
+        }
+        if let Some(security_mcu) = &mut self.security_mcu {
+            checks.push(security_mcu.run().await?);
+        }
+        let report = Report::new(checks);
+        if let Err(err) = trend::record(&report).await {
+            tracing::error!("Failed to record the health check trend history: {err:?}");
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(name: &str, failure_class: Option<FailureClass>) -> Check {
+        Check {
+            name: name.to_owned(),
+            success: failure_class.is_none(),
+            failure_class,
+            measurements: BTreeMap::from([("fps".to_owned(), 30.0)]),
+            message: None,
+        }
+    }
+
+    #[test]
+    fn test_report_success() {
+        let report = Report::new(vec![check("ir_camera_fps", None)]);
+        assert!(report.success);
+        assert_eq!(report.exit_code, 0);
+    }
+
+    #[test]
+    fn test_report_first_failure_class() {
+        let report = Report::new(vec![
+            check("a", None),
+            check("b", Some(FailureClass::Camera)),
+            check("c", Some(FailureClass::Setup)),
+        ]);
+        assert!(!report.success);
+        assert_eq!(report.failure_class, Some(FailureClass::Camera));
+        assert_eq!(report.exit_code, FailureClass::Camera.exit_code());
+    }
+
+    #[test]
+    fn test_report_field_names() {
+        let report = Report::new(vec![check("ir_camera_fps", Some(FailureClass::Camera))]);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["version"], REPORT_VERSION);
+        assert_eq!(json["failure_class"], "camera");
+        assert_eq!(json["checks"][0]["name"], "ir_camera_fps");
+        assert_eq!(json["checks"][0]["measurements"]["fps"], 30.0);
     }
 }
@@ -0,0 +1,361 @@
+//! Optics MTF check.
+//!
+//! The operator holds a slanted-edge test target at the marked distance in
+//! front of the orb, so the edge crosses the center of the IR eye and RGB
+//! camera views. The sharpness of each camera is measured as MTF50, the
+//! spatial frequency at which the contrast of the edge drops to a half, with a
+//! simplified ISO 12233 slanted-edge method: the pixels around the edge are
+//! projected onto the edge normal into an oversampled edge spread function,
+//! which is differentiated and transformed into the modulation transfer
+//! function. The median of the measured frames is compared against the golden
+//! thresholds of the hardware revision.
+
+use super::{Check, FailureClass};
+use crate::{
+    agents::camera::{self, Frame as _},
+    brokers::{Orb, OrbPlan},
+    consts::RGB_FPS,
+    identification,
+};
+use agentwire::{port, BrokerFlow};
+use eyre::Result;
+use futures::prelude::*;
+use std::{
+    collections::BTreeMap,
+    f64::consts::PI,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::time::{sleep, Duration, Sleep};
+
+const TOTAL_TIME: Duration = Duration::from_secs(60);
+
+/// Number of valid measurements per camera to finish the check early.
+const MEASUREMENTS: usize = 15;
+
+/// Side of the square region of interest at the frame center.
+const ROI_SIZE: usize = 128;
+
+/// Oversampling factor of the edge spread function.
+const OVERSAMPLING: usize = 4;
+
+/// Half-width of the edge spread function in pixels.
+const ESF_HALF_WIDTH: usize = 16;
+
+/// Minimum contrast of the edge in luma levels.
+const MIN_CONTRAST: f64 = 20.0;
+
+/// Allowed range of the edge slant. Too small slant doesn't give enough
+/// sub-pixel phases, too large one smears the projection.
+const SLANT_RANGE: (f64, f64) = (0.035, 0.36);
+
+/// MTF50 golden thresholds in cycles per pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+    /// Minimum MTF50 of the IR eye camera.
+    pub ir_eye: f64,
+    /// Minimum MTF50 of the RGB camera.
+    pub rgb: f64,
+}
+
+/// Optics MTF check plan.
+pub struct Plan {
+    timeout: Pin<Box<Sleep>>,
+    ir_eye: Vec<f64>,
+    rgb: Vec<f64>,
+}
+
+impl Thresholds {
+    /// Returns the golden thresholds of the hardware revision.
+    #[must_use]
+    pub fn for_hardware(hardware_version: &str) -> Self {
+        if hardware_version.contains("Diamond") {
+            Self { ir_eye: 0.14, rgb: 0.12 }
+        } else {
+            Self { ir_eye: 0.12, rgb: 0.10 }
+        }
+    }
+}
+
+impl OrbPlan for Plan {
+    fn handle_ir_eye_camera(
+        &mut self,
+        _orb: &mut Orb,
+        output: port::Output<camera::ir::Sensor>,
+    ) -> Result<BrokerFlow> {
+        if self.ir_eye.len() < MEASUREMENTS {
+            let frame = &output.value;
+            let (width, height) = (frame.width() as usize, frame.height() as usize);
+            let data = frame.as_bytes();
+            if data.len() >= width * height {
+                self.ir_eye.extend(center_mtf50(width, height, |x, y| data[y * width + x]));
+            }
+        }
+        Ok(BrokerFlow::Continue)
+    }
+
+    fn handle_rgb_camera(
+        &mut self,
+        _orb: &mut Orb,
+        output: port::Output<camera::rgb::Sensor>,
+    ) -> Result<BrokerFlow> {
+        if self.rgb.len() < MEASUREMENTS {
+            let frame = &output.value;
+            let (width, height) = (frame.width() as usize, frame.height() as usize);
+            let data = frame.as_bytes();
+            if data.len() >= width * height * 3 {
+                // The green channel carries most of the luma.
+                self.rgb.extend(center_mtf50(width, height, |x, y| data[(y * width + x) * 3 + 1]));
+            }
+        }
+        Ok(BrokerFlow::Continue)
+    }
+
+    fn poll_extra(&mut self, _orb: &mut Orb, cx: &mut Context<'_>) -> Result<BrokerFlow> {
+        if self.ir_eye.len() >= MEASUREMENTS && self.rgb.len() >= MEASUREMENTS {
+            return Ok(BrokerFlow::Break);
+        }
+        if let Poll::Ready(()) = self.timeout.poll_unpin(cx) {
+            return Ok(BrokerFlow::Break);
+        }
+        Ok(BrokerFlow::Continue)
+    }
+}
+
+impl Default for Plan {
+    fn default() -> Self {
+        Self { timeout: Box::pin(sleep(TOTAL_TIME)), ir_eye: Vec::new(), rgb: Vec::new() }
+    }
+}
+
+impl Plan {
+    /// Runs the optics MTF check plan.
+    pub async fn run(&mut self, orb: &mut Orb) -> Result<Check> {
+        tracing::info!(
+            "Optics MTF check: running, hold the slanted-edge target at the marked distance"
+        );
+
+        // The plan is created with the other checks, so the timeout starts here.
+        self.timeout = Box::pin(sleep(TOTAL_TIME));
+        orb.enable_ir_led().await?;
+        orb.start_ir_eye_camera().await?;
+        orb.start_rgb_camera(RGB_FPS).await?;
+        orb.run(self).await?;
+        orb.stop_rgb_camera().await?;
+        orb.stop_ir_eye_camera().await?;
+        orb.disable_ir_led().await?;
+
+        let thresholds = Thresholds::for_hardware(&identification::HARDWARE_VERSION);
+        let ir_eye = median(&mut self.ir_eye);
+        let rgb = median(&mut self.rgb);
+        tracing::info!("IR eye camera MTF50: {ir_eye:?} (min {})", thresholds.ir_eye);
+        tracing::info!("RGB camera MTF50: {rgb:?} (min {})", thresholds.rgb);
+
+        let mut failures = Vec::new();
+        for (camera, mtf50, min) in
+            [("IR eye", ir_eye, thresholds.ir_eye), ("RGB", rgb, thresholds.rgb)]
+        {
+            match mtf50 {
+                Some(mtf50) if mtf50 >= min => {}
+                Some(mtf50) => {
+                    failures.push(format!("{camera} camera MTF50 {mtf50:.3} below {min}"));
+                }
+                None => failures.push(format!("{camera} camera didn't see the target")),
+            }
+        }
+        let success = failures.is_empty();
+        tracing::info!("Optics MTF check: {}", if success { "OK!" } else { "FAILURE!" });
+        let mut measurements = BTreeMap::new();
+        measurements.extend(ir_eye.map(|mtf50| ("ir_eye_camera_mtf50".to_owned(), mtf50)));
+        measurements.extend(rgb.map(|mtf50| ("rgb_camera_mtf50".to_owned(), mtf50)));
+        #[allow(clippy::cast_precision_loss)]
+        {
+            measurements.insert("ir_eye_camera_samples".to_owned(), self.ir_eye.len() as f64);
+            measurements.insert("rgb_camera_samples".to_owned(), self.rgb.len() as f64);
+        }
+        Ok(Check {
+            name: "optics_mtf".to_owned(),
+            success,
+            failure_class: (!success).then_some(FailureClass::Optics),
+            measurements,
+            message: (!success).then(|| failures.join(", ")),
+        })
+    }
+}
+
+/// Measures MTF50 of a slanted edge in the center of a `width`×`height`
+/// image, reading the luma of a pixel with `luma(x, y)`.
+pub fn center_mtf50(width: usize, height: usize, luma: impl Fn(usize, usize) -> u8) -> Option<f64> {
+    if width < ROI_SIZE || height < ROI_SIZE {
+        return None;
+    }
+    let (x0, y0) = ((width - ROI_SIZE) / 2, (height - ROI_SIZE) / 2);
+    mtf50(ROI_SIZE, ROI_SIZE, |x, y| luma(x0 + x, y0 + y))
+}
+
+/// Measures MTF50 of a slanted edge crossing a `width`×`height` image in
+/// cycles per pixel. Returns `None` if no suitable edge is found.
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn mtf50(width: usize, height: usize, luma: impl Fn(usize, usize) -> u8) -> Option<f64> {
+    if width < 2 || height < 2 {
+        return None;
+    }
+    let luma = |x: usize, y: usize| f64::from(luma(x, y));
+    let (mut gx, mut gy) = (0.0, 0.0);
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            gx += (luma(x + 1, y) - luma(x, y)).abs();
+            gy += (luma(x, y + 1) - luma(x, y)).abs();
+        }
+    }
+    // Work with a near-vertical edge, transposing a near-horizontal one.
+    let transposed = gy > gx;
+    let (width, height) = if transposed { (height, width) } else { (width, height) };
+    let px = |x: usize, y: usize| if transposed { luma(y, x) } else { luma(x, y) };
+
+    // Locate the edge in each row by the centroid of the horizontal gradient.
+    let mut centroids = Vec::with_capacity(height);
+    for y in 0..height {
+        let (mut sum, mut moment) = (0.0, 0.0);
+        for x in 0..width - 1 {
+            let d = (px(x + 1, y) - px(x, y)).abs();
+            sum += d;
+            moment += d * (x as f64 + 0.5);
+        }
+        if sum > 0.0 {
+            centroids.push((y as f64, moment / sum));
+        }
+    }
+    if centroids.len() < height / 2 {
+        return None;
+    }
+    let n = centroids.len() as f64;
+    let mean_y = centroids.iter().map(|&(y, _)| y).sum::<f64>() / n;
+    let mean_c = centroids.iter().map(|&(_, c)| c).sum::<f64>() / n;
+    let syy = centroids.iter().map(|&(y, _)| (y - mean_y).powi(2)).sum::<f64>();
+    let syc = centroids.iter().map(|&(y, c)| (y - mean_y) * (c - mean_c)).sum::<f64>();
+    let slope = syc / syy;
+    let offset = mean_c - slope * mean_y;
+    if !(SLANT_RANGE.0..=SLANT_RANGE.1).contains(&slope.abs()) {
+        return None;
+    }
+
+    // Project the pixels onto the edge normal into the oversampled bins.
+    let cos = 1.0 / slope.mul_add(slope, 1.0).sqrt();
+    let bins = 2 * ESF_HALF_WIDTH * OVERSAMPLING;
+    let (mut sums, mut counts) = (vec![0.0; bins], vec![0_u32; bins]);
+    for y in 0..height {
+        let edge = slope.mul_add(y as f64, offset);
+        for x in 0..width {
+            let distance = (x as f64 - edge) * cos;
+            let bin =
+                (distance * OVERSAMPLING as f64).floor() + (ESF_HALF_WIDTH * OVERSAMPLING) as f64;
+            if (0.0..bins as f64).contains(&bin) {
+                sums[bin as usize] += px(x, y);
+                counts[bin as usize] += 1;
+            }
+        }
+    }
+    let first = sums.iter().zip(&counts).find(|(_, &count)| count > 0)?;
+    let mut last = first.0 / f64::from(*first.1);
+    let esf = sums
+        .iter()
+        .zip(&counts)
+        .map(|(&sum, &count)| {
+            if count > 0 {
+                last = sum / f64::from(count);
+            }
+            last
+        })
+        .collect::<Vec<_>>();
+    let (min, max) =
+        esf.iter().fold((f64::MAX, f64::MIN), |(min, max), &v| (min.min(v), max.max(v)));
+    if max - min < MIN_CONTRAST {
+        return None;
+    }
+
+    // Differentiate into the line spread function and apply a Hamming window
+    // centered at its peak.
+    let lsf = esf.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+    let len = lsf.len();
+    let peak = (0..len).max_by(|&a, &b| lsf[a].abs().total_cmp(&lsf[b].abs()))?;
+    let lsf = lsf
+        .iter()
+        .enumerate()
+        .map(|(i, v)| v * 0.46f64.mul_add((PI * (i as f64 - peak as f64) / len as f64).cos(), 0.54))
+        .collect::<Vec<_>>();
+
+    // Find the first frequency where the MTF drops below 0.5.
+    let magnitude = |k: usize| {
+        let (mut re, mut im) = (0.0, 0.0);
+        for (i, v) in lsf.iter().enumerate() {
+            let phase = 2.0 * PI * (k * i) as f64 / len as f64;
+            re += v * phase.cos();
+            im += v * phase.sin();
+        }
+        re.hypot(im)
+    };
+    let dc = magnitude(0);
+    if dc <= 0.0 {
+        return None;
+    }
+    let mut prev = 1.0;
+    for k in 1..=len / 2 {
+        let mtf = magnitude(k) / dc;
+        if mtf < 0.5 {
+            let frequency = |k: usize| (k * OVERSAMPLING) as f64 / len as f64;
+            let t = (prev - 0.5) / (prev - mtf);
+            return Some(t.mul_add(frequency(k) - frequency(k - 1), frequency(k - 1)));
+        }
+        prev = mtf;
+    }
+    None
+}
+
+pub(super) fn median(values: &mut [f64]) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    match values.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((values[mid - 1] + values[mid]) / 2.0),
+        _ => Some(values[mid]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Slanted edge blurred by a logistic spread function of the scale `s`,
+    /// whose MTF50 is `2.1773 / (2π²s)`.
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn edge(s: f64, slope: f64, transposed: bool) -> impl Fn(usize, usize) -> u8 {
+        move |x, y| {
+            let (x, y) = if transposed { (y, x) } else { (x, y) };
+            let d =
+                (x as f64 - 64.0 - slope * (y as f64 - 64.0)) / slope.mul_add(slope, 1.0).sqrt();
+            (40.0 + 160.0 / (1.0 + (-d / s).exp())).round() as u8
+        }
+    }
+
+    #[test]
+    fn test_mtf50() {
+        for s in [0.5, 1.0, 1.5] {
+            let expected = 2.1773 / (2.0 * PI * PI * s);
+            let mtf50 = mtf50(ROI_SIZE, ROI_SIZE, edge(s, 0.1, false)).unwrap();
+            assert!((mtf50 / expected - 1.0).abs() < 0.1, "s={s}: {mtf50} vs {expected}");
+        }
+        let vertical = mtf50(ROI_SIZE, ROI_SIZE, edge(1.0, 0.1, false)).unwrap();
+        let horizontal = mtf50(ROI_SIZE, ROI_SIZE, edge(1.0, 0.1, true)).unwrap();
+        assert!((vertical - horizontal).abs() < 1e-9);
+        assert!(mtf50(ROI_SIZE, ROI_SIZE, edge(1.0, 0.0, false)).is_none());
+        assert!(mtf50(ROI_SIZE, ROI_SIZE, |_, _| 100).is_none());
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&mut []), None);
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), Some(2.0));
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), Some(2.5));
+    }
+}
@@ -0,0 +1,121 @@
+//! Security MCU check.
+//!
+//! Queries the firmware versions of the security MCU. The check fails if the
+//! MCU doesn't answer, or if it doesn't run the release listed in
+//! `versions.json`.
+//!
+//! The tamper switches, the secure boot state, and the battery-backed RTC
+//! aren't checked until the pinned `orb-messages` revision defines their
+//! messages.
+
+use super::{Check, FailureClass};
+use crate::{
+    ext::broadcast::ReceiverExt as _,
+    identification::MCU_SEC_RELEASE,
+    mcu::{
+        self,
+        main::{Version, Versions},
+        sec::{Input, Output},
+        Mcu,
+    },
+};
+use eyre::Result;
+use std::collections::BTreeMap;
+use tokio::time::{self, Duration};
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Security MCU check plan.
+#[derive(Default)]
+pub struct Plan;
+
+impl Plan {
+    /// Runs the security MCU check plan.
+    pub async fn run(&mut self) -> Result<Check> {
+        tracing::info!("Security MCU check: running");
+        let mut sec_mcu = mcu::sec::Jetson::spawn()?;
+        if let Err(err) = sec_mcu.send(Input::Version).await {
+            tracing::error!("Security MCU check: versions request failed: {err:?}");
+        }
+        let versions = async {
+            loop {
+                if let Output::Versions(versions) = sec_mcu.rx_mut().next_broadcast().await? {
+                    break Ok::<_, eyre::Error>(versions);
+                }
+            }
+        };
+        let versions = time::timeout(TIMEOUT, versions).await.ok().transpose()?;
+        let expected = MCU_SEC_RELEASE.as_deref().map(str::parse::<Version>).transpose()?;
+
+        let result = evaluate(versions.as_ref(), expected.as_ref());
+        let success = result.message.is_none();
+        tracing::info!("Security MCU check: {}", if success { "OK!" } else { "FAILURE!" });
+        Ok(Check {
+            name: "security_mcu".to_owned(),
+            success,
+            failure_class: result.failure_class,
+            measurements: result.measurements,
+            message: result.message,
+        })
+    }
+}
+
+struct Evaluation {
+    failure_class: Option<FailureClass>,
+    measurements: BTreeMap<String, f64>,
+    message: Option<String>,
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn evaluate(versions: Option<&Versions>, expected: Option<&Version>) -> Evaluation {
+    let mut measurements = BTreeMap::new();
+    let Some(versions) = versions else {
+        return Evaluation {
+            failure_class: Some(FailureClass::Setup),
+            measurements,
+            message: Some("no firmware versions response from the security MCU".to_owned()),
+        };
+    };
+    measurements.insert("primary_version".to_owned(), versions.primary.counter_value() as f64);
+    let message =
+        expected.filter(|expected| !versions.primary.same_release(expected)).map(|expected| {
+            format!("security MCU runs firmware {}, expected {expected}", versions.primary)
+        });
+    Evaluation {
+        failure_class: message.is_some().then_some(FailureClass::Security),
+        measurements,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(primary: &str) -> Versions {
+        Versions { primary: primary.parse().unwrap(), secondary: Version::default() }
+    }
+
+    #[test]
+    fn test_evaluate_healthy() {
+        let expected = "v1.2.3".parse().unwrap();
+        let evaluation = evaluate(Some(&versions("v1.2.3")), Some(&expected));
+        assert!(evaluation.message.is_none());
+        assert!(evaluation.failure_class.is_none());
+        assert!(evaluate(Some(&versions("v1.2.3")), None).message.is_none());
+    }
+
+    #[test]
+    fn test_evaluate_mismatch() {
+        let expected = "v1.2.4".parse().unwrap();
+        let evaluation = evaluate(Some(&versions("v1.2.3")), Some(&expected));
+        assert_eq!(evaluation.failure_class, Some(FailureClass::Security));
+        assert!(evaluation.message.unwrap().contains("v1.2.4"));
+    }
+
+    #[test]
+    fn test_evaluate_no_response() {
+        let evaluation = evaluate(None, None);
+        assert_eq!(evaluation.failure_class, Some(FailureClass::Setup));
+    }
+}
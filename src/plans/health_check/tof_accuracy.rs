@@ -0,0 +1,147 @@
+//! 1D ToF accuracy check.
+//!
+//! The operator places the flat reference target at the marked distance in
+//! front of the orb, and the median of the measured distances is compared
+//! against the reference distance. The median rejects the ranging noise of the
+//! default timing budget, which can't be changed by the main MCU protocol.
+
+use super::{optics_mtf::median, Check, FailureClass};
+use crate::{
+    brokers::{Orb, OrbPlan},
+    ext::broadcast::ReceiverExt as _,
+    mcu,
+};
+use agentwire::BrokerFlow;
+use eyre::Result;
+use futures::prelude::*;
+use std::{
+    collections::BTreeMap,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::time::{sleep, Duration, Sleep};
+
+const TOTAL_TIME: Duration = Duration::from_secs(15);
+
+/// Number of measurements to finish the check early.
+const MEASUREMENTS: usize = 50;
+
+/// Minimum number of measurements for a valid result.
+const MIN_MEASUREMENTS: usize = 10;
+
+/// Distance of the reference target in mm.
+pub const REFERENCE_DISTANCE_MM: u32 = 400;
+
+/// Maximal deviation of the median distance from the reference distance in mm.
+pub const TOLERANCE_MM: f64 = 15.0;
+
+/// 1D ToF accuracy check plan.
+pub struct Plan {
+    timeout: Pin<Box<Sleep>>,
+    distances: Vec<f64>,
+}
+
+impl OrbPlan for Plan {
+    fn poll_extra(&mut self, orb: &mut Orb, cx: &mut Context<'_>) -> Result<BrokerFlow> {
+        while let Poll::Ready(output) = orb.main_mcu.rx_mut().next_broadcast().poll_unpin(cx) {
+            if let mcu::main::Output::TofDistance(distance) = output? {
+                self.distances.push(f64::from(distance));
+                if self.distances.len() >= MEASUREMENTS {
+                    return Ok(BrokerFlow::Break);
+                }
+            }
+        }
+        if let Poll::Ready(()) = self.timeout.poll_unpin(cx) {
+            return Ok(BrokerFlow::Break);
+        }
+        Ok(BrokerFlow::Continue)
+    }
+}
+
+impl Default for Plan {
+    fn default() -> Self {
+        Self { timeout: Box::pin(sleep(TOTAL_TIME)), distances: Vec::new() }
+    }
+}
+
+impl Plan {
+    /// Runs the 1D ToF accuracy check plan.
+    pub async fn run(&mut self, orb: &mut Orb) -> Result<Check> {
+        tracing::info!(
+            "ToF accuracy check: running, place the reference target at {REFERENCE_DISTANCE_MM} mm"
+        );
+
+        // The plan is created with the other checks, so the timeout starts here.
+        self.timeout = Box::pin(sleep(TOTAL_TIME));
+        orb.main_mcu.rx_mut().clear()?;
+        orb.run(self).await?;
+
+        #[allow(clippy::cast_precision_loss)]
+        let samples = self.distances.len() as f64;
+        let result = evaluate(&mut self.distances);
+        let mut measurements = BTreeMap::from([
+            ("tof_reference_distance_mm".to_owned(), f64::from(REFERENCE_DISTANCE_MM)),
+            ("tof_samples".to_owned(), samples),
+        ]);
+        if let Some(distance) = result.distance {
+            tracing::info!("ToF median distance: {distance} mm");
+            measurements.insert("tof_distance_mm".to_owned(), distance);
+            measurements
+                .insert("tof_error_mm".to_owned(), distance - f64::from(REFERENCE_DISTANCE_MM));
+        }
+        let success = result.message.is_none();
+        tracing::info!("ToF accuracy check: {}", if success { "OK!" } else { "FAILURE!" });
+        Ok(Check {
+            name: "tof_accuracy".to_owned(),
+            success,
+            failure_class: (!success).then_some(FailureClass::Tof),
+            measurements,
+            message: result.message,
+        })
+    }
+}
+
+struct Evaluation {
+    distance: Option<f64>,
+    message: Option<String>,
+}
+
+fn evaluate(distances: &mut [f64]) -> Evaluation {
+    if distances.len() < MIN_MEASUREMENTS {
+        return Evaluation {
+            distance: median(distances),
+            message: Some(format!("only {} ToF measurements received", distances.len())),
+        };
+    }
+    let distance = median(distances);
+    let message = distance.and_then(|distance| {
+        let error = distance - f64::from(REFERENCE_DISTANCE_MM);
+        (error.abs() > TOLERANCE_MM).then(|| {
+            format!(
+                "ToF distance {distance} mm deviates from {REFERENCE_DISTANCE_MM} mm by more than \
+                 {TOLERANCE_MM} mm"
+            )
+        })
+    });
+    Evaluation { distance, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let mut distances = vec![405.0; MIN_MEASUREMENTS];
+        distances[0] = 2000.0;
+        let evaluation = evaluate(&mut distances);
+        assert!(evaluation.message.is_none());
+        assert!((evaluation.distance.unwrap() - 405.0).abs() < f64::EPSILON);
+
+        let evaluation = evaluate(&mut [430.0; MIN_MEASUREMENTS]);
+        assert!(evaluation.message.is_some());
+
+        let evaluation = evaluate(&mut [400.0; MIN_MEASUREMENTS - 1]);
+        assert!(evaluation.message.is_some());
+    }
+}
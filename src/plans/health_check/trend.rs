@@ -0,0 +1,145 @@
+//! Health check trend history.
+//!
+//! Keeps the measurements of the latest [`HEALTH_CHECK_HISTORY_LEN`] health
+//! check runs, so slow degradations, like optics losing sharpness, can be
+//! spotted before a check starts failing.
+
+use super::Report;
+use crate::{
+    consts::{HEALTH_CHECK_HISTORY_FILE_PATH, HEALTH_CHECK_HISTORY_LEN},
+    identification::{GIT_VERSION, HARDWARE_VERSION},
+};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::fs;
+
+/// Health check trend history.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    /// Recorded entries, the oldest first.
+    pub entries: VecDeque<Entry>,
+}
+
+/// Health check trend history entry.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// UNIX time in seconds.
+    pub timestamp: u64,
+    /// Hardware version of the orb.
+    pub hardware_version: String,
+    /// Version of the software which ran the checks.
+    pub git_version: String,
+    /// Results of the individual checks keyed by the check name.
+    pub checks: BTreeMap<String, CheckEntry>,
+}
+
+/// Recorded result of a single check.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CheckEntry {
+    /// Whether the check passed.
+    pub success: bool,
+    /// Measured values keyed by a stable name.
+    pub measurements: BTreeMap<String, f64>,
+}
+
+impl History {
+    /// Loads the history from the file system. Returns an empty history if the
+    /// file doesn't exist or is malformed.
+    pub async fn load<P: AsRef<Path>>(path: P) -> Self {
+        let Ok(contents) = fs::read_to_string(path).await else {
+            return Self::default();
+        };
+        serde_json::from_str(&contents).unwrap_or_else(|err| {
+            tracing::error!("Health check history loading error: {err:?}");
+            Self::default()
+        })
+    }
+
+    /// Stores the history to the file system.
+    pub async fn store<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?).await.map_err(Into::into)
+    }
+
+    /// Appends the results of a report, dropping the oldest entries over the
+    /// limit.
+    pub fn push(&mut self, report: &Report) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let checks = report
+            .checks
+            .iter()
+            .map(|check| {
+                let entry =
+                    CheckEntry { success: check.success, measurements: check.measurements.clone() };
+                (check.name.clone(), entry)
+            })
+            .collect();
+        self.entries.push_back(Entry {
+            timestamp,
+            hardware_version: HARDWARE_VERSION.clone(),
+            git_version: GIT_VERSION.clone(),
+            checks,
+        });
+        while self.entries.len() > HEALTH_CHECK_HISTORY_LEN {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Returns the recorded values of a measurement of a check, the oldest
+    /// first.
+    pub fn series<'a>(
+        &'a self,
+        check: &'a str,
+        measurement: &'a str,
+    ) -> impl Iterator<Item = f64> + 'a {
+        self.entries.iter().filter_map(move |entry| {
+            entry.checks.get(check)?.measurements.get(measurement).copied()
+        })
+    }
+}
+
+/// Records the report to the health check trend history on the file system.
+pub async fn record(report: &Report) -> Result<()> {
+    let mut history = History::load(HEALTH_CHECK_HISTORY_FILE_PATH).await;
+    history.push(report);
+    history.store(HEALTH_CHECK_HISTORY_FILE_PATH).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::Check, *};
+
+    fn report(mtf50: f64) -> Report {
+        Report::new(vec![Check {
+            name: "optics_mtf".to_owned(),
+            success: true,
+            failure_class: None,
+            measurements: BTreeMap::from([("ir_eye_camera_mtf50".to_owned(), mtf50)]),
+            message: None,
+        }])
+    }
+
+    #[tokio::test]
+    async fn test_store_and_load_history() {
+        let temp_dir = tempfile::tempdir().expect("to create temp dir");
+        let path = temp_dir.path().join("health_check_history.json");
+        assert!(History::load(&path).await.entries.is_empty());
+
+        let mut history = History::default();
+        for i in 0..HEALTH_CHECK_HISTORY_LEN + 5 {
+            history.push(&report(f64::from(u32::try_from(i).unwrap())));
+        }
+        history.store(&path).await.expect("to store history");
+
+        let loaded = History::load(&path).await;
+        assert_eq!(loaded.entries.len(), HEALTH_CHECK_HISTORY_LEN);
+        let series = loaded.series("optics_mtf", "ir_eye_camera_mtf50").collect::<Vec<_>>();
+        assert_eq!(series.first(), Some(&5.0));
+        assert_eq!(series.last(), Some(&104.0));
+        assert_eq!(loaded.series("optics_mtf", "rgb_camera_mtf50").count(), 0);
+    }
+}
@@ -0,0 +1,282 @@
+//! Fan and mirror vibration check.
+//!
+//! Mechanical wear shows up in the vibrations picked up by the main MCU IMU
+//! long before it affects the captures. The accelerometer is sampled for
+//! [`TOTAL_TIME`] while the mirror is swept back and forth. The sample rate is
+//! derived from the MCU sample timestamps, and the check fails if it can't
+//! resolve [`MIRROR_RESONANCE_BAND`], instead of passing without a
+//! measurement. An unbalanced fan
+//! vibrates at its rotation frequency, which is derived from the fan speed
+//! reported by the MCU and measured with the Goertzel algorithm. Loose mirror
+//! steppers resonate within [`MIRROR_RESONANCE_BAND`], where the strongest
+//! component is looked up in the FFT spectrum.
+
+use super::{optics_mtf::median, Check, FailureClass};
+use crate::{
+    brokers::{Orb, OrbPlan},
+    dsp::{goertzel, Spectrum},
+    ext::broadcast::ReceiverExt as _,
+    mcu::{self, main::ImuSample},
+};
+use agentwire::BrokerFlow;
+use eyre::Result;
+use futures::prelude::*;
+use std::{
+    collections::BTreeMap,
+    ops::RangeInclusive,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::time::{self, sleep, Duration, Interval, Sleep};
+
+const TOTAL_TIME: Duration = Duration::from_secs(10);
+
+/// Minimum number of IMU samples for a valid result.
+const MIN_SAMPLES: usize = 256;
+
+/// Mirror sweep half-period.
+const MIRROR_SWEEP_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Mirror sweep positions in millidegrees, within the range of all hardware
+/// revisions.
+const MIRROR_SWEEP: [(u32, u32); 2] = [(40000, 85000), (50000, 95000)];
+
+/// Mirror center position in millidegrees.
+const MIRROR_CENTER: (u32, u32) = (45000, 90000);
+
+/// Frequency band of the mirror stepper resonances in Hz.
+pub const MIRROR_RESONANCE_BAND: RangeInclusive<f64> = 80.0..=160.0;
+
+/// Maximal acceleration amplitude at the fan rotation frequency in raw
+/// accelerometer units.
+pub const FAN_IMBALANCE_MAX_AMPLITUDE: f64 = 40.0;
+
+/// Maximal acceleration amplitude within [`MIRROR_RESONANCE_BAND`] in raw
+/// accelerometer units.
+pub const MIRROR_RESONANCE_MAX_AMPLITUDE: f64 = 60.0;
+
+/// Fan and mirror vibration check plan.
+pub struct Plan {
+    timeout: Pin<Box<Sleep>>,
+    sweep: Interval,
+    sweep_index: usize,
+    samples: Vec<ImuSample>,
+    fan_rpms: Vec<f64>,
+}
+
+impl OrbPlan for Plan {
+    fn poll_extra(&mut self, orb: &mut Orb, cx: &mut Context<'_>) -> Result<BrokerFlow> {
+        while let Poll::Ready(output) = orb.main_mcu.rx_mut().next_broadcast().poll_unpin(cx) {
+            match output? {
+                mcu::main::Output::Imu(sample) => self.samples.push(sample),
+                mcu::main::Output::FanStatus(status)
+                    if status.fan_id == orb_messages::mcu_main::fan_status::FanId::Main as i32 =>
+                {
+                    self.fan_rpms.push(f64::from(status.measured_speed_rpm));
+                }
+                _ => {}
+            }
+        }
+        while self.sweep.poll_tick(cx).is_ready() {
+            self.sweep_index = (self.sweep_index + 1) % MIRROR_SWEEP.len();
+            let (phi, theta) = MIRROR_SWEEP[self.sweep_index];
+            orb.main_mcu.send_now(mcu::main::Input::Mirror(phi, theta))?;
+        }
+        if let Poll::Ready(()) = self.timeout.poll_unpin(cx) {
+            return Ok(BrokerFlow::Break);
+        }
+        Ok(BrokerFlow::Continue)
+    }
+}
+
+impl Default for Plan {
+    fn default() -> Self {
+        Self {
+            timeout: Box::pin(sleep(TOTAL_TIME)),
+            sweep: time::interval(MIRROR_SWEEP_INTERVAL),
+            sweep_index: 0,
+            samples: Vec::new(),
+            fan_rpms: Vec::new(),
+        }
+    }
+}
+
+impl Plan {
+    /// Runs the fan and mirror vibration check plan.
+    pub async fn run(&mut self, orb: &mut Orb) -> Result<Check> {
+        tracing::info!("Vibration check: running");
+
+        // The plan is created with the other checks, so the timers start here.
+        self.timeout = Box::pin(sleep(TOTAL_TIME));
+        self.sweep = time::interval(MIRROR_SWEEP_INTERVAL);
+        orb.main_mcu.rx_mut().clear()?;
+        orb.run(self).await?;
+        let (phi, theta) = MIRROR_CENTER;
+        orb.main_mcu.send(mcu::main::Input::Mirror(phi, theta)).await?;
+
+        let result = evaluate(&self.samples, median(&mut self.fan_rpms));
+        let success = result.message.is_none();
+        tracing::info!("Vibration check: {}", if success { "OK!" } else { "FAILURE!" });
+        Ok(Check {
+            name: "vibration".to_owned(),
+            success,
+            failure_class: result.failure_class,
+            measurements: result.measurements,
+            message: result.message,
+        })
+    }
+}
+
+struct Evaluation {
+    measurements: BTreeMap<String, f64>,
+    message: Option<String>,
+    failure_class: Option<FailureClass>,
+}
+
+impl Evaluation {
+    fn firmware_failure(measurements: BTreeMap<String, f64>, message: String) -> Self {
+        Self {
+            measurements,
+            message: Some(message),
+            failure_class: Some(FailureClass::IncompatibleFirmware),
+        }
+    }
+}
+
+fn evaluate(samples: &[ImuSample], fan_rpm: Option<f64>) -> Evaluation {
+    #[allow(clippy::cast_precision_loss)]
+    let mut measurements = BTreeMap::from([("imu_samples".to_owned(), samples.len() as f64)]);
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return Evaluation::firmware_failure(measurements, "no IMU samples received".to_owned());
+    };
+    if samples.len() < MIN_SAMPLES {
+        let message = format!("only {} IMU samples received", samples.len());
+        return Evaluation::firmware_failure(measurements, message);
+    }
+    let Some(span_us) = last.timestamp_us.checked_sub(first.timestamp_us).filter(|span| *span > 0)
+    else {
+        let message = "IMU sample timestamps aren't increasing".to_owned();
+        return Evaluation::firmware_failure(measurements, message);
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let sample_rate = (samples.len() - 1) as f64 / (span_us as f64 / 1_000_000.0);
+    measurements.insert("imu_sample_rate_hz".to_owned(), sample_rate);
+    let max_frequency = MIRROR_RESONANCE_BAND.end();
+    if sample_rate / 2.0 <= *max_frequency {
+        let message = format!(
+            "IMU sample rate {sample_rate:.1} Hz can't resolve the mirror resonance band up to \
+             {max_frequency} Hz"
+        );
+        return Evaluation::firmware_failure(measurements, message);
+    }
+    let axes = (0..3)
+        .map(|axis| samples.iter().map(|sample| f64::from(sample.accel[axis])).collect())
+        .collect::<Vec<Vec<f64>>>();
+    let mut findings = Vec::new();
+
+    let fan_frequency = fan_rpm.map(|rpm| rpm / 60.0).filter(|frequency| *frequency > 0.0);
+    if let Some(frequency) = fan_frequency.filter(|frequency| *frequency < sample_rate / 2.0) {
+        let amplitude = axes
+            .iter()
+            .map(|axis| goertzel(axis, sample_rate, frequency).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        tracing::info!("Fan vibration: {amplitude:.1} at {frequency:.1} Hz");
+        measurements.insert("fan_frequency_hz".to_owned(), frequency);
+        measurements.insert("fan_vibration_amplitude".to_owned(), amplitude);
+        if amplitude > FAN_IMBALANCE_MAX_AMPLITUDE {
+            findings.push(format!(
+                "fan vibration {amplitude:.1} at {frequency:.1} Hz exceeds \
+                 {FAN_IMBALANCE_MAX_AMPLITUDE}, the fan may be unbalanced"
+            ));
+        }
+    }
+
+    let resonance = axes
+        .iter()
+        .filter_map(|axis| Spectrum::new(axis, sample_rate).peak(MIRROR_RESONANCE_BAND))
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+    if let Some((frequency, amplitude)) = resonance {
+        tracing::info!("Mirror resonance: {amplitude:.1} at {frequency:.1} Hz");
+        measurements.insert("mirror_resonance_frequency_hz".to_owned(), frequency);
+        measurements.insert("mirror_resonance_amplitude".to_owned(), amplitude);
+        if amplitude > MIRROR_RESONANCE_MAX_AMPLITUDE {
+            findings.push(format!(
+                "mirror resonance {amplitude:.1} at {frequency:.1} Hz exceeds \
+                 {MIRROR_RESONANCE_MAX_AMPLITUDE}, the mirror steppers may be loose"
+            ));
+        }
+    }
+
+    let message = (!findings.is_empty()).then(|| findings.join("; "));
+    let failure_class = message.is_some().then_some(FailureClass::Mechanical);
+    Evaluation { measurements, message, failure_class }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const SAMPLE_RATE: f64 = 400.0;
+
+    fn samples(components: &[(f64, f64)]) -> Vec<ImuSample> {
+        samples_at(SAMPLE_RATE, components)
+    }
+
+    fn samples_at(sample_rate: f64, components: &[(f64, f64)]) -> Vec<ImuSample> {
+        (0..1024_u32)
+            .map(|i| {
+                let t = f64::from(i) / sample_rate;
+                let z = components
+                    .iter()
+                    .map(|(frequency, amplitude)| amplitude * (2.0 * PI * frequency * t).sin())
+                    .sum::<f64>();
+                #[allow(clippy::cast_possible_truncation)]
+                let accel = [0, 0, 1000 + z.round() as i32];
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let timestamp_us = 1_000_000 + (t * 1_000_000.0).round() as u64;
+                ImuSample { timestamp_us, accel, gyro: [0; 3] }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_evaluate_healthy() {
+        let evaluation = evaluate(&samples(&[(50.0, 10.0), (120.0, 20.0)]), Some(3000.0));
+        assert!(evaluation.message.is_none());
+        let frequency = evaluation.measurements["mirror_resonance_frequency_hz"];
+        assert!((frequency - 120.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_evaluate_fan_imbalance() {
+        let evaluation = evaluate(&samples(&[(50.0, 100.0)]), Some(3000.0));
+        assert!(evaluation.message.unwrap().contains("fan"));
+        assert!(evaluation.measurements["fan_vibration_amplitude"] > FAN_IMBALANCE_MAX_AMPLITUDE);
+    }
+
+    #[test]
+    fn test_evaluate_mirror_resonance() {
+        let evaluation = evaluate(&samples(&[(100.0, 150.0)]), None);
+        assert!(evaluation.message.unwrap().contains("mirror"));
+        assert_eq!(evaluation.failure_class, Some(FailureClass::Mechanical));
+        assert!(!evaluation.measurements.contains_key("fan_vibration_amplitude"));
+    }
+
+    #[test]
+    fn test_evaluate_no_samples() {
+        assert!(evaluate(&[], Some(3000.0)).message.is_some());
+        assert!(evaluate(&samples(&[])[..MIN_SAMPLES - 1], None).message.is_some());
+    }
+
+    #[test]
+    fn test_evaluate_low_sample_rate() {
+        let evaluation = evaluate(&samples_at(200.0, &[(50.0, 10.0)]), Some(3000.0));
+        assert!(evaluation.message.unwrap().contains("sample rate"));
+        assert_eq!(evaluation.failure_class, Some(FailureClass::IncompatibleFirmware));
+        let mut samples = samples(&[]);
+        samples.iter_mut().for_each(|sample| sample.timestamp_us = 0);
+        assert!(evaluate(&samples, None).message.unwrap().contains("timestamps"));
+    }
+}
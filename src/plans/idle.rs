@@ -1,10 +1,16 @@
 //! Run background tasks until the button is pressed.
+//!
+//! The livestream remote control commands are served only by this plan, so
+//! they are disabled during signups. The mirror control is served by the
+//! [maintenance](super::maintenance) plan instead.
 
-use super::qr_scan;
+use super::{health_check, qr_scan, scheduled_reboot};
+#[cfg(feature = "livestream")]
+use crate::agents::livestream;
 use crate::{
     agents::{camera, qr_code},
     brokers::{Orb, OrbPlan},
-    consts::BUTTON_LONG_PRESS_DURATION,
+    consts::{BUTTON_LONG_PRESS_DURATION, SCHEDULED_REBOOT_CHECK_INTERVAL},
     ext::broadcast::ReceiverExt as _,
     mcu,
 };
@@ -17,6 +23,7 @@ use std::{
     time::{Duration, SystemTime},
 };
 use tokio::time;
+use tokio_stream::wrappers::{IntervalStream, WatchStream};
 
 #[cfg(feature = "internal-data-acquisition")]
 use once_cell::sync::Lazy;
@@ -39,6 +46,12 @@ pub struct Plan {
     ui_idle_delay: Option<Pin<Box<time::Sleep>>>,
     timeout: Fuse<Pin<Box<time::Sleep>>>,
     timed_out: bool,
+    health_check_requested: bool,
+    maintenance_mode: Option<WatchStream<bool>>,
+    maintenance_requested: bool,
+    scheduled_reboot: bool,
+    reboot_check: Option<IntervalStream>,
+    reboot_uptime: Option<Duration>,
     #[cfg(feature = "internal-data-acquisition")]
     data_acquisition: bool,
 }
@@ -51,6 +64,10 @@ pub enum Value {
     ButtonPress,
     /// The plan timed out.
     TimedOut,
+    /// The maintenance mode was requested.
+    Maintenance,
+    /// The scheduled reboot is due. Contains the system uptime.
+    Reboot(Duration),
 }
 
 impl OrbPlan for Plan {
@@ -78,6 +95,25 @@ impl OrbPlan for Plan {
         }
     }
 
+    #[cfg(feature = "livestream")]
+    fn handle_livestream(
+        &mut self,
+        orb: &mut Orb,
+        output: port::Output<livestream::Agent>,
+    ) -> Result<BrokerFlow> {
+        match output.value {
+            livestream::Command::TriggerHealthCheck => {
+                self.health_check_requested = true;
+                return Ok(BrokerFlow::Break);
+            }
+            livestream::Command::MoveMirror(_) => {
+                tracing::warn!("Livestream mirror control is allowed only in the maintenance mode");
+            }
+            livestream::Command::ToggleOverlay => {}
+        }
+        Ok(BrokerFlow::Continue)
+    }
+
     fn poll_extra(&mut self, orb: &mut Orb, cx: &mut Context<'_>) -> Result<BrokerFlow> {
         if let Some(qr_scan) = &mut self.user_qr_scan {
             if let BrokerFlow::Break = qr_scan.poll_extra(orb, cx)? {
@@ -115,6 +151,23 @@ impl OrbPlan for Plan {
                 }
             }
         }
+        if let Some(maintenance_mode) = &mut self.maintenance_mode {
+            while let Poll::Ready(Some(enabled)) = maintenance_mode.poll_next_unpin(cx) {
+                if enabled {
+                    self.maintenance_requested = true;
+                    return Ok(BrokerFlow::Break);
+                }
+            }
+        }
+        if let Some(reboot_check) = &mut self.reboot_check {
+            while let Poll::Ready(Some(_)) = reboot_check.poll_next_unpin(cx) {
+                let Ok(config) = orb.config.try_lock() else { continue };
+                if let Some(uptime) = scheduled_reboot::check(&config) {
+                    self.reboot_uptime = Some(uptime);
+                    return Ok(BrokerFlow::Break);
+                }
+            }
+        }
         if let Poll::Ready(()) = self.timeout.poll_unpin(cx) {
             self.timed_out = true;
             return Ok(BrokerFlow::Break);
@@ -137,6 +190,12 @@ impl Plan {
             ui_idle_delay: ui_idle_delay.map(Box::pin),
             timeout: Fuse::terminated(),
             timed_out: false,
+            health_check_requested: false,
+            maintenance_mode: None,
+            maintenance_requested: false,
+            scheduled_reboot: false,
+            reboot_check: None,
+            reboot_uptime: None,
             #[cfg(feature = "internal-data-acquisition")]
             data_acquisition,
         }
@@ -158,11 +217,25 @@ impl Plan {
             timeout: timeout
                 .map_or_else(Fuse::terminated, |timeout| Box::pin(time::sleep(timeout)).fuse()),
             timed_out: false,
+            health_check_requested: false,
+            maintenance_mode: None,
+            maintenance_requested: false,
+            scheduled_reboot: false,
+            reboot_check: None,
+            reboot_uptime: None,
             #[cfg(feature = "internal-data-acquisition")]
             data_acquisition,
         }
     }
 
+    /// Ends the plan with [`Value::Reboot`] when the scheduled reboot is due.
+    /// See [`scheduled_reboot`].
+    #[must_use]
+    pub fn with_scheduled_reboot(mut self) -> Self {
+        self.scheduled_reboot = true;
+        self
+    }
+
     /// Runs the idle plan. Returns `Ok(Some(qr_scan_result))` or `Ok(None)`
     /// depending on whether the user QR-code scanning feature was enabled or not.
     pub async fn run(&mut self, orb: &mut Orb) -> Result<Value> {
@@ -172,20 +245,41 @@ impl Plan {
         #[cfg(feature = "internal-data-acquisition")]
         if self.data_acquisition {
             orb.enable_image_uploader()?;
+            let config = orb.config.lock().await;
+            let retention = crate::agents::image_notary::retention::Policies::from_config(&config);
+            let compaction = crate::agents::image_notary::compaction::Policy::from_config(&config);
+            drop(config);
+            let compactor = crate::agents::image_notary::compaction::Compactor::new(
+                compaction,
+                orb.cpu_monitor.clone(),
+            );
             orb.image_uploader
                 .enabled()
                 .unwrap()
                 .send(port::Input::new(crate::agents::image_uploader::Input::StartUpload {
                     image_upload_delay: *IMAGE_UPLOAD_DELAY,
+                    retention,
+                    compactor,
                 }))
                 .await?;
         }
 
         orb.main_mcu.rx_mut().clear()?;
+        self.maintenance_mode = Some(orb.maintenance_mode_stream());
+        if self.scheduled_reboot {
+            self.reboot_check =
+                Some(IntervalStream::new(time::interval(SCHEDULED_REBOOT_CHECK_INTERVAL)));
+        }
         if let Some(qr_scan) = &mut self.user_qr_scan {
             qr_scan.run_pre(orb).await?;
         }
         orb.run(self).await?;
+        while self.health_check_requested {
+            self.health_check_requested = false;
+            let report = health_check::Plan::default().run(orb).await?;
+            tracing::info!("Remote health check report: {}", serde_json::to_string(&report)?);
+            orb.run(self).await?;
+        }
         let user_qr_code = self.user_qr_scan.as_mut().map(qr_scan::Plan::take_qr_code);
         if let Some(qr_scan) = &mut self.user_qr_scan {
             qr_scan.run_post(orb).await?;
@@ -204,7 +298,11 @@ impl Plan {
         if self.ui_idle_delay.is_some() && self.user_qr_scan.is_none() {
             orb.ui.idle();
         }
-        if self.timed_out {
+        if self.maintenance_requested {
+            Ok(Value::Maintenance)
+        } else if let Some(uptime) = self.reboot_uptime {
+            Ok(Value::Reboot(uptime))
+        } else if self.timed_out {
             Ok(Value::TimedOut)
         } else if let Some(user_qr_code) = user_qr_code {
             Ok(Value::UserQrCode(user_qr_code))
@@ -0,0 +1,72 @@
+//! Maintenance mode.
+//!
+//! Keeps the broker running while the hardware is parked, until the
+//! maintenance mode is cleared through DBus. The mirror can be repositioned
+//! with the livestream remote control in the meantime.
+
+#[cfg(feature = "livestream")]
+use crate::agents::livestream;
+use crate::{
+    brokers::{Orb, OrbPlan},
+    ext::broadcast::ReceiverExt as _,
+};
+#[cfg(feature = "livestream")]
+use agentwire::port;
+use agentwire::BrokerFlow;
+use eyre::Result;
+use futures::prelude::*;
+use std::task::{Context, Poll};
+use tokio_stream::wrappers::WatchStream;
+
+/// Maintenance plan.
+#[derive(Default)]
+pub struct Plan {
+    maintenance_mode: Option<WatchStream<bool>>,
+}
+
+impl OrbPlan for Plan {
+    #[cfg(feature = "livestream")]
+    fn handle_livestream(
+        &mut self,
+        orb: &mut Orb,
+        output: port::Output<livestream::Agent>,
+    ) -> Result<BrokerFlow> {
+        if let livestream::Command::MoveMirror(direction) = output.value {
+            orb.move_mirror(direction)?;
+        }
+        Ok(BrokerFlow::Continue)
+    }
+
+    fn poll_extra(&mut self, orb: &mut Orb, cx: &mut Context<'_>) -> Result<BrokerFlow> {
+        while let Poll::Ready(output) = orb.main_mcu.rx_mut().next_broadcast().poll_unpin(cx) {
+            output?;
+        }
+        if let Some(maintenance_mode) = &mut self.maintenance_mode {
+            while let Poll::Ready(enabled) = maintenance_mode.poll_next_unpin(cx) {
+                match enabled {
+                    Some(true) => {}
+                    Some(false) => return Ok(BrokerFlow::Break),
+                    None => {
+                        tracing::error!(
+                            "Maintenance mode can't be cleared, the DBus interface is gone"
+                        );
+                        self.maintenance_mode = None;
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(BrokerFlow::Continue)
+    }
+}
+
+impl Plan {
+    /// Runs the maintenance plan until the maintenance mode is cleared.
+    pub async fn run(&mut self, orb: &mut Orb) -> Result<()> {
+        orb.main_mcu.rx_mut().clear()?;
+        self.maintenance_mode = Some(orb.maintenance_mode_stream());
+        orb.run(self).await?;
+        orb.disable_mirror();
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+//! MCU firmware update.
+//!
+//! The Orb OS ships the main and security MCU firmware releases listed in
+//! `versions.json`. When an MCU runs an older release, or doesn't report a
+//! valid primary image, the image is streamed into the secondary slot block by
+//! block over [ISO-TP](crate::mcu::isotp), and the MCU checks the image CRC.
+//! The secondary-slot version reported for the firmware versions request must
+//! match the expected release before the slot is activated, which reboots the
+//! MCU into the new firmware. The plan then waits for the MCU to come back and
+//! checks that the primary slot runs the expected release.
+//!
+//! A primary image newer than the shipped release is never downgraded, so the
+//! update doesn't undo a firmware installed out of band nor trip the MCU
+//! anti-rollback check.
+
+use crate::{
+    brokers::Orb,
+    consts::{MCU_MAIN_FIRMWARE_PATH, MCU_SEC_FIRMWARE_PATH},
+    dd_incr, dd_timing,
+    ext::broadcast::ReceiverExt as _,
+    identification::{MCU_MAIN_RELEASE, MCU_SEC_RELEASE},
+    mcu::{
+        self,
+        can::CAN_SOCKET,
+        isotp::IsoTp,
+        main::{Version, Versions},
+        protocol::Protocol,
+        Interface, Main, Mcu, Sec,
+    },
+};
+use eyre::{bail, Result, WrapErr};
+use std::{marker::PhantomData, time::SystemTime};
+use tokio::{
+    fs, task,
+    time::{self, Duration, Instant},
+};
+
+/// Size of the firmware image blocks.
+const BLOCK_SIZE: usize = 1024;
+
+const VERSIONS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximal time for the MCU to reboot into the activated firmware.
+const ACTIVATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Microcontroller which firmware can be updated.
+pub trait Firmware: Interface + 'static {
+    /// Name used in the logs.
+    const NAME: &'static str;
+
+    /// Datadog tag of the microcontroller.
+    const TAG: &'static str;
+
+    /// Path to the firmware image shipped with the Orb OS.
+    const IMAGE_PATH: &'static str;
+
+    /// Returns the firmware release shipped with the Orb OS, if listed.
+    fn release() -> Option<&'static str>;
+
+    /// Returns the input requesting the firmware versions of both slots.
+    fn versions_request() -> Self::Input;
+
+    /// Returns the firmware versions carried by an output message, if any.
+    fn versions(output: Self::Output) -> Option<Versions>;
+
+    /// Returns the input carrying a firmware image block.
+    fn block(number: u32, count: u32, data: Vec<u8>) -> Self::Input;
+
+    /// Returns the input checking the CRC-32 of the secondary slot image.
+    fn image_check(crc32: u32) -> Self::Input;
+
+    /// Returns the input activating the secondary slot.
+    fn activate() -> Self::Input;
+}
+
+impl Firmware for Main {
+    const IMAGE_PATH: &'static str = MCU_MAIN_FIRMWARE_PATH;
+    const NAME: &'static str = "MCU";
+    const TAG: &'static str = "mcu:main";
+
+    fn release() -> Option<&'static str> {
+        MCU_MAIN_RELEASE.as_deref()
+    }
+
+    fn versions_request() -> mcu::main::Input {
+        mcu::main::Input::ValueGet(mcu::main::Property::FirmwareVersions)
+    }
+
+    fn versions(output: mcu::main::Output) -> Option<Versions> {
+        match output {
+            mcu::main::Output::Versions(versions) => Some(versions),
+            _ => None,
+        }
+    }
+
+    fn block(number: u32, count: u32, data: Vec<u8>) -> mcu::main::Input {
+        mcu::main::Input::FirmwareBlock { number, count, data }
+    }
+
+    fn image_check(crc32: u32) -> mcu::main::Input {
+        mcu::main::Input::FirmwareImageCheck(crc32)
+    }
+
+    fn activate() -> mcu::main::Input {
+        mcu::main::Input::FirmwareActivateSecondary
+    }
+}
+
+impl Firmware for Sec {
+    const IMAGE_PATH: &'static str = MCU_SEC_FIRMWARE_PATH;
+    const NAME: &'static str = "Security MCU";
+    const TAG: &'static str = "mcu:sec";
+
+    fn release() -> Option<&'static str> {
+        MCU_SEC_RELEASE.as_deref()
+    }
+
+    fn versions_request() -> mcu::sec::Input {
+        mcu::sec::Input::Version
+    }
+
+    fn versions(output: mcu::sec::Output) -> Option<Versions> {
+        match output {
+            mcu::sec::Output::Versions(versions) => Some(versions),
+            _ => None,
+        }
+    }
+
+    fn block(number: u32, count: u32, data: Vec<u8>) -> mcu::sec::Input {
+        mcu::sec::Input::FirmwareBlock { number, count, data }
+    }
+
+    fn image_check(crc32: u32) -> mcu::sec::Input {
+        mcu::sec::Input::FirmwareImageCheck(crc32)
+    }
+
+    fn activate() -> mcu::sec::Input {
+        mcu::sec::Input::FirmwareActivateSecondary
+    }
+}
+
+/// MCU firmware update plan.
+pub struct Plan<I: Firmware> {
+    image: Vec<u8>,
+    version: Version,
+    _interface: PhantomData<I>,
+}
+
+impl<I: Firmware> Plan<I> {
+    /// Creates a new plan installing the firmware `image` of `version`.
+    #[must_use]
+    pub fn new(image: Vec<u8>, version: Version) -> Self {
+        Self { image, version, _interface: PhantomData }
+    }
+
+    /// Runs the MCU firmware update plan.
+    pub async fn run(self, mcu: &mut dyn Mcu<I>) -> Result<()> {
+        let Self { image, version, .. } = self;
+        tracing::info!(
+            "{} update: transferring firmware {version} ({} bytes)",
+            I::NAME,
+            image.len()
+        );
+        let protocol = mcu
+            .protocol()
+            .cloned()
+            .unwrap_or_else(|| Protocol::new(I::PROTOCOL_VERSION, I::SUPPORTED_PROTOCOL_VERSIONS));
+        let t = SystemTime::now();
+        if let Err(err) = task::spawn_blocking(move || transfer::<I>(&image, protocol)).await? {
+            dd_incr!("main.count.global.mcu_update", I::TAG, "result:transfer_failed");
+            return Err(err);
+        }
+        dd_timing!("main.time.mcu_update.transfer", t, I::TAG);
+
+        let versions = request_versions(mcu).await?;
+        if !versions.secondary.same_release(&version) {
+            dd_incr!("main.count.global.mcu_update", I::TAG, "result:version_mismatch");
+            bail!("{} secondary slot reports {}, expected {version}", I::NAME, versions.secondary);
+        }
+        activate(mcu, &version).await
+    }
+}
+
+/// Updates the main and security MCU firmwares if they run an older release
+/// than the one shipped with the Orb OS.
+pub async fn heal(orb: &mut Orb) -> Result<()> {
+    let main = heal_mcu(&mut *orb.main_mcu).await;
+    let sec = async { heal_mcu(&mut mcu::sec::Jetson::spawn()?).await }.await;
+    if let Err(err) = sec {
+        tracing::error!("Security MCU firmware update failed: {err:?}");
+    }
+    main
+}
+
+async fn heal_mcu<I: Firmware>(mcu: &mut dyn Mcu<I>) -> Result<()> {
+    let Some(release) = I::release() else {
+        return Ok(());
+    };
+    let expected = release.parse::<Version>()?;
+    let versions = request_versions(mcu).await?;
+    if versions.primary.same_release(&expected) {
+        return Ok(());
+    }
+    if versions.primary.counter_value() > expected.counter_value() {
+        tracing::warn!(
+            "{} update: running {}, newer than the shipped {expected}, not downgrading",
+            I::NAME,
+            versions.primary
+        );
+        dd_incr!("main.count.global.mcu_update", I::TAG, "result:newer_primary");
+        return Ok(());
+    }
+    tracing::warn!(
+        "{} firmware mismatch: running {}, expected {expected}",
+        I::NAME,
+        versions.primary
+    );
+    if versions.secondary.same_release(&expected) {
+        tracing::info!("{} update: firmware {expected} already in the secondary slot", I::NAME);
+        return activate(mcu, &expected).await;
+    }
+    let image = fs::read(I::IMAGE_PATH)
+        .await
+        .wrap_err_with(|| format!("reading {} firmware image {}", I::NAME, I::IMAGE_PATH))?;
+    Plan::<I>::new(image, expected).run(mcu).await
+}
+
+/// Activates the secondary slot, waits for the MCU to reboot, and checks that
+/// the primary slot runs `version`.
+async fn activate<I: Firmware>(mcu: &mut dyn Mcu<I>, version: &Version) -> Result<()> {
+    tracing::info!("{} update: activating firmware {version}", I::NAME);
+    match mcu.send(I::activate()).await {
+        Ok(()) => {}
+        // The MCU reboots into the new firmware, possibly before the
+        // acknowledge gets through.
+        Err(err) if err.is::<mcu::AckTimeout>() => {
+            tracing::debug!("{} update: activation not acknowledged: {err}", I::NAME);
+        }
+        Err(err) => return Err(err),
+    }
+    let deadline = Instant::now() + ACTIVATION_TIMEOUT;
+    let versions = loop {
+        match request_versions(mcu).await {
+            Ok(versions) => break versions,
+            Err(err) if Instant::now() < deadline => {
+                tracing::debug!("{} update: waiting for the MCU to reboot: {err}", I::NAME);
+            }
+            Err(err) => {
+                dd_incr!("main.count.global.mcu_update", I::TAG, "result:reboot_timeout");
+                return Err(err.wrap_err(format!(
+                    "{} didn't come back after the firmware activation",
+                    I::NAME
+                )));
+            }
+        }
+    };
+    if !versions.primary.same_release(version) {
+        dd_incr!("main.count.global.mcu_update", I::TAG, "result:verify_failed");
+        bail!(
+            "{} primary slot reports {} after the activation, expected {version}",
+            I::NAME,
+            versions.primary
+        );
+    }
+    tracing::info!("{} update: running firmware {version}", I::NAME);
+    dd_incr!("main.count.global.mcu_update", I::TAG, "result:activated");
+    Ok(())
+}
+
+/// Streams the firmware image blocks to the MCU and checks the image CRC.
+fn transfer<I: Firmware>(image: &[u8], protocol: Protocol) -> Result<()> {
+    let mut isotp = IsoTp::<I>::open(CAN_SOCKET, protocol)?;
+    let count = u32::try_from(image.len().div_ceil(BLOCK_SIZE))?;
+    for (number, data) in (0..).zip(image.chunks(BLOCK_SIZE)) {
+        isotp
+            .send(&I::block(number, count, data.to_vec()))
+            .wrap_err_with(|| format!("sending firmware block {number}/{count}"))?;
+    }
+    isotp.send(&I::image_check(crc32fast::hash(image))).wrap_err("checking image CRC")
+}
+
+/// Requests the firmware versions of both slots from the MCU.
+async fn request_versions<I: Firmware>(mcu: &mut dyn Mcu<I>) -> Result<Versions> {
+    mcu.rx_mut().clear()?;
+    mcu.send(I::versions_request()).await?;
+    let versions = async {
+        loop {
+            if let Some(versions) = I::versions(mcu.rx_mut().next_broadcast().await?) {
+                break Ok::<_, eyre::Error>(versions);
+            }
+        }
+    };
+    time::timeout(VERSIONS_TIMEOUT, versions)
+        .await
+        .wrap_err_with(|| format!("timed out waiting for the {} firmware versions", I::NAME))?
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        let version = "v2.1.3".parse::<Version>().unwrap();
+        assert_eq!(version.to_string(), "v2.1.3/0");
+        assert!(version.same_release(&"2.1.3".parse().unwrap()));
+        assert!(!version.same_release(&"v2.1.4".parse().unwrap()));
+        assert!("v2.1".parse::<Version>().is_err());
+        assert!("main".parse::<Version>().is_err());
+    }
+}
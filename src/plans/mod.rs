@@ -10,24 +10,25 @@ use crate::{
         endpoints::RELAY_BACKEND_URL,
         operator_status::Coordinates,
         orb_os_status::{self, OrbOsVersionStatus},
+        presigned_url::UrlType,
         s3_region,
         signup_post::SignupReason,
-        upload_debug_report,
+        upload_debug_report, upload_image,
     },
-    brokers::Orb,
+    brokers::{snapshot::Snapshot, Orb},
     calibration::Calibration,
-    config::Config,
+    config::{audit, Config, Subsystem},
     consts::{
         BIOMETRIC_CAPTURE_TIMEOUT, CALIBRATION_FILE_PATH, DBUS_SIGNUP_OBJECT_PATH,
         DEFAULT_IR_LED_DURATION, DEFAULT_IR_LED_WAVELENGTH, DETECT_FACE_TIMEOUT,
         DETECT_FACE_TIMEOUT_SELF_SERVE, EXTRA_IR_LED_WAVELENGTHS, IR_CAMERA_FRAME_RATE,
-        QR_SCAN_INTERVAL, QR_SCAN_TIMEOUT,
+        QR_SCAN_INTERVAL, QR_SCAN_TIMEOUT, SELF_SERVE_USER_QUEUE_MAX_RESCANS,
     },
     dbus, dd_incr, dd_timing,
     debug_report::{self, DebugReport, SignupStatus},
     identification::{self, get_orb_token, ORB_ID},
-    mcu, network,
-    ui::{QrScanSchema, QrScanUnexpectedReason, SignupFailReason},
+    mcu, monitor, network,
+    ui::{self, QrScanSchema, QrScanUnexpectedReason, SignupFailReason},
     utils::log_iris_data,
 };
 use agentwire::port;
@@ -43,6 +44,7 @@ use qr_scan::{
 };
 use ring::digest::Digest;
 use std::{
+    mem::take,
     path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -50,21 +52,23 @@ use std::{
     },
     time::{Duration, Instant, SystemTime},
 };
-use tokio::time::{self, sleep};
+use tokio::{
+    task,
+    time::{self, sleep},
+};
 use walkdir::WalkDir;
 
 #[cfg(feature = "allow-plan-mods")]
 mod _imports_for_plan_mods {
     pub use crate::{
         agents::{
-            camera,
+            camera, image_notary,
             python::{self, init_sys_argv, ir_net, rgb_net},
         },
         backend::signup_post,
         plans::biometric_capture::SelfCustodyCandidate,
     };
     pub use pyo3::Python;
-    pub use std::fs::File;
     pub use tokio::{fs, task::spawn_blocking};
 }
 #[cfg(feature = "allow-plan-mods")]
@@ -80,8 +84,11 @@ pub mod health_check;
 pub mod idle;
 #[cfg(feature = "integration_testing")]
 pub mod integration_testing;
+pub mod maintenance;
+pub mod mcu_update;
 pub mod personal_custody_package;
 pub mod qr_scan;
+pub mod scheduled_reboot;
 pub mod warmup;
 pub mod wifi;
 
@@ -101,6 +108,13 @@ pub struct MasterPlan {
     s3_region: orb_wld_data_id::S3Region,
     s3_region_str: String,
     ui_idle_delay: Option<time::Sleep>,
+    operator_prevalidation: Option<qr_scan::prevalidation::Prevalidation>,
+    location_session: qr_scan::location_session::Tracker,
+    user_queue: qr_scan::user_queue::Queue,
+    /// Whether the users who lined up during the last signup should be
+    /// queued before the next one starts.
+    user_queue_fill_due: bool,
+    rate_limiter: qr_scan::rate_limit::Limiter,
     #[cfg(feature = "integration_testing")]
     ci_hacks: Option<integration_testing::CiHacks>,
     #[cfg(feature = "internal-data-acquisition")]
@@ -173,6 +187,7 @@ struct SignupResult {
     capture_start: SystemTime,
     signup_id: SignupId,
     debug_report: Option<debug_report::Builder>,
+    user_qr_key: Option<qr_scan::rate_limit::Key>,
 }
 
 impl Builder {
@@ -214,6 +229,11 @@ impl Builder {
             s3_region,
             s3_region_str,
             ui_idle_delay: None,
+            operator_prevalidation: None,
+            location_session: qr_scan::location_session::Tracker::default(),
+            user_queue: qr_scan::user_queue::Queue::default(),
+            user_queue_fill_due: false,
+            rate_limiter: qr_scan::rate_limit::Limiter::default(),
             #[cfg(feature = "integration_testing")]
             ci_hacks,
             #[cfg(feature = "internal-data-acquisition")]
@@ -332,8 +352,23 @@ impl MasterPlan {
             orb_relay_shutdown_wait_for_pending_messages,
             orb_relay_shutdown_wait_for_shutdown,
             operator_qr_expiration_time,
+            self_serve_user_queue_size,
+            self_serve_user_queue_expiration,
+            self_serve_queue_indicator,
+            signup_rate_limit_max_failures,
+            signup_rate_limit_window,
+            signup_rate_limit_cooldown,
             ..
         } = *orb.config.lock().await;
+        self.user_queue.set_limits(
+            if self_serve { self_serve_user_queue_size as usize } else { 0 },
+            self_serve_user_queue_expiration,
+        );
+        self.rate_limiter.set_limits(
+            signup_rate_limit_max_failures,
+            signup_rate_limit_window,
+            signup_rate_limit_cooldown,
+        );
         let dbus = orb
             .dbus_conn
             .as_ref()
@@ -341,8 +376,16 @@ impl MasterPlan {
             .transpose()?;
         self.reset_hardware(orb, Duration::from_secs(10)).await?;
         orb.enable_data_uploader()?;
-        let mut initial_qr_codes = QrCodes::None;
+        let mut initial_qr_codes =
+            self.recover_operator_session(orb, operator_qr_expiration_time).await;
+        orb.heartbeat.arm();
         loop {
+            orb.heartbeat.beat();
+            orb.apply_kill_switches().await?;
+            if orb.maintenance_mode() {
+                self.run_maintenance(orb).await?;
+                continue;
+            }
             self.scan_initial_qr_codes(
                 orb,
                 &mut initial_qr_codes,
@@ -350,6 +393,13 @@ impl MasterPlan {
                 operator_qr_expiration_time,
             )
             .await?;
+            if self_serve {
+                if let Some(operator_data) = initial_qr_codes.operator_data() {
+                    self.location_session.refresh(operator_data).await;
+                }
+            } else {
+                self.check_operator_prevalidation(orb);
+            }
             let Some(qr_codes) = self
                 .idle_wait_for_signup_request(
                     orb,
@@ -362,13 +412,36 @@ impl MasterPlan {
             else {
                 continue;
             };
+            if !self_serve {
+                // Outside of self-serve mode, a restored operator session is
+                // only used for the first signup.
+                initial_qr_codes = QrCodes::None;
+            }
 
             dd_incr!("main.count.signup.during.general.signup_started");
             self.signup_flag.store(true, Ordering::Relaxed);
-            let signup_result = Box::pin(self.do_signup(orb, qr_codes, dbus.as_ref())).await?;
-            let success = signup_result.success;
-            Box::pin(self.after_signup(orb, signup_result)).await?;
+            orb.start_signup_queue(if self_serve && self_serve_queue_indicator {
+                self.user_queue.len()
+            } else {
+                0
+            });
+            let signup = async {
+                let signup_result = Box::pin(self.do_signup(orb, qr_codes, dbus.as_ref())).await?;
+                let success = signup_result.success;
+                if let Some(user_qr_key) = signup_result.user_qr_key {
+                    self.rate_limiter.record(user_qr_key, success, Instant::now());
+                }
+                Box::pin(self.after_signup(orb, signup_result)).await
+            };
+            let result = signup.await;
+            // Turn the indicator off even if the signup failed.
+            orb.start_signup_queue(0);
+            result?;
+            self.user_queue_fill_due = self_serve;
             self.signup_flag.store(false, Ordering::Relaxed);
+            if !self_serve {
+                orb.set_operator_session(None).await;
+            }
 
             orb.disable_image_notary();
             if let Some(r) = orb.orb_relay.as_mut() {
@@ -391,6 +464,68 @@ impl MasterPlan {
         }
     }
 
+    /// Restores the operator session from the broker snapshot left by a
+    /// previous orb-core run, e.g. after a crash. The snapshot is not trusted,
+    /// so the operator QR code is validated by the backend again, and the
+    /// location data is taken from the backend response.
+    async fn recover_operator_session(
+        &self,
+        orb: &mut Orb,
+        operator_qr_expiration_time: Duration,
+    ) -> QrCodes {
+        if self.oneshot || self.has_biometric_input() {
+            return QrCodes::None;
+        }
+        let snapshot = Snapshot::load().await.unwrap_or_else(|err| {
+            tracing::error!("Failed to load the orb broker snapshot: {err:?}");
+            None
+        });
+        let Some(mut operator_data) = snapshot
+            .and_then(|snapshot| snapshot.operator_session)
+            .and_then(|session| session.into_operator_data(operator_qr_expiration_time))
+        else {
+            return QrCodes::None;
+        };
+        match backend::operator_status::request(&operator_data.qr_code).await {
+            Ok(backend::operator_status::Status {
+                valid: true,
+                location_data: Some(location_data),
+                ..
+            }) => {
+                operator_data.location_data = location_data;
+            }
+            Ok(_) => {
+                tracing::warn!("Restored operator session is no longer valid, dropping it");
+                dd_incr!("main.count.signup.during.general.operator_session_rejected");
+                return QrCodes::None;
+            }
+            Err(err) => {
+                tracing::warn!("Failed to validate the restored operator session: {err:?}");
+                dd_incr!("main.count.signup.during.general.operator_session_rejected");
+                return QrCodes::None;
+            }
+        }
+        tracing::info!("Restored the operator session from the orb broker snapshot");
+        dd_incr!("main.count.signup.during.general.operator_session_restored");
+        orb.set_operator_session(Some(&operator_data)).await;
+        QrCodes::Operator { operator_data }
+    }
+
+    /// Notifies the operator while idle if the background refresh found
+    /// their QR code revoked, and drops the pre-validation once it's stale.
+    fn check_operator_prevalidation(&mut self, orb: &mut Orb) {
+        let Some(prevalidation) = &self.operator_prevalidation else {
+            return;
+        };
+        if prevalidation.is_revoked() {
+            tracing::warn!("Pre-validated operator QR-code was revoked");
+            orb.ui.qr_scan_fail(QrScanSchema::Operator);
+        }
+        if prevalidation.is_stale() {
+            self.operator_prevalidation = None;
+        }
+    }
+
     async fn idle_wait_for_signup_request(
         &mut self,
         orb: &mut Orb,
@@ -428,28 +563,34 @@ impl MasterPlan {
             qr_codes.with_user_qr_code(user_qr_code, user_data, user_qr_code_string)
         } else {
             orb.set_phase("Idle waiting for button press").await;
-            self.idle_wait_for_button_press(orb, ui_idle_delay).await?;
+            if !self.idle_wait_for_button_press(orb, ui_idle_delay).await? {
+                return Ok(None);
+            }
             orb.ui.signup_start_operator();
             qr_codes.clone()
         };
         Ok(Some(qr_codes))
     }
 
+    /// Returns `false` if the wait was interrupted by the maintenance mode.
     async fn idle_wait_for_button_press(
         &mut self,
         orb: &mut Orb,
         ui_idle_delay: Option<time::Sleep>,
-    ) -> Result<()> {
+    ) -> Result<bool> {
         match idle::Plan::new(
             ui_idle_delay,
             #[cfg(feature = "internal-data-acquisition")]
             self.data_acquisition,
         )
+        .with_scheduled_reboot()
         .run(orb)
         .await?
         {
             idle::Value::UserQrCode(_) | idle::Value::TimedOut => unreachable!(),
-            idle::Value::ButtonPress => Ok(()),
+            idle::Value::ButtonPress => Ok(true),
+            idle::Value::Maintenance => Ok(false),
+            idle::Value::Reboot(uptime) => match scheduled_reboot::run(orb, uptime).await? {},
         }
     }
 
@@ -460,6 +601,26 @@ impl MasterPlan {
         operator_qr_expiration_time: Duration,
         mut ui_idle_delay: Option<time::Sleep>,
     ) -> Result<Option<(qr_scan::user::Data, backend::user_status::UserData, String)>> {
+        let fill_due = take(&mut self.user_queue_fill_due);
+        if operator_data.timestamp.elapsed() >= operator_qr_expiration_time {
+            // Queued users were validated against the expired operator session.
+            self.user_queue.clear();
+        } else if fill_due && self.user_queue.is_enabled() {
+            self.fill_user_queue(orb, operator_data).await?;
+        }
+        // The queued users were validated earlier, but the signup conditions
+        // must still hold when their signups start. Otherwise they stay queued
+        // until the next check or their expiration.
+        if !self.user_queue.is_empty() && !check_signup_conditions(orb).await? {
+            tracing::info!("Self-serve: signup conditions are not met, holding the user queue");
+        } else if let Some(entry) = self.user_queue.pop() {
+            tracing::info!(
+                "Self-serve: starting the next queued user, {} more in the queue",
+                self.user_queue.len()
+            );
+            dd_incr!("main.count.signup.during.general.queued_user_started");
+            return Ok(Some((entry.user_qr_code, entry.user_data, entry.user_qr_code_string)));
+        }
         loop {
             orb.reset_rgb_camera().await?;
             match idle::Plan::with_user_qr_scan(
@@ -468,6 +629,7 @@ impl MasterPlan {
                 #[cfg(feature = "internal-data-acquisition")]
                 self.data_acquisition,
             )
+            .with_scheduled_reboot()
             .run(orb)
             .await?
             {
@@ -475,18 +637,90 @@ impl MasterPlan {
                     if !check_signup_conditions(orb).await? {
                         continue;
                     }
-                    if let Some(Some((user_qr_code, user_data, user_qr_code_string))) =
+                    let Some(Some((user_qr_code, user_data, user_qr_code_string))) =
                         self.handle_user_qr_code(qr_scan_result, orb, operator_data, None).await?
-                    {
+                    else {
+                        continue;
+                    };
+                    if self.user_queue.is_empty() {
                         break Ok(Some((user_qr_code, user_data, user_qr_code_string)));
                     }
+                    // The queue was held, a walk-up user lines up behind the
+                    // queued ones.
+                    if !self.user_queue.contains(&user_qr_code.user_id)
+                        && !self.user_queue.push(qr_scan::user_queue::Entry::new(
+                            user_qr_code,
+                            user_data,
+                            user_qr_code_string,
+                        ))
+                    {
+                        tracing::info!("Self-serve: user queue is full, ignoring walk-up user");
+                        dd_incr!("main.count.signup.during.general.user_queue_full");
+                        continue;
+                    }
+                    if let Some(entry) = self.user_queue.pop() {
+                        dd_incr!("main.count.signup.during.general.queued_user_started");
+                        break Ok(Some((
+                            entry.user_qr_code,
+                            entry.user_data,
+                            entry.user_qr_code_string,
+                        )));
+                    }
                 }
-                idle::Value::TimedOut => break Ok(None),
+                idle::Value::TimedOut | idle::Value::Maintenance => break Ok(None),
+                idle::Value::Reboot(uptime) => match scheduled_reboot::run(orb, uptime).await? {},
                 idle::Value::ButtonPress => unreachable!(),
             }
         }
     }
 
+    /// Scans the user QR codes of the users who lined up during the last
+    /// signup, queueing them behind the already queued ones. Stops when the
+    /// queue is full, no QR code is scanned within the scan window, or after
+    /// [`SELF_SERVE_USER_QUEUE_MAX_RESCANS`] scans which didn't queue anybody.
+    async fn fill_user_queue(&mut self, orb: &mut Orb, operator_data: &OperatorData) -> Result<()> {
+        let Config { self_serve_user_queue_scan_window, .. } = *orb.config.lock().await;
+        let mut rescans = 0;
+        while !self.user_queue.is_full() && rescans < SELF_SERVE_USER_QUEUE_MAX_RESCANS {
+            orb.set_phase("User QR-code queue scanning").await;
+            orb.reset_rgb_camera().await?;
+            let qr_scan_result = match idle::Plan::with_user_qr_scan(
+                None,
+                Some(self_serve_user_queue_scan_window),
+                #[cfg(feature = "internal-data-acquisition")]
+                self.data_acquisition,
+            )
+            .run(orb)
+            .await?
+            {
+                idle::Value::UserQrCode(qr_scan_result) => qr_scan_result,
+                idle::Value::TimedOut | idle::Value::Maintenance => break,
+                idle::Value::ButtonPress | idle::Value::Reboot(_) => unreachable!(),
+            };
+            if let Ok((user_qr_code, _)) = &qr_scan_result {
+                if self.user_queue.contains(&user_qr_code.user_id) {
+                    tracing::info!("Self-serve: user QR-code is already queued, ignoring");
+                    rescans += 1;
+                    continue;
+                }
+            }
+            if let Some(Some((user_qr_code, user_data, user_qr_code_string))) =
+                self.handle_user_qr_code(qr_scan_result, orb, operator_data, None).await?
+            {
+                tracing::info!("Self-serve: queued user {}", user_qr_code.user_id);
+                dd_incr!("main.count.signup.during.general.user_queued");
+                self.user_queue.push(qr_scan::user_queue::Entry::new(
+                    user_qr_code,
+                    user_data,
+                    user_qr_code_string,
+                ));
+            } else {
+                rescans += 1;
+            }
+        }
+        Ok(())
+    }
+
     #[allow(clippy::too_many_lines)]
     async fn do_signup(
         &mut self,
@@ -510,6 +744,7 @@ impl MasterPlan {
         else {
             return Ok(result);
         };
+        result.user_qr_key = Some(qr_scan::rate_limit::key(&qr_codes.user_qr_code_string));
         let debug_report = result.debug_report.insert(DebugReport::builder(
             result.capture_start,
             &result.signup_id,
@@ -555,13 +790,17 @@ impl MasterPlan {
         let Some(capture) = capture else {
             return Ok(result);
         };
+        let location = capture.location.as_ref().filter(|location| location.is_reliable());
+        if let Some(location) = location {
+            self.location_session.observe_position(location.latitude, location.longitude);
+        }
         if self.skip_pipeline() || debug_report.signup_extension_config.is_some() {
             result.success = true;
             return Ok(result);
         }
         let pipeline = Box::pin(self.biometric_pipeline(orb, debug_report, &capture)).await?;
         let fraud_detected = !self.skip_fraud_checks()
-            && self.detect_fraud(orb, debug_report, pipeline.as_ref()).await?;
+            && self.detect_fraud(orb, debug_report, &capture, pipeline.as_ref()).await?;
         let signup_reason = if pipeline.is_none() {
             SignupReason::Failure
         } else if fraud_detected {
@@ -673,6 +912,12 @@ impl MasterPlan {
         } else if signup_reason == SignupReason::Fraud {
             tracing::info!("User enrollment failed due to fraud");
             debug_report.signup_fraud();
+        } else if matches!(
+            debug_report.enrollment_status,
+            Some(enroll_user::Status::InvalidIrisCode(_))
+        ) {
+            tracing::info!("User enrollment aborted due to an invalid iris code");
+            debug_report.signup_orb_failure();
         } else if success {
             debug_report.signup_successful();
             dd_incr!("main.count.signup.result.success.successful_signup");
@@ -694,12 +939,21 @@ impl MasterPlan {
         }
         let signup_id = SignupId::new(self.s3_region);
         tracing::info!("Starting signup with ID: {}", signup_id.to_string());
+        orb.start_event_log();
+        let broker_message_sample_rate = orb.config.lock().await.broker_message_sample_rate;
+        orb.start_message_trace(broker_message_sample_rate);
+        orb.start_frame_drops();
         #[cfg(feature = "livestream")]
         if let Some(livestream) = orb.livestream.enabled() {
             livestream.send(port::Input::new(livestream::Input::Clear)).await?;
         }
-        let signup_result =
-            SignupResult { success: false, capture_start, signup_id, debug_report: None };
+        let signup_result = SignupResult {
+            success: false,
+            capture_start,
+            signup_id,
+            debug_report: None,
+            user_qr_key: None,
+        };
         Ok(signup_result)
     }
 
@@ -728,6 +982,26 @@ impl MasterPlan {
         Ok(())
     }
 
+    /// Parks the hardware and blocks new signups until the maintenance mode
+    /// is cleared.
+    async fn run_maintenance(&mut self, orb: &mut Orb) -> Result<()> {
+        orb.set_phase("Maintenance").await;
+        dd_incr!("main.count.global.maintenance_mode");
+        orb.ui.maintenance(true);
+        self.user_queue.clear();
+        self.user_queue_fill_due = false;
+        orb.disable_rgb_net();
+        orb.disable_ir_net();
+        orb.park_hardware().await?;
+        tracing::info!("Hardware is parked, waiting for the maintenance mode to be cleared");
+        maintenance::Plan::default().run(orb).await?;
+        tracing::info!("Leaving maintenance mode");
+        orb.ui.maintenance(false);
+        self.reset_hardware(orb, Duration::from_secs(10)).await?;
+        orb.ui.idle();
+        Ok(())
+    }
+
     /// Resets the mirror calibration.
     pub async fn reset_mirror_calibration(&self, orb: &mut Orb) -> Result<()> {
         let calibration: Calibration = (&*orb.config.lock().await).into();
@@ -772,13 +1046,13 @@ impl MasterPlan {
                 else {
                     continue;
                 };
-                *qr_codes = QrCodes::Operator {
-                    operator_data: OperatorData {
-                        qr_code: operator_qr_code,
-                        location_data: operator_location_data,
-                        timestamp: Instant::now(),
-                    },
+                let operator_data = OperatorData {
+                    qr_code: operator_qr_code,
+                    location_data: operator_location_data,
+                    timestamp: Instant::now(),
                 };
+                orb.set_operator_session(Some(&operator_data)).await;
+                *qr_codes = QrCodes::Operator { operator_data };
                 break;
             }
         }
@@ -851,6 +1125,7 @@ impl MasterPlan {
                         location_data: operator_location_data,
                         timestamp: Instant::now(),
                     };
+                    orb.set_operator_session(Some(&operator_data)).await;
                     let Some((user_qr_code, user_data, user_qr_code_string)) =
                         self.scan_user_qr_code(orb, &operator_data).await?
                     else {
@@ -1005,6 +1280,13 @@ impl MasterPlan {
                 orb.ui.magic_qr_action_completed(result.is_ok());
                 Ok(None)
             }
+            qr_scan::operator::Data::MagicToggleNightMode => {
+                tracing::info!("Magic QR-code detected: Toggle Night Mode");
+                dd_incr!("main.count.signup.during.general.magic_qr.toggle_night_mode");
+                ui::night_mode::toggle(&*orb.config.lock().await, orb.ui.as_ref());
+                orb.ui.magic_qr_action_completed(true);
+                Ok(None)
+            }
         }
     }
 
@@ -1041,6 +1323,20 @@ impl MasterPlan {
                     #[cfg(not(feature = "integration_testing"))]
                     return Ok(None);
                 }
+                if let Some(remaining) = self
+                    .rate_limiter
+                    .check(&qr_scan::rate_limit::key(&user_qr_code_string), Instant::now())
+                {
+                    orb.ui.qr_scan_unexpected(
+                        QrScanSchema::User,
+                        QrScanUnexpectedReason::RateLimited,
+                    );
+                    dd_incr!("main.count.signup.result.failure.user_qr_code", "type:rate_limited");
+                    tracing::warn!("User QR-code is rate limited for {remaining:?}, retrying");
+                    // Give time to remove the QR code from the front of the camera
+                    sleep(Duration::from_millis(1500)).await;
+                    return Ok(None);
+                }
                 (user_qr_code, user_qr_code_string)
             }
             Err(qr_scan::ScanError::Invalid) => {
@@ -1095,7 +1391,7 @@ impl MasterPlan {
         orb.set_phase("Face detection").await;
         let t = Instant::now();
         let Config { self_serve, .. } = *orb.config.lock().await;
-        let face_detected = detect_face::Plan::new(if self_serve {
+        let outcome = detect_face::Plan::new(if self_serve {
             DETECT_FACE_TIMEOUT_SELF_SERVE
         } else {
             DETECT_FACE_TIMEOUT
@@ -1103,15 +1399,23 @@ impl MasterPlan {
         .run(orb)
         .await?;
         dd_timing!("main.time.signup.face_detection", t);
-        if face_detected {
-            tracing::info!("Face detected");
-            dd_incr!("main.count.signup.during.general.face_detected");
-        } else {
-            tracing::info!("Face not detected");
-            dd_incr!("main.count.signup.result.failure.face_detection", "type:timeout");
-            notify_failed_signup(orb, Some(SignupFailReason::FaceNotFound));
+        match outcome {
+            detect_face::Outcome::FaceDetected => {
+                tracing::info!("Face detected");
+                dd_incr!("main.count.signup.during.general.face_detected");
+            }
+            detect_face::Outcome::NobodyPresent => {
+                tracing::info!("Face not detected: nobody present");
+                dd_incr!("main.count.signup.result.failure.face_detection", "type:nobody_present");
+                notify_failed_signup(orb, Some(SignupFailReason::FaceNotFound));
+            }
+            detect_face::Outcome::TimedOut => {
+                tracing::info!("Face not detected");
+                dd_incr!("main.count.signup.result.failure.face_detection", "type:timeout");
+                notify_failed_signup(orb, Some(SignupFailReason::FaceNotFound));
+            }
         }
-        Ok(face_detected)
+        Ok(outcome == detect_face::Outcome::FaceDetected)
     }
 
     async fn after_biometric_capture(
@@ -1202,8 +1506,12 @@ impl MasterPlan {
                     biometric_capture::multi_wavelength::Plan::from(plan).run(orb).await?
                 }
                 qr_scan::user::SignupMode::Overcapture => {
-                    tracing::info!("Overcapture extension: activated");
-                    biometric_capture::overcapture::Plan::from(plan).run(orb).await?
+                    if orb.is_killed(Subsystem::Overcapture).await {
+                        plan.run(orb).await?
+                    } else {
+                        tracing::info!("Overcapture extension: activated");
+                        biometric_capture::overcapture::Plan::from(plan).run(orb).await?
+                    }
                 }
                 qr_scan::user::SignupMode::Basic => plan.run(orb).await?,
             }
@@ -1218,10 +1526,9 @@ impl MasterPlan {
                 #[cfg(feature = "internal-data-acquisition")]
                 if self.data_acquisition {
                     break 'identification_image_ids ({
-                        debug_report.biometric_capture_gps_location(
-                            capture.latitude.unwrap_or(0.0),
-                            capture.longitude.unwrap_or(0.0),
-                        );
+                        if let Some(location) = &capture.location {
+                            debug_report.biometric_capture_gps_location(location.clone());
+                        }
 
                         orb.save_identification_images(
                             capture.eye_left.clone(),
@@ -1274,6 +1581,7 @@ impl MasterPlan {
                 capture.eye_left.rgb_net_estimate.clone(),
                 capture.eye_right.rgb_net_estimate.clone(),
             );
+            debug_report.deep_debug_capture(&capture);
             debug_report.biometric_capture_succeeded();
             orb.ui.biometric_capture_success();
             Ok(Some(capture))
@@ -1350,6 +1658,7 @@ impl MasterPlan {
             pipeline.v2.eye_left.iris_normalized_image_resized.clone(),
             pipeline.v2.eye_right.iris_normalized_image_resized.clone(),
         );
+        debug_report.deep_debug_pipeline(&pipeline);
         debug_report.mega_agent_one_config(pipeline.mega_agent_one_config.clone());
         debug_report.mega_agent_two_config(pipeline.mega_agent_two_config.clone());
         debug_report.face_identifier_results(pipeline.face_identifier_fraud_checks.clone());
@@ -1393,6 +1702,7 @@ impl MasterPlan {
         &mut self,
         orb: &mut Orb,
         _debug_report: &mut debug_report::Builder,
+        capture: &biometric_capture::Capture,
         pipeline: Option<&biometric_pipeline::Pipeline>,
     ) -> Result<bool> {
         orb.set_phase("Fraud detection").await;
@@ -1400,6 +1710,13 @@ impl MasterPlan {
             return Ok(false);
         };
 
+        // Reported only: the RGB-Net tracker can lose the track between the
+        // eyes on a fast head movement.
+        if capture.is_rgb_net_identity_switched() {
+            tracing::warn!("RGB-Net person track changed between the eye captures");
+            dd_incr!("main.count.signup.rgb_net_identity_switch");
+        }
+
         // FOSS: WE HAVE DELETED ALL FRAUD CHECKS
 
         Ok(false)
@@ -1439,14 +1756,22 @@ impl MasterPlan {
     async fn upload_debug_report(
         &self,
         orb: &mut Orb,
-        debug_report: debug_report::Builder,
+        mut debug_report: debug_report::Builder,
     ) -> Result<()> {
         let signup_id = debug_report.signup_id.clone();
 
         tracing::info!("After-signup phase - Uploading signup data");
         let t1 = Instant::now();
-        let debug_report = debug_report.build(SystemTime::now(), orb.config.lock().await.clone());
-        match upload_debug_report::request(&signup_id, &debug_report).await {
+        debug_report.broker_events(orb.take_event_log());
+        debug_report.broker_message_trace(orb.take_message_trace());
+        debug_report.frame_drops(orb.take_frame_drops());
+        debug_report.time_sync(monitor::clock::time_sync().await);
+        let end_timestamp = SystemTime::now();
+        debug_report
+            .config_changes(audit::overlapping(debug_report.start_timestamp, end_timestamp).await);
+        let deep_debug_artifacts = debug_report.take_deep_debug_artifacts();
+        let mut debug_report = debug_report.build(end_timestamp, orb.config.lock().await.clone());
+        match upload_debug_report::request(&signup_id, &mut debug_report).await {
             Ok(()) => {
                 dd_incr!("main.count.data_acquisition.upload.success.signup_json");
             }
@@ -1457,6 +1782,10 @@ impl MasterPlan {
         }
         dd_timing!("main.time.signup.signup_json_upload", t1);
 
+        if !deep_debug_artifacts.is_empty() {
+            task::spawn(upload_deep_debug_artifacts(signup_id, deep_debug_artifacts));
+        }
+
         Ok(())
     }
 
@@ -1523,6 +1852,7 @@ impl MasterPlan {
                             notify_failed_signup(orb, Some(SignupFailReason::Verification));
                         }
                         enroll_user::Status::SignatureCalculationError
+                        | enroll_user::Status::InvalidIrisCode(_)
                         | enroll_user::Status::Error
                         | enroll_user::Status::ServerError => {
                             notify_failed_signup(orb, Some(SignupFailReason::Server));
@@ -1543,7 +1873,7 @@ impl MasterPlan {
     /// Checks if `qr_code` is a valid operator QR-code through the backend.
     #[allow(clippy::cast_possible_truncation)]
     async fn verify_operator_qr_code(
-        &self,
+        &mut self,
         orb: &mut Orb,
         qr_code: &qr_scan::user::Data,
         qr_capture_start: Instant,
@@ -1555,6 +1885,18 @@ impl MasterPlan {
                 stationary_location_coordinates: None,
             })));
         }
+        if let Some(location_data) = self
+            .operator_prevalidation
+            .as_ref()
+            .and_then(|prevalidation| prevalidation.lookup(qr_code))
+        {
+            orb.ui.qr_scan_success(QrScanSchema::Operator);
+            dd_incr!("main.count.global.distr_code_validated");
+            dd_incr!("main.count.global.operator_prevalidation.hit");
+            tracing::info!("Operator QR-code pre-validated: {qr_code:?}");
+            dd_timing!("main.time.signup.distr_qr_code_capture", qr_capture_start);
+            return Ok(Some((0, location_data)));
+        }
         let http_start = Instant::now();
         match backend::operator_status::request(qr_code).await {
             Ok(backend::operator_status::Status { valid: true, location_data, reason: _ }) => {
@@ -1564,6 +1906,16 @@ impl MasterPlan {
                 dd_incr!("main.count.global.distr_code_validated");
                 tracing::info!("Operator QR-code validated: {qr_code:?}");
                 dd_timing!("main.time.signup.distr_qr_code_capture", qr_capture_start);
+                let Config { self_serve, operator_qr_expiration_time, .. } =
+                    *orb.config.lock().await;
+                if !self_serve {
+                    self.operator_prevalidation =
+                        Some(qr_scan::prevalidation::Prevalidation::start(
+                            qr_code.clone(),
+                            location_data.clone(),
+                            operator_qr_expiration_time,
+                        ));
+                }
                 return Ok(Some((http_start.elapsed().as_millis() as u64, location_data)));
             }
             Ok(backend::operator_status::Status { valid: false, .. }) => {
@@ -1595,6 +1947,7 @@ impl MasterPlan {
             operator_data,
             user_qr_validation_use_full_operator_qr,
             user_qr_validation_use_only_operator_location,
+            self.location_session.current(),
         )
         .await
         {
@@ -1886,9 +2239,13 @@ impl QrCodes {
     }
 
     fn operator_timestamp(&self) -> Option<Instant> {
+        self.operator_data().map(|operator_data| operator_data.timestamp)
+    }
+
+    fn operator_data(&self) -> Option<&OperatorData> {
         match self {
             QrCodes::Operator { operator_data } | QrCodes::Both { operator_data, .. } => {
-                Some(operator_data.timestamp)
+                Some(operator_data)
             }
             QrCodes::None => None,
         }
@@ -1956,16 +2313,24 @@ async fn load_biometric_input(
     let rgb_self_custody_candidate_path =
         first_file_in_dir(&biometric_input.join("identification/rgb/self_custody_candidate"))?;
 
-    let ir_left =
-        spawn_blocking(|| camera::ir::Frame::read_png(File::open(ir_left_path)?)).await??;
-    let ir_right =
-        spawn_blocking(|| camera::ir::Frame::read_png(File::open(ir_right_path)?)).await??;
-    let rgb_left =
-        spawn_blocking(|| camera::rgb::Frame::read_png(File::open(rgb_left_path)?)).await??;
-    let rgb_right =
-        spawn_blocking(|| camera::rgb::Frame::read_png(File::open(rgb_right_path)?)).await??;
-    let rgb_self_custody_candidate = spawn_blocking(|| {
-        camera::rgb::Frame::read_png(File::open(rgb_self_custody_candidate_path)?)
+    let ir_left = spawn_blocking(move || {
+        read_saved_frame(&ir_left_path, |png| camera::ir::Frame::read_png(png))
+    })
+    .await??;
+    let ir_right = spawn_blocking(move || {
+        read_saved_frame(&ir_right_path, |png| camera::ir::Frame::read_png(png))
+    })
+    .await??;
+    let rgb_left = spawn_blocking(move || {
+        read_saved_frame(&rgb_left_path, |png| camera::rgb::Frame::read_png(png))
+    })
+    .await??;
+    let rgb_right = spawn_blocking(move || {
+        read_saved_frame(&rgb_right_path, |png| camera::rgb::Frame::read_png(png))
+    })
+    .await??;
+    let rgb_self_custody_candidate = spawn_blocking(move || {
+        read_saved_frame(&rgb_self_custody_candidate_path, |png| camera::rgb::Frame::read_png(png))
     })
     .await??;
 
@@ -2037,6 +2402,18 @@ async fn load_biometric_input(
     })
 }
 
+/// Reads an image saved by the image notary, logging its embedded metadata.
+#[cfg(feature = "allow-plan-mods")]
+fn read_saved_frame<T>(path: &Path, decode: impl FnOnce(&[u8]) -> Result<T>) -> Result<T> {
+    let png = std::fs::read(path)?;
+    match image_notary::metadata::read(&png) {
+        Ok(Some(metadata)) => tracing::info!("Replaying {}: {metadata:?}", path.display()),
+        Ok(None) => tracing::info!("Replaying {} without embedded metadata", path.display()),
+        Err(err) => tracing::warn!("Failed to read metadata of {}: {err:?}", path.display()),
+    }
+    decode(&png)
+}
+
 #[cfg_attr(not(feature = "allow-plan-mods"), expect(dead_code))]
 fn first_file_in_dir(dir: &Path) -> Result<PathBuf> {
     Ok(WalkDir::new(dir)
@@ -2047,6 +2424,31 @@ fn first_file_in_dir(dir: &Path) -> Result<PathBuf> {
         .into_path())
 }
 
+/// Uploads the deep debug artifacts of a sampled signup.
+async fn upload_deep_debug_artifacts(
+    signup_id: SignupId,
+    artifacts: Vec<debug_report::artifacts::Artifact>,
+) {
+    for artifact in artifacts {
+        let data = artifact.data.as_ref().clone();
+        let result = upload_image::request(
+            &signup_id,
+            &artifact.image_id,
+            UrlType::DebugArtifact,
+            data,
+            "debug_artifact",
+        )
+        .await;
+        match result {
+            Ok(()) => dd_incr!("main.count.data_acquisition.upload.success.debug_artifact"),
+            Err(err) => {
+                dd_incr!("main.count.data_acquisition.upload.error.debug_artifact");
+                tracing::error!("Uploading deep debug artifact {} failed: {err:?}", artifact.name);
+            }
+        }
+    }
+}
+
 async fn check_signup_conditions(orb: &mut Orb) -> Result<bool> {
     if let Some(report) = orb.net_monitor.last_report()? {
         // Drop the mutex lock fast.
@@ -1,5 +1,8 @@
 //! Building and uploading the personal custody package.
 
+pub mod manifest;
+
+use self::manifest::{Tier, Versions};
 use super::{biometric_capture, qr_scan};
 use crate::{
     agents::{
@@ -10,6 +13,7 @@ use crate::{
     backend::signup_post::SignupReason,
     debug_report::LocationData,
     identification::{ORB_ID, ORB_OS_VERSION, ORB_PUBLIC_KEY},
+    image::phash::PerceptualHash,
     secure_element::sign,
     utils::serialize_with_sorted_keys::SerializeWithSortedKeys,
 };
@@ -31,8 +35,8 @@ use std::{
 use tokio::task;
 
 const IRIS_MPC_VERSION: &str = env!("IRIS_MPC_VERSION");
-const VERSION_V2: &str = "2.3";
-const VERSION_V3: &str = "3.0";
+const VERSION_V2: &str = "2.4";
+const VERSION_V3: &str = "3.1";
 
 /// A plan for building and uploading the personal custody package.
 #[allow(missing_docs)]
@@ -139,6 +143,9 @@ struct InfoJson<'a> {
     left_ir_image_id: String,
     right_ir_image_id: String,
     thumbnail_image_id: String,
+    left_ir_image_phash: PerceptualHash,
+    right_ir_image_phash: PerceptualHash,
+    thumbnail_image_phash: PerceptualHash,
     software_version: &'static str,
     software_version_salt: String,
     orb_country: String,
@@ -292,9 +299,11 @@ impl Package<'_> {
             ..
         } = credentials;
         let mut hashes = BTreeMap::new();
-        let mut tier0 = tar::Builder::new(Vec::new());
-        let mut tier1 = tar::Builder::new(Vec::new());
-        let mut tier2 = tar::Builder::new(Vec::new());
+        let mut tier0 = Tier::new(0);
+        let mut tier1 = Tier::new(1);
+        let mut tier2 = Tier::new(2);
+        let version = if *pcp_version >= 3 { VERSION_V3 } else { VERSION_V2 };
+        let versions = self.component_versions();
 
         let mut iris_tar = self.make_iris_tar(&mut hashes)?;
         iris_tar = encrypt(iris_tar, backend_iris_public_key);
@@ -308,19 +317,23 @@ impl Package<'_> {
         let backend_keys_json = self.make_backend_keys_json(&mut hashes)?;
 
         if *pcp_version >= 3 {
-            tar_append(&mut tier1, ts, "iris.tar", iris_tar)?;
-            tar_append(&mut tier1, ts, "normalized_iris.tar", normalized_iris_tar)?;
-            tar_append(&mut tier1, ts, "face.tar", face_tar)?;
+            tier1.append(ts, "iris.tar", iris_tar)?;
+            tier1.append(ts, "normalized_iris.tar", normalized_iris_tar)?;
+            tier1.append(ts, "face.tar", face_tar)?;
             self.make_tier2(&mut tier2)?;
         } else {
-            tar_append(&mut tier0, ts, "iris.tar", iris_tar)?;
-            tar_append(&mut tier0, ts, "normalized_iris.tar", normalized_iris_tar)?;
-            tar_append(&mut tier0, ts, "face.tar", face_tar)?;
+            tier0.append(ts, "iris.tar", iris_tar)?;
+            tier0.append(ts, "normalized_iris.tar", normalized_iris_tar)?;
+            tier0.append(ts, "face.tar", face_tar)?;
         }
 
-        let tier1_compressed = compress(tier1.into_inner()?, ts, "tier1.tar.gz")?;
+        let tier1_manifest = tier1.manifest(version, &versions, &[])?;
+        hashes.insert(tier1.manifest_hash_key(), digest(&SHA256, &tier1_manifest));
+        let tier1_compressed = compress(tier1.finish(ts, &tier1_manifest)?, ts, "tier1.tar.gz")?;
         let tier1_encrypted = encrypt(tier1_compressed, self_custody_user_public_key);
-        let tier2_compressed = compress(tier2.into_inner()?, ts, "tier2.tar.gz")?;
+        let tier2_manifest = tier2.manifest(version, &versions, &[])?;
+        hashes.insert(tier2.manifest_hash_key(), digest(&SHA256, &tier2_manifest));
+        let tier2_compressed = compress(tier2.finish(ts, &tier2_manifest)?, ts, "tier2.tar.gz")?;
         let tier2_single_encrypted = backend_tier2_public_key
             .map(|backend_tier2_public_key| encrypt(tier2_compressed, &backend_tier2_public_key))
             .unwrap_or_default();
@@ -331,23 +344,30 @@ impl Package<'_> {
         let iris_codes_json = self.make_iris_codes_json(&mut hashes)?;
         let iris_code_shares_jsons = self.make_iris_code_shares_jsons(&mut hashes)?;
 
+        tier0.append(ts, "info.json", info_json)?;
+        tier0.append(ts, "face_embeddings.json", face_embeddings_json)?;
+        tier0.append(ts, "iris_codes.json", iris_codes_json)?;
+        for (i, share_json) in iris_code_shares_jsons.iter().enumerate() {
+            tier0.append(ts, &format!("iris_code_shares_{i}.json"), share_json)?;
+        }
+        let tier0_manifest = tier0.manifest(version, &versions, &[
+            "hashes.sign",
+            "hashes.json",
+            "backend_keys.json",
+        ])?;
+        hashes.insert(tier0.manifest_hash_key(), digest(&SHA256, &tier0_manifest));
+
         let hashes_json = self.make_hashes_json(
             hashes,
             digest(&SHA256, &tier1_encrypted),
             digest(&SHA256, &tier2_encrypted),
         )?;
 
-        tar_append(&mut tier0, ts, "info.json", info_json)?;
-        tar_append(&mut tier0, ts, "face_embeddings.json", face_embeddings_json)?;
-        tar_append(&mut tier0, ts, "iris_codes.json", iris_codes_json)?;
-        for (i, share_json) in iris_code_shares_jsons.iter().enumerate() {
-            tar_append(&mut tier0, ts, &format!("iris_code_shares_{i}.json"), share_json)?;
-        }
-        tar_append(&mut tier0, ts, "hashes.sign", sign(digest(&SHA256, &hashes_json))?)?;
-        tar_append(&mut tier0, ts, "hashes.json", hashes_json)?;
-        tar_append(&mut tier0, ts, "backend_keys.json", backend_keys_json)?;
+        tier0.append(ts, "hashes.sign", sign(digest(&SHA256, &hashes_json))?)?;
+        tier0.append(ts, "hashes.json", hashes_json)?;
+        tier0.append(ts, "backend_keys.json", backend_keys_json)?;
 
-        let tier0_compressed = compress(tier0.into_inner()?, ts, "tier0.tar.gz")?;
+        let tier0_compressed = compress(tier0.finish(ts, &tier0_manifest)?, ts, "tier0.tar.gz")?;
         let tier0_encrypted = encrypt(tier0_compressed, self_custody_user_public_key);
         Ok((tier0_encrypted, tier1_encrypted, tier2_encrypted))
     }
@@ -479,6 +499,12 @@ impl Package<'_> {
         let left_ir_image_id = self.identification_image_ids.left_ir.to_string();
         let right_ir_image_id = self.identification_image_ids.right_ir.to_string();
         let thumbnail_image_id = self.identification_image_ids.self_custody_candidate.to_string();
+        let left_ir_image_phash = PerceptualHash::from_frame(&self.capture.eye_left.ir_frame);
+        let right_ir_image_phash = PerceptualHash::from_frame(&self.capture.eye_right.ir_frame);
+        // The hash is computed on the same frame as the one identified by
+        // `thumbnail_image_id`, not on the derived face identifier thumbnail.
+        let thumbnail_image_phash =
+            PerceptualHash::from_frame(&self.capture.face_self_custody_candidate.rgb_frame);
         let software_version = &**ORB_OS_VERSION;
         let orb_country = self.location_data.operator_team_operating_country.clone();
         hashes.insert("signup_id".to_owned(), salted_sha256(signup_id, &signup_id_salt));
@@ -519,6 +545,9 @@ impl Package<'_> {
             left_ir_image_id,
             right_ir_image_id,
             thumbnail_image_id,
+            left_ir_image_phash,
+            right_ir_image_phash,
+            thumbnail_image_phash,
             software_version,
             software_version_salt,
             orb_country,
@@ -653,20 +682,33 @@ impl Package<'_> {
         Ok(json)
     }
 
-    fn make_tier2(&self, archive: &mut tar::Builder<Vec<u8>>) -> Result<()> {
+    fn make_tier2(&self, tier: &mut Tier) -> Result<()> {
         if let Some(face_ir) = &self.capture.face_ir {
             let mut face_ir_png = Vec::new();
             face_ir.write_png(&mut face_ir_png, FrameResolution::MAX)?;
-            tar_append(archive, self.ts, "face_ir.png", &face_ir_png)?;
+            tier.append(self.ts, "face_ir.png", &face_ir_png)?;
         }
         if let Some(thermal) = &self.capture.thermal {
             let mut thermal_png = Vec::new();
             thermal.write_png(&mut thermal_png, FrameResolution::MAX)?;
-            tar_append(archive, self.ts, "thermal.png", &thermal_png)?;
+            tier.append(self.ts, "thermal.png", &thermal_png)?;
         }
         Ok(())
     }
 
+    fn component_versions(&self) -> Versions {
+        Versions {
+            orb_core: Some(ORB_OS_VERSION.clone()),
+            iris: self.pipeline.iris_version.clone(),
+            iris_mpc: Some(IRIS_MPC_VERSION.to_owned()),
+            face_identifier: self
+                .pipeline
+                .face_identifier_embeddings
+                .first()
+                .map(|embedding| embedding.embedding_version.clone()),
+        }
+    }
+
     fn make_hashes_json(
         &self,
         hashes: BTreeMap<String, Digest>,
@@ -0,0 +1,333 @@
+//! Self-describing manifest of the personal custody package tiers.
+//!
+//! Each tier archive contains a [`MANIFEST_FILE_NAME`] entry enumerating the
+//! contained entries, their encodings and the versions of the producing
+//! components. The manifests are hashed into `hashes.json`, so they are
+//! covered by its signature. The entries are described by the central
+//! [`REGISTRY`], which is exported together with the debug report schema by the
+//! `debug-report-schema` tool, so the app and the backend can parse packages
+//! across orb-core versions.
+
+use super::tar_append;
+use eyre::{bail, Result, WrapErr};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Version of the manifest format.
+pub const MANIFEST_VERSION: &str = "1.0";
+
+/// Name of the manifest entry inside each tier archive.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Encoding of a package entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// UTF-8 JSON document.
+    Json,
+    /// PNG image.
+    Png,
+    /// DER-encoded ECDSA signature from the secure element.
+    Signature,
+    /// Tar archive encrypted with a backend public key.
+    EncryptedTar,
+}
+
+/// Component producing a package entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Producer {
+    /// The orb software itself.
+    OrbCore,
+    /// IRIS pipeline.
+    Iris,
+    /// Iris MPC secret sharing.
+    IrisMpc,
+    /// Face identifier.
+    FaceIdentifier,
+}
+
+/// Registered package entry.
+#[derive(Clone, Copy, Debug, Serialize, JsonSchema)]
+pub struct FieldSpec {
+    /// Entry path inside the tier archive. A `*` matches a decimal index.
+    pub name: &'static str,
+    /// Entry encoding.
+    pub encoding: Encoding,
+    /// Component producing the entry.
+    pub producer: Producer,
+    /// First package version whose manifest registers the entry.
+    pub since: &'static str,
+    /// Human-readable description.
+    pub description: &'static str,
+}
+
+/// Central registry of the package entries.
+pub static REGISTRY: &[FieldSpec] = &[
+    FieldSpec {
+        name: "info.json",
+        encoding: Encoding::Json,
+        producer: Producer::OrbCore,
+        since: "2.4",
+        description: "Signup information with salts for the hashed values",
+    },
+    FieldSpec {
+        name: "face_embeddings.json",
+        encoding: Encoding::Json,
+        producer: Producer::FaceIdentifier,
+        since: "2.4",
+        description: "Base64-encoded big-endian face embeddings",
+    },
+    FieldSpec {
+        name: "iris_codes.json",
+        encoding: Encoding::Json,
+        producer: Producer::Iris,
+        since: "2.4",
+        description: "Iris and mask codes of both eyes",
+    },
+    FieldSpec {
+        name: "iris_code_shares_*.json",
+        encoding: Encoding::Json,
+        producer: Producer::IrisMpc,
+        since: "2.4",
+        description: "Iris and mask code secret shares, one entry per share",
+    },
+    FieldSpec {
+        name: "hashes.json",
+        encoding: Encoding::Json,
+        producer: Producer::OrbCore,
+        since: "2.4",
+        description: "Hex-encoded SHA-256 hashes of the package contents",
+    },
+    FieldSpec {
+        name: "hashes.sign",
+        encoding: Encoding::Signature,
+        producer: Producer::OrbCore,
+        since: "2.4",
+        description: "Signature of the SHA-256 hash of hashes.json",
+    },
+    FieldSpec {
+        name: "backend_keys.json",
+        encoding: Encoding::Json,
+        producer: Producer::OrbCore,
+        since: "2.4",
+        description: "Backend public keys and encrypted private keys of the tar entries",
+    },
+    FieldSpec {
+        name: "iris.tar",
+        encoding: Encoding::EncryptedTar,
+        producer: Producer::OrbCore,
+        since: "2.4",
+        description: "IR images of both eyes",
+    },
+    FieldSpec {
+        name: "normalized_iris.tar",
+        encoding: Encoding::EncryptedTar,
+        producer: Producer::Iris,
+        since: "2.4",
+        description: "Normalized iris images and masks with their Hyrax commitments",
+    },
+    FieldSpec {
+        name: "face.tar",
+        encoding: Encoding::EncryptedTar,
+        producer: Producer::FaceIdentifier,
+        since: "2.4",
+        description: "Face thumbnail",
+    },
+    FieldSpec {
+        name: "face_ir.png",
+        encoding: Encoding::Png,
+        producer: Producer::OrbCore,
+        since: "3.1",
+        description: "IR face camera frame",
+    },
+    FieldSpec {
+        name: "thermal.png",
+        encoding: Encoding::Png,
+        producer: Producer::OrbCore,
+        since: "3.1",
+        description: "Thermal camera frame",
+    },
+];
+
+/// Tier manifest.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Manifest {
+    /// Version of the manifest format.
+    pub manifest_version: String,
+    /// Package version.
+    pub pcp_version: String,
+    /// Tier number.
+    pub tier: u8,
+    /// Entries contained in the tier, in the archive order.
+    pub fields: Vec<Field>,
+}
+
+/// Manifest entry.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Field {
+    /// Entry path inside the tier archive.
+    pub name: String,
+    /// Entry encoding.
+    pub encoding: Encoding,
+    /// Component producing the entry.
+    pub producer: Producer,
+    /// Version of the producing component, if known.
+    pub producer_version: Option<String>,
+}
+
+/// Versions of the producing components.
+#[derive(Clone, Debug, Default)]
+pub struct Versions {
+    /// Orb software version.
+    pub orb_core: Option<String>,
+    /// IRIS pipeline version.
+    pub iris: Option<String>,
+    /// Iris MPC version.
+    pub iris_mpc: Option<String>,
+    /// Face identifier embedding version.
+    pub face_identifier: Option<String>,
+}
+
+/// Tier archive builder, which records the appended entries for the manifest.
+pub struct Tier {
+    number: u8,
+    archive: tar::Builder<Vec<u8>>,
+    names: Vec<String>,
+}
+
+impl FieldSpec {
+    /// Returns `true` if the entry `name` matches the registered name.
+    #[must_use]
+    pub fn matches(&self, name: &str) -> bool {
+        match self.name.split_once('*') {
+            Some((prefix, suffix)) => {
+                name.strip_prefix(prefix).and_then(|name| name.strip_suffix(suffix)).is_some_and(
+                    |index| !index.is_empty() && index.bytes().all(|byte| byte.is_ascii_digit()),
+                )
+            }
+            None => self.name == name,
+        }
+    }
+}
+
+impl Manifest {
+    /// Creates a manifest for the tier entries `names`. Fails if an entry is
+    /// missing from the [`REGISTRY`].
+    pub fn new(tier: u8, pcp_version: &str, names: &[&str], versions: &Versions) -> Result<Self> {
+        let fields = names
+            .iter()
+            .map(|name| {
+                let Some(spec) = REGISTRY.iter().find(|spec| spec.matches(name)) else {
+                    bail!("PCP entry {name} is missing from the manifest registry");
+                };
+                Ok(Field {
+                    name: (*name).to_owned(),
+                    encoding: spec.encoding,
+                    producer: spec.producer,
+                    producer_version: versions.get(spec.producer).map(ToOwned::to_owned),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            manifest_version: MANIFEST_VERSION.to_owned(),
+            pcp_version: pcp_version.to_owned(),
+            tier,
+            fields,
+        })
+    }
+}
+
+impl Versions {
+    fn get(&self, producer: Producer) -> Option<&str> {
+        match producer {
+            Producer::OrbCore => self.orb_core.as_deref(),
+            Producer::Iris => self.iris.as_deref(),
+            Producer::IrisMpc => self.iris_mpc.as_deref(),
+            Producer::FaceIdentifier => self.face_identifier.as_deref(),
+        }
+    }
+}
+
+impl Tier {
+    /// Creates a new empty tier archive.
+    pub fn new(number: u8) -> Self {
+        Self { number, archive: tar::Builder::new(Vec::new()), names: Vec::new() }
+    }
+
+    /// Appends an entry to the tier archive.
+    pub fn append<T: AsRef<[u8]>>(&mut self, ts: Duration, path: &str, data: T) -> Result<()> {
+        tar_append(&mut self.archive, ts, path, data)?;
+        self.names.push(path.to_owned());
+        Ok(())
+    }
+
+    /// Returns the serialized manifest of the appended entries followed by the
+    /// `pending` ones, which are to be appended before
+    /// [`finish`](Self::finish).
+    pub fn manifest(
+        &self,
+        pcp_version: &str,
+        versions: &Versions,
+        pending: &[&str],
+    ) -> Result<Vec<u8>> {
+        let names = self.names.iter().map(String::as_str).chain(pending.iter().copied());
+        let manifest =
+            Manifest::new(self.number, pcp_version, &names.collect::<Vec<_>>(), versions)?;
+        serde_json::to_vec(&manifest).wrap_err("serializing PCP manifest as json")
+    }
+
+    /// Returns the key of the manifest hash in `hashes.json`.
+    #[must_use]
+    pub fn manifest_hash_key(&self) -> String {
+        format!("tier{}_{MANIFEST_FILE_NAME}", self.number)
+    }
+
+    /// Appends the `manifest` and returns the tier archive.
+    pub fn finish(mut self, ts: Duration, manifest: &[u8]) -> Result<Vec<u8>> {
+        tar_append(&mut self.archive, ts, MANIFEST_FILE_NAME, manifest)?;
+        Ok(self.archive.into_inner()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_spec_matches() {
+        let spec = REGISTRY.iter().find(|spec| spec.name == "iris_code_shares_*.json").unwrap();
+        assert!(spec.matches("iris_code_shares_0.json"));
+        assert!(spec.matches("iris_code_shares_12.json"));
+        assert!(!spec.matches("iris_code_shares_.json"));
+        assert!(!spec.matches("iris_code_shares_x.json"));
+        assert!(!spec.matches("iris_codes.json"));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let versions = Versions { iris: Some("1.2.3".to_owned()), ..Default::default() };
+        let manifest =
+            Manifest::new(0, "3.1", &["iris_codes.json", "hashes.sign"], &versions).unwrap();
+        assert_eq!(manifest.fields.len(), 2);
+        assert_eq!(manifest.fields[0].encoding, Encoding::Json);
+        assert_eq!(manifest.fields[0].producer_version.as_deref(), Some("1.2.3"));
+        assert_eq!(manifest.fields[1].producer, Producer::OrbCore);
+        assert!(manifest.fields[1].producer_version.is_none());
+        assert!(Manifest::new(0, "3.1", &["unknown.bin"], &versions).is_err());
+    }
+
+    #[test]
+    fn test_tier_manifest() {
+        let versions = Versions::default();
+        let mut tier = Tier::new(0);
+        tier.append(Duration::ZERO, "info.json", b"{}").unwrap();
+        let manifest = tier.manifest("3.1", &versions, &["hashes.sign", "hashes.json"]).unwrap();
+        tier.append(Duration::ZERO, "hashes.sign", b"").unwrap();
+        tier.append(Duration::ZERO, "hashes.json", b"{}").unwrap();
+        assert_eq!(manifest, tier.manifest("3.1", &versions, &[]).unwrap());
+        assert_eq!(tier.manifest_hash_key(), "tier0_manifest.json");
+        assert!(!tier.finish(Duration::ZERO, &manifest).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+//! Operator location sessions for stationary self-serve orbs.
+//!
+//! A self-serve orb stays at the operator's stationary location, so instead of
+//! sending the same coordinates with every user QR-code validation, the
+//! location is registered once with the backend and the signup requests refer
+//! to the returned session. The session is registered again when the operator
+//! changes, when it gets too old, or when the GPS fix collected during a
+//! biometric capture shows that the orb has moved.
+
+use crate::{
+    backend::operator_status::{self, Coordinates, LocationData, LocationSession, LocationSource},
+    consts::{
+        LOCATION_SESSION_MAX_AGE, LOCATION_SESSION_MOVEMENT_THRESHOLD,
+        LOCATION_SESSION_RETRY_INTERVAL,
+    },
+    dd_incr,
+    plans::OperatorData,
+};
+use std::time::Instant;
+
+/// Tracks the location session of the current operator.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    session: Option<Entry>,
+    last_failure: Option<Instant>,
+    gps_fix: Option<Coordinates>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    operator_id: String,
+    session: LocationSession,
+    registered_at: Instant,
+}
+
+impl Tracker {
+    /// Returns the current location session.
+    #[must_use]
+    pub fn current(&self) -> Option<&LocationSession> {
+        self.session.as_ref().map(|entry| &entry.session)
+    }
+
+    /// Makes sure there is a valid location session for the operator,
+    /// registering a new one with the backend if needed. Operators without a
+    /// stationary location don't get a session.
+    pub async fn refresh(&mut self, operator_data: &OperatorData) {
+        let Some((coordinates, source)) = self.coordinates(&operator_data.location_data) else {
+            self.session = None;
+            return;
+        };
+        let now = Instant::now();
+        if !self.needs_registration(&operator_data.qr_code.user_id, &coordinates, now) {
+            return;
+        }
+        if self.last_failure.is_some_and(|last_failure| {
+            now.saturating_duration_since(last_failure) < LOCATION_SESSION_RETRY_INTERVAL
+        }) {
+            self.session = None;
+            return;
+        }
+        match operator_status::register_location_session(
+            &operator_data.qr_code,
+            &operator_data.location_data,
+            coordinates,
+            source,
+        )
+        .await
+        {
+            Ok(session) => {
+                tracing::info!("Location session registered: {session:?}");
+                dd_incr!("main.count.global.location_session.registered");
+                self.session = Some(Entry {
+                    operator_id: operator_data.qr_code.user_id.clone(),
+                    session,
+                    registered_at: now,
+                });
+                self.last_failure = None;
+            }
+            Err(err) => {
+                tracing::error!("Location session registration failed: {err:?}");
+                dd_incr!("main.count.global.location_session.registration_failed");
+                self.session = None;
+                self.last_failure = Some(now);
+            }
+        }
+    }
+
+    /// Updates the orb position from a GPS fix. Drops the current session if
+    /// the orb has moved away from the registered coordinates.
+    pub fn observe_position(&mut self, latitude: f64, longitude: f64) {
+        let fix = Coordinates { latitude, longitude };
+        if let Some(entry) = &self.session {
+            let distance = entry.session.coordinates.distance(&fix);
+            if distance > LOCATION_SESSION_MOVEMENT_THRESHOLD {
+                tracing::warn!("Orb moved {distance:.0} m away from the location session");
+                dd_incr!("main.count.global.location_session.moved");
+                self.session = None;
+            }
+        }
+        self.gps_fix = Some(fix);
+    }
+
+    /// Returns the coordinates to register: the stationary location, unless
+    /// the GPS shows that the orb is somewhere else.
+    fn coordinates(&self, location_data: &LocationData) -> Option<(Coordinates, LocationSource)> {
+        let stationary = location_data.stationary_location_coordinates.clone()?;
+        match &self.gps_fix {
+            Some(fix) if fix.distance(&stationary) > LOCATION_SESSION_MOVEMENT_THRESHOLD => {
+                Some((fix.clone(), LocationSource::Gps))
+            }
+            _ => Some((stationary, LocationSource::Stationary)),
+        }
+    }
+
+    fn needs_registration(
+        &self,
+        operator_id: &str,
+        coordinates: &Coordinates,
+        now: Instant,
+    ) -> bool {
+        let Some(entry) = &self.session else {
+            return true;
+        };
+        entry.operator_id != operator_id
+            || now.saturating_duration_since(entry.registered_at) >= LOCATION_SESSION_MAX_AGE
+            || entry.session.coordinates.distance(coordinates) > LOCATION_SESSION_MOVEMENT_THRESHOLD
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATOR_ID: &str = "66ad4897-0ca7-4727-8365-ca808348e3cd";
+
+    fn tracker(now: Instant) -> Tracker {
+        Tracker {
+            session: Some(Entry {
+                operator_id: OPERATOR_ID.to_owned(),
+                session: LocationSession {
+                    id: "session".to_owned(),
+                    coordinates: Coordinates { latitude: 52.52, longitude: 13.405 },
+                    source: LocationSource::Stationary,
+                },
+                registered_at: now,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_needs_registration() {
+        let now = Instant::now();
+        let tracker = tracker(now);
+        let here = Coordinates { latitude: 52.5201, longitude: 13.4051 };
+        assert!(!tracker.needs_registration(OPERATOR_ID, &here, now));
+        assert!(tracker.needs_registration("other", &here, now));
+        assert!(tracker.needs_registration(OPERATOR_ID, &here, now + LOCATION_SESSION_MAX_AGE));
+        let elsewhere = Coordinates { latitude: 52.53, longitude: 13.405 };
+        assert!(tracker.needs_registration(OPERATOR_ID, &elsewhere, now));
+        assert!(Tracker::default().needs_registration(OPERATOR_ID, &here, now));
+    }
+
+    #[test]
+    fn test_observe_position() {
+        let now = Instant::now();
+        let mut tracker = tracker(now);
+        let location_data = LocationData {
+            stationary_location_coordinates: Some(Coordinates {
+                latitude: 52.52,
+                longitude: 13.405,
+            }),
+            ..Default::default()
+        };
+        tracker.observe_position(52.5201, 13.4051);
+        assert!(tracker.current().is_some());
+        assert_eq!(tracker.coordinates(&location_data).unwrap().1, LocationSource::Stationary);
+        tracker.observe_position(52.53, 13.405);
+        assert!(tracker.current().is_none());
+        let (coordinates, source) = tracker.coordinates(&location_data).unwrap();
+        assert_eq!(source, LocationSource::Gps);
+        assert!((coordinates.latitude - 52.53).abs() < f64::EPSILON);
+        assert!(tracker.coordinates(&LocationData::default()).is_none());
+    }
+}
@@ -1,7 +1,11 @@
 //! QR-code scanning.
 
+pub mod location_session;
 pub mod operator;
+pub mod prevalidation;
+pub mod rate_limit;
 pub mod user;
+pub mod user_queue;
 pub mod wifi;
 
 use crate::{
@@ -30,6 +30,8 @@ pub enum Data {
     MagicResetWifi,
     /// Action to reset mirror calibration.
     MagicResetMirror,
+    /// Action to toggle the night mode.
+    MagicToggleNightMode,
 }
 
 impl Schema for Data {
@@ -54,6 +56,7 @@ impl Schema for Data {
             {
                 "reset_wifi_credentials" => Some(Data::MagicResetWifi),
                 "reset_mirror_calibration" => Some(Data::MagicResetMirror),
+                "toggle_night_mode" => Some(Data::MagicToggleNightMode),
                 _ => None,
             };
         }
@@ -79,6 +82,10 @@ mod tests {
             let code = "magic_action:reset_mirror_calibration";
             assert!(matches!(Data::try_parse(code), Some(Data::MagicResetMirror)));
         }
+        {
+            let code = "magic_action:toggle_night_mode";
+            assert!(matches!(Data::try_parse(code), Some(Data::MagicToggleNightMode)));
+        }
         {
             let code = "magic_action:burn_and_destroy_everything";
             assert!(Data::try_parse(code).is_none());
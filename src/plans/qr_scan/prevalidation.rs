@@ -0,0 +1,197 @@
+//! Operator QR-code pre-validation.
+//!
+//! Outside of self-serve mode the operator scans their QR code for every
+//! signup. After the first successful validation, [`Prevalidation`] keeps the
+//! backend response and refreshes it in the background before it expires, so
+//! the following scans don't depend on the backend, and an expired or revoked
+//! operator QR code is detected while the orb is idle rather than mid-signup.
+
+use super::user;
+use crate::{
+    backend::{self, operator_status::LocationData},
+    consts::{OPERATOR_PREVALIDATION_REFRESH_MARGIN, OPERATOR_PREVALIDATION_RETRY_INTERVAL},
+    dd_incr,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    task::{self, JoinHandle},
+    time::sleep,
+};
+
+/// Pre-validated operator QR code with a background refresh task.
+pub struct Prevalidation {
+    state: Arc<Mutex<State>>,
+    refresh_task: JoinHandle<()>,
+}
+
+#[derive(Debug)]
+struct State {
+    qr_code: user::Data,
+    location_data: LocationData,
+    validated_at: Instant,
+    last_used: Instant,
+    expiration_time: Duration,
+    revoked: bool,
+}
+
+impl Prevalidation {
+    /// Starts tracking the operator QR code, which has just been validated by
+    /// the backend.
+    #[must_use]
+    pub fn start(
+        qr_code: user::Data,
+        location_data: LocationData,
+        expiration_time: Duration,
+    ) -> Self {
+        let now = Instant::now();
+        let state = Arc::new(Mutex::new(State {
+            qr_code,
+            location_data,
+            validated_at: now,
+            last_used: now,
+            expiration_time,
+            revoked: false,
+        }));
+        let refresh_task = task::spawn(refresh(Arc::clone(&state)));
+        Self { state, refresh_task }
+    }
+
+    /// Returns the location data for `qr_code` if it matches the pre-validated
+    /// operator QR code and the validation is still fresh.
+    #[must_use]
+    pub fn lookup(&self, qr_code: &user::Data) -> Option<LocationData> {
+        let mut state = self.state.lock().unwrap();
+        let location_data = state.lookup(qr_code, Instant::now())?;
+        state.last_used = Instant::now();
+        Some(location_data)
+    }
+
+    /// Returns `true` if the backend revoked the operator QR code during a
+    /// background refresh.
+    #[must_use]
+    pub fn is_revoked(&self) -> bool {
+        self.state.lock().unwrap().revoked
+    }
+
+    /// Returns `true` if the pre-validation can't be used anymore.
+    #[must_use]
+    pub fn is_stale(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.revoked || state.validated_at.elapsed() >= state.expiration_time
+    }
+}
+
+impl Drop for Prevalidation {
+    fn drop(&mut self) {
+        self.refresh_task.abort();
+    }
+}
+
+impl State {
+    fn lookup(&self, qr_code: &user::Data, now: Instant) -> Option<LocationData> {
+        (!self.revoked
+            && self.qr_code.user_id == qr_code.user_id
+            && now.saturating_duration_since(self.validated_at) < self.expiration_time)
+            .then(|| self.location_data.clone())
+    }
+
+    /// Returns the time of the next refresh, or `None` if the operator hasn't
+    /// used the orb for a whole expiration period.
+    fn next_refresh(&self, now: Instant) -> Option<Instant> {
+        if self.revoked || now.saturating_duration_since(self.last_used) >= self.expiration_time {
+            return None;
+        }
+        let refresh_after =
+            self.expiration_time.saturating_sub(OPERATOR_PREVALIDATION_REFRESH_MARGIN);
+        Some(self.validated_at + refresh_after)
+    }
+}
+
+async fn refresh(state: Arc<Mutex<State>>) {
+    loop {
+        let (next_refresh, qr_code) = {
+            let state = state.lock().unwrap();
+            let Some(next_refresh) = state.next_refresh(Instant::now()) else {
+                tracing::info!("Operator QR-code pre-validation: refresh stopped");
+                return;
+            };
+            (next_refresh, state.qr_code.clone())
+        };
+        sleep(next_refresh.saturating_duration_since(Instant::now())).await;
+        match backend::operator_status::request(&qr_code).await {
+            Ok(backend::operator_status::Status {
+                valid: true,
+                location_data: Some(location_data),
+                ..
+            }) => {
+                tracing::info!("Operator QR-code pre-validation: refreshed");
+                dd_incr!("main.count.global.operator_prevalidation.refreshed");
+                let mut state = state.lock().unwrap();
+                state.location_data = location_data;
+                state.validated_at = Instant::now();
+            }
+            Ok(backend::operator_status::Status { reason, .. }) => {
+                tracing::warn!(
+                    "Operator QR-code pre-validation: revoked, reason: {}",
+                    reason.as_deref().unwrap_or("<empty>")
+                );
+                dd_incr!("main.count.global.operator_prevalidation.revoked");
+                state.lock().unwrap().revoked = true;
+                return;
+            }
+            Err(err) => {
+                tracing::error!("Operator QR-code pre-validation: refresh failed: {err:?}");
+                dd_incr!("main.count.global.operator_prevalidation.refresh_failed");
+                sleep(OPERATOR_PREVALIDATION_RETRY_INTERVAL).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(now: Instant) -> State {
+        State {
+            qr_code: user::Data {
+                user_id: "66ad4897-0ca7-4727-8365-ca808348e3cd".to_owned(),
+                ..Default::default()
+            },
+            location_data: LocationData::default(),
+            validated_at: now,
+            last_used: now,
+            expiration_time: Duration::from_secs(3600),
+            revoked: false,
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let now = Instant::now();
+        let state = state(now);
+        let other = user::Data {
+            user_id: "00000000-0000-0000-0000-000000000000".to_owned(),
+            ..Default::default()
+        };
+        assert!(state.lookup(&state.qr_code, now + Duration::from_secs(60)).is_some());
+        assert!(state.lookup(&other, now).is_none());
+        assert!(state.lookup(&state.qr_code, now + Duration::from_secs(3600)).is_none());
+    }
+
+    #[test]
+    fn test_next_refresh() {
+        let now = Instant::now();
+        let mut state = state(now);
+        assert_eq!(
+            state.next_refresh(now),
+            Some(now + Duration::from_secs(3600) - OPERATOR_PREVALIDATION_REFRESH_MARGIN)
+        );
+        assert!(state.next_refresh(now + Duration::from_secs(3600)).is_none());
+        state.revoked = true;
+        assert!(state.next_refresh(now).is_none());
+    }
+}
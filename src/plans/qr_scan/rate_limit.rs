@@ -0,0 +1,148 @@
+//! Signup attempt rate limiting.
+//!
+//! A user whose signup fails tends to retry right away with the same QR code,
+//! and a broken app or a bad actor can keep retrying indefinitely. Each retry
+//! costs a backend validation and several minutes of the orb time. The
+//! [`Limiter`] tracks the failed signups per user QR code, and once a code
+//! fails `max_failures` times within the window, rejects it until the
+//! cool-down passes. The QR codes are tracked by their SHA-256 hashes and only
+//! in memory.
+
+use crate::dd_incr;
+use ring::digest::{digest, SHA256};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// Hash of a raw user QR code string.
+pub type Key = [u8; 32];
+
+/// Per user QR code signup attempt rate limiter.
+#[derive(Default)]
+pub struct Limiter {
+    attempts: HashMap<Key, Attempts>,
+    max_failures: u32,
+    window: Duration,
+    cooldown: Duration,
+}
+
+#[derive(Default)]
+struct Attempts {
+    failures: VecDeque<Instant>,
+    cooldown_until: Option<Instant>,
+}
+
+/// Returns the rate limiter key of a raw user QR code string.
+#[must_use]
+pub fn key(user_qr_code_string: &str) -> Key {
+    let mut key = Key::default();
+    key.copy_from_slice(digest(&SHA256, user_qr_code_string.as_bytes()).as_ref());
+    key
+}
+
+impl Limiter {
+    /// Updates the limits from the configuration. A zero `max_failures`
+    /// disables the rate limiting.
+    pub fn set_limits(&mut self, max_failures: u32, window: Duration, cooldown: Duration) {
+        self.max_failures = max_failures;
+        self.window = window;
+        self.cooldown = cooldown;
+        if max_failures == 0 {
+            self.attempts.clear();
+        }
+    }
+
+    /// Returns the remaining cool-down time if the user QR code is rate
+    /// limited.
+    #[must_use]
+    pub fn check(&self, key: &Key, now: Instant) -> Option<Duration> {
+        let cooldown_until = self.attempts.get(key)?.cooldown_until?;
+        (cooldown_until > now).then(|| cooldown_until - now)
+    }
+
+    /// Records the outcome of a signup with the user QR code. A successful
+    /// signup resets the failures.
+    pub fn record(&mut self, key: Key, success: bool, now: Instant) {
+        self.prune(now);
+        if success {
+            self.attempts.remove(&key);
+            return;
+        }
+        if self.max_failures == 0 {
+            return;
+        }
+        let attempts = self.attempts.entry(key).or_default();
+        attempts.failures.push_back(now);
+        if attempts.failures.len() >= self.max_failures as usize {
+            tracing::warn!(
+                "User QR-code failed {} signups within {:?}, cooling down for {:?}",
+                attempts.failures.len(),
+                self.window,
+                self.cooldown
+            );
+            dd_incr!("main.count.signup.rate_limit.cooldown");
+            attempts.failures.clear();
+            attempts.cooldown_until = Some(now + self.cooldown);
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        let window = self.window;
+        self.attempts.retain(|_, attempts| {
+            while attempts
+                .failures
+                .front()
+                .is_some_and(|&failure| now.saturating_duration_since(failure) > window)
+            {
+                attempts.failures.pop_front();
+            }
+            if attempts.cooldown_until.is_some_and(|until| until <= now) {
+                attempts.cooldown_until = None;
+            }
+            !attempts.failures.is_empty() || attempts.cooldown_until.is_some()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cooldown() {
+        let mut limiter = Limiter::default();
+        limiter.set_limits(3, Duration::from_secs(60), Duration::from_secs(300));
+        let (a, b) = (key("a"), key("b"));
+        let t = Instant::now();
+        for i in 0..3 {
+            assert_eq!(limiter.check(&a, t), None);
+            limiter.record(a, false, t + Duration::from_secs(i));
+        }
+        let t = t + Duration::from_secs(2);
+        assert_eq!(limiter.check(&a, t), Some(Duration::from_secs(300)));
+        assert_eq!(limiter.check(&b, t), None);
+        assert_eq!(limiter.check(&a, t + Duration::from_secs(300)), None);
+        limiter.record(b, false, t + Duration::from_secs(301));
+        assert!(limiter.attempts.get(&a).is_none());
+    }
+
+    #[test]
+    fn test_window() {
+        let mut limiter = Limiter::default();
+        limiter.set_limits(2, Duration::from_secs(60), Duration::from_secs(300));
+        let key = key("a");
+        let t = Instant::now();
+        limiter.record(key, false, t);
+        limiter.record(key, false, t + Duration::from_secs(61));
+        assert_eq!(limiter.check(&key, t + Duration::from_secs(61)), None);
+        limiter.record(key, true, t + Duration::from_secs(62));
+        limiter.record(key, false, t + Duration::from_secs(63));
+        assert_eq!(limiter.check(&key, t + Duration::from_secs(63)), None);
+
+        limiter.set_limits(0, Duration::from_secs(60), Duration::from_secs(300));
+        limiter.record(key, false, t + Duration::from_secs(64));
+        limiter.record(key, false, t + Duration::from_secs(65));
+        assert_eq!(limiter.check(&key, t + Duration::from_secs(65)), None);
+    }
+}
@@ -0,0 +1,189 @@
+//! Queue of pre-validated user QR codes.
+//!
+//! In self-serve mode the users can line up at a venue: after each signup, the
+//! orb scans for a short window and accepts up to `self_serve_user_queue_size`
+//! users who lined up meanwhile. Every queued QR code is validated by the
+//! backend right away, so the following users don't wait for the validation
+//! at the orb. [`MasterPlan`](crate::plans::MasterPlan) then runs the signups
+//! one after another in the queue order, announcing the orb to each user's app
+//! over the relay. A walk-up user lines up behind the queued users. A queued
+//! user who doesn't come to the orb in time is dropped once the entry
+//! expires.
+
+use super::user;
+use crate::{backend::user_status::UserData, dd_incr};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Pre-validated user QR code.
+pub struct Entry {
+    /// User QR code.
+    pub user_qr_code: user::Data,
+    /// User data returned by the backend validation.
+    pub user_data: UserData,
+    /// Raw user QR code string.
+    pub user_qr_code_string: String,
+    validated_at: Instant,
+}
+
+/// Queue of pre-validated user QR codes.
+#[derive(Default)]
+pub struct Queue {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    expiration_time: Duration,
+}
+
+impl Entry {
+    /// Creates a new entry for a user QR code which has just been validated.
+    #[must_use]
+    pub fn new(user_qr_code: user::Data, user_data: UserData, user_qr_code_string: String) -> Self {
+        Self { user_qr_code, user_data, user_qr_code_string, validated_at: Instant::now() }
+    }
+
+    fn is_expired(&self, now: Instant, expiration_time: Duration) -> bool {
+        now.saturating_duration_since(self.validated_at) >= expiration_time
+    }
+}
+
+impl Queue {
+    /// Updates the queue limits from the configuration. A zero `capacity`
+    /// disables the queue and drops the queued users.
+    pub fn set_limits(&mut self, capacity: usize, expiration_time: Duration) {
+        self.capacity = capacity;
+        self.expiration_time = expiration_time;
+        if self.entries.len() > capacity {
+            tracing::warn!(
+                "Self-serve user queue: dropping {} users",
+                self.entries.len() - capacity
+            );
+            self.entries.truncate(capacity);
+        }
+    }
+
+    /// Returns `true` if the queue accepts users.
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Returns `true` if no more users can be queued.
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.entries.len() >= self.capacity
+    }
+
+    /// Returns the number of queued users.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no queued users.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns `true` if the user is already queued.
+    #[must_use]
+    pub fn contains(&self, user_id: &str) -> bool {
+        self.entries.iter().any(|entry| entry.user_qr_code.user_id == user_id)
+    }
+
+    /// Queues a user. Returns `false` if the queue is full or the user is
+    /// already queued.
+    pub fn push(&mut self, entry: Entry) -> bool {
+        if self.is_full() || self.contains(&entry.user_qr_code.user_id) {
+            return false;
+        }
+        self.entries.push_back(entry);
+        true
+    }
+
+    /// Takes the next user, skipping the expired entries.
+    pub fn pop(&mut self) -> Option<Entry> {
+        self.pop_at(Instant::now())
+    }
+
+    /// Drops all queued users.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn pop_at(&mut self, now: Instant) -> Option<Entry> {
+        while let Some(entry) = self.entries.pop_front() {
+            if !entry.is_expired(now, self.expiration_time) {
+                return Some(entry);
+            }
+            tracing::info!(
+                "Self-serve user queue: dropping expired user {}",
+                entry.user_qr_code.user_id
+            );
+            dd_incr!("main.count.signup.during.general.queued_user_expired");
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(user_id: &str, validated_at: Instant) -> Entry {
+        let user_qr_code = user::Data {
+            user_id: user_id.to_owned(),
+            ..user::Data::try_parse(user::DUMMY_USER_QR_CODE).unwrap()
+        };
+        Entry {
+            user_qr_code,
+            user_data: UserData::default(),
+            user_qr_code_string: user::DUMMY_USER_QR_CODE.to_owned(),
+            validated_at,
+        }
+    }
+
+    #[test]
+    fn test_capacity_and_duplicates() {
+        let now = Instant::now();
+        let mut queue = Queue::default();
+        assert!(!queue.is_enabled());
+        assert!(!queue.push(entry("a", now)));
+        queue.set_limits(2, Duration::from_secs(60));
+        assert!(queue.push(entry("a", now)));
+        assert!(!queue.push(entry("a", now)));
+        assert!(queue.push(entry("b", now)));
+        assert!(queue.is_full());
+        assert!(!queue.push(entry("c", now)));
+        assert_eq!(queue.pop_at(now).unwrap().user_qr_code.user_id, "a");
+        assert_eq!(queue.pop_at(now).unwrap().user_qr_code.user_id, "b");
+        assert!(queue.pop_at(now).is_none());
+    }
+
+    #[test]
+    fn test_expiration() {
+        let now = Instant::now();
+        let mut queue = Queue::default();
+        queue.set_limits(3, Duration::from_secs(60));
+        queue.push(entry("a", now));
+        queue.push(entry("b", now + Duration::from_secs(30)));
+        let entry = queue.pop_at(now + Duration::from_secs(70)).unwrap();
+        assert_eq!(entry.user_qr_code.user_id, "b");
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_shrink() {
+        let now = Instant::now();
+        let mut queue = Queue::default();
+        queue.set_limits(3, Duration::from_secs(60));
+        queue.push(entry("a", now));
+        queue.push(entry("b", now));
+        queue.set_limits(1, Duration::from_secs(60));
+        assert_eq!(queue.len(), 1);
+        queue.set_limits(0, Duration::from_secs(60));
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+//! Scheduled nightly reboot.
+//!
+//! Long-running orbs accumulate leaks in vendor libraries. Within the daily
+//! reboot window from the backend config, the idle plan ends with
+//! [`idle::Value::Reboot`](super::idle::Value::Reboot) once the orb has been
+//! up for the minimum uptime, which prevents reboot loops within the window.
+//! The orb then parks the hardware, drains the upload queues, shuts the agents
+//! down, flushes the state to disk, and reboots through the supervisor. The
+//! main MCU is asked for a delayed power cycle beforehand, which it carries out
+//! only if the Jetson hasn't signaled the reboot by then, so a hung graceful
+//! reboot still ends with a power cycle.
+
+use crate::{
+    agents::data_uploader,
+    brokers::Orb,
+    config::Config,
+    consts::{SCHEDULED_REBOOT_DELAY_SECONDS, SCHEDULED_REBOOT_DRAIN_TIMEOUT},
+    dbus::SupervisorProxy,
+    dd_gauge, dd_incr, mcu,
+    ui::night_mode::Schedule,
+};
+use eyre::{Result, WrapErr};
+use nix::unistd::sync;
+use std::{convert::Infallible, fs, process, time::Duration};
+use time::OffsetDateTime;
+use tokio::{task, time::timeout};
+
+const UPTIME_PATH: &str = "/proc/uptime";
+
+/// Returns `true` if the orb with `uptime` should reboot at `now`.
+#[must_use]
+pub fn is_due(
+    window: Option<&Schedule>,
+    min_uptime: Duration,
+    uptime: Duration,
+    now: OffsetDateTime,
+) -> bool {
+    window.is_some_and(|window| window.contains(now)) && uptime >= min_uptime
+}
+
+/// Returns the system uptime if the scheduled reboot is due now.
+#[must_use]
+pub fn check(config: &Config) -> Option<Duration> {
+    let window = config.reboot_window.as_ref()?;
+    let uptime =
+        uptime().map_err(|err| tracing::error!("Scheduled reboot check failed: {err:?}")).ok()?;
+    is_due(Some(window), config.reboot_min_uptime, uptime, OffsetDateTime::now_utc())
+        .then_some(uptime)
+}
+
+/// Returns the time since the system boot.
+pub fn uptime() -> Result<Duration> {
+    let uptime = fs::read_to_string(UPTIME_PATH).wrap_err("reading system uptime")?;
+    parse_uptime(&uptime).wrap_err_with(|| format!("parsing system uptime: {uptime:?}"))
+}
+
+fn parse_uptime(uptime: &str) -> Result<Duration> {
+    let seconds = uptime.split_whitespace().next().unwrap_or_default().parse::<f64>()?;
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// Reboots the orb.
+pub async fn run(orb: &mut Orb, uptime: Duration) -> Result<Infallible> {
+    tracing::warn!("Scheduled reboot after {uptime:?} of uptime");
+    dd_incr!("main.count.global.scheduled_reboot");
+    dd_gauge!("main.gauge.system.uptime_before_reboot", uptime.as_secs().to_string());
+
+    orb.set_phase("Scheduled reboot").await;
+    orb.park_hardware().await?;
+    if let Some(data_uploader) = orb.data_uploader.enabled() {
+        tracing::info!("Draining the upload queues before the scheduled reboot");
+        match timeout(SCHEDULED_REBOOT_DRAIN_TIMEOUT, data_uploader::drain(data_uploader)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => tracing::error!("Draining the upload queues failed: {err:?}"),
+            Err(_) => tracing::warn!(
+                "Upload queues weren't drained in {SCHEDULED_REBOOT_DRAIN_TIMEOUT:?}, the \
+                 persisted items are resumed after the reboot"
+            ),
+        }
+    }
+    orb.disable_rgb_net();
+    orb.disable_ir_net();
+    orb.disable_qr_code();
+    orb.disable_image_notary();
+    orb.disable_data_uploader();
+
+    tracing::info!("Flushing state to disk before the scheduled reboot");
+    orb.config.lock().await.store().await?;
+    task::spawn_blocking(sync).await?;
+
+    // reboot comes from the MCU in last resort
+    orb.main_mcu.send(mcu::main::Input::Reboot(SCHEDULED_REBOOT_DELAY_SECONDS)).await?;
+    if let Err(err) = schedule_reboot().await {
+        tracing::error!(
+            "Graceful reboot failed, the MCU power-cycles the orb in \
+             {SCHEDULED_REBOOT_DELAY_SECONDS}s: {err:?}"
+        );
+    }
+    process::exit(0);
+}
+
+async fn schedule_reboot() -> Result<()> {
+    let connection = zbus::Connection::session()
+        .await
+        .wrap_err("failed establishing a `session` dbus connection")?;
+    let proxy =
+        SupervisorProxy::new(&connection).await.wrap_err("failed creating supervisor proxy")?;
+    tracing::info!(
+        "scheduling reboot in 0ms by calling org.worldcoin.OrbSupervisor1.Manager.ScheduleShutdown"
+    );
+    proxy.schedule_shutdown("reboot", 0).await.wrap_err("failed to schedule reboot")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::Time;
+
+    fn at(hour: u8) -> OffsetDateTime {
+        OffsetDateTime::UNIX_EPOCH.replace_time(Time::from_hms(hour, 0, 0).unwrap())
+    }
+
+    #[test]
+    fn test_is_due() {
+        let window = Schedule { start_minute: 3 * 60, end_minute: 5 * 60, utc_offset_minutes: 0 };
+        let min_uptime = Duration::from_secs(12 * 60 * 60);
+        let long = Duration::from_secs(3 * 24 * 60 * 60);
+        assert!(is_due(Some(&window), min_uptime, long, at(4)));
+        assert!(!is_due(Some(&window), min_uptime, long, at(12)));
+        assert!(!is_due(Some(&window), min_uptime, Duration::from_secs(60), at(4)));
+        assert!(!is_due(None, min_uptime, long, at(4)));
+    }
+
+    #[test]
+    fn test_parse_uptime() {
+        let uptime = parse_uptime("350735.50 234388.90\n").unwrap();
+        assert_eq!(uptime.as_millis(), 350_735_500);
+        assert!(parse_uptime("").is_err());
+    }
+}
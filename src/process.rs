@@ -1,4 +1,7 @@
 //! A set of helpers for spawning sub-processes in a sandboxes.
+
+pub mod watchdog;
+
 /// # Examples
 ///
 /// Simply spawn a command, same way as with `std::process::Command` except that the executable should be an absolute path.
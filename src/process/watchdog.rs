@@ -0,0 +1,298 @@
+//! Watchdog feeding.
+//!
+//! The watchdog is fed from the observer broker event loop, as long as the orb
+//! broker and the master plan loop beat their [`Heartbeat`] within
+//! [`HEARTBEAT_TIMEOUT`]. The heartbeat is enforced only after it's armed at
+//! the start of the master plan, because the boot steps before it, e.g. the
+//! token acquisition or an MCU firmware update, can legitimately block for
+//! longer. The observer runs in its own task, so the heartbeat
+//! keeps a deadlocked orb broker from being masked by a healthy observer. If
+//! either loop deadlocks or the tokio runtime stalls, the feeding stops and
+//! the supervisor restarts the orb: systemd restarts the service after
+//! `WatchdogSec`, and the Jetson hardware watchdog resets the board after
+//! [`HARDWARE_TIMEOUT`].
+//!
+//! The hardware watchdog is never disarmed, not even on exit, so a panic
+//! can't leave the board unguarded. The restarted service reopens the device
+//! and resumes the feeding.
+
+use eyre::{bail, Result, WrapErr as _};
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{self, Write as _},
+    os::{
+        linux::net::SocketAddrExt as _,
+        unix::{
+            io::AsRawFd as _,
+            net::{SocketAddr, UnixDatagram},
+        },
+    },
+    process,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Path to the Jetson hardware watchdog device.
+pub const HARDWARE_DEVICE: &str = "/dev/watchdog";
+
+/// Time without a feed after which the hardware watchdog resets the board.
+pub const HARDWARE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Interval of feeding the hardware watchdog.
+pub const HARDWARE_FEED_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time without a [`Heartbeat`] after which the watchdog isn't fed anymore.
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `_IOWR('W', 6, int)` from `linux/watchdog.h`.
+const WDIOC_SETTIMEOUT: libc::c_ulong = 0xC004_5706;
+
+/// Watchdog handle.
+#[derive(Debug, Default)]
+pub struct Watchdog {
+    backend: Backend,
+    heartbeat: Heartbeat,
+    starved: bool,
+}
+
+/// Liveness signal of the orb broker and the master plan loop.
+#[derive(Clone, Debug)]
+pub struct Heartbeat {
+    state: Arc<Mutex<HeartbeatState>>,
+}
+
+#[derive(Debug)]
+struct HeartbeatState {
+    last: Instant,
+    armed: bool,
+}
+
+#[derive(Debug, Default)]
+enum Backend {
+    #[default]
+    Disabled,
+    Systemd {
+        socket: UnixDatagram,
+        address: SocketAddr,
+        interval: Duration,
+    },
+    Hardware {
+        device: File,
+    },
+}
+
+impl Watchdog {
+    /// Returns a watchdog which is fed by `systemd` if the service has
+    /// `WatchdogSec` set, or by the Jetson hardware watchdog if `hardware` is
+    /// `true`. Falls back to a disabled watchdog on errors.
+    #[must_use]
+    pub fn open(hardware: bool) -> Self {
+        let backend = match Backend::systemd() {
+            Ok(Some(backend)) => backend,
+            Ok(None) if hardware => Backend::hardware().unwrap_or_else(|err| {
+                tracing::error!("Failed to arm the hardware watchdog: {err:?}");
+                Backend::Disabled
+            }),
+            Ok(None) => Backend::Disabled,
+            Err(err) => {
+                tracing::error!("Failed to connect to the systemd watchdog: {err:?}");
+                Backend::Disabled
+            }
+        };
+        tracing::info!("Watchdog: {}", backend.name());
+        Self { backend, ..Self::default() }
+    }
+
+    /// Returns the heartbeat required for the feeding.
+    #[must_use]
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    /// Returns the interval the watchdog must be fed at, or `None` if the
+    /// watchdog is disabled.
+    #[must_use]
+    pub fn interval(&self) -> Option<Duration> {
+        match &self.backend {
+            Backend::Disabled => None,
+            Backend::Systemd { interval, .. } => Some(*interval),
+            Backend::Hardware { .. } => Some(HARDWARE_FEED_INTERVAL),
+        }
+    }
+
+    /// Feeds the watchdog, unless the heartbeat is armed and older than
+    /// [`HEARTBEAT_TIMEOUT`].
+    pub fn feed(&mut self) -> Result<()> {
+        let starved = self.heartbeat.elapsed().is_some_and(|elapsed| elapsed > HEARTBEAT_TIMEOUT);
+        if starved && !self.starved {
+            tracing::error!(
+                "Watchdog: no heartbeat for {HEARTBEAT_TIMEOUT:?}, stopping the feeding"
+            );
+        }
+        self.starved = starved;
+        if starved {
+            return Ok(());
+        }
+        match &mut self.backend {
+            Backend::Disabled => {}
+            Backend::Systemd { socket, address, .. } => {
+                socket.send_to_addr(b"WATCHDOG=1", address)?;
+            }
+            Backend::Hardware { device } => {
+                device.write_all(b"\0")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Backend {
+    fn systemd() -> Result<Option<Self>> {
+        let (Ok(socket_path), Ok(usec)) = (env::var("NOTIFY_SOCKET"), env::var("WATCHDOG_USEC"))
+        else {
+            return Ok(None);
+        };
+        if env::var("WATCHDOG_PID").is_ok_and(|pid| pid != process::id().to_string()) {
+            return Ok(None);
+        }
+        let usec = usec.parse::<u64>().wrap_err("parsing WATCHDOG_USEC")?;
+        if usec == 0 {
+            return Ok(None);
+        }
+        let address = match socket_path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(&socket_path)?,
+        };
+        // Feed at half the timeout, as recommended by `sd_watchdog_enabled(3)`.
+        let interval = Duration::from_micros(usec) / 2;
+        Ok(Some(Self::Systemd { socket: UnixDatagram::unbound()?, address, interval }))
+    }
+
+    fn hardware() -> Result<Self> {
+        let device = OpenOptions::new()
+            .write(true)
+            .open(HARDWARE_DEVICE)
+            .wrap_err_with(|| format!("opening {HARDWARE_DEVICE}"))?;
+        let mut timeout = libc::c_int::try_from(HARDWARE_TIMEOUT.as_secs())?;
+        if unsafe { libc::ioctl(device.as_raw_fd(), WDIOC_SETTIMEOUT, &mut timeout) } < 0 {
+            bail!("setting the hardware watchdog timeout: {}", io::Error::last_os_error());
+        }
+        Ok(Self::Hardware { device })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::Systemd { .. } => "systemd",
+            Self::Hardware { .. } => "hardware",
+        }
+    }
+}
+
+impl Heartbeat {
+    /// Signals that the loop is alive.
+    pub fn beat(&self) {
+        self.state.lock().unwrap().last = Instant::now();
+    }
+
+    /// Starts enforcing the heartbeat. Until then the watchdog is fed
+    /// regardless of the beats.
+    pub fn arm(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.armed {
+            tracing::info!("Watchdog: heartbeat armed");
+        }
+        *state = HeartbeatState { last: Instant::now(), armed: true };
+    }
+
+    /// Returns the time since the last beat, or `None` if the heartbeat isn't
+    /// armed.
+    fn elapsed(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state.armed.then(|| state.last.elapsed())
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self { state: Arc::new(Mutex::new(HeartbeatState { last: Instant::now(), armed: false })) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_systemd_feed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let listener = UnixDatagram::bind(&path).unwrap();
+        let mut watchdog = Watchdog {
+            backend: Backend::Systemd {
+                socket: UnixDatagram::unbound().unwrap(),
+                address: SocketAddr::from_pathname(&path).unwrap(),
+                interval: Duration::from_secs(1),
+            },
+            ..Watchdog::default()
+        };
+        assert_eq!(watchdog.interval(), Some(Duration::from_secs(1)));
+        watchdog.feed().unwrap();
+        let mut buf = [0; 32];
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"WATCHDOG=1");
+
+        // A stale heartbeat stops the feeding until the next beat.
+        let heartbeat = watchdog.heartbeat();
+        heartbeat.arm();
+        heartbeat.state.lock().unwrap().last -= HEARTBEAT_TIMEOUT + Duration::from_secs(1);
+        listener.set_nonblocking(true).unwrap();
+        watchdog.feed().unwrap();
+        assert!(listener.recv(&mut buf).is_err());
+        heartbeat.beat();
+        watchdog.feed().unwrap();
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"WATCHDOG=1");
+    }
+
+    #[test]
+    fn test_boot_feed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let listener = UnixDatagram::bind(&path).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let mut watchdog = Watchdog {
+            backend: Backend::Systemd {
+                socket: UnixDatagram::unbound().unwrap(),
+                address: SocketAddr::from_pathname(&path).unwrap(),
+                interval: Duration::from_secs(1),
+            },
+            ..Watchdog::default()
+        };
+        let mut buf = [0; 32];
+
+        // A boot step blocking for longer than the timeout, e.g. waiting for
+        // the orb token or flashing the MCU, doesn't stop the feeding.
+        let heartbeat = watchdog.heartbeat();
+        heartbeat.state.lock().unwrap().last -= HEARTBEAT_TIMEOUT * 10;
+        watchdog.feed().unwrap();
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"WATCHDOG=1");
+
+        // Once the master plan arms the heartbeat, it's enforced.
+        heartbeat.arm();
+        watchdog.feed().unwrap();
+        assert!(listener.recv(&mut buf).is_ok());
+        heartbeat.state.lock().unwrap().last -= HEARTBEAT_TIMEOUT + Duration::from_secs(1);
+        watchdog.feed().unwrap();
+        assert!(listener.recv(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut watchdog = Watchdog::default();
+        assert_eq!(watchdog.interval(), None);
+        watchdog.feed().unwrap();
+    }
+}
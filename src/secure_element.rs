@@ -2,11 +2,40 @@
 
 #![cfg_attr(test, allow(unused_imports))]
 
-use crate::process::Command;
+use crate::{dd_incr, process::Command};
 use data_encoding::BASE64;
 use eyre::{bail, Result, WrapErr};
 use std::{io::prelude::*, process::Stdio};
 
+/// Secure Element monotonic counter. The counters can only be advanced, so
+/// they survive reflashing of the orb software and can't be reset by an
+/// attacker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Counter {
+    /// Epoch of the last accepted remote config.
+    ConfigEpoch,
+    /// Version of the last seen main MCU firmware.
+    McuFirmware,
+}
+
+impl Counter {
+    /// Returns the Secure Element counter slot.
+    #[must_use]
+    pub fn slot(self) -> u8 {
+        match self {
+            Self::ConfigEpoch => 0,
+            Self::McuFirmware => 1,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::ConfigEpoch => "config_epoch",
+            Self::McuFirmware => "mcu_firmware",
+        }
+    }
+}
+
 /// Signs this buffer with Secure Element and returns the output.
 #[cfg(not(test))]
 pub fn sign<T: AsRef<[u8]>>(data: T) -> Result<Vec<u8>> {
@@ -46,6 +75,87 @@ pub fn sign<T: AsRef<[u8]>>(data: T) -> Result<Vec<u8>> {
     inner(data.as_ref())
 }
 
+/// Enforces anti-rollback of a versioned policy. Returns `false` if `value` is
+/// lower than the counter, i.e. the policy is stale and must be rejected.
+/// Otherwise returns `true`, and advances the counter to `value` if `advance`
+/// is set. Only an authenticated `value` should advance the counter.
+pub fn check_rollback(counter: Counter, value: u64, advance: bool) -> Result<bool> {
+    let current = read_counter(counter)?;
+    if value < current {
+        tracing::error!(
+            "Rejecting {counter:?} rollback: {value} is lower than the Secure Element counter \
+             {current}"
+        );
+        dd_incr!(
+            "main.count.global.anti_rollback",
+            &format!("counter:{}", counter.name()),
+            "result:rejected"
+        );
+        return Ok(false);
+    }
+    if advance && value > current {
+        let advanced = advance_counter(counter, value)?;
+        tracing::info!("Advanced {counter:?} Secure Element counter: {current} -> {advanced}");
+        dd_incr!(
+            "main.count.global.anti_rollback",
+            &format!("counter:{}", counter.name()),
+            "result:advanced"
+        );
+    }
+    Ok(true)
+}
+
+/// Reads the current value of a Secure Element monotonic counter.
+#[cfg(not(test))]
+pub fn read_counter(counter: Counter) -> Result<u64> {
+    run_counter_command(&["read", &counter.slot().to_string()])
+}
+
+/// Advances a Secure Element monotonic counter to `value` and returns the
+/// resulting value. The Secure Element never lowers a counter.
+#[cfg(not(test))]
+pub fn advance_counter(counter: Counter, value: u64) -> Result<u64> {
+    run_counter_command(&["advance", &counter.slot().to_string(), &value.to_string()])
+}
+
+#[cfg(not(test))]
+fn run_counter_command(args: &[&str]) -> Result<u64> {
+    tracing::debug!("Running orb-se-counter {}", args.join(" "));
+    let output = Command::new("/usr/bin/orb-se-counter")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .wrap_err("running orb-se-counter")?;
+    if !output.status.success() {
+        for line in String::from_utf8_lossy(&output.stderr).lines() {
+            tracing::error!("orb-se-counter {}", line);
+        }
+        if let Some(code) = output.status.code() {
+            bail!("orb-se-counter exited with non-zero exit code: {code}");
+        } else {
+            bail!("orb-se-counter terminated by signal");
+        }
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().wrap_err("parsing orb-se-counter output")
+}
+
+#[cfg(test)]
+static COUNTERS: once_cell::sync::Lazy<std::sync::Mutex<std::collections::HashMap<Counter, u64>>> =
+    once_cell::sync::Lazy::new(Default::default);
+
+#[cfg(test)]
+pub fn read_counter(counter: Counter) -> Result<u64> {
+    Ok(COUNTERS.lock().unwrap().get(&counter).copied().unwrap_or_default())
+}
+
+#[cfg(test)]
+pub fn advance_counter(counter: Counter, value: u64) -> Result<u64> {
+    let mut counters = COUNTERS.lock().unwrap();
+    let current = counters.entry(counter).or_default();
+    *current = (*current).max(value);
+    Ok(*current)
+}
+
 #[cfg(test)]
 static SIGNING_KEY: once_cell::sync::Lazy<
     std::sync::Arc<std::sync::Mutex<openssl::ec::EcKey<openssl::pkey::Private>>>,
@@ -72,3 +182,19 @@ pub fn get_private_pem() -> Result<String> {
     let pkey = SIGNING_KEY.lock().unwrap();
     Ok(String::from_utf8(pkey.private_key_to_pem()?)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_rollback() {
+        assert!(check_rollback(Counter::McuFirmware, 5, true).unwrap());
+        assert_eq!(read_counter(Counter::McuFirmware).unwrap(), 5);
+        assert!(check_rollback(Counter::McuFirmware, 5, true).unwrap());
+        assert!(!check_rollback(Counter::McuFirmware, 4, true).unwrap());
+        assert!(check_rollback(Counter::McuFirmware, 7, false).unwrap());
+        assert_eq!(read_counter(Counter::McuFirmware).unwrap(), 5);
+        assert_eq!(advance_counter(Counter::McuFirmware, 3).unwrap(), 5);
+    }
+}
@@ -0,0 +1,204 @@
+//! Sound cues.
+//!
+//! The cues are played through GStreamer on the ALSA [`SOUND_CARD_NAME`] card.
+//! They are queued and played one at a time, so that consecutive cues don't
+//! overlap. A cue which waited in the queue for longer than
+//! [`SOUND_MAX_LATENCY`] is dropped, because it would be out of sync with the
+//! LED animation it accompanies.
+
+use crate::{
+    consts::{
+        DEFAULT_SOUND_VOLUME, MAX_SOUND_VOLUME, SOUNDS_DIR, SOUND_CARD_NAME, SOUND_MAX_LATENCY,
+    },
+    dd_incr,
+};
+use eyre::{eyre, Result};
+use gstreamer::{prelude::*, ClockTime, ElementFactory, MessageView};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use tokio::{sync::mpsc, task};
+
+/// Sound cue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Cue {
+    /// Boot-up completed.
+    BootComplete,
+    /// QR code captured.
+    QrCapture,
+    /// QR code accepted.
+    QrSuccess,
+    /// QR code rejected.
+    QrFail,
+    /// Biometric capture started.
+    SignupStart,
+    /// Biometric capture completed.
+    CaptureSuccess,
+    /// Signup succeeded.
+    SignupSuccess,
+    /// Signup failed.
+    SignupFail,
+    /// No internet connection.
+    NoInternet,
+    /// Orb is shutting down.
+    Shutdown,
+}
+
+/// Sound player for the Orb hardware.
+#[derive(Clone, Debug)]
+pub struct Jetson {
+    tx: mpsc::UnboundedSender<Command>,
+}
+
+#[derive(Debug)]
+enum Command {
+    Play(Cue, Instant),
+    Volume(u64),
+    Language(Option<String>),
+}
+
+impl Cue {
+    /// Returns the sound file name without the extension.
+    #[must_use]
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::BootComplete => "boot_complete",
+            Self::QrCapture => "qr_capture",
+            Self::QrSuccess => "qr_success",
+            Self::QrFail => "qr_fail",
+            Self::SignupStart => "signup_start",
+            Self::CaptureSuccess => "capture_success",
+            Self::SignupSuccess => "signup_success",
+            Self::SignupFail => "signup_fail",
+            Self::NoInternet => "no_internet",
+            Self::Shutdown => "shutdown",
+        }
+    }
+
+    /// Returns the path of the sound file for the `language`, falling back to
+    /// the language-neutral file.
+    #[must_use]
+    pub fn path(self, sounds_dir: &Path, language: Option<&str>) -> Option<PathBuf> {
+        let file_name = format!("{}.wav", self.file_name());
+        language
+            .map(|language| sounds_dir.join(language).join(&file_name))
+            .into_iter()
+            .chain([sounds_dir.join(&file_name)])
+            .find(|path| path.exists())
+    }
+}
+
+impl Jetson {
+    /// Spawns the sound player. A missing sound file is skipped, and logged as
+    /// an error unless `ignore_missing` is `true`.
+    #[must_use]
+    pub fn spawn(ignore_missing: bool) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        task::spawn(player_loop(rx, ignore_missing));
+        Self { tx }
+    }
+
+    /// Queues the `cue`.
+    pub fn play(&self, cue: Cue) {
+        self.send(Command::Play(cue, Instant::now()));
+    }
+
+    /// Sets the volume in the range `0..=MAX_SOUND_VOLUME`.
+    pub fn set_volume(&self, volume: u64) {
+        self.send(Command::Volume(volume));
+    }
+
+    /// Sets the language of the sound files.
+    pub fn set_language(&self, language: Option<String>) {
+        self.send(Command::Language(language));
+    }
+
+    fn send(&self, command: Command) {
+        if self.tx.send(command).is_err() {
+            tracing::error!("Sound player is not running");
+        }
+    }
+}
+
+async fn player_loop(mut rx: mpsc::UnboundedReceiver<Command>, ignore_missing: bool) {
+    let mut volume = DEFAULT_SOUND_VOLUME;
+    let mut language = None;
+    while let Some(command) = rx.recv().await {
+        match command {
+            Command::Volume(level) => volume = level.min(MAX_SOUND_VOLUME),
+            Command::Language(lang) => language = lang,
+            Command::Play(cue, queued) => {
+                if volume == 0 {
+                    continue;
+                }
+                if queued.elapsed() > SOUND_MAX_LATENCY {
+                    tracing::warn!("Sound cue {cue:?} dropped after waiting in the queue");
+                    dd_incr!("main.count.sound.dropped", &format!("cue:{}", cue.file_name()));
+                    continue;
+                }
+                let Some(path) = cue.path(Path::new(SOUNDS_DIR), language.as_deref()) else {
+                    if ignore_missing {
+                        tracing::warn!("Sound file for {cue:?} is missing");
+                    } else {
+                        tracing::error!("Sound file for {cue:?} is missing");
+                        dd_incr!("main.count.sound.missing", &format!("cue:{}", cue.file_name()));
+                    }
+                    continue;
+                };
+                #[allow(clippy::cast_precision_loss)]
+                let level = volume as f64 / MAX_SOUND_VOLUME as f64;
+                match task::spawn_blocking(move || play_file(&path, level)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => tracing::error!("Playing sound cue {cue:?} failed: {err:?}"),
+                    Err(err) => tracing::error!("Sound player task failed: {err:?}"),
+                }
+            }
+        }
+    }
+}
+
+/// Plays the sound file at `path` with the `volume` in the range `0.0..=1.0`
+/// and blocks until the end of the stream.
+fn play_file(path: &Path, volume: f64) -> Result<()> {
+    let uri = format!("file://{}", path.canonicalize()?.display());
+    let sink = ElementFactory::make("alsasink").property("device", SOUND_CARD_NAME).build()?;
+    let playbin = ElementFactory::make("playbin")
+        .property("uri", uri)
+        .property("volume", volume)
+        .property("audio-sink", sink)
+        .build()?;
+    let bus = playbin.bus().ok_or_else(|| eyre!("playbin has no bus"))?;
+    playbin.set_state(gstreamer::State::Playing)?;
+    let result = loop {
+        let Some(message) = bus.timed_pop(ClockTime::NONE) else { break Ok(()) };
+        match message.view() {
+            MessageView::Eos(_) => break Ok(()),
+            MessageView::Error(err) => break Err(eyre!("{}", err.error())),
+            _ => {}
+        }
+    };
+    playbin.set_state(gstreamer::State::Null)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_cue_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let neutral = dir.path().join("signup_success.wav");
+        fs::write(&neutral, []).unwrap();
+        fs::create_dir(dir.path().join("es")).unwrap();
+        let spanish = dir.path().join("es").join("signup_success.wav");
+        fs::write(&spanish, []).unwrap();
+        assert_eq!(Cue::SignupSuccess.path(dir.path(), Some("es")), Some(spanish));
+        assert_eq!(Cue::SignupSuccess.path(dir.path(), Some("de")), Some(neutral.clone()));
+        assert_eq!(Cue::SignupSuccess.path(dir.path(), None), Some(neutral));
+        assert_eq!(Cue::SignupFail.path(dir.path(), None), None);
+    }
+}
@@ -0,0 +1,323 @@
+//! Declarative LED animation scripts.
+//!
+//! The LED animations are played by the orb-ui service. To let the UX
+//! designers iterate on the ring and center animations without recompiling,
+//! [`watch`] loads the JSON [`Script`] at [`LED_ANIMATIONS_PATH`] on startup
+//! and every time the file changes. The validated animations are rendered into
+//! [`Rendered`] frames at [`LED_ANIMATIONS_FPS`] and sent to the orb-ui
+//! service, which replays the frames in place of the built-in animations with
+//! the same name. Removing the file restores the built-in animations.
+//!
+//! An animation is a list of [`Keyframe`]s over a normalized time axis. The
+//! color ramps between consecutive keyframes with the [`Easing`] of the
+//! earlier keyframe:
+//!
+//! ```json
+//! {
+//!   "animations": [
+//!     {
+//!       "name": "signup_progress",
+//!       "target": "ring",
+//!       "duration_ms": 1200,
+//!       "repeat": true,
+//!       "keyframes": [
+//!         { "at": 0.0, "color": [0, 0, 0], "easing": "ease_in_out" },
+//!         { "at": 0.5, "color": [255, 255, 255], "easing": "ease_in_out" },
+//!         { "at": 1.0, "color": [0, 0, 0] }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+
+use super::Engine;
+use crate::consts::{LED_ANIMATIONS_FPS, LED_ANIMATIONS_PATH, LED_ANIMATIONS_RELOAD_INTERVAL};
+use eyre::{ensure, Result, WrapErr as _};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+use tokio::{fs, time};
+
+/// Set of animations.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Script {
+    /// Animations to override.
+    pub animations: Vec<Animation>,
+}
+
+/// Single animation.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Animation {
+    /// Name of the orb-ui animation to override.
+    pub name: String,
+    /// LEDs to animate.
+    pub target: Target,
+    /// Duration of one cycle in milliseconds.
+    pub duration_ms: u64,
+    /// Whether to loop the animation until the next one.
+    #[serde(default)]
+    pub repeat: bool,
+    /// Keyframes in the ascending time order.
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// LEDs an animation is played on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    /// Outer ring LEDs.
+    Ring,
+    /// Center LEDs.
+    Center,
+}
+
+/// Animation keyframe.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Keyframe {
+    /// Time of the keyframe as a fraction of the animation duration.
+    pub at: f64,
+    /// Color at the keyframe.
+    pub color: Color,
+    /// Easing of the ramp to the next keyframe.
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+/// Animation rendered into frames.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Rendered {
+    /// Name of the orb-ui animation to override.
+    pub name: String,
+    /// LEDs to animate.
+    pub target: Target,
+    /// Whether to loop the frames until the next animation.
+    pub repeat: bool,
+    /// Interval between the frames in milliseconds.
+    pub frame_interval_ms: u64,
+    /// Colors of the consecutive frames.
+    pub frames: Vec<Color>,
+}
+
+/// RGB color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct Color(pub u8, pub u8, pub u8);
+
+/// Easing function of a color ramp.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    /// Constant speed.
+    #[default]
+    Linear,
+    /// Slow start.
+    EaseIn,
+    /// Slow end.
+    EaseOut,
+    /// Slow start and end.
+    EaseInOut,
+    /// Holds the color until the next keyframe.
+    Step,
+}
+
+impl Script {
+    /// Loads and validates the script at [`LED_ANIMATIONS_PATH`]. Returns
+    /// `None` if there is no script.
+    pub async fn load() -> Result<Option<Self>> {
+        Self::load_from(Path::new(LED_ANIMATIONS_PATH)).await
+    }
+
+    /// Loads and validates the script at `path`. Returns `None` if there is no
+    /// script.
+    pub async fn load_from(path: &Path) -> Result<Option<Self>> {
+        if !fs::try_exists(path).await? {
+            return Ok(None);
+        }
+        let script: Self = serde_json::from_str(&fs::read_to_string(path).await?)
+            .wrap_err_with(|| format!("parsing {}", path.display()))?;
+        for animation in &script.animations {
+            animation.validate().wrap_err_with(|| format!("animation {}", animation.name))?;
+        }
+        Ok(Some(script))
+    }
+
+    /// Renders the animations into frames.
+    #[must_use]
+    pub fn render(&self) -> Vec<Rendered> {
+        self.animations.iter().map(Animation::render).collect()
+    }
+}
+
+impl Animation {
+    /// Checks that the animation can be played.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.duration_ms > 0, "zero duration");
+        ensure!(!self.keyframes.is_empty(), "no keyframes");
+        ensure!(
+            self.keyframes.iter().all(|keyframe| (0.0..=1.0).contains(&keyframe.at)),
+            "keyframe time out of the 0.0..=1.0 range"
+        );
+        ensure!(
+            self.keyframes.windows(2).all(|pair| pair[0].at <= pair[1].at),
+            "keyframes are not in the ascending time order"
+        );
+        Ok(())
+    }
+
+    /// Returns the color at `elapsed` since the animation start. Must be
+    /// called on a validated animation.
+    #[must_use]
+    pub fn sample(&self, elapsed: Duration) -> Color {
+        let duration = Duration::from_millis(self.duration_ms);
+        let t = if self.repeat {
+            (elapsed.as_secs_f64() % duration.as_secs_f64()) / duration.as_secs_f64()
+        } else {
+            (elapsed.as_secs_f64() / duration.as_secs_f64()).min(1.0)
+        };
+        let next = self.keyframes.partition_point(|keyframe| keyframe.at <= t);
+        let (Some(from), Some(to)) = (next.checked_sub(1), self.keyframes.get(next)) else {
+            // Before the first or after the last keyframe.
+            return self.keyframes[next.saturating_sub(1)].color;
+        };
+        let from = &self.keyframes[from];
+        let span = to.at - from.at;
+        let progress = if span > 0.0 { from.easing.apply((t - from.at) / span) } else { 1.0 };
+        from.color.lerp(to.color, progress)
+    }
+
+    /// Renders one cycle of the animation at [`LED_ANIMATIONS_FPS`]. A
+    /// non-repeating animation ends with its final color. Must be called on a
+    /// validated animation.
+    #[must_use]
+    pub fn render(&self) -> Rendered {
+        let frame_interval = Duration::from_millis(1000 / u64::from(LED_ANIMATIONS_FPS));
+        let duration = Duration::from_millis(self.duration_ms);
+        let count = u32::try_from(duration.as_nanos().div_ceil(frame_interval.as_nanos()))
+            .unwrap_or(u32::MAX)
+            .max(1);
+        let mut frames = (0..count).map(|i| self.sample(frame_interval * i)).collect::<Vec<_>>();
+        if !self.repeat {
+            frames.push(self.sample(duration));
+        }
+        Rendered {
+            name: self.name.clone(),
+            target: self.target,
+            repeat: self.repeat,
+            frame_interval_ms: 1000 / u64::from(LED_ANIMATIONS_FPS),
+            frames,
+        }
+    }
+}
+
+impl Easing {
+    /// Maps the linear progress `t` in the `0.0..=1.0` range.
+    #[must_use]
+    pub fn apply(self, t: f64) -> f64 {
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => t * (2.0 - t),
+            Self::EaseInOut => t * t * (3.0 - 2.0 * t),
+            Self::Step => 0.0,
+        }
+    }
+}
+
+impl Color {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn lerp(self, other: Self, t: f64) -> Self {
+        let channel =
+            |a: u8, b: u8| (f64::from(a) + (f64::from(b) - f64::from(a)) * t).round() as u8;
+        Self(channel(self.0, other.0), channel(self.1, other.1), channel(self.2, other.2))
+    }
+}
+
+/// Sends the script at [`LED_ANIMATIONS_PATH`] to the orb-ui service on
+/// startup and every time the file changes.
+pub async fn watch(ui: Box<dyn Engine>) {
+    let mut interval = time::interval(LED_ANIMATIONS_RELOAD_INTERVAL);
+    let mut last_modified: Option<SystemTime> = None;
+    let mut loaded = false;
+    loop {
+        interval.tick().await;
+        let modified =
+            fs::metadata(LED_ANIMATIONS_PATH).await.and_then(|metadata| metadata.modified()).ok();
+        if modified == last_modified {
+            continue;
+        }
+        last_modified = modified;
+        match Script::load().await {
+            Ok(Some(script)) => {
+                tracing::info!("Loaded {} scripted LED animations", script.animations.len());
+                ui.animation_script(script.render());
+                loaded = true;
+            }
+            Ok(None) if loaded => {
+                tracing::info!("LED animation script removed, restoring the built-in animations");
+                ui.animation_script(Vec::new());
+                loaded = false;
+            }
+            Ok(None) => {}
+            Err(err) => tracing::error!("Failed to load the LED animation script: {err:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pulse() -> Animation {
+        serde_json::from_str(
+            r#"{
+                "name": "pulse",
+                "target": "ring",
+                "duration_ms": 1000,
+                "repeat": true,
+                "keyframes": [
+                    { "at": 0.0, "color": [0, 0, 0] },
+                    { "at": 0.5, "color": [200, 100, 0], "easing": "step" },
+                    { "at": 1.0, "color": [0, 0, 0] }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sample() {
+        let animation = pulse();
+        animation.validate().unwrap();
+        assert_eq!(animation.sample(Duration::ZERO), Color(0, 0, 0));
+        assert_eq!(animation.sample(Duration::from_millis(250)), Color(100, 50, 0));
+        assert_eq!(animation.sample(Duration::from_millis(500)), Color(200, 100, 0));
+        assert_eq!(animation.sample(Duration::from_millis(750)), Color(200, 100, 0));
+        assert_eq!(animation.sample(Duration::from_millis(1250)), Color(100, 50, 0));
+    }
+
+    #[test]
+    fn test_render() {
+        let mut animation = pulse();
+        let rendered = animation.render();
+        assert_eq!(rendered.frame_interval_ms, 33);
+        assert_eq!(rendered.frames.len(), 31);
+        assert_eq!(rendered.frames[0], Color(0, 0, 0));
+        assert_eq!(rendered.frames[15], Color(198, 99, 0));
+        assert_eq!(rendered.frames[16], Color(200, 100, 0));
+        animation.repeat = false;
+        let rendered = animation.render();
+        assert_eq!(rendered.frames.len(), 32);
+        assert_eq!(rendered.frames.last(), Some(&Color(0, 0, 0)));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut animation = pulse();
+        animation.keyframes.swap(0, 1);
+        assert!(animation.validate().is_err());
+        animation.keyframes.clear();
+        assert!(animation.validate().is_err());
+    }
+}
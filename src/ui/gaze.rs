@@ -0,0 +1,111 @@
+//! Gaze-direction guidance.
+//!
+//! When the iris is seen off the optical axis, the ring LEDs light a segment
+//! in the direction the user should look. The error comes from the iris
+//! center offset computed from the IR-Net landmarks, the same input the
+//! [`eye_pid_controller`](crate::agents::eye_pid_controller) uses to steer the
+//! mirror. The guidance is shown only above a deadzone, with hysteresis, so
+//! that small errors the mirror can correct on its own don't distract the
+//! user.
+
+use crate::consts::{
+    GAZE_GUIDANCE_ARC_DEGREES, GAZE_GUIDANCE_DEADZONE_MM, GAZE_GUIDANCE_HYSTERESIS,
+    GAZE_GUIDANCE_MAX_OFFSET_MM,
+};
+use serde::{Deserialize, Serialize};
+
+/// Ring segment lit by the gaze guidance animation.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct GazeGuidance {
+    /// Center of the segment in degrees, clockwise from the top of the ring as
+    /// seen by the user.
+    pub angle: f64,
+    /// Angular length of the segment in degrees.
+    pub arc: f64,
+    /// Strength of the cue in the `0.0..=1.0` range, growing with the error.
+    pub intensity: f64,
+}
+
+/// Gaze guidance state change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Update {
+    /// Show or move the guidance segment.
+    Show(GazeGuidance),
+    /// Turn the guidance off.
+    Clear,
+    /// Nothing to change.
+    Unchanged,
+}
+
+/// Gaze guidance state with hysteresis.
+#[derive(Debug, Default)]
+pub struct Guide {
+    active: bool,
+}
+
+impl Guide {
+    /// Updates the guidance with the iris center offset from the optical axis
+    /// in millimeters, in IR camera image coordinates. `None` means the iris
+    /// is not detected.
+    pub fn update(&mut self, offset: Option<(f64, f64)>) -> Update {
+        let threshold = if self.active {
+            GAZE_GUIDANCE_DEADZONE_MM * GAZE_GUIDANCE_HYSTERESIS
+        } else {
+            GAZE_GUIDANCE_DEADZONE_MM
+        };
+        match offset {
+            Some((x, y)) if x.hypot(y) > threshold => {
+                self.active = true;
+                Update::Show(guidance(x, y))
+            }
+            _ if self.active => {
+                self.active = false;
+                Update::Clear
+            }
+            _ => Update::Unchanged,
+        }
+    }
+}
+
+/// Computes the ring segment for the iris center offset `(x, y)` in
+/// millimeters, in IR camera image coordinates.
+///
+/// The user looks at the camera, so the image is mirrored horizontally
+/// relative to the user's view. The segment points back towards the optical
+/// axis: an iris seen below the center lights the top of the ring.
+#[must_use]
+pub fn guidance(x: f64, y: f64) -> GazeGuidance {
+    let angle = x.atan2(y).to_degrees().rem_euclid(360.0);
+    let intensity = ((x.hypot(y) - GAZE_GUIDANCE_DEADZONE_MM)
+        / (GAZE_GUIDANCE_MAX_OFFSET_MM - GAZE_GUIDANCE_DEADZONE_MM))
+        .clamp(0.0, 1.0);
+    GazeGuidance { angle, arc: GAZE_GUIDANCE_ARC_DEGREES, intensity }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guidance_angle() {
+        assert!(guidance(0.0, 5.0).angle.abs() < 1e-9);
+        assert!((guidance(5.0, 0.0).angle - 90.0).abs() < 1e-9);
+        assert!((guidance(0.0, -5.0).angle - 180.0).abs() < 1e-9);
+        assert!((guidance(-5.0, 0.0).angle - 270.0).abs() < 1e-9);
+        assert!((guidance(0.0, GAZE_GUIDANCE_MAX_OFFSET_MM * 2.0).intensity - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_guide_hysteresis() {
+        let mut guide = Guide::default();
+        let inside = GAZE_GUIDANCE_DEADZONE_MM * (1.0 + GAZE_GUIDANCE_HYSTERESIS) / 2.0;
+        assert_eq!(guide.update(Some((0.0, inside))), Update::Unchanged);
+        assert!(matches!(
+            guide.update(Some((0.0, GAZE_GUIDANCE_DEADZONE_MM * 1.5))),
+            Update::Show(_)
+        ));
+        assert!(matches!(guide.update(Some((0.0, inside))), Update::Show(_)));
+        assert_eq!(guide.update(None), Update::Clear);
+        assert_eq!(guide.update(None), Update::Unchanged);
+    }
+}
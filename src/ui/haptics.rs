@@ -0,0 +1,186 @@
+//! Silent-mode confirmation cues.
+//!
+//! In venues with the sound turned off the users miss the audio cues of the
+//! key signup events. While the sound volume is zero, the observer confirms
+//! these events with a brief fan pulse or a cone LED flash instead, as selected
+//! by [`Mode`]. A cue is deferred while the IR cameras are being triggered, so
+//! the fan vibration or the flash never overlaps an IR frame exposure, and is
+//! dropped if it can't start within [`HAPTIC_CUE_MAX_DELAY`].
+
+use crate::{consts::HAPTIC_CUE_MAX_DELAY, dd_incr};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Confirmation cue output used while the sound is disabled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// No confirmation cues.
+    #[default]
+    Off,
+    /// A brief fan speed pulse.
+    FanPulse,
+    /// A white cone LED flash.
+    ConeFlash,
+}
+
+/// Confirmed event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cue {
+    /// Biometric capture completed.
+    CaptureComplete,
+    /// Signup succeeded.
+    SignupSuccess,
+}
+
+/// Cue sender handle. The default handle drops the cues.
+#[derive(Clone, Debug, Default)]
+pub struct Sender(Option<mpsc::UnboundedSender<Cue>>);
+
+/// Cue playback request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Start the cue output.
+    Start(Mode),
+    /// Stop the cue output and restore the previous state.
+    Stop(Mode),
+}
+
+/// Cue playback state.
+#[derive(Debug, Default)]
+pub struct Player {
+    pending: Option<(Mode, Cue, Instant)>,
+    active: Option<(Mode, Instant)>,
+    ir_eye_triggering: bool,
+    ir_face_triggering: bool,
+}
+
+/// Creates a new cue channel.
+#[must_use]
+pub fn channel() -> (Sender, mpsc::UnboundedReceiver<Cue>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (Sender(Some(tx)), rx)
+}
+
+impl Cue {
+    /// Returns the duration of the cue output.
+    #[must_use]
+    pub fn duration(self) -> Duration {
+        match self {
+            Self::CaptureComplete => Duration::from_millis(300),
+            Self::SignupSuccess => Duration::from_millis(600),
+        }
+    }
+}
+
+impl Sender {
+    /// Requests a confirmation cue. Whether it's played depends on the
+    /// configuration.
+    pub fn send(&self, cue: Cue) {
+        if let Some(tx) = &self.0 {
+            tx.send(cue).ok();
+        }
+    }
+}
+
+impl Player {
+    /// Queues a cue, replacing the previously queued one.
+    pub fn queue(&mut self, mode: Mode, cue: Cue, now: Instant) {
+        if mode != Mode::Off {
+            self.pending = Some((mode, cue, now));
+        }
+    }
+
+    /// Updates the IR eye camera triggering state.
+    pub fn set_ir_eye_triggering(&mut self, triggering: bool) {
+        self.ir_eye_triggering = triggering;
+    }
+
+    /// Updates the IR face camera triggering state.
+    pub fn set_ir_face_triggering(&mut self, triggering: bool) {
+        self.ir_face_triggering = triggering;
+    }
+
+    /// Returns `true` while a fan pulse is being played.
+    #[must_use]
+    pub fn is_fan_pulse_active(&self) -> bool {
+        matches!(self.active, Some((Mode::FanPulse, _)))
+    }
+
+    /// Returns the time the active cue output ends.
+    #[must_use]
+    pub fn deadline(&self) -> Option<Instant> {
+        self.active.map(|(_, end)| end)
+    }
+
+    /// Advances the playback. An active cue is cut short as soon as an IR
+    /// camera starts triggering.
+    pub fn poll(&mut self, now: Instant) -> Option<Action> {
+        let exposing = self.ir_eye_triggering || self.ir_face_triggering;
+        if let Some((mode, end)) = self.active {
+            if exposing || now >= end {
+                self.active = None;
+                return Some(Action::Stop(mode));
+            }
+            return None;
+        }
+        if exposing {
+            return None;
+        }
+        let (mode, cue, queued) = self.pending.take()?;
+        if now.saturating_duration_since(queued) > HAPTIC_CUE_MAX_DELAY {
+            tracing::debug!("Dropping the {cue:?} confirmation cue");
+            dd_incr!("main.count.global.haptic_cue", "result:dropped");
+            return None;
+        }
+        dd_incr!("main.count.global.haptic_cue", "result:played");
+        self.active = Some((mode, now + cue.duration()));
+        Some(Action::Start(mode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playback() {
+        let mut player = Player::default();
+        let t = Instant::now();
+        player.queue(Mode::FanPulse, Cue::CaptureComplete, t);
+        assert_eq!(player.poll(t), Some(Action::Start(Mode::FanPulse)));
+        assert!(player.is_fan_pulse_active());
+        assert_eq!(player.poll(t + Duration::from_millis(100)), None);
+        let end = player.deadline().unwrap();
+        assert_eq!(player.poll(end), Some(Action::Stop(Mode::FanPulse)));
+        assert_eq!(player.poll(end), None);
+
+        player.queue(Mode::Off, Cue::SignupSuccess, end);
+        assert_eq!(player.poll(end), None);
+    }
+
+    #[test]
+    fn test_ir_exposure() {
+        let mut player = Player::default();
+        let t = Instant::now();
+        player.set_ir_eye_triggering(true);
+        player.queue(Mode::ConeFlash, Cue::SignupSuccess, t);
+        assert_eq!(player.poll(t), None);
+        player.set_ir_eye_triggering(false);
+        assert_eq!(
+            player.poll(t + Duration::from_millis(500)),
+            Some(Action::Start(Mode::ConeFlash))
+        );
+        player.set_ir_face_triggering(true);
+        assert_eq!(
+            player.poll(t + Duration::from_millis(600)),
+            Some(Action::Stop(Mode::ConeFlash))
+        );
+        player.set_ir_face_triggering(false);
+
+        player.queue(Mode::FanPulse, Cue::CaptureComplete, t);
+        assert_eq!(player.poll(t + HAPTIC_CUE_MAX_DELAY * 2), None);
+    }
+}
@@ -1,4 +1,20 @@
 //! UI events forwarding to the [orb-ui service](https://github.com/worldcoin/orb-software/orb-ui) through dbus.
+//!
+//! The [`Jetson`] engine can also play the [sound cues](crate::sound) of the
+//! events, queued right after the matching LED animation is started, and send
+//! their [silent-mode confirmation cues](haptics). With a sound player, orb-core
+//! owns the sound playback: the orb-ui player is muted at startup, and the
+//! sound settings and the sound test aren't forwarded to orb-ui anymore.
+//!
+//! If the orb-ui service restarts, the engine restores the state it has lost:
+//! the mute of its sound player and the [theme](theme).
+
+pub mod animation;
+pub mod gaze;
+pub mod haptics;
+pub mod night_mode;
+pub mod queue;
+pub mod theme;
 
 use eyre::Result;
 use futures::StreamExt;
@@ -9,14 +25,18 @@ use zbus::Connection;
 
 use tracing::warn;
 
-use crate::dbus::SignupStateProxy;
+use crate::{dbus::SignupStateProxy, sound};
+
+use self::{
+    gaze::GazeGuidance, night_mode::NightModeSettings, queue::QueueIndicator, theme::Theme,
+};
 
 macro_rules! event_enum {
     (
         $(#[$($enum_attrs:tt)*])*
         $vis:vis enum $name:ident {
             $(
-                $(#[doc = $doc:expr])?
+                $(#[doc = $doc:expr])*
                 #[event_enum(method = $method:ident)]
                 $(#[$($event_attrs:tt)*])*
                 $event:ident $({$($field:ident: $ty:ty),*$(,)?})?,
@@ -27,7 +47,7 @@ macro_rules! event_enum {
         #[derive(Debug, Deserialize, Serialize)]
         $vis enum $name {
             $(
-                $(#[doc = $doc])?
+                $(#[doc = $doc])*
                 $(#[$($event_attrs)*])*
                 $event $({$($field: $ty,)*})?,
             )*
@@ -36,7 +56,7 @@ macro_rules! event_enum {
         /// LED engine interface.
         pub trait Engine: Send + Sync {
             $(
-                $(#[doc = $doc])?
+                $(#[doc = $doc])*
                 fn $method(&self, $($($field: $ty,)*)?);
             )*
 
@@ -46,7 +66,7 @@ macro_rules! event_enum {
 
         impl Engine for Jetson {
             $(
-                $(#[doc = $doc])?
+                $(#[doc = $doc])*
                 fn $method(&self, $($($field: $ty,)*)?) {
                     let event = $name::$event $({$($field,)*})?;
                     self.tx.send(event).expect("LED engine is not running");
@@ -60,7 +80,7 @@ macro_rules! event_enum {
 
         impl Engine for Fake {
             $(
-                $(#[doc = $doc])?
+                $(#[doc = $doc])*
                 #[allow(unused_variables)]
                 fn $method(&self, $($($field: $ty,)*)?) {}
             )*
@@ -92,6 +112,8 @@ pub enum QrScanUnexpectedReason {
     Invalid,
     /// Wrong QR Format
     WrongFormat,
+    /// The QR code failed too many signups recently and is cooling down
+    RateLimited,
 }
 
 /// Signup failure reason
@@ -117,6 +139,18 @@ pub enum SignupFailReason {
     Unknown,
 }
 
+/// Camera of a lens dirt notification.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LensCamera {
+    /// RGB camera.
+    Rgb,
+    /// IR eye camera.
+    IrEye,
+    /// IR face camera.
+    IrFace,
+}
+
 event_enum! {
     /// Definition of all the events
     #[allow(dead_code)]
@@ -188,6 +222,11 @@ event_enum! {
         BiometricCaptureOcclusion {
             occlusion_detected: bool
         },
+        /// Gaze-direction guidance on the ring. `None` turns it off.
+        #[event_enum(method = biometric_capture_gaze)]
+        BiometricCaptureGaze {
+            guidance: Option<GazeGuidance>
+        },
         /// User not in distance range.
         #[event_enum(method = biometric_capture_distance)]
         BiometricCaptureDistance {
@@ -242,6 +281,15 @@ event_enum! {
         /// No internet with the intent of starting a signup.
         #[event_enum(method = no_internet_for_signup)]
         NoInternetForSignup,
+        /// Internet works, but the backend host name doesn't resolve.
+        #[event_enum(method = dns_failure)]
+        DnsFailure,
+        /// Internet works, but the signup backend is unreachable.
+        #[event_enum(method = backend_unreachable)]
+        BackendUnreachable,
+        /// Signup backend works, but the relay is unreachable.
+        #[event_enum(method = relay_unreachable)]
+        RelayUnreachable,
         /// Good wlan connection.
         #[event_enum(method = good_wlan)]
         GoodWlan,
@@ -274,6 +322,12 @@ event_enum! {
         #[event_enum(method = recovery)]
         RecoveryImage,
 
+        /// Maintenance mode, the hardware is parked for servicing.
+        #[event_enum(method = maintenance)]
+        Maintenance {
+            enabled: bool,
+        },
+
         /// Set volume [0..100]
         #[event_enum(method = sound_volume)]
         SoundVolume {
@@ -284,9 +338,91 @@ event_enum! {
         SoundLanguage {
             lang: Option<String>,
         },
+        /// Reduced-light night mode. `None` turns it off.
+        #[event_enum(method = night_mode)]
+        NightMode {
+            settings: Option<NightModeSettings>
+        },
+        /// LED palette and animation set. `None` restores the built-in theme.
+        #[event_enum(method = theme)]
+        Theme {
+            theme: Option<Theme>
+        },
+        /// Self-serve queue length and the progress of the current signup.
+        /// `None` turns it off.
+        #[event_enum(method = signup_queue)]
+        SignupQueue {
+            indicator: Option<QueueIndicator>
+        },
+        /// Camera lens is likely dirty and the operator should clean it, or
+        /// the lens is clean again.
+        #[event_enum(method = lens_dirty)]
+        LensDirty {
+            camera: LensCamera,
+            dirty: bool,
+        },
+        /// Diamond cone was attached or detached.
+        #[event_enum(method = cone_present)]
+        ConePresent {
+            present: bool,
+        },
+        /// Overrides the orb-ui animations with the scripted ones. An empty
+        /// list restores the built-in animations.
+        #[event_enum(method = animation_script)]
+        AnimationScript {
+            animations: Vec<animation::Rendered>,
+        },
         /// Plays boot-up complete sound for testing
         #[event_enum(method = sound_test)]
         SoundTest,
+        /// Queues a sound cue without an LED animation.
+        #[event_enum(method = play_sound)]
+        PlaySound {
+            cue: sound::Cue,
+        },
+    }
+}
+
+impl Event {
+    /// Returns `true` if the event is handled by the orb-core sound player
+    /// only.
+    fn is_sound_only(&self) -> bool {
+        matches!(
+            self,
+            Self::PlaySound { .. }
+                | Self::SoundTest
+                | Self::SoundVolume { .. }
+                | Self::SoundLanguage { .. }
+        )
+    }
+
+    /// Returns the silent-mode confirmation cue of the event.
+    fn haptic_cue(&self) -> Option<haptics::Cue> {
+        match self {
+            Self::BiometricCaptureSuccess => Some(haptics::Cue::CaptureComplete),
+            Self::SignupSuccess => Some(haptics::Cue::SignupSuccess),
+            _ => None,
+        }
+    }
+
+    /// Returns the sound cue accompanying the event.
+    fn cue(&self) -> Option<sound::Cue> {
+        Some(match self {
+            Self::BootComplete { .. } | Self::SoundTest => sound::Cue::BootComplete,
+            Self::QrScanCapture => sound::Cue::QrCapture,
+            Self::QrScanSuccess { .. } => sound::Cue::QrSuccess,
+            Self::QrScanUnexpected { .. }
+            | Self::QrScanFail { .. }
+            | Self::QrScanTimeout { .. } => sound::Cue::QrFail,
+            Self::SignupStartOperator | Self::SignupStart => sound::Cue::SignupStart,
+            Self::BiometricCaptureSuccess => sound::Cue::CaptureSuccess,
+            Self::SignupSuccess => sound::Cue::SignupSuccess,
+            Self::SignupFail { .. } => sound::Cue::SignupFail,
+            Self::NoInternetForSignup => sound::Cue::NoInternet,
+            Self::Shutdown { .. } => sound::Cue::Shutdown,
+            Self::PlaySound { cue } => *cue,
+            _ => return None,
+        })
     }
 }
 
@@ -302,25 +438,84 @@ impl Jetson {
     /// Creates the event forwarder
     #[must_use]
     pub fn spawn() -> Self {
+        Self::spawn_with_cues(None, haptics::Sender::default())
+    }
+
+    /// Creates the event forwarder, which also plays the sound cues of the
+    /// events on the `sound` player and sends their confirmation cues to
+    /// `haptics`.
+    #[must_use]
+    pub fn spawn_with_cues(sound: Option<sound::Jetson>, haptics: haptics::Sender) -> Self {
         let (tx, rx) = mpsc::unbounded_channel();
-        task::spawn(event_loop(rx));
+        task::spawn(event_loop(rx, sound, haptics));
         Self { tx }
     }
 }
 
-#[allow(clippy::too_many_lines)]
-async fn event_loop(rx: mpsc::UnboundedReceiver<Event>) -> Result<()> {
+async fn event_loop(
+    rx: mpsc::UnboundedReceiver<Event>,
+    sound: Option<sound::Jetson>,
+    haptics: haptics::Sender,
+) -> Result<()> {
     let mut rx = UnboundedReceiverStream::new(rx);
     let connection = Connection::session().await?;
     let proxy = SignupStateProxy::new(&connection).await?;
+    let mut owner_changed = proxy.inner().receive_owner_changed().await?;
+    let mute = serde_json::to_string(&Event::SoundVolume { level: 0 })?;
+    let mut theme = None;
+    if sound.is_some() {
+        // Mute the orb-ui player, so the cues aren't played twice.
+        forward(&proxy, mute.clone()).await;
+    }
     loop {
-        while let Some(event) = rx.next().await {
-            match proxy.orb_signup_state_event(serde_json::to_string(&event)?).await {
-                Ok(()) => {}
-                Err(e) => {
-                    warn!("Error: {:#?}", e);
+        tokio::select! {
+            event = rx.next() => {
+                let Some(event) = event else {
+                    break;
+                };
+                if sound.is_none() || !event.is_sound_only() {
+                    let event_json = serde_json::to_string(&event)?;
+                    if let Event::Theme { .. } = event {
+                        theme = Some(event_json.clone());
+                    }
+                    forward(&proxy, event_json).await;
+                }
+                if let Some(cue) = event.haptic_cue() {
+                    haptics.send(cue);
+                }
+                if let Some(sound) = &sound {
+                    match &event {
+                        Event::SoundVolume { level } => sound.set_volume(*level),
+                        Event::SoundLanguage { lang } => sound.set_language(lang.clone()),
+                        event => {
+                            if let Some(cue) = event.cue() {
+                                sound.play(cue);
+                            }
+                        }
+                    }
+                }
+            }
+            Some(owner) = owner_changed.next() => {
+                if owner.is_none() {
+                    continue;
+                }
+                // A restarted orb-ui service starts unmuted with the built-in
+                // theme.
+                tracing::info!("orb-ui service restarted, restoring its state");
+                if sound.is_some() {
+                    forward(&proxy, mute.clone()).await;
+                }
+                if let Some(theme) = &theme {
+                    forward(&proxy, theme.clone()).await;
                 }
             }
         }
     }
+    Ok(())
+}
+
+async fn forward(proxy: &SignupStateProxy<'_>, event_json: String) {
+    if let Err(e) = proxy.orb_signup_state_event(event_json).await {
+        warn!("Error: {:#?}", e);
+    }
 }
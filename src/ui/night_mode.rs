@@ -0,0 +1,171 @@
+//! Reduced-light night mode.
+//!
+//! In dark venues the full-brightness ring and center LEDs dazzle the users.
+//! The night mode asks the orb-ui service to cap the LED brightness, dim the
+//! white flashes, and slow down the pulsing animations. It is driven by the
+//! per-venue [`Schedule`] from the backend config, and can be toggled by the
+//! operator with the `magic_action:toggle_night_mode` QR code. A manual toggle
+//! holds until the next scheduled transition.
+
+use super::Engine;
+use crate::config::Config;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use time::OffsetDateTime;
+
+const MINUTES_PER_DAY: i32 = 24 * 60;
+
+static CONTROLLER: Lazy<Mutex<Controller>> = Lazy::new(|| Mutex::new(Controller::default()));
+
+/// LED settings applied by the orb-ui service while the night mode is active.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub struct NightModeSettings {
+    /// Maximum ring and center LED brightness in the `0.0..=1.0` range.
+    pub brightness_cap: f64,
+    /// White flash intensity in the `0.0..=1.0` range.
+    pub flash_intensity: f64,
+    /// Pulsing animation speed factor, `1.0` being the normal speed.
+    pub animation_speed: f64,
+}
+
+/// Daily night mode window in the venue local time. Equal start and end make
+/// the night mode active all day, for permanently dark venues.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct Schedule {
+    /// Start of the window in minutes after the local midnight.
+    pub start_minute: u16,
+    /// End of the window in minutes after the local midnight.
+    pub end_minute: u16,
+    /// Offset of the venue local time from UTC in minutes.
+    pub utc_offset_minutes: i16,
+}
+
+/// Night mode state.
+#[derive(Debug, Default)]
+pub struct Controller {
+    forced: Option<bool>,
+    scheduled: Option<bool>,
+    active: Option<bool>,
+}
+
+impl Default for NightModeSettings {
+    fn default() -> Self {
+        Self { brightness_cap: 0.3, flash_intensity: 0.25, animation_speed: 0.5 }
+    }
+}
+
+impl Schedule {
+    /// Returns `true` if `now` falls within the window.
+    #[must_use]
+    pub fn contains(&self, now: OffsetDateTime) -> bool {
+        let utc_minute = i32::from(now.hour()) * 60 + i32::from(now.minute());
+        let minute = (utc_minute + i32::from(self.utc_offset_minutes)).rem_euclid(MINUTES_PER_DAY);
+        let start = i32::from(self.start_minute);
+        let end = i32::from(self.end_minute);
+        match start.cmp(&end) {
+            std::cmp::Ordering::Less => (start..end).contains(&minute),
+            std::cmp::Ordering::Greater => minute >= start || minute < end,
+            std::cmp::Ordering::Equal => true,
+        }
+    }
+}
+
+impl Controller {
+    /// Evaluates the schedule. Returns the new state if it has changed.
+    pub fn evaluate(&mut self, schedule: Option<&Schedule>, now: OffsetDateTime) -> Option<bool> {
+        let scheduled = schedule.is_some_and(|schedule| schedule.contains(now));
+        if self.scheduled.is_some_and(|previous| previous != scheduled) {
+            self.forced = None;
+        }
+        self.scheduled = Some(scheduled);
+        let active = self.forced.unwrap_or(scheduled);
+        (self.active != Some(active)).then(|| {
+            self.active = Some(active);
+            active
+        })
+    }
+
+    /// Flips the current state until the next scheduled transition.
+    pub fn toggle(&mut self) {
+        self.forced = Some(!self.active.unwrap_or(false));
+    }
+}
+
+/// Evaluates the night mode schedule, and notifies the UI on a change.
+///
+/// # Panics
+///
+/// If the mutex is poisoned
+pub fn update(config: &Config, ui: &dyn Engine) {
+    let changed =
+        CONTROLLER.lock().unwrap().evaluate(config.night_mode.as_ref(), OffsetDateTime::now_utc());
+    if let Some(active) = changed {
+        apply(active, config, ui);
+    }
+}
+
+/// Toggles the night mode from the maintenance QR flow.
+///
+/// # Panics
+///
+/// If the mutex is poisoned
+pub fn toggle(config: &Config, ui: &dyn Engine) {
+    let changed = {
+        let mut controller = CONTROLLER.lock().unwrap();
+        controller.toggle();
+        controller.evaluate(config.night_mode.as_ref(), OffsetDateTime::now_utc())
+    };
+    if let Some(active) = changed {
+        apply(active, config, ui);
+    }
+}
+
+fn apply(active: bool, config: &Config, ui: &dyn Engine) {
+    tracing::info!("Night mode {}", if active { "enabled" } else { "disabled" });
+    ui.night_mode(active.then_some(config.night_mode_settings));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::Time;
+
+    fn at(hour: u8, minute: u8) -> OffsetDateTime {
+        OffsetDateTime::UNIX_EPOCH.replace_time(Time::from_hms(hour, minute, 0).unwrap())
+    }
+
+    const SCHEDULE: Schedule =
+        Schedule { start_minute: 20 * 60, end_minute: 6 * 60, utc_offset_minutes: -120 };
+
+    #[test]
+    fn test_schedule() {
+        assert!(SCHEDULE.contains(at(23, 0)));
+        assert!(SCHEDULE.contains(at(3, 0)));
+        assert!(!SCHEDULE.contains(at(8, 30)));
+        assert!(!SCHEDULE.contains(at(21, 59)));
+        let day = Schedule { start_minute: 8 * 60, end_minute: 18 * 60, utc_offset_minutes: 0 };
+        assert!(day.contains(at(12, 0)));
+        assert!(!day.contains(at(18, 0)));
+        let always = Schedule { start_minute: 0, end_minute: 0, utc_offset_minutes: 0 };
+        assert!(always.contains(at(12, 0)));
+    }
+
+    #[test]
+    fn test_toggle() {
+        let day = at(12, 0);
+        let night = at(23, 0);
+        let mut controller = Controller::default();
+        assert_eq!(controller.evaluate(Some(&SCHEDULE), day), Some(false));
+        assert_eq!(controller.evaluate(Some(&SCHEDULE), day), None);
+        controller.toggle();
+        assert_eq!(controller.evaluate(Some(&SCHEDULE), day), Some(true));
+        assert_eq!(controller.evaluate(Some(&SCHEDULE), day), None);
+        // The scheduled transition clears the manual toggle.
+        assert_eq!(controller.evaluate(Some(&SCHEDULE), night), None);
+        controller.toggle();
+        assert_eq!(controller.evaluate(Some(&SCHEDULE), night), Some(false));
+        assert_eq!(controller.evaluate(None, night), None);
+    }
+}
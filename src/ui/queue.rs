@@ -0,0 +1,126 @@
+//! Signup queue indicator.
+//!
+//! At self-serve venues with the user queue, the users queued behind the
+//! current one watch the orb while they wait. The ring shows how many users
+//! are waiting, and once the presence of the current user is confirmed, fills
+//! approximately with the progress of the signup, so the queue can see that the
+//! capture is progressing for the person ahead. The progress is quantized into
+//! [`QUEUE_INDICATOR_SEGMENTS`] ring segments and never goes backwards within a
+//! signup, so the indicator changes only a few times per signup.
+
+use crate::consts::QUEUE_INDICATOR_SEGMENTS;
+use serde::{Deserialize, Serialize};
+
+/// Queue indicator ring animation.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct QueueIndicator {
+    /// Number of users waiting behind the current one.
+    pub waiting: u32,
+    /// Number of the lit ring segments out of [`QUEUE_INDICATOR_SEGMENTS`].
+    pub segments: u32,
+}
+
+/// Queue indicator state change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Update {
+    /// Show or update the indicator.
+    Show(QueueIndicator),
+    /// Turn the indicator off.
+    Clear,
+    /// Nothing to change.
+    Unchanged,
+}
+
+/// Queue indicator state.
+#[derive(Debug, Default)]
+pub struct Indicator {
+    waiting: u32,
+    shown: Option<QueueIndicator>,
+}
+
+impl Indicator {
+    /// Starts a new signup with `waiting` users behind it. The indicator is
+    /// shown with the first progress update. Zero turns the indicator off.
+    pub fn start(&mut self, waiting: usize) -> Update {
+        self.waiting = u32::try_from(waiting).unwrap_or(u32::MAX);
+        if self.waiting == 0 {
+            return self.clear();
+        }
+        if self.shown.is_some() {
+            self.show(0)
+        } else {
+            Update::Unchanged
+        }
+    }
+
+    /// Advances the indicator with the current signup `progress` in the
+    /// `0.0..=1.0` range, where zero is the confirmed user presence, the first
+    /// half is the biometric capture, and the second half is the biometric
+    /// pipeline.
+    pub fn progress(&mut self, progress: f64) -> Update {
+        if self.waiting == 0 {
+            return Update::Unchanged;
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let segments =
+            (progress.clamp(0.0, 1.0) * f64::from(QUEUE_INDICATOR_SEGMENTS)).floor() as u32;
+        let segments = segments.max(self.shown.map_or(0, |shown| shown.segments));
+        self.show(segments)
+    }
+
+    /// Turns the indicator off.
+    pub fn clear(&mut self) -> Update {
+        self.waiting = 0;
+        if self.shown.take().is_some() {
+            Update::Clear
+        } else {
+            Update::Unchanged
+        }
+    }
+
+    fn show(&mut self, segments: u32) -> Update {
+        let indicator = QueueIndicator { waiting: self.waiting, segments };
+        if self.shown == Some(indicator) {
+            return Update::Unchanged;
+        }
+        self.shown = Some(indicator);
+        Update::Show(indicator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled() {
+        let mut indicator = Indicator::default();
+        assert_eq!(indicator.start(0), Update::Unchanged);
+        assert_eq!(indicator.progress(0.5), Update::Unchanged);
+    }
+
+    #[test]
+    fn test_progress() {
+        let mut indicator = Indicator::default();
+        assert_eq!(indicator.start(2), Update::Unchanged);
+        assert_eq!(
+            indicator.progress(0.01),
+            Update::Show(QueueIndicator { waiting: 2, segments: 0 })
+        );
+        assert_eq!(indicator.progress(0.0), Update::Unchanged);
+        let half = QUEUE_INDICATOR_SEGMENTS / 2;
+        assert_eq!(
+            indicator.progress(0.5),
+            Update::Show(QueueIndicator { waiting: 2, segments: half })
+        );
+        // The progress never goes backwards within a signup.
+        assert_eq!(indicator.progress(0.1), Update::Unchanged);
+        assert_eq!(
+            indicator.progress(2.0),
+            Update::Show(QueueIndicator { waiting: 2, segments: QUEUE_INDICATOR_SEGMENTS })
+        );
+        assert_eq!(indicator.start(1), Update::Show(QueueIndicator { waiting: 1, segments: 0 }));
+        assert_eq!(indicator.start(0), Update::Clear);
+        assert_eq!(indicator.clear(), Update::Unchanged);
+    }
+}
@@ -0,0 +1,134 @@
+//! Per-country and per-hardware LED themes.
+//!
+//! The orb-ui service colors the ring, center, and operator LEDs from a
+//! built-in palette. A [`Theme`] overrides the palette colors and selects the
+//! animation set at runtime. The theme is chosen from the [`Rule`]s of the
+//! backend config by the operating country of the orb and its exact hardware
+//! version, and is sent to the orb-ui service when the selection changes, so a
+//! new theme doesn't require a new build. The UI event loop sends the last
+//! theme again if the orb-ui service restarts.
+//!
+//! Themes are purely visual. The signup behavior, e.g. for outdated software,
+//! stays selected at compile time.
+
+use super::{animation::Color, Engine};
+use crate::{config::Config, identification::HARDWARE_VERSION};
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// The last theme sent to the orb-ui service.
+static SENT: Lazy<Mutex<Option<Option<Theme>>>> = Lazy::new(Default::default);
+
+/// LED colors and animation set.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct Theme {
+    /// Palette overrides.
+    pub palette: Palette,
+    /// Name of the orb-ui animation set. `None` keeps the default set.
+    pub animation_set: Option<String>,
+}
+
+/// LED palette. `None` keeps the built-in color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct Palette {
+    /// Ring and center color during a signup.
+    pub user_signup: Option<Color>,
+    /// Ring color of a successful signup.
+    pub user_success: Option<Color>,
+    /// Ring color of a failed signup.
+    pub user_fail: Option<Color>,
+    /// Operator LEDs color while idle.
+    pub operator_default: Option<Color>,
+    /// Operator LEDs color in self-serve mode.
+    pub operator_self_serve: Option<Color>,
+}
+
+/// Theme selection rule. A rule without conditions matches every orb.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct Rule {
+    /// ISO 3166-1 alpha-2 code of the operating country.
+    pub country: Option<String>,
+    /// Hardware version, e.g. `Diamond_EVT`.
+    pub hardware: Option<String>,
+    /// Theme applied if the rule matches.
+    pub theme: Theme,
+}
+
+impl Rule {
+    /// Returns `true` if the rule matches the orb.
+    #[must_use]
+    pub fn matches(&self, country: Option<&str>, hardware_version: &str) -> bool {
+        let country_matches = match (self.country.as_deref(), country) {
+            (None, _) => true,
+            (Some(rule), Some(country)) => rule.eq_ignore_ascii_case(country),
+            (Some(_), None) => false,
+        };
+        let hardware_matches = match self.hardware.as_deref() {
+            None => true,
+            Some(rule) => rule == hardware_version.trim(),
+        };
+        country_matches && hardware_matches
+    }
+}
+
+/// Returns the theme of the first matching rule.
+#[must_use]
+pub fn select<'a>(
+    rules: &'a [Rule],
+    country: Option<&str>,
+    hardware_version: &str,
+) -> Option<&'a Theme> {
+    rules.iter().find(|rule| rule.matches(country, hardware_version)).map(|rule| &rule.theme)
+}
+
+/// Returns the theme selected by the `config` for this orb.
+#[must_use]
+pub fn current(config: &Config) -> Option<&Theme> {
+    select(&config.ui_themes, config.operation_country.as_deref(), &HARDWARE_VERSION)
+}
+
+/// Sends the theme selected by the `config` to the orb-ui service if it
+/// differs from the last one sent.
+pub fn update(config: &Config, ui: &dyn Engine) {
+    let theme = current(config).cloned();
+    let mut sent = SENT.lock().unwrap();
+    if sent.as_ref() != Some(&theme) {
+        ui.theme(theme.clone());
+        *sent = Some(theme);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(country: Option<&str>, hardware: Option<&str>, animation_set: &str) -> Rule {
+        Rule {
+            country: country.map(ToOwned::to_owned),
+            hardware: hardware.map(ToOwned::to_owned),
+            theme: Theme { animation_set: Some(animation_set.to_owned()), ..Theme::default() },
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let rules = [
+            rule(Some("AR"), Some("Diamond_EVT"), "ar_diamond"),
+            rule(Some("ar"), None, "ar"),
+            rule(None, Some("Diamond_EVT"), "diamond"),
+            rule(None, Some("EVT"), "evt"),
+        ];
+        let set = |country, hardware| {
+            select(&rules, country, hardware).and_then(|theme| theme.animation_set.as_deref())
+        };
+        assert_eq!(set(Some("AR"), "Diamond_EVT"), Some("ar_diamond"));
+        assert_eq!(set(Some("AR"), "Pearl_EVT"), Some("ar"));
+        assert_eq!(set(Some("PT"), "Diamond_EVT"), Some("diamond"));
+        assert_eq!(set(None, "Pearl_EVT"), None);
+        assert_eq!(set(None, "Diamond_EVT\n"), Some("diamond"));
+        assert_eq!(set(None, "Diamond_B3"), None);
+        assert_eq!(set(None, "EVT"), Some("evt"));
+    }
+}
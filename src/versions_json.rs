@@ -9,8 +9,18 @@ pub struct SlotReleases {
     pub slot_b: String,
 }
 
+#[allow(missing_docs, dead_code)]
+#[derive(Deserialize)]
+pub struct McuReleases {
+    pub main: String,
+    #[serde(default)]
+    pub sec: Option<String>,
+}
+
 #[allow(missing_docs, dead_code)]
 #[derive(Deserialize)]
 pub struct VersionsJson {
     pub releases: SlotReleases,
+    #[serde(default)]
+    pub mcu: Option<McuReleases>,
 }
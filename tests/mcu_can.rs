@@ -0,0 +1,200 @@
+//! `mcu::can` integration tests against a fake MCU on a virtual CAN interface.
+//!
+//! These tests require the vcan kernel module and CAP_NET_ADMIN. Run them with
+//! `cargo test --test mcu_can -- --ignored`.
+
+#[path = "mcu_can/fake_mcu.rs"]
+mod fake_mcu;
+
+use fake_mcu::{FakeMcu, Reply};
+use futures::{
+    channel::{mpsc, oneshot},
+    prelude::*,
+};
+use orb::mcu::{
+    can::Can,
+    main::{Input, Jetson, Main, Output},
+    protocol::{Negotiation, Protocol},
+    AckPolicies, Interface, Mcu, MessageClass,
+};
+use orb_can::vcan::Vcan;
+use orb_messages::mcu_main::{
+    ack::ErrorCode, jetson_to_mcu, mcu_message, mcu_to_jetson, GnssDataPartial, PowerButton,
+};
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{sync::broadcast, time::timeout};
+
+const NMEA: &str = "$GPGGA,092750.000,5321.6802,N,00630.3372,W,1,8,1.03,61.7,M,55.2,M,,*76";
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(2);
+
+struct Setup {
+    _vcan: Vcan,
+    fake_mcu: FakeMcu,
+    input_tx: mpsc::Sender<(Input, Option<oneshot::Sender<eyre::Result<()>>>)>,
+    output_rx: broadcast::Receiver<Output>,
+    protocol: Protocol,
+}
+
+fn setup(spawn_fake_mcu: impl FnOnce(&str) -> FakeMcu) -> Setup {
+    let vcan = Vcan::new().unwrap();
+    let fake_mcu = spawn_fake_mcu(vcan.name());
+    let (input_tx, input_rx) = mpsc::channel(10);
+    let (output_tx, output_rx) = broadcast::channel(10);
+    let protocol = Protocol::new(Main::PROTOCOL_VERSION, Main::SUPPORTED_PROTOCOL_VERSIONS);
+    Can::<Main>::spawn_on(
+        vcan.name(),
+        input_rx,
+        output_tx,
+        protocol.clone(),
+        AckPolicies::default(),
+    )
+    .unwrap();
+    Setup { _vcan: vcan, fake_mcu, input_tx, output_rx, protocol }
+}
+
+async fn send(setup: &mut Setup, input: Input) -> eyre::Result<()> {
+    let (completion_tx, completion_rx) = oneshot::channel();
+    setup.input_tx.send((input, Some(completion_tx))).await.unwrap();
+    completion_rx.await.unwrap()
+}
+
+async fn next_output(output_rx: &mut broadcast::Receiver<Output>) -> Output {
+    timeout(OUTPUT_TIMEOUT, output_rx.recv()).await.expect("no output from the MCU").unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires the vcan kernel module and CAP_NET_ADMIN"]
+async fn test_ack() {
+    let mut setup = setup(FakeMcu::spawn_acking);
+    send(&mut setup, Input::UserLedBrightness(42)).await.unwrap();
+    assert!(matches!(
+        next_output(&mut setup.output_rx).await,
+        Output::SuccessAck(Input::UserLedBrightness(42))
+    ));
+    let received = setup.fake_mcu.received();
+    assert_eq!(received.len(), 1);
+    assert!(matches!(
+        received[0].payload,
+        Some(jetson_to_mcu::Payload::UserLedsBrightness(ref payload)) if payload.brightness == 42
+    ));
+}
+
+#[tokio::test]
+#[ignore = "requires the vcan kernel module and CAP_NET_ADMIN"]
+async fn test_error_ack() {
+    let mut setup = setup(|interface| FakeMcu::spawn(interface, |_| Reply::Ack(ErrorCode::Fail)));
+    // MCU errors are logged, but don't fail the completion.
+    send(&mut setup, Input::UserLedBrightness(42)).await.unwrap();
+    assert!(timeout(Duration::from_millis(200), setup.output_rx.recv()).await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "requires the vcan kernel module and CAP_NET_ADMIN"]
+async fn test_ack_timeout() {
+    let mut setup = setup(|interface| FakeMcu::spawn(interface, |_| Reply::Drop));
+    assert!(send(&mut setup, Input::UserLedBrightness(42)).await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "requires the vcan kernel module and CAP_NET_ADMIN"]
+async fn test_retries() {
+    let vcan = Vcan::new().unwrap();
+    let attempts = Arc::new(AtomicU32::new(0));
+    let attempts2 = Arc::clone(&attempts);
+    let fake_mcu = FakeMcu::spawn(vcan.name(), move |message| match message.payload {
+        Some(jetson_to_mcu::Payload::FanSpeed(_))
+            if attempts2.fetch_add(1, Ordering::Relaxed) < 2 =>
+        {
+            Reply::Drop
+        }
+        _ => Reply::Ack(ErrorCode::Success),
+    });
+    let mut mcu = Jetson::spawn_on(vcan.name()).unwrap();
+    mcu.send(Input::FanSpeed(50.0)).await.unwrap();
+    assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    let fan_speed_messages = fake_mcu
+        .received()
+        .into_iter()
+        .filter(|message| matches!(message.payload, Some(jetson_to_mcu::Payload::FanSpeed(_))))
+        .count();
+    assert_eq!(fan_speed_messages, 3);
+}
+
+#[tokio::test]
+#[ignore = "requires the vcan kernel module and CAP_NET_ADMIN"]
+async fn test_class_retries() {
+    let vcan = Vcan::new().unwrap();
+    let fake_mcu = FakeMcu::spawn(vcan.name(), |_| Reply::Drop);
+    let mut mcu = Jetson::spawn_on(vcan.name()).unwrap();
+    let cases = [
+        (Input::UserLedBrightness(42), MessageClass::Led, 1),
+        (Input::VoltageRequest, MessageClass::Query, 4),
+        (Input::PerformIrEyeCameraFocusSweep, MessageClass::Motion, 1),
+        (Input::FanSpeed(50.0), MessageClass::Control, 4),
+    ];
+    for (input, class, expected_attempts) in cases {
+        assert_eq!(Main::input_class(&input), class);
+        let Some(mcu_message::Message::JMessage(message)) = Main::input_to_message(&input, 0)
+        else {
+            panic!("expected a Jetson message");
+        };
+        let payload = std::mem::discriminant(&message.payload.unwrap());
+        assert!(mcu.send(input).await.is_err());
+        let attempts = fake_mcu
+            .received()
+            .into_iter()
+            .filter(|message| message.payload.as_ref().map(std::mem::discriminant) == Some(payload))
+            .count();
+        assert_eq!(attempts, expected_attempts, "{class:?}");
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires the vcan kernel module and CAP_NET_ADMIN"]
+async fn test_gnss_reassembly() {
+    let mut setup = setup(FakeMcu::spawn_acking);
+    let (first, second) = NMEA.split_at(NMEA.len() / 2);
+    // An orphan second half is ignored.
+    setup.fake_mcu.send(mcu_to_jetson::Payload::GnssPartial(GnssDataPartial {
+        counter: 5,
+        nmea_part: second.to_owned(),
+    }));
+    setup.fake_mcu.send(mcu_to_jetson::Payload::GnssPartial(GnssDataPartial {
+        counter: 6,
+        nmea_part: first.to_owned(),
+    }));
+    setup.fake_mcu.send(mcu_to_jetson::Payload::GnssPartial(GnssDataPartial {
+        counter: 7,
+        nmea_part: second.to_owned(),
+    }));
+    let Output::Gps(nmea_parser::ParsedMessage::Gga(gga)) = next_output(&mut setup.output_rx).await
+    else {
+        panic!("expected a GGA message");
+    };
+    assert!((gga.latitude.unwrap() - 53.361_336).abs() < 1e-5);
+    assert_eq!(gga.satellite_count, Some(8));
+}
+
+#[tokio::test]
+#[ignore = "requires the vcan kernel module and CAP_NET_ADMIN"]
+async fn test_protocol_errors() {
+    let mut setup = setup(FakeMcu::spawn_acking);
+    setup.fake_mcu.send_raw(&[0xFF; 16]);
+    setup.fake_mcu.send_with_version(
+        i32::MAX,
+        mcu_to_jetson::Payload::PowerButton(PowerButton { pressed: false }),
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(matches!(setup.protocol.negotiation(), Negotiation::Incompatible(_)));
+    setup.fake_mcu.send(mcu_to_jetson::Payload::PowerButton(PowerButton { pressed: true }));
+    assert!(matches!(next_output(&mut setup.output_rx).await, Output::Button(true)));
+    assert_eq!(setup.protocol.negotiation(), Negotiation::Compatible {
+        firmware: Main::PROTOCOL_VERSION
+    });
+}
@@ -0,0 +1,96 @@
+//! Scripted fake main MCU speaking the orb-messages protocol on a virtual CAN
+//! interface.
+
+use orb::mcu::{can::CAN_FD_ADDR_JETSON, main::Main, Interface};
+use orb_can::fd;
+use orb_messages::mcu_main::{
+    ack::ErrorCode, mcu_message, mcu_to_jetson, Ack, JetsonToMcu, McuMessage, McuToJetson,
+};
+use prost::Message;
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// Reply of the fake MCU to a Jetson message.
+#[derive(Clone, Copy, Debug)]
+pub enum Reply {
+    /// Acknowledge with the error code.
+    Ack(ErrorCode),
+    /// Don't acknowledge, so that the Jetson times out.
+    Drop,
+}
+
+/// Fake main MCU.
+pub struct FakeMcu {
+    tx: fd::Tx,
+    received: Arc<Mutex<Vec<JetsonToMcu>>>,
+}
+
+impl FakeMcu {
+    /// Spawns a fake MCU on `interface`, replying to every Jetson message
+    /// with the result of `script`.
+    pub fn spawn(
+        interface: &str,
+        mut script: impl FnMut(&JetsonToMcu) -> Reply + Send + 'static,
+    ) -> Self {
+        let (tx, rx) = fd::open(interface).unwrap();
+        let (ack_tx, _) = fd::open(interface).unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received2 = Arc::clone(&received);
+        thread::spawn(move || {
+            while let Ok(frame) = rx.recv() {
+                if frame.can_id != Main::CAN_ADDRESS {
+                    continue;
+                }
+                let Ok(McuMessage {
+                    message: Some(mcu_message::Message::JMessage(message)), ..
+                }) = McuMessage::decode_length_delimited(&frame.data[..usize::from(frame.len)])
+                else {
+                    continue;
+                };
+                let reply = script(&message);
+                let ack_number = message.ack_number;
+                received2.lock().unwrap().push(message);
+                if let Reply::Ack(error) = reply {
+                    let ack = Ack { ack_number, error: error as i32 };
+                    send(&ack_tx, Main::PROTOCOL_VERSION, mcu_to_jetson::Payload::Ack(ack));
+                }
+            }
+        });
+        Self { tx, received }
+    }
+
+    /// Spawns a fake MCU which acknowledges everything successfully.
+    pub fn spawn_acking(interface: &str) -> Self {
+        Self::spawn(interface, |_| Reply::Ack(ErrorCode::Success))
+    }
+
+    /// Sends an unsolicited payload to the Jetson.
+    pub fn send(&self, payload: mcu_to_jetson::Payload) {
+        send(&self.tx, Main::PROTOCOL_VERSION, payload);
+    }
+
+    /// Sends a payload encoded with a specific protocol version.
+    pub fn send_with_version(&self, version: i32, payload: mcu_to_jetson::Payload) {
+        send(&self.tx, version, payload);
+    }
+
+    /// Sends raw bytes to the Jetson.
+    pub fn send_raw(&self, bytes: &[u8]) {
+        self.tx.send(CAN_FD_ADDR_JETSON, bytes).unwrap();
+    }
+
+    /// Returns the messages received from the Jetson so far.
+    pub fn received(&self) -> Vec<JetsonToMcu> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+fn send(tx: &fd::Tx, version: i32, payload: mcu_to_jetson::Payload) {
+    let message = McuMessage {
+        version,
+        message: Some(mcu_message::Message::MMessage(McuToJetson { payload: Some(payload) })),
+    };
+    tx.send(CAN_FD_ADDR_JETSON, &message.encode_length_delimited_to_vec()).unwrap();
+}
@@ -4,16 +4,27 @@
 #define CONSTIFY_U32(name) const __u32 __CONSTIFY_MACRO_##name = name
 
 CONSTIFY_U64(VIDIOC_DQBUF);
+CONSTIFY_U64(VIDIOC_DQEVENT);
+CONSTIFY_U64(VIDIOC_ENUM_FMT);
+CONSTIFY_U64(VIDIOC_ENUM_FRAMEINTERVALS);
+CONSTIFY_U64(VIDIOC_ENUM_FRAMESIZES);
+CONSTIFY_U64(VIDIOC_G_CTRL);
 CONSTIFY_U64(VIDIOC_G_EXT_CTRLS);
 CONSTIFY_U64(VIDIOC_G_FMT);
+CONSTIFY_U64(VIDIOC_G_PARM);
+CONSTIFY_U64(VIDIOC_QUERYCAP);
 CONSTIFY_U64(VIDIOC_QBUF);
 CONSTIFY_U64(VIDIOC_QUERYBUF);
 CONSTIFY_U64(VIDIOC_QUERYCTRL);
 CONSTIFY_U64(VIDIOC_REQBUFS);
 CONSTIFY_U64(VIDIOC_STREAMOFF);
 CONSTIFY_U64(VIDIOC_STREAMON);
+CONSTIFY_U64(VIDIOC_S_CTRL);
 CONSTIFY_U64(VIDIOC_S_EXT_CTRLS);
 CONSTIFY_U64(VIDIOC_S_FMT);
+CONSTIFY_U64(VIDIOC_S_PARM);
+CONSTIFY_U64(VIDIOC_SUBSCRIBE_EVENT);
+CONSTIFY_U64(VIDIOC_UNSUBSCRIBE_EVENT);
 
 CONSTIFY_U32(V4L2_BUF_FLAG_QUEUED);
 
@@ -35,6 +35,40 @@ pub enum S3Region {
     Unknown = 0xFF,
 }
 
+impl S3Region {
+    /// Returns the AWS region name, or `None` if the region is unknown.
+    #[must_use]
+    pub fn as_str(self) -> Option<&'static str> {
+        Some(match self {
+            S3Region::AfSouth1 => "af-south-1",
+            S3Region::ApEast1 => "ap-east-1",
+            S3Region::ApNortheast1 => "ap-northeast-1",
+            S3Region::ApNortheast2 => "ap-northeast-2",
+            S3Region::ApNortheast3 => "ap-northeast-3",
+            S3Region::ApSouth1 => "ap-south-1",
+            S3Region::ApSoutheast1 => "ap-southeast-1",
+            S3Region::ApSoutheast2 => "ap-southeast-2",
+            S3Region::CaCentral1 => "ca-central-1",
+            S3Region::CnNorthwest1 => "cn-northwest-1",
+            S3Region::EuCentral1 => "eu-central-1",
+            S3Region::EuNorth1 => "eu-north-1",
+            S3Region::EuSouth1 => "eu-south-1",
+            S3Region::EuWest1 => "eu-west-1",
+            S3Region::EuWest2 => "eu-west-2",
+            S3Region::EuWest3 => "eu-west-3",
+            S3Region::MeSouth1 => "me-south-1",
+            S3Region::SaEast1 => "sa-east-1",
+            S3Region::UsEast1 => "us-east-1",
+            S3Region::UsEast2 => "us-east-2",
+            S3Region::UsGovEast1 => "us-gov-east-1",
+            S3Region::UsGovWest1 => "us-gov-west-1",
+            S3Region::UsWest1 => "us-west-1",
+            S3Region::UsWest2 => "us-west-2",
+            S3Region::Unknown => return None,
+        })
+    }
+}
+
 impl FromStr for S3Region {
     type Err = Infallible;
 
@@ -58,6 +58,12 @@ impl SignupId {
         Self(WldDataId { version: VERSION, s3_region, signup_id: thread_rng().gen(), data_id: 0 })
     }
 
+    /// Returns the AWS region the signup data is uploaded to.
+    #[must_use]
+    pub fn s3_region(&self) -> S3Region {
+        self.0.s3_region
+    }
+
     /// Parses a signup id from the signup directory.
     pub fn from_signup_dir(path: &Path) -> Result<Self> {
         path.file_name().ok_or_else(|| eyre!("Invalid path {:?}", path))?.to_string_lossy().parse()
@@ -133,6 +139,18 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_s3_region() {
+        let signup_id = SignupId::new(S3Region::EuWest1);
+        assert_eq!(
+            signup_id.to_string().parse::<SignupId>().unwrap().s3_region(),
+            S3Region::EuWest1
+        );
+        assert_eq!(S3Region::EuWest1.as_str(), Some("eu-west-1"));
+        assert_eq!("eu-west-1".parse::<S3Region>(), Ok(S3Region::EuWest1));
+        assert_eq!(S3Region::Unknown.as_str(), None);
+    }
+
     #[test]
     fn test_sensitivity() {
         let signup_id = SignupId::new(S3Region::Unknown);